@@ -0,0 +1,20 @@
+package redisless
+
+import "strings"
+
+// Capabilities lists what the loaded RedisLess library supports
+type Capabilities struct {
+	Version  string   `json:"version"`
+	Commands []string `json:"commands"`
+	Features []string `json:"features"`
+}
+
+func (c Capabilities) SupportsCommand(command string) bool {
+	command = strings.ToLower(command)
+	for _, supported := range c.Commands {
+		if supported == command {
+			return true
+		}
+	}
+	return false
+}
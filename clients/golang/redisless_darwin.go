@@ -5,12 +5,17 @@ package redisless
 #include <stdbool.h>
             typedef void* server;
             server redisless_server_new(unsigned short);
+            server redisless_server_new_on_free_port(unsigned short, unsigned short);
+            unsigned short redisless_server_port(void* server);
             void redisless_server_free(void* server);
             bool redisless_server_start(void* server);
             bool redisless_server_stop(void* server);
+            char* redisless_capabilities();
+            void redisless_capabilities_free(char* capabilities);
 */
 import "C"
 import (
+	"encoding/json"
 	"unsafe"
 )
 
@@ -20,6 +25,16 @@ func NewRedisLess(port int) RedisLess {
 	return RedisLess(C.redisless_server_new(C.ushort(port)))
 }
 
+// NewRedisLessOnFreePort listens on the first free port from firstPort to lastPort, 0 and 0
+// letting the system pick one. It returns nil when they're all taken.
+func NewRedisLessOnFreePort(firstPort int, lastPort int) RedisLess {
+	return RedisLess(C.redisless_server_new_on_free_port(C.ushort(firstPort), C.ushort(lastPort)))
+}
+
+func Port(r RedisLess) int {
+	return int(C.redisless_server_port(unsafe.Pointer(r)))
+}
+
 func Start(r RedisLess) bool {
 	return bool(C.redisless_server_start(unsafe.Pointer(r)))
 }
@@ -27,3 +42,12 @@ func Start(r RedisLess) bool {
 func Stop(r RedisLess) bool {
 	return bool(C.redisless_server_stop(unsafe.Pointer(r)))
 }
+
+func GetCapabilities() (Capabilities, error) {
+	raw := C.redisless_capabilities()
+	defer C.redisless_capabilities_free(raw)
+
+	var capabilities Capabilities
+	err := json.Unmarshal([]byte(C.GoString(raw)), &capabilities)
+	return capabilities, err
+}
@@ -45,3 +45,27 @@ func TestExecSetGetDelCommands(t *testing.T) {
 	stopped := Stop(redisLess)
 	assert.True(t, stopped)
 }
+
+func TestFreePort(t *testing.T) {
+	redisLess := NewRedisLessOnFreePort(0, 0)
+	assert.NotNil(t, redisLess)
+	port := Port(redisLess)
+	assert.NotZero(t, port)
+
+	assert.True(t, Start(redisLess))
+	client := redis.NewClient(&redis.Options{
+		Addr: "localhost:" + strconv.Itoa(port),
+	})
+	pong, err := client.Ping(ctx).Result()
+	assert.Nil(t, err)
+	assert.Equal(t, "PONG", pong)
+	assert.True(t, Stop(redisLess))
+}
+
+func TestCapabilities(t *testing.T) {
+	capabilities, err := GetCapabilities()
+	assert.Nil(t, err)
+	assert.NotEmpty(t, capabilities.Version)
+	assert.True(t, capabilities.SupportsCommand("GET"))
+	assert.False(t, capabilities.SupportsCommand("SUBSCRIBE"))
+}
@@ -1,5 +1,6 @@
 const path = require('path');
 const ffi = require('ffi-napi');
+const ref = require('ref-napi');
 
 let libExtension = null;
 if (process.platform === "linux") {
@@ -17,8 +18,12 @@ const libPath = path.join(__dirname, `libredisless.${libExtension}`);
 
 const libm = ffi.Library(libPath, {
     redisless_server_new: ['void*', ['short']],
+    redisless_server_new_on_free_port: ['void*', ['ushort', 'ushort']],
+    redisless_server_port: ['ushort', ['void*']],
     redisless_server_start: ['bool', ['void*']],
-    redisless_server_stop: ['bool', ['void*']]
+    redisless_server_stop: ['bool', ['void*']],
+    redisless_capabilities: ['pointer', []],
+    redisless_capabilities_free: ['void', ['pointer']]
 });
 
 function RedisLess(port = 16379) {
@@ -26,6 +31,21 @@ function RedisLess(port = 16379) {
     return this;
 }
 
+// listen on the first free port from `firstPort` to `lastPort`, see `port()`, the system picks
+// one with the defaults
+RedisLess.onFreePort = function (firstPort = 0, lastPort = 0) {
+    const redisless = Object.create(RedisLess.prototype);
+    redisless.server = libm.redisless_server_new_on_free_port(firstPort, lastPort);
+    if (redisless.server.isNull()) {
+        throw new Error(`no free port from ${firstPort} to ${lastPort}`);
+    }
+    return redisless;
+};
+
+RedisLess.prototype.port = function () {
+    return libm.redisless_server_port(this.server);
+};
+
 RedisLess.prototype.start = function () {
     return libm.redisless_server_start(this.server);
 };
@@ -34,4 +54,17 @@ RedisLess.prototype.stop = function () {
     return libm.redisless_server_stop(this.server);
 };
 
+RedisLess.prototype.capabilities = function () {
+    const raw = libm.redisless_capabilities();
+    try {
+        return JSON.parse(ref.readCString(raw, 0));
+    } finally {
+        libm.redisless_capabilities_free(raw);
+    }
+};
+
+RedisLess.prototype.supports = function (command) {
+    return this.capabilities().commands.includes(command.toLowerCase());
+};
+
 exports.RedisLess = RedisLess;
@@ -25,3 +25,26 @@ test('exec set get delete commands', async (done) => {
 
     done();
 });
+
+test('free port', async (done) => {
+    const redisless = RedisLess.onFreePort();
+    const port = redisless.port();
+    strictEqual(port > 0, true);
+
+    strictEqual(redisless.start(), true);
+    const r = redis.createClient({host: 'localhost', port: port});
+    strictEqual(await r.ping(), 'PONG');
+    strictEqual(redisless.stop(), true);
+
+    done();
+});
+
+test('capabilities', () => {
+    const redisless = new RedisLess(16380);
+    const capabilities = redisless.capabilities();
+
+    strictEqual(typeof capabilities.version, 'string');
+    strictEqual(capabilities.commands.includes('get'), true);
+    strictEqual(redisless.supports('SET'), true);
+    strictEqual(redisless.supports('SUBSCRIBE'), false);
+});
@@ -7,6 +7,7 @@ if __name__ == '__main__':
     port = 16379
 
     redisless = RedisLess(port=port)
+    assert redisless.supports('GET')
     assert redisless.start()
 
     redis = redis.Redis(host='127.0.0.1', port=port)
@@ -28,3 +29,8 @@ if __name__ == '__main__':
         assert redis.delete('key') == 1
 
     assert redisless.stop()
+
+    redisless = RedisLess(free_ports=range(0, 1))
+    assert redisless.start()
+    assert redis.Redis(host='127.0.0.1', port=redisless.port()).ping()
+    assert redisless.stop()
@@ -1,3 +1,4 @@
+import json
 from os.path import dirname, abspath
 from sys import platform
 
@@ -10,7 +11,12 @@ class RedisLess(object):
     compatible with the Redis API.
     """
 
-    def __init__(self, port: int = 16379):
+    def __init__(self, port: int = 16379, free_ports: range = None):
+        """
+        :param port: port to listen on
+        :param free_ports: listen instead on the first free port of this range, see `port()`; with
+            `range(0, 1)` the system picks one
+        """
         ffi = FFI()
 
         c_def = """
@@ -18,9 +24,13 @@ class RedisLess(object):
             typedef void* server;
             
             server redisless_server_new(unsigned short);
+            server redisless_server_new_on_free_port(unsigned short, unsigned short);
+            unsigned short redisless_server_port(void* server);
             void redisless_server_free(void* server);
             bool redisless_server_start(void* server);
             bool redisless_server_stop(void* server);
+            char* redisless_capabilities();
+            void redisless_capabilities_free(char* capabilities);
         """
 
         # ffi.set_source("c.binding", c_def)
@@ -41,11 +51,24 @@ class RedisLess(object):
             exit(1)
 
         source_path = dirname(abspath(__file__))
+        self._ffi = ffi
         self._C = ffi.dlopen("{}/libredisless.{}".format(source_path, lib_extension))
-        self._redisless_server = self._C.redisless_server_new(port)
+        if free_ports is None:
+            self._redisless_server = self._C.redisless_server_new(port)
+        else:
+            self._redisless_server = self._C.redisless_server_new_on_free_port(
+                free_ports.start, free_ports.stop - 1)
+            if self._redisless_server == ffi.NULL:
+                raise OSError('no free port in {}'.format(free_ports))
 
     # TODO implement destructor and free redisless
 
+    def port(self) -> int:
+        """
+        :return: the port RedisLess listens on
+        """
+        return self._C.redisless_server_port(self._redisless_server)
+
     def start(self) -> bool:
         """
         Start local embedded RedisLess instance
@@ -59,3 +82,20 @@ class RedisLess(object):
         :return: true if RedisLess instance has been stopped correctly; false otherwise
         """
         return self._C.redisless_server_stop(self._redisless_server)
+
+    def capabilities(self) -> dict:
+        """
+        Commands and features supported by the loaded RedisLess library
+        :return: a dict with the `version`, the lowercase `commands` and the `features`
+        """
+        raw = self._C.redisless_capabilities()
+        try:
+            return json.loads(self._ffi.string(raw).decode('utf-8'))
+        finally:
+            self._C.redisless_capabilities_free(raw)
+
+    def supports(self, command: str) -> bool:
+        """
+        :return: true if the loaded RedisLess library handles `command`; false otherwise
+        """
+        return command.lower() in self.capabilities()['commands']
@@ -1,10 +1,15 @@
 use std::io::{Read, Write};
 use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
 
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
 
-use redisless::server::{Server, ServerState};
+use redisless::server::{Server, ServerConfig, ServerState};
 use redisless::storage::in_memory::InMemoryStorage;
+use redisless::storage::striped::DEFAULT_STRIPES;
+use redisless::storage::Storage;
 
 fn criterion_benchmarks(c: &mut Criterion) {
     let port = 3335;
@@ -23,9 +28,9 @@ fn criterion_benchmarks(c: &mut Criterion) {
 
             // run command `GET mykey`
             let _ = stream.write(b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n");
-            let mut get_res = [0; 8];
+            let mut get_res = [0; 11];
             let _ = stream.read(&mut get_res);
-            assert_eq!(get_res, b"+value\r\n"[..]);
+            assert_eq!(get_res, b"$5\r\nvalue\r\n"[..]);
 
             // run command `DEL mykey`
             let _ = stream.write(b"*2\r\n$3\r\nDEL\r\n$5\r\nmykey\r\n");
@@ -38,5 +43,221 @@ fn criterion_benchmarks(c: &mut Criterion) {
     assert_eq!(server.stop(), Some(ServerState::Stopped));
 }
 
-criterion_group!(benches, criterion_benchmarks);
+fn pipelining_benchmarks(c: &mut Criterion) {
+    let port = 3336;
+    let server = Server::new(InMemoryStorage::new(), port);
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    let mut stream = TcpStream::connect(format!("localhost:{}", port)).unwrap();
+
+    // what `redis-benchmark -t set,get -P 16` sends
+    let pipeline = 16;
+    let set = b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$5\r\nvalue\r\n".repeat(pipeline);
+    let get = b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n".repeat(pipeline);
+    let mut set_res = vec![0; b"+OK\r\n".len() * pipeline];
+    let mut get_res = vec![0; b"$5\r\nvalue\r\n".len() * pipeline];
+
+    c.bench_function("set and get, 16 pipelined requests", |b| {
+        b.iter(|| {
+            stream.write_all(&set).unwrap();
+            stream.read_exact(&mut set_res).unwrap();
+
+            stream.write_all(&get).unwrap();
+            stream.read_exact(&mut get_res).unwrap();
+        });
+    });
+
+    // a full batch handed to a worker at once, where queuing the requests weighs the most
+    let pipeline = 128;
+    let set = b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$5\r\nvalue\r\n".repeat(pipeline);
+    let get = b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n".repeat(pipeline);
+    let mut set_res = vec![0; b"+OK\r\n".len() * pipeline];
+    let mut get_res = vec![0; b"$5\r\nvalue\r\n".len() * pipeline];
+
+    let mut group = c.benchmark_group("request path");
+    group.throughput(Throughput::Elements(2 * pipeline as u64));
+    group.bench_function("set and get, 128 pipelined requests", |b| {
+        b.iter(|| {
+            stream.write_all(&set).unwrap();
+            stream.read_exact(&mut set_res).unwrap();
+
+            stream.write_all(&get).unwrap();
+            stream.read_exact(&mut get_res).unwrap();
+        });
+    });
+    group.finish();
+
+    // values larger than a single read
+    let value = vec![b'x'; 64 * 1024];
+    let mut set = format!("*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n${}\r\n", value.len()).into_bytes();
+    set.extend_from_slice(&value);
+    set.extend_from_slice(b"\r\n");
+    let get = b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n";
+    let mut set_res = [0; 5];
+    let mut get_res = vec![0; format!("${}\r\n", value.len()).len() + value.len() + 2];
+
+    c.bench_function("set and get a 64KB value", |b| {
+        b.iter(|| {
+            stream.write_all(&set).unwrap();
+            stream.read_exact(&mut set_res).unwrap();
+
+            stream.write_all(get).unwrap();
+            stream.read_exact(&mut get_res).unwrap();
+        });
+    });
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+/// GET latency while other clients read a large list and write keys of their own, with the
+/// whole storage behind a single lock, then split into stripes locked on their own
+fn contention_benchmarks(c: &mut Criterion) {
+    for (port, stripes) in [(3337, 1), (3338, DEFAULT_STRIPES)] {
+        let config = ServerConfig {
+            storage_stripes: stripes,
+            ..ServerConfig::default()
+        };
+        let server = Server::new_with_config(InMemoryStorage::new(), config, port);
+        assert_eq!(server.start(), Some(ServerState::Started));
+
+        let mut stream = TcpStream::connect(format!("localhost:{}", port)).unwrap();
+        stream
+            .write_all(b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$5\r\nvalue\r\n")
+            .unwrap();
+        let mut set_res = [0; 5];
+        stream.read_exact(&mut set_res).unwrap();
+
+        let mut rpush = b"*10002\r\n$5\r\nRPUSH\r\n$6\r\nmylist\r\n".to_vec();
+        for _ in 0..10_000 {
+            rpush.extend_from_slice(b"$5\r\nvalue\r\n");
+        }
+        stream.write_all(&rpush).unwrap();
+        let mut rpush_res = [0; 8];
+        stream.read_exact(&mut rpush_res).unwrap();
+        assert_eq!(rpush_res, b":10000\r\n"[..]);
+
+        let get = b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n";
+        let mut get_res = [0; 11];
+        c.bench_function(&format!("get, uncontended, {} stripes", stripes), |b| {
+            b.iter(|| {
+                stream.write_all(get).unwrap();
+                stream.read_exact(&mut get_res).unwrap();
+            });
+        });
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let lrange = b"*4\r\n$6\r\nLRANGE\r\n$6\r\nmylist\r\n$1\r\n0\r\n$2\r\n-1\r\n".to_vec();
+        let lrange_res_len = b"*10000\r\n".len() + 10_000 * b"$5\r\nvalue\r\n".len();
+        // MSET takes the stripes of both its keys, in the same order as every other command
+        let mset =
+            b"*5\r\n$4\r\nMSET\r\n$4\r\nkey1\r\n$1\r\n1\r\n$4\r\nkey2\r\n$1\r\n2\r\n".to_vec();
+        let workload = [
+            (lrange.clone(), lrange_res_len),
+            (lrange, lrange_res_len),
+            (mset, 5),
+        ];
+        let clients: Vec<_> = workload
+            .iter()
+            .cloned()
+            .map(|(request, reply_len)| {
+                let stop = stop.clone();
+                let mut stream = TcpStream::connect(format!("localhost:{}", port)).unwrap();
+                thread::spawn(move || {
+                    let mut reply = vec![0; reply_len];
+                    while !stop.load(Ordering::SeqCst) {
+                        stream.write_all(&request).unwrap();
+                        stream.read_exact(&mut reply).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        c.bench_function(
+            &format!(
+                "get while 2 clients read a 10k elements list and 1 runs mset, {} stripes",
+                stripes
+            ),
+            |b| {
+                b.iter(|| {
+                    stream.write_all(get).unwrap();
+                    stream.read_exact(&mut get_res).unwrap();
+                });
+            },
+        );
+
+        stop.store(true, Ordering::SeqCst);
+        for client in clients {
+            client.join().unwrap();
+        }
+        assert_eq!(server.stop(), Some(ServerState::Stopped));
+    }
+}
+
+fn list_benchmarks(c: &mut Criterion) {
+    let mut storage = InMemoryStorage::new();
+    let values = (0..100_000).map(|i| i.to_string().into_bytes()).collect();
+    storage.lpush_back(b"mylist", values);
+
+    c.bench_function("lpush and rpop on a 100k elements list", |b| {
+        b.iter(|| {
+            storage.lpush_front(b"mylist", vec![b"value".to_vec()]);
+            assert!(storage.lpop_back(b"mylist").is_some());
+        });
+    });
+}
+
+fn string_benchmarks(c: &mut Criterion) {
+    let mut storage = InMemoryStorage::new();
+    storage.write(b"counter", b"0");
+    storage.write(b"mystring", &[b'a'; 64 * 1024]);
+
+    c.bench_function("incr in place", |b| {
+        b.iter(|| {
+            storage.update_in_place(b"counter", |value| {
+                let n: i64 = std::str::from_utf8(value).unwrap().parse().unwrap();
+                *value = (n + 1).to_string().into_bytes();
+            });
+        });
+    });
+
+    c.bench_function("incr by read and write", |b| {
+        b.iter(|| {
+            let n: i64 = std::str::from_utf8(storage.read(b"counter").unwrap())
+                .unwrap()
+                .parse()
+                .unwrap();
+            storage.write(b"counter", (n + 1).to_string().as_bytes());
+        });
+    });
+
+    c.bench_function("overwrite the end of a 64KB string in place", |b| {
+        b.iter(|| {
+            storage.update_in_place(b"mystring", |value| {
+                let len = value.len();
+                value[len - 5..].copy_from_slice(b"value");
+            });
+        });
+    });
+
+    c.bench_function(
+        "overwrite the end of a 64KB string by read and write",
+        |b| {
+            b.iter(|| {
+                let mut value = storage.read(b"mystring").unwrap().to_vec();
+                let len = value.len();
+                value[len - 5..].copy_from_slice(b"value");
+                storage.write(b"mystring", &value);
+            });
+        },
+    );
+}
+
+criterion_group!(
+    benches,
+    criterion_benchmarks,
+    pipelining_benchmarks,
+    contention_benchmarks,
+    list_benchmarks,
+    string_benchmarks
+);
 criterion_main!(benches);
@@ -1,7 +1,11 @@
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{bounded, unbounded, Receiver, Select, Sender, TryRecvError};
+
+type Subscribers<X> = Arc<Mutex<Vec<(usize, Sender<X>)>>>;
 
 /// Multi-Producer Broadcast to do many to many (N*N) message passing.
 pub struct MPB<X>
@@ -9,7 +13,11 @@ where
     X: Clone + Send + Sync + 'static,
 {
     sender: Sender<X>,
-    internal_senders: Arc<Mutex<Vec<Sender<X>>>>,
+    capacity: Option<usize>,
+    internal_senders: Subscribers<X>,
+    next_receiver_id: AtomicUsize,
+    // dropping it stops the forwarding thread
+    _shutdown: Sender<()>,
 }
 
 impl<X> MPB<X>
@@ -17,30 +25,60 @@ where
     X: Clone + Send + Sync + 'static,
 {
     pub fn new() -> Self {
-        let (sender, receiver) = unbounded::<X>();
+        MPB::with_capacity(None)
+    }
+
+    /// Every channel holds at most `capacity` messages: senders block while the forwarding
+    /// thread waits for the slowest receiver to catch up.
+    pub fn bounded(capacity: usize) -> Self {
+        MPB::with_capacity(Some(capacity))
+    }
+
+    fn with_capacity(capacity: Option<usize>) -> Self {
+        let (sender, receiver) = channel::<X>(capacity);
+        let (shutdown_sender, shutdown_receiver) = bounded::<()>(0);
 
         let mpb = MPB {
             sender,
+            capacity,
             internal_senders: Arc::new(Mutex::new(vec![])),
+            next_receiver_id: AtomicUsize::new(0),
+            _shutdown: shutdown_sender,
         };
 
-        mpb._init(receiver);
+        mpb._init(receiver, shutdown_receiver);
 
         mpb
     }
 
-    fn _init(&self, receiver: Receiver<X>) {
+    fn _init(&self, receiver: Receiver<X>, shutdown_receiver: Receiver<()>) {
         let internal_senders = self.internal_senders.clone();
 
         let _ = thread::spawn(move || {
-            for msg in receiver {
-                match internal_senders.lock() {
-                    Ok(senders) => {
-                        for sender in senders.iter() {
-                            let _ = sender.send(msg.clone());
-                        }
-                    }
-                    Err(_) => {} // TODO manage deadlock
+            let mut select = Select::new();
+            let msg_ready = select.recv(&receiver);
+            let _shutdown_ready = select.recv(&shutdown_receiver);
+
+            loop {
+                if select.ready() != msg_ready {
+                    // the MPB has been dropped
+                    return;
+                }
+
+                let msg = match receiver.try_recv() {
+                    Ok(msg) => msg,
+                    Err(TryRecvError::Empty) => continue,
+                    Err(TryRecvError::Disconnected) => return,
+                };
+
+                // don't hold the lock while a bounded receiver applies back-pressure
+                let senders = match internal_senders.lock() {
+                    Ok(senders) => senders.iter().map(|(_, s)| s.clone()).collect::<Vec<_>>(),
+                    Err(_) => continue, // TODO manage deadlock
+                };
+
+                for sender in senders.iter() {
+                    let _ = sender.send(msg.clone());
                 }
             }
         });
@@ -50,23 +88,76 @@ where
         self.sender.clone()
     }
 
-    pub fn receiver(&self) -> Receiver<X> {
-        let (sender, receiver) = unbounded();
+    /// Subscribe to every message sent from now on, until the returned receiver is dropped
+    pub fn receiver(&self) -> MPBReceiver<X> {
+        let (sender, receiver) = channel(self.capacity);
+        let id = self.next_receiver_id.fetch_add(1, Ordering::SeqCst);
 
         match self.internal_senders.lock() {
             Ok(mut s) => {
-                s.push(sender);
+                s.push((id, sender));
             }
             Err(_) => {} // TODO manage deadlock
         }
 
-        receiver
+        MPBReceiver {
+            id,
+            receiver,
+            internal_senders: self.internal_senders.clone(),
+        }
+    }
+
+    /// Number of live receivers
+    pub fn receiver_count(&self) -> usize {
+        self.internal_senders.lock().map(|s| s.len()).unwrap_or(0)
+    }
+}
+
+impl<X> Default for MPB<X>
+where
+    X: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        MPB::new()
+    }
+}
+
+/// Receiving end of an `MPB`, unsubscribed when dropped.
+pub struct MPBReceiver<X> {
+    id: usize,
+    receiver: Receiver<X>,
+    internal_senders: Subscribers<X>,
+}
+
+impl<X> Deref for MPBReceiver<X> {
+    type Target = Receiver<X>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.receiver
+    }
+}
+
+impl<X> Drop for MPBReceiver<X> {
+    fn drop(&mut self) {
+        if let Ok(mut s) = self.internal_senders.lock() {
+            s.retain(|(id, _)| *id != self.id);
+        }
+    }
+}
+
+fn channel<X>(capacity: Option<usize>) -> (Sender<X>, Receiver<X>) {
+    match capacity {
+        Some(capacity) => bounded(capacity),
+        None => unbounded(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::thread;
+    use std::time::Duration;
+
+    use crossbeam_channel::TrySendError;
 
     use crate::MPB;
 
@@ -98,4 +189,58 @@ mod tests {
         let _ = j1.join();
         let _ = j2.join();
     }
+
+    #[test]
+    fn receiver_unsubscribes_on_drop() {
+        let mpb = MPB::<&str>::new();
+
+        let receiver1 = mpb.receiver();
+        let receiver2 = mpb.receiver();
+        assert_eq!(mpb.receiver_count(), 2);
+
+        drop(receiver1);
+        assert_eq!(mpb.receiver_count(), 1);
+
+        let _ = mpb.sender().send("hello");
+        assert_eq!(receiver2.recv_timeout(Duration::from_secs(1)), Ok("hello"));
+
+        drop(receiver2);
+        assert_eq!(mpb.receiver_count(), 0);
+    }
+
+    #[test]
+    fn bounded_applies_back_pressure() {
+        let mpb = MPB::bounded(1);
+        let sender = mpb.sender();
+        let receiver = mpb.receiver();
+
+        // one message waits in the receiver, one in the forwarding thread, one in the bus
+        for _ in 0..3 {
+            while let Err(TrySendError::Full(_)) = sender.try_send("hello") {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert!(sender.try_send("hello").is_err());
+
+        assert_eq!(receiver.recv(), Ok("hello"));
+        assert_eq!(receiver.recv(), Ok("hello"));
+        assert_eq!(receiver.recv(), Ok("hello"));
+    }
+
+    #[test]
+    fn forwarding_thread_stops_on_drop() {
+        let mpb = MPB::new();
+        let sender = mpb.sender();
+        drop(mpb);
+
+        // the bus receiver is dropped along with the forwarding thread
+        for _ in 0..100 {
+            if sender.send("hello").is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("forwarding thread is still running");
+    }
 }
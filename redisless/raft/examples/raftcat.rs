@@ -21,6 +21,8 @@ const RAFT_CONFIG: Config = Config {
     election_timeout_ticks: 10,
     heartbeat_interval_ticks: 5,
     replication_chunk_size: 65536,
+    check_quorum: false,
+    checksum_entries: false,
 };
 
 type NodeId = String;
@@ -24,6 +24,8 @@ fn main() {
                     election_timeout_ticks: 10,
                     heartbeat_interval_ticks: 1,
                     replication_chunk_size: usize::max_value(),
+                    check_quorum: false,
+                    checksum_entries: false,
                 },
             )
         })
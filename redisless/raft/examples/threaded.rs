@@ -20,6 +20,8 @@ const RAFT_CONFIG: Config = Config {
     election_timeout_ticks: 10,
     heartbeat_interval_ticks: 1,
     replication_chunk_size: usize::max_value(),
+    check_quorum: false,
+    checksum_entries: false,
 };
 
 #[derive(Clone)]
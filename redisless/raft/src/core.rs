@@ -10,7 +10,7 @@ use rand_core::RngCore;
 
 use crate::log::{CommittedIter, Log, LogState};
 use crate::message::*;
-use crate::node::{AppendError, Config};
+use crate::node::{AppendError, Config, MessageCounts, Metrics, Role};
 use crate::prelude::*;
 
 use self::LeadershipState::*;
@@ -36,6 +36,12 @@ pub struct ReplicationState {
 
     /// Whether a heartbeat "ping" message is due to be sent to this peer.
     send_heartbeat: bool,
+
+    /// The commit index last sent to this peer, so that it's sent a heartbeat as soon as it's behind.
+    sent_commit_idx: LogIndex,
+
+    /// Whether this peer responded since the last quorum check.
+    active: bool,
 }
 
 // \* Server states.
@@ -48,6 +54,8 @@ enum LeadershipState<NodeId> {
 
 struct FollowerState<NodeId> {
     leader: Option<NodeId>,
+    // ticks left of the leader's lease, as of its last append request
+    lease_ticks: Option<u32>,
 
     election_ticks: u32,
     random_election_ticks: u32,
@@ -66,12 +74,24 @@ struct LeaderState<NodeId> {
     followers: BTreeMap<NodeId, ReplicationState>,
 
     heartbeat_ticks: u32,
+    quorum_ticks: u32,
+
+    transfer: Option<Transfer<NodeId>>,
+}
+
+struct Transfer<NodeId> {
+    to: NodeId,
+    // ticks left before the transfer is abandoned
+    ticks: u32,
+    timeout_now_sent: bool,
 }
 
 /// The complete state of a Raft node.
 pub struct State<L, Random, NodeId> {
     node_id: NodeId,
     peers: BTreeSet<NodeId>,
+    // replicated to but not voting, this node itself included if it's one
+    learners: BTreeSet<NodeId>,
     random: Random,
     config: Config,
 
@@ -95,6 +115,10 @@ pub struct State<L, Random, NodeId> {
     // \* The index of the latest entry in the log the state machine may apply.
     // VARIABLE commitIndex
     log: LogState<L>,
+
+    elections: u64,
+    sent: MessageCounts,
+    dropped: MessageCounts,
 }
 
 #[allow(missing_docs)]
@@ -117,6 +141,7 @@ where
         Self {
             node_id,
             peers,
+            learners: BTreeSet::new(),
             random,
             config,
             log: LogState::new(log),
@@ -124,9 +149,13 @@ where
             voted_for: Default::default(),
             leadership: Follower(FollowerState {
                 leader: None,
+                lease_ticks: None,
                 election_ticks: random_election_ticks,
                 random_election_ticks,
             }),
+            elections: 0,
+            sent: Default::default(),
+            dropped: Default::default(),
         }
     }
 
@@ -146,6 +175,10 @@ where
         }
     }
 
+    pub fn is_learner(&self) -> bool {
+        self.learners.contains(&self.node_id)
+    }
+
     pub fn leader(&self) -> (Option<&NodeId>, &TermId) {
         let leader = match &self.leadership {
             Follower(follower_state) => follower_state.leader.as_ref(),
@@ -163,6 +196,25 @@ where
         self.log.log_mut()
     }
 
+    pub fn metrics(&self) -> Metrics {
+        let (role, votes_received) = match &self.leadership {
+            Follower(_) if self.is_learner() => (Role::Learner, 0),
+            Follower(_) => (Role::Follower, 0),
+            Candidate(candidate_state) => (Role::Candidate, candidate_state.votes_granted.len()),
+            Leader(_) => (Role::Leader, 0),
+        };
+        Metrics {
+            current_term: self.current_term,
+            commit_idx: self.log.commit_idx,
+            last_applied_idx: self.log.log().last_taken_index(),
+            role,
+            votes_received,
+            elections: self.elections,
+            sent: self.sent,
+            dropped: self.dropped,
+        }
+    }
+
     pub fn node_id(&self) -> &NodeId {
         &self.node_id
     }
@@ -171,6 +223,63 @@ where
         &self.peers
     }
 
+    pub fn learners(&self) -> &BTreeSet<NodeId> {
+        &self.learners
+    }
+
+    pub fn leader_lease_ticks(&self) -> Option<u32> {
+        match &self.leadership {
+            Follower(follower_state) if follower_state.leader.is_some() => {
+                follower_state.lease_ticks
+            }
+            Follower(_) | Candidate(_) => None,
+            Leader(leader_state) => match self.config.check_quorum {
+                true => Some(leader_state.quorum_ticks),
+                false => None,
+            },
+        }
+    }
+
+    pub fn add_learner(&mut self, node_id: NodeId) -> bool {
+        let is_self = node_id == self.node_id;
+        if self.peers.contains(&node_id) || self.learners.contains(&node_id) {
+            return false;
+        }
+        if is_self && !matches!(self.leadership, Follower(_)) {
+            return false;
+        }
+
+        info!("added learner {}", &node_id);
+        if let Leader(leader_state) = &mut self.leadership {
+            leader_state.followers.insert(
+                node_id.clone(),
+                ReplicationState {
+                    next_idx: self.log.last_index() + 1,
+                    match_idx: Default::default(),
+                    inflight: Default::default(),
+                    send_probe: Default::default(),
+                    send_heartbeat: true,
+                    sent_commit_idx: Default::default(),
+                    active: Default::default(),
+                },
+            );
+        }
+        self.learners.insert(node_id);
+        true
+    }
+
+    pub fn promote_learner(&mut self, node_id: NodeId) -> bool {
+        if !self.learners.remove(&node_id) {
+            return false;
+        }
+
+        info!("promoted learner {} to voter", &node_id);
+        if node_id != self.node_id {
+            self.peers.insert(node_id);
+        }
+        true
+    }
+
     pub fn replication_state(&self, peer_node_id: &NodeId) -> Option<&ReplicationState> {
         if let LeadershipState::Leader(leader_state) = &self.leadership {
             leader_state.followers.get(peer_node_id)
@@ -179,6 +288,28 @@ where
         }
     }
 
+    pub fn needs_snapshot(&self, peer_node_id: &NodeId) -> bool {
+        match self.replication_state(peer_node_id) {
+            // the entry before the next one to send is needed to check the peer's log matches
+            Some(replication) => replication.next_idx <= self.log.prev_index(),
+            None => false,
+        }
+    }
+
+    pub fn install_snapshot(&mut self, log: L) -> Result<(), L> {
+        if self.is_leader() || log.last_taken_index() <= self.log.commit_idx {
+            return Err(log);
+        }
+
+        info!(
+            "installed snapshot up to {} at {}",
+            log.last_taken_index(),
+            log.prev_term()
+        );
+        self.log = LogState::new(log);
+        Ok(())
+    }
+
     pub fn set_config(&mut self, config: Config) {
         self.config = config;
 
@@ -207,11 +338,16 @@ where
                 }
             }
             Leader(LeaderState {
-                heartbeat_ticks, ..
+                heartbeat_ticks,
+                quorum_ticks,
+                ..
             }) => {
                 if *heartbeat_ticks > self.config.heartbeat_interval_ticks {
                     *heartbeat_ticks = self.config.heartbeat_interval_ticks;
                 }
+                if *quorum_ticks > self.config.election_timeout_ticks {
+                    *quorum_ticks = self.config.election_timeout_ticks;
+                }
             }
         }
     }
@@ -221,7 +357,16 @@ where
     }
 
     pub fn timer_tick(&mut self) -> Option<SendableMessage<NodeId>> {
+        if let Follower(FollowerState {
+            lease_ticks: Some(lease_ticks),
+            ..
+        }) = &mut self.leadership
+        {
+            *lease_ticks = lease_ticks.saturating_sub(1);
+        }
         match &mut self.leadership {
+            // learners never start elections
+            Follower(_) if self.learners.contains(&self.node_id) => None,
             Follower(FollowerState { election_ticks, .. })
             | Candidate(CandidateState { election_ticks, .. }) => {
                 match election_ticks.saturating_sub(1) {
@@ -248,11 +393,74 @@ where
                         leader_state.heartbeat_ticks = new_heartbeat_ticks;
                     }
                 }
+
+                let quorum_lost = self.config.check_quorum
+                    && match leader_state.quorum_ticks.saturating_sub(1) {
+                        0 => {
+                            leader_state.quorum_ticks = self.config.election_timeout_ticks;
+                            // this node counts towards its own quorum
+                            let mut active = 1;
+                            for (node_id, replication) in &mut leader_state.followers {
+                                if replication.active && self.peers.contains(node_id) {
+                                    active += 1;
+                                }
+                                replication.active = false;
+                            }
+                            active < quorum_size(self.peers.len())
+                        }
+                        new_quorum_ticks => {
+                            leader_state.quorum_ticks = new_quorum_ticks;
+                            false
+                        }
+                    };
+                if let Some(transfer) = &mut leader_state.transfer {
+                    transfer.ticks = transfer.ticks.saturating_sub(1);
+                    if transfer.ticks == 0 {
+                        info!(
+                            "abandoned leadership transfer at {} to {}",
+                            &self.current_term, &transfer.to
+                        );
+                        leader_state.transfer = None;
+                    }
+                }
+
+                if quorum_lost {
+                    info!(
+                        "stepped down at {} without responses from a quorum",
+                        &self.current_term
+                    );
+                    let random_election_ticks = self.random_election_timeout();
+                    self.leadership = Follower(FollowerState {
+                        leader: None,
+                        lease_ticks: None,
+                        election_ticks: random_election_ticks,
+                        random_election_ticks,
+                    });
+                }
                 None
             }
         }
     }
 
+    pub fn transfer_leadership(&mut self, to: NodeId) -> Option<SendableMessage<NodeId>> {
+        if !self.peers.contains(&to) {
+            return None;
+        }
+        if let Leader(leader_state) = &mut self.leadership {
+            info!(
+                "transferring leadership at {} to {}",
+                &self.current_term, &to
+            );
+            leader_state.transfer = Some(Transfer {
+                to,
+                ticks: self.config.election_timeout_ticks,
+                timeout_now_sent: false,
+            });
+        }
+        let timeout_now = self.timeout_now();
+        self.count_sent(timeout_now)
+    }
+
     pub fn reset_peer(&mut self, peer_node_id: NodeId) -> Option<SendableMessage<NodeId>> {
         match &mut self.leadership {
             Follower(_) => None,
@@ -260,10 +468,10 @@ where
                 if self.peers.contains(&peer_node_id) {
                     let vote_request = self.request_vote();
                     let from = peer_node_id;
-                    vote_request.map(|message| SendableMessage {
+                    self.count_sent(vote_request.map(|message| SendableMessage {
                         message,
                         dest: MessageDestination::To(from),
-                    })
+                    }))
                 } else {
                     None
                 }
@@ -274,6 +482,7 @@ where
                     replication.next_idx = self.log.last_index() + 1;
                     replication.send_probe = true;
                     replication.send_heartbeat = true;
+                    replication.sent_commit_idx = Default::default();
                     replication.inflight = None;
                 }
                 None
@@ -295,12 +504,22 @@ where
     // \* Server i times out and starts a new election.
     pub fn timeout(&mut self) -> Option<SendableMessage<NodeId>> {
         // Timeout(i) ==
+        if self.is_learner() {
+            return None;
+        }
         match &self.leadership {
             Follower(_) | Candidate(_) => {
                 // /\ state[i] \in {Follower, Candidate}
-                self.current_term += 1; // /\ currentTerm' = [currentTerm EXCEPT ![i] = currentTerm[i] + 1]
-                                        // \* Most implementations would probably just set the local vote
-                                        // \* atomically, but messaging localhost for it is weaker.
+                self.current_term = match self.current_term.checked_add(1) {
+                    // /\ currentTerm' = [currentTerm EXCEPT ![i] = currentTerm[i] + 1]
+                    Some(next_term) => next_term,
+                    None => {
+                        error!("can't start an election after {}", &self.current_term);
+                        return None;
+                    }
+                };
+                // \* Most implementations would probably just set the local vote
+                // \* atomically, but messaging localhost for it is weaker.
                 self.voted_for = Some(self.node_id.clone()); // /\ votedFor' = [votedFor EXCEPT ![i] = Nil]
                 let votes_granted = iter::once(self.node_id.clone()).collect(); // /\ votesGranted'   = [votesGranted EXCEPT ![i] = {}]
                 self.leadership = Candidate(CandidateState {
@@ -309,13 +528,16 @@ where
                     election_ticks: self.random_election_timeout(),
                 });
 
+                self.elections = self.elections.saturating_add(1);
+
                 info!("became candidate at {}", self.current_term);
                 self.become_leader();
                 self.advance_commit_idx();
-                self.request_vote().map(|message| SendableMessage {
+                let vote_request = self.request_vote().map(|message| SendableMessage {
                     message,
                     dest: MessageDestination::Broadcast,
-                })
+                });
+                self.count_sent(vote_request)
             }
             Leader(_) => None,
         }
@@ -357,7 +579,10 @@ where
             let last_log_idx = self.log.last_index();
             let next_idx = replication.next_idx;
             let send_entries = (last_log_idx >= next_idx && !replication.send_probe);
-            if !send_entries && !replication.send_heartbeat {
+            // rather than waiting for the next heartbeat, tell the peer as soon as entries it has are committed
+            let send_commit = !replication.send_probe
+                && self.log.commit_idx.min(next_idx - 1) > replication.sent_commit_idx;
+            if !send_entries && !replication.send_heartbeat && !send_commit {
                 return None;
             }
             if replication.inflight.is_some() {
@@ -392,6 +617,13 @@ where
                 for entry_log_idx in entry_log_idxs {
                     //        entries == SubSeq(log[i], nextIndex[i][j], lastEntry)
                     let append_log_entry = if let Some(log_entry) = self.log.get(entry_log_idx) {
+                        if self.config.checksum_entries && !log_entry.is_intact() {
+                            error!(
+                                "corrupted raft log {} not sent to {}!",
+                                &entry_log_idx, &to_node_id
+                            );
+                            break;
+                        }
                         let first_entry = entries_size == 0;
                         if !first_entry && entries_size == max_entries_size {
                             None
@@ -421,6 +653,11 @@ where
             } else {
                 last_entry = prev_log_idx;
             }
+            let leader_commit = self.log.commit_idx.min(last_entry);
+            let lease_ticks = match self.config.check_quorum {
+                true => Some(leader_state.quorum_ticks),
+                false => None,
+            };
             let append_request_msg = Message {
                 //    IN Send([
                 term: self.current_term, //             mterm          |-> currentTerm[i],
@@ -429,15 +666,17 @@ where
                     prev_log_idx,  //             mprevLogIndex  |-> prevLogIndex,
                     prev_log_term, //             mprevLogTerm   |-> prevLogTerm,
                     entries,       //             mentries       |-> entries,
-                    leader_commit: self.log.commit_idx.min(last_entry), //             mcommitIndex   |-> Min({commitIndex[i], lastEntry}),
+                    leader_commit, //             mcommitIndex   |-> Min({commitIndex[i], lastEntry}),
+                    lease_ticks,
                 })),
             };
             replication.send_heartbeat = false;
+            replication.sent_commit_idx = replication.sent_commit_idx.max(leader_commit);
             replication.inflight = Some(last_entry);
-            Some(SendableMessage {
+            self.count_sent(Some(SendableMessage {
                 message: append_request_msg,
                 dest: MessageDestination::To(to_node_id),
-            })
+            }))
         } else {
             None
         }
@@ -453,7 +692,7 @@ where
                 info!("became leader at {}", &self.current_term);
                 self.leadership = Leader(LeaderState {
                     // /\ state'      = [state EXCEPT ![i] = Leader]
-                    followers: (self.peers.iter().cloned())
+                    followers: (self.peers.iter().chain(&self.learners).cloned())
                         .map(|id| {
                             (
                                 id,
@@ -463,11 +702,15 @@ where
                                     inflight: Default::default(),
                                     send_probe: Default::default(),
                                     send_heartbeat: Default::default(),
+                                    sent_commit_idx: Default::default(),
+                                    active: Default::default(),
                                 },
                             )
                         })
                         .collect(),
                     heartbeat_ticks: 0,
+                    quorum_ticks: self.config.election_timeout_ticks,
+                    transfer: None,
                 });
                 // append a noop in the new term to commit entries from past terms (Raft Section 5.4.2)
                 let _ignore = self.client_request(Default::default());
@@ -478,11 +721,22 @@ where
     // \* Leader i receives a client request to add v to the log.
     pub fn client_request(&mut self, data: Bytes) -> Result<(), AppendError<L::Error>> {
         // ClientRequest(i, v) ==
-        let entry = LogEntry {
+        let mut entry = LogEntry {
             term: self.current_term, // /\ LET entry == [term  |-> currentTerm[i],
             data,                    //                  value |-> v]
+            checksum: None,
         };
+        if self.config.checksum_entries {
+            entry.checksum = Some(entry.compute_checksum());
+        }
 
+        if let Leader(LeaderState {
+            transfer: Some(_), ..
+        }) = &self.leadership
+        {
+            // the entry might not make it to the next leader
+            return Err(AppendError::Cancelled { data: entry.data });
+        }
         if let Leader(_) = &self.leadership {
             // /\ state[i] = Leader
             self.log.append(entry).map_err(AppendError::LogErr)?; //        newLog == Append(log[i], entry)
@@ -502,8 +756,9 @@ where
         if let Leader(leader_state) = &self.leadership {
             // /\ state[i] = Leader
             let mut match_idxs: Vec<_> =                                        // /\ LET \* The set of servers that agree up through index.
-                (leader_state.followers.values())
-                    .map(|follower| follower.match_idx)
+                (leader_state.followers.iter())
+                    .filter(|(node_id, _)| self.peers.contains(node_id))
+                    .map(|(_, follower)| follower.match_idx)
                     .chain(iter::once(self.log.last_index()))
                     .collect();
             match_idxs.sort_unstable(); //        Agree(index) == {i} \cup {k \in Server : matchIndex[i][k] >= index}
@@ -555,6 +810,7 @@ where
                     msg.last_log_idx >= last_log_idx); //        /\ m.mlastLogIndex >= Len(log[i])
         let grant =                                                             // LET grant ==
             msg_term == self.current_term &&                                    //     /\ m.mterm = currentTerm[i]
+                !self.is_learner() &&
                 log_ok &&                                                           //     /\ logOk
                 self.voted_for.as_ref().map(|vote| &from == vote).unwrap_or(true); //     /\ votedFor[i] \in {Nil, j}
         assert!(msg_term <= self.current_term); // IN /\ m.mterm <= currentTerm[i]
@@ -585,6 +841,11 @@ where
                 "ignored message with {} < current {}: {}",
                 &msg_term, &self.current_term, &msg
             );
+        } else if self.is_learner() {
+            info!(
+                "rejected vote at {} for node {} as a learner",
+                &self.current_term, &from
+            );
         } else if let Some(vote) = &self.voted_for {
             info!(
                 "rejected vote at {} for node {} as already voted for {}",
@@ -641,6 +902,8 @@ where
                     &from, &self.current_term
                 );
             }
+        } else {
+            self.dropped.vote_responses = self.dropped.vote_responses.saturating_add(1);
         }
         None
     }
@@ -661,6 +924,11 @@ where
         let our_prev_log_term = self.log.get_term(prev_log_idx);
         let log_ok = prev_log_idx == Default::default() ||                               // LET logOk == \/ m.mprevLogIndex = 0
             Some(msg_prev_log_term) == our_prev_log_term; //              \/ /\ m.mprevLogIndex > 0 /\ m.mprevLogIndex <= Len(log[i]) /\ m.mprevLogTerm = log[i][m.mprevLogIndex].term
+                                                          // corrupted entries are refused like a log mismatch, for the leader to send them again
+        let corrupted_entry = match self.config.checksum_entries {
+            true => msg.entries.iter().position(|entry| !entry.is_intact()),
+            false => None,
+        };
         assert!(msg_term <= self.current_term); // IN /\ m.mterm <= currentTerm[i]
                                                 //    /\ \/ \* return to follower state
         if msg_term == self.current_term {
@@ -672,6 +940,7 @@ where
                     self.leadership = Follower(FollowerState {
                         //          /\ state' = [state EXCEPT ![i] = Follower]
                         leader: Some(from.clone()),
+                        lease_ticks: msg.lease_ticks,
                         election_ticks: random_election_ticks,
                         random_election_ticks,
                     });
@@ -682,13 +951,16 @@ where
                         info!("became follower at {} of {}", &self.current_term, &from);
                     }
                     follower_state.leader = Some(from.clone());
+                    follower_state.lease_ticks = msg.lease_ticks;
                     follower_state.election_ticks = follower_state.random_election_ticks;
                 }
                 Leader { .. } => {
-                    panic!(
+                    error!(
                         "received append request as leader at {} from {}",
                         &self.current_term, &from
                     );
+                    self.dropped.append_requests = self.dropped.append_requests.saturating_add(1);
+                    return None;
                 }
             }
         }
@@ -696,7 +968,7 @@ where
         if msg_term < self.current_term ||                                     //             \/ m.mterm < currentTerm[i]
             (assert_true!(msg_term == self.current_term) &&                     //             \/ /\ m.mterm = currentTerm[i]
                 assert_match!(Follower(_) = &self.leadership) &&                   //                /\ state[i] = Follower
-                !log_ok)
+                (!log_ok || corrupted_entry.is_some()))
         //                /\ \lnot logOk
         {
             if msg_term < self.current_term {
@@ -704,6 +976,12 @@ where
                     "ignored message with {} < current {}: {}",
                     &msg_term, &self.current_term, &msg
                 );
+            } else if let Some(corrupted_entry) = corrupted_entry {
+                error!(
+                    "rejected append from {} with corrupted entry {}",
+                    &from,
+                    prev_log_idx + (corrupted_entry as u64 + 1)
+                );
             } else if let Some(our_prev_log_term) = our_prev_log_term {
                 warn!(
                     "rejected append from {} with {} at {}, we have {}",
@@ -744,14 +1022,22 @@ where
             let msg_entries_iter = (1..).map(|idx| prev_log_idx + idx).zip(msg.entries);
             let mut last_processed_idx = prev_log_idx;
             for (msg_entry_log_idx, msg_entry) in msg_entries_iter {
-                if msg_entry_log_idx == self.log.last_index() + 1 {
+                if msg_entry_log_idx <= self.log.prev_index() {
+                    // discarded once committed, e.g. as included in an installed snapshot
+                } else if msg_entry_log_idx == self.log.last_index() + 1 {
                     match self.log.append(msg_entry) {
                         Ok(()) => (),
                         Err(_) => break,
                     }
                 } else if let Some(our_entry_log_term) = self.log.get_term(msg_entry_log_idx) {
                     if our_entry_log_term != msg_entry.term {
-                        assert!(msg_entry_log_idx > self.log.commit_idx);
+                        if msg_entry_log_idx <= self.log.commit_idx {
+                            error!(
+                                "refused append from {} conflicting with committed {}",
+                                &from, &msg_entry_log_idx
+                            );
+                            break;
+                        }
                         match self.log.cancel_from(msg_entry_log_idx) {
                             Ok(cancelled_len) => info!(
                                 "cancelled {} transactions from {}",
@@ -812,8 +1098,15 @@ where
     ) -> Option<SendableMessage<NodeId>> {
         // HandleAppendEntriesResponse(i, j, m) ==
         assert!(msg_term == self.current_term); // /\ m.mterm = currentTerm[i]
-        if let Leader(leader_state) = &mut self.leadership {
+        let last_log_idx = self.log.last_index();
+        if msg.match_idx > last_log_idx {
+            error!(
+                "ignored append response from {} matching {} past {}",
+                &from, &msg.match_idx, &last_log_idx
+            );
+        } else if let Leader(leader_state) = &mut self.leadership {
             if let Some(replication) = leader_state.followers.get_mut(&from) {
+                replication.active = true;
                 if msg.success {
                     // /\ \/ /\ m.msuccess \* successful
                     if Some(msg.match_idx) >= replication.inflight {
@@ -863,11 +1156,40 @@ where
                         replication.next_idx = next_idx;
                     }
                 }
+                return self.timeout_now();
             }
         }
+        self.dropped.append_responses = self.dropped.append_responses.saturating_add(1);
         None
     }
 
+    // The leader transferring leadership asks the follower to start an election.
+    fn handle_timeout_now(
+        &mut self,
+        msg_term: TermId,
+        msg: TimeoutNow,
+        from: NodeId,
+    ) -> Option<SendableMessage<NodeId>> {
+        let from_leader = match &self.leadership {
+            Follower(follower_state) => follower_state.leader.as_ref() == Some(&from),
+            Candidate(_) | Leader(_) => false,
+        };
+        if msg_term == self.current_term && from_leader && !self.is_learner() {
+            info!(
+                "election requested at {} by leader {}",
+                &self.current_term, &from
+            );
+            self.timeout()
+        } else {
+            info!(
+                "ignored message at {} from {}, not following it: {}",
+                &self.current_term, &from, &msg
+            );
+            self.dropped.timeout_nows = self.dropped.timeout_nows.saturating_add(1);
+            None
+        }
+    }
+
     // \* Any RPC with a newer term causes the recipient to advance its term first.
     fn update_term(&mut self, from: &NodeId, msg: &Message) {
         // UpdateTerm(i, j, m) ==
@@ -888,6 +1210,7 @@ where
             self.leadership = Follower(FollowerState {
                 // /\ state'          = [state       EXCEPT ![i] = Follower]
                 leader: None,
+                lease_ticks: None,
                 election_ticks,
                 random_election_ticks,
             });
@@ -917,8 +1240,9 @@ where
     // /* Receive a message.
     pub fn receive(&mut self, msg: Message, from: NodeId) -> Option<SendableMessage<NodeId>> {
         // Receive(m) ==
-        if !self.peers.contains(&from) {
+        if !self.peers.contains(&from) && !self.learners.contains(&from) {
             error!("received raft message from {} for wrong group", &from);
+            self.count_dropped(&msg);
             return None;
         }
         // IN \* Any RPC with a newer term causes the recipient to advance
@@ -934,7 +1258,10 @@ where
                 //    \/ /\ m.mtype = RequestVoteResponse
                 match self.drop_stale_response(msg.term, response) {
                     //       /\ \/ DropStaleResponse(i, j, m)
-                    Ok(()) => None,
+                    Ok(()) => {
+                        self.dropped.vote_responses = self.dropped.vote_responses.saturating_add(1);
+                        None
+                    }
                     Err(response) => self.handle_vote_response(msg.term, response, from), //          \/ HandleRequestVoteResponse(i, j, m)
                 }
             }
@@ -947,21 +1274,81 @@ where
                 //    \/ /\ m.mtype = AppendEntriesResponse
                 match self.drop_stale_response(msg.term, response) {
                     //       /\ \/ DropStaleResponse(i, j, m)
-                    Ok(()) => None,
+                    Ok(()) => {
+                        self.dropped.append_responses =
+                            self.dropped.append_responses.saturating_add(1);
+                        None
+                    }
                     Err(response) => self.handle_append_response(msg.term, response, from), //          \/ HandleAppendEntriesResponse(i, j, m)
                 }
             }
+            // the vote request is counted as sent already
+            Some(Rpc::TimeoutNow(request)) => {
+                return self.handle_timeout_now(msg.term, request, from)
+            }
             None => None,
         };
         self.become_leader();
         self.advance_commit_idx();
-        reply
+        self.count_sent(reply)
     }
 
     //
     // helpers
     //
 
+    fn count_sent(
+        &mut self,
+        message: Option<SendableMessage<NodeId>>,
+    ) -> Option<SendableMessage<NodeId>> {
+        if let Some(SendableMessage {
+            message: Message { rpc: Some(rpc), .. },
+            dest,
+        }) = &message
+        {
+            let count = match dest {
+                MessageDestination::Broadcast => {
+                    let learners = self.learners.iter().filter(|id| **id != self.node_id);
+                    (self.peers.len() + learners.count()) as u64
+                }
+                MessageDestination::To(_) => 1,
+            };
+            self.sent.add(rpc, count);
+        }
+        message
+    }
+
+    fn count_dropped(&mut self, msg: &Message) {
+        if let Some(rpc) = &msg.rpc {
+            self.dropped.add(rpc, 1);
+        }
+    }
+
+    // The TimeoutNow message of the leadership transfer, once the peer's log matches the leader's.
+    fn timeout_now(&mut self) -> Option<SendableMessage<NodeId>> {
+        let last_log_idx = self.log.last_index();
+        if let Leader(LeaderState {
+            followers,
+            transfer: Some(transfer),
+            ..
+        }) = &mut self.leadership
+        {
+            let caught_up = (followers.get(&transfer.to))
+                .map_or(false, |replication| replication.match_idx == last_log_idx);
+            if caught_up && !transfer.timeout_now_sent {
+                transfer.timeout_now_sent = true;
+                return Some(SendableMessage {
+                    message: Message {
+                        term: self.current_term,
+                        rpc: Some(Rpc::TimeoutNow(TimeoutNow {})),
+                    },
+                    dest: MessageDestination::To(transfer.to.clone()),
+                });
+            }
+        }
+        None
+    }
+
     fn quorum_size(&self) -> usize {
         quorum_size(self.peers.len())
     }
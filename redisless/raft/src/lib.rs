@@ -43,6 +43,8 @@
 //!         election_timeout_ticks: 10,
 //!         heartbeat_interval_ticks: 1,
 //!         replication_chunk_size: usize::max_value(),
+//!         check_quorum: false,
+//!         checksum_entries: false,
 //!     },
 //! )).collect::<Vec<_>>();
 //!
@@ -112,3 +114,5 @@ pub mod log;
 pub mod message;
 pub mod node;
 mod prelude;
+#[cfg(any(feature = "sim", test))]
+pub mod sim;
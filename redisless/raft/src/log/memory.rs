@@ -4,10 +4,21 @@ use alloc::collections::VecDeque;
 use core::convert::{TryFrom, TryInto};
 
 use crate::message::{LogEntry, LogIndex, TermId};
+use crate::prelude::*;
 
 use super::Log;
 
 /// A naive in-memory implementation of [`Log`](super::Log), primarily for testing.
+///
+/// # Compaction
+///
+/// Entries returned by [`take_next`](Log::take_next) can be discarded explicitly with [`compact_until`], typically
+/// once the state they built up has been snapshotted, and are discarded automatically when appending would exceed the
+/// data capacity. Either way, the callback set with [`on_compact`] is told about it. A leader can then find out with
+/// [`Node::needs_snapshot`](crate::node::Node::needs_snapshot) which followers are too far behind to be sent entries.
+///
+/// [`compact_until`]: Self::compact_until
+/// [`on_compact`]: Self::on_compact
 pub struct InMemoryLog {
     entries: VecDeque<LogEntry>,
     prev_log_idx: LogIndex,
@@ -15,6 +26,24 @@ pub struct InMemoryLog {
     last_taken: LogIndex,
     data_len: usize,
     data_capacity: usize,
+    discarded_data_len: u64,
+    on_compact: Option<CompactCallback>,
+}
+
+type CompactCallback = Box<dyn FnMut(&Compaction) + Send>;
+
+/// Entries discarded from the beginning of an [`InMemoryLog`] at once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Compaction {
+    /// The index of the last entry discarded, now returned by [`prev_index`](Log::prev_index).
+    pub until: LogIndex,
+    /// The number of entries discarded.
+    pub entries: u64,
+    /// The total length of the data of the entries discarded.
+    pub data_len: usize,
+    /// Whether the entries were discarded to make room for a new entry rather than by
+    /// [`compact_until`](InMemoryLog::compact_until).
+    pub automatic: bool,
 }
 
 impl InMemoryLog {
@@ -36,9 +65,56 @@ impl InMemoryLog {
             last_taken: LogIndex::default(),
             data_len: 0,
             data_capacity,
+            discarded_data_len: 0,
+            on_compact: None,
+        }
+    }
+
+    /// Constructs an empty Raft log with unbounded capacity continuing after the entry at `prev_log_idx` of term
+    /// `prev_log_term`, which is considered taken, e.g. for a node installing a snapshot including every entry up to
+    /// it with [`Node::install_snapshot`](crate::node::Node::install_snapshot).
+    pub fn starting_after(prev_log_idx: LogIndex, prev_log_term: TermId) -> Self {
+        Self {
+            prev_log_idx,
+            prev_log_term,
+            last_taken: prev_log_idx,
+            ..Self::new_unbounded()
         }
     }
 
+    /// Sets a callback called every time entries are discarded from the beginning of the log.
+    pub fn on_compact<F: FnMut(&Compaction) + Send + 'static>(&mut self, callback: F) {
+        self.on_compact = Some(Box::new(callback));
+    }
+
+    /// Discards every entry up to and including `log_idx`, returning the number of entries discarded.
+    ///
+    /// # Errors
+    ///
+    /// If `log_idx` is past the last entry returned by [`take_next`](Log::take_next), nothing is discarded and an
+    /// error is returned.
+    pub fn compact_until(&mut self, log_idx: LogIndex) -> Result<u64, <Self as Log>::Error> {
+        if log_idx > self.last_taken {
+            return Err(());
+        }
+
+        let mut compaction = self.new_compaction(false);
+        while self.prev_log_idx < log_idx {
+            compaction.data_len += self.pop_front()?;
+        }
+        self.report(compaction)
+    }
+
+    /// Returns the total length of the data of the entries still in the log.
+    pub fn data_len(&self) -> usize {
+        self.data_len
+    }
+
+    /// Returns the total length of the data of every entry discarded so far.
+    pub fn discarded_data_len(&self) -> u64 {
+        self.discarded_data_len
+    }
+
     fn entry_index(&self, log_idx: LogIndex) -> Option<usize> {
         log_idx
             .id
@@ -48,12 +124,36 @@ impl InMemoryLog {
             .ok()
     }
 
-    fn pop_front(&mut self) -> Result<(), <Self as Log>::Error> {
+    /// Discards the first entry, as long as it was taken, returning the length of its data.
+    fn pop_front(&mut self) -> Result<usize, <Self as Log>::Error> {
         self.entry_index(self.last_taken).ok_or(())?;
         let prev_log = self.entries.pop_front().ok_or(())?;
         self.prev_log_idx = self.prev_log_idx + 1;
         self.prev_log_term = prev_log.term;
-        Ok(())
+        self.data_len -= prev_log.data.len();
+        self.discarded_data_len += prev_log.data.len() as u64;
+        Ok(prev_log.data.len())
+    }
+
+    fn new_compaction(&self, automatic: bool) -> Compaction {
+        Compaction {
+            until: self.prev_log_idx,
+            entries: self.prev_log_idx.id,
+            data_len: 0,
+            automatic,
+        }
+    }
+
+    /// Calls the compaction callback if entries were discarded since `compaction` was created, returning their number.
+    fn report(&mut self, mut compaction: Compaction) -> Result<u64, ()> {
+        compaction.entries = self.prev_log_idx.id - compaction.entries;
+        compaction.until = self.prev_log_idx;
+        if compaction.entries != 0 {
+            if let Some(on_compact) = &mut self.on_compact {
+                on_compact(&compaction);
+            }
+        }
+        Ok(compaction.entries)
     }
 }
 
@@ -65,15 +165,20 @@ impl Log for InMemoryLog {
             return Err(());
         }
 
-        self.data_len = loop {
+        let mut compaction = self.new_compaction(true);
+        let data_len = loop {
             match self.data_len.checked_add(log_entry.data.len()) {
-                Some(new_data_len) if new_data_len <= self.data_capacity => break new_data_len,
-                Some(_) | None => {
-                    self.pop_front()?;
-                }
+                Some(new_data_len) if new_data_len <= self.data_capacity => break Ok(new_data_len),
+                Some(_) | None => match self.pop_front() {
+                    Ok(popped_len) => compaction.data_len += popped_len,
+                    Err(()) => break Err(()),
+                },
             }
         };
+        // entries discarded before failing are gone all the same
+        self.report(compaction)?;
 
+        self.data_len = data_len?;
         self.entries.push_back(log_entry);
         Ok(())
     }
@@ -83,7 +188,13 @@ impl Log for InMemoryLog {
         match self.entries.len().checked_sub(from_index) {
             Some(0) | None => Err(()),
             Some(cancelled_len) => {
+                let cancelled_data_len: usize = self
+                    .entries
+                    .range(from_index..)
+                    .map(|log_entry: &LogEntry| log_entry.data.len())
+                    .sum();
                 self.entries.truncate(from_index);
+                self.data_len -= cancelled_data_len;
                 Ok(cancelled_len)
             }
         }
@@ -147,4 +258,42 @@ mod test {
     use super::*;
 
     raft_log_tests!(InMemoryLog, InMemoryLog::new_unbounded());
+
+    fn entry(data: &'static str) -> LogEntry {
+        LogEntry {
+            term: TermId { id: 1 },
+            data: data.into(),
+            checksum: None,
+        }
+    }
+
+    #[test]
+    fn test_log_auto_compaction() {
+        let compactions = alloc::sync::Arc::new(core::sync::atomic::AtomicU64::new(0));
+        let mut log = InMemoryLog::with_capacity(0, 8);
+        let counted = compactions.clone();
+        log.on_compact(move |compaction| {
+            assert!(compaction.automatic);
+            counted.fetch_add(compaction.entries, core::sync::atomic::Ordering::SeqCst);
+        });
+
+        log.append(entry("abc")).unwrap();
+        log.append(entry("def")).unwrap();
+        // nothing was taken, nothing can be discarded to make room
+        assert!(log.append(entry("gh")).is_ok());
+        assert!(log.append(entry("i")).is_err());
+        assert_eq!(log.data_len(), 8);
+
+        log.take_next().unwrap();
+        log.append(entry("ijk")).unwrap();
+        assert_eq!(log.prev_index(), LogIndex { id: 1 });
+        assert_eq!(log.prev_term(), TermId { id: 1 });
+        assert_eq!(log.data_len(), 8);
+        assert_eq!(log.discarded_data_len(), 3);
+        assert_eq!(compactions.load(core::sync::atomic::Ordering::SeqCst), 1);
+
+        // cancelled entries make room too
+        log.cancel_from(LogIndex { id: 3 }).unwrap();
+        assert_eq!(log.data_len(), 3);
+    }
 }
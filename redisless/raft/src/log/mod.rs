@@ -118,10 +118,9 @@ pub struct CommittedIter<'a, L> {
 
 impl<L: Log> LogState<L> {
     pub fn new(log: L) -> Self {
-        Self {
-            log,
-            commit_idx: LogIndex::default(),
-        }
+        // entries can only have been taken once committed
+        let commit_idx = log.last_taken_index();
+        Self { log, commit_idx }
     }
 
     pub fn append(&mut self, entry: LogEntry) -> Result<(), L::Error> {
@@ -77,24 +77,29 @@ fn test_entries() -> [LogEntry; 5] {
         LogEntry {
             term: TermId { id: 1 },
             data: Bytes::from_static(&[]),
+            checksum: None,
         },
         LogEntry {
             term: TermId { id: 1 },
             data: Bytes::from_static(&[2; 1]),
+            checksum: None,
         },
         LogEntry {
             term: TermId { id: 2 },
             data: Bytes::from_static(&[3; 2]),
+            checksum: None,
         },
         LogEntry {
             term: TermId { id: 9 },
             data: Bytes::from_static(&[4; 100]),
+            checksum: None,
         },
         LogEntry {
             term: TermId {
                 id: u64::max_value(),
             },
             data: Bytes::from_static(&[5; 100]),
+            checksum: None,
         },
     ]
 }
@@ -41,7 +41,7 @@ pub struct Message {
     /// The Remote Procedure Call contained by this message.
     ///
     /// This field is only optional in order to support protobuf serialization.
-    #[cfg_attr(feature = "prost", prost(oneof = "Rpc", tags = "3, 4, 5, 6"))]
+    #[cfg_attr(feature = "prost", prost(oneof = "Rpc", tags = "3, 4, 5, 6, 7"))]
     pub rpc: Option<Rpc>,
 }
 
@@ -65,6 +65,10 @@ pub enum Rpc {
     /// A response to an [`AppendRequest`] allowing or denying an append to the Raft node's log.
     #[cfg_attr(feature = "prost", prost(message, tag = "6"))]
     AppendResponse(AppendResponse),
+
+    /// A request from the leader to start an election right away, handing leadership over to the recipient.
+    #[cfg_attr(feature = "prost", prost(message, tag = "7"))]
+    TimeoutNow(TimeoutNow),
 }
 
 /// A request to obtain leadership amongst Raft nodes.
@@ -111,6 +115,11 @@ pub struct AppendRequest {
     /// A list of consecutive Raft log entries to append.
     #[cfg_attr(feature = "prost", prost(message, repeated, tag = "4"))]
     pub entries: Vec<LogEntry>,
+
+    /// The number of timer ticks left before the requester steps down unless a quorum of its peers responds to it,
+    /// when it checks for a quorum.
+    #[cfg_attr(feature = "prost", prost(uint32, optional, tag = "5"))]
+    pub lease_ticks: Option<u32>,
 }
 
 /// The response to an [`AppendRequest`] allowing or denying an append to the Raft node's log.
@@ -131,6 +140,15 @@ pub struct AppendResponse {
     pub last_log_idx: LogIndex,
 }
 
+/// A request from the leader to start an election right away, sent by [`transfer_leadership`] once the recipient's log
+/// is up to date.
+///
+/// [`transfer_leadership`]: crate::node::Node::transfer_leadership
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "prost", derive(prost::Message))]
+#[cfg_attr(not(feature = "prost"), derive(Debug, Default))]
+pub struct TimeoutNow {}
+
 /// An entry in a [Raft log][crate::log::RaftLog].
 #[derive(Clone, PartialEq)]
 #[cfg_attr(feature = "prost", derive(prost::Message))]
@@ -143,6 +161,12 @@ pub struct LogEntry {
     /// Arbitrary data associated with the log entry.
     #[cfg_attr(feature = "prost", prost(bytes = "vec", required, tag = "2"))]
     pub data: Bytes,
+
+    /// The CRC-32 of [`term`](Self::term) and [`data`](Self::data), given by leaders configured with
+    /// [`checksum_entries`](crate::node::Config::checksum_entries) to detect entries corrupted on disk or over the
+    /// network.
+    #[cfg_attr(feature = "prost", prost(fixed32, optional, tag = "3"))]
+    pub checksum: Option<u32>,
 }
 
 /// The unique, monotonically-increasing ID for a term of Raft group leadership.
@@ -194,6 +218,7 @@ impl fmt::Display for Rpc {
             Rpc::VoteResponse(msg) => fmt::Display::fmt(msg, fmt),
             Rpc::AppendRequest(msg) => fmt::Display::fmt(msg, fmt),
             Rpc::AppendResponse(msg) => fmt::Display::fmt(msg, fmt),
+            Rpc::TimeoutNow(msg) => fmt::Display::fmt(msg, fmt),
         }
     }
 }
@@ -239,12 +264,14 @@ impl fmt::Display for AppendRequest {
             prev_log_term,
             leader_commit,
             entries,
+            lease_ticks,
         } = self;
         fmt.debug_struct("AppendRequest")
             .field("prev_log_idx", &format_args!("{}", prev_log_idx))
             .field("prev_log_term", &format_args!("{}", prev_log_term))
             .field("leader_commit", &format_args!("{}", leader_commit))
             .field("entries", &entries.len())
+            .field("lease_ticks", lease_ticks)
             .finish()
     }
 }
@@ -268,10 +295,61 @@ impl fmt::Display for AppendResponse {
     }
 }
 
+//
+// TimeoutNow impls
+//
+
+impl fmt::Display for TimeoutNow {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {} = self;
+        fmt.debug_struct("TimeoutNow").finish()
+    }
+}
+
+//
+// LogEntry impls
+//
+
+impl LogEntry {
+    /// Returns the CRC-32 of the entry's term, as 8 bytes big endian, followed by its data.
+    pub fn compute_checksum(&self) -> u32 {
+        let crc = crc32_update(!0, &self.term.id.to_be_bytes());
+        !crc32_update(crc, &self.data)
+    }
+
+    /// Returns whether the entry matches its checksum, if it has one.
+    pub fn is_intact(&self) -> bool {
+        self.checksum
+            .is_none_or(|checksum| checksum == self.compute_checksum())
+    }
+}
+
+// the IEEE CRC-32 of Ethernet and zlib, a bit at a time
+fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, byte| {
+        (0..8).fold(crc ^ (*byte as u32), |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
 //
 // TermId impls
 //
 
+impl TermId {
+    /// Addition with a non-negative integer, checking for overflow. Returns `self + inc`, or `None` if an overflow
+    /// occurred.
+    pub fn checked_add(self, inc: u64) -> Option<Self> {
+        let id = self.id.checked_add(inc)?;
+        Some(Self { id })
+    }
+}
+
 impl fmt::Display for TermId {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         let Self { id } = self;
@@ -295,12 +373,10 @@ impl Ord for TermId {
     }
 }
 
+/// Saturates at the maximum term rather than overflowing, see [`TermId::checked_add`].
 impl AddAssign<u64> for TermId {
     fn add_assign(&mut self, rhs: u64) {
-        self.id = self
-            .id
-            .checked_add(rhs)
-            .unwrap_or_else(|| panic!("overflow"));
+        self.id = self.id.saturating_add(rhs);
     }
 }
 
@@ -309,6 +385,13 @@ impl AddAssign<u64> for TermId {
 //
 
 impl LogIndex {
+    /// Addition with a non-negative integer, checking for overflow. Returns `self + inc`, or `None` if an overflow
+    /// occurred.
+    pub fn checked_add(self, inc: u64) -> Option<Self> {
+        let id = self.id.checked_add(inc)?;
+        Some(Self { id })
+    }
+
     /// Subtraction with a non-negative integer, checking for overflow. Returns `self - dec`, or `None` if an overflow
     /// occurred.
     pub fn checked_sub(self, dec: u64) -> Option<Self> {
@@ -343,14 +426,12 @@ impl Ord for LogIndex {
     }
 }
 
+/// Saturates at the maximum index rather than overflowing, see [`LogIndex::checked_add`].
 impl Add<u64> for LogIndex {
     type Output = Self;
     fn add(self, inc: u64) -> Self {
         Self {
-            id: self
-                .id
-                .checked_add(inc)
-                .unwrap_or_else(|| panic!("overflow")),
+            id: self.id.saturating_add(inc),
         }
     }
 }
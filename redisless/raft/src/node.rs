@@ -8,7 +8,8 @@ use rand_core::RngCore;
 
 use crate::core::{ReplicationState, State};
 use crate::log::{CommittedIter, Log};
-use crate::message::{LogIndex, Message, SendableMessage, TermId};
+use crate::message::{LogEntry, LogIndex, Message, Rpc, SendableMessage, TermId};
+use crate::prelude::*;
 
 /// A Raft node, used for replicating a strongly-consistent distributed log of entries with arbitrary data amongst its
 /// peers.
@@ -28,6 +29,24 @@ use crate::message::{LogIndex, Message, SendableMessage, TermId};
 /// entry may be first returned from [`take_committed`] on a node different than that to which it was submitted.
 /// However, [`take_committed`] is guaranteed to return the same entries in the same order on every node.
 ///
+/// Instead of calling [`take_committed`], a function set with [`set_apply_fn`] can be given each entry as soon as it is
+/// committed, e.g. to apply it to a state machine on the leader and followers alike.
+///
+/// # Learners
+///
+/// A node added to the group with [`add_learner`] is replicated to like the other peers, but doesn't vote nor start
+/// elections, and doesn't count towards the quorum, so it can catch up with the log without slowing down commits.
+/// Learners are added on every node of the group, the new node included with its own ID, then
+/// [promoted](Self::promote_learner) on every node, one at a time, once caught up. The change isn't replicated through
+/// the log: it's up to the caller to apply it to every node.
+///
+/// # Leadership transfer
+///
+/// [`transfer_leadership`] hands leadership over to another voting peer, e.g. to take the leader down for maintenance.
+/// The leader first replicates its whole log to the peer, then asks it to start an election right away, which it wins
+/// unless another peer times out first. [`append`] is cancelled in the meantime. The transfer is abandoned if leadership
+/// hasn't changed hands after an election timeout.
+///
 /// # Timer ticks
 ///
 /// Timeouts in [`Node`] are driven by a timer ticking at fixed interval, with the number of ticks between timeouts
@@ -46,17 +65,23 @@ use crate::message::{LogIndex, Message, SendableMessage, TermId};
 /// To prevent unbounded queueing, the API is designed to only ever return a bounded amount of unacknowledged unicast
 /// message data. This amount can be approximately controlled by [`replication_chunk_size`].
 ///
+/// [`add_learner`]: Self::add_learner
 /// [`append`]: Self::append
 /// [`leader`]: Self::leader
 /// [`receive`]: Self::receive
 /// [`replication_chunk_size`]: Config::replication_chunk_size
+/// [`set_apply_fn`]: Self::set_apply_fn
 /// [`SendableMessage`]: crate::message::SendableMessage
 /// [`take_committed`]: Self::take_committed
 /// [`timer_tick`]: Self::timer_tick
+/// [`transfer_leadership`]: Self::transfer_leadership
 pub struct Node<Log, Random, NodeId> {
     state: State<Log, Random, NodeId>,
+    apply_fn: Option<ApplyFn>,
 }
 
+type ApplyFn = Box<dyn FnMut(&LogEntry) + Send>;
+
 /// Configurable parameters of a Raft node.
 #[derive(Clone, Eq, PartialEq)]
 pub struct Config {
@@ -68,6 +93,77 @@ pub struct Config {
 
     /// The maximum number of bytes to replicate to a peer at a time.
     pub replication_chunk_size: usize,
+
+    /// Whether the leader steps down when it didn't get a response from a quorum of its peers within
+    /// `election_timeout_ticks`, so that a leader cut off from the group stops accepting entries which can't be
+    /// committed.
+    pub check_quorum: bool,
+
+    /// Whether the leader gives each new log entry a [checksum](crate::message::LogEntry::checksum), and entries with
+    /// one are verified when read from the log to be sent to peers and when received from the leader. A corrupted
+    /// entry isn't sent, and an [`AppendRequest`](crate::message::AppendRequest) with one is rejected for the leader to
+    /// send it again, rather than the state machines silently diverging.
+    pub checksum_entries: bool,
+}
+
+/// A snapshot of the state of a Raft node and of counters kept since it was constructed, returned by
+/// [`Node::metrics`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    /// The latest term known to this node.
+    pub current_term: TermId,
+
+    /// The index of the last log entry known to be committed.
+    pub commit_idx: LogIndex,
+
+    /// The index of the last committed log entry returned by [`Node::take_committed`].
+    pub last_applied_idx: LogIndex,
+
+    /// The role of this node in the current term.
+    pub role: Role,
+
+    /// The number of votes, its own included, this node received in the current term while a candidate. Zero in
+    /// the other roles.
+    pub votes_received: usize,
+
+    /// The number of elections this node started.
+    pub elections: u64,
+
+    /// The number of messages returned to be sent, a broadcast counting once per peer.
+    pub sent: MessageCounts,
+
+    /// The number of messages received but ignored, either from a node outside the group, with a stale term, or
+    /// unexpected in this node's role.
+    pub dropped: MessageCounts,
+}
+
+/// The role of a Raft node in a term.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Role {
+    /// Following the leader of the term, if one is known.
+    #[default]
+    Follower,
+    /// Requesting votes to become leader.
+    Candidate,
+    /// Appending entries to the distributed log.
+    Leader,
+    /// Following the leader without voting nor starting elections, until promoted.
+    Learner,
+}
+
+/// Counters of Raft messages, by [`Rpc`] type.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MessageCounts {
+    /// The number of [`VoteRequest`](crate::message::VoteRequest) messages.
+    pub vote_requests: u64,
+    /// The number of [`VoteResponse`](crate::message::VoteResponse) messages.
+    pub vote_responses: u64,
+    /// The number of [`AppendRequest`](crate::message::AppendRequest) messages.
+    pub append_requests: u64,
+    /// The number of [`AppendResponse`](crate::message::AppendResponse) messages.
+    pub append_responses: u64,
+    /// The number of [`TimeoutNow`](crate::message::TimeoutNow) messages.
+    pub timeout_nows: u64,
 }
 
 /// An error returned while attempting to append to a Raft log.
@@ -81,6 +177,28 @@ pub enum AppendError<E> {
     LogErr(E),
 }
 
+impl MessageCounts {
+    /// Returns the number of messages of every type.
+    pub fn total(&self) -> u64 {
+        self.vote_requests
+            + self.vote_responses
+            + self.append_requests
+            + self.append_responses
+            + self.timeout_nows
+    }
+
+    pub(crate) fn add(&mut self, rpc: &Rpc, count: u64) {
+        let counter = match rpc {
+            Rpc::VoteRequest(_) => &mut self.vote_requests,
+            Rpc::VoteResponse(_) => &mut self.vote_responses,
+            Rpc::AppendRequest(_) => &mut self.append_requests,
+            Rpc::AppendResponse(_) => &mut self.append_responses,
+            Rpc::TimeoutNow(_) => &mut self.timeout_nows,
+        };
+        *counter = counter.saturating_add(count);
+    }
+}
+
 impl<L, Random, NodeId> Node<L, Random, NodeId>
 where
     L: Log,
@@ -101,6 +219,7 @@ where
     ) -> Self {
         Self {
             state: State::new(node_id, peers, log, random, config),
+            apply_fn: None,
         }
     }
 
@@ -119,6 +238,7 @@ where
         data: T,
     ) -> Result<impl Iterator<Item = SendableMessage<NodeId>> + '_, AppendError<L::Error>> {
         let () = self.state.client_request(data.into())?;
+        self.apply_committed();
         Ok(self.append_entries())
     }
 
@@ -157,6 +277,11 @@ where
         self.state.log_mut()
     }
 
+    /// Returns a snapshot of this node's state and message counters, e.g. to report on replication.
+    pub fn metrics(&self) -> Metrics {
+        self.state.metrics()
+    }
+
     /// Returns this node's ID.
     pub fn node_id(&self) -> &NodeId {
         self.state.node_id()
@@ -167,6 +292,62 @@ where
         self.state.peers()
     }
 
+    /// Returns the IDs of the learners, this node's own included if it's one.
+    pub fn learners(&self) -> &BTreeSet<NodeId> {
+        self.state.learners()
+    }
+
+    /// Returns the number of timer ticks left before the leader steps down unless a quorum of its peers responds to
+    /// it, as of the last [`AppendRequest`] this node received from the leader, or as known by the leader itself.
+    /// Only leaders checking for a quorum with [`check_quorum`] give this lease, and a leader may still lose its
+    /// leadership earlier to a newer term.
+    ///
+    /// [`AppendRequest`]: crate::message::AppendRequest
+    /// [`check_quorum`]: Config::check_quorum
+    pub fn leader_lease_ticks(&self) -> Option<u32> {
+        self.state.leader_lease_ticks()
+    }
+
+    /// Returns whether this node is a learner.
+    pub fn is_learner(&self) -> bool {
+        self.state.is_learner()
+    }
+
+    /// Adds `node_id` to the learners, to be replicated to by the leader. Learners don't vote, start elections, nor
+    /// count towards the quorum until [promoted](Self::promote_learner). See ["Learners"] for details.
+    ///
+    /// Returns `false` and changes nothing if `node_id` is already a peer or a learner, or is this node's own ID while
+    /// it isn't a follower.
+    ///
+    /// ["Learners"]: Node#learners
+    pub fn add_learner(&mut self, node_id: NodeId) -> bool {
+        self.state.add_learner(node_id)
+    }
+
+    /// Makes the learner `node_id` a voting peer. See ["Learners"] for details.
+    ///
+    /// Returns `false` and changes nothing if `node_id` isn't a learner.
+    ///
+    /// ["Learners"]: Node#learners
+    pub fn promote_learner(&mut self, node_id: NodeId) -> bool {
+        self.state.promote_learner(node_id)
+    }
+
+    /// Replaces the Raft log with `log`, whose entries up to its [`last_taken_index`] were applied from a snapshot of
+    /// the state machine rather than taken from this node's log. Entries after it are then replicated as usual by the
+    /// leader, and the entries before it are never returned by [`take_committed`].
+    ///
+    /// # Errors
+    ///
+    /// If this node is the leader, or the snapshot doesn't go past the entries already committed on this node, `log`
+    /// is returned and nothing changes.
+    ///
+    /// [`last_taken_index`]: Log::last_taken_index
+    /// [`take_committed`]: Self::take_committed
+    pub fn install_snapshot(&mut self, log: L) -> Result<(), L> {
+        self.state.install_snapshot(log)
+    }
+
     /// Processes receipt of a `message` from a peer with ID `from`, returning messages to be sent.
     ///
     /// See ["Message delivery"] for details about delivery requirements for the returned messages.
@@ -179,6 +360,22 @@ where
         from: NodeId,
     ) -> impl Iterator<Item = SendableMessage<NodeId>> + '_ {
         let message = self.state.receive(message, from);
+        self.apply_committed();
+        message.into_iter().chain(self.append_entries())
+    }
+
+    /// Hands leadership over to the voting peer with ID `to`, returning messages to be sent. See ["Leadership
+    /// transfer"] for details.
+    ///
+    /// Does nothing if this node isn't the leader, or `to` isn't one of its voting peers.
+    ///
+    /// ["Leadership transfer"]: Node#leadership-transfer
+    #[must_use = "This function returns Raft messages to be sent."]
+    pub fn transfer_leadership(
+        &mut self,
+        to: NodeId,
+    ) -> impl Iterator<Item = SendableMessage<NodeId>> + '_ {
+        let message = self.state.transfer_leadership(to);
         message.into_iter().chain(self.append_entries())
     }
 
@@ -187,6 +384,24 @@ where
         self.state.replication_state(peer_node_id)
     }
 
+    /// Returns whether the peer with ID `peer_node_id` needs entries which were discarded from this node's log, and
+    /// can't be replicated to anymore until it's sent a snapshot. Always `false` if this node isn't the leader.
+    pub fn needs_snapshot(&self, peer_node_id: &NodeId) -> bool {
+        self.state.needs_snapshot(peer_node_id)
+    }
+
+    /// Sets a function given every committed [`LogEntry`], in order, from within [`append`], [`receive`] and
+    /// [`timer_tick`], so committed entries don't have to be polled with [`take_committed`]. Entries committed before
+    /// it is set and not taken yet are given to it on the next call to one of these.
+    ///
+    /// [`append`]: Self::append
+    /// [`receive`]: Self::receive
+    /// [`take_committed`]: Self::take_committed
+    /// [`timer_tick`]: Self::timer_tick
+    pub fn set_apply_fn<F: FnMut(&LogEntry) + Send + 'static>(&mut self, apply_fn: F) {
+        self.apply_fn = Some(Box::new(apply_fn));
+    }
+
     /// Returns a reference to the low-level state of the Raft node.
     pub fn state(&mut self) -> &State<L, Random, NodeId> {
         &self.state
@@ -215,12 +430,25 @@ where
     #[must_use = "This function returns Raft messages to be sent."]
     pub fn timer_tick(&mut self) -> impl Iterator<Item = SendableMessage<NodeId>> + '_ {
         let message = self.state.timer_tick();
+        self.apply_committed();
         message.into_iter().chain(self.append_entries())
     }
 
+    fn apply_committed(&mut self) {
+        if let Some(apply_fn) = &mut self.apply_fn {
+            for entry in self.state.take_committed() {
+                apply_fn(&entry);
+            }
+        }
+    }
+
     #[must_use = "This function returns Raft messages to be sent."]
     fn append_entries(&mut self) -> impl Iterator<Item = SendableMessage<NodeId>> + '_ {
-        let peers = self.state.peers().clone().into_iter();
+        let peers: Vec<_> = (self.state.peers().iter())
+            .chain(self.state.learners())
+            .cloned()
+            .collect();
+        let peers = peers.into_iter();
         peers.flat_map(move |peer| self.state.append_entries(peer))
     }
 }
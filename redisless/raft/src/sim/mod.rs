@@ -0,0 +1,389 @@
+//! Deterministic simulation of a Raft group over an adverse network.
+//!
+//! A [`Sim`] drives a group of [`Node`]s step by step, delivering the messages they send each other through a
+//! [`Network`] which may drop messages between given nodes, lose a share of them at random, and deliver them out of
+//! order. A lost message breaks the connection it was sent over, which both of its nodes notice on their next timer
+//! tick, see [`State::reset_peer`](crate::core::State::reset_peer). The clock of each node may also run faster or
+//! slower than the others. All the randomness, that of the nodes included, derives from a single seed: a run is
+//! replayed exactly from its seed, so the state machine fed by the committed entries can be tested against adverse
+//! networks deterministically.
+//!
+//! ```
+//! use raft::sim::{Network, Sim};
+//! use rand_chacha::ChaChaRng;
+//!
+//! let mut sim = Sim::<ChaChaRng>::new(3, 42, Network::default().with_loss(10).with_reordering());
+//! sim.run_until(|sim| sim.leader().is_some());
+//!
+//! let leader = sim.leader().unwrap();
+//! assert!(sim.append(leader, "entry").is_ok());
+//! let mut committed = vec![false; sim.len()];
+//! sim.run_until(|sim| {
+//!     for (node, committed) in committed.iter_mut().enumerate() {
+//!         *committed |= sim.take_committed(node).any(|entry| entry.data.as_ref() == b"entry");
+//!     }
+//!     committed.iter().all(|committed| *committed)
+//! });
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use alloc::collections::{BTreeSet, VecDeque};
+
+use bytes::Bytes;
+use rand_core::{RngCore, SeedableRng};
+
+use crate::log::memory::InMemoryLog;
+use crate::log::{CommittedIter, Log};
+use crate::message::{Message, MessageDestination, SendableMessage};
+use crate::node::{AppendError, Config, Node};
+use crate::prelude::*;
+
+/// The configuration of the nodes of a [`Sim`] constructed with [`Sim::new`].
+pub const DEFAULT_CONFIG: Config = Config {
+    election_timeout_ticks: 10,
+    heartbeat_interval_ticks: 9,
+    replication_chunk_size: 1024,
+    check_quorum: false,
+    checksum_entries: false,
+};
+
+/// The number of steps after which [`Sim::run_until`] gives up.
+pub const MAX_STEPS: u64 = 100_000;
+
+/// A simulated Raft node, identified by its index in the [`Sim`].
+pub type SimNode<R> = Node<InMemoryLog, R, u64>;
+
+/// A group of Raft nodes exchanging messages over a simulated [`Network`].
+pub struct Sim<R> {
+    nodes: Vec<SimNode<R>>,
+    clocks: Vec<Clock>,
+    network: Network,
+    random: R,
+    in_flight: VecDeque<(u64, u64, Message)>,
+    // broken connections, by the node yet to notice and its peer
+    resets: BTreeSet<(u64, u64)>,
+    step: u64,
+}
+
+/// The conditions of the network between the nodes of a [`Sim`].
+#[derive(Clone, Debug, Default)]
+pub struct Network {
+    drops: BTreeSet<(Option<u64>, Option<u64>)>,
+    down: BTreeSet<u64>,
+    loss_percent: u32,
+    reorder: bool,
+}
+
+/// Timer ticks given to a node every so many steps.
+struct Clock {
+    ticks: u32,
+    per_steps: u32,
+    elapsed: u32,
+}
+
+//
+// Sim impls
+//
+
+impl<R> Sim<R>
+where
+    R: RngCore + SeedableRng,
+{
+    /// Constructs a group of `size` nodes configured with [`DEFAULT_CONFIG`], whose randomness derives from `seed`.
+    pub fn new(size: u64, seed: u64, network: Network) -> Self {
+        Self::with_config(size, seed, network, DEFAULT_CONFIG)
+    }
+
+    /// Constructs a group of `size` nodes configured with `config`, whose randomness derives from `seed`.
+    pub fn with_config(size: u64, seed: u64, network: Network, config: Config) -> Self {
+        let mut random = R::seed_from_u64(seed);
+        let peers: BTreeSet<u64> = (0..size).collect();
+        let nodes = (0..size)
+            .map(|node_id| {
+                Node::new(
+                    node_id,
+                    peers.clone(),
+                    InMemoryLog::new_unbounded(),
+                    R::seed_from_u64(random.next_u64()),
+                    config.clone(),
+                )
+            })
+            .collect();
+        Self {
+            nodes,
+            clocks: (0..size)
+                .map(|_| Clock {
+                    ticks: 1,
+                    per_steps: 1,
+                    elapsed: 0,
+                })
+                .collect(),
+            network,
+            random,
+            in_flight: VecDeque::new(),
+            resets: BTreeSet::new(),
+            step: 0,
+        }
+    }
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns whether the group has no node.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the number of steps run so far.
+    pub fn steps(&self) -> u64 {
+        self.step
+    }
+
+    /// Returns the node `node`.
+    pub fn node(&self, node: usize) -> &SimNode<R> {
+        &self.nodes[node]
+    }
+
+    /// Returns the node `node`, e.g. to [set its apply function](Node::set_apply_fn).
+    pub fn node_mut(&mut self, node: usize) -> &mut SimNode<R> {
+        &mut self.nodes[node]
+    }
+
+    /// Returns the conditions of the network.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// Replaces the conditions of the network, e.g. with [`Network::default`] to heal every partition. Messages
+    /// already in flight are subject to the new conditions.
+    pub fn set_network(&mut self, network: Network) {
+        self.network = network;
+    }
+
+    /// Gives `ticks` timer ticks to the node `node` every `per_steps` steps, rather than one per step, so its clock
+    /// runs faster or slower than those of the other nodes.
+    ///
+    /// # Panics
+    ///
+    /// If `per_steps` is zero.
+    pub fn set_clock_rate(&mut self, node: usize, ticks: u32, per_steps: u32) {
+        assert!(per_steps != 0, "a clock rate needs a number of steps");
+        self.clocks[node] = Clock {
+            ticks,
+            per_steps,
+            elapsed: 0,
+        };
+    }
+
+    /// Returns the index of the leader of the latest term, if any.
+    pub fn leader(&self) -> Option<usize> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.is_leader())
+            .max_by_key(|(_, node)| node.metrics().current_term.id)
+            .map(|(idx, _)| idx)
+    }
+
+    /// Requests appending an entry with `data` to the Raft log through the node `node`, sending the resulting
+    /// messages.
+    ///
+    /// # Errors
+    ///
+    /// If the request would immediately be cancelled, e.g. because the node is not the leader.
+    pub fn append<T: Into<Bytes>>(
+        &mut self,
+        node: usize,
+        data: T,
+    ) -> Result<(), AppendError<<InMemoryLog as Log>::Error>> {
+        let messages: Vec<_> = self.nodes[node].append(data)?.collect();
+        self.send(node as u64, messages);
+        self.deliver();
+        Ok(())
+    }
+
+    /// Returns the entries committed on the node `node` since the last call, see [`Node::take_committed`].
+    pub fn take_committed(&mut self, node: usize) -> CommittedIter<'_, InMemoryLog> {
+        self.nodes[node].take_committed()
+    }
+
+    /// Ticks the clock of every node which isn't down, after resetting its broken connections, then delivers messages
+    /// until there are none left.
+    pub fn step(&mut self) -> &mut Self {
+        self.step += 1;
+        for node in 0..self.nodes.len() {
+            let node_id = node as u64;
+            if self.network.is_node_down(node_id) {
+                continue;
+            }
+
+            let peers: Vec<_> = self
+                .resets
+                .iter()
+                .filter(|(node, _)| *node == node_id)
+                .map(|(_, peer)| *peer)
+                .collect();
+            for peer in peers {
+                self.resets.remove(&(node_id, peer));
+                let messages: Vec<_> = self.nodes[node]
+                    .state_mut()
+                    .reset_peer(peer)
+                    .into_iter()
+                    .collect();
+                self.send(node_id, messages);
+            }
+
+            let clock = &mut self.clocks[node];
+            clock.elapsed += clock.ticks;
+            let ticks = clock.elapsed / clock.per_steps;
+            clock.elapsed %= clock.per_steps;
+            for _ in 0..ticks {
+                let messages: Vec<_> = self.nodes[node].timer_tick().collect();
+                self.send(node_id, messages);
+            }
+        }
+        self.deliver();
+        self
+    }
+
+    /// Runs `steps` steps.
+    pub fn run_for(&mut self, steps: u64) -> &mut Self {
+        for _ in 0..steps {
+            self.step();
+        }
+        self
+    }
+
+    /// Runs steps until `until_fun` returns `true`, which is checked before every step.
+    ///
+    /// # Panics
+    ///
+    /// If `until_fun` still returns `false` after [`MAX_STEPS`] steps.
+    pub fn run_until(&mut self, mut until_fun: impl FnMut(&mut Self) -> bool) -> &mut Self {
+        let mut steps_remaining = MAX_STEPS;
+        while !until_fun(self) {
+            steps_remaining = steps_remaining
+                .checked_sub(1)
+                .expect("condition failed after maximum simulation length");
+            self.step();
+        }
+        self
+    }
+
+    fn send(&mut self, from: u64, messages: Vec<SendableMessage<u64>>) {
+        let node_count = self.nodes.len() as u64;
+        for sendable in messages {
+            let to_nodes = match sendable.dest {
+                MessageDestination::Broadcast => (0..node_count).filter(|to| *to != from).collect(),
+                MessageDestination::To(to) => vec![to],
+            };
+            for to in to_nodes {
+                if self.network.should_drop(from, to) || self.lost() {
+                    log::debug!(
+                        "step {} {} -> {} DROPPED {}",
+                        self.step,
+                        from,
+                        to,
+                        &sendable.message
+                    );
+                    self.break_connection(from, to);
+                } else {
+                    self.in_flight
+                        .push_back((from, to, sendable.message.clone()));
+                }
+            }
+        }
+    }
+
+    fn break_connection(&mut self, from: u64, to: u64) {
+        self.resets.insert((from, to));
+        self.resets.insert((to, from));
+    }
+
+    fn lost(&mut self) -> bool {
+        self.network.loss_percent != 0 && self.random.next_u32() % 100 < self.network.loss_percent
+    }
+
+    fn deliver(&mut self) {
+        while !self.in_flight.is_empty() {
+            let next = match self.network.reorder {
+                true => self.random.next_u32() as usize % self.in_flight.len(),
+                false => 0,
+            };
+            // will never panic, the index is within the queue
+            let (from, to, message) = self.in_flight.remove(next).unwrap();
+
+            // the network may have changed since the message was sent
+            if self.network.should_drop(from, to) {
+                log::debug!("step {} {} -> {} DROPPED {}", self.step, from, to, &message);
+                self.break_connection(from, to);
+                continue;
+            }
+            log::debug!("step {} {} -> {} {}", self.step, from, to, &message);
+            let replies: Vec<_> = self.nodes[to as usize].receive(message, from).collect();
+            self.send(to, replies);
+        }
+    }
+}
+
+//
+// Network impls
+//
+
+impl Network {
+    /// Drops every message to and from `node_id`, and stops its clock.
+    pub fn node_down(mut self, node_id: u64) -> Self {
+        self.down.insert(node_id);
+        self
+    }
+
+    /// Drops every message to and from `node_id`.
+    pub fn isolate(mut self, node_id: u64) -> Self {
+        self.drops.insert((Some(node_id), None));
+        self.drops.insert((None, Some(node_id)));
+        self
+    }
+
+    /// Drops every message between `from` and `to`, both ways.
+    pub fn drop_between(mut self, from: u64, to: u64) -> Self {
+        self.drops.insert((Some(from), Some(to)));
+        self.drops.insert((Some(to), Some(from)));
+        self
+    }
+
+    /// Drops every message to `node_id`.
+    pub fn drop_to(mut self, node_id: u64) -> Self {
+        self.drops.insert((None, Some(node_id)));
+        self
+    }
+
+    /// Loses `percent` percent of the messages, at random.
+    pub fn with_loss(mut self, percent: u32) -> Self {
+        self.loss_percent = percent.min(100);
+        self
+    }
+
+    /// Delivers messages in a random order rather than in the order they were sent.
+    pub fn with_reordering(mut self) -> Self {
+        self.reorder = true;
+        self
+    }
+
+    /// Returns whether `node_id` is down.
+    pub fn is_node_down(&self, node_id: u64) -> bool {
+        self.down.contains(&node_id)
+    }
+
+    /// Returns whether every message from `from` to `to` is dropped.
+    pub fn should_drop(&self, from: u64, to: u64) -> bool {
+        self.drops.contains(&(Some(from), Some(to)))
+            || self.drops.contains(&(Some(from), None))
+            || self.drops.contains(&(None, Some(to)))
+            || self.down.contains(&from)
+            || self.down.contains(&to)
+    }
+}
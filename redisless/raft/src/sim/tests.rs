@@ -0,0 +1,124 @@
+use rand_chacha::ChaChaRng;
+
+use super::{Network, Sim, DEFAULT_CONFIG};
+use crate::node::Config;
+use crate::prelude::*;
+
+type TestSim = Sim<ChaChaRng>;
+
+/// Runs until every node committed an entry with `data`
+fn run_until_committed(sim: &mut TestSim, data: &[u8]) {
+    let mut committed = vec![false; sim.len()];
+    sim.run_until(|sim| {
+        for (node, committed) in committed.iter_mut().enumerate() {
+            *committed |= sim
+                .take_committed(node)
+                .any(|entry| entry.data.as_ref() == data);
+        }
+        committed.iter().all(|committed| *committed)
+    });
+}
+
+fn append_on_leader(sim: &mut TestSim, data: &'static [u8]) {
+    sim.run_until(|sim| match sim.leader() {
+        Some(leader) => sim.append(leader, data).is_ok(),
+        None => false,
+    });
+}
+
+#[test]
+fn runs_are_replayed_from_their_seed() {
+    let run = |seed: u64| {
+        let mut sim = TestSim::new(5, seed, Network::default().with_loss(20).with_reordering());
+        append_on_leader(&mut sim, b"entry");
+        run_until_committed(&mut sim, b"entry");
+        let terms: Vec<u64> = (0..sim.len())
+            .map(|node| sim.node(node).metrics().current_term.id)
+            .collect();
+        (sim.steps(), sim.leader(), terms)
+    };
+
+    assert_eq!(run(1), run(1));
+    assert_eq!(run(7), run(7));
+}
+
+#[test]
+fn commits_over_a_lossy_reordering_network() {
+    let mut sim = TestSim::new(3, 0, Network::default().with_loss(30).with_reordering());
+    for data in [&b"a"[..], b"b", b"c"] {
+        append_on_leader(&mut sim, data);
+        run_until_committed(&mut sim, data);
+    }
+}
+
+#[test]
+fn isolated_leader_is_replaced() {
+    let mut sim = TestSim::new(3, 0, Network::default());
+    sim.run_until(|sim| sim.leader().is_some());
+    let old_leader = sim.leader().unwrap();
+
+    sim.set_network(Network::default().isolate(old_leader as u64));
+    sim.run_until(|sim| sim.leader().map_or(false, |leader| leader != old_leader));
+    // the old leader doesn't know yet
+    assert!(sim.node(old_leader).is_leader());
+
+    sim.set_network(Network::default());
+    sim.run_until(|sim| !sim.node(old_leader).is_leader());
+    append_on_leader(&mut sim, b"entry");
+    run_until_committed(&mut sim, b"entry");
+}
+
+#[test]
+fn fast_clocks_time_out_first() {
+    let mut sim = TestSim::new(3, 0, Network::default());
+    sim.set_clock_rate(2, 3, 1);
+    sim.run_until(|sim| sim.leader().is_some());
+    assert_eq!(sim.leader(), Some(2));
+}
+
+#[test]
+fn down_nodes_stay_still() {
+    let mut sim = TestSim::new(3, 0, Network::default().node_down(0));
+    append_on_leader(&mut sim, b"entry");
+    sim.run_for(100);
+
+    assert!(!sim.node(0).is_leader());
+    assert_eq!(sim.node(0).metrics().current_term.id, 0);
+    assert_eq!(sim.take_committed(0).count(), 0);
+}
+
+fn check_quorum_sim(network: Network) -> TestSim {
+    let config = Config {
+        check_quorum: true,
+        ..DEFAULT_CONFIG
+    };
+    TestSim::with_config(3, 0, network, config)
+}
+
+#[test]
+fn isolated_leader_steps_down() {
+    let mut sim = check_quorum_sim(Network::default());
+    sim.run_until(|sim| sim.leader().is_some());
+    let old_leader = sim.leader().unwrap();
+
+    sim.set_network(Network::default().isolate(old_leader as u64));
+    sim.run_until(|sim| !sim.node(old_leader).is_leader());
+    assert!(sim.append(old_leader, "entry").is_err());
+
+    // the others elect a leader of their own meanwhile
+    sim.run_until(|sim| sim.leader().is_some());
+    assert_ne!(sim.leader(), Some(old_leader));
+}
+
+#[test]
+fn leader_with_a_quorum_stays() {
+    let mut sim = check_quorum_sim(Network::default().isolate(2));
+    sim.run_until(|sim| sim.leader().is_some());
+    let leader = sim.leader().unwrap();
+    let elections = sim.node(leader).metrics().elections;
+
+    // a single peer answering is enough in a group of three
+    sim.run_for(1000);
+    assert_eq!(sim.leader(), Some(leader));
+    assert_eq!(sim.node(leader).metrics().elections, elections);
+}
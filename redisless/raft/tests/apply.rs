@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use raft::log::memory::InMemoryLog;
+use raft::message::{MessageDestination, SendableMessage};
+use raft::node::Node;
+use rand_chacha::ChaChaRng;
+use rand_core::{RngCore, SeedableRng};
+
+mod common;
+
+type TestNode = Node<InMemoryLog, ChaChaRng, NodeId>;
+
+fn node(node_id: u64, random: &mut impl RngCore) -> TestNode {
+    TestLogger::init();
+    Node::new(
+        node_id.into(),
+        vec![1.into(), 2.into()].into_iter().collect(),
+        InMemoryLog::new_unbounded(),
+        ChaChaRng::seed_from_u64(random.next_u64()),
+        CONFIG,
+    )
+}
+
+fn deliver(nodes: &mut [TestNode], mut queue: VecDeque<(NodeId, SendableMessage<NodeId>)>) {
+    while let Some((from, message)) = queue.pop_front() {
+        for to in nodes.iter_mut() {
+            let is_dest = match &message.dest {
+                MessageDestination::Broadcast => *to.node_id() != from,
+                MessageDestination::To(dest) => to.node_id() == dest,
+            };
+            if is_dest {
+                let to_id = *to.node_id();
+                let replies = to.receive(message.message.clone(), from);
+                queue.extend(replies.map(|reply| (to_id, reply)));
+            }
+        }
+    }
+}
+
+#[test]
+pub fn committed_entries_are_applied() {
+    let mut random = init_random();
+    let mut nodes = vec![node(1, &mut random), node(2, &mut random)];
+    let applied: Vec<_> = (0..2).map(|_| Arc::new(Mutex::new(Vec::new()))).collect();
+    for (node, applied) in nodes.iter_mut().zip(&applied) {
+        let applied = applied.clone();
+        node.set_apply_fn(move |entry| applied.lock().unwrap().push(entry.data.clone()));
+    }
+
+    let mut queue = VecDeque::new();
+    while !nodes[0].is_leader() {
+        queue.extend(nodes[0].timer_tick().map(|message| (1.into(), message)));
+        deliver(&mut nodes, std::mem::take(&mut queue));
+    }
+
+    for data in &["one", "two"] {
+        let messages = nodes[0].append(*data).ok().unwrap();
+        queue.extend(messages.map(|message| (1.into(), message)));
+        deliver(&mut nodes, std::mem::take(&mut queue));
+    }
+    // followers learn the commit index with the next append request
+    for _ in 0..CONFIG.heartbeat_interval_ticks {
+        queue.extend(nodes[0].timer_tick().map(|message| (1.into(), message)));
+        deliver(&mut nodes, std::mem::take(&mut queue));
+    }
+
+    for (node, applied) in nodes.iter_mut().zip(&applied) {
+        // the empty entry appended when elected, then "one" and "two"
+        assert_eq!(*applied.lock().unwrap(), vec!["", "one", "two"]);
+        assert_eq!(node.take_committed().count(), 0);
+    }
+}
@@ -0,0 +1,110 @@
+use common::*;
+use raft::log::Log;
+use raft::message::{AppendRequest, LogEntry, LogIndex, Rpc, SendableMessage, TermId};
+use raft::node::Config;
+
+mod common;
+
+fn checksum_config() -> Config {
+    Config {
+        checksum_entries: true,
+        ..CONFIG
+    }
+}
+
+fn entry(term: TermId, data: &'static str) -> LogEntry {
+    let mut entry = LogEntry {
+        term,
+        data: data.into(),
+        checksum: None,
+    };
+    entry.checksum = Some(entry.compute_checksum());
+    entry
+}
+
+#[test]
+pub fn checksum_detects_corruption() {
+    let term = TermId { id: 1 };
+    let intact = entry(term, "one");
+    // the CRC-32 of zlib over the term as 8 bytes big endian and the data
+    assert_eq!(intact.checksum, Some(0x5616_896a));
+    assert!(intact.is_intact());
+    assert_ne!(
+        intact.compute_checksum(),
+        entry(term, "two").compute_checksum()
+    );
+    assert_ne!(
+        intact.compute_checksum(),
+        entry(TermId { id: 2 }, "one").compute_checksum()
+    );
+
+    let corrupted = LogEntry {
+        data: "onf".into(),
+        ..intact.clone()
+    };
+    assert!(!corrupted.is_intact());
+    // entries appended without checksums can't be verified
+    assert!(LogEntry {
+        checksum: None,
+        ..corrupted
+    }
+    .is_intact());
+}
+
+#[test]
+pub fn corrupted_entries_are_rejected() {
+    let mut raft = raft(1, vec![2], None, &mut init_random());
+    raft.set_config(checksum_config());
+    let term = TermId { id: 1 };
+    let mut corrupted = entry(term, "one");
+    corrupted.data = "onf".into();
+
+    let append = |entries| {
+        Rpc::AppendRequest(AppendRequest {
+            prev_log_idx: LogIndex::default(),
+            prev_log_term: TermId::default(),
+            leader_commit: LogIndex::default(),
+            entries,
+            lease_ticks: None,
+        })
+    };
+    let success =
+        |reply: Option<SendableMessage<NodeId>>| match reply.map(|reply| reply.message.rpc) {
+            Some(Some(Rpc::AppendResponse(response))) => response.success,
+            _ => panic!("no append response"),
+        };
+
+    let reply = send(
+        &mut raft,
+        2,
+        term,
+        append(vec![entry(term, "zero"), corrupted]),
+    );
+    assert!(!success(reply));
+    assert_eq!(raft.log().last_index(), LogIndex::default());
+
+    let reply = send(
+        &mut raft,
+        2,
+        term,
+        append(vec![entry(term, "zero"), entry(term, "one")]),
+    );
+    assert!(success(reply));
+    assert_eq!(raft.log().last_index(), LogIndex { id: 2 });
+}
+
+#[test]
+pub fn leader_checksums_replicated_entries() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config());
+    for raft in &mut group.nodes {
+        raft.set_config(checksum_config());
+    }
+    group.run_on_node(0, |raft| raft.timeout());
+    group.run_until(|group| group.nodes[0].is_leader());
+    assert!(group.nodes[0].client_request("one".into()).is_ok());
+    group.run_until_commit(|commit| {
+        assert_eq!(commit.data, "one");
+        assert!(commit.checksum.is_some() && commit.is_intact());
+        true
+    });
+}
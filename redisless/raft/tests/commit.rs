@@ -1,4 +1,5 @@
 use common::*;
+use raft::node::Config;
 
 mod common;
 
@@ -99,3 +100,55 @@ pub fn cancel_uncommitted() {
         })
     });
 }
+
+#[test]
+pub fn commit_visible_on_idle_followers() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config());
+    group.run_on_node(0, |raft| raft.timeout());
+    group.run_until(|group| group.nodes[0].is_leader());
+    // let the noop of the new term commit everywhere, then wait for the next heartbeat
+    group.run_until(|group| {
+        let commit_idx = *group.nodes[0].commit_idx();
+        group
+            .nodes
+            .iter()
+            .all(|raft| *raft.commit_idx() == commit_idx)
+    });
+    group.run_for(CONFIG.heartbeat_interval_ticks);
+
+    let commit_idx = *group.nodes[0].commit_idx();
+    assert!(group.nodes[0].client_request("one".into()).is_ok());
+    let start_tick = group.tick;
+    group.run_until(|group| {
+        group
+            .nodes
+            .iter()
+            .all(|raft| *raft.commit_idx() > commit_idx)
+    });
+    // followers learn of the commit right after the leader, not on its next heartbeat
+    assert_eq!(group.tick - start_tick, 1);
+}
+
+#[test]
+pub fn heartbeats_carry_leader_lease() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config());
+    for raft in &mut group.nodes {
+        raft.set_config(Config {
+            check_quorum: true,
+            ..CONFIG
+        });
+    }
+    group.run_on_node(0, |raft| raft.timeout());
+    group.run_until(|group| group.nodes[0].is_leader());
+    group.run_for(CONFIG.heartbeat_interval_ticks);
+
+    let lease_ticks = group.nodes[1]
+        .leader_lease_ticks()
+        .expect("no lease from the leader");
+    assert!(lease_ticks > 0 && lease_ticks <= CONFIG.election_timeout_ticks);
+
+    // the follower counts the lease down while it doesn't hear from the leader
+    group.config = config().isolate(0);
+    group.run_for(1);
+    assert_eq!(group.nodes[1].leader_lease_ticks(), Some(lease_ticks - 1));
+}
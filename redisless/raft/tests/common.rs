@@ -15,6 +15,8 @@ pub const CONFIG: Config = Config {
     election_timeout_ticks: 10,
     heartbeat_interval_ticks: 9,
     replication_chunk_size: 1024,
+    check_quorum: false,
+    checksum_entries: false,
 };
 const RANDOM_SEED: u64 = 0;
 const MAX_TICKS: u32 = 100_000;
@@ -47,12 +49,13 @@ pub struct TestLoggerContext {
     tick: Option<u32>,
 }
 
-pub fn rpc_types() -> [Rpc; 4] {
+pub fn rpc_types() -> [Rpc; 5] {
     [
         Rpc::VoteRequest(Default::default()),
         Rpc::VoteResponse(Default::default()),
         Rpc::AppendRequest(Default::default()),
         Rpc::AppendResponse(Default::default()),
+        Rpc::TimeoutNow(Default::default()),
     ]
 }
 
@@ -0,0 +1,89 @@
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use raft::log::memory::{Compaction, InMemoryLog};
+use raft::log::Log;
+
+mod common;
+
+#[test]
+pub fn compacted_log_needs_snapshot() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config().isolate(2));
+    group.run_on_node(0, |raft| raft.timeout());
+    group.run_until(|group| group.nodes[0].is_leader());
+
+    for data in &["one", "two", "three"] {
+        assert!(group.nodes[0].client_request((*data).into()).is_ok());
+    }
+    group.run_until_commit(|commit| commit.data == "three");
+
+    let compactions = Arc::new(Mutex::new(Vec::new()));
+    let commit_idx = *group.nodes[0].commit_idx();
+    let leader = &mut group.nodes[0];
+    let compactions_seen = compactions.clone();
+    leader
+        .log_mut()
+        .on_compact(move |compaction| compactions_seen.lock().unwrap().push(compaction.clone()));
+
+    assert!(!leader.needs_snapshot(&2.into()));
+    assert!(leader.log_mut().compact_until(commit_idx + 1).is_err());
+    // the empty entry appended when elected, then "one", "two" and "three"
+    assert_eq!(leader.log_mut().compact_until(commit_idx), Ok(4));
+    assert_eq!(leader.log_mut().compact_until(commit_idx), Ok(0));
+    assert_eq!(leader.log().prev_index(), commit_idx);
+    assert_eq!(leader.log().discarded_data_len(), 11);
+    assert_eq!(leader.log().data_len(), 0);
+    assert_eq!(
+        *compactions.lock().unwrap(),
+        vec![Compaction {
+            until: commit_idx,
+            entries: 4,
+            data_len: 11,
+            automatic: false,
+        }]
+    );
+
+    // the isolated node never got any entry, the node in sync still can
+    assert!(leader.needs_snapshot(&2.into()));
+    assert!(!leader.needs_snapshot(&1.into()));
+    assert!(!leader.needs_snapshot(&0.into()));
+}
+
+#[test]
+pub fn snapshot_catches_up_lagging_follower() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config().isolate(2));
+    group.run_on_node(0, |raft| raft.timeout());
+    group.run_until(|group| group.nodes[0].is_leader());
+
+    for data in &["one", "two", "three"] {
+        assert!(group.nodes[0].client_request((*data).into()).is_ok());
+    }
+    group.run_until_commit(|commit| commit.data == "three");
+
+    // the leader discards its log as the entries are part of a snapshot of its state machine
+    let leader = &mut group.nodes[0];
+    let snapshot_idx = *leader.commit_idx();
+    let snapshot_term = leader.log_mut().get_term(snapshot_idx).unwrap();
+    assert!(leader.log_mut().compact_until(snapshot_idx).is_ok());
+    assert!(leader.needs_snapshot(&2.into()));
+
+    let lagging = &mut group.nodes[2];
+    assert!(lagging
+        .install_snapshot(InMemoryLog::starting_after(snapshot_idx, snapshot_term))
+        .is_ok());
+    assert_eq!(*lagging.commit_idx(), snapshot_idx);
+    // the same snapshot again doesn't go past the entries committed
+    assert!(lagging
+        .install_snapshot(InMemoryLog::starting_after(snapshot_idx, snapshot_term))
+        .is_err());
+
+    assert!(group.nodes[0].client_request("four".into()).is_ok());
+    group.config = config();
+    let mut taken = Vec::new();
+    group.run_until(|group| {
+        taken.extend(group.nodes[2].take_committed().map(|entry| entry.data));
+        taken.iter().any(|data| *data == "four")
+    });
+    // the entries in the snapshot are never taken from the log
+    assert!(!taken.iter().any(|data| *data == "one" || *data == "three"));
+}
@@ -0,0 +1,162 @@
+use common::*;
+use raft::message::*;
+use rand::Rng;
+use rand_chacha::ChaChaRng;
+
+mod common;
+
+const ROUNDS: usize = 200;
+const MESSAGES: usize = 50;
+
+/// 0, 1 and the largest values are picked as often as any other
+fn extreme(random: &mut ChaChaRng) -> u64 {
+    match random.gen_range(0..5) {
+        0 => 0,
+        1 => 1,
+        2 => u64::MAX - 1,
+        3 => u64::MAX,
+        _ => random.gen_range(0..16),
+    }
+}
+
+fn term(random: &mut ChaChaRng) -> TermId {
+    TermId {
+        id: extreme(random),
+    }
+}
+
+fn idx(random: &mut ChaChaRng) -> LogIndex {
+    LogIndex {
+        id: extreme(random),
+    }
+}
+
+fn rpc(random: &mut ChaChaRng) -> Rpc {
+    match random.gen_range(0..4) {
+        0 => Rpc::VoteRequest(VoteRequest {
+            last_log_idx: idx(random),
+            last_log_term: term(random),
+        }),
+        1 => Rpc::VoteResponse(VoteResponse {
+            vote_granted: random.gen_bool(0.5),
+        }),
+        2 => Rpc::AppendRequest(AppendRequest {
+            prev_log_idx: idx(random),
+            prev_log_term: term(random),
+            leader_commit: idx(random),
+            entries: (0..random.gen_range(0..4))
+                .map(|_| LogEntry {
+                    term: term(random),
+                    data: "fuzz".into(),
+                    checksum: None,
+                })
+                .collect(),
+            lease_ticks: None,
+        }),
+        _ => Rpc::AppendResponse(AppendResponse {
+            success: random.gen_bool(0.5),
+            match_idx: idx(random),
+            last_log_idx: idx(random),
+        }),
+    }
+}
+
+/// A node of a group of three, as a follower, a candidate, or a leader with a few entries
+fn node(role: usize, random: &mut ChaChaRng) -> TestRaft {
+    let mut raft = raft(1, vec![1, 2, 3], None, random);
+    if role > 0 {
+        let term = raft.timeout().unwrap().message.term;
+        if role > 1 {
+            send(
+                &mut raft,
+                2,
+                term,
+                Rpc::VoteResponse(VoteResponse { vote_granted: true }),
+            );
+            assert!(raft.is_leader());
+            for _ in 0..random.gen_range(0..4) {
+                assert!(raft.client_request("entry".into()).is_ok());
+            }
+        }
+    }
+    raft
+}
+
+#[test]
+pub fn extreme_messages_dont_panic() {
+    let mut random = init_random();
+    for round in 0..ROUNDS {
+        let mut raft = node(round % 3, &mut random);
+        for _ in 0..MESSAGES {
+            let from = random.gen_range(2..=3);
+            let term = term(&mut random);
+            let rpc = rpc(&mut random);
+            let _ = send(&mut raft, from, term, rpc);
+            for peer in 2..=3 {
+                let _ = raft.append_entries(peer.into());
+            }
+            let _ = raft.timer_tick();
+            let _ = raft.client_request("entry".into());
+            raft.take_committed().for_each(drop);
+        }
+    }
+}
+
+#[test]
+pub fn elections_stop_at_the_last_term() {
+    let mut raft = raft(1, vec![1, 2, 3], None, &mut init_random());
+    let last_term = TermId { id: u64::MAX };
+    send(
+        &mut raft,
+        2,
+        last_term,
+        Rpc::VoteResponse(Default::default()),
+    );
+    assert_eq!(raft.leader().1, &last_term);
+
+    // the term can't be incremented, no election can be started
+    assert!(raft.timeout().is_none());
+    assert_eq!(raft.leader().1, &last_term);
+    assert!(!raft.is_leader());
+}
+
+#[test]
+pub fn appends_past_the_last_index_are_refused() {
+    let mut raft = raft(1, vec![1, 2, 3], None, &mut init_random());
+    let mut term = TermId::default();
+    term += 1;
+    let reply = send(
+        &mut raft,
+        2,
+        term,
+        Rpc::AppendRequest(AppendRequest {
+            prev_log_idx: LogIndex { id: u64::MAX },
+            prev_log_term: term,
+            leader_commit: LogIndex { id: u64::MAX },
+            entries: vec![LogEntry {
+                term,
+                data: "entry".into(),
+                checksum: None,
+            }],
+            lease_ticks: None,
+        }),
+    );
+    match reply.map(|reply| reply.message.rpc) {
+        Some(Some(Rpc::AppendResponse(response))) => assert!(!response.success),
+        _ => panic!("no append response"),
+    }
+    assert_eq!(raft.commit_idx(), &LogIndex::default());
+}
+
+#[test]
+pub fn index_arithmetic_saturates() {
+    let last = LogIndex { id: u64::MAX };
+    assert_eq!(last + 1, last);
+    assert_eq!(last.checked_add(1), None);
+    assert_eq!(LogIndex::default().checked_add(1), Some(LogIndex { id: 1 }));
+
+    let mut term = TermId { id: u64::MAX };
+    assert_eq!(term.checked_add(1), None);
+    term += 1;
+    assert_eq!(term, TermId { id: u64::MAX });
+}
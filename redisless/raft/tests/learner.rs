@@ -0,0 +1,117 @@
+use common::*;
+use raft::log::Log;
+use raft::message::{Rpc, TermId, VoteRequest};
+use raft::node::Role;
+
+mod common;
+
+/// A group of `voters` voters, followed by `learners` learners
+fn group(voters: u64, learners: u64) -> TestRaftGroup {
+    let mut random = init_random();
+    let peers: Vec<u64> = (0..voters).collect();
+    let mut group = TestRaftGroup::new(0, &mut random, config());
+    group.nodes = (0..voters + learners)
+        .map(|node_id| raft(node_id, peers.clone(), None, &mut random))
+        .collect();
+    for node in &mut group.nodes {
+        for learner in voters..voters + learners {
+            assert!(node.add_learner(learner.into()));
+        }
+    }
+    group
+}
+
+fn commit_idx(group: &TestRaftGroup, node: usize) -> u64 {
+    group.nodes[node].commit_idx().id
+}
+
+#[test]
+pub fn learner_doesnt_vote() {
+    let mut raft = raft(1, vec![2, 3], None, &mut init_random());
+    assert!(raft.add_learner(1.into()));
+    assert!(raft.is_learner());
+    assert_eq!(raft.metrics().role, Role::Learner);
+    assert!(raft.timeout().is_none());
+
+    let mut term = TermId::default();
+    term += 1;
+    let reply = send(&mut raft, 2, term, Rpc::VoteRequest(VoteRequest::default()));
+    match reply.map(|reply| reply.message.rpc) {
+        Some(Some(Rpc::VoteResponse(response))) => assert!(!response.vote_granted),
+        _ => panic!("no vote response"),
+    }
+}
+
+#[test]
+pub fn learner_is_replicated() {
+    let mut group = group(3, 1);
+    group
+        .run_for_inspect(1000, |group| assert!(!group.nodes[3].is_leader()))
+        .modify(|group| {
+            assert!(group
+                .nodes
+                .iter_mut()
+                .any(|raft| raft.client_request("one".into()).is_ok()))
+        })
+        .run_until(|group| {
+            commit_idx(group, 3) == commit_idx(group, 0) && commit_idx(group, 0) > 1
+        });
+    assert_eq!(group.nodes[3].metrics().elections, 0);
+    assert!(group.nodes[3]
+        .take_committed()
+        .any(|entry| entry.data == "one"));
+}
+
+#[test]
+pub fn learner_isnt_in_quorum() {
+    let mut group = group(2, 2);
+    group.run_until(|group| group.has_leader());
+    let leader = (0..2).find(|node| group.nodes[*node].is_leader()).unwrap();
+    let commit = commit_idx(&group, leader);
+
+    group.config = config().node_down(1 - leader as u64);
+    group
+        .modify(|group| assert!(group.nodes[leader].client_request("one".into()).is_ok()))
+        .run_for(100);
+    // both learners have the entry, but not a majority of voters
+    for learner in 2..4 {
+        assert_eq!(
+            group.nodes[learner].log().last_index(),
+            group.nodes[leader].log().last_index()
+        );
+    }
+    assert_eq!(commit_idx(&group, leader), commit);
+}
+
+#[test]
+pub fn promoted_learner_votes() {
+    let mut group = group(1, 1);
+    group.run_until(|group| group.nodes[0].is_leader());
+    let commit = commit_idx(&group, 0);
+
+    for node in &mut group.nodes {
+        assert!(node.promote_learner(1.into()));
+        assert!(!node.promote_learner(1.into()));
+    }
+    assert!(!group.nodes[1].is_learner());
+    assert_eq!(group.nodes[1].metrics().role, Role::Follower);
+    assert!(group.nodes[1].timeout().is_some());
+
+    // the former learner is now needed to commit
+    group.config = config().node_down(1);
+    group
+        .modify(|group| assert!(group.nodes[0].client_request("one".into()).is_ok()))
+        .run_for(100);
+    assert_eq!(commit_idx(&group, 0), commit);
+}
+
+#[test]
+pub fn voter_cant_be_learner() {
+    let mut raft = raft(1, vec![1, 2, 3], None, &mut init_random());
+    assert!(!raft.add_learner(2.into()));
+    assert!(raft.add_learner(4.into()));
+    assert!(!raft.add_learner(4.into()));
+    assert!(raft.timeout().is_some());
+    // a candidate can't turn learner
+    assert!(!raft.add_learner(1.into()));
+}
@@ -0,0 +1,91 @@
+use common::*;
+use raft::message::{AppendResponse, Rpc, TermId, VoteResponse};
+use raft::node::{MessageCounts, Role};
+
+mod common;
+
+#[test]
+pub fn metrics_follow_election() {
+    let mut raft = raft(1, vec![2, 3], None, &mut init_random());
+    let metrics = raft.metrics();
+    assert_eq!(metrics.role, Role::Follower);
+    assert_eq!(metrics.elections, 0);
+    assert_eq!(metrics.sent, MessageCounts::default());
+
+    let mut term = TermId::default();
+    term += 1;
+    assert!(raft.timeout().is_some());
+    let metrics = raft.metrics();
+    assert_eq!(metrics.role, Role::Candidate);
+    assert_eq!(metrics.current_term, term);
+    assert_eq!(metrics.votes_received, 1);
+    assert_eq!(metrics.elections, 1);
+    // the vote request is broadcast to both peers
+    assert_eq!(metrics.sent.vote_requests, 2);
+
+    send(
+        &mut raft,
+        2,
+        term,
+        Rpc::VoteResponse(VoteResponse { vote_granted: true }),
+    );
+    let metrics = raft.metrics();
+    assert_eq!(metrics.role, Role::Leader);
+    assert_eq!(metrics.votes_received, 0);
+
+    // the vote of the last peer arrives once leading already
+    send(
+        &mut raft,
+        3,
+        term,
+        Rpc::VoteResponse(VoteResponse { vote_granted: true }),
+    );
+    // responses of a past term are ignored
+    send(
+        &mut raft,
+        2,
+        TermId::default(),
+        Rpc::AppendResponse(AppendResponse::default()),
+    );
+    // and so are messages from outside the group
+    send(
+        &mut raft,
+        4,
+        term,
+        Rpc::AppendResponse(AppendResponse::default()),
+    );
+    assert_eq!(
+        raft.metrics().dropped,
+        MessageCounts {
+            vote_requests: 0,
+            vote_responses: 1,
+            append_requests: 0,
+            append_responses: 2,
+            timeout_nows: 0,
+        }
+    );
+
+    assert_eq!(
+        append_entries(&mut raft, vec![2.into(), 3.into()]).count(),
+        2
+    );
+    assert_eq!(raft.metrics().sent.append_requests, 2);
+    assert_eq!(raft.metrics().sent.total(), 4);
+}
+
+#[test]
+pub fn metrics_track_commits() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config());
+    group.run_on_node(0, |raft| raft.timeout());
+    group.run_until(|group| group.nodes[0].is_leader());
+
+    assert!(group.nodes[0].client_request("one".into()).is_ok());
+    group.run_until_commit(|commit| commit.data == "one");
+
+    // committed entries were all taken by now
+    let metrics = group.nodes[0].metrics();
+    assert_eq!(metrics.commit_idx, *group.nodes[0].commit_idx());
+    assert_eq!(metrics.last_applied_idx, metrics.commit_idx);
+    assert!(metrics.sent.append_requests > 0);
+    assert!(group.nodes[1].metrics().sent.append_responses > 0);
+}
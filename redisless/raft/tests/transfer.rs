@@ -0,0 +1,103 @@
+use common::*;
+use raft::log::Log;
+use raft::message::{Rpc, TermId, TimeoutNow};
+use raft::node::AppendError;
+
+mod common;
+
+fn leader(group: &TestRaftGroup) -> Option<usize> {
+    group.nodes.iter().position(|node| node.is_leader())
+}
+
+#[test]
+pub fn leadership_is_transferred() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config());
+    group.run_until(|group| group.has_leader());
+    let from = leader(&group).unwrap();
+    let to = (from + 1) % 3;
+    let to_id = *group.nodes[to].node_id();
+    let (_, &term) = group.nodes[from].leader();
+
+    group
+        .run_on_node(from, |raft| raft.transfer_leadership(to_id))
+        .run_until(|group| group.nodes[to].is_leader());
+    assert!(!group.nodes[from].is_leader());
+    assert!(group.nodes[to].leader().1 > &term);
+    assert!(group.nodes[to].client_request("one".into()).is_ok());
+}
+
+#[test]
+pub fn lagging_peer_catches_up_before_taking_over() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config());
+    group.run_until(|group| group.has_leader());
+    let from = leader(&group).unwrap();
+    let to = (from + 1) % 3;
+    let to_id = *group.nodes[to].node_id();
+
+    group.config = config().isolate(to as u64);
+    assert!(group.nodes[from].client_request("one".into()).is_ok());
+    group.run_for(5);
+    assert!(group.nodes[to].log().last_index() < group.nodes[from].log().last_index());
+
+    group.config = config();
+    group.run_on_node(from, |raft| raft.transfer_leadership(to_id));
+    // appends are cancelled until leadership changed hands
+    assert!(matches!(
+        group.nodes[from].client_request("two".into()),
+        Err(AppendError::Cancelled { .. })
+    ));
+    group.run_until(|group| group.nodes[to].is_leader());
+    assert!(group.nodes[to]
+        .take_committed()
+        .any(|entry| entry.data == "one"));
+}
+
+#[test]
+pub fn followers_and_non_peers_dont_transfer() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config());
+    group.run_until(|group| group.has_leader());
+    let from = leader(&group).unwrap();
+    let follower = (from + 1) % 3;
+    let other_id = *group.nodes[(from + 2) % 3].node_id();
+
+    assert!(group.nodes[follower]
+        .transfer_leadership(other_id)
+        .is_none());
+    assert!(group.nodes[from].transfer_leadership(7.into()).is_none());
+    assert!(group.nodes[from].client_request("one".into()).is_ok());
+}
+
+#[test]
+pub fn abandoned_transfer_accepts_appends_again() {
+    let mut group = TestRaftGroup::new(3, &mut init_random(), config());
+    group.run_until(|group| group.has_leader());
+    let from = leader(&group).unwrap();
+    let to = (from + 1) % 3;
+    let to_id = *group.nodes[to].node_id();
+
+    group.config = config().node_down(to as u64);
+    group
+        .run_on_node(from, |raft| raft.transfer_leadership(to_id))
+        .run_for(CONFIG.election_timeout_ticks);
+    assert!(group.nodes[from].is_leader());
+    assert!(group.nodes[from].client_request("one".into()).is_ok());
+}
+
+#[test]
+pub fn timeout_now_only_from_the_leader() {
+    let mut raft = raft(1, vec![2, 3], None, &mut init_random());
+    let mut term = TermId::default();
+    term += 1;
+    send(&mut raft, 2, term, Rpc::AppendRequest(Default::default()));
+
+    send(&mut raft, 3, term, Rpc::TimeoutNow(TimeoutNow {}));
+    assert_eq!(raft.metrics().elections, 0);
+    assert_eq!(raft.metrics().dropped.timeout_nows, 1);
+
+    let vote_request = send(&mut raft, 2, term, Rpc::TimeoutNow(TimeoutNow {}));
+    assert!(matches!(
+        vote_request.and_then(|request| request.message.rpc),
+        Some(Rpc::VoteRequest(_))
+    ));
+    assert_eq!(raft.metrics().elections, 1);
+}
@@ -0,0 +1,72 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use redisless::server::ServerConfig;
+
+pub const USAGE: &str = "\
+Usage: redisless-server [OPTIONS]
+
+Options:
+    --port <port>                 Port to serve RESP on [default: 16379]
+    --maxclients <count>          Connections above this limit are refused [default: 10000]
+    --protected-mode <yes|no>     Refuse the clients not connecting from the loopback
+                                  interface [default: yes]
+    --http <addr>                 Serve /healthz and /metrics over HTTP on this address
+    --snapshot-file <path>        Load the keys from this file when it exists, and save them
+                                  to it on shutdown
+    --max-keys <count>            Refuse the writes adding keys past this many with an OOM
+                                  error
+    --max-value-bytes <bytes>     Refuse the writes growing the dataset once its values take
+                                  this many bytes, with an OOM error
+    -h, --help                    Print this help";
+
+/// What the server is run with, see `USAGE`
+#[derive(Debug)]
+pub struct Args {
+    pub port: u16,
+    pub config: ServerConfig,
+    pub snapshot_file: Option<PathBuf>,
+}
+
+/// The options of `args`, the program name left out. `None` when the help was asked for.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Args>, String> {
+    let mut parsed = Args {
+        port: 16379,
+        config: ServerConfig::default(),
+        snapshot_file: None,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(None);
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} expects a value", arg))?;
+        match arg.as_str() {
+            "--port" => parsed.port = parse_value(&arg, &value)?,
+            "--maxclients" => parsed.config.maxclients = parse_value(&arg, &value)?,
+            "--protected-mode" => {
+                parsed.config.protected_mode = match value.as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(format!("{} expects yes or no, not {}", arg, value)),
+                }
+            }
+            "--http" => parsed.config.http_addr = Some(parse_value::<SocketAddr>(&arg, &value)?),
+            "--snapshot-file" => parsed.snapshot_file = Some(PathBuf::from(value)),
+            "--max-keys" => parsed.config.max_keys = Some(parse_value(&arg, &value)?),
+            "--max-value-bytes" => parsed.config.max_value_bytes = Some(parse_value(&arg, &value)?),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    Ok(Some(parsed))
+}
+
+fn parse_value<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, arg))
+}
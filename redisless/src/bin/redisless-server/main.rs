@@ -0,0 +1,78 @@
+//! RedisLess as a standalone process, e.g. as a service of a docker-compose setup
+
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use redisless::server::{Server, ServerEvent, ServerState};
+use redisless::storage::in_memory::InMemoryStorage;
+
+mod args;
+mod signals;
+#[cfg(test)]
+mod tests;
+
+/// How often the process checks for a signal to shut down
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+fn main() {
+    let args = match args::parse(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", args::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, args::USAGE);
+            process::exit(2);
+        }
+    };
+
+    signals::install();
+    let server = Server::new_with_config(InMemoryStorage::new(), args.config, args.port);
+    let events = server.events();
+
+    if let Some(path) = args.snapshot_file.as_ref().filter(|path| path.exists()) {
+        match server.load_snapshot(path) {
+            Ok(keys) => println!("loaded {} keys from {}", keys, path.display()),
+            Err(err) => {
+                eprintln!("can't load the snapshot in {}: {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+    }
+
+    match server.start() {
+        Some(ServerState::Started) => println!("listening on port {}", server.port()),
+        state => {
+            eprintln!("can't start the server: {:?}", state);
+            process::exit(1);
+        }
+    }
+
+    let mut failed = false;
+    while !signals::received() {
+        if let Ok(ServerEvent::Error(err)) = events.recv_timeout(SIGNAL_POLL_INTERVAL) {
+            eprintln!("{}", err);
+            failed = true;
+            break;
+        }
+    }
+
+    // every client is disconnected before the keys are saved, none of their writes is lost
+    println!("shutting down");
+    server.stop();
+    if let Some(path) = &args.snapshot_file {
+        match server.save_snapshot(path) {
+            Ok(()) => println!("saved the keys to {}", path.display()),
+            Err(err) => {
+                eprintln!("can't save the snapshot to {}: {}", path.display(), err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,37 @@
+//! SIGINT and SIGTERM, caught to shut the server down gracefully
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Whether SIGINT or SIGTERM was received since `install`
+pub fn received() -> bool {
+    RECEIVED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+pub fn install() {
+    use std::os::raw::c_int;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    extern "C" {
+        // from the libc the standard library links
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn on_signal(_: c_int) {
+        // the only thing a handler may safely do here
+        RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    unsafe {
+        signal(SIGINT, on_signal);
+        signal(SIGTERM, on_signal);
+    }
+}
+
+/// The process is killed by the signals without shutting the server down first
+#[cfg(not(unix))]
+pub fn install() {}
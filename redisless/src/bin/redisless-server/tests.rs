@@ -0,0 +1,58 @@
+use super::args::parse;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn options_are_parsed() {
+    let parsed = parse(args(&[])).unwrap().unwrap();
+    assert_eq!(parsed.port, 16379);
+    assert!(parsed.config.protected_mode);
+    assert!(parsed.snapshot_file.is_none());
+
+    let parsed = parse(args(&[
+        "--port",
+        "6379",
+        "--maxclients",
+        "10",
+        "--protected-mode",
+        "no",
+        "--http",
+        "127.0.0.1:8080",
+        "--snapshot-file",
+        "/data/dump.resp",
+        "--max-keys",
+        "1000",
+        "--max-value-bytes",
+        "1048576",
+    ]))
+    .unwrap()
+    .unwrap();
+    assert_eq!(parsed.port, 6379);
+    assert_eq!(parsed.config.maxclients, 10);
+    assert!(!parsed.config.protected_mode);
+    assert_eq!(
+        parsed.config.http_addr,
+        Some("127.0.0.1:8080".parse().unwrap())
+    );
+    assert_eq!(
+        parsed.snapshot_file.as_deref(),
+        Some(std::path::Path::new("/data/dump.resp"))
+    );
+    assert_eq!(parsed.config.max_keys, Some(1000));
+    assert_eq!(parsed.config.max_value_bytes, Some(1048576));
+
+    assert!(parse(args(&["--port", "1", "--help"])).unwrap().is_none());
+    for (invalid, err) in &[
+        (&["--port"][..], "--port expects a value"),
+        (&["--port", "99999"], "invalid value 99999 for --port"),
+        (
+            &["--protected-mode", "maybe"],
+            "--protected-mode expects yes or no, not maybe",
+        ),
+        (&["--maxmemory", "1gb"], "unknown option --maxmemory"),
+    ] {
+        assert_eq!(parse(args(invalid)).unwrap_err(), *err);
+    }
+}
@@ -0,0 +1,61 @@
+//! What this build of RedisLess supports, so bindings can feature-gate instead of failing on
+//! unknown commands at runtime.
+//!
+//! Extensions Redis doesn't have are listed as features too, so clients only rely on them when
+//! talking to RedisLess. `cas` is optimistic concurrency without MULTI/WATCH:
+//! `CASVERSION key` returns the version of the key, 0 when it doesn't exist, and
+//! `CAS key version value` sets the key only if its version is still `version`, replying with
+//! its new version, or nil when another write got there first. `delpattern` is
+//! `DELPATTERN pattern`, deleting every key matching a glob-style pattern at once and replying
+//! with how many, to clean up between tests sharing an instance.
+
+use crate::command::SUPPORTED_COMMANDS;
+
+/// Behaviours that don't map to a single command
+pub const FEATURES: &[&str] = &["pipelining", "expiry", "scan", "cas", "delpattern"];
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Capabilities {
+    pub version: &'static str,
+    /// Lowercase command names
+    pub commands: &'static [&'static str],
+    pub features: &'static [&'static str],
+}
+
+impl Capabilities {
+    pub fn supports_command(&self, command: &str) -> bool {
+        let command = command.to_lowercase();
+        self.commands.iter().any(|c| *c == command)
+    }
+
+    pub fn supports_feature(&self, feature: &str) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// `{"version": "...", "commands": [...], "features": [...]}`
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"version":"{}","commands":[{}],"features":[{}]}}"#,
+            self.version,
+            json_strings(self.commands),
+            json_strings(self.features)
+        )
+    }
+}
+
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        commands: SUPPORTED_COMMANDS,
+        features: FEATURES,
+    }
+}
+
+// names are plain ASCII, nothing to escape
+fn json_strings(strings: &[&str]) -> String {
+    strings
+        .iter()
+        .map(|s| format!("\"{}\"", s))
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -0,0 +1,268 @@
+//! Minimal async client for tests talking to an embedded server, without pulling in a full Redis
+//! client. Requests are written by a thread of the client, so its futures work on any executor,
+//! tokio's included, and never block it.
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! use redisless::client::{Client, Value};
+//!
+//! let client = Client::connect("127.0.0.1:16379")?;
+//! client.command(&[b"SET", b"key", b"value"]).await?;
+//! assert_eq!(
+//!     client.command(&[b"GET", b"key"]).await?,
+//!     Value::Bulk(b"value".to_vec())
+//! );
+//!
+//! let replies = client
+//!     .pipeline()
+//!     .command(&[b"INCR", b"counter"])
+//!     .command(&[b"INCR", b"counter"])
+//!     .execute()
+//!     .await?;
+//! assert_eq!(replies, vec![Value::Integer(1), Value::Integer(2)]);
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use std::future::Future;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+use crate::protocol::parser::RedisProtocolParser;
+use crate::protocol::writer::RespWriter;
+use crate::protocol::Resp;
+
+/// A reply of the server
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// `+OK`
+    Status(String),
+    /// An error reply, e.g. `ERR unknown command`. The request still went through.
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Array(Vec<Value>),
+    /// A null bulk string or array
+    Nil,
+}
+
+impl Value {
+    fn from_resp(resp: Resp) -> io::Result<Value> {
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_string();
+        Ok(match resp {
+            Resp::String(s) => Value::Status(text(s)),
+            Resp::Error(s) => Value::Error(text(s)),
+            Resp::Integer(n) => Value::Integer(
+                text(n)
+                    .parse()
+                    .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?,
+            ),
+            Resp::BulkString(s) => Value::Bulk(s.to_vec()),
+            Resp::Array(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(Value::from_resp)
+                    .collect::<io::Result<_>>()?,
+            ),
+            Resp::Nil => Value::Nil,
+        })
+    }
+
+    fn parse(frame: &[u8]) -> io::Result<Value> {
+        // the null array, which the parser has no representation for
+        if frame == b"*-1\r\n" {
+            return Ok(Value::Nil);
+        }
+
+        match RedisProtocolParser::parse(frame) {
+            Ok((resp, _)) => Value::from_resp(resp),
+            Err(err) => Err(io::Error::new(ErrorKind::InvalidData, err.to_string())),
+        }
+    }
+}
+
+/// Connection to a server. Requests sent concurrently, e.g. from several tasks, are pipelined.
+pub struct Client {
+    requests: Sender<Request>,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Client> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+
+        let (requests, requests_recv) = unbounded();
+        // ends once the `Client` is dropped
+        thread::spawn(move || serve_requests(stream, requests_recv));
+
+        Ok(Client { requests })
+    }
+
+    /// Send a command, `args` starting with its name, and wait for its reply
+    pub async fn command(&self, args: &[&[u8]]) -> io::Result<Value> {
+        let mut replies = self.pipeline().command(args).execute().await?;
+        // will never panic, there is a reply per command
+        Ok(replies.pop().unwrap())
+    }
+
+    /// Commands to send at once, see `Pipeline::execute`
+    pub fn pipeline(&self) -> Pipeline<'_> {
+        Pipeline {
+            client: self,
+            writer: RespWriter::default(),
+            commands: 0,
+        }
+    }
+}
+
+/// Commands written in a single go, their replies are read in a single go too
+pub struct Pipeline<'a> {
+    client: &'a Client,
+    writer: RespWriter,
+    commands: usize,
+}
+
+impl Pipeline<'_> {
+    /// Queue a command, `args` starting with its name
+    pub fn command(mut self, args: &[&[u8]]) -> Self {
+        self.writer.array_len(args.len());
+        for arg in args {
+            self.writer.bulk_string(arg);
+        }
+        self.commands += 1;
+        self
+    }
+
+    /// Send the commands and wait for their replies, in the same order
+    pub async fn execute(self) -> io::Result<Vec<Value>> {
+        if self.commands == 0 {
+            return Ok(Vec::new());
+        }
+
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        let request = Request {
+            bytes: self.writer.as_slice().to_vec(),
+            replies: self.commands,
+            slot: slot.clone(),
+        };
+
+        if self.client.requests.send(request).is_err() {
+            return Err(ErrorKind::BrokenPipe.into());
+        }
+        Replies { slot }.await
+    }
+}
+
+struct Request {
+    bytes: Vec<u8>,
+    replies: usize,
+    slot: Arc<Mutex<Slot>>,
+}
+
+#[derive(Default)]
+struct Slot {
+    result: Option<io::Result<Vec<Value>>>,
+    waker: Option<Waker>,
+}
+
+impl Slot {
+    fn fulfill(&mut self, result: io::Result<Vec<Value>>) {
+        self.result = Some(result);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Resolves once the client thread has read the replies of a request
+struct Replies {
+    slot: Arc<Mutex<Slot>>,
+}
+
+impl Future for Replies {
+    type Output = io::Result<Vec<Value>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self
+            .slot
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Write every request waiting and then read their replies, until the `Client` is dropped
+fn serve_requests(mut stream: TcpStream, requests: Receiver<Request>) {
+    let mut buffer = Vec::new();
+    let mut broken: Option<ErrorKind> = None;
+
+    while let Ok(first) = requests.recv() {
+        let batch: Vec<Request> = Some(first).into_iter().chain(requests.try_iter()).collect();
+
+        if broken.is_none() {
+            let bytes: Vec<u8> = batch.iter().flat_map(|r| r.bytes.iter().copied()).collect();
+            if let Err(err) = stream.write_all(&bytes) {
+                broken = Some(err.kind());
+            }
+        }
+
+        for request in batch {
+            let result = match broken {
+                Some(kind) => Err(kind.into()),
+                None => read_replies(&mut stream, &mut buffer, request.replies),
+            };
+            if let Err(err) = &result {
+                // replies can't be told apart anymore
+                broken = Some(err.kind());
+            }
+
+            request
+                .slot
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .fulfill(result);
+        }
+    }
+}
+
+fn read_replies(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+    count: usize,
+) -> io::Result<Vec<Value>> {
+    let mut replies = Vec::with_capacity(count);
+    let mut chunk = [0; 4096];
+
+    while replies.len() < count {
+        match RedisProtocolParser::frame_len(buffer) {
+            Ok(Some(len)) => {
+                replies.push(Value::parse(&buffer[..len])?);
+                buffer.drain(..len);
+                continue;
+            }
+            Ok(None) => {}
+            Err(err) => return Err(io::Error::new(ErrorKind::InvalidData, err.to_string())),
+        }
+
+        match stream.read(&mut chunk)? {
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            n => buffer.extend_from_slice(&chunk[..n]),
+        }
+    }
+    Ok(replies)
+}
@@ -0,0 +1,109 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+use crate::client::{Client, Value};
+use crate::server::ServerState;
+use crate::storage::in_memory::InMemoryStorage;
+use crate::Server;
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Run `future` on the current thread, as the crate has no async runtime to test with
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Poll both futures until both are done
+fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let (mut a, mut b) = (Box::pin(a), Box::pin(b));
+    let (mut a_out, mut b_out) = (None, None);
+    block_on(std::future::poll_fn(move |cx| {
+        if a_out.is_none() {
+            if let Poll::Ready(out) = Pin::new(&mut a).poll(cx) {
+                a_out = Some(out);
+            }
+        }
+        if b_out.is_none() {
+            if let Poll::Ready(out) = Pin::new(&mut b).poll(cx) {
+                b_out = Some(out);
+            }
+        }
+        match (a_out.is_some(), b_out.is_some()) {
+            (true, true) => Poll::Ready((a_out.take().unwrap(), b_out.take().unwrap())),
+            _ => Poll::Pending,
+        }
+    }))
+}
+
+#[test]
+#[serial]
+fn client_commands_and_pipelines() {
+    let server = Server::new(InMemoryStorage::new(), 3389);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let client = Client::connect("127.0.0.1:3389").unwrap();
+
+    block_on(async {
+        assert_eq!(
+            client.command(&[b"SET", b"key", b"value"]).await.unwrap(),
+            Value::Status("OK".to_string())
+        );
+        assert_eq!(
+            client.command(&[b"GET", b"key"]).await.unwrap(),
+            Value::Bulk(b"value".to_vec())
+        );
+        assert_eq!(
+            client.command(&[b"GET", b"missing"]).await.unwrap(),
+            Value::Nil
+        );
+        assert!(matches!(
+            client.command(&[b"NOPE"]).await.unwrap(),
+            Value::Error(_)
+        ));
+
+        let replies = client
+            .pipeline()
+            .command(&[b"RPUSH", b"list", b"a", b"b"])
+            .command(&[b"INCR", b"counter"])
+            .command(&[b"LRANGE", b"list", b"0", b"-1"])
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            replies,
+            vec![
+                Value::Integer(2),
+                Value::Integer(1),
+                Value::Array(vec![Value::Bulk(b"a".to_vec()), Value::Bulk(b"b".to_vec())]),
+            ]
+        );
+        assert_eq!(client.pipeline().execute().await.unwrap(), vec![]);
+    });
+
+    // requests from concurrent tasks get their own replies
+    let (a, b) = join(
+        client.command(&[b"INCR", b"counter"]),
+        client.command(&[b"GET", b"key"]),
+    );
+    assert_eq!(a.unwrap(), Value::Integer(2));
+    assert_eq!(b.unwrap(), Value::Bulk(b"value".to_vec()));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert!(block_on(client.command(&[b"PING"])).is_err());
+}
@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+use raft::message::SendableMessage;
+
+use crate::cluster::node::RaftNode;
+use crate::protocol::parser::RedisProtocolParser;
+use crate::protocol::writer::RespWriter;
+use crate::protocol::Resp;
+use crate::storage::models::RedisString;
+use crate::storage::Storage;
+
+/// First element of the log entries removing expired keys
+const EXPIRED: &[u8] = b"expired";
+
+/// Keys removed by a single log entry at most
+const MAX_KEYS_PER_ENTRY: usize = 1000;
+
+/// Keeps the keys identical on every node of a cluster by removing expired keys through the
+/// Raft log, as nodes expiring keys on their own clock would serve different reads. Nodes
+/// use a storage created with `InMemoryStorage::new_with_replicated_expiry` for that.
+///
+/// The leader appends an entry `["expired", <leader time in ms>, key...]` for the keys due, and
+/// every node removes them when the entry is committed, unless a write made since gave them
+/// an expiry past the leader time. Writes going through the same log, the outcome only
+/// depends on the order of the entries, not on when each node applies them.
+#[derive(Default)]
+pub struct ExpiryCoordinator {
+    // keys in an entry appended but not applied yet, so they aren't appended again meanwhile
+    in_flight: HashSet<RedisString>,
+}
+
+impl ExpiryCoordinator {
+    /// On the leader, append a single entry for the keys whose expiry is past, and return the
+    /// messages to send to the peers. Does nothing on the other nodes.
+    pub fn append_expired<S: Storage>(
+        &mut self,
+        node: &mut RaftNode,
+        storage: &mut S,
+    ) -> Vec<SendableMessage<String>> {
+        if !node.is_leader() {
+            // entries appended while leading may never commit, the next leader appends them again
+            self.in_flight.clear();
+            return Vec::new();
+        }
+
+        let keys: Vec<RedisString> = storage
+            .due_expirations(MAX_KEYS_PER_ENTRY + self.in_flight.len())
+            .into_iter()
+            .filter(|key| !self.in_flight.contains(key))
+            .take(MAX_KEYS_PER_ENTRY)
+            .collect();
+
+        if keys.is_empty() {
+            return Vec::new();
+        }
+
+        let entry = expired_entry(Utc::now().timestamp_millis(), &keys);
+        match node.append(entry) {
+            Ok(messages) => {
+                let messages = messages.collect();
+                self.in_flight.extend(keys);
+                messages
+            }
+            // leadership was lost, the keys are still due for the next leader
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Apply the entries committed since the last call to `storage`, on every node, and return
+    /// how many keys were removed
+    pub fn apply_committed<S: Storage>(&mut self, node: &mut RaftNode, storage: &mut S) -> u32 {
+        let mut removed = 0;
+        for entry in node.take_committed() {
+            // appended by every new leader
+            if entry.data.is_empty() {
+                continue;
+            }
+
+            match parse_expired_entry(&entry.data) {
+                Some((at, keys)) => {
+                    for key in keys {
+                        let is_due = storage
+                            .meta(key)
+                            .and_then(|meta| meta.expiry)
+                            .is_some_and(|expiry| expiry.timestamp <= at);
+                        if is_due {
+                            removed += storage.remove(key);
+                        }
+                        self.in_flight.remove(key);
+                    }
+                }
+                None => log::warn!(
+                    "skipping unknown log entry {:?}",
+                    String::from_utf8_lossy(&entry.data)
+                ),
+            }
+        }
+        removed
+    }
+}
+
+fn expired_entry(at: i64, keys: &[RedisString]) -> Vec<u8> {
+    let mut writer = RespWriter::default();
+    writer.array_len(keys.len() + 2);
+    writer.bulk_string(EXPIRED);
+    writer.bulk_string(at.to_string().as_bytes());
+    for key in keys {
+        writer.bulk_string(key);
+    }
+    writer.as_slice().to_vec()
+}
+
+fn parse_expired_entry(data: &[u8]) -> Option<(i64, Vec<&[u8]>)> {
+    let args = match RedisProtocolParser::parse(data) {
+        Ok((Resp::Array(args), _)) => args,
+        _ => return None,
+    };
+
+    let mut args = args.into_iter().map(|arg| match arg {
+        Resp::BulkString(arg) => Some(arg),
+        _ => None,
+    });
+
+    if args.next()?? != EXPIRED {
+        return None;
+    }
+    let at = std::str::from_utf8(args.next()??).ok()?.parse().ok()?;
+    let keys = args.collect::<Option<Vec<_>>>()?;
+    Some((at, keys))
+}
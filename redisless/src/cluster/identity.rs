@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::path::Path;
+
+use raft::message::LogIndex;
+use uuid::Uuid;
+
+use crate::protocol::parser::RedisProtocolParser;
+use crate::protocol::writer::RespWriter;
+use crate::protocol::Resp;
+
+/// First element of an encoded identity
+const IDENTITY: &[u8] = b"identity";
+
+/// What a node keeps across restarts to rejoin its cluster as the same Raft node, rather than as
+/// a new one the others don't know about, see `ServerClusterOptions::with_identity_file`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeIdentity {
+    pub id: String,
+    /// The peers known when last saved, by ID
+    pub peers: BTreeMap<String, SocketAddr>,
+    /// The index of the last log entry applied when last saved, everything after it has to be
+    /// replicated again
+    pub last_applied: LogIndex,
+}
+
+impl NodeIdentity {
+    /// A node seen for the first time, with a random ID
+    pub fn new() -> Self {
+        NodeIdentity {
+            id: Uuid::new_v4().to_string(),
+            peers: BTreeMap::new(),
+            last_applied: LogIndex::default(),
+        }
+    }
+
+    /// Returns `None` when there is no file at `path`, and an error when it holds something else
+    /// than an identity
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        match NodeIdentity::decode(&data) {
+            Some(identity) => Ok(Some(identity)),
+            None => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "not a node identity file",
+            )),
+        }
+    }
+
+    /// The identity saved at `path`, or a new one saved there
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        if let Some(identity) = NodeIdentity::load(&path)? {
+            return Ok(identity);
+        }
+
+        let identity = NodeIdentity::new();
+        identity.save(path)?;
+        Ok(identity)
+    }
+
+    /// Replace the file at `path` at once, so a crash never leaves half an identity behind
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        fs::write(&tmp, self.encode())?;
+        fs::rename(&tmp, path)
+    }
+
+    /// `["identity", id, last applied index, peer id, peer address...]`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = RespWriter::default();
+        writer.array_len(self.peers.len() * 2 + 3);
+        writer.bulk_string(IDENTITY);
+        writer.bulk_string(self.id.as_bytes());
+        writer.bulk_string(self.last_applied.id.to_string().as_bytes());
+        for (id, addr) in &self.peers {
+            writer.bulk_string(id.as_bytes());
+            writer.bulk_string(addr.to_string().as_bytes());
+        }
+        writer.as_slice().to_vec()
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let args = match RedisProtocolParser::parse(data) {
+            Ok((Resp::Array(args), b"")) => args,
+            _ => return None,
+        };
+
+        let mut args = args.into_iter().map(|arg| match arg {
+            Resp::BulkString(bytes) => std::str::from_utf8(bytes).ok(),
+            _ => None,
+        });
+        if args.next()??.as_bytes() != IDENTITY {
+            return None;
+        }
+        let id = args.next()??.to_string();
+        let last_applied = LogIndex {
+            id: args.next()??.parse().ok()?,
+        };
+
+        let mut peers = BTreeMap::new();
+        while let Some(peer_id) = args.next() {
+            let addr = args.next()??.parse().ok()?;
+            peers.insert(peer_id?.to_string(), addr);
+        }
+
+        Some(NodeIdentity {
+            id,
+            peers,
+            last_applied,
+        })
+    }
+}
+
+impl Default for NodeIdentity {
+    fn default() -> Self {
+        NodeIdentity::new()
+    }
+}
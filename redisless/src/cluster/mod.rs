@@ -1,4 +1,11 @@
-mod node;
+pub mod expiry;
+pub mod identity;
+pub mod multi_raft;
+pub mod node;
 pub mod peer;
+mod slots;
+pub mod snapshot;
 mod tests;
 mod util;
+
+pub use slots::{key_slot, range_of_slot, slot_ranges, SLOTS};
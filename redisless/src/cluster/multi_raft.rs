@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+use raft::log::memory::InMemoryLog;
+use raft::message::{Message, SendableMessage};
+use raft::node::{Config, Node};
+use rand::rngs::OsRng;
+
+use crate::cluster::node::RaftNode;
+use crate::cluster::slots::{key_slot, SLOTS};
+
+/// Index of a Raft group in a `MultiRaft`, the same on every node
+pub type GroupId = usize;
+
+/// Raft group replicating the keys of a range of slots
+pub struct SlotGroup {
+    pub slots: RangeInclusive<u16>,
+    pub node: RaftNode,
+}
+
+/// The Raft groups a node is part of, each owning the keys of a range of slots, so that writes
+/// to different ranges are ordered by different leaders rather than all going through one.
+///
+/// Every node of the cluster runs the same groups over the same ranges. Messages are exchanged
+/// between the nodes of a same group only, tagged with its `GroupId` on the wire.
+pub struct MultiRaft {
+    // by slot range, in order
+    groups: Vec<SlotGroup>,
+}
+
+impl MultiRaft {
+    /// One group per range of `ranges`, each made of `peers`. `None` unless the ranges cover
+    /// every slot, in order, without overlapping.
+    pub fn new(
+        node_id: String,
+        peers: BTreeSet<String>,
+        ranges: Vec<RangeInclusive<u16>>,
+        config: Config,
+    ) -> Option<Self> {
+        let mut next_slot = 0;
+        for range in &ranges {
+            if *range.start() != next_slot || range.end() < range.start() {
+                return None;
+            }
+            next_slot = range.end() + 1;
+        }
+        if next_slot != SLOTS {
+            return None;
+        }
+
+        let groups = ranges
+            .into_iter()
+            .map(|slots| SlotGroup {
+                slots,
+                node: Node::new(
+                    node_id.clone(),
+                    peers.clone(),
+                    InMemoryLog::new_unbounded(),
+                    OsRng,
+                    config.clone(),
+                ),
+            })
+            .collect();
+        Some(MultiRaft { groups })
+    }
+
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn group(&self, group: GroupId) -> &SlotGroup {
+        &self.groups[group]
+    }
+
+    pub fn node_mut(&mut self, group: GroupId) -> &mut RaftNode {
+        &mut self.groups[group].node
+    }
+
+    /// The group owning `slot`
+    pub fn group_of_slot(&self, slot: u16) -> GroupId {
+        // the ranges cover every slot in order, the first ending at or after `slot` owns it
+        self.groups
+            .partition_point(|group| *group.slots.end() < slot)
+            .min(self.groups.len() - 1)
+    }
+
+    /// The group owning `key`, see `key_slot`
+    pub fn group_of(&self, key: &[u8]) -> GroupId {
+        self.group_of_slot(key_slot(key))
+    }
+
+    /// The leader of `group` as far as this node knows
+    pub fn leader(&self, group: GroupId) -> Option<&String> {
+        self.groups[group].node.leader().0
+    }
+
+    /// Append `data`, a write to `key`, to the log of the group owning it, and return the group
+    /// with the messages to send to its peers. `None` when this node doesn't lead that group, the
+    /// write then has to be sent to its `leader`.
+    pub fn append(
+        &mut self,
+        key: &[u8],
+        data: Vec<u8>,
+    ) -> Option<(GroupId, Vec<SendableMessage<String>>)> {
+        let group = self.group_of(key);
+        let messages = self.groups[group].node.append(data).ok()?.collect();
+        Some((group, messages))
+    }
+
+    /// Tick the clock of every group, and return the messages to send with their group
+    pub fn timer_tick(&mut self) -> Vec<(GroupId, SendableMessage<String>)> {
+        let mut messages = Vec::new();
+        for (group, slot_group) in self.groups.iter_mut().enumerate() {
+            messages.extend(slot_group.node.timer_tick().map(|message| (group, message)));
+        }
+        messages
+    }
+
+    /// Hand `message` from `from` to `group`, and return the replies to send within the group
+    pub fn receive(
+        &mut self,
+        group: GroupId,
+        message: Message,
+        from: String,
+    ) -> Vec<SendableMessage<String>> {
+        match self.groups.get_mut(group) {
+            Some(slot_group) => slot_group.node.receive(message, from).collect(),
+            // from a node running other groups, it can't be part of this cluster
+            None => Vec::new(),
+        }
+    }
+}
@@ -1,12 +1,18 @@
+use std::collections::BTreeMap;
+use std::io;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use rand::rngs::OsRng;
 
 use raft::log::memory::InMemoryLog;
+use raft::log::Log;
 use raft::node::Node;
 
+use crate::cluster::identity::NodeIdentity;
 use crate::cluster::peer::{Peer, Peers, PeersDiscovery, DEFAULT_NODE_LISTENING_PORT};
 use crate::cluster::util::{get_ip_addresses, get_local_network_ip_addresses, scan_ip_range};
+use crate::raft_transport::directory::PeerDirectory;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use std::borrow::Borrow;
 use std::collections::{HashSet, LinkedList};
@@ -17,7 +23,7 @@ const SEARCH_PEERS_TICK_SECONDS: u64 = 600;
 pub const GETINFO_REQUEST: &[u8; 7] = b"getinfo";
 pub const GETINFO_RESPONSE: &[u8; 9] = b"redisless";
 
-type RaftNode = Node<InMemoryLog, OsRng, String>;
+pub type RaftNode = Node<InMemoryLog, OsRng, String>;
 
 pub struct ClusterNode {
     node: RaftNode,
@@ -25,6 +31,10 @@ pub struct ClusterNode {
     peer_receiver: Receiver<Peer>,
     listener_started: bool,
     search_peers_started: bool,
+    known_peers: BTreeMap<String, SocketAddr>,
+    // addresses of the known and discovered peers, for the transport to reach them
+    directory: PeerDirectory,
+    identity_file: Option<PathBuf>,
 }
 
 impl ClusterNode {
@@ -32,6 +42,7 @@ impl ClusterNode {
         node: RaftNode,
         peers_discovery: PeersDiscovery,
         listening_socket_addr: SocketAddr,
+        known_peers: BTreeMap<String, SocketAddr>,
     ) -> Self {
         let (tx, rx) = unbounded::<Peer>();
 
@@ -41,6 +52,9 @@ impl ClusterNode {
             peer_receiver: rx,
             listener_started: false,
             search_peers_started: false,
+            directory: PeerDirectory::from(known_peers.clone()),
+            known_peers,
+            identity_file: None,
         };
 
         cn.start_search_peers(tx, peers_discovery);
@@ -48,6 +62,60 @@ impl ClusterNode {
         cn
     }
 
+    /// Save the identity of the node to `path` with `save_identity`
+    pub fn with_identity_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.identity_file = Some(path.into());
+        self
+    }
+
+    pub fn identity(&self) -> NodeIdentity {
+        NodeIdentity {
+            id: self.node.node_id().clone(),
+            peers: self.known_peers.clone(),
+            last_applied: self.node.log().last_taken_index(),
+        }
+    }
+
+    /// Record the identity of the node in its identity file, if it has one, for it to rejoin as
+    /// itself once restarted
+    pub fn save_identity(&self) -> io::Result<()> {
+        match &self.identity_file {
+            Some(path) => self.identity().save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Remember `peer` across restarts, once it's part of the Raft group
+    pub fn remember_peer(&mut self, peer: &Peer) -> io::Result<()> {
+        self.known_peers
+            .insert(peer.id().to_string(), peer.listening_socket_addr());
+        self.directory
+            .insert(peer.id(), peer.listening_socket_addr());
+        self.save_identity()
+    }
+
+    /// Forget `node_id` once it left the Raft group, its messages aren't sent anymore
+    pub fn forget_peer(&mut self, node_id: &str) -> io::Result<()> {
+        self.known_peers.remove(node_id);
+        self.directory.remove(node_id);
+        self.save_identity()
+    }
+
+    /// Addresses of the peers, updated as they're remembered, forgotten or discovered
+    pub fn peer_directory(&self) -> &PeerDirectory {
+        &self.directory
+    }
+
+    /// Record the address of the peers discovered since last called, those that moved included
+    pub fn address_discovered_peers(&self) {
+        for peer in self.peer_receiver.try_iter() {
+            if peer.id() != self.node.node_id() {
+                self.directory
+                    .insert(peer.id(), peer.listening_socket_addr());
+            }
+        }
+    }
+
     /// search for peers every tick
     fn start_search_peers(&mut self, sender: Sender<Peer>, peers_discovery: PeersDiscovery) {
         if self.search_peers_started {
@@ -1,9 +1,10 @@
+use crate::cluster::identity::NodeIdentity;
 use crate::cluster::node::ClusterNode;
 use crate::cluster::util::{get_ip_addresses, get_local_network_ip_addresses, scan_ip_range};
 use raft::log::memory::InMemoryLog;
 use raft::node::{Config, Node};
 use rand::rngs::OsRng;
-use std::collections::BTreeSet;
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
 
 pub const DEFAULT_NODE_LISTENING_PORT: u16 = 8686;
@@ -12,6 +13,8 @@ const CONFIG: Config = Config {
     election_timeout_ticks: 10,
     heartbeat_interval_ticks: 5,
     replication_chunk_size: 65536,
+    check_quorum: false,
+    checksum_entries: false,
 };
 
 pub type Peers = Vec<Peer>;
@@ -21,6 +24,8 @@ pub struct Peer {
     id: String,
     peers_discovery: PeersDiscovery,
     listening_socket_addr: SocketAddr,
+    // the Raft group the node was part of before restarting, empty for a new node
+    known_peers: BTreeMap<String, SocketAddr>,
 }
 
 impl Peer {
@@ -33,20 +38,46 @@ impl Peer {
             id: id.into(),
             peers_discovery,
             listening_socket_addr,
+            known_peers: BTreeMap::new(),
         }
     }
 
+    /// The node `identity` was saved by, to rejoin the same Raft group with the same ID
+    pub fn from_identity(
+        identity: NodeIdentity,
+        peers_discovery: PeersDiscovery,
+        listening_socket_addr: SocketAddr,
+    ) -> Self {
+        Peer {
+            id: identity.id,
+            peers_discovery,
+            listening_socket_addr,
+            known_peers: identity.peers,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn listening_socket_addr(&self) -> SocketAddr {
+        self.listening_socket_addr
+    }
+
+    /// The known peers are the voters of the Raft group, as they were before restarting: the
+    /// others still count the node in their quorum, it isn't a learner to add again
     pub fn into_cluster_node(self) -> ClusterNode {
         ClusterNode::new(
             Node::new(
                 self.id,
-                BTreeSet::new(),
+                self.known_peers.keys().cloned().collect(),
                 InMemoryLog::new_unbounded(),
                 OsRng::default(),
                 CONFIG,
             ),
             self.peers_discovery,
             self.listening_socket_addr,
+            self.known_peers,
         )
     }
 }
@@ -0,0 +1,50 @@
+use std::ops::RangeInclusive;
+
+/// Number of hash slots the keys are spread over, as in Redis Cluster
+pub const SLOTS: u16 = 16384;
+
+/// Hash slot of `key`, as computed by Redis Cluster: the CRC16 of the key modulo `SLOTS`. When
+/// the key holds a non-empty `{...}` hash tag, only the tag is hashed, so that keys sharing it
+/// land in the same slot.
+pub fn key_slot(key: &[u8]) -> u16 {
+    let hashed = match key.iter().position(|b| *b == b'{') {
+        Some(open) => match key[open + 1..].iter().position(|b| *b == b'}') {
+            Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+            _ => key,
+        },
+        None => key,
+    };
+    crc16(hashed) % SLOTS
+}
+
+/// `count` ranges of about the same number of slots, covering every slot in order, to spread
+/// the keys evenly over `count` nodes
+pub fn slot_ranges(count: u16) -> Vec<RangeInclusive<u16>> {
+    let count = count.clamp(1, SLOTS);
+    (0..count)
+        .map(|n| {
+            let start = (n as u32 * SLOTS as u32 / count as u32) as u16;
+            let end = ((n as u32 + 1) * SLOTS as u32 / count as u32) as u16 - 1;
+            start..=end
+        })
+        .collect()
+}
+
+/// Index in `ranges` of the range holding `slot`, such as the node serving it when there's one
+/// range per node, `None` when no range holds it
+pub fn range_of_slot(ranges: &[RangeInclusive<u16>], slot: u16) -> Option<usize> {
+    ranges.iter().position(|range| range.contains(&slot))
+}
+
+/// CRC16-CCITT (XMODEM), the variant used by Redis Cluster
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
@@ -0,0 +1,98 @@
+use raft::log::memory::InMemoryLog;
+use raft::log::Log;
+use raft::message::{LogIndex, TermId};
+
+use crate::cluster::node::RaftNode;
+use crate::protocol::parser::RedisProtocolParser;
+use crate::protocol::writer::RespWriter;
+use crate::protocol::Resp;
+use crate::storage::models::entry::{bulk_string, parse_number};
+use crate::storage::models::{DatasetSnapshot, StorageEntry};
+use crate::storage::Storage;
+
+/// First element of an encoded snapshot
+const SNAPSHOT: &[u8] = b"snapshot";
+
+/// Every key of the storage of a node as of a log entry, to bring a node joining the cluster, or
+/// too far behind to be sent the entries it misses (see `raft::node::Node::needs_snapshot`), up
+/// to date before it applies the log.
+///
+/// The entry at `index` is the cut-over point: the snapshot holds the writes of every entry up to
+/// it, and the node installing it then applies the entries after it only, so no committed write is
+/// missed or applied twice.
+#[derive(Debug, PartialEq)]
+pub struct Snapshot {
+    pub index: LogIndex,
+    pub term: TermId,
+    pub entries: Vec<StorageEntry>,
+}
+
+impl Snapshot {
+    /// Copy `storage`, which must hold the writes of every entry taken from the log of `node` and
+    /// nothing else, e.g. by keeping it locked from applying the entries until the copy is done
+    pub fn take<S: Storage>(node: &mut RaftNode, storage: &mut S) -> Snapshot {
+        let index = node.log().last_taken_index();
+        // the term of an entry taken is known until it is discarded, and after as `prev_term`
+        let term = node.log_mut().get_term(index).unwrap_or_default();
+
+        Snapshot {
+            index,
+            term,
+            entries: storage.export().entries,
+        }
+    }
+
+    /// Replace the content of `storage` with the snapshot, and the log of `node` with one starting
+    /// after the cut-over point. Returns `false` without changing anything when `node` leads, or
+    /// already committed the entries of the snapshot.
+    pub fn install<S: Storage>(self, node: &mut RaftNode, storage: &mut S) -> bool {
+        let log = InMemoryLog::starting_after(self.index, self.term);
+        if node.install_snapshot(log).is_err() {
+            return false;
+        }
+
+        storage.import(DatasetSnapshot {
+            entries: self.entries,
+        });
+        true
+    }
+
+    /// `["snapshot", index, term, entry...]`, see `StorageEntry::encode`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = RespWriter::default();
+        writer.array_len(self.entries.len() + 3);
+        writer.bulk_string(SNAPSHOT);
+        writer.bulk_string(self.index.id.to_string().as_bytes());
+        writer.bulk_string(self.term.id.to_string().as_bytes());
+
+        for entry in &self.entries {
+            entry.encode(&mut writer);
+        }
+        writer.as_slice().to_vec()
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Snapshot> {
+        let args = match RedisProtocolParser::parse(data) {
+            Ok((Resp::Array(args), b"")) => args,
+            _ => return None,
+        };
+
+        let mut args = args.into_iter();
+        if bulk_string(args.next()?)? != SNAPSHOT {
+            return None;
+        }
+        let index = LogIndex {
+            id: parse_number(bulk_string(args.next()?)?)?,
+        };
+        let term = TermId {
+            id: parse_number(bulk_string(args.next()?)?)?,
+        };
+        let entries = args.map(StorageEntry::decode).collect::<Option<_>>()?;
+
+        Some(Snapshot {
+            index,
+            term,
+            entries,
+        })
+    }
+}
@@ -7,6 +7,45 @@ use crate::cluster::util::{
     Range,
 };
 
+#[cfg(test)]
+const RAFT_CONFIG: raft::node::Config = raft::node::Config {
+    election_timeout_ticks: 10,
+    heartbeat_interval_ticks: 5,
+    replication_chunk_size: 65536,
+    check_quorum: false,
+    checksum_entries: false,
+};
+
+/// Hand every message to its destination, and the replies in turn, until there are none left.
+/// The nodes in `down` get nothing.
+#[cfg(test)]
+fn deliver(
+    nodes: &mut [crate::cluster::node::RaftNode],
+    from: usize,
+    messages: Vec<raft::message::SendableMessage<String>>,
+    down: &[usize],
+) {
+    use raft::message::MessageDestination;
+    use std::collections::VecDeque;
+
+    let mut queue: VecDeque<_> = messages.into_iter().map(|m| (from, m)).collect();
+    while let Some((from, sendable)) = queue.pop_front() {
+        let from_id = nodes[from].node_id().clone();
+        for to in (0..nodes.len()).filter(|to| *to != from && !down.contains(to)) {
+            let is_dest = match &sendable.dest {
+                MessageDestination::Broadcast => true,
+                MessageDestination::To(id) => id == nodes[to].node_id(),
+            };
+            if is_dest {
+                let replies: Vec<_> = nodes[to]
+                    .receive(sendable.message.clone(), from_id.clone())
+                    .collect();
+                queue.extend(replies.into_iter().map(|m| (to, m)));
+            }
+        }
+    }
+}
+
 #[test]
 fn start_cluster() {
     // TODO
@@ -131,3 +170,509 @@ fn test_scan_ip_range_with_4_peers() {
 
     //assert_eq!(opened_sockets.len(), 0);
 }
+
+#[test]
+fn expirations_are_replicated_through_the_log() {
+    use std::collections::BTreeSet;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use raft::log::memory::InMemoryLog;
+    use raft::node::Node;
+    use rand::rngs::OsRng;
+
+    use crate::cluster::expiry::ExpiryCoordinator;
+    use crate::cluster::node::RaftNode;
+    use crate::storage::in_memory::InMemoryStorage;
+    use crate::storage::models::Expiry;
+    use crate::storage::Storage;
+
+    let ids: BTreeSet<String> = ["a", "b"].iter().map(|id| id.to_string()).collect();
+    let mut nodes: Vec<RaftNode> = ids
+        .iter()
+        .map(|id| {
+            Node::new(
+                id.clone(),
+                ids.clone(),
+                InMemoryLog::new_unbounded(),
+                OsRng,
+                RAFT_CONFIG,
+            )
+        })
+        .collect();
+
+    // only the first node's clock runs, so it gets elected
+    for _ in 0..1000 {
+        if nodes[0].is_leader() {
+            break;
+        }
+        let messages = nodes[0].timer_tick().collect();
+        deliver(&mut nodes, 0, messages, &[]);
+    }
+    assert!(nodes[0].is_leader());
+
+    // as if SET and PEXPIRE had been applied from the log on both nodes
+    let soon = Expiry::new_from_millis(10).unwrap();
+    let later = Expiry::new_from_secs(100).unwrap();
+    let mut storages: Vec<InMemoryStorage> = (0..2)
+        .map(|_| {
+            let mut storage = InMemoryStorage::new_with_replicated_expiry();
+            storage.write(b"expired", b"v");
+            storage.expire(b"expired", soon);
+            storage.write(b"kept", b"v");
+            storage.expire(b"kept", later);
+            storage
+        })
+        .collect();
+    let mut coordinators: Vec<ExpiryCoordinator> = (0..2).map(|_| Default::default()).collect();
+    sleep(Duration::from_millis(20));
+
+    // nodes don't expire keys on their own clock
+    for storage in storages.iter_mut() {
+        assert_eq!(storage.remove_expired(10), 0);
+        assert_eq!(storage.read(b"expired"), Some(&b"v"[..]));
+    }
+
+    // only the leader appends, once per key
+    let messages = coordinators[1].append_expired(&mut nodes[1], &mut storages[1]);
+    assert!(messages.is_empty());
+    let messages = coordinators[0].append_expired(&mut nodes[0], &mut storages[0]);
+    assert!(!messages.is_empty());
+    deliver(&mut nodes, 0, messages, &[]);
+    let messages = coordinators[0].append_expired(&mut nodes[0], &mut storages[0]);
+    assert!(messages.is_empty());
+
+    // the follower learns the entry is committed with the next heartbeat
+    for _ in 0..RAFT_CONFIG.heartbeat_interval_ticks {
+        let messages = nodes[0].timer_tick().collect();
+        deliver(&mut nodes, 0, messages, &[]);
+    }
+
+    for i in 0..2 {
+        assert_eq!(
+            coordinators[i].apply_committed(&mut nodes[i], &mut storages[i]),
+            1
+        );
+        assert_eq!(storages[i].read(b"expired"), None);
+        assert_eq!(storages[i].read(b"kept"), Some(&b"v"[..]));
+        assert_eq!(storages[i].size(), 1);
+    }
+
+    let messages = coordinators[0].append_expired(&mut nodes[0], &mut storages[0]);
+    assert!(messages.is_empty());
+}
+
+#[test]
+fn lagging_node_resyncs_from_a_snapshot() {
+    use std::collections::BTreeSet;
+
+    use raft::log::memory::InMemoryLog;
+    use raft::node::Node;
+    use rand::rngs::OsRng;
+
+    use crate::cluster::node::RaftNode;
+    use crate::cluster::snapshot::Snapshot;
+    use crate::storage::in_memory::InMemoryStorage;
+    use crate::storage::models::Expiry;
+    use crate::storage::Storage;
+
+    // as if the entries were SET commands, return how many were applied
+    fn apply(node: &mut RaftNode, storage: &mut InMemoryStorage) -> usize {
+        let mut applied = 0;
+        for entry in node.take_committed().filter(|entry| !entry.data.is_empty()) {
+            let mut split = entry.data.splitn(2, |b| *b == b'=');
+            let (key, value) = (split.next().unwrap(), split.next().unwrap());
+            storage.write(key, value);
+            applied += 1;
+        }
+        applied
+    }
+
+    let ids: BTreeSet<String> = ["a", "b", "c"].iter().map(|id| id.to_string()).collect();
+    let mut nodes: Vec<RaftNode> = ids
+        .iter()
+        .map(|id| {
+            Node::new(
+                id.clone(),
+                ids.clone(),
+                InMemoryLog::new_unbounded(),
+                OsRng,
+                RAFT_CONFIG,
+            )
+        })
+        .collect();
+    let mut storages: Vec<InMemoryStorage> = (0..3).map(|_| InMemoryStorage::new()).collect();
+
+    // the last node joins later
+    let down = [2];
+    for _ in 0..1000 {
+        if nodes[0].is_leader() {
+            break;
+        }
+        let messages = nodes[0].timer_tick().collect();
+        deliver(&mut nodes, 0, messages, &down);
+    }
+    assert!(nodes[0].is_leader());
+
+    for data in &["k1=v1", "k2=v2"] {
+        let messages = nodes[0].append(*data).ok().unwrap().collect();
+        deliver(&mut nodes, 0, messages, &down);
+    }
+    assert_eq!(apply(&mut nodes[0], &mut storages[0]), 2);
+    storages[0].expire(b"k2", Expiry::new_from_secs(100).unwrap());
+
+    // the leader discards the entries in its snapshot
+    let snapshot = Snapshot::take(&mut nodes[0], &mut storages[0]);
+    assert_eq!(snapshot.entries.len(), 2);
+    assert!(nodes[0].log_mut().compact_until(snapshot.index).is_ok());
+    assert!(nodes[0].needs_snapshot(&"c".to_string()));
+
+    // committed after the snapshot was taken
+    let messages = nodes[0].append("k3=v3").ok().unwrap().collect();
+    deliver(&mut nodes, 0, messages, &down);
+
+    let snapshot = Snapshot::decode(&snapshot.encode()).unwrap();
+    assert_eq!(Snapshot::decode(b"*1\r\n$8\r\nsnapshot\r\n"), None);
+    let index = snapshot.index;
+    storages[2].write(b"stale", b"v");
+    assert!(snapshot.install(&mut nodes[2], &mut storages[2]));
+    assert_eq!(storages[2].read(b"k1"), Some(&b"v1"[..]));
+    assert_eq!(storages[2].read(b"k3"), None);
+    assert_eq!(storages[2].read(b"stale"), None);
+    assert_eq!(storages[2].dump_entry(b"k2"), storages[0].dump_entry(b"k2"));
+
+    // the messages dropped while the node was down are sent again
+    assert!(nodes[0].state_mut().reset_peer("c".to_string()).is_none());
+
+    // the entries past the cut-over are replicated as usual, the ones before never applied
+    for _ in 0..RAFT_CONFIG.heartbeat_interval_ticks * 4 {
+        let messages = nodes[0].timer_tick().collect();
+        deliver(&mut nodes, 0, messages, &[]);
+    }
+    assert_eq!(apply(&mut nodes[2], &mut storages[2]), 1);
+    assert_eq!(storages[2].read(b"k3"), Some(&b"v3"[..]));
+    assert_eq!(storages[2].size(), 3);
+
+    // too late once the entries are committed on the node
+    let snapshot = Snapshot {
+        index,
+        term: Default::default(),
+        entries: Vec::new(),
+    };
+    assert!(!snapshot.install(&mut nodes[2], &mut storages[2]));
+    assert_eq!(storages[2].size(), 3);
+}
+
+#[test]
+fn identities_survive_restarts() {
+    use std::fs;
+    use std::io::ErrorKind;
+
+    use raft::message::LogIndex;
+
+    use crate::cluster::identity::NodeIdentity;
+
+    let path = std::env::temp_dir().join(format!("redisless-identity-{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    assert!(NodeIdentity::load(&path).unwrap().is_none());
+    let identity = NodeIdentity::load_or_create(&path).unwrap();
+    assert!(identity.peers.is_empty());
+    assert_eq!(NodeIdentity::load_or_create(&path).unwrap(), identity);
+    assert_ne!(NodeIdentity::new().id, identity.id);
+
+    let mut identity = identity;
+    identity.peers.insert(
+        "b".to_string(),
+        SocketAddr::from(([192, 168, 0, 2], DEFAULT_NODE_LISTENING_PORT)),
+    );
+    identity.last_applied = LogIndex { id: 42 };
+    assert_eq!(
+        NodeIdentity::decode(&identity.encode()),
+        Some(identity.clone())
+    );
+    identity.save(&path).unwrap();
+    assert_eq!(NodeIdentity::load(&path).unwrap(), Some(identity.clone()));
+
+    // the restarted node has the same ID and peers
+    let addr = SocketAddr::from(([127, 0, 0, 1], DEFAULT_NODE_LISTENING_PORT));
+    let peers_discovery = PeersDiscovery::Manual(vec![]);
+    let node = Peer::from_identity(identity.clone(), peers_discovery, addr).into_cluster_node();
+    let restarted = node.identity();
+    assert_eq!(restarted.id, identity.id);
+    assert_eq!(restarted.peers, identity.peers);
+
+    fs::write(&path, b"*1\r\n$8\r\nsnapshot\r\n").unwrap();
+    assert_eq!(
+        NodeIdentity::load(&path).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    assert!(NodeIdentity::load_or_create(&path).is_err());
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn server_role_follows_the_raft_leader() {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use raft::log::memory::InMemoryLog;
+    use raft::node::Node;
+    use rand::rngs::OsRng;
+
+    use crate::cluster::node::RaftNode;
+    use crate::server::ServerRole;
+
+    let ids: BTreeSet<String> = ["a", "b", "c"].iter().map(|id| id.to_string()).collect();
+    let addrs: BTreeMap<String, SocketAddr> = ids
+        .iter()
+        .zip(3000..)
+        .map(|(id, port)| (id.clone(), SocketAddr::from(([127, 0, 0, 1], port))))
+        .collect();
+    let mut nodes: Vec<RaftNode> = ids
+        .iter()
+        .map(|id| {
+            Node::new(
+                id.clone(),
+                ids.clone(),
+                InMemoryLog::new_unbounded(),
+                OsRng,
+                RAFT_CONFIG,
+            )
+        })
+        .collect();
+
+    // no leader known before the first election
+    assert_eq!(
+        ServerRole::of_raft_node(&nodes[1], &addrs),
+        ServerRole::Follower { leader: None }
+    );
+
+    for _ in 0..1000 {
+        if nodes[1].is_leader() {
+            break;
+        }
+        let messages = nodes[1].timer_tick().collect();
+        deliver(&mut nodes, 1, messages, &[]);
+    }
+    // the followers learn about the leader from its first heartbeat
+    let messages = nodes[1].append("").ok().unwrap().collect();
+    deliver(&mut nodes, 1, messages, &[]);
+
+    assert_eq!(
+        ServerRole::of_raft_node(&nodes[1], &addrs),
+        ServerRole::Leader
+    );
+    for follower in [0, 2] {
+        assert_eq!(
+            ServerRole::of_raft_node(&nodes[follower], &addrs),
+            ServerRole::Follower {
+                leader: Some(addrs["b"])
+            }
+        );
+    }
+}
+
+#[test]
+fn cluster_status_tracks_the_replication_of_each_peer() {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::time::Instant;
+
+    use raft::log::memory::InMemoryLog;
+    use raft::node::Node;
+    use rand::rngs::OsRng;
+
+    use crate::cluster::node::RaftNode;
+    use crate::server::ClusterStatus;
+
+    let ids: BTreeSet<String> = ["a", "b", "c"].iter().map(|id| id.to_string()).collect();
+    let mut nodes: Vec<RaftNode> = ids
+        .iter()
+        .map(|id| {
+            Node::new(
+                id.clone(),
+                ids.clone(),
+                InMemoryLog::new_unbounded(),
+                OsRng,
+                RAFT_CONFIG,
+            )
+        })
+        .collect();
+
+    for _ in 0..1000 {
+        if nodes[0].is_leader() {
+            break;
+        }
+        let messages = nodes[0].timer_tick().collect();
+        deliver(&mut nodes, 0, messages, &[]);
+    }
+    // "c" is down and misses both entries
+    let messages = nodes[0].append("x").ok().unwrap().collect();
+    deliver(&mut nodes, 0, messages, &[2]);
+    let messages = nodes[0].append("y").ok().unwrap().collect();
+    deliver(&mut nodes, 0, messages, &[2]);
+
+    let contact = Instant::now();
+    let last_contact: BTreeMap<String, Instant> = [("b".to_string(), contact)].into();
+    let status = ClusterStatus::of_raft_node(&nodes[0], &last_contact);
+    assert_eq!(status.node_id, "a");
+    assert_eq!(status.leader.as_deref(), Some("a"));
+    assert_eq!(status.term, nodes[0].metrics().current_term.id);
+    assert_eq!(status.commit_index, status.last_index);
+
+    let peers: Vec<_> = status
+        .peers
+        .iter()
+        .map(|peer| (peer.node_id.as_str(), peer.lag, peer.last_contact))
+        .collect();
+    assert_eq!(
+        peers,
+        vec![
+            ("b", Some(0), Some(contact)),
+            ("c", Some(2), None)
+        ]
+    );
+
+    // followers don't track the replication of their peers
+    let status = ClusterStatus::of_raft_node(&nodes[1], &BTreeMap::new());
+    assert_eq!(status.leader.as_deref(), Some("a"));
+    assert!(status.peers.iter().all(|peer| peer.match_index.is_none()));
+}
+
+#[test]
+fn keys_are_hashed_to_slots() {
+    use crate::cluster::slots::{key_slot, SLOTS};
+
+    // as computed by Redis Cluster
+    assert_eq!(key_slot(b"123456789"), 0x31c3);
+    assert_eq!(key_slot(b"foo"), 12182);
+    assert_eq!(key_slot(b"bar"), 5061);
+    assert_eq!(key_slot(b""), 0);
+
+    // only the hash tag is hashed, unless it's empty
+    assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+    assert_eq!(
+        key_slot(b"{user1000}.following"),
+        key_slot(b"{user1000}.followers")
+    );
+    assert_eq!(key_slot(b"foo{}{bar}"), key_slot(b"foo{}{bar}"));
+    assert_ne!(key_slot(b"foo{}{bar}"), key_slot(b"bar"));
+    assert_eq!(key_slot(b"foo{{bar}}zap"), key_slot(b"{bar"));
+    assert_eq!(key_slot(b"foo{bar"), key_slot(b"foo{bar"));
+    assert!((0..1000).all(|n| key_slot(format!("key:{}", n).as_bytes()) < SLOTS));
+}
+
+#[test]
+fn writes_are_ordered_by_the_group_owning_their_slot() {
+    use std::collections::BTreeSet;
+
+    use raft::message::{MessageDestination, SendableMessage};
+
+    use crate::cluster::multi_raft::{GroupId, MultiRaft};
+    use crate::cluster::slots::{key_slot, range_of_slot, slot_ranges, SLOTS};
+
+    fn deliver_group(
+        nodes: &mut [MultiRaft],
+        ids: &[String],
+        group: GroupId,
+        from: usize,
+        messages: Vec<SendableMessage<String>>,
+    ) {
+        let mut queue: Vec<_> = messages.into_iter().map(|m| (from, m)).collect();
+        while let Some((from, sendable)) = queue.pop() {
+            for to in (0..nodes.len()).filter(|to| *to != from) {
+                let is_dest = match &sendable.dest {
+                    MessageDestination::Broadcast => true,
+                    MessageDestination::To(id) => *id == ids[to],
+                };
+                if is_dest {
+                    let replies =
+                        nodes[to].receive(group, sendable.message.clone(), ids[from].clone());
+                    queue.extend(replies.into_iter().map(|m| (to, m)));
+                }
+            }
+        }
+    }
+
+    assert!(MultiRaft::new("a".to_string(), BTreeSet::new(), vec![0..=100], RAFT_CONFIG).is_none());
+    assert!(MultiRaft::new(
+        "a".to_string(),
+        BTreeSet::new(),
+        vec![0..=100, 100..=SLOTS - 1],
+        RAFT_CONFIG
+    )
+    .is_none());
+    let ranges = slot_ranges(3);
+    assert_eq!(ranges, vec![0..=5460, 5461..=10921, 10922..=16383]);
+    assert_eq!(slot_ranges(1), vec![0..=SLOTS - 1]);
+    assert_eq!(range_of_slot(&ranges, 0), Some(0));
+    assert_eq!(range_of_slot(&ranges, 5461), Some(1));
+    assert_eq!(range_of_slot(&ranges, SLOTS - 1), Some(2));
+    assert_eq!(range_of_slot(&ranges, SLOTS), None);
+
+    let ids: Vec<String> = ["a", "b", "c"].iter().map(|id| id.to_string()).collect();
+    let peers: BTreeSet<String> = ids.iter().cloned().collect();
+    let mut nodes: Vec<MultiRaft> = ids
+        .iter()
+        .map(|id| MultiRaft::new(id.clone(), peers.clone(), slot_ranges(2), RAFT_CONFIG).unwrap())
+        .collect();
+    assert_eq!(nodes[0].len(), 2);
+    assert_eq!(nodes[0].group_of_slot(0), 0);
+    assert_eq!(nodes[0].group_of_slot(8191), 0);
+    assert_eq!(nodes[0].group_of_slot(8192), 1);
+    assert_eq!(nodes[0].group_of_slot(SLOTS - 1), 1);
+    // "bar" is in slot 5061, "foo" in 12182
+    assert_eq!(nodes[0].group_of(b"bar"), 0);
+    assert_eq!(nodes[0].group_of(b"foo"), 1);
+    assert_eq!(key_slot(b"{foo}.other"), key_slot(b"foo"));
+
+    // each group elects a leader of its own: "a" leads group 0 and "b" group 1
+    for (group, leader) in [(0, 0), (1, 1)] {
+        for _ in 0..1000 {
+            if nodes[leader].group(group).node.is_leader() {
+                break;
+            }
+            let messages = nodes[leader].node_mut(group).timer_tick().collect();
+            deliver_group(&mut nodes, &ids, group, leader, messages);
+        }
+        assert!(nodes[leader].group(group).node.is_leader());
+    }
+
+    // writes go to the leader of the group owning the key, whichever it is
+    assert!(nodes[1].append(b"bar", b"bar=1".to_vec()).is_none());
+    let (group, messages) = nodes[0].append(b"bar", b"bar=1".to_vec()).unwrap();
+    assert_eq!(group, 0);
+    deliver_group(&mut nodes, &ids, group, 0, messages);
+    assert!(nodes[0].append(b"foo", b"foo=1".to_vec()).is_none());
+    let (group, messages) = nodes[1].append(b"foo", b"foo=1".to_vec()).unwrap();
+    assert_eq!(group, 1);
+    deliver_group(&mut nodes, &ids, group, 1, messages);
+
+    // the leaders tell the followers what was committed
+    for (group, leader) in [(0, 0), (1, 1)] {
+        for _ in 0..RAFT_CONFIG.heartbeat_interval_ticks {
+            let messages = nodes[leader].node_mut(group).timer_tick().collect();
+            deliver_group(&mut nodes, &ids, group, leader, messages);
+        }
+    }
+    for node in &mut nodes {
+        assert_eq!(node.leader(0), Some(&ids[0]));
+        assert_eq!(node.leader(1), Some(&ids[1]));
+        for (group, data) in [(0, &b"bar=1"[..]), (1, b"foo=1")] {
+            let committed: Vec<_> = node
+                .node_mut(group)
+                .take_committed()
+                .filter(|entry| !entry.data.is_empty())
+                .map(|entry| entry.data.to_vec())
+                .collect();
+            assert_eq!(committed, vec![data.to_vec()]);
+        }
+    }
+
+    // a message for a group the node doesn't run is dropped
+    let message = loop {
+        if let Some((_, message)) = nodes[0].timer_tick().into_iter().next() {
+            break message.message;
+        }
+    };
+    assert!(nodes[2].receive(7, message, ids[0].clone()).is_empty());
+}
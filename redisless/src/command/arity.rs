@@ -0,0 +1,53 @@
+use super::command_error::RedisCommandError;
+use super::table::command_spec;
+
+/// Number of arguments a command accepts, counting the command name like Redis does
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Arity {
+    pub min: usize,
+    /// `None` for variadic commands
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    /// As reported by COMMAND: the number of arguments, or its opposite for a minimum
+    pub fn to_redis(self) -> i64 {
+        match self.max {
+            Some(max) if max == self.min => self.min as i64,
+            _ => -(self.min as i64),
+        }
+    }
+}
+
+pub(super) const fn exactly(n: usize) -> Arity {
+    Arity {
+        min: n,
+        max: Some(n),
+    }
+}
+
+pub(super) const fn between(min: usize, max: usize) -> Arity {
+    Arity {
+        min,
+        max: Some(max),
+    }
+}
+
+pub(super) const fn at_least(min: usize) -> Arity {
+    Arity { min, max: None }
+}
+
+/// Arity of `command`, `None` if it's not supported
+pub fn arity(command: &str) -> Option<Arity> {
+    command_spec(command).map(|spec| spec.arity)
+}
+
+/// Check the number of arguments of a request, `args` counting the command name
+pub fn check_arity(command: &[u8], args: usize) -> Result<(), RedisCommandError> {
+    let command = String::from_utf8_lossy(command).to_lowercase();
+    match arity(&command) {
+        Some(Arity { min, .. }) if args < min => Err(RedisCommandError::ArgNumber),
+        Some(Arity { max: Some(max), .. }) if args > max => Err(RedisCommandError::ArgNumber),
+        _ => Ok(()),
+    }
+}
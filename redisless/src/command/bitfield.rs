@@ -0,0 +1,209 @@
+use super::command_error::RedisCommandError;
+use super::util::{get_bytes_vec, parse_variation};
+use crate::protocol::Resp;
+
+/// Strings can't grow past 512MB, like in Redis
+const MAX_BITS: u64 = 512 * 1024 * 1024 * 8;
+
+/// Integer type of a field, `i1` to `i64` or `u1` to `u63`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BitFieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+/// What SET and INCRBY do with a value out of the range of its type
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BitFieldOp {
+    Get(BitFieldType, u64),
+    Set(BitFieldType, u64, i64, Overflow),
+    IncrBy(BitFieldType, u64, i64, Overflow),
+}
+
+impl BitFieldType {
+    fn parse(bytes: &[u8]) -> Result<Self, RedisCommandError> {
+        let bits = std::str::from_utf8(bytes.get(1..).unwrap_or_default())
+            .ok()
+            .and_then(|bits| bits.parse::<u32>().ok());
+
+        match (bytes.first(), bits) {
+            (Some(b'i'), Some(bits)) | (Some(b'I'), Some(bits)) if (1..=64).contains(&bits) => {
+                Ok(BitFieldType { signed: true, bits })
+            }
+            (Some(b'u'), Some(bits)) | (Some(b'U'), Some(bits)) if (1..=63).contains(&bits) => {
+                Ok(BitFieldType {
+                    signed: false,
+                    bits,
+                })
+            }
+            _ => Err(RedisCommandError::InvalidBitfieldType),
+        }
+    }
+
+    fn min(&self) -> i64 {
+        match self.signed {
+            true => i64::MIN >> (64 - self.bits),
+            false => 0,
+        }
+    }
+
+    fn max(&self) -> i64 {
+        match self.signed {
+            true => i64::MAX >> (64 - self.bits),
+            false => (u64::MAX >> (64 - self.bits)) as i64,
+        }
+    }
+
+    /// Value of a field from its bits, held by the lowest bits of `raw`
+    fn value_of(&self, raw: u64) -> i64 {
+        let shift = 64 - self.bits;
+        match self.signed {
+            true => ((raw << shift) as i64) >> shift,
+            false => raw as i64,
+        }
+    }
+
+    /// `value` in the range of the type as decided by `overflow`, `None` when it fails
+    fn fit(&self, value: i128, overflow: Overflow) -> Option<i64> {
+        if value >= self.min() as i128 && value <= self.max() as i128 {
+            return Some(value as i64);
+        }
+
+        match overflow {
+            // the lowest bits of the two's complement
+            Overflow::Wrap => Some(self.value_of(value as u64 & (u64::MAX >> (64 - self.bits)))),
+            Overflow::Sat if value < 0 => Some(self.min()),
+            Overflow::Sat => Some(self.max()),
+            Overflow::Fail => None,
+        }
+    }
+}
+
+/// Parse the `GET type offset`, `SET type offset value`, `INCRBY type offset increment` and
+/// `OVERFLOW WRAP|SAT|FAIL` subcommands following the key of BITFIELD
+pub fn parse_bitfield_ops(args: &[Resp]) -> Result<Vec<BitFieldOp>, RedisCommandError> {
+    let mut ops = Vec::new();
+    let mut overflow = Overflow::Wrap;
+    let mut args = args.iter();
+
+    while let Some(subcommand) = args.next() {
+        let subcommand = get_bytes_vec(Some(subcommand))?;
+        let mut next = || args.next().ok_or(RedisCommandError::SyntaxErr);
+
+        if subcommand.eq_ignore_ascii_case(b"OVERFLOW") {
+            let mode = get_bytes_vec(Some(next()?))?;
+            overflow = if mode.eq_ignore_ascii_case(b"WRAP") {
+                Overflow::Wrap
+            } else if mode.eq_ignore_ascii_case(b"SAT") {
+                Overflow::Sat
+            } else if mode.eq_ignore_ascii_case(b"FAIL") {
+                Overflow::Fail
+            } else {
+                return Err(RedisCommandError::InvalidOverflow);
+            };
+            continue;
+        }
+
+        let is_get = subcommand.eq_ignore_ascii_case(b"GET");
+        let is_set = subcommand.eq_ignore_ascii_case(b"SET");
+        let is_incrby = subcommand.eq_ignore_ascii_case(b"INCRBY");
+        if !is_get && !is_set && !is_incrby {
+            return Err(RedisCommandError::SyntaxErr);
+        }
+
+        let field_type = BitFieldType::parse(&get_bytes_vec(Some(next()?))?)?;
+        let offset = parse_offset(&get_bytes_vec(Some(next()?))?, field_type)?;
+        ops.push(match is_get {
+            true => BitFieldOp::Get(field_type, offset),
+            false => {
+                let value = get_bytes_vec(Some(next()?)).and_then(parse_variation)?;
+                match is_set {
+                    true => BitFieldOp::Set(field_type, offset, value, overflow),
+                    false => BitFieldOp::IncrBy(field_type, offset, value, overflow),
+                }
+            }
+        });
+    }
+
+    Ok(ops)
+}
+
+/// Offset in bits, `#n` standing for the `n`th field of the type
+fn parse_offset(bytes: &[u8], field_type: BitFieldType) -> Result<u64, RedisCommandError> {
+    let (by_type, digits) = match bytes.split_first() {
+        Some((b'#', digits)) => (true, digits),
+        _ => (false, bytes),
+    };
+
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse::<u64>().ok())
+        .and_then(|offset| match by_type {
+            true => offset.checked_mul(field_type.bits as u64),
+            false => Some(offset),
+        })
+        .filter(|offset| *offset <= MAX_BITS - field_type.bits as u64)
+        .ok_or(RedisCommandError::BitOffsetOutOfRange)
+}
+
+/// Run `ops` in order against the string `value`, missing bytes reading as zeros and being
+/// added by writes. Returns the reply of each op, `None` where OVERFLOW FAIL prevented a write,
+/// and whether `value` changed.
+pub fn apply_bitfield_ops(value: &mut Vec<u8>, ops: &[BitFieldOp]) -> (Vec<Option<i64>>, bool) {
+    let mut written = false;
+    let replies = ops
+        .iter()
+        .map(|op| match *op {
+            BitFieldOp::Get(field_type, offset) => Some(read_field(value, field_type, offset)),
+            BitFieldOp::Set(field_type, offset, new, overflow) => {
+                let old = read_field(value, field_type, offset);
+                let new = field_type.fit(new as i128, overflow)?;
+                write_field(value, field_type, offset, new);
+                written = true;
+                Some(old)
+            }
+            BitFieldOp::IncrBy(field_type, offset, increment, overflow) => {
+                let old = read_field(value, field_type, offset);
+                let new = field_type.fit(old as i128 + increment as i128, overflow)?;
+                write_field(value, field_type, offset, new);
+                written = true;
+                Some(new)
+            }
+        })
+        .collect();
+
+    (replies, written)
+}
+
+/// Bits are numbered from the most significant bit of the first byte, like SETBIT does
+fn read_field(value: &[u8], field_type: BitFieldType, offset: u64) -> i64 {
+    let raw = (offset..offset + field_type.bits as u64).fold(0u64, |raw, bit| {
+        let byte = value.get((bit / 8) as usize).copied().unwrap_or(0);
+        (raw << 1) | ((byte >> (7 - bit % 8)) & 1) as u64
+    });
+    field_type.value_of(raw)
+}
+
+fn write_field(value: &mut Vec<u8>, field_type: BitFieldType, offset: u64, field: i64) {
+    let end = offset + field_type.bits as u64;
+    let len = end.div_ceil(8) as usize;
+    if value.len() < len {
+        value.resize(len, 0);
+    }
+
+    for bit in offset..end {
+        let is_set = (field as u64 >> (end - 1 - bit)) & 1 == 1;
+        let mask = 0x80 >> (bit % 8);
+        match is_set {
+            true => value[(bit / 8) as usize] |= mask,
+            false => value[(bit / 8) as usize] &= !mask,
+        }
+    }
+}
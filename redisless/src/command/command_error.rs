@@ -1,22 +1,33 @@
 use std::{
     fmt::{Display, Formatter},
+    net::SocketAddr,
     num::ParseIntError,
     str::Utf8Error,
 };
 
+use super::errors::{self, ErrorClass};
 use crate::protocol::error::RedisError;
+use crate::protocol::writer::RespWriter;
 use crate::storage::models::expiry::TimeOverflow;
 
 #[derive(Debug)]
 pub enum RedisCommandError {
-    // Wrong number of arguments, holds command
+    // Wrong number of arguments, the command is not known yet
     ArgNumber,
+    // Wrong number of arguments, holds command
+    WrongArity(String),
     // Overflow when setting the expiry timestamp
     TimeOverflow(TimeOverflow),
+    // Overflow when setting the expiry timestamp, holds command
+    InvalidExpireTime(String),
     // Could not convert bytes to UTF8
-    BadString(Utf8Error),
+    BadString,
     // Could not parse string for a u64
-    IntParse(ParseIntError),
+    IntParse,
+    // Stored value can't be used as an integer
+    NotAnInteger,
+    // INCR or INCRBY would take the value out of the range of a 64 bits integer
+    IncrOverflow,
     // Command is not supported by Redisless
     NotSupported(String),
     ProtocolParse(RedisError),
@@ -27,36 +38,217 @@ pub enum RedisCommandError {
     NoSuchKey,
     IndexOutOfRange,
     SyntaxErr,
+    InvalidCursor,
+    // The command panicked, the server carries on
+    Internal,
+    // The connection must authenticate first
+    NoAuth,
+    // AUTH was sent a token selecting no virtual instance
+    WrongPass,
+    // Only pub/sub commands are allowed once subscribed, holds command
+    SubscribedContext(String),
+    DbIndexOutOfRange,
+    InvalidClientName,
+    // CLIENT PAUSE was sent a timeout that isn't a number of milliseconds
+    InvalidTimeout,
+    // A blocking command was sent a timeout that isn't a number of seconds
+    TimeoutNotAFloat,
+    NegativeTimeout,
+    // Holds command and subcommand
+    UnknownSubcommand(String, String),
+    InvalidBitfieldType,
+    BitOffsetOutOfRange,
+    InvalidOverflow,
+    // FIELDS of the hash field expiration commands was given no field
+    NumFieldsNotPositive,
+    // FIELDS of the hash field expiration commands was given another number of fields
+    NumFieldsMismatch,
+    // SCAN TYPE was given no type Redis knows of
+    UnknownTypeName(String),
+    // DEBUG FAULT SET was given an option it doesn't know, or a duration it can't parse
+    InvalidFaultOption(String),
+    // CONFIG SET was given a parameter it doesn't know
+    UnknownConfigOption(String),
+    // CONFIG SET was given a value the parameter can't take, holds the parameter
+    InvalidConfigValue(String),
+    // CONFIG SET was given neither yes nor no for a boolean parameter, holds the parameter
+    InvalidConfigBool(String),
+    // SENTINEL was asked about a master it doesn't know
+    NoSuchMaster,
+    // This node follows, holds the slot of the key and the address of the leader serving it
+    Moved(u16, SocketAddr),
+    // This node follows and doesn't know of a leader
+    ClusterDown,
+    // No cluster status was ever set on the server
+    ClusterSupportDisabled,
+    // CLUSTER FAILOVER was sent to the leader
+    FailoverOnLeader,
+    // CLUSTER FAILOVER without FORCE was sent while no leader is known
+    FailoverWithoutLeader,
+    // HELLO was sent a protocol version that isn't a number
+    InvalidProtocolVersion,
+    // HELLO was sent a protocol version other than 2 and 3
+    NoProto,
+    // CLIENT TRACKING was sent PREFIX without BCAST
+    TrackingPrefixWithoutBcast,
+    // CLIENT TRACKING was sent an option it doesn't support, holds the option
+    TrackingOptionNotSupported(String),
+    // CLIENT TRACKING ON was sent before switching to RESP3
+    TrackingWithoutResp3,
+    // DEBUG RELOAD couldn't read back the dataset it dumped
+    ReloadFailed,
+    // The server is read-only and the command writes
+    ReadOnlyReplica,
+    // The user the connection runs as may not run the command, holds the user and the command
+    NoPermCommand(String, String),
+    // The user the connection runs as may not access one of the keys of the command
+    NoPermKey,
+    // The command would grow the dataset past `ServerConfig::max_keys` or
+    // `ServerConfig::max_value_bytes`
+    OutOfMemory,
+    // The connection sent more commands than `ServerConfig::rate_limit` allows
+    RateLimitExceeded,
+    // Another command has been holding the storage for too long
+    Busy,
+    // The connection is refused for coming from another host while in protected mode
+    ProtectedMode,
+    // The connection is refused for going over `maxclients`
+    MaxClientsReached,
 }
 
 impl RedisCommandError {
     pub fn to_vec(self) -> Vec<u8> {
-        format!("-{}\r\n", self).as_bytes().to_vec()
+        let mut writer = RespWriter::default();
+        writer.error(&self.to_string());
+        writer.as_slice().to_vec()
+    }
+
+    /// Class of the error, sent ahead of its message
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::WrongTypeOperation => ErrorClass::WrongType,
+            Self::NoAuth => ErrorClass::NoAuth,
+            Self::WrongPass => ErrorClass::WrongPass,
+            Self::ProtectedMode => ErrorClass::Denied,
+            Self::Moved(..) => ErrorClass::Moved,
+            Self::ClusterDown => ErrorClass::ClusterDown,
+            Self::Busy => ErrorClass::Busy,
+            Self::ReadOnlyReplica => ErrorClass::ReadOnly,
+            Self::NoProto => ErrorClass::NoProto,
+            Self::OutOfMemory => ErrorClass::Oom,
+            Self::NoPermCommand(..) | Self::NoPermKey => ErrorClass::NoPerm,
+            _ => ErrorClass::Err,
+        }
+    }
+
+    /// Whether the server refused to run the command, as opposed to the command failing
+    pub fn is_refusal(&self) -> bool {
+        matches!(
+            self,
+            Self::NoAuth
+                | Self::NoPermCommand(..)
+                | Self::NoPermKey
+                | Self::SubscribedContext(_)
+                | Self::Moved(..)
+                | Self::ClusterDown
+                | Self::ReadOnlyReplica
+                | Self::OutOfMemory
+                | Self::RateLimitExceeded
+                | Self::Busy
+                | Self::ProtectedMode
+                | Self::MaxClientsReached
+        )
+    }
+
+    /// Attach the name of the command being parsed to errors whose Redis message mentions it
+    pub fn for_command(self, command: &str) -> Self {
+        match self {
+            Self::ArgNumber => Self::WrongArity(command.to_lowercase()),
+            Self::TimeOverflow(_) => Self::InvalidExpireTime(command.to_lowercase()),
+            Self::SubscribedContext(_) => Self::SubscribedContext(command.to_lowercase()),
+            Self::NoPermCommand(user, _) => Self::NoPermCommand(user, command.to_lowercase()),
+            err => err,
+        }
     }
 }
 
 impl Display for RedisCommandError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ", self.class())?;
         match self {
-            Self::ArgNumber => {
-                write!(f, "wrong number of arguments for command")
+            Self::ArgNumber => write!(f, "{}", errors::WRONG_ARITY),
+            Self::WrongArity(cmd) => write!(f, "{}", errors::wrong_arity(cmd)),
+            Self::TimeOverflow(_) => write!(f, "{}", errors::INVALID_EXPIRE_TIME),
+            Self::InvalidExpireTime(cmd) => write!(f, "{}", errors::invalid_expire_time(cmd)),
+            Self::BadString | Self::IntParse | Self::NotAnInteger => {
+                write!(f, "{}", errors::NOT_AN_INTEGER)
+            }
+            Self::IncrOverflow => write!(f, "{}", errors::INCR_OVERFLOW),
+            Self::NotSupported(cmd) => write!(f, "{}", errors::unknown_command(cmd)),
+            Self::ProtocolParse(err) => write!(f, "{}", errors::protocol_error(&err.to_string())),
+            Self::InvalidCommand => write!(f, "{}", errors::protocol_error("expected '$'")),
+            Self::CommandNotFound => write!(f, "{}", errors::UNKNOWN_COMMAND),
+            Self::WrongTypeOperation => write!(f, "{}", errors::WRONG_TYPE),
+            Self::NoSuchKey => write!(f, "{}", errors::NO_SUCH_KEY),
+            Self::IndexOutOfRange => write!(f, "{}", errors::INDEX_OUT_OF_RANGE),
+            Self::SyntaxErr => write!(f, "{}", errors::SYNTAX_ERROR),
+            Self::InvalidCursor => write!(f, "{}", errors::INVALID_CURSOR),
+            Self::Internal => write!(f, "{}", errors::INTERNAL_ERROR),
+            Self::NoAuth => write!(f, "{}", errors::NO_AUTH),
+            Self::WrongPass => write!(f, "{}", errors::WRONG_PASS),
+            Self::SubscribedContext(cmd) => write!(f, "{}", errors::subscribed_context(cmd)),
+            Self::DbIndexOutOfRange => write!(f, "{}", errors::DB_INDEX_OUT_OF_RANGE),
+            Self::InvalidClientName => write!(f, "{}", errors::INVALID_CLIENT_NAME),
+            Self::InvalidTimeout => write!(f, "{}", errors::INVALID_TIMEOUT),
+            Self::TimeoutNotAFloat => write!(f, "{}", errors::TIMEOUT_NOT_A_FLOAT),
+            Self::NegativeTimeout => write!(f, "{}", errors::NEGATIVE_TIMEOUT),
+            Self::UnknownSubcommand(cmd, sub) => {
+                write!(f, "{}", errors::unknown_subcommand(cmd, sub))
+            }
+            Self::InvalidBitfieldType => write!(f, "{}", errors::INVALID_BITFIELD_TYPE),
+            Self::BitOffsetOutOfRange => write!(f, "{}", errors::BIT_OFFSET_OUT_OF_RANGE),
+            Self::InvalidOverflow => write!(f, "{}", errors::INVALID_OVERFLOW),
+            Self::NumFieldsNotPositive => write!(f, "{}", errors::NUMFIELDS_NOT_POSITIVE),
+            Self::NumFieldsMismatch => write!(f, "{}", errors::NUMFIELDS_MISMATCH),
+            Self::UnknownTypeName(name) => write!(f, "{}", errors::unknown_type_name(name)),
+            Self::InvalidFaultOption(option) => {
+                write!(f, "{}", errors::invalid_fault_option(option))
+            }
+            Self::UnknownConfigOption(option) => {
+                write!(f, "{}", errors::unknown_config_option(option))
+            }
+            Self::InvalidConfigValue(option) => {
+                write!(f, "{}", errors::invalid_config_value(option))
+            }
+            Self::InvalidConfigBool(option) => write!(f, "{}", errors::invalid_config_bool(option)),
+            Self::NoSuchMaster => write!(f, "{}", errors::NO_SUCH_MASTER),
+            Self::Moved(slot, leader) => write!(f, "{}", errors::moved(*slot, leader)),
+            Self::ClusterDown => write!(f, "{}", errors::CLUSTER_DOWN),
+            Self::ClusterSupportDisabled => write!(f, "{}", errors::CLUSTER_SUPPORT_DISABLED),
+            Self::FailoverOnLeader => write!(f, "{}", errors::FAILOVER_ON_LEADER),
+            Self::FailoverWithoutLeader => write!(f, "{}", errors::FAILOVER_WITHOUT_LEADER),
+            Self::InvalidProtocolVersion => {
+                write!(f, "{}", errors::PROTOCOL_VERSION_NOT_AN_INTEGER)
+            }
+            Self::NoProto => write!(f, "{}", errors::UNSUPPORTED_PROTOCOL_VERSION),
+            Self::TrackingPrefixWithoutBcast => {
+                write!(f, "{}", errors::TRACKING_PREFIX_WITHOUT_BCAST)
+            }
+            Self::TrackingOptionNotSupported(option) => {
+                write!(f, "{}", errors::tracking_option_not_supported(option))
             }
-            Self::TimeOverflow(e) => write!(f, "{:?}", e),
-            Self::BadString(e) => write!(f, "{}", e),
-            Self::IntParse(e) => write!(f, "{}", e),
-            Self::NotSupported(cmd) => {
-                write!(f, "command {} not supported by redisless", cmd)
+            Self::TrackingWithoutResp3 => write!(f, "{}", errors::TRACKING_WITHOUT_RESP3),
+            Self::ReloadFailed => write!(f, "{}", errors::RELOAD_FAILED),
+            Self::ReadOnlyReplica => write!(f, "{}", errors::READ_ONLY),
+            Self::NoPermCommand(user, command) => {
+                write!(f, "{}", errors::no_permission_to_run(user, command))
             }
-            Self::ProtocolParse(err) => write!(f, "{}", err),
-            Self::InvalidCommand => write!(f, "invalid command"),
-            Self::CommandNotFound => write!(f, "command not found"),
-            Self::WrongTypeOperation => write!(
-                f,
-                "WRONGTYPE Operation against a key holding the wrong kind of value"
-            ),
-            Self::NoSuchKey => write!(f, "no such key"),
-            Self::IndexOutOfRange => write!(f, "index out of range"),
-            Self::SyntaxErr => write!(f, "systax error"),
+            Self::NoPermKey => write!(f, "{}", errors::NO_PERMISSION_FOR_KEY),
+            Self::OutOfMemory => write!(f, "{}", errors::OUT_OF_MEMORY),
+            Self::RateLimitExceeded => write!(f, "{}", errors::RATE_LIMIT_EXCEEDED),
+            Self::Busy => write!(f, "{}", errors::BUSY),
+            Self::ProtectedMode => write!(f, "{}", errors::PROTECTED_MODE),
+            Self::MaxClientsReached => write!(f, "{}", errors::MAX_CLIENTS_REACHED),
         }
     }
 }
@@ -68,13 +260,13 @@ impl From<TimeOverflow> for RedisCommandError {
 }
 
 impl From<Utf8Error> for RedisCommandError {
-    fn from(err: Utf8Error) -> Self {
-        Self::BadString(err)
+    fn from(_: Utf8Error) -> Self {
+        Self::BadString
     }
 }
 
 impl From<ParseIntError> for RedisCommandError {
-    fn from(err: ParseIntError) -> Self {
-        Self::IntParse(err)
+    fn from(_: ParseIntError) -> Self {
+        Self::IntParse
     }
 }
@@ -0,0 +1,178 @@
+//! Error strings sent back to clients.
+//!
+//! They are byte-for-byte copies of the ones emitted by Redis: client libraries match on
+//! the error class (`ERR`, `WRONGTYPE`, ...) and sometimes on the full message, so they
+//! must not drift from upstream. The class, the first word Redis sends, is an `ErrorClass`
+//! and the messages here go without it.
+
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+
+/// Class of an error, the first word of its message
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorClass {
+    Err,
+    WrongType,
+    NoAuth,
+    WrongPass,
+    Denied,
+    Moved,
+    ClusterDown,
+    Busy,
+    ReadOnly,
+    NoProto,
+    Oom,
+    NoPerm,
+}
+
+impl ErrorClass {
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ErrorClass::Err => "ERR",
+            ErrorClass::WrongType => "WRONGTYPE",
+            ErrorClass::NoAuth => "NOAUTH",
+            ErrorClass::WrongPass => "WRONGPASS",
+            ErrorClass::Denied => "DENIED",
+            ErrorClass::Moved => "MOVED",
+            ErrorClass::ClusterDown => "CLUSTERDOWN",
+            ErrorClass::Busy => "BUSY",
+            ErrorClass::ReadOnly => "READONLY",
+            ErrorClass::NoProto => "NOPROTO",
+            ErrorClass::Oom => "OOM",
+            ErrorClass::NoPerm => "NOPERM",
+        }
+    }
+}
+
+impl Display for ErrorClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.prefix())
+    }
+}
+
+pub const WRONG_TYPE: &str = "Operation against a key holding the wrong kind of value";
+pub const NOT_AN_INTEGER: &str = "value is not an integer or out of range";
+pub const INCR_OVERFLOW: &str = "increment or decrement would overflow";
+pub const NO_SUCH_KEY: &str = "no such key";
+pub const INDEX_OUT_OF_RANGE: &str = "index out of range";
+pub const SYNTAX_ERROR: &str = "syntax error";
+pub const UNKNOWN_COMMAND: &str = "unknown command";
+pub const WRONG_ARITY: &str = "wrong number of arguments";
+pub const INVALID_EXPIRE_TIME: &str = "invalid expire time";
+pub const INVALID_CURSOR: &str = "invalid cursor";
+pub const INTERNAL_ERROR: &str = "internal error while running the command";
+pub const NO_AUTH: &str = "Authentication required.";
+pub const WRONG_PASS: &str = "invalid username-password pair or user is disabled.";
+pub const DB_INDEX_OUT_OF_RANGE: &str = "DB index is out of range";
+pub const INVALID_CLIENT_NAME: &str =
+    "Client names cannot contain spaces, newlines or special characters.";
+pub const INVALID_TIMEOUT: &str = "timeout is not an integer or out of range";
+pub const TIMEOUT_NOT_A_FLOAT: &str = "timeout is not a float or out of range";
+pub const NEGATIVE_TIMEOUT: &str = "timeout is negative";
+pub const INVALID_BITFIELD_TYPE: &str =
+    "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.";
+pub const BIT_OFFSET_OUT_OF_RANGE: &str = "bit offset is not an integer or out of range";
+pub const INVALID_OVERFLOW: &str = "Invalid OVERFLOW type specified";
+pub const NUMFIELDS_NOT_POSITIVE: &str = "Parameter `numFields` should be greater than 0";
+pub const NUMFIELDS_MISMATCH: &str = "The `numfields` parameter must match the number of arguments";
+pub const MAX_CLIENTS_REACHED: &str = "max number of clients reached";
+pub const PROTECTED_MODE: &str = "Redis is running in protected mode because protected mode is enabled and no password is set for the default user. In this mode connections are only accepted from the loopback interface. If you want to connect from external computers to Redis you may adopt one of the following solutions: 1) Just disable protected mode sending the command 'CONFIG SET protected-mode no' from the loopback interface by connecting to Redis from the same host the server is running, however MAKE SURE Redis is not publicly accessible from internet if you do so. Use CONFIG REWRITE to make this change permanent. 2) Alternatively you can just disable the protected mode by editing the Redis configuration file, and setting the protected mode option to 'no', and then restarting the server. 3) If you started the server manually just for testing, restart it with the '--protected-mode no' option. 4) Set up an authentication password for the default user. NOTE: You only need to do one of the above things in order for the server to start accepting connections from the outside.";
+pub const NO_SUCH_MASTER: &str = "No such master with that name";
+pub const CLUSTER_DOWN: &str = "The cluster is down";
+pub const CLUSTER_SUPPORT_DISABLED: &str = "This instance has cluster support disabled";
+pub const FAILOVER_ON_LEADER: &str = "You should send CLUSTER FAILOVER to a replica";
+pub const FAILOVER_WITHOUT_LEADER: &str =
+    "Master is down or failed, please use CLUSTER FAILOVER FORCE";
+pub const READ_ONLY: &str = "You can't write against a read only replica.";
+pub const NO_PERMISSION_FOR_KEY: &str = "No permissions to access a key";
+pub const OUT_OF_MEMORY: &str = "command not allowed when used memory > 'maxmemory'.";
+pub const RATE_LIMIT_EXCEEDED: &str = "rate limit exceeded";
+pub const BUSY: &str = "Redis is busy running a command, try again once it's done";
+pub const PROTOCOL_VERSION_NOT_AN_INTEGER: &str =
+    "Protocol version is not an integer or out of range";
+pub const UNSUPPORTED_PROTOCOL_VERSION: &str = "unsupported protocol version";
+pub const TRACKING_PREFIX_WITHOUT_BCAST: &str = "PREFIX option requires BCAST mode to be enabled";
+pub const TRACKING_WITHOUT_RESP3: &str =
+    "Client tracking invalidation messages are pushed over RESP3, switch to it with HELLO 3 first";
+pub const RELOAD_FAILED: &str = "Error trying to load the dump, the dataset was left as it was";
+
+pub fn wrong_arity(command: &str) -> String {
+    format!("{} for '{}' command", WRONG_ARITY, command)
+}
+
+pub fn invalid_expire_time(command: &str) -> String {
+    format!("{} in '{}' command", INVALID_EXPIRE_TIME, command)
+}
+
+pub fn unknown_command(command: &str) -> String {
+    format!(
+        "{} `{}`, with args beginning with: ",
+        UNKNOWN_COMMAND, command
+    )
+}
+
+pub fn unknown_subcommand(command: &str, subcommand: &str) -> String {
+    format!(
+        "unknown subcommand '{}'. Try {} HELP.",
+        subcommand,
+        command.to_uppercase()
+    )
+}
+
+pub fn unknown_type_name(name: &str) -> String {
+    format!("unknown type name '{}'", name)
+}
+
+pub fn invalid_fault_option(option: &str) -> String {
+    format!(
+        "Invalid fault option '{}', expected latency=<duration> or jitter=<duration>",
+        option
+    )
+}
+
+pub fn no_permission_to_run(user: &str, command: &str) -> String {
+    format!(
+        "User {} has no permissions to run the '{}' command",
+        user, command
+    )
+}
+
+pub fn tracking_option_not_supported(option: &str) -> String {
+    format!("The {} option of CLIENT TRACKING is not supported", option)
+}
+
+pub fn unknown_config_option(option: &str) -> String {
+    format!(
+        "Unknown option or number of arguments for CONFIG SET - '{}'",
+        option
+    )
+}
+
+pub fn invalid_config_value(option: &str) -> String {
+    format!(
+        "CONFIG SET failed (possibly related to argument '{}') - argument couldn't be parsed into an integer",
+        option
+    )
+}
+
+pub fn invalid_config_bool(option: &str) -> String {
+    format!(
+        "CONFIG SET failed (possibly related to argument '{}') - argument must be 'yes' or 'no'",
+        option
+    )
+}
+
+pub fn subscribed_context(command: &str) -> String {
+    format!(
+        "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+        command
+    )
+}
+
+pub fn moved(slot: u16, addr: &SocketAddr) -> String {
+    format!("{} {}", slot, addr)
+}
+
+pub fn protocol_error(reason: &str) -> String {
+    format!("Protocol error: {}", reason)
+}
@@ -1,14 +1,22 @@
 #[cfg(test)]
 mod tests;
 
+pub mod arity;
+pub mod bitfield;
 pub mod command_error;
+pub mod errors;
+pub mod table;
 mod util;
 
 use std::collections::HashSet;
+use std::time::Duration;
 
 use crate::protocol::Resp;
 use crate::storage::models::Expiry;
+use crate::storage::scan::ScanOptions;
+use bitfield::BitFieldOp;
 use command_error::RedisCommandError;
+use table::{command_spec, CommandKind, CommandSpec};
 
 use super::storage::models::RedisString;
 
@@ -19,10 +27,97 @@ type Keys = Vec<Key>;
 type Values = Vec<Value>;
 type SetValues = HashSet<Value>;
 
-#[derive(Debug, PartialEq)]
+/// Every command `Command::parse` knows about, as reported by `capabilities()`
+pub const SUPPORTED_COMMANDS: &[&str] = &[
+    "append",
+    "auth",
+    "bitfield",
+    "blpop",
+    "cas",
+    "casversion",
+    "client",
+    "cluster",
+    "command",
+    "config",
+    "dbsize",
+    "debug",
+    "decr",
+    "decrby",
+    "del",
+    "delpattern",
+    "exists",
+    "expire",
+    "flushall",
+    "flushdb",
+    "get",
+    "getset",
+    "hello",
+    "hexpire",
+    "hget",
+    "hgetall",
+    "hmset",
+    "hpersist",
+    "hpexpire",
+    "hpttl",
+    "hscan",
+    "hset",
+    "httl",
+    "incr",
+    "incrby",
+    "info",
+    "latency",
+    "lindex",
+    "linsert",
+    "llen",
+    "lpop",
+    "lpush",
+    "lpushx",
+    "lrange",
+    "lrem",
+    "lset",
+    "ltrim",
+    "memory",
+    "mget",
+    "mset",
+    "msetnx",
+    "object",
+    "pexpire",
+    "ping",
+    "psetex",
+    "pttl",
+    "quit",
+    "readonly",
+    "readwrite",
+    "rename",
+    "reset",
+    "rpop",
+    "rpoplpush",
+    "rpush",
+    "rpushx",
+    "sadd",
+    "scan",
+    "scard",
+    "select",
+    "sentinel",
+    "set",
+    "setex",
+    "setnx",
+    "srem",
+    "sscan",
+    "ttl",
+    "type",
+];
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Command {
     Append(Key, Value),
+    BitField(Key, Vec<BitFieldOp>),
+    // Set the key if its version is the expected one, a RedisLess extension
+    Cas(Key, u64, Value),
+    CasVersion(Key),
     Set(Key, Value),
+    // SET with KEEPTTL
+    SetKeepTtl(Key, Value),
     Setnx(Key, Value),
     Setex(Key, Expiry, Value),
     PSetex(Key, Expiry, Value),
@@ -34,38 +129,465 @@ pub enum Command {
     GetSet(Key, Value),
     MGet(Keys),
     HSet(Key, Items),
+    HMSet(Key, Items),
     HGet(Key, Key),
+    HScan(Key, u64, ScanOptions),
+    HGetAll(Key),
+    // Expiry of the fields that follow
+    HExpire(Key, Expiry, Keys),
+    HPExpire(Key, Expiry, Keys),
+    HPersist(Key, Keys),
+    HTtl(Key, Keys),
+    HPttl(Key, Keys),
     RPush(Key, Values),
     LPush(Key, Values),
     LLen(Key),
+    LRange(Key, i64, i64),
     RPushx(Key, Values),
     LPushx(Key, Values),
     RPop(Key),
     LPop(Key),
+    // Waits for as long as it takes when the timeout is `None`
+    BLPop(Keys, Option<Duration>),
     LIndex(Key, i64),
     LSet(Key, i64, Value),
     LInsert(Key, RedisString, RedisString, Value),
     LTrim(Key, i64, i64),
     LRem(Key, i64, Value),
     RPopLPush(Key, Key),
+    Rename(Key, Key),
     SAdd(Key, SetValues),
     SCard(Key),
     SRem(Key, SetValues),
-    Del(Key),
+    SScan(Key, u64, ScanOptions),
+    Scan(u64, ScanOptions),
+    Del(Keys),
+    // Glob-style pattern of the keys to delete, a RedisLess extension
+    DelPattern(Value),
     Incr(Key),
     IncrBy(Key, i64),
     Exists(Key),
     Type(Key),
     Ttl(Key),
     Pttl(Key),
-    Info,
+    Info(Option<Value>),
+    Select(i64),
+    // Username, `None` for the default user, and password
+    Auth(Option<Value>, Value),
+    // Protocol version, username and password of AUTH, and name of SETNAME
+    Hello(Option<i64>, Option<(Value, Value)>, Option<Value>),
+    ClientSetName(Value),
+    ClientGetName,
+    // Timeout in milliseconds, and whether only the writes are paused
+    ClientPause(u64, bool),
+    ClientUnpause,
+    ClientNoEvict(bool),
+    // Options of CLIENT TRACKING ON, `None` for OFF
+    ClientTracking(Option<TrackingOptions>),
+    CommandCount,
+    // Commands to describe, every command when empty
+    CommandInfo(Values),
+    ConfigGet(Value),
+    // Parameter and its value
+    ConfigSet(Value, Value),
+    ConfigResetStat,
+    LatencyLatest,
+    LatencyHistory(Value),
+    // Events to reset, every event when empty
+    LatencyReset(Values),
+    LatencyDoctor,
+    MemoryPurge,
+    ObjectEncoding(Key),
+    DebugTypeStats,
+    DebugReload,
+    DebugDigest,
+    DebugDigestValue(Keys),
+    DebugChangeReplId,
+    // Command name, latency and jitter
+    DebugFaultSet(Value, Duration, Duration),
+    // Command name, every command when `None`
+    DebugFaultReset(Option<Value>),
+    DebugFaultList,
+    ClusterLeader,
+    ClusterRedislessStatus,
+    // Whether FORCE was given
+    ClusterFailover(bool),
+    SentinelGetMasterAddrByName(Value),
+    SentinelMasters,
+    SentinelSlaves(Value),
     Ping,
     Quit,
+    ReadOnly,
+    ReadWrite,
+    Reset,
     Dbsize,
+    FlushAll,
+    FlushDb,
+}
+
+/// What CLIENT TRACKING ON invalidates for the connection
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct TrackingOptions {
+    /// Every key starting with one of `prefixes` is invalidated, whether the connection read it
+    /// or not, instead of the keys it read
+    pub bcast: bool,
+    /// Prefixes of the keys invalidated in BCAST mode, every key when empty
+    pub prefixes: Keys,
+    /// Keys written by the connection itself aren't invalidated for it
+    pub noloop: bool,
 }
 
 impl Command {
+    /// Name of the command in the command table
+    pub fn name(&self) -> &'static str {
+        use Command::*;
+        match self {
+            Append(..) => "append",
+            BitField(..) => "bitfield",
+            Cas(..) => "cas",
+            CasVersion(..) => "casversion",
+            Set(..) | SetKeepTtl(..) => "set",
+            Setnx(..) => "setnx",
+            Setex(..) => "setex",
+            PSetex(..) => "psetex",
+            MSet(..) => "mset",
+            MSetnx(..) => "msetnx",
+            Expire(..) => "expire",
+            PExpire(..) => "pexpire",
+            Get(..) => "get",
+            GetSet(..) => "getset",
+            MGet(..) => "mget",
+            HSet(..) => "hset",
+            HMSet(..) => "hmset",
+            HGet(..) => "hget",
+            HScan(..) => "hscan",
+            HGetAll(..) => "hgetall",
+            HExpire(..) => "hexpire",
+            HPExpire(..) => "hpexpire",
+            HPersist(..) => "hpersist",
+            HTtl(..) => "httl",
+            HPttl(..) => "hpttl",
+            RPush(..) => "rpush",
+            LPush(..) => "lpush",
+            LLen(..) => "llen",
+            LRange(..) => "lrange",
+            RPushx(..) => "rpushx",
+            LPushx(..) => "lpushx",
+            RPop(..) => "rpop",
+            LPop(..) => "lpop",
+            BLPop(..) => "blpop",
+            LIndex(..) => "lindex",
+            LSet(..) => "lset",
+            LInsert(..) => "linsert",
+            LTrim(..) => "ltrim",
+            LRem(..) => "lrem",
+            RPopLPush(..) => "rpoplpush",
+            Rename(..) => "rename",
+            SAdd(..) => "sadd",
+            SCard(..) => "scard",
+            SRem(..) => "srem",
+            SScan(..) => "sscan",
+            Scan(..) => "scan",
+            Del(..) => "del",
+            DelPattern(..) => "delpattern",
+            Incr(..) => "incr",
+            IncrBy(..) => "incrby",
+            Exists(..) => "exists",
+            Type(..) => "type",
+            Ttl(..) => "ttl",
+            Pttl(..) => "pttl",
+            Info(..) => "info",
+            Select(..) => "select",
+            Auth(..) => "auth",
+            Hello(..) => "hello",
+            ClientSetName(..) | ClientGetName | ClientPause(..) | ClientUnpause
+            | ClientNoEvict(_) | ClientTracking(_) => "client",
+            CommandCount | CommandInfo(..) => "command",
+            ConfigGet(_) | ConfigSet(..) | ConfigResetStat => "config",
+            LatencyLatest | LatencyHistory(..) | LatencyReset(..) | LatencyDoctor => "latency",
+            MemoryPurge => "memory",
+            ObjectEncoding(_) => "object",
+            DebugTypeStats | DebugReload | DebugDigest | DebugDigestValue(..)
+            | DebugChangeReplId | DebugFaultSet(..) | DebugFaultReset(_) | DebugFaultList => {
+                "debug"
+            }
+            ClusterLeader | ClusterRedislessStatus | ClusterFailover(_) => "cluster",
+            SentinelGetMasterAddrByName(..) | SentinelMasters | SentinelSlaves(..) => "sentinel",
+            Ping => "ping",
+            Quit => "quit",
+            ReadOnly => "readonly",
+            ReadWrite => "readwrite",
+            Reset => "reset",
+            Dbsize => "dbsize",
+            FlushAll => "flushall",
+            FlushDb => "flushdb",
+        }
+    }
+
+    /// Metadata of the command, see `table`
+    pub fn spec(&self) -> &'static CommandSpec {
+        // will never panic, the table has an entry for every name returned by `name`
+        command_spec(self.name()).unwrap()
+    }
+
+    /// Whether the command may modify the storage
+    pub fn is_write(&self) -> bool {
+        self.spec().kind == CommandKind::Write
+    }
+
+    /// Whether the command only reads the storage, so a follower may serve it to a client
+    /// that sent READONLY
+    pub fn is_read(&self) -> bool {
+        self.spec().kind == CommandKind::Read
+    }
+
+    /// Whether the command may add keys or make values bigger, and is then refused once the
+    /// dataset reaches `ServerConfig::max_keys` or `ServerConfig::max_value_bytes`, as Redis
+    /// refuses the commands it flags `denyoom` past `maxmemory`
+    pub fn grows(&self) -> bool {
+        use Command::*;
+        matches!(
+            self,
+            Append(..)
+                | BitField(..)
+                | Cas(..)
+                | Set(..)
+                | SetKeepTtl(..)
+                | Setnx(..)
+                | Setex(..)
+                | PSetex(..)
+                | MSet(_)
+                | MSetnx(_)
+                | GetSet(..)
+                | HSet(..)
+                | HMSet(..)
+                | RPush(..)
+                | LPush(..)
+                | RPushx(..)
+                | LPushx(..)
+                | LSet(..)
+                | LInsert(..)
+                | RPopLPush(..)
+                | SAdd(..)
+                | Incr(_)
+                | IncrBy(..)
+        )
+    }
+
+    /// First key the command names, the one whose slot MOVED redirections report
+    pub fn first_key(&self) -> Option<&Key> {
+        use Command::*;
+        match self {
+            Append(key, _)
+            | BitField(key, _)
+            | Set(key, _)
+            | SetKeepTtl(key, _)
+            | Setnx(key, _)
+            | Setex(key, ..)
+            | PSetex(key, ..)
+            | Expire(key, _)
+            | PExpire(key, _)
+            | Get(key)
+            | GetSet(key, _)
+            | HSet(key, _)
+            | HMSet(key, _)
+            | HGet(key, _)
+            | HScan(key, ..)
+            | HGetAll(key)
+            | HExpire(key, ..)
+            | HPExpire(key, ..)
+            | HPersist(key, _)
+            | HTtl(key, _)
+            | HPttl(key, _)
+            | RPush(key, _)
+            | LPush(key, _)
+            | LLen(key)
+            | LRange(key, ..)
+            | RPushx(key, _)
+            | LPushx(key, _)
+            | RPop(key)
+            | LPop(key)
+            | LIndex(key, _)
+            | LSet(key, ..)
+            | LInsert(key, ..)
+            | LTrim(key, ..)
+            | LRem(key, ..)
+            | Cas(key, ..)
+            | CasVersion(key)
+            | SAdd(key, _)
+            | SCard(key)
+            | SRem(key, _)
+            | SScan(key, ..)
+            | Incr(key)
+            | IncrBy(key, _)
+            | Exists(key)
+            | Type(key)
+            | Ttl(key)
+            | Pttl(key)
+            | ObjectEncoding(key)
+            | RPopLPush(key, _)
+            | Rename(key, _) => Some(key),
+            BLPop(keys, _) | MGet(keys) | Del(keys) | DebugDigestValue(keys) => keys.first(),
+            MSet(items) | MSetnx(items) => items.first().map(|(key, _)| key),
+            _ => None,
+        }
+    }
+
+    /// Every key the command names, those it writes for a write command
+    pub fn keys(&self) -> Vec<&Key> {
+        use Command::*;
+        match self {
+            BLPop(keys, _) | MGet(keys) | Del(keys) | DebugDigestValue(keys) => {
+                keys.iter().collect()
+            }
+            RPopLPush(source, destination) | Rename(source, destination) => {
+                vec![source, destination]
+            }
+            MSet(items) | MSetnx(items) => items.iter().map(|(key, _)| key).collect(),
+            command => command.first_key().into_iter().collect(),
+        }
+    }
+
+    /// Prepend `prefix` to every key the command names, and confine the keys matched by SCAN
+    /// to those starting with it
+    pub fn prefix_keys(&mut self, prefix: &[u8]) {
+        use Command::*;
+        fn prefixed(prefix: &[u8], key: &mut Key) {
+            key.splice(0..0, prefix.iter().copied());
+        }
+
+        match self {
+            Append(key, _)
+            | BitField(key, _)
+            | Set(key, _)
+            | SetKeepTtl(key, _)
+            | Setnx(key, _)
+            | Setex(key, ..)
+            | PSetex(key, ..)
+            | Expire(key, _)
+            | PExpire(key, _)
+            | Get(key)
+            | GetSet(key, _)
+            | HSet(key, _)
+            | HMSet(key, _)
+            | HGet(key, _)
+            | HScan(key, ..)
+            | HGetAll(key)
+            | HExpire(key, ..)
+            | HPExpire(key, ..)
+            | HPersist(key, _)
+            | HTtl(key, _)
+            | HPttl(key, _)
+            | RPush(key, _)
+            | LPush(key, _)
+            | LLen(key)
+            | LRange(key, ..)
+            | RPushx(key, _)
+            | LPushx(key, _)
+            | RPop(key)
+            | LPop(key)
+            | LIndex(key, _)
+            | LSet(key, ..)
+            | LInsert(key, ..)
+            | LTrim(key, ..)
+            | LRem(key, ..)
+            | Cas(key, ..)
+            | CasVersion(key)
+            | SAdd(key, _)
+            | SCard(key)
+            | SRem(key, _)
+            | SScan(key, ..)
+            | Incr(key)
+            | IncrBy(key, _)
+            | Exists(key)
+            | Type(key)
+            | Ttl(key)
+            | Pttl(key)
+            | ObjectEncoding(key) => prefixed(prefix, key),
+            BLPop(keys, _) => keys.iter_mut().for_each(|key| prefixed(prefix, key)),
+            RPopLPush(source, destination) | Rename(source, destination) => {
+                prefixed(prefix, source);
+                prefixed(prefix, destination);
+            }
+            MSet(items) | MSetnx(items) => {
+                items.iter_mut().for_each(|(key, _)| prefixed(prefix, key))
+            }
+            MGet(keys) | Del(keys) | DebugDigestValue(keys) => {
+                keys.iter_mut().for_each(|key| prefixed(prefix, key))
+            }
+            Scan(_, options) => {
+                let mut pattern = escaped_glob(prefix);
+                pattern.extend_from_slice(options.pattern.as_deref().unwrap_or(b"*"));
+                options.pattern = Some(pattern);
+            }
+            DelPattern(pattern) => {
+                pattern.splice(0..0, escaped_glob(prefix));
+            }
+            // BCAST without a prefix is about every key, those under `prefix` here
+            ClientTracking(Some(options)) if options.bcast && options.prefixes.is_empty() => {
+                options.prefixes.push(prefix.to_vec())
+            }
+            ClientTracking(Some(options)) => options
+                .prefixes
+                .iter_mut()
+                .for_each(|key| prefixed(prefix, key)),
+            // no key, or keys found by the server which is left to deal with the prefix
+            Info(_)
+            | Select(_)
+            | Auth(..)
+            | Hello(..)
+            | ClientSetName(_)
+            | ClientGetName
+            | ClientPause(..)
+            | ClientUnpause
+            | ClientNoEvict(_)
+            | ClientTracking(None)
+            | CommandCount
+            | CommandInfo(_)
+            | ConfigGet(_)
+            | ConfigSet(..)
+            | ConfigResetStat
+            | LatencyLatest
+            | LatencyHistory(_)
+            | LatencyReset(_)
+            | LatencyDoctor
+            | MemoryPurge
+            | DebugTypeStats
+            | DebugReload
+            | DebugDigest
+            | DebugChangeReplId
+            | DebugFaultSet(..)
+            | DebugFaultReset(_)
+            | DebugFaultList
+            | ClusterLeader
+            | ClusterRedislessStatus
+            | ClusterFailover(_)
+            | SentinelGetMasterAddrByName(_)
+            | SentinelMasters
+            | SentinelSlaves(_)
+            | Ping
+            | Quit
+            | ReadOnly
+            | ReadWrite
+            | Reset
+            | Dbsize
+            | FlushAll
+            | FlushDb => {}
+        }
+    }
+
     pub fn parse(v: Vec<Resp>) -> Result<Self, RedisCommandError> {
+        let command: &[u8] = match v.first() {
+            Some(Resp::BulkString(command)) => command,
+            _ => return Err(RedisCommandError::InvalidCommand),
+        };
+
+        arity::check_arity(command, v.len())
+            .and_then(|_| Self::parse_args(v))
+            .map_err(|err| err.for_command(&String::from_utf8_lossy(command)))
+    }
+
+    fn parse_args(v: Vec<Resp>) -> Result<Self, RedisCommandError> {
         use util::*;
         use Command::*;
         use RedisCommandError::*;
@@ -76,7 +598,35 @@ impl Command {
                     let key = get_bytes_vec(v.get(1))?;
                     let value = get_bytes_vec(v.get(2))?;
 
-                    Ok(Set(key, value))
+                    match v.get(3..).unwrap_or_default() {
+                        [] => Ok(Set(key, value)),
+                        [option] => {
+                            let option = get_bytes_vec(Some(option))?;
+                            if option.eq_ignore_ascii_case(b"KEEPTTL") {
+                                Ok(SetKeepTtl(key, value))
+                            } else {
+                                Err(SyntaxErr)
+                            }
+                        }
+                        [option, duration] => {
+                            let option = get_bytes_vec(Some(option))?;
+                            let duration =
+                                get_bytes_vec(Some(duration)).and_then(parse_duration)?;
+                            if duration == 0 {
+                                return Err(InvalidExpireTime("set".to_string()));
+                            }
+
+                            if option.eq_ignore_ascii_case(b"EX") {
+                                Ok(Setex(key, Expiry::new_from_secs(duration)?, value))
+                            } else if option.eq_ignore_ascii_case(b"PX") {
+                                Ok(PSetex(key, Expiry::new_from_millis(duration)?, value))
+                            } else {
+                                Err(SyntaxErr)
+                            }
+                        }
+                        // NX, XX and GET aren't supported, better refuse them than ignore them
+                        _ => Err(SyntaxErr),
+                    }
                 }
                 b"APPEND" | b"append" | b"Append" => {
                     let key = get_bytes_vec(v.get(1))?;
@@ -84,6 +634,22 @@ impl Command {
 
                     Ok(Append(key, value))
                 }
+                b"BITFIELD" | b"bitfield" | b"BitField" | b"Bitfield" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let ops = bitfield::parse_bitfield_ops(v.get(2..).unwrap_or_default())?;
+
+                    Ok(BitField(key, ops))
+                }
+                b"CAS" | b"cas" | b"Cas" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let version = get_bytes_vec(v.get(2)).and_then(parse_duration)?;
+                    let value = get_bytes_vec(v.get(3))?;
+
+                    Ok(Cas(key, version, value))
+                }
+                b"CASVERSION" | b"casversion" | b"CasVersion" => {
+                    Ok(CasVersion(get_bytes_vec(v.get(1))?))
+                }
                 b"SETEX" | b"setex" | b"SetEx" | b"Setex" => {
                     let key = get_bytes_vec(v.get(1))?;
                     let duration = get_bytes_vec(v.get(2)).and_then(parse_duration)?;
@@ -191,6 +757,7 @@ impl Command {
                     Ok(MGet(keys_vec))
                 }
                 b"HSET" | b"hset" | b"HMSET" | b"hmset" => {
+                    let hmset = command.eq_ignore_ascii_case(b"HMSET");
                     let hash_key = get_bytes_vec(v.get(1))?;
                     let pairs = &v[2..];
 
@@ -210,7 +777,10 @@ impl Command {
                             _ => unreachable!(),
                         }
                     }
-                    Ok(HSet(hash_key, items))
+                    match hmset {
+                        true => Ok(HMSet(hash_key, items)),
+                        false => Ok(HSet(hash_key, items)),
+                    }
                 }
                 b"HGET" | b"hget" => {
                     //HGet(Key, Key),
@@ -219,6 +789,47 @@ impl Command {
 
                     Ok(HGet(hash_key, field_key))
                 }
+                b"HSCAN" | b"hscan" | b"HScan" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let (cursor, options) = parse_scan_args(v.get(2..).unwrap_or_default(), false)?;
+
+                    Ok(HScan(key, cursor, options))
+                }
+                b"HGETALL" | b"hgetall" | b"HGetAll" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    Ok(HGetAll(key))
+                }
+                b"HEXPIRE" | b"hexpire" | b"HExpire" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let duration = get_bytes_vec(v.get(2)).and_then(parse_duration)?;
+                    let expiry = Expiry::new_from_secs(duration)?;
+                    let fields = parse_fields(v.get(3..).unwrap_or_default())?;
+
+                    Ok(HExpire(key, expiry, fields))
+                }
+                b"HPEXPIRE" | b"hpexpire" | b"HPExpire" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let duration = get_bytes_vec(v.get(2)).and_then(parse_duration)?;
+                    let expiry = Expiry::new_from_millis(duration)?;
+                    let fields = parse_fields(v.get(3..).unwrap_or_default())?;
+
+                    Ok(HPExpire(key, expiry, fields))
+                }
+                b"HPERSIST" | b"hpersist" | b"HPersist" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let fields = parse_fields(v.get(2..).unwrap_or_default())?;
+                    Ok(HPersist(key, fields))
+                }
+                b"HTTL" | b"httl" | b"HTtl" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let fields = parse_fields(v.get(2..).unwrap_or_default())?;
+                    Ok(HTtl(key, fields))
+                }
+                b"HPTTL" | b"hpttl" | b"HPTtl" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let fields = parse_fields(v.get(2..).unwrap_or_default())?;
+                    Ok(HPttl(key, fields))
+                }
                 b"RPUSH" | b"RPush" | b"Rpush" | b"rpush" => {
                     let key = get_bytes_vec(v.get(1))?;
                     let values = &v[2..];
@@ -247,6 +858,12 @@ impl Command {
                     let key = get_bytes_vec(v.get(1))?;
                     Ok(LLen(key))
                 }
+                b"LRANGE" | b"LRange" | b"Lrange" | b"lrange" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let start = get_bytes_vec(v.get(2)).and_then(parse_variation)?;
+                    let stop = get_bytes_vec(v.get(3)).and_then(parse_variation)?;
+                    Ok(LRange(key, start, stop))
+                }
                 b"RPUSHX" | b"RPushx" | b"Rpushx" | b"rpushx" => {
                     let key = get_bytes_vec(v.get(1))?;
                     let values = &v[2..];
@@ -277,6 +894,16 @@ impl Command {
                     let key = get_bytes_vec(v.get(1))?;
                     Ok(LPop(key))
                 }
+                b"BLPOP" | b"BLPop" | b"Blpop" | b"blpop" => {
+                    let keys = v
+                        .get(1..v.len() - 1)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|key| get_bytes_vec(Some(key)))
+                        .collect::<Result<Keys, _>>()?;
+                    let timeout = get_bytes_vec(v.last()).and_then(parse_blocking_timeout)?;
+                    Ok(BLPop(keys, timeout))
+                }
                 b"LINDEX" | b"LIndex" | b"Lindex" | b"lindex" => {
                     let key = get_bytes_vec(v.get(1))?;
                     let index = get_bytes_vec(v.get(2)).and_then(parse_variation)?;
@@ -312,6 +939,16 @@ impl Command {
                     let dest = get_bytes_vec(v.get(2))?;
                     Ok(RPopLPush(src, dest))
                 }
+                b"RENAME" | b"rename" | b"Rename" => {
+                    let key = get_bytes_vec(v.get(1))?;
+                    let new_key = get_bytes_vec(v.get(2))?;
+                    Ok(Rename(key, new_key))
+                }
+                b"SCAN" | b"scan" | b"Scan" => {
+                    let (cursor, options) = parse_scan_args(v.get(1..).unwrap_or_default(), true)?;
+
+                    Ok(Scan(cursor, options))
+                }
                 b"SADD" | b"SAdd" | b"Sadd" | b"sadd" => {
                     let key = get_bytes_vec(v.get(1))?;
                     let values = &v[2..];
@@ -339,9 +976,29 @@ impl Command {
                     Ok(SRem(key, values_set))
                 }
 
-                b"DEL" | b"del" | b"Del" => {
+                b"SSCAN" | b"sscan" | b"SScan" => {
                     let key = get_bytes_vec(v.get(1))?;
-                    Ok(Del(key))
+                    let (cursor, options) = parse_scan_args(v.get(2..).unwrap_or_default(), false)?;
+
+                    Ok(SScan(key, cursor, options))
+                }
+
+                b"DELPATTERN" | b"delpattern" | b"DelPattern" => {
+                    Ok(DelPattern(get_bytes_vec(v.get(1))?))
+                }
+                b"DEL" | b"del" | b"Del" => {
+                    let keys = &v[1..];
+                    if keys.is_empty() {
+                        return Err(ArgNumber);
+                    }
+
+                    let mut keys_vec = Keys::with_capacity(keys.len());
+                    for key in keys {
+                        let key = get_bytes_vec(Some(key))?;
+                        keys_vec.push(key);
+                    }
+
+                    Ok(Del(keys_vec))
                 }
                 b"INCR" | b"incr" | b"Incr" => {
                     let key = get_bytes_vec(v.get(1))?;
@@ -359,7 +1016,10 @@ impl Command {
                 b"DECRBY" | b"decrby" | b"DecrBy" => {
                     let key = get_bytes_vec(v.get(1))?;
                     let decrement = get_bytes_vec(v.get(2)).and_then(parse_variation)?;
-                    Ok(IncrBy(key, -decrement))
+                    let increment = decrement
+                        .checked_neg()
+                        .ok_or(RedisCommandError::IncrOverflow)?;
+                    Ok(IncrBy(key, increment))
                 }
                 b"EXISTS" | b"exists" | b"Exists" => {
                     let key = get_bytes_vec(v.get(1))?;
@@ -377,17 +1037,250 @@ impl Command {
                     let key = get_bytes_vec(v.get(1))?;
                     Ok(Pttl(key))
                 }
-                b"INFO" | b"info" | b"Info" => Ok(Info),
+                b"INFO" | b"info" | b"Info" => {
+                    let section = match v.get(1) {
+                        Some(_) => Some(get_bytes_vec(v.get(1))?),
+                        None => None,
+                    };
+                    Ok(Info(section))
+                }
+                b"SELECT" | b"select" | b"Select" => {
+                    let db = get_bytes_vec(v.get(1)).and_then(parse_variation)?;
+                    Ok(Select(db))
+                }
+                b"AUTH" | b"auth" | b"Auth" => {
+                    let password = get_bytes_vec(v.last())?;
+                    let username = match v.len() {
+                        3 => Some(get_bytes_vec(v.get(1))?),
+                        _ => None,
+                    };
+                    Ok(Auth(username, password))
+                }
+                b"HELLO" | b"hello" | b"Hello" => parse_hello(&v[1..]),
+                b"CLIENT" | b"client" | b"Client" => {
+                    let subcommand = get_bytes_vec(v.get(1))?;
+                    match subcommand.to_ascii_uppercase().as_slice() {
+                        b"SETNAME" if v.len() != 3 => Err(ArgNumber),
+                        b"GETNAME" | b"UNPAUSE" if v.len() != 2 => Err(ArgNumber),
+                        b"PAUSE" if v.len() != 3 && v.len() != 4 => Err(ArgNumber),
+                        b"NO-EVICT" if v.len() != 3 => Err(ArgNumber),
+                        b"TRACKING" if v.len() < 3 => Err(ArgNumber),
+                        b"SETNAME" => {
+                            let name = get_bytes_vec(v.get(2))?;
+                            Ok(ClientSetName(name))
+                        }
+                        b"GETNAME" => Ok(ClientGetName),
+                        b"PAUSE" => {
+                            let timeout = get_bytes_vec(v.get(2))
+                                .and_then(parse_duration)
+                                .map_err(|_| InvalidTimeout)?;
+                            let writes_only = match v.get(3) {
+                                None => false,
+                                Some(mode) => {
+                                    match get_bytes_vec(Some(mode))?.to_ascii_uppercase().as_slice()
+                                    {
+                                        b"WRITE" => true,
+                                        b"ALL" => false,
+                                        _ => return Err(SyntaxErr),
+                                    }
+                                }
+                            };
+                            Ok(ClientPause(timeout, writes_only))
+                        }
+                        b"UNPAUSE" => Ok(ClientUnpause),
+                        b"NO-EVICT" => {
+                            match get_bytes_vec(v.get(2))?.to_ascii_uppercase().as_slice() {
+                                b"ON" => Ok(ClientNoEvict(true)),
+                                b"OFF" => Ok(ClientNoEvict(false)),
+                                _ => Err(SyntaxErr),
+                            }
+                        }
+                        b"TRACKING" => parse_client_tracking(&v[2..]),
+                        _ => Err(UnknownSubcommand(
+                            "client".to_string(),
+                            String::from_utf8_lossy(&subcommand).to_string(),
+                        )),
+                    }
+                }
+                b"COMMAND" | b"command" | b"Command" => match v.get(1) {
+                    None => Ok(CommandInfo(Vec::new())),
+                    Some(subcommand) => {
+                        let subcommand = get_bytes_vec(Some(subcommand))?;
+                        match subcommand.to_ascii_uppercase().as_slice() {
+                            b"COUNT" if v.len() != 2 => Err(ArgNumber),
+                            b"COUNT" => Ok(CommandCount),
+                            b"INFO" => {
+                                let names = v[2..]
+                                    .iter()
+                                    .map(|name| get_bytes_vec(Some(name)))
+                                    .collect::<Result<_, _>>()?;
+                                Ok(CommandInfo(names))
+                            }
+                            _ => Err(UnknownSubcommand(
+                                "command".to_string(),
+                                String::from_utf8_lossy(&subcommand).to_string(),
+                            )),
+                        }
+                    }
+                },
+                b"CONFIG" | b"config" | b"Config" => {
+                    let subcommand = get_bytes_vec(v.get(1))?;
+                    match subcommand.to_ascii_uppercase().as_slice() {
+                        b"GET" if v.len() != 3 => Err(ArgNumber),
+                        b"SET" if v.len() != 4 => Err(ArgNumber),
+                        b"RESETSTAT" if v.len() != 2 => Err(ArgNumber),
+                        b"GET" => Ok(ConfigGet(get_bytes_vec(v.get(2))?)),
+                        b"SET" => Ok(ConfigSet(
+                            get_bytes_vec(v.get(2))?,
+                            get_bytes_vec(v.get(3))?,
+                        )),
+                        b"RESETSTAT" => Ok(ConfigResetStat),
+                        _ => Err(UnknownSubcommand(
+                            "config".to_string(),
+                            String::from_utf8_lossy(&subcommand).to_string(),
+                        )),
+                    }
+                }
+                b"LATENCY" | b"latency" | b"Latency" => {
+                    let subcommand = get_bytes_vec(v.get(1))?;
+                    match subcommand.to_ascii_uppercase().as_slice() {
+                        b"LATEST" | b"DOCTOR" if v.len() != 2 => Err(ArgNumber),
+                        b"HISTORY" if v.len() != 3 => Err(ArgNumber),
+                        b"LATEST" => Ok(LatencyLatest),
+                        b"DOCTOR" => Ok(LatencyDoctor),
+                        b"HISTORY" => Ok(LatencyHistory(get_bytes_vec(v.get(2))?)),
+                        b"RESET" => {
+                            let events = v[2..]
+                                .iter()
+                                .map(|event| get_bytes_vec(Some(event)))
+                                .collect::<Result<_, _>>()?;
+                            Ok(LatencyReset(events))
+                        }
+                        _ => Err(UnknownSubcommand(
+                            "latency".to_string(),
+                            String::from_utf8_lossy(&subcommand).to_string(),
+                        )),
+                    }
+                }
+                b"MEMORY" | b"memory" | b"Memory" => {
+                    let subcommand = get_bytes_vec(v.get(1))?;
+                    match subcommand.to_ascii_uppercase().as_slice() {
+                        b"PURGE" if v.len() != 2 => Err(ArgNumber),
+                        b"PURGE" => Ok(MemoryPurge),
+                        _ => Err(UnknownSubcommand(
+                            "memory".to_string(),
+                            String::from_utf8_lossy(&subcommand).to_string(),
+                        )),
+                    }
+                }
+                b"OBJECT" | b"object" | b"Object" => {
+                    let subcommand = get_bytes_vec(v.get(1))?;
+                    match subcommand.to_ascii_uppercase().as_slice() {
+                        b"ENCODING" if v.len() != 3 => Err(ArgNumber),
+                        b"ENCODING" => Ok(ObjectEncoding(get_bytes_vec(v.get(2))?)),
+                        _ => Err(UnknownSubcommand(
+                            "object".to_string(),
+                            String::from_utf8_lossy(&subcommand).to_string(),
+                        )),
+                    }
+                }
+                b"DEBUG" | b"debug" | b"Debug" => {
+                    let subcommand = get_bytes_vec(v.get(1))?;
+                    match subcommand.to_ascii_uppercase().as_slice() {
+                        b"TYPESTATS" | b"RELOAD" | b"DIGEST" | b"CHANGE-REPL-ID"
+                            if v.len() != 2 =>
+                        {
+                            Err(ArgNumber)
+                        }
+                        b"TYPESTATS" => Ok(DebugTypeStats),
+                        b"RELOAD" => Ok(DebugReload),
+                        b"DIGEST" => Ok(DebugDigest),
+                        b"CHANGE-REPL-ID" => Ok(DebugChangeReplId),
+                        b"FAULT" => parse_debug_fault(&v[2..]),
+                        b"DIGEST-VALUE" => Ok(DebugDigestValue(
+                            v[2..]
+                                .iter()
+                                .map(|key| get_bytes_vec(Some(key)))
+                                .collect::<Result<_, _>>()?,
+                        )),
+                        _ => Err(UnknownSubcommand(
+                            "debug".to_string(),
+                            String::from_utf8_lossy(&subcommand).to_string(),
+                        )),
+                    }
+                }
+                b"CLUSTER" | b"cluster" | b"Cluster" => {
+                    let subcommand = get_bytes_vec(v.get(1))?;
+                    match subcommand.to_ascii_uppercase().as_slice() {
+                        b"LEADER" if v.len() != 2 => Err(ArgNumber),
+                        b"LEADER" => Ok(ClusterLeader),
+                        b"REDISLESS-STATUS" if v.len() != 2 => Err(ArgNumber),
+                        b"REDISLESS-STATUS" => Ok(ClusterRedislessStatus),
+                        b"FAILOVER" => match v.get(2) {
+                            None => Ok(ClusterFailover(false)),
+                            Some(option) if v.len() == 3 => {
+                                match get_bytes_vec(Some(option))?.to_ascii_uppercase().as_slice() {
+                                    b"FORCE" => Ok(ClusterFailover(true)),
+                                    _ => Err(SyntaxErr),
+                                }
+                            }
+                            Some(_) => Err(SyntaxErr),
+                        },
+                        _ => Err(UnknownSubcommand(
+                            "cluster".to_string(),
+                            String::from_utf8_lossy(&subcommand).to_string(),
+                        )),
+                    }
+                }
+                b"SENTINEL" | b"sentinel" | b"Sentinel" => {
+                    let subcommand = get_bytes_vec(v.get(1))?;
+                    match subcommand.to_ascii_uppercase().as_slice() {
+                        b"MASTERS" if v.len() != 2 => Err(ArgNumber),
+                        b"GET-MASTER-ADDR-BY-NAME" | b"SLAVES" | b"REPLICAS" if v.len() != 3 => {
+                            Err(ArgNumber)
+                        }
+                        b"MASTERS" => Ok(SentinelMasters),
+                        b"GET-MASTER-ADDR-BY-NAME" => {
+                            Ok(SentinelGetMasterAddrByName(get_bytes_vec(v.get(2))?))
+                        }
+                        b"SLAVES" | b"REPLICAS" => Ok(SentinelSlaves(get_bytes_vec(v.get(2))?)),
+                        _ => Err(UnknownSubcommand(
+                            "sentinel".to_string(),
+                            String::from_utf8_lossy(&subcommand).to_string(),
+                        )),
+                    }
+                }
                 b"PING" | b"ping" | b"Ping" => Ok(Ping),
                 b"DBSIZE" | b"dbsize" | b"Dbsize" => Ok(Dbsize),
+                b"FLUSHALL" | b"flushall" | b"FlushAll" | b"Flushall" => {
+                    parse_flush_mode(v.get(1))?;
+                    Ok(FlushAll)
+                }
+                b"FLUSHDB" | b"flushdb" | b"FlushDb" | b"Flushdb" => {
+                    parse_flush_mode(v.get(1))?;
+                    Ok(FlushDb)
+                }
                 b"QUIT" | b"quit" | b"Quit" => Ok(Quit),
+                b"READONLY" | b"readonly" | b"ReadOnly" | b"Readonly" => Ok(ReadOnly),
+                b"READWRITE" | b"readwrite" | b"ReadWrite" | b"Readwrite" => Ok(ReadWrite),
+                b"RESET" | b"reset" | b"Reset" => Ok(Reset),
                 unsupported_command => Err(NotSupported(
-                    std::str::from_utf8(unsupported_command)
-                        .unwrap()
-                        .to_string(),
+                    String::from_utf8_lossy(unsupported_command).to_string(),
                 )),
             },
             _ => Err(InvalidCommand),
         }
     }
 }
+
+/// Glob-style pattern matching `s` literally, whatever glob characters it holds
+fn escaped_glob(s: &[u8]) -> Vec<u8> {
+    let mut pattern = Vec::with_capacity(s.len());
+    for c in s {
+        if matches!(c, b'*' | b'?' | b'[' | b']' | b'\\') {
+            pattern.push(b'\\');
+        }
+        pattern.push(*c);
+    }
+    pattern
+}
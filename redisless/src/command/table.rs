@@ -0,0 +1,205 @@
+use super::arity::{at_least, between, exactly, Arity};
+use CommandKind::*;
+
+/// What a command does with the keyspace, deciding where it may run
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CommandKind {
+    /// Only reads keys, a follower may serve it to a client that sent READONLY
+    Read,
+    /// May modify keys, only the leader serves it
+    Write,
+    /// Administers the server without touching any key
+    Admin,
+    /// About the connection, or describing the server
+    Connection,
+}
+
+impl CommandKind {
+    /// Flag reported by COMMAND, `None` for connection commands
+    pub fn flag(self) -> Option<&'static str> {
+        match self {
+            CommandKind::Read => Some("readonly"),
+            CommandKind::Write => Some("write"),
+            CommandKind::Admin => Some("admin"),
+            CommandKind::Connection => None,
+        }
+    }
+}
+
+/// ACL category of commands, granted to users or denied by the `+@<category>` and
+/// `-@<category>` rules of an `AclUser`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AclCategory {
+    /// Every command
+    All,
+    Read,
+    Write,
+    Admin,
+    /// The admin commands, and those that may disrupt the server or tell too much about it
+    Dangerous,
+    Connection,
+}
+
+impl AclCategory {
+    /// Category called `name` in lowercase, `None` if there is none
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "all" => Some(AclCategory::All),
+            "read" => Some(AclCategory::Read),
+            "write" => Some(AclCategory::Write),
+            "admin" => Some(AclCategory::Admin),
+            "dangerous" => Some(AclCategory::Dangerous),
+            "connection" => Some(AclCategory::Connection),
+            _ => None,
+        }
+    }
+}
+
+/// Metadata of a command, as reported by COMMAND INFO
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub kind: CommandKind,
+    /// Position of the first key among the arguments, the command name being at 0. 0 when the
+    /// command names no key.
+    pub first_key: usize,
+    /// Position of the last key, negative ones counting from the end: -1 is the last argument
+    pub last_key: i64,
+    /// Arguments from one key to the next
+    pub step: usize,
+}
+
+impl CommandSpec {
+    /// Whether the command is in the ACL `category`, that of its kind or `dangerous`
+    pub fn is_in(&self, category: AclCategory) -> bool {
+        match category {
+            AclCategory::All => true,
+            AclCategory::Read => self.kind == Read,
+            AclCategory::Write => self.kind == Write,
+            AclCategory::Admin => self.kind == Admin,
+            AclCategory::Dangerous => {
+                self.kind == Admin || DANGEROUS_COMMANDS.binary_search(&self.name).is_ok()
+            }
+            AclCategory::Connection => self.kind == Connection,
+        }
+    }
+}
+
+const fn spec(
+    name: &'static str,
+    arity: Arity,
+    kind: CommandKind,
+    (first_key, last_key, step): (usize, i64, usize),
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        kind,
+        first_key,
+        last_key,
+        step,
+    }
+}
+
+const NO_KEY: (usize, i64, usize) = (0, 0, 0);
+const FIRST_KEY: (usize, i64, usize) = (1, 1, 1);
+const EVERY_KEY: (usize, i64, usize) = (1, -1, 1);
+
+/// Commands other than the admin ones Redis puts in the `dangerous` ACL category, sorted by name
+const DANGEROUS_COMMANDS: &[&str] = &["client", "delpattern", "flushall", "flushdb", "info"];
+
+/// Every supported command, sorted by name. Options are validated by `Command::parse`, the
+/// arity only rejects requests that can't be right whatever their options are.
+const COMMAND_TABLE: &[CommandSpec] = &[
+    spec("append", exactly(3), Write, FIRST_KEY),
+    spec("auth", between(2, 3), Connection, NO_KEY),
+    spec("bitfield", at_least(2), Write, FIRST_KEY),
+    spec("blpop", at_least(3), Write, (1, -2, 1)),
+    spec("cas", exactly(4), Write, FIRST_KEY),
+    spec("casversion", exactly(2), Read, FIRST_KEY),
+    spec("client", at_least(2), Connection, NO_KEY),
+    spec("cluster", at_least(2), Admin, NO_KEY),
+    spec("command", at_least(1), Connection, NO_KEY),
+    spec("config", at_least(2), Admin, NO_KEY),
+    spec("dbsize", exactly(1), Read, NO_KEY),
+    spec("debug", at_least(2), Admin, NO_KEY),
+    spec("decr", exactly(2), Write, FIRST_KEY),
+    spec("decrby", exactly(3), Write, FIRST_KEY),
+    spec("del", at_least(2), Write, EVERY_KEY),
+    spec("delpattern", exactly(2), Write, NO_KEY),
+    spec("exists", exactly(2), Read, FIRST_KEY),
+    spec("expire", exactly(3), Write, FIRST_KEY),
+    spec("flushall", between(1, 2), Write, NO_KEY),
+    spec("flushdb", between(1, 2), Write, NO_KEY),
+    spec("get", exactly(2), Read, FIRST_KEY),
+    spec("getset", exactly(3), Write, FIRST_KEY),
+    spec("hello", at_least(1), Connection, NO_KEY),
+    spec("hexpire", at_least(6), Write, FIRST_KEY),
+    spec("hget", exactly(3), Read, FIRST_KEY),
+    spec("hgetall", exactly(2), Read, FIRST_KEY),
+    spec("hmset", at_least(4), Write, FIRST_KEY),
+    spec("hpersist", at_least(5), Write, FIRST_KEY),
+    spec("hpexpire", at_least(6), Write, FIRST_KEY),
+    spec("hpttl", at_least(5), Read, FIRST_KEY),
+    spec("hscan", at_least(3), Read, FIRST_KEY),
+    spec("hset", at_least(4), Write, FIRST_KEY),
+    spec("httl", at_least(5), Read, FIRST_KEY),
+    spec("incr", exactly(2), Write, FIRST_KEY),
+    spec("incrby", exactly(3), Write, FIRST_KEY),
+    spec("info", between(1, 2), Connection, NO_KEY),
+    spec("latency", at_least(2), Admin, NO_KEY),
+    spec("lindex", exactly(3), Read, FIRST_KEY),
+    spec("linsert", exactly(5), Write, FIRST_KEY),
+    spec("llen", exactly(2), Read, FIRST_KEY),
+    spec("lpop", exactly(2), Write, FIRST_KEY),
+    spec("lpush", at_least(3), Write, FIRST_KEY),
+    spec("lpushx", at_least(3), Write, FIRST_KEY),
+    spec("lrange", exactly(4), Read, FIRST_KEY),
+    spec("lrem", exactly(4), Write, FIRST_KEY),
+    spec("lset", exactly(4), Write, FIRST_KEY),
+    spec("ltrim", exactly(4), Write, FIRST_KEY),
+    spec("memory", at_least(2), Admin, NO_KEY),
+    spec("mget", at_least(2), Read, EVERY_KEY),
+    spec("mset", at_least(3), Write, (1, -1, 2)),
+    spec("msetnx", at_least(3), Write, (1, -1, 2)),
+    spec("object", at_least(2), Read, (2, 2, 1)),
+    spec("pexpire", exactly(3), Write, FIRST_KEY),
+    spec("ping", between(1, 2), Connection, NO_KEY),
+    spec("psetex", exactly(4), Write, FIRST_KEY),
+    spec("pttl", exactly(2), Read, FIRST_KEY),
+    spec("quit", at_least(1), Connection, NO_KEY),
+    spec("readonly", exactly(1), Connection, NO_KEY),
+    spec("readwrite", exactly(1), Connection, NO_KEY),
+    spec("rename", exactly(3), Write, (1, 2, 1)),
+    spec("reset", exactly(1), Connection, NO_KEY),
+    spec("rpop", exactly(2), Write, FIRST_KEY),
+    spec("rpoplpush", exactly(3), Write, (1, 2, 1)),
+    spec("rpush", at_least(3), Write, FIRST_KEY),
+    spec("rpushx", at_least(3), Write, FIRST_KEY),
+    spec("sadd", at_least(3), Write, FIRST_KEY),
+    spec("scan", at_least(2), Read, NO_KEY),
+    spec("scard", exactly(2), Read, FIRST_KEY),
+    spec("select", exactly(2), Connection, NO_KEY),
+    spec("sentinel", at_least(2), Admin, NO_KEY),
+    spec("set", at_least(3), Write, FIRST_KEY),
+    spec("setex", exactly(4), Write, FIRST_KEY),
+    spec("setnx", exactly(3), Write, FIRST_KEY),
+    spec("srem", at_least(3), Write, FIRST_KEY),
+    spec("sscan", at_least(3), Read, FIRST_KEY),
+    spec("ttl", exactly(2), Read, FIRST_KEY),
+    spec("type", exactly(2), Read, FIRST_KEY),
+];
+
+/// Metadata of the command called `name` in lowercase, `None` if it's not supported
+pub fn command_spec(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE
+        .binary_search_by(|spec| spec.name.cmp(name))
+        .ok()
+        .map(|idx| &COMMAND_TABLE[idx])
+}
+
+/// Every supported command, sorted by name
+pub fn command_table() -> &'static [CommandSpec] {
+    COMMAND_TABLE
+}
@@ -1,5 +1,9 @@
-use crate::command::Command;
+use crate::command::command_error::RedisCommandError;
+use crate::command::{Command, SUPPORTED_COMMANDS};
 use crate::protocol::Resp;
+use crate::storage::models::RedisType;
+use crate::storage::scan::ScanOptions;
+use proptest::prelude::*;
 
 #[test]
 fn set_command() {
@@ -15,3 +19,782 @@ fn set_command() {
         assert_eq!(command, Command::Set(b"mykey".to_vec(), b"value".to_vec()));
     }
 }
+
+#[test]
+fn errors_match_redis_strings() {
+    let resp = vec![Resp::BulkString(b"GET")];
+    let err = Command::parse(resp).unwrap_err();
+    assert_eq!(
+        err.to_vec(),
+        b"-ERR wrong number of arguments for 'get' command\r\n".to_vec()
+    );
+
+    let resp = vec![
+        Resp::BulkString(b"INCRBY"),
+        Resp::BulkString(b"mykey"),
+        Resp::BulkString(b"abc"),
+    ];
+    let err = Command::parse(resp).unwrap_err();
+    assert_eq!(
+        err.to_vec(),
+        b"-ERR value is not an integer or out of range\r\n".to_vec()
+    );
+
+    let resp = vec![Resp::BulkString(b"NOPE"), Resp::BulkString(b"mykey")];
+    let err = Command::parse(resp).unwrap_err();
+    assert_eq!(
+        err.to_vec(),
+        b"-ERR unknown command `NOPE`, with args beginning with: \r\n".to_vec()
+    );
+
+    assert_eq!(
+        RedisCommandError::WrongTypeOperation.to_vec(),
+        b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n".to_vec()
+    );
+    assert_eq!(
+        RedisCommandError::SyntaxErr.to_vec(),
+        b"-ERR syntax error\r\n".to_vec()
+    );
+    assert_eq!(
+        RedisCommandError::Moved(12182, "127.0.0.1:6380".parse().unwrap()).to_vec(),
+        b"-MOVED 12182 127.0.0.1:6380\r\n".to_vec()
+    );
+    assert_eq!(
+        RedisCommandError::ClusterDown.to_vec(),
+        b"-CLUSTERDOWN The cluster is down\r\n".to_vec()
+    );
+}
+
+#[test]
+fn errors_are_sent_with_their_class() {
+    use crate::command::errors::ErrorClass;
+
+    let classes = vec![
+        (RedisCommandError::SyntaxErr, ErrorClass::Err),
+        (RedisCommandError::WrongTypeOperation, ErrorClass::WrongType),
+        (RedisCommandError::NoAuth, ErrorClass::NoAuth),
+        (RedisCommandError::WrongPass, ErrorClass::WrongPass),
+        (RedisCommandError::ProtectedMode, ErrorClass::Denied),
+        (RedisCommandError::MaxClientsReached, ErrorClass::Err),
+        (RedisCommandError::ClusterDown, ErrorClass::ClusterDown),
+        (RedisCommandError::Busy, ErrorClass::Busy),
+        (RedisCommandError::ReadOnlyReplica, ErrorClass::ReadOnly),
+        (RedisCommandError::NoProto, ErrorClass::NoProto),
+        (RedisCommandError::OutOfMemory, ErrorClass::Oom),
+        (RedisCommandError::NoPermKey, ErrorClass::NoPerm),
+    ];
+    for (err, class) in classes {
+        assert_eq!(err.class(), class);
+        let sent = err.to_string();
+        assert!(
+            sent.starts_with(&format!("{} ", class.prefix())),
+            "{}",
+            sent
+        );
+        // the class is sent once, the messages go without it
+        assert!(
+            !sent[class.prefix().len()..].contains(class.prefix()),
+            "{}",
+            sent
+        );
+    }
+
+    assert_eq!(
+        RedisCommandError::NoAuth.to_vec(),
+        b"-NOAUTH Authentication required.\r\n".to_vec()
+    );
+    assert_eq!(
+        RedisCommandError::UnknownSubcommand("client".to_string(), "nope".to_string()).to_vec(),
+        b"-ERR unknown subcommand 'nope'. Try CLIENT HELP.\r\n".to_vec()
+    );
+}
+
+#[test]
+fn scan_options() {
+    let resp = vec![
+        Resp::BulkString(b"HSCAN"),
+        Resp::BulkString(b"myhash"),
+        Resp::BulkString(b"42"),
+        Resp::BulkString(b"match"),
+        Resp::BulkString(b"f*"),
+        Resp::BulkString(b"COUNT"),
+        Resp::BulkString(b"100"),
+    ];
+    let command = Command::parse(resp).unwrap();
+    let options = ScanOptions {
+        pattern: Some(b"f*".to_vec()),
+        count: 100,
+        data_type: None,
+    };
+    assert_eq!(command, Command::HScan(b"myhash".to_vec(), 42, options));
+
+    let resp = vec![
+        Resp::BulkString(b"SSCAN"),
+        Resp::BulkString(b"myset"),
+        Resp::BulkString(b"nope"),
+    ];
+    let err = Command::parse(resp).unwrap_err();
+    assert_eq!(err.to_vec(), b"-ERR invalid cursor\r\n".to_vec());
+
+    let resp = vec![
+        Resp::BulkString(b"SSCAN"),
+        Resp::BulkString(b"myset"),
+        Resp::BulkString(b"0"),
+        Resp::BulkString(b"COUNT"),
+    ];
+    let err = Command::parse(resp).unwrap_err();
+    assert_eq!(err.to_vec(), b"-ERR syntax error\r\n".to_vec());
+
+    let resp = vec![
+        Resp::BulkString(b"SCAN"),
+        Resp::BulkString(b"0"),
+        Resp::BulkString(b"type"),
+        Resp::BulkString(b"HASH"),
+    ];
+    let command = Command::parse(resp).unwrap();
+    let options = ScanOptions {
+        data_type: Some(RedisType::Hash),
+        ..ScanOptions::default()
+    };
+    assert_eq!(command, Command::Scan(0, options));
+
+    let resp = vec![
+        Resp::BulkString(b"SCAN"),
+        Resp::BulkString(b"0"),
+        Resp::BulkString(b"TYPE"),
+        Resp::BulkString(b"nope"),
+    ];
+    let err = Command::parse(resp).unwrap_err();
+    assert_eq!(err.to_vec(), b"-ERR unknown type name 'nope'\r\n".to_vec());
+
+    // only SCAN walks keys of different types
+    let resp = vec![
+        Resp::BulkString(b"SSCAN"),
+        Resp::BulkString(b"myset"),
+        Resp::BulkString(b"0"),
+        Resp::BulkString(b"TYPE"),
+        Resp::BulkString(b"set"),
+    ];
+    let err = Command::parse(resp).unwrap_err();
+    assert_eq!(err.to_vec(), b"-ERR syntax error\r\n".to_vec());
+}
+
+proptest! {
+    #[test]
+    fn prop_any_arguments_are_handled(
+        command in 0..SUPPORTED_COMMANDS.len() + 1,
+        args in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..16), 0..6),
+    ) {
+        // one past the table is a command that doesn't exist
+        let name = SUPPORTED_COMMANDS.get(command).unwrap_or(&"nope");
+        let mut resp = vec![Resp::BulkString(name.as_bytes())];
+        resp.extend(args.iter().map(|arg| Resp::BulkString(arg)));
+        let _ = Command::parse(resp);
+    }
+}
+
+#[test]
+fn supported_commands_are_parsed() {
+    for command in SUPPORTED_COMMANDS {
+        for name in &[command.to_string(), command.to_uppercase()] {
+            let resp = vec![Resp::BulkString(name.as_bytes())];
+            if let Err(err) = Command::parse(resp) {
+                assert!(
+                    !matches!(err, RedisCommandError::NotSupported(_)),
+                    "{} is listed but not parsed",
+                    name
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn arity_table_covers_supported_commands() {
+    use crate::command::arity::arity;
+
+    for command in SUPPORTED_COMMANDS {
+        assert!(arity(command).is_some(), "no arity for {}", command);
+    }
+    assert!(arity("nope").is_none());
+}
+
+#[test]
+fn command_table_lists_supported_commands() {
+    use crate::command::table::command_table;
+
+    let names: Vec<&str> = command_table().iter().map(|spec| spec.name).collect();
+    assert_eq!(names, SUPPORTED_COMMANDS);
+}
+
+#[test]
+fn every_command_is_classified() {
+    use crate::command::table::CommandKind::{self, *};
+
+    let requests: &[(&[&str], CommandKind)] = &[
+        (&["APPEND", "k", "v"], Write),
+        (&["BITFIELD", "k", "GET", "u8", "0"], Write),
+        (&["CAS", "k", "0", "v"], Write),
+        (&["CASVERSION", "k"], Read),
+        (&["SET", "k", "v"], Write),
+        (&["SET", "k", "v", "KEEPTTL"], Write),
+        (&["SETNX", "k", "v"], Write),
+        (&["SETEX", "k", "10", "v"], Write),
+        (&["PSETEX", "k", "10", "v"], Write),
+        (&["MSET", "k", "v"], Write),
+        (&["MSETNX", "k", "v"], Write),
+        (&["EXPIRE", "k", "10"], Write),
+        (&["PEXPIRE", "k", "10"], Write),
+        (&["GET", "k"], Read),
+        (&["GETSET", "k", "v"], Write),
+        (&["MGET", "k", "l"], Read),
+        (&["HSET", "k", "f", "v"], Write),
+        (&["HMSET", "k", "f", "v"], Write),
+        (&["HGET", "k", "f"], Read),
+        (&["HSCAN", "k", "0"], Read),
+        (&["HGETALL", "k"], Read),
+        (&["HEXPIRE", "k", "10", "FIELDS", "1", "f"], Write),
+        (&["HPEXPIRE", "k", "10", "FIELDS", "1", "f"], Write),
+        (&["HPERSIST", "k", "FIELDS", "1", "f"], Write),
+        (&["HTTL", "k", "FIELDS", "1", "f"], Read),
+        (&["HPTTL", "k", "FIELDS", "1", "f"], Read),
+        (&["RPUSH", "k", "v"], Write),
+        (&["LPUSH", "k", "v"], Write),
+        (&["LLEN", "k"], Read),
+        (&["LRANGE", "k", "0", "-1"], Read),
+        (&["RPUSHX", "k", "v"], Write),
+        (&["LPUSHX", "k", "v"], Write),
+        (&["RPOP", "k"], Write),
+        (&["LPOP", "k"], Write),
+        (&["LINDEX", "k", "0"], Read),
+        (&["LSET", "k", "0", "v"], Write),
+        (&["LINSERT", "k", "BEFORE", "p", "v"], Write),
+        (&["LTRIM", "k", "0", "-1"], Write),
+        (&["LREM", "k", "0", "v"], Write),
+        (&["RPOPLPUSH", "k", "l"], Write),
+        (&["BLPOP", "k", "l", "0"], Write),
+        (&["RENAME", "k", "l"], Write),
+        (&["SADD", "k", "v"], Write),
+        (&["SCARD", "k"], Read),
+        (&["SREM", "k", "v"], Write),
+        (&["SSCAN", "k", "0"], Read),
+        (&["SCAN", "0"], Read),
+        (&["DEL", "k", "l"], Write),
+        (&["DELPATTERN", "k*"], Write),
+        (&["INCR", "k"], Write),
+        (&["INCRBY", "k", "2"], Write),
+        (&["DECR", "k"], Write),
+        (&["DECRBY", "k", "2"], Write),
+        (&["EXISTS", "k"], Read),
+        (&["TYPE", "k"], Read),
+        (&["TTL", "k"], Read),
+        (&["PTTL", "k"], Read),
+        (&["DBSIZE"], Read),
+        (&["FLUSHALL"], Write),
+        (&["FLUSHDB"], Write),
+        (&["INFO"], Connection),
+        (&["SELECT", "0"], Connection),
+        (&["AUTH", "token"], Connection),
+        (&["HELLO", "3"], Connection),
+        (&["CLIENT", "SETNAME", "name"], Connection),
+        (&["CLIENT", "GETNAME"], Connection),
+        (&["CLIENT", "PAUSE", "100", "WRITE"], Connection),
+        (&["CLIENT", "UNPAUSE"], Connection),
+        (&["CLIENT", "NO-EVICT", "ON"], Connection),
+        (&["CLIENT", "TRACKING", "ON", "BCAST"], Connection),
+        (&["COMMAND"], Connection),
+        (&["COMMAND", "COUNT"], Connection),
+        (&["COMMAND", "INFO", "get"], Connection),
+        (&["PING"], Connection),
+        (&["QUIT"], Connection),
+        (&["READONLY"], Connection),
+        (&["READWRITE"], Connection),
+        (&["RESET"], Connection),
+        (&["CONFIG", "GET", "*"], Admin),
+        (&["CONFIG", "SET", "set-max-intset-entries", "16"], Admin),
+        (&["CONFIG", "RESETSTAT"], Admin),
+        (&["LATENCY", "LATEST"], Admin),
+        (&["LATENCY", "HISTORY", "command"], Admin),
+        (&["LATENCY", "RESET"], Admin),
+        (&["LATENCY", "DOCTOR"], Admin),
+        (&["MEMORY", "PURGE"], Admin),
+        (&["OBJECT", "ENCODING", "k"], Read),
+        (&["DEBUG", "TYPESTATS"], Admin),
+        (&["DEBUG", "RELOAD"], Admin),
+        (&["DEBUG", "DIGEST"], Admin),
+        (&["DEBUG", "DIGEST-VALUE", "key"], Admin),
+        (&["DEBUG", "CHANGE-REPL-ID"], Admin),
+        (&["DEBUG", "FAULT", "SET", "get", "latency=50ms"], Admin),
+        (&["DEBUG", "FAULT", "RESET"], Admin),
+        (&["DEBUG", "FAULT", "LIST"], Admin),
+        (&["CLUSTER", "LEADER"], Admin),
+        (&["CLUSTER", "REDISLESS-STATUS"], Admin),
+        (&["CLUSTER", "FAILOVER", "FORCE"], Admin),
+        (&["SENTINEL", "GET-MASTER-ADDR-BY-NAME", "mymaster"], Admin),
+        (&["SENTINEL", "MASTERS"], Admin),
+        (&["SENTINEL", "SLAVES", "mymaster"], Admin),
+    ];
+
+    for (args, kind) in requests {
+        let command = Command::parse(
+            args.iter()
+                .map(|arg| Resp::BulkString(arg.as_bytes()))
+                .collect(),
+        )
+        .unwrap_or_else(|err| panic!("{:?} isn't parsed: {}", args, err));
+        assert_eq!(command.spec().kind, *kind, "{:?}", args);
+        assert_eq!(command.is_read(), *kind == Read, "{:?}", args);
+        assert_eq!(command.is_write(), *kind == Write, "{:?}", args);
+    }
+}
+
+#[test]
+fn command_specs() {
+    use crate::command::table::command_spec;
+
+    let mset = command_spec("mset").unwrap();
+    assert_eq!(mset.arity.to_redis(), -3);
+    assert_eq!((mset.first_key, mset.last_key, mset.step), (1, -1, 2));
+    let get = command_spec("get").unwrap();
+    assert_eq!(get.arity.to_redis(), 2);
+    assert_eq!(get.kind.flag(), Some("readonly"));
+    assert_eq!((get.first_key, get.last_key, get.step), (1, 1, 1));
+    let ping = command_spec("ping").unwrap();
+    assert_eq!((ping.first_key, ping.last_key, ping.step), (0, 0, 0));
+    assert!(command_spec("GET").is_none());
+}
+
+#[test]
+fn arity_and_options_are_validated() {
+    let parse = |args: &[&'static [u8]]| {
+        Command::parse(args.iter().map(|arg| Resp::BulkString(arg)).collect())
+    };
+
+    let err = parse(&[b"GET", b"key", b"extra"]).unwrap_err();
+    assert_eq!(
+        err.to_vec(),
+        b"-ERR wrong number of arguments for 'get' command\r\n".to_vec()
+    );
+    let err = parse(&[b"dbsize", b"extra"]).unwrap_err();
+    assert_eq!(
+        err.to_vec(),
+        b"-ERR wrong number of arguments for 'dbsize' command\r\n".to_vec()
+    );
+    let err = parse(&[b"CLIENT", b"GETNAME", b"extra"]).unwrap_err();
+    assert!(matches!(err, RedisCommandError::WrongArity(_)));
+
+    assert!(matches!(
+        parse(&[b"SET", b"key", b"value", b"EX", b"10"]),
+        Ok(Command::Setex(..))
+    ));
+    assert!(matches!(
+        parse(&[b"SET", b"key", b"value", b"px", b"10"]),
+        Ok(Command::PSetex(..))
+    ));
+    assert!(matches!(
+        parse(&[b"SET", b"key", b"value", b"keepttl"]),
+        Ok(Command::SetKeepTtl(..))
+    ));
+    for args in &[
+        &[&b"SET"[..], b"key", b"value", b"EX"][..],
+        &[b"SET", b"key", b"value", b"NX"],
+        &[b"SET", b"key", b"value", b"EX", b"10", b"NX"],
+        &[b"SET", b"key", b"value", b"KEEP", b"10"],
+        &[b"SET", b"key", b"value", b"EX", b"10", b"KEEPTTL"],
+    ] {
+        let err = parse(args).unwrap_err();
+        assert_eq!(err.to_vec(), b"-ERR syntax error\r\n".to_vec());
+    }
+    let err = parse(&[b"SET", b"key", b"value", b"EX", b"0"]).unwrap_err();
+    assert_eq!(
+        err.to_vec(),
+        b"-ERR invalid expire time in 'set' command\r\n".to_vec()
+    );
+}
+
+#[test]
+fn blocking_timeouts() {
+    use std::time::Duration;
+
+    let parse = |args: &[&'static [u8]]| {
+        Command::parse(args.iter().map(|arg| Resp::BulkString(arg)).collect())
+    };
+
+    assert_eq!(
+        parse(&[b"BLPOP", b"a", b"b", b"0"]).unwrap(),
+        Command::BLPop(vec![b"a".to_vec(), b"b".to_vec()], None)
+    );
+    assert_eq!(
+        parse(&[b"blpop", b"a", b"0.25"]).unwrap(),
+        Command::BLPop(vec![b"a".to_vec()], Some(Duration::from_millis(250)))
+    );
+
+    let err = parse(&[b"BLPOP", b"a", b"soon"]).unwrap_err();
+    assert_eq!(
+        err.to_vec(),
+        b"-ERR timeout is not a float or out of range\r\n".to_vec()
+    );
+    let err = parse(&[b"BLPOP", b"a", b"-1"]).unwrap_err();
+    assert_eq!(err.to_vec(), b"-ERR timeout is negative\r\n".to_vec());
+    let err = parse(&[b"BLPOP", b"a"]).unwrap_err();
+    assert!(matches!(err, RedisCommandError::WrongArity(_)));
+}
+
+#[test]
+fn debug_fault_options() {
+    use std::time::Duration;
+
+    let parse = |args: &[&'static [u8]]| {
+        Command::parse(args.iter().map(|arg| Resp::BulkString(arg)).collect())
+    };
+
+    assert_eq!(
+        parse(&[b"DEBUG", b"FAULT", b"SET", b"get", b"latency=50ms"]).unwrap(),
+        Command::DebugFaultSet(
+            b"get".to_vec(),
+            Duration::from_millis(50),
+            Duration::from_secs(0)
+        )
+    );
+    assert_eq!(
+        parse(&[
+            b"debug",
+            b"fault",
+            b"set",
+            b"EXEC",
+            b"JITTER=250us",
+            b"latency=1s"
+        ])
+        .unwrap(),
+        Command::DebugFaultSet(
+            b"EXEC".to_vec(),
+            Duration::from_secs(1),
+            Duration::from_micros(250)
+        )
+    );
+    assert_eq!(
+        parse(&[b"DEBUG", b"FAULT", b"SET", b"get", b"latency=20"]).unwrap(),
+        Command::DebugFaultSet(
+            b"get".to_vec(),
+            Duration::from_millis(20),
+            Duration::from_secs(0)
+        )
+    );
+    assert_eq!(
+        parse(&[b"DEBUG", b"FAULT", b"RESET", b"get"]).unwrap(),
+        Command::DebugFaultReset(Some(b"get".to_vec()))
+    );
+
+    for option in &[
+        &b"latency"[..],
+        b"latency=soon",
+        b"latency=-5ms",
+        b"delay=5ms",
+    ] {
+        let err = parse(&[b"DEBUG", b"FAULT", b"SET", b"get", option]).unwrap_err();
+        assert!(matches!(err, RedisCommandError::InvalidFaultOption(_)));
+    }
+    let err = parse(&[b"DEBUG", b"FAULT", b"SET", b"get"]).unwrap_err();
+    assert!(matches!(err, RedisCommandError::WrongArity(_)));
+    let err = parse(&[b"DEBUG", b"FAULT", b"BREAK"]).unwrap_err();
+    assert_eq!(err.to_vec(), b"-ERR syntax error\r\n".to_vec());
+}
+
+#[test]
+fn auth_takes_an_optional_username() {
+    let parse = |args: &[&'static [u8]]| {
+        Command::parse(args.iter().map(|arg| Resp::BulkString(arg)).collect())
+    };
+
+    assert_eq!(
+        parse(&[b"AUTH", b"secret"]).unwrap(),
+        Command::Auth(None, b"secret".to_vec())
+    );
+    assert_eq!(
+        parse(&[b"auth", b"alice", b"secret"]).unwrap(),
+        Command::Auth(Some(b"alice".to_vec()), b"secret".to_vec())
+    );
+}
+
+#[test]
+fn commands_are_put_in_acl_categories() {
+    use crate::command::table::{command_spec, AclCategory};
+
+    let categories = |name: &str| {
+        let spec = command_spec(name).unwrap();
+        [
+            ("all", AclCategory::All),
+            ("read", AclCategory::Read),
+            ("write", AclCategory::Write),
+            ("admin", AclCategory::Admin),
+            ("dangerous", AclCategory::Dangerous),
+            ("connection", AclCategory::Connection),
+        ]
+        .iter()
+        .filter(|(_, category)| spec.is_in(*category))
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(categories("get"), vec!["all", "read"]);
+    assert_eq!(categories("set"), vec!["all", "write"]);
+    assert_eq!(categories("flushall"), vec!["all", "write", "dangerous"]);
+    assert_eq!(categories("config"), vec!["all", "admin", "dangerous"]);
+    assert_eq!(categories("info"), vec!["all", "dangerous", "connection"]);
+    assert_eq!(categories("ping"), vec!["all", "connection"]);
+    assert_eq!(AclCategory::from_name("keyspace"), None);
+}
+
+#[test]
+fn hello_and_client_tracking_options() {
+    use crate::command::TrackingOptions;
+
+    let parse = |args: &[&'static [u8]]| {
+        Command::parse(args.iter().map(|arg| Resp::BulkString(arg)).collect())
+    };
+
+    assert_eq!(
+        parse(&[b"HELLO"]).unwrap(),
+        Command::Hello(None, None, None)
+    );
+    assert_eq!(
+        parse(&[b"hello", b"3", b"auth", b"default", b"token", b"SETNAME", b"app"]).unwrap(),
+        Command::Hello(
+            Some(3),
+            Some((b"default".to_vec(), b"token".to_vec())),
+            Some(b"app".to_vec())
+        )
+    );
+    let err = parse(&[b"HELLO", b"three"]).unwrap_err();
+    assert_eq!(
+        err.to_vec(),
+        b"-ERR Protocol version is not an integer or out of range\r\n".to_vec()
+    );
+    let err = parse(&[b"HELLO", b"3", b"AUTH", b"token"]).unwrap_err();
+    assert!(matches!(err, RedisCommandError::SyntaxErr));
+
+    assert_eq!(
+        parse(&[b"CLIENT", b"TRACKING", b"on"]).unwrap(),
+        Command::ClientTracking(Some(TrackingOptions::default()))
+    );
+    assert_eq!(
+        parse(&[
+            b"client",
+            b"tracking",
+            b"ON",
+            b"bcast",
+            b"PREFIX",
+            b"user:",
+            b"prefix",
+            b"cart:",
+            b"NOLOOP"
+        ])
+        .unwrap(),
+        Command::ClientTracking(Some(TrackingOptions {
+            bcast: true,
+            prefixes: vec![b"user:".to_vec(), b"cart:".to_vec()],
+            noloop: true,
+        }))
+    );
+    assert_eq!(
+        parse(&[b"CLIENT", b"TRACKING", b"OFF"]).unwrap(),
+        Command::ClientTracking(None)
+    );
+
+    let err = parse(&[b"CLIENT", b"TRACKING", b"ON", b"PREFIX", b"user:"]).unwrap_err();
+    assert_eq!(
+        err.to_vec(),
+        b"-ERR PREFIX option requires BCAST mode to be enabled\r\n".to_vec()
+    );
+    let err = parse(&[b"CLIENT", b"TRACKING", b"ON", b"REDIRECT", b"5"]).unwrap_err();
+    assert!(matches!(
+        err,
+        RedisCommandError::TrackingOptionNotSupported(_)
+    ));
+    let err = parse(&[b"CLIENT", b"TRACKING", b"MAYBE"]).unwrap_err();
+    assert!(matches!(err, RedisCommandError::SyntaxErr));
+    let err = parse(&[b"CLIENT", b"TRACKING"]).unwrap_err();
+    assert!(matches!(err, RedisCommandError::WrongArity(_)));
+
+    // the prefixes are keys, confined to those the connection sees
+    let mut command = parse(&[b"CLIENT", b"TRACKING", b"ON", b"BCAST"]).unwrap();
+    command.prefix_keys(b"app:");
+    assert_eq!(
+        command,
+        Command::ClientTracking(Some(TrackingOptions {
+            bcast: true,
+            prefixes: vec![b"app:".to_vec()],
+            noloop: false,
+        }))
+    );
+}
+
+#[test]
+fn bitfield_ops() {
+    use crate::command::bitfield::{apply_bitfield_ops, BitFieldOp, BitFieldType, Overflow};
+
+    let parse = |args: &[&'static [u8]]| {
+        Command::parse(args.iter().map(|arg| Resp::BulkString(arg)).collect())
+    };
+    let ops = |args: &[&'static [u8]]| match parse(args) {
+        Ok(Command::BitField(_, ops)) => ops,
+        other => panic!("{:?}", other),
+    };
+
+    let u8_type = BitFieldType {
+        signed: false,
+        bits: 8,
+    };
+    let i64_type = BitFieldType {
+        signed: true,
+        bits: 64,
+    };
+    assert_eq!(
+        ops(&[
+            b"BITFIELD",
+            b"k",
+            b"GET",
+            b"u8",
+            b"#2",
+            b"OVERFLOW",
+            b"sat",
+            b"set",
+            b"i64",
+            b"3",
+            b"-1"
+        ]),
+        vec![
+            BitFieldOp::Get(u8_type, 16),
+            BitFieldOp::Set(i64_type, 3, -1, Overflow::Sat)
+        ]
+    );
+    assert!(ops(&[b"bitfield", b"k"]).is_empty());
+
+    for (args, message) in &[
+        (&[&b"BITFIELD"[..], b"k", b"GET", b"u64", b"0"][..], "-ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.\r\n"),
+        (&[b"BITFIELD", b"k", b"GET", b"i0", b"0"], "-ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.\r\n"),
+        (&[b"BITFIELD", b"k", b"GET", b"u8", b"-1"], "-ERR bit offset is not an integer or out of range\r\n"),
+        (&[b"BITFIELD", b"k", b"GET", b"u8", b"18446744073709551615"], "-ERR bit offset is not an integer or out of range\r\n"),
+        (&[b"BITFIELD", b"k", b"GET", b"u8", b"4294967289"], "-ERR bit offset is not an integer or out of range\r\n"),
+        (&[b"BITFIELD", b"k", b"OVERFLOW", b"NOPE"], "-ERR Invalid OVERFLOW type specified\r\n"),
+        (&[b"BITFIELD", b"k", b"SET", b"u8", b"0"], "-ERR syntax error\r\n"),
+        (&[b"BITFIELD", b"k", b"NOPE"], "-ERR syntax error\r\n"),
+    ] {
+        assert_eq!(
+            parse(args).unwrap_err().to_vec(),
+            message.as_bytes().to_vec(),
+            "{:?}",
+            args
+        );
+    }
+
+    // examples from the Redis documentation
+    let mut value = Vec::new();
+    let (replies, written) = apply_bitfield_ops(
+        &mut value,
+        &ops(&[
+            b"BITFIELD",
+            b"k",
+            b"INCRBY",
+            b"i5",
+            b"100",
+            b"1",
+            b"GET",
+            b"u4",
+            b"0",
+        ]),
+    );
+    assert_eq!(replies, vec![Some(1), Some(0)]);
+    assert!(written);
+    assert_eq!(value.len(), 14);
+
+    let counters = ops(&[
+        b"BITFIELD",
+        b"k",
+        b"INCRBY",
+        b"u2",
+        b"100",
+        b"1",
+        b"OVERFLOW",
+        b"SAT",
+        b"INCRBY",
+        b"u2",
+        b"102",
+        b"1",
+        b"OVERFLOW",
+        b"FAIL",
+        b"INCRBY",
+        b"u2",
+        b"104",
+        b"1",
+    ]);
+    let mut value = Vec::new();
+    let mut replies = Vec::new();
+    for _ in 0..4 {
+        replies.push(apply_bitfield_ops(&mut value, &counters).0);
+    }
+    assert_eq!(
+        replies,
+        vec![
+            vec![Some(1), Some(1), Some(1)],
+            vec![Some(2), Some(2), Some(2)],
+            vec![Some(3), Some(3), Some(3)],
+            vec![Some(0), Some(3), None],
+        ]
+    );
+
+    // the sign bit comes first, SETBIT numbering
+    let mut value = vec![0b1000_0000, 0xff];
+    let (replies, written) = apply_bitfield_ops(
+        &mut value,
+        &ops(&[
+            b"BITFIELD",
+            b"k",
+            b"GET",
+            b"i8",
+            b"0",
+            b"GET",
+            b"u1",
+            b"0",
+            b"GET",
+            b"i4",
+            b"8",
+            b"OVERFLOW",
+            b"FAIL",
+            b"SET",
+            b"i4",
+            b"0",
+            b"8",
+        ]),
+    );
+    assert_eq!(replies, vec![Some(-128), Some(1), Some(-1), None]);
+    assert!(!written);
+
+    let (replies, _) = apply_bitfield_ops(
+        &mut value,
+        &ops(&[
+            b"BITFIELD",
+            b"k",
+            b"SET",
+            b"i8",
+            b"0",
+            b"200",
+            b"GET",
+            b"i8",
+            b"0",
+            b"OVERFLOW",
+            b"SAT",
+            b"INCRBY",
+            b"i64",
+            b"0",
+            b"-9223372036854775808",
+            b"INCRBY",
+            b"i64",
+            b"0",
+            b"-1",
+        ]),
+    );
+    assert_eq!(
+        replies,
+        vec![Some(-128), Some(-56), Some(i64::MIN), Some(i64::MIN)]
+    );
+}
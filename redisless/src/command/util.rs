@@ -1,5 +1,10 @@
+use std::time::Duration;
+
 use super::command_error::RedisCommandError;
+use super::{Command, TrackingOptions};
 use crate::protocol::Resp;
+use crate::storage::models::RedisType;
+use crate::storage::scan::ScanOptions;
 
 pub fn get_bytes_vec(resp: Option<&Resp>) -> Result<Vec<u8>, RedisCommandError> {
     match resp {
@@ -17,3 +22,234 @@ pub fn parse_variation(bytes: Vec<u8>) -> Result<i64, RedisCommandError> {
     let delta = std::str::from_utf8(&bytes[..])?;
     Ok(delta.parse::<i64>()?)
 }
+
+/// Parse the timeout of a blocking command, in seconds with decimals, `None` when it's 0 to
+/// wait for as long as it takes
+pub fn parse_blocking_timeout(bytes: Vec<u8>) -> Result<Option<Duration>, RedisCommandError> {
+    let timeout = std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|timeout| timeout.parse::<f64>().ok())
+        .filter(|timeout| timeout.is_finite())
+        .ok_or(RedisCommandError::TimeoutNotAFloat)?;
+
+    if timeout < 0.0 {
+        Err(RedisCommandError::NegativeTimeout)
+    } else if timeout == 0.0 {
+        Ok(None)
+    } else {
+        Duration::try_from_secs_f64(timeout)
+            .map(Some)
+            .map_err(|_| RedisCommandError::TimeoutNotAFloat)
+    }
+}
+
+/// Parse `cursor [MATCH pattern] [COUNT count]` as sent to the *SCAN commands, followed by
+/// `[TYPE type]` for SCAN itself when `with_type` is set
+pub fn parse_scan_args(
+    args: &[Resp],
+    with_type: bool,
+) -> Result<(u64, ScanOptions), RedisCommandError> {
+    let cursor = get_bytes_vec(args.first())?;
+    let cursor = std::str::from_utf8(&cursor)
+        .ok()
+        .and_then(|cursor| cursor.parse::<u64>().ok())
+        .ok_or(RedisCommandError::InvalidCursor)?;
+
+    let mut options = ScanOptions::default();
+    let mut args = args[1..].iter();
+    while let Some(option) = args.next() {
+        let option = get_bytes_vec(Some(option))?;
+        let value = args.next().ok_or(RedisCommandError::SyntaxErr);
+
+        if option.eq_ignore_ascii_case(b"MATCH") {
+            options.pattern = Some(get_bytes_vec(Some(value?))?);
+        } else if option.eq_ignore_ascii_case(b"COUNT") {
+            let count = get_bytes_vec(Some(value?)).and_then(parse_variation)?;
+            if count < 1 {
+                return Err(RedisCommandError::SyntaxErr);
+            }
+            options.count = count as usize;
+        } else if with_type && option.eq_ignore_ascii_case(b"TYPE") {
+            let name = get_bytes_vec(Some(value?))?;
+            let data_type = RedisType::from_name(&name).ok_or_else(|| {
+                RedisCommandError::UnknownTypeName(String::from_utf8_lossy(&name).to_string())
+            })?;
+            options.data_type = Some(data_type);
+        } else {
+            return Err(RedisCommandError::SyntaxErr);
+        }
+    }
+
+    Ok((cursor, options))
+}
+
+/// Parse `FIELDS numfields field [field ...]` as sent to HEXPIRE and the other hash field
+/// expiration commands. The NX, XX, GT and LT conditions aren't supported, they fail here as
+/// they come before FIELDS.
+pub fn parse_fields(args: &[Resp]) -> Result<Vec<Vec<u8>>, RedisCommandError> {
+    let keyword = get_bytes_vec(args.first())?;
+    if !keyword.eq_ignore_ascii_case(b"FIELDS") {
+        return Err(RedisCommandError::SyntaxErr);
+    }
+
+    let count = get_bytes_vec(args.get(1)).and_then(parse_variation)?;
+    if count < 1 {
+        return Err(RedisCommandError::NumFieldsNotPositive);
+    }
+    let fields = args.get(2..).unwrap_or_default();
+    if fields.len() as i64 != count {
+        return Err(RedisCommandError::NumFieldsMismatch);
+    }
+
+    fields
+        .iter()
+        .map(|field| get_bytes_vec(Some(field)))
+        .collect()
+}
+
+/// Check the optional `ASYNC`/`SYNC` argument of FLUSHALL and FLUSHDB. Clearing the storage is
+/// always done synchronously, dropping the values doesn't take long enough to be worth a thread.
+pub fn parse_flush_mode(arg: Option<&Resp>) -> Result<(), RedisCommandError> {
+    match arg {
+        None => Ok(()),
+        Some(arg) => {
+            let mode = get_bytes_vec(Some(arg))?;
+            if mode.eq_ignore_ascii_case(b"ASYNC") || mode.eq_ignore_ascii_case(b"SYNC") {
+                Ok(())
+            } else {
+                Err(RedisCommandError::SyntaxErr)
+            }
+        }
+    }
+}
+
+/// Parse what follows HELLO: `[protover [AUTH username password] [SETNAME clientname]]`
+pub fn parse_hello(args: &[Resp]) -> Result<Command, RedisCommandError> {
+    let version = match args.first() {
+        None => return Ok(Command::Hello(None, None, None)),
+        Some(version) => get_bytes_vec(Some(version))
+            .and_then(parse_variation)
+            .map_err(|_| RedisCommandError::InvalidProtocolVersion)?,
+    };
+
+    let (mut credentials, mut name) = (None, None);
+    let mut args = args[1..].iter();
+    while let Some(option) = args.next() {
+        let option = get_bytes_vec(Some(option))?;
+        if option.eq_ignore_ascii_case(b"AUTH") {
+            let username = args.next().ok_or(RedisCommandError::SyntaxErr)?;
+            let password = args.next().ok_or(RedisCommandError::SyntaxErr)?;
+            credentials = Some((
+                get_bytes_vec(Some(username))?,
+                get_bytes_vec(Some(password))?,
+            ));
+        } else if option.eq_ignore_ascii_case(b"SETNAME") {
+            let value = args.next().ok_or(RedisCommandError::SyntaxErr)?;
+            name = Some(get_bytes_vec(Some(value))?);
+        } else {
+            return Err(RedisCommandError::SyntaxErr);
+        }
+    }
+
+    Ok(Command::Hello(Some(version), credentials, name))
+}
+
+/// Parse what follows CLIENT TRACKING: `ON|OFF [BCAST] [PREFIX prefix ...] [NOLOOP]`. The
+/// invalidation messages can only be pushed over RESP3, REDIRECT isn't supported, and neither
+/// are OPTIN and OPTOUT.
+pub fn parse_client_tracking(args: &[Resp]) -> Result<Command, RedisCommandError> {
+    let on = match get_bytes_vec(args.first())?.to_ascii_uppercase().as_slice() {
+        b"ON" => true,
+        b"OFF" => false,
+        _ => return Err(RedisCommandError::SyntaxErr),
+    };
+
+    let mut options = TrackingOptions::default();
+    let mut args = args[1..].iter();
+    while let Some(option) = args.next() {
+        let option = get_bytes_vec(Some(option))?;
+        match option.to_ascii_uppercase().as_slice() {
+            b"BCAST" => options.bcast = true,
+            b"NOLOOP" => options.noloop = true,
+            b"PREFIX" => {
+                let prefix = args.next().ok_or(RedisCommandError::SyntaxErr)?;
+                options.prefixes.push(get_bytes_vec(Some(prefix))?);
+            }
+            name @ b"REDIRECT" | name @ b"OPTIN" | name @ b"OPTOUT" => {
+                return Err(RedisCommandError::TrackingOptionNotSupported(
+                    String::from_utf8_lossy(name).to_string(),
+                ))
+            }
+            _ => return Err(RedisCommandError::SyntaxErr),
+        }
+    }
+    if !options.prefixes.is_empty() && !options.bcast {
+        return Err(RedisCommandError::TrackingPrefixWithoutBcast);
+    }
+
+    Ok(Command::ClientTracking(if on {
+        Some(options)
+    } else {
+        None
+    }))
+}
+
+/// Parse what follows DEBUG FAULT: `SET command [latency=duration] [jitter=duration]`,
+/// `RESET [command]` or `LIST`
+pub fn parse_debug_fault(args: &[Resp]) -> Result<Command, RedisCommandError> {
+    let action = get_bytes_vec(args.first())?;
+    match action.to_ascii_uppercase().as_slice() {
+        b"SET" if args.len() < 3 => Err(RedisCommandError::ArgNumber),
+        b"SET" => {
+            let command = get_bytes_vec(args.get(1))?;
+            let (mut latency, mut jitter) = (Duration::from_secs(0), Duration::from_secs(0));
+            for option in &args[2..] {
+                let option = get_bytes_vec(Some(option))?;
+                let invalid = || {
+                    RedisCommandError::InvalidFaultOption(
+                        String::from_utf8_lossy(&option).to_string(),
+                    )
+                };
+                let mut parts = option.splitn(2, |byte| *byte == b'=');
+                let (name, value) = match (parts.next(), parts.next()) {
+                    (Some(name), Some(value)) => (name, value),
+                    _ => return Err(invalid()),
+                };
+                let value = parse_fault_duration(value).ok_or_else(invalid)?;
+                if name.eq_ignore_ascii_case(b"latency") {
+                    latency = value;
+                } else if name.eq_ignore_ascii_case(b"jitter") {
+                    jitter = value;
+                } else {
+                    return Err(invalid());
+                }
+            }
+            Ok(Command::DebugFaultSet(command, latency, jitter))
+        }
+        b"RESET" if args.len() > 2 => Err(RedisCommandError::ArgNumber),
+        b"RESET" => Ok(Command::DebugFaultReset(
+            args.get(1)
+                .map(|command| get_bytes_vec(Some(command)))
+                .transpose()?,
+        )),
+        b"LIST" if args.len() != 1 => Err(RedisCommandError::ArgNumber),
+        b"LIST" => Ok(Command::DebugFaultList),
+        _ => Err(RedisCommandError::SyntaxErr),
+    }
+}
+
+/// Parse a duration of DEBUG FAULT, in `us`, `ms` or `s`, milliseconds when it has no unit
+fn parse_fault_duration(bytes: &[u8]) -> Option<Duration> {
+    let duration = std::str::from_utf8(bytes).ok()?;
+    let unit_at = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (amount, unit) = duration.split_at(unit_at);
+    let amount = amount.parse::<u64>().ok()?;
+    match unit {
+        "us" => Some(Duration::from_micros(amount)),
+        "" | "ms" => Some(Duration::from_millis(amount)),
+        "s" => Some(Duration::from_secs(amount)),
+        _ => None,
+    }
+}
@@ -2,6 +2,9 @@
 #[macro_use]
 extern crate serial_test;
 
+use std::ffi::CString;
+use std::os::raw::c_char;
+
 use storage::in_memory::InMemoryStorage;
 
 use crate::server::{Server, ServerState};
@@ -9,18 +12,66 @@ use crate::server::{Server, ServerState};
 #[cfg(test)]
 mod tests;
 
-mod cluster;
+pub mod capabilities;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod cluster;
 mod command;
 mod error;
 mod protocol;
+pub mod raft_transport;
 pub mod server;
 pub mod storage;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+
+pub use command::Command;
+pub use storage::models::diff::diff;
+
+/// Run untrusted bytes through everything a client request goes through before it reaches
+/// the storage, for the fuzz targets in `fuzz/`
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_request(data: &[u8]) {
+    use protocol::{parser::RedisProtocolParser, Resp};
+
+    let _ = RedisProtocolParser::frame_len(data);
+    if let Ok((Resp::Array(args), _)) = RedisProtocolParser::parse(data) {
+        let _ = command::Command::parse(args);
+    }
+}
 
 #[no_mangle]
 pub unsafe extern "C" fn redisless_server_new(port: u16) -> *mut Server {
     Box::into_raw(Box::new(Server::new(InMemoryStorage::new(), port)))
 }
 
+/// Server listening on the first free port from `first_port` to `last_port`, see
+/// `redisless_server_port`, or null when they're all taken. With 0 and 0, the system picks one.
+#[no_mangle]
+pub extern "C" fn redisless_server_new_on_free_port(
+    first_port: u16,
+    last_port: u16,
+) -> *mut Server {
+    match Server::new_on_free_port(InMemoryStorage::new(), first_port..=last_port) {
+        Ok(server) => Box::into_raw(Box::new(server)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Port `server` listens on, 0 when `server` is null
+///
+/// # Safety
+///
+/// `server` must come from `redisless_server_new` or `redisless_server_new_on_free_port`, and
+/// not be freed yet
+#[no_mangle]
+pub unsafe extern "C" fn redisless_server_port(server: *mut Server) -> u16 {
+    match server.as_ref() {
+        Some(server) => server.port(),
+        None => 0,
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn redisless_server_free(server: *mut Server) {
     let _ = Box::from_raw(server);
@@ -51,3 +102,23 @@ pub unsafe extern "C" fn redisless_server_stop(server: *mut Server) -> bool {
         None => false,
     }
 }
+
+/// Supported commands and features as a JSON string, to be released with
+/// `redisless_capabilities_free`
+#[no_mangle]
+pub extern "C" fn redisless_capabilities() -> *mut c_char {
+    // the JSON never contains a nul byte
+    CString::new(capabilities::capabilities().to_json())
+        .map(CString::into_raw)
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `capabilities` must come from `redisless_capabilities` and be released only once
+#[no_mangle]
+pub unsafe extern "C" fn redisless_capabilities_free(capabilities: *mut c_char) {
+    if !capabilities.is_null() {
+        let _ = CString::from_raw(capabilities);
+    }
+}
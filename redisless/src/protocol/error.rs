@@ -12,6 +12,14 @@ pub enum RedisErrorType {
     NoCrlf,
     // Incorrect format detected
     IncorrectFormat,
+    // Arrays nested too deep
+    TooDeep,
+    // No CRLF after a type and length line of unreasonable size
+    LineTooLong,
+    // Bulk string longer than allowed
+    BulkTooLong,
+    // Array with more elements than allowed
+    MultibulkTooLong,
     Other(Box<dyn std::error::Error>),
 }
 
@@ -38,11 +46,45 @@ impl RedisError {
             err_type: RedisErrorType::IncorrectFormat,
         }
     }
+
+    pub fn too_deep() -> Self {
+        Self {
+            err_type: RedisErrorType::TooDeep,
+        }
+    }
+
+    pub fn line_too_long() -> Self {
+        Self {
+            err_type: RedisErrorType::LineTooLong,
+        }
+    }
+
+    pub fn bulk_too_long() -> Self {
+        Self {
+            err_type: RedisErrorType::BulkTooLong,
+        }
+    }
+
+    pub fn multibulk_too_long() -> Self {
+        Self {
+            err_type: RedisErrorType::MultibulkTooLong,
+        }
+    }
 }
 
 impl<'a> std::fmt::Display for RedisError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+        match &self.err_type {
+            RedisErrorType::UnknownSymbol => write!(f, "unknown type symbol"),
+            RedisErrorType::EmptyInput => write!(f, "empty input"),
+            RedisErrorType::NoCrlf => write!(f, "missing CRLF"),
+            RedisErrorType::IncorrectFormat => write!(f, "invalid bulk length"),
+            RedisErrorType::TooDeep => write!(f, "too many nested arrays"),
+            RedisErrorType::LineTooLong => write!(f, "too big count string"),
+            RedisErrorType::BulkTooLong => write!(f, "invalid bulk length"),
+            RedisErrorType::MultibulkTooLong => write!(f, "invalid multibulk length"),
+            RedisErrorType::Other(err) => write!(f, "{}", err),
+        }
     }
 }
 
@@ -4,6 +4,7 @@ mod tests;
 pub mod error;
 pub mod parser;
 pub mod response;
+pub mod writer;
 
 use error::RedisError;
 
@@ -16,6 +17,8 @@ const LF: u8 = b'\n';
 pub const OK: &[u8; 5] = b"+OK\r\n";
 pub const PONG: &[u8; 7] = b"+PONG\r\n";
 pub const NIL: &[u8; 5] = b"$-1\r\n";
+pub const NIL_ARRAY: &[u8; 5] = b"*-1\r\n";
+pub const RESP3_NULL: &[u8; 3] = b"_\r\n";
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum Resp<'a> {
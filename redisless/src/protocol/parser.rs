@@ -2,18 +2,50 @@ use super::error::RedisError;
 use super::{Resp, Result};
 use super::{CR, LF, NIL_VALUE_SIZE};
 
+/// Arrays nested deeper than this are rejected instead of growing the stack
+const MAX_NESTING_DEPTH: usize = 32;
+/// Longest type and length line accepted before its CRLF, like Redis' inline limit
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Largest bulk string and array accepted from a client, anything announcing more is a
+/// protocol error before a single byte of it is buffered
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ProtocolLimits {
+    pub max_bulk_len: usize,
+    pub max_multibulk_len: usize,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        ProtocolLimits {
+            // Redis' proto-max-bulk-len
+            max_bulk_len: 512 * 1024 * 1024,
+            // what Redis accepts from a client that hasn't authenticated
+            max_multibulk_len: 1024 * 1024,
+        }
+    }
+}
+
 pub struct RedisProtocolParser;
 
 impl RedisProtocolParser {
     pub fn parse(input: &[u8]) -> Result {
+        RedisProtocolParser::parse_with_limits(input, &ProtocolLimits::default())
+    }
+
+    pub fn parse_with_limits<'a>(input: &'a [u8], limits: &ProtocolLimits) -> Result<'a> {
+        RedisProtocolParser::parse_nested(input, 0, limits)
+    }
+
+    fn parse_nested<'a>(input: &'a [u8], depth: usize, limits: &ProtocolLimits) -> Result<'a> {
         if let Some(first) = input.get(0) {
             let first = *first as char;
             let input = &input[1..];
             let (resp, left) = match first {
                 '+' => RedisProtocolParser::parse_simple_string(input)?,
                 ':' => RedisProtocolParser::parse_integers(input)?,
-                '$' => RedisProtocolParser::parse_bulk_strings(input)?,
-                '*' => RedisProtocolParser::parse_arrays(input)?,
+                '$' => RedisProtocolParser::parse_bulk_strings(input, limits.max_bulk_len)?,
+                '*' => RedisProtocolParser::parse_arrays(input, depth + 1, limits)?,
                 '-' => RedisProtocolParser::parse_errors(input)?,
                 _ => return Err(RedisError::unknown_symbol()),
             };
@@ -23,6 +55,84 @@ impl RedisProtocolParser {
         }
     }
 
+    /// Length of the first complete value at the start of `input`
+    ///
+    /// Returns `None` when `input` ends before the value does, so the caller knows to wait for
+    /// more bytes instead of failing on a request split across several reads.
+    pub fn frame_len(input: &[u8]) -> std::result::Result<Option<usize>, RedisError> {
+        RedisProtocolParser::frame_len_with_limits(input, &ProtocolLimits::default())
+    }
+
+    /// Like `frame_len`, failing as soon as a length over `limits` is announced
+    pub fn frame_len_with_limits(
+        input: &[u8],
+        limits: &ProtocolLimits,
+    ) -> std::result::Result<Option<usize>, RedisError> {
+        RedisProtocolParser::nested_frame_len(input, 0, limits)
+    }
+
+    fn nested_frame_len(
+        input: &[u8],
+        depth: usize,
+        limits: &ProtocolLimits,
+    ) -> std::result::Result<Option<usize>, RedisError> {
+        let first = match input.first() {
+            Some(first) => *first,
+            None => return Ok(None),
+        };
+
+        let line = match RedisProtocolParser::parse_everything_until_crlf(&input[1..]) {
+            Ok((line, _)) => line,
+            Err(_) if input.len() > MAX_LINE_LEN => return Err(RedisError::line_too_long()),
+            Err(_) => return Ok(None),
+        };
+        let header_len = 1 + line.len() + 2;
+
+        match first {
+            b'+' | b'-' | b':' => Ok(Some(header_len)),
+            b'$' => {
+                let size = std::str::from_utf8(line)?.parse::<i64>()?;
+                if size < 0 {
+                    return Ok(Some(header_len));
+                }
+                if size as u64 > limits.max_bulk_len as u64 {
+                    return Err(RedisError::bulk_too_long());
+                }
+
+                let len = (size as usize)
+                    .checked_add(header_len + 2)
+                    .ok_or_else(RedisError::incorrect_format)?;
+                if input.len() < len {
+                    return Ok(None);
+                }
+                if input[len - 2] != CR || input[len - 1] != LF {
+                    return Err(RedisError::incorrect_format());
+                }
+                Ok(Some(len))
+            }
+            b'*' => {
+                if depth >= MAX_NESTING_DEPTH {
+                    return Err(RedisError::too_deep());
+                }
+
+                let size = std::str::from_utf8(line)?.parse::<i64>()?;
+                if size > 0 && size as u64 > limits.max_multibulk_len as u64 {
+                    return Err(RedisError::multibulk_too_long());
+                }
+
+                let mut len = header_len;
+                for _ in 0..size.max(0) {
+                    match RedisProtocolParser::nested_frame_len(&input[len..], depth + 1, limits)? {
+                        Some(element_len) => len += element_len,
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(len))
+            }
+            _ => Err(RedisError::unknown_symbol()),
+        }
+    }
+
     fn parse_everything_until_crlf(
         input: &[u8],
     ) -> std::result::Result<(&[u8], &[u8]), RedisError> {
@@ -46,15 +156,20 @@ impl RedisProtocolParser {
         RedisProtocolParser::parse_everything_until_crlf(input).map(|(x, y)| (Resp::Integer(x), y))
     }
 
-    pub fn parse_bulk_strings(input: &[u8]) -> Result {
+    pub fn parse_bulk_strings(input: &[u8], max_len: usize) -> Result<'_> {
         // Check Null Strings.
         if RedisProtocolParser::check_null_value(input) {
             Ok((Resp::Nil, &input[NIL_VALUE_SIZE..]))
         } else {
             let (size_str, input_after_size) =
                 RedisProtocolParser::parse_everything_until_crlf(input)?;
-            let size = std::str::from_utf8(size_str)?.parse::<u64>()? as usize;
+            let size = std::str::from_utf8(size_str)?.parse::<u64>()?;
+            if size > max_len as u64 {
+                return Err(RedisError::bulk_too_long());
+            }
+            let size = size as usize;
             if RedisProtocolParser::check_crlf_at_index(input_after_size, size) {
+                // will never panic, `check_crlf_at_index` made sure `size + 2` bytes are there
                 Ok((
                     Resp::BulkString(&input_after_size[..size]),
                     &input_after_size[size + 2..],
@@ -66,21 +181,32 @@ impl RedisProtocolParser {
     }
 
     fn check_crlf_at_index(input: &[u8], index: usize) -> bool {
-        input.len() >= index && input[index] == CR && input[index + 1] == LF
+        match index.checked_add(2) {
+            Some(end) if input.len() >= end => input[index] == CR && input[index + 1] == LF,
+            _ => false,
+        }
     }
 
     fn check_null_value(input: &[u8]) -> bool {
         input.len() >= 4 && input[0] == b'-' && input[1] == b'1' && input[2] == CR && input[3] == LF
     }
 
-    pub fn parse_arrays(input: &[u8]) -> Result {
+    fn parse_arrays<'a>(input: &'a [u8], depth: usize, limits: &ProtocolLimits) -> Result<'a> {
+        if depth > MAX_NESTING_DEPTH {
+            return Err(RedisError::too_deep());
+        }
+
         let (size_str, input) = RedisProtocolParser::parse_everything_until_crlf(input)?;
         let size = std::str::from_utf8(size_str)?.parse::<u64>()?;
+        if size > limits.max_multibulk_len as u64 {
+            return Err(RedisError::multibulk_too_long());
+        }
         let sizes = size as usize;
         let mut left = input;
-        let mut result = Vec::with_capacity(sizes);
+        // every element takes at least 3 bytes, don't trust the announced size any further
+        let mut result = Vec::with_capacity(sizes.min(left.len() / 3));
         for _ in 0..sizes {
-            let (element, tmp) = RedisProtocolParser::parse(left)?;
+            let (element, tmp) = RedisProtocolParser::parse_nested(left, depth, limits)?;
             result.push(element);
             left = tmp;
         }
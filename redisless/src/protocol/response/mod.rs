@@ -1,13 +1,19 @@
-use prost::bytes::BufMut;
-
-use super::{NIL, OK, PONG};
+use super::writer::RespWriter;
+use super::{OK, PONG};
 use crate::{command::command_error::RedisCommandError, storage::models::RedisString};
 
 pub enum RedisResponseType {
     SimpleString(RedisString),
     BulkString(RedisString),
     Integer(i64),
+    /// Null bulk string, for a missing value
     Nil,
+    /// Null array, for a missing array such as the address of an unknown master. A missing
+    /// collection of elements, e.g. a list that doesn't exist, is an empty `Array` instead.
+    NullArray,
+    Array(Vec<RedisResponseType>),
+    /// RESP3 map, only for clients that switched to RESP3 with HELLO
+    Map(Vec<(RedisResponseType, RedisResponseType)>),
 }
 
 pub struct RedisResponse {
@@ -21,40 +27,27 @@ enum RedisResponseInner {
     Okay,
     Pong,
     Quit,
+    Blocked,
 }
 
 impl RedisResponseType {
-    // move out of the enum
-    fn to_vec(self) -> Vec<u8> {
+    pub fn write_to(self, writer: &mut RespWriter) {
         use RedisResponseType::*;
         match self {
-            SimpleString(s) | BulkString(s) => s,
-            Integer(num) => num.to_string().as_bytes().to_vec(),
-            Nil => NIL.to_vec(),
-        }
-    }
-    /// Move out of self and return bytes analogous to `format!("{}{}{}", symbol, data, CRLF)`
-    pub fn get_formatted(self) -> Vec<u8> {
-        use RedisResponseType::*;
-
-        let symbol = match &self {
-            SimpleString(_) => b'+',
-            BulkString(_) => b'$',
-            Integer(_) => b':',
-            Nil => return self.to_vec(),
-        };
-        let mut bytes = self.to_vec();
-        let mut reply =
-            Vec::<u8>::with_capacity(bytes.len() + 3 /* 3 more bytes for symbol and /r/n */);
-        reply.push(symbol);
-        if symbol == b'$' {
-            reply.put_slice(bytes.len().to_string().as_bytes());
-            reply.put_slice(b"\r\n");
+            SimpleString(s) => writer.simple_string(&s),
+            BulkString(s) => writer.bulk_string(&s),
+            Integer(num) => writer.integer(num),
+            Nil => writer.null(),
+            NullArray => writer.null_array(),
+            Array(responses) => write_array(responses, writer),
+            Map(pairs) => {
+                writer.map_len(pairs.len());
+                for (key, value) in pairs {
+                    key.write_to(writer);
+                    value.write_to(writer);
+                }
+            }
         }
-        //eprintln!("{:?}", bytes);
-        reply.append(&mut bytes);
-        reply.put_slice(b"\r\n");
-        reply
     }
 }
 
@@ -69,11 +62,24 @@ impl RedisResponse {
             responses: RedisResponseInner::Pong,
         }
     }
+    /// Reply to a command queued inside MULTI
+    pub fn queued() -> Self {
+        Self::single(RedisResponseType::SimpleString(b"QUEUED".to_vec()))
+    }
     pub fn quit() -> Self {
         Self {
             responses: RedisResponseInner::Quit,
         }
     }
+    /// No reply yet, the blocking command is run again once keys were written or it timed out
+    pub fn blocked() -> Self {
+        Self {
+            responses: RedisResponseInner::Blocked,
+        }
+    }
+    pub fn is_blocked(&self) -> bool {
+        matches!(self.responses, RedisResponseInner::Blocked)
+    }
     pub fn is_quit(&self) -> bool {
         match self.responses {
             RedisResponseInner::Quit => true,
@@ -81,6 +87,14 @@ impl RedisResponse {
         }
     }
 
+    /// The error replied, if the command failed
+    pub fn error_replied(&self) -> Option<&RedisCommandError> {
+        match &self.responses {
+            RedisResponseInner::Error(err) => Some(err),
+            _ => None,
+        }
+    }
+
     pub fn single(response: RedisResponseType) -> Self {
         Self {
             responses: RedisResponseInner::Single(response),
@@ -99,24 +113,22 @@ impl RedisResponse {
         }
     }
 
-    pub fn reply(self) -> Vec<u8> {
+    pub fn write_to(self, writer: &mut RespWriter) {
         use RedisResponseInner::*;
         match self.responses {
-            Okay | Quit => OK.to_vec(),
-            Error(e) => e.to_vec(),
-            Pong => PONG.to_vec(),
-            Single(single) => single.get_formatted(),
-            Array(responses) => {
-                let mut reply = Vec::<u8>::with_capacity(512);
-                reply.push(b'*');
-                reply.put_slice(&responses.len().to_string().as_bytes().to_vec());
-                reply.put_slice(b"\r\n");
-                for response in responses {
-                    let mut response = response.get_formatted();
-                    reply.append(&mut response);
-                }
-                reply
-            }
+            Okay | Quit => writer.raw(OK),
+            Error(e) => writer.raw(&e.to_vec()),
+            Pong => writer.raw(PONG),
+            Single(single) => single.write_to(writer),
+            Array(responses) => write_array(responses, writer),
+            Blocked => {}
         }
     }
 }
+
+fn write_array(responses: Vec<RedisResponseType>, writer: &mut RespWriter) {
+    writer.array_len(responses.len());
+    for response in responses {
+        response.write_to(writer);
+    }
+}
@@ -1,5 +1,9 @@
 use super::*;
-use crate::protocol::{error::RedisErrorType, parser::RedisProtocolParser};
+use crate::protocol::{
+    error::RedisErrorType,
+    parser::{ProtocolLimits, RedisProtocolParser},
+};
+use proptest::prelude::*;
 
 #[test]
 pub fn test_simple_string() -> std::result::Result<(), RedisError> {
@@ -101,3 +105,171 @@ pub fn test_array_of_arrays() -> std::result::Result<(), RedisError> {
     assert!(left.is_empty());
     Ok(())
 }
+
+#[test]
+pub fn test_frame_len() -> std::result::Result<(), RedisError> {
+    let request = b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n";
+    assert_eq!(
+        RedisProtocolParser::frame_len(request)?,
+        Some(request.len())
+    );
+
+    // every prefix of a request is incomplete, not an error
+    for len in 0..request.len() {
+        assert_eq!(RedisProtocolParser::frame_len(&request[..len])?, None);
+    }
+
+    // pipelined requests are framed one at a time
+    let pipeline = b"*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPING\r\n";
+    assert_eq!(RedisProtocolParser::frame_len(pipeline)?, Some(14));
+    assert_eq!(RedisProtocolParser::frame_len(b"$-1\r\n+OK")?, Some(5));
+
+    let err = RedisProtocolParser::frame_len(b"$3\r\nfoobar\r\n").unwrap_err();
+    assert!(matches!(err.err_type, RedisErrorType::IncorrectFormat));
+    let err = RedisProtocolParser::frame_len(b"GET mykey\r\n").unwrap_err();
+    assert!(matches!(err.err_type, RedisErrorType::UnknownSymbol));
+    Ok(())
+}
+
+#[test]
+pub fn test_malformed_input_does_not_panic() {
+    let inputs: &[&[u8]] = &[
+        b"$3\r\nfo",
+        b"$18446744073709551615\r\n",
+        b"*18446744073709551615\r\n",
+        b"*4294967295\r\n$1\r\na\r\n",
+        b"$-5\r\n",
+        b"*-1\r\n",
+    ];
+    for input in inputs {
+        let _ = RedisProtocolParser::parse(input);
+        let _ = RedisProtocolParser::frame_len(input);
+    }
+
+    let nested = b"*1\r\n".repeat(100_000);
+    let err = RedisProtocolParser::parse(&nested).unwrap_err();
+    assert!(matches!(err.err_type, RedisErrorType::TooDeep));
+    let err = RedisProtocolParser::frame_len(&nested).unwrap_err();
+    assert!(matches!(err.err_type, RedisErrorType::TooDeep));
+
+    let endless_line = vec![b'1'; 100_000];
+    let err = RedisProtocolParser::frame_len(&[b"*", &endless_line[..]].concat()).unwrap_err();
+    assert!(matches!(err.err_type, RedisErrorType::LineTooLong));
+}
+
+#[test]
+pub fn test_protocol_limits() {
+    let limits = ProtocolLimits {
+        max_bulk_len: 3,
+        max_multibulk_len: 2,
+    };
+
+    // rejected on the length alone, without waiting for the announced bytes
+    let err = RedisProtocolParser::frame_len_with_limits(b"*1\r\n$4\r\n", &limits).unwrap_err();
+    assert!(matches!(err.err_type, RedisErrorType::BulkTooLong));
+    let err = RedisProtocolParser::frame_len_with_limits(b"*3\r\n", &limits).unwrap_err();
+    assert!(matches!(err.err_type, RedisErrorType::MultibulkTooLong));
+
+    let err = RedisProtocolParser::parse_with_limits(b"*1\r\n$4\r\nPING\r\n", &limits).unwrap_err();
+    assert_eq!(err.to_string(), "invalid bulk length");
+    let err = RedisProtocolParser::parse_with_limits(b"*3\r\n", &limits).unwrap_err();
+    assert_eq!(err.to_string(), "invalid multibulk length");
+
+    let request = b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n";
+    assert_eq!(
+        RedisProtocolParser::frame_len_with_limits(request, &limits).unwrap(),
+        Some(request.len())
+    );
+    assert!(RedisProtocolParser::parse_with_limits(request, &limits).is_ok());
+}
+
+fn encode_request(args: &[Vec<u8>]) -> Vec<u8> {
+    let mut request = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        request.extend(format!("${}\r\n", arg.len()).into_bytes());
+        request.extend(arg);
+        request.extend(b"\r\n");
+    }
+    request
+}
+
+proptest! {
+    #[test]
+    fn prop_any_input_is_handled(input in prop::collection::vec(any::<u8>(), 0..512)) {
+        let _ = RedisProtocolParser::parse(&input);
+        if let Ok(Some(len)) = RedisProtocolParser::frame_len(&input) {
+            prop_assert!(len <= input.len());
+        }
+    }
+
+    #[test]
+    fn prop_requests_are_framed_and_parsed(
+        args in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..64), 1..8),
+        garbage in prop::collection::vec(any::<u8>(), 0..64),
+    ) {
+        let request = encode_request(&args);
+
+        let (resp, left) = RedisProtocolParser::parse(&request).unwrap();
+        prop_assert!(left.is_empty());
+        let expected: Vec<Resp> = args.iter().map(|arg| Resp::BulkString(arg)).collect();
+        prop_assert_eq!(resp, Resp::Array(expected));
+
+        // whatever follows a request doesn't change where it ends
+        let pipelined = [&request[..], &garbage[..]].concat();
+        prop_assert_eq!(RedisProtocolParser::frame_len(&pipelined).unwrap(), Some(request.len()));
+
+        for len in 0..request.len() {
+            prop_assert_eq!(RedisProtocolParser::frame_len(&request[..len]).unwrap(), None);
+        }
+    }
+}
+
+#[test]
+fn test_resp_writer() {
+    use crate::protocol::writer::RespWriter;
+
+    let mut writer = RespWriter::default();
+    writer.array_len(8);
+    writer.simple_string(b"OK");
+    writer.bulk_string(b"hello");
+    writer.bulk_string(b"");
+    writer.integer(0);
+    writer.integer(i64::MIN);
+    writer.null();
+    writer.null_array();
+    writer.array_len(0);
+    writer.error("ERR oops");
+    assert_eq!(
+        writer.as_slice(),
+        &b"*8\r\n+OK\r\n$5\r\nhello\r\n$0\r\n\r\n:0\r\n:-9223372036854775808\r\n$-1\r\n*-1\r\n*0\r\n-ERR oops\r\n"[..]
+    );
+
+    writer.truncate(4);
+    writer.map_len(1);
+    writer.push_len(2);
+    writer.resp3_null();
+    assert_eq!(writer.as_slice(), &b"*8\r\n%1\r\n>2\r\n_\r\n"[..]);
+
+    // the buffer is kept for the next replies
+    let capacity = writer.capacity();
+    writer.clear();
+    assert!(writer.as_slice().is_empty());
+    assert_eq!(writer.capacity(), capacity);
+}
+
+proptest! {
+    #[test]
+    fn written_replies_parse_back(value in prop::collection::vec(any::<u8>(), 0..64), n in any::<i64>()) {
+        use crate::protocol::writer::RespWriter;
+
+        let mut writer = RespWriter::default();
+        writer.array_len(2);
+        writer.bulk_string(&value);
+        writer.integer(n);
+
+        let (resp, left) = RedisProtocolParser::parse(writer.as_slice()).unwrap();
+        let n = n.to_string();
+        prop_assert_eq!(resp, Resp::Array(vec![Resp::BulkString(&value), Resp::Integer(n.as_bytes())]));
+        prop_assert!(left.is_empty());
+    }
+}
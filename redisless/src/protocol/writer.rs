@@ -0,0 +1,119 @@
+use prost::bytes::{BufMut, BytesMut};
+
+use super::{CR, LF, NIL, NIL_ARRAY, RESP3_NULL};
+
+const CRLF: [u8; 2] = [CR, LF];
+
+/// Serializes replies straight into a buffer meant to be reused across commands, so
+/// answering a command doesn't allocate once the buffer has grown to fit the replies.
+#[derive(Debug, Default)]
+pub struct RespWriter {
+    buf: BytesMut,
+}
+
+impl RespWriter {
+    /// `+<s>\r\n`, `s` must not contain `\r` or `\n`
+    pub fn simple_string(&mut self, s: &[u8]) {
+        debug_assert!(!s.contains(&CR) && !s.contains(&LF));
+        self.buf.put_u8(b'+');
+        self.buf.put_slice(s);
+        self.buf.put_slice(&CRLF);
+    }
+
+    /// `-<message>\r\n`, `message` starting with the error code, e.g. `ERR`
+    pub fn error(&mut self, message: &str) {
+        self.buf.put_u8(b'-');
+        self.buf.put_slice(message.as_bytes());
+        self.buf.put_slice(&CRLF);
+    }
+
+    pub fn integer(&mut self, n: i64) {
+        self.header(b':', n);
+    }
+
+    pub fn bulk_string(&mut self, s: &[u8]) {
+        self.header(b'$', s.len() as i64);
+        self.buf.put_slice(s);
+        self.buf.put_slice(&CRLF);
+    }
+
+    /// The null bulk string, `$-1\r\n`
+    pub fn null(&mut self) {
+        self.buf.put_slice(NIL);
+    }
+
+    /// The null array, `*-1\r\n`, some clients only accept where an array is expected
+    pub fn null_array(&mut self) {
+        self.buf.put_slice(NIL_ARRAY);
+    }
+
+    /// Header of an array of `len` elements, to be written next
+    pub fn array_len(&mut self, len: usize) {
+        self.header(b'*', len as i64);
+    }
+
+    /// Header of a RESP3 map of `len` key/value pairs, to be written next
+    pub fn map_len(&mut self, len: usize) {
+        self.header(b'%', len as i64);
+    }
+
+    /// Header of a RESP3 push message of `len` elements, to be written next
+    pub fn push_len(&mut self, len: usize) {
+        self.header(b'>', len as i64);
+    }
+
+    /// The RESP3 null, `_\r\n`
+    pub fn resp3_null(&mut self) {
+        self.buf.put_slice(RESP3_NULL);
+    }
+
+    /// Replies already serialized, e.g. received from another server
+    pub fn raw(&mut self, bytes: &[u8]) {
+        self.buf.put_slice(bytes);
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Drop what was written past `len`
+    pub fn truncate(&mut self, len: usize) {
+        self.buf.truncate(len);
+    }
+
+    /// Forget what was written but keep the memory for the next replies
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// `<symbol><n>\r\n`
+    fn header(&mut self, symbol: u8, n: i64) {
+        // enough for `i64::MIN`
+        let mut digits = [0u8; 20];
+        let mut pos = digits.len();
+        let mut rest = n.unsigned_abs();
+        loop {
+            pos -= 1;
+            digits[pos] = b'0' + (rest % 10) as u8;
+            rest /= 10;
+            if rest == 0 {
+                break;
+            }
+        }
+
+        self.buf.put_u8(symbol);
+        if n < 0 {
+            self.buf.put_u8(b'-');
+        }
+        self.buf.put_slice(&digits[pos..]);
+        self.buf.put_slice(&CRLF);
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crossbeam_channel::{Receiver, Sender};
+
+/// Change of the address of a peer in a `PeerDirectory`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerUpdate {
+    /// The peer is now reached at this address, in place of any previous one
+    Addressed(String, SocketAddr),
+    /// The peer left the group, its messages have nowhere to go anymore
+    Removed(String),
+}
+
+/// Address of each peer of a node by Raft node ID, kept up to date as peers are discovered,
+/// join or leave the group, and read by the transport to deliver `MessageDestination::To`
+/// messages. Clones share the same addresses.
+#[derive(Debug, Clone, Default)]
+pub struct PeerDirectory {
+    shared: Arc<Mutex<Directory>>,
+}
+
+#[derive(Debug, Default)]
+struct Directory {
+    addrs: BTreeMap<String, SocketAddr>,
+    watchers: Vec<Sender<PeerUpdate>>,
+}
+
+impl PeerDirectory {
+    pub fn new() -> Self {
+        PeerDirectory::default()
+    }
+
+    /// Reach `node_id` at `addr` from now on
+    pub fn insert<T: Into<String>>(&self, node_id: T, addr: SocketAddr) {
+        let node_id = node_id.into();
+        let mut directory = self.lock();
+        if directory.addrs.insert(node_id.clone(), addr) != Some(addr) {
+            directory.notify(PeerUpdate::Addressed(node_id, addr));
+        }
+    }
+
+    pub fn remove(&self, node_id: &str) {
+        let mut directory = self.lock();
+        if directory.addrs.remove(node_id).is_some() {
+            directory.notify(PeerUpdate::Removed(node_id.to_string()));
+        }
+    }
+
+    pub fn get(&self, node_id: &str) -> Option<SocketAddr> {
+        self.lock().addrs.get(node_id).copied()
+    }
+
+    /// Every peer with its address
+    pub fn peers(&self) -> BTreeMap<String, SocketAddr> {
+        self.lock().addrs.clone()
+    }
+
+    /// Changes from now on, until the receiver is dropped
+    pub fn watch(&self) -> Receiver<PeerUpdate> {
+        let (send, recv) = crossbeam_channel::unbounded();
+        self.lock().watchers.push(send);
+        recv
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Directory> {
+        self.shared
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl From<BTreeMap<String, SocketAddr>> for PeerDirectory {
+    fn from(addrs: BTreeMap<String, SocketAddr>) -> Self {
+        PeerDirectory {
+            shared: Arc::new(Mutex::new(Directory {
+                addrs,
+                watchers: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl Directory {
+    /// Send `update` to every watcher still around, forgetting the dropped ones
+    fn notify(&mut self, update: PeerUpdate) {
+        self.watchers
+            .retain(|watcher| watcher.send(update.clone()).is_ok());
+    }
+}
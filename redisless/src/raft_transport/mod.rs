@@ -0,0 +1,98 @@
+//! Delivery of the Raft messages of a cluster node to its peers. Messages are framed as a 4 bytes
+//! big endian length followed by a protobuf encoded `MessageBatch`, the messages queued for a peer
+//! being written together to save on syscalls and packets. The receiving side answers each frame
+//! with the sequence number of its last message as 8 bytes big endian, once handed over.
+
+pub mod directory;
+pub mod tcp;
+#[cfg(test)]
+mod tests;
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::Duration;
+
+use prost::Message as _;
+use raft::message::{Message, SendableMessage};
+
+/// Frames longer than this are rejected rather than allocated
+const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// A Raft message along with the id of the node which sent it
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct NetworkMessage {
+    #[prost(string, required, tag = "1")]
+    pub from: String,
+    #[prost(message, required, tag = "2")]
+    pub message: Message,
+    /// Position of the message in what the sending node sent to the receiving one since it
+    /// started, from 1, to be acknowledged back. 0 for a message not to acknowledge.
+    #[prost(uint64, tag = "3")]
+    pub seq: u64,
+}
+
+/// Messages written to a peer in one frame, in the order they were sent
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MessageBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub messages: Vec<NetworkMessage>,
+}
+
+impl MessageBatch {
+    /// Sequence number to acknowledge once the messages are handed over, 0 for none
+    pub fn last_seq(&self) -> u64 {
+        self.messages
+            .iter()
+            .map(|message| message.seq)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Carries the messages returned by a `raft::node::Node` to its peers, and the messages of the
+/// peers back to it
+pub trait Transport {
+    /// Queue `message` for its destination without blocking. A message to a peer which can't be
+    /// reached yet is kept until it can be.
+    fn send(&self, message: SendableMessage<String>);
+
+    /// The next message received from a peer, waiting `timeout` at most
+    fn recv_timeout(&self, timeout: Duration) -> Option<NetworkMessage>;
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, batch: &MessageBatch) -> io::Result<()> {
+    let len = batch.encoded_len();
+    let mut frame = Vec::with_capacity(4 + len);
+    frame.extend_from_slice(&(len as u32).to_be_bytes());
+    // only fails when out of capacity
+    batch
+        .encode(&mut frame)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+    writer.write_all(&frame)
+}
+
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<MessageBatch> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("frame of {} bytes is too long", len),
+        ));
+    }
+
+    let mut data = vec![0; len];
+    reader.read_exact(&mut data)?;
+    MessageBatch::decode(&data[..]).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Acknowledge the message `seq`, and every one sent before it
+pub fn write_ack<W: Write>(writer: &mut W, seq: u64) -> io::Result<()> {
+    writer.write_all(&seq.to_be_bytes())
+}
+
+pub fn read_ack<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut seq = [0; 8];
+    reader.read_exact(&mut seq)?;
+    Ok(u64::from_be_bytes(seq))
+}
@@ -0,0 +1,500 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufReader, ErrorKind};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{select, unbounded, Receiver, RecvTimeoutError, Sender};
+use prost::Message as _;
+use raft::message::{Message, MessageDestination, SendableMessage};
+
+use super::directory::{PeerDirectory, PeerUpdate};
+use super::{
+    read_ack, read_frame, write_ack, write_frame, MessageBatch, NetworkMessage, Transport,
+};
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const MIN_RECONNECT_BACKOFF: Duration = Duration::from_millis(50);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(5);
+/// A connection over which messages were sent without being acknowledged for this long is
+/// opened again, and the messages sent again
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long the thread sending to a peer sleeps at most when it has nothing to do
+const MAX_IDLE_WAIT: Duration = Duration::from_secs(1);
+/// Messages kept for a peer until it acknowledges them, the oldest are dropped past this
+const MAX_UNACKNOWLEDGED_MESSAGES: usize = 16 * 1024;
+/// Messages queued for a peer are written together in frames of up to about this many bytes
+const MAX_BATCH_BYTES: usize = 1024 * 1024;
+
+type Connections = Arc<Mutex<HashMap<u64, TcpStream>>>;
+type Peers = Arc<Mutex<HashMap<String, Sender<Outgoing>>>>;
+
+/// What the thread sending to a peer is handed
+enum Outgoing {
+    Message(Message),
+    /// The address of the peer changed in the directory
+    Readdressed,
+}
+
+/// `Transport` over a TCP connection to each peer, opened by the sending side to the address the
+/// `PeerDirectory` has for it. A thread per peer writes its messages, those queued meanwhile
+/// coalesced into a single frame, and keeps them until the peer acknowledges them, sending them again over a new connection when the previous one broke or
+/// stalled; reconnections are spaced with an exponential backoff. Messages can thus be received
+/// more than once, which Raft copes with. Threads stop when the transport is dropped.
+pub struct TcpTransport {
+    node_id: String,
+    local_addr: SocketAddr,
+    directory: PeerDirectory,
+    peers: Peers,
+    incoming: Receiver<NetworkMessage>,
+    // accepted from peers, shut down on drop to unblock their reading threads
+    connections: Connections,
+    stopped: Arc<AtomicBool>,
+}
+
+impl TcpTransport {
+    /// Listen for the messages of peers on `addr`, and send messages as `node_id`
+    pub fn bind<T: Into<String>>(node_id: T, addr: SocketAddr) -> io::Result<TcpTransport> {
+        TcpTransport::bind_with_directory(node_id, addr, PeerDirectory::new())
+    }
+
+    /// Like `bind`, sending to the peers at the addresses `directory` has for them as it's
+    /// updated
+    pub fn bind_with_directory<T: Into<String>>(
+        node_id: T,
+        addr: SocketAddr,
+        directory: PeerDirectory,
+    ) -> io::Result<TcpTransport> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let (incoming_send, incoming) = unbounded();
+        let connections = Connections::default();
+        let peers = Peers::default();
+        let stopped = Arc::new(AtomicBool::new(false));
+        {
+            let connections = connections.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || accept_peers(listener, incoming_send, connections, stopped));
+        }
+        {
+            let updates = directory.watch();
+            let peers = peers.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || follow_directory(updates, peers, stopped));
+        }
+
+        Ok(TcpTransport {
+            node_id: node_id.into(),
+            local_addr,
+            directory,
+            peers,
+            incoming,
+            connections,
+            stopped,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Addresses the messages are sent to
+    pub fn directory(&self) -> &PeerDirectory {
+        &self.directory
+    }
+
+    /// Send the messages to the peer `node_id` to `addr`, in place of its previous address
+    pub fn add_peer<T: Into<String>>(&self, node_id: T, addr: SocketAddr) {
+        self.directory.insert(node_id, addr);
+    }
+
+    pub fn remove_peer(&self, node_id: &str) {
+        self.directory.remove(node_id);
+    }
+
+    /// Queue `message` for the thread sending to `node_id`, started on its first message
+    fn queue(
+        &self,
+        peers: &mut HashMap<String, Sender<Outgoing>>,
+        node_id: String,
+        message: Message,
+    ) {
+        let peer = peers.entry(node_id).or_insert_with_key(|node_id| {
+            let (send, recv) = unbounded();
+            let from = self.node_id.clone();
+            let node_id = node_id.clone();
+            let directory = self.directory.clone();
+            thread::spawn(move || send_to_peer(from, node_id, directory, recv));
+            send
+        });
+        let _ = peer.send(Outgoing::Message(message));
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&self, message: SendableMessage<String>) {
+        let mut peers = lock(&self.peers);
+        match message.dest {
+            MessageDestination::Broadcast => {
+                for node_id in self.directory.peers().into_keys() {
+                    if node_id != self.node_id {
+                        self.queue(&mut peers, node_id, message.message.clone());
+                    }
+                }
+            }
+            // kept until the peer has an address, it may not be known yet
+            MessageDestination::To(node_id) => self.queue(&mut peers, node_id, message.message),
+        }
+    }
+
+    fn recv_timeout(&self, timeout: Duration) -> Option<NetworkMessage> {
+        self.incoming.recv_timeout(timeout).ok()
+    }
+}
+
+impl Drop for TcpTransport {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        for connection in lock(&self.connections).values() {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn accept_peers(
+    listener: TcpListener,
+    incoming: Sender<NetworkMessage>,
+    connections: Connections,
+    stopped: Arc<AtomicBool>,
+) {
+    let mut next_connection_id = 0u64;
+
+    while !stopped.load(Ordering::SeqCst) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_INTERVAL);
+                continue;
+            }
+            Err(err) => {
+                log::error!("raft transport stopped accepting peers: {}", err);
+                return;
+            }
+        };
+
+        // accepted streams may inherit the non-blocking mode of the listener
+        let stream = match stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.try_clone())
+        {
+            Ok(clone) => {
+                lock(&connections).insert(next_connection_id, clone);
+                stream
+            }
+            Err(err) => {
+                log::warn!("dropped raft peer connection: {}", err);
+                continue;
+            }
+        };
+
+        let connection_id = next_connection_id;
+        next_connection_id = next_connection_id.wrapping_add(1);
+        let incoming = incoming.clone();
+        let connections = connections.clone();
+        thread::spawn(move || {
+            receive_from_peer(stream, incoming);
+            lock(&connections).remove(&connection_id);
+        });
+    }
+}
+
+/// Hand over the messages read from `stream`, acknowledging them, until it is closed
+fn receive_from_peer(stream: TcpStream, incoming: Sender<NetworkMessage>) {
+    let addr = stream.peer_addr().ok();
+    let mut reader = BufReader::new(stream);
+
+    loop {
+        let result = read_frame(&mut reader).and_then(|batch| {
+            let seq = batch.last_seq();
+            for message in batch.messages {
+                if incoming.send(message).is_err() {
+                    return Err(io::Error::other("transport dropped"));
+                }
+            }
+            match seq {
+                0 => Ok(()),
+                seq => write_ack(reader.get_mut(), seq),
+            }
+        });
+
+        if let Err(err) = result {
+            if err.kind() != ErrorKind::UnexpectedEof {
+                log::info!("raft peer connection from {:?} closed: {}", addr, err);
+            }
+            return;
+        }
+    }
+}
+
+/// Hand the changes of the directory over to the threads sending to the peers, and stop those
+/// of the peers removed, until the transport is dropped
+fn follow_directory(updates: Receiver<PeerUpdate>, peers: Peers, stopped: Arc<AtomicBool>) {
+    while !stopped.load(Ordering::SeqCst) {
+        match updates.recv_timeout(ACCEPT_INTERVAL) {
+            Ok(PeerUpdate::Addressed(node_id, _)) => {
+                if let Some(peer) = lock(&peers).get(&node_id) {
+                    let _ = peer.send(Outgoing::Readdressed);
+                }
+            }
+            // the thread ends with its channel, dropping what was kept for the peer
+            Ok(PeerUpdate::Removed(node_id)) => {
+                lock(&peers).remove(&node_id);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// What the thread reading the acknowledgements of a connection reports, along with the
+/// generation of the connection
+enum Delivery {
+    Acked(u64),
+    Closed,
+}
+
+/// Connection to a peer, shut down when dropped to stop the thread reading its acknowledgements
+struct PeerConnection {
+    stream: TcpStream,
+    generation: u64,
+    // when the peer last acknowledged a message, or was sent one after acknowledging them all
+    waiting_since: Instant,
+}
+
+impl Drop for PeerConnection {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Messages to a peer, from the first it didn't acknowledge yet
+struct Outbound {
+    // by sequence number
+    unacked: VecDeque<(u64, Message)>,
+    // how many of `unacked` were written on the current connection
+    written: usize,
+    next_seq: u64,
+}
+
+impl Outbound {
+    fn push(&mut self, message: Message, node_id: &str) {
+        if self.unacked.len() >= MAX_UNACKNOWLEDGED_MESSAGES {
+            self.unacked.pop_front();
+            self.written = self.written.saturating_sub(1);
+            log::warn!("dropped raft message to unreachable peer {}", node_id);
+        }
+        self.unacked.push_back((self.next_seq, message));
+        self.next_seq += 1;
+    }
+
+    /// Forget the messages up to `seq`, they were handed over
+    fn ack(&mut self, seq: u64) {
+        while self.unacked.front().is_some_and(|(next, _)| *next <= seq) {
+            self.unacked.pop_front();
+            self.written = self.written.saturating_sub(1);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.unacked.is_empty()
+    }
+
+    fn has_unwritten(&self) -> bool {
+        self.written < self.unacked.len()
+    }
+
+    /// The next messages to write as sent by `from`, up to `MAX_BATCH_BYTES` unless the first
+    /// is longer
+    fn next_batch(&self, from: &str) -> MessageBatch {
+        let mut batch = MessageBatch::default();
+        let mut batch_len = 0;
+        for (seq, message) in self.unacked.iter().skip(self.written) {
+            let message = NetworkMessage {
+                from: from.to_string(),
+                message: message.clone(),
+                seq: *seq,
+            };
+            batch_len += message.encoded_len();
+            if !batch.messages.is_empty() && batch_len > MAX_BATCH_BYTES {
+                break;
+            }
+            batch.messages.push(message);
+        }
+        batch
+    }
+}
+
+/// Write the messages to the peer `node_id` until its channel is dropped, connecting to the
+/// address `directory` has for it when needed. Messages are kept until acknowledged, while the
+/// peer has no address or can't be reached included.
+fn send_to_peer(
+    from: String,
+    node_id: String,
+    directory: PeerDirectory,
+    queue: Receiver<Outgoing>,
+) {
+    let (delivery_send, deliveries) = unbounded();
+    let mut outbound = Outbound {
+        unacked: VecDeque::new(),
+        written: 0,
+        next_seq: 1,
+    };
+    let mut connection: Option<PeerConnection> = None;
+    let mut generation = 0;
+    let mut backoff = MIN_RECONNECT_BACKOFF;
+    let mut next_attempt = Instant::now();
+
+    loop {
+        // wait for messages and acknowledgements, or for what has to be done next while some
+        // messages aren't acknowledged
+        let wait = match &connection {
+            _ if outbound.is_empty() => MAX_IDLE_WAIT,
+            None => next_attempt.saturating_duration_since(Instant::now()),
+            Some(_) if outbound.has_unwritten() => Duration::from_secs(0),
+            Some(connection) => {
+                (connection.waiting_since + ACK_TIMEOUT).saturating_duration_since(Instant::now())
+            }
+        };
+        let mut received = None;
+        select! {
+            recv(queue) -> outgoing => match outgoing {
+                Ok(outgoing) => received = Some(outgoing),
+                Err(_) => return,
+            },
+            recv(deliveries) -> delivery => match delivery {
+                Ok((of, delivery)) if connection.as_ref().is_some_and(|c| c.generation == of) => {
+                    match delivery {
+                        Delivery::Acked(seq) => {
+                            outbound.ack(seq);
+                            backoff = MIN_RECONNECT_BACKOFF;
+                            if let Some(connection) = connection.as_mut() {
+                                connection.waiting_since = Instant::now();
+                            }
+                        }
+                        Delivery::Closed => {
+                            log::info!("raft peer {} closed the connection", node_id);
+                            connection = None;
+                            outbound.written = 0;
+                        }
+                    }
+                }
+                // about a connection already replaced
+                _ => {}
+            },
+            default(wait) => {}
+        }
+        for outgoing in received.into_iter().chain(queue.try_iter()) {
+            match outgoing {
+                Outgoing::Message(message) => outbound.push(message, &node_id),
+                Outgoing::Readdressed => {
+                    // connect to the new address right away
+                    connection = None;
+                    outbound.written = 0;
+                    backoff = MIN_RECONNECT_BACKOFF;
+                    next_attempt = Instant::now();
+                }
+            }
+        }
+
+        if connection
+            .as_ref()
+            .is_some_and(|c| outbound.written > 0 && c.waiting_since.elapsed() >= ACK_TIMEOUT)
+        {
+            log::info!("raft peer {} stopped acknowledging messages", node_id);
+            connection = None;
+            outbound.written = 0;
+        }
+
+        if connection.is_none() && !outbound.is_empty() && Instant::now() >= next_attempt {
+            generation += 1;
+            connection = connect(&node_id, &directory, generation, &delivery_send);
+            // reset once the peer acknowledges a message
+            next_attempt = Instant::now() + backoff;
+            backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+        }
+
+        let open = match &mut connection {
+            Some(connection) => connection,
+            None => continue,
+        };
+        if outbound.written == 0 && outbound.has_unwritten() {
+            open.waiting_since = Instant::now();
+        }
+        while outbound.has_unwritten() {
+            let batch = outbound.next_batch(&from);
+            if let Err(err) = write_frame(&mut open.stream, &batch) {
+                log::info!("error sending to raft peer {}: {}", node_id, err);
+                connection = None;
+                outbound.written = 0;
+                break;
+            }
+            outbound.written += batch.messages.len();
+        }
+    }
+}
+
+/// Connect to the address `directory` has for `node_id`, with a thread reporting what the peer
+/// acknowledges over the connection to `deliveries`
+fn connect(
+    node_id: &str,
+    directory: &PeerDirectory,
+    generation: u64,
+    deliveries: &Sender<(u64, Delivery)>,
+) -> Option<PeerConnection> {
+    // until the directory has an address for it
+    let addr = directory.get(node_id)?;
+    let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .and_then(|stream| Ok((stream.try_clone()?, stream)));
+    let (ack_stream, stream) = match stream {
+        Ok(streams) => streams,
+        Err(err) => {
+            log::info!(
+                "error connecting to raft peer {} at {}: {}",
+                node_id,
+                addr,
+                err
+            );
+            return None;
+        }
+    };
+
+    let _ = stream.set_nodelay(true);
+    log::info!("connected to raft peer {} at {}", node_id, addr);
+    let deliveries = deliveries.clone();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(ack_stream);
+        while let Ok(seq) = read_ack(&mut reader) {
+            if deliveries.send((generation, Delivery::Acked(seq))).is_err() {
+                return;
+            }
+        }
+        let _ = deliveries.send((generation, Delivery::Closed));
+    });
+
+    Some(PeerConnection {
+        stream,
+        generation,
+        waiting_since: Instant::now(),
+    })
+}
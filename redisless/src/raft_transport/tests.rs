@@ -0,0 +1,285 @@
+use std::collections::BTreeSet;
+use std::io::{Cursor, ErrorKind};
+use std::net::{SocketAddr, TcpListener};
+use std::time::{Duration, Instant};
+
+use raft::log::memory::InMemoryLog;
+use raft::message::{Message, MessageDestination, Rpc, SendableMessage, TermId, VoteResponse};
+use raft::node::{Config, Node};
+use rand::rngs::OsRng;
+
+use super::directory::{PeerDirectory, PeerUpdate};
+use super::tcp::TcpTransport;
+use super::{
+    read_ack, read_frame, write_ack, write_frame, MessageBatch, NetworkMessage, Transport,
+};
+
+const CONFIG: Config = Config {
+    election_timeout_ticks: 10,
+    heartbeat_interval_ticks: 5,
+    replication_chunk_size: 65536,
+    check_quorum: false,
+    checksum_entries: false,
+};
+
+fn vote_response(term: u64) -> Message {
+    Message {
+        term: TermId { id: term },
+        rpc: Some(Rpc::VoteResponse(VoteResponse { vote_granted: true })),
+    }
+}
+
+fn send_to(transport: &TcpTransport, node_id: &str, message: Message) {
+    transport.send(SendableMessage {
+        message,
+        dest: MessageDestination::To(node_id.to_string()),
+    });
+}
+
+#[test]
+fn frames() {
+    let message = NetworkMessage {
+        from: "node-1".to_string(),
+        message: Message {
+            term: TermId { id: 3 },
+            rpc: Some(Rpc::VoteResponse(VoteResponse { vote_granted: true })),
+        },
+        seq: 1,
+    };
+    let batch = MessageBatch {
+        messages: vec![message.clone(), NetworkMessage { seq: 2, ..message }],
+    };
+    assert_eq!(batch.last_seq(), 2);
+    assert_eq!(MessageBatch::default().last_seq(), 0);
+
+    let mut bytes = Vec::new();
+    write_frame(&mut bytes, &batch).unwrap();
+    write_frame(&mut bytes, &batch).unwrap();
+    let mut reader = Cursor::new(bytes.clone());
+    assert_eq!(read_frame(&mut reader).unwrap(), batch);
+    assert_eq!(read_frame(&mut reader).unwrap(), batch);
+    assert_eq!(
+        read_frame(&mut reader).unwrap_err().kind(),
+        ErrorKind::UnexpectedEof
+    );
+
+    let truncated = &bytes[..bytes.len() / 2 - 1];
+    assert!(read_frame(&mut Cursor::new(truncated)).is_err());
+
+    let mut acks = Vec::new();
+    write_ack(&mut acks, 7).unwrap();
+    assert_eq!(read_ack(&mut Cursor::new(acks)).unwrap(), 7);
+
+    let too_long = u32::MAX.to_be_bytes();
+    assert_eq!(
+        read_frame(&mut Cursor::new(too_long)).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn raft_group_over_tcp() {
+    let localhost: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let ids = ["a", "b", "c"];
+    let transports: Vec<TcpTransport> = ids
+        .iter()
+        .map(|id| TcpTransport::bind(*id, localhost).unwrap())
+        .collect();
+    for transport in &transports {
+        for (id, peer) in ids.iter().zip(&transports) {
+            if peer.local_addr() != transport.local_addr() {
+                transport.add_peer(*id, peer.local_addr());
+            }
+        }
+    }
+
+    let peers: BTreeSet<String> = ids.iter().map(|id| id.to_string()).collect();
+    let mut nodes: Vec<_> = ids
+        .iter()
+        .map(|id| {
+            Node::new(
+                id.to_string(),
+                peers.clone(),
+                InMemoryLog::new_unbounded(),
+                OsRng,
+                CONFIG,
+            )
+        })
+        .collect();
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let mut appended = false;
+    let mut committed = vec![false; nodes.len()];
+
+    while committed.contains(&false) {
+        assert!(Instant::now() < deadline, "no entry committed over tcp");
+
+        for (node, transport) in nodes.iter_mut().zip(&transports) {
+            node.timer_tick()
+                .for_each(|message| transport.send(message));
+            while let Some(NetworkMessage { from, message, .. }) =
+                transport.recv_timeout(Duration::from_millis(5))
+            {
+                node.receive(message, from)
+                    .for_each(|message| transport.send(message));
+            }
+
+            if node.is_leader() && !appended {
+                if let Ok(messages) = node.append("entry") {
+                    messages.for_each(|message| transport.send(message));
+                    appended = true;
+                }
+            }
+        }
+
+        for (node, committed) in nodes.iter_mut().zip(committed.iter_mut()) {
+            *committed |= node
+                .take_committed()
+                .any(|entry| entry.data.as_ref() == b"entry");
+        }
+    }
+}
+
+#[test]
+fn peer_directory() {
+    let a: SocketAddr = "127.0.0.1:7001".parse().unwrap();
+    let moved: SocketAddr = "127.0.0.1:7002".parse().unwrap();
+    let directory = PeerDirectory::new();
+    let updates = directory.watch();
+
+    directory.insert("a", a);
+    // nothing changes
+    directory.insert("a", a);
+    directory.insert("a", moved);
+    directory.remove("a");
+    directory.remove("a");
+    assert_eq!(
+        updates.try_iter().collect::<Vec<_>>(),
+        vec![
+            PeerUpdate::Addressed("a".to_string(), a),
+            PeerUpdate::Addressed("a".to_string(), moved),
+            PeerUpdate::Removed("a".to_string()),
+        ]
+    );
+
+    // clones share the addresses
+    let clone = directory.clone();
+    clone.insert("b", a);
+    assert_eq!(directory.get("b"), Some(a));
+    assert_eq!(directory.get("a"), None);
+    assert_eq!(directory.peers().into_keys().collect::<Vec<_>>(), vec!["b"]);
+    drop(updates);
+    directory.insert("c", a);
+}
+
+#[test]
+fn messages_wait_for_the_address_of_their_peer() {
+    let localhost: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let sender = TcpTransport::bind("a", localhost).unwrap();
+    let receiver = TcpTransport::bind("b", localhost).unwrap();
+
+    send_to(&sender, "b", vote_response(1));
+    assert!(receiver.recv_timeout(Duration::from_millis(200)).is_none());
+
+    sender.directory().insert("b", receiver.local_addr());
+    let received = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(received.from, "a");
+    assert_eq!(received.message, vote_response(1));
+}
+
+#[test]
+fn messages_are_kept_while_the_peer_is_down() {
+    let localhost: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let directory = PeerDirectory::new();
+    let sender = TcpTransport::bind_with_directory("a", localhost, directory.clone()).unwrap();
+    let receiver = TcpTransport::bind("b", localhost).unwrap();
+    let addr = receiver.local_addr();
+    directory.insert("b", addr);
+    drop(receiver);
+
+    for term in 1..=3 {
+        send_to(&sender, "b", vote_response(term));
+    }
+    std::thread::sleep(Duration::from_millis(200));
+
+    // back on the same address
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let receiver = loop {
+        match TcpTransport::bind("b", addr) {
+            Ok(receiver) => break receiver,
+            Err(err) => assert!(Instant::now() < deadline, "{}", err),
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    for term in 1..=3 {
+        let received = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(received.message, vote_response(term));
+    }
+}
+
+#[test]
+fn unacknowledged_messages_are_sent_again() {
+    let localhost: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let peer = TcpListener::bind(localhost).unwrap();
+    let sender = TcpTransport::bind("a", localhost).unwrap();
+    sender.add_peer("b", peer.local_addr().unwrap());
+    let accept = || {
+        let (stream, _) = peer.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+    };
+
+    // the connection breaks before the message is acknowledged
+    send_to(&sender, "b", vote_response(1));
+    let mut stream = accept();
+    assert_eq!(read_frame(&mut stream).unwrap().last_seq(), 1);
+    drop(stream);
+
+    let mut stream = accept();
+    let received = read_frame(&mut stream).unwrap().messages.remove(0);
+    assert_eq!((received.seq, received.message), (1, vote_response(1)));
+    write_ack(&mut stream, 1).unwrap();
+    send_to(&sender, "b", vote_response(2));
+    let received = read_frame(&mut stream).unwrap().messages.remove(0);
+    assert_eq!((received.seq, received.message), (2, vote_response(2)));
+
+    // the peer stops acknowledging, only what it didn't acknowledge is sent again
+    let mut stream = accept();
+    let received = read_frame(&mut stream).unwrap().messages.remove(0);
+    assert_eq!((received.seq, received.message), (2, vote_response(2)));
+    write_ack(&mut stream, 2).unwrap();
+}
+
+#[test]
+fn queued_messages_are_coalesced_into_one_frame() {
+    let localhost: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let peer = TcpListener::bind(localhost).unwrap();
+    let sender = TcpTransport::bind("a", localhost).unwrap();
+
+    // queued until the peer has an address
+    for term in 1..=3 {
+        send_to(&sender, "b", vote_response(term));
+    }
+    std::thread::sleep(Duration::from_millis(100));
+    sender.add_peer("b", peer.local_addr().unwrap());
+
+    let (mut stream, _) = peer.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let batch = read_frame(&mut stream).unwrap();
+    let received: Vec<_> = batch
+        .messages
+        .into_iter()
+        .map(|received| (received.seq, received.message))
+        .collect();
+    assert_eq!(
+        received,
+        (1..=3)
+            .map(|term| (term, vote_response(term)))
+            .collect::<Vec<_>>()
+    );
+    write_ack(&mut stream, 3).unwrap();
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::command::table::AclCategory;
+use crate::command::Command;
+use crate::storage::models::RedisString;
+use crate::storage::scan::glob_match;
+
+/// Name of the user connections run as before they authenticate
+pub const DEFAULT_USER: &str = "default";
+
+/// User a client authenticates as with AUTH, with the commands it may run and the keys it may
+/// access, see `ServerConfig::users`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclUser {
+    name: String,
+    enabled: bool,
+    // any password is accepted, as with `nopass`
+    nopass: bool,
+    passwords: Vec<String>,
+    // the last rule matching a command decides whether it may run, none denies it
+    commands: Vec<(bool, CommandRule)>,
+    key_patterns: Vec<RedisString>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CommandRule {
+    Category(AclCategory),
+    /// Lowercase name of the command
+    Command(String),
+}
+
+impl AclUser {
+    /// User of a line of an ACL file of Redis, `user <name>` followed by its rules:
+    ///
+    /// - `on` and `off` enable and disable it
+    /// - `>password` adds a password, `nopass` accepts any and `resetpass` forgets them
+    /// - `~pattern` grants the keys matching the glob-style pattern, `allkeys` every key and
+    ///   `resetkeys` none
+    /// - `+command` and `-command` grant and deny a command, `+@category` and `-@category` the
+    ///   commands of `all`, `read`, `write`, `admin`, `dangerous` or `connection`, and
+    ///   `allcommands` and `nocommands` stand for `+@all` and `-@all`
+    ///
+    /// As in Redis, a user starts disabled, without password, command nor key, and the rules
+    /// apply in order: a command is granted when the last rule about it grants it.
+    pub fn parse(line: &str) -> Result<AclUser, String> {
+        let mut words = line.split_whitespace();
+        let name = match (words.next(), words.next()) {
+            (Some("user"), Some(name)) => name,
+            _ => return Err(format!("expected 'user <name> <rules>', not '{}'", line)),
+        };
+
+        let mut user = AclUser {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: Vec::new(),
+            commands: Vec::new(),
+            key_patterns: Vec::new(),
+        };
+        for rule in words {
+            user.apply(rule).map_err(|reason| {
+                format!("invalid rule '{}' for user {}: {}", rule, name, reason)
+            })?;
+        }
+        Ok(user)
+    }
+
+    fn apply(&mut self, rule: &str) -> Result<(), &'static str> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.key_patterns = vec![b"*".to_vec()],
+            "resetkeys" => self.key_patterns.clear(),
+            "allcommands" => self.commands = vec![(true, CommandRule::Category(AclCategory::All))],
+            "nocommands" => self.commands = vec![(false, CommandRule::Category(AclCategory::All))],
+            _ => return self.apply_argument(rule),
+        }
+        Ok(())
+    }
+
+    fn apply_argument(&mut self, rule: &str) -> Result<(), &'static str> {
+        let (kind, argument) = rule.split_at(rule.chars().next().map_or(0, char::len_utf8));
+        match kind {
+            ">" => {
+                self.nopass = false;
+                self.passwords.push(argument.to_string());
+            }
+            "~" => self.key_patterns.push(argument.as_bytes().to_vec()),
+            "+" | "-" => {
+                let argument = argument.to_lowercase();
+                let command = match argument.strip_prefix('@') {
+                    Some(category) => CommandRule::Category(
+                        AclCategory::from_name(category).ok_or("unknown category")?,
+                    ),
+                    None if argument.contains('|') => return Err("subcommands aren't supported"),
+                    None => CommandRule::Command(argument),
+                };
+                self.commands.push((kind == "+", command));
+            }
+            _ => return Err("unsupported rule"),
+        }
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether a client may authenticate as the user with `password`
+    pub fn accepts(&self, password: &[u8]) -> bool {
+        self.enabled
+            && (self.nopass
+                || self
+                    .passwords
+                    .iter()
+                    .any(|accepted| accepted.as_bytes() == password))
+    }
+
+    /// Whether connections run as the user without sending a password, for the default user
+    pub fn needs_no_password(&self) -> bool {
+        self.enabled && self.nopass
+    }
+
+    /// Whether the user may run `command`. Authenticating and leaving are always allowed.
+    pub fn can_run(&self, command: &Command) -> bool {
+        if matches!(
+            command,
+            Command::Auth(..) | Command::Hello(..) | Command::Reset | Command::Quit
+        ) {
+            return true;
+        }
+
+        let spec = command.spec();
+        self.commands
+            .iter()
+            .rev()
+            .find(|(_, rule)| match rule {
+                CommandRule::Category(category) => spec.is_in(*category),
+                CommandRule::Command(name) => name == spec.name,
+            })
+            .is_some_and(|(granted, _)| *granted)
+    }
+
+    /// Whether the user may access `key`, as sent by the client
+    pub fn can_access(&self, key: &[u8]) -> bool {
+        self.key_patterns
+            .iter()
+            .any(|pattern| glob_match(pattern, key))
+    }
+}
+
+/// Users clients may authenticate as, by name
+#[derive(Debug, Default)]
+pub struct Acl {
+    users: HashMap<String, Arc<AclUser>>,
+}
+
+impl Acl {
+    /// Users of `users`, the last of those sharing a name replacing the others
+    pub fn new(users: &[AclUser]) -> Self {
+        Acl {
+            users: users
+                .iter()
+                .map(|user| (user.name.clone(), Arc::new(user.clone())))
+                .collect(),
+        }
+    }
+
+    pub fn user(&self, name: &[u8]) -> Option<Arc<AclUser>> {
+        let name = std::str::from_utf8(name).ok()?;
+        self.users.get(name).cloned()
+    }
+
+    /// The user new connections run as, `None` when they may run anything
+    pub fn default_user(&self) -> Option<Arc<AclUser>> {
+        self.users.get(DEFAULT_USER).cloned()
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::storage::models::RedisString;
+
+/// Keys the connections blocked on BLPOP wait for, and which of those connections saw one of
+/// their keys written since, for the I/O loop to run their command again. Writes to other keys
+/// leave them waiting. Connections are known by the address of their client.
+#[derive(Debug, Default)]
+pub struct Blocking {
+    state: Mutex<BlockingState>,
+    // connections waiting, so that writes skip the lock while there are none
+    clients: AtomicUsize,
+    // connections with a key written since they started waiting, so that the I/O loop skips
+    // the lock while there are none
+    ready: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct BlockingState {
+    // whether a key each connection waits for was written since
+    clients: HashMap<SocketAddr, bool>,
+    waiters: HashMap<RedisString, HashSet<SocketAddr>>,
+}
+
+impl Blocking {
+    /// Whether any connection waits for a key
+    pub fn is_active(&self) -> bool {
+        self.clients.load(Ordering::SeqCst) > 0
+    }
+
+    /// Wait for one of `keys` to be written, in place of what `client` waited for before
+    pub fn wait(&self, client: SocketAddr, keys: &[RedisString]) {
+        let mut state = self.state();
+        self.forget(&mut state, client);
+        self.clients.fetch_add(1, Ordering::SeqCst);
+        state.clients.insert(client, false);
+        for key in keys {
+            state.waiters.entry(key.clone()).or_default().insert(client);
+        }
+    }
+
+    /// Stop waiting, once the command of `client` ran for good or its connection closed
+    pub fn stop(&self, client: SocketAddr) {
+        if !self.is_active() {
+            return;
+        }
+        let mut state = self.state();
+        self.forget(&mut state, client);
+    }
+
+    /// Wake the connections waiting for `keys`, just written
+    pub fn written(&self, keys: &[&RedisString]) {
+        if !self.is_active() || keys.is_empty() {
+            return;
+        }
+        let mut state = self.state();
+        let BlockingState { clients, waiters } = &mut *state;
+        for &key in keys {
+            for client in waiters.remove(key).unwrap_or_default() {
+                if let Some(ready) = clients.get_mut(&client) {
+                    if !*ready {
+                        *ready = true;
+                        self.ready.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a key `client` waits for was written since it started waiting
+    pub fn is_ready(&self, client: SocketAddr) -> bool {
+        if self.ready.load(Ordering::SeqCst) == 0 {
+            return false;
+        }
+        self.state().clients.get(&client).copied().unwrap_or(false)
+    }
+
+    fn forget(&self, state: &mut BlockingState, client: SocketAddr) {
+        let ready = match state.clients.remove(&client) {
+            Some(ready) => ready,
+            None => return,
+        };
+        self.clients.fetch_sub(1, Ordering::SeqCst);
+        if ready {
+            self.ready.fetch_sub(1, Ordering::SeqCst);
+        }
+        state.waiters.retain(|_, waiters| {
+            waiters.remove(&client);
+            !waiters.is_empty()
+        });
+    }
+
+    fn state(&self) -> MutexGuard<'_, BlockingState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use super::{bind_free_port, Server, ServerClusterOptions, ServerConfig};
+use crate::storage::models::{Expiry, RedisType};
+use crate::storage::Storage;
+
+/// `Server` put together one setting at a time, with keys seeded into its storage before it
+/// ever accepts a client, see `seed`
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use redisless::server::ServerBuilder;
+/// use redisless::storage::in_memory::InMemoryStorage;
+///
+/// let server = ServerBuilder::new(InMemoryStorage::new())
+///     .seed(|db| {
+///         db.set("k", "v")
+///             .hset("h", &[("f", "v")])
+///             .expire("k", Duration::from_secs(5));
+///     })
+///     .build()
+///     .unwrap();
+/// server.start();
+/// ```
+pub struct ServerBuilder<T: Storage> {
+    storage: T,
+    config: ServerConfig,
+    cluster_options: ServerClusterOptions,
+    listen: Listen,
+}
+
+enum Listen {
+    Port(u16),
+    FreePort(RangeInclusive<u16>),
+}
+
+impl<T: Storage + Send + 'static> ServerBuilder<T> {
+    /// Server with the default config, listening on a port the system picks
+    pub fn new(storage: T) -> Self {
+        ServerBuilder {
+            storage,
+            config: ServerConfig::default(),
+            cluster_options: ServerClusterOptions::default(),
+            listen: Listen::FreePort(0..=0),
+        }
+    }
+
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn cluster_options(mut self, cluster_options: ServerClusterOptions) -> Self {
+        self.cluster_options = cluster_options;
+        self
+    }
+
+    /// Listen on `port`, bound when the server starts as with `Server::new`
+    pub fn port(mut self, port: u16) -> Self {
+        self.listen = Listen::Port(port);
+        self
+    }
+
+    /// Listen on the first free port of `ports`, see `Server::new_on_free_port`
+    pub fn free_port(mut self, ports: RangeInclusive<u16>) -> Self {
+        self.listen = Listen::FreePort(ports);
+        self
+    }
+
+    /// Write keys into the storage right away, so that they're there for the very first
+    /// command of the first client. Seeds run in the order they're given.
+    pub fn seed<F: FnOnce(&mut Seed<'_, T>)>(mut self, seed: F) -> Self {
+        seed(&mut Seed {
+            storage: &mut self.storage,
+        });
+        self
+    }
+
+    /// The server, still to be started. Fails when no port of `free_port` is free.
+    pub fn build(self) -> io::Result<Server> {
+        let ServerBuilder {
+            storage,
+            config,
+            cluster_options,
+            listen,
+        } = self;
+        let (port, listener) = match listen {
+            Listen::Port(port) => (port, None),
+            Listen::FreePort(ports) => {
+                let listener = bind_free_port(ports)?;
+                (listener.local_addr()?.port(), Some(listener))
+            }
+        };
+        Ok(Server::with_listener(
+            storage,
+            config,
+            cluster_options,
+            port,
+            listener,
+        ))
+    }
+}
+
+/// Keys written by `ServerBuilder::seed`, each method chaining to the next. Like their Redis
+/// counterparts, they add to a key of the same type, but replace a key of another type
+/// instead of failing.
+pub struct Seed<'a, T: Storage> {
+    storage: &'a mut T,
+}
+
+impl<T: Storage> Seed<'_, T> {
+    pub fn set<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> &mut Self {
+        self.storage.write(key.as_ref(), value.as_ref());
+        self
+    }
+
+    pub fn hset<K, F, V>(&mut self, key: K, fields: &[(F, V)]) -> &mut Self
+    where
+        K: AsRef<[u8]>,
+        F: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let key = key.as_ref();
+        self.replace_other_type(key, RedisType::Hash);
+        let limits = self.storage.encoding_limits();
+        let fields = fields
+            .iter()
+            .map(|(field, value)| (field.as_ref().to_vec(), value.as_ref().to_vec()));
+        match self.storage.hread_mut(key) {
+            Some(hash) => fields.for_each(|(field, value)| hash.insert(field, value, &limits)),
+            None => self.storage.hwrite(key, fields.collect::<HashMap<_, _>>()),
+        }
+        self
+    }
+
+    pub fn rpush<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, values: &[V]) -> &mut Self {
+        let key = key.as_ref();
+        self.replace_other_type(key, RedisType::List);
+        let values = values.iter().map(|value| value.as_ref().to_vec()).collect();
+        self.storage.lpush_back(key, values);
+        self
+    }
+
+    pub fn sadd<K: AsRef<[u8]>, M: AsRef<[u8]>>(&mut self, key: K, members: &[M]) -> &mut Self {
+        let key = key.as_ref();
+        self.replace_other_type(key, RedisType::Set);
+        let limits = self.storage.encoding_limits();
+        let members = members.iter().map(|member| member.as_ref().to_vec());
+        match self.storage.sread_mut(key) {
+            Some(set) => members.for_each(|member| {
+                set.insert(member, &limits);
+            }),
+            None => self.storage.swrite(key, members.collect::<HashSet<_>>()),
+        }
+        self
+    }
+
+    /// Expire `key` in `ttl` from now, if it was seeded. A `ttl` too long to be held by an
+    /// expiry leaves the key persistent, which amounts to the same.
+    pub fn expire<K: AsRef<[u8]>>(&mut self, key: K, ttl: Duration) -> &mut Self {
+        let millis = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+        if let Ok(expiry) = Expiry::new_from_millis(millis) {
+            self.storage.expire(key.as_ref(), expiry);
+        }
+        self
+    }
+
+    fn replace_other_type(&mut self, key: &[u8], data_type: RedisType) {
+        let current = self.storage.type_of(key);
+        if current != b"none" && current != data_type.name().as_bytes() {
+            self.storage.remove(key);
+        }
+    }
+}
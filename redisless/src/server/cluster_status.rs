@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::time::Instant;
+
+use raft::log::Log;
+use raft::node::Node;
+use rand::RngCore;
+
+/// Health of the Raft group of a server as last seen by its node, see `Server::cluster_status`
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ClusterStatus {
+    /// Raft node ID of the server
+    pub node_id: String,
+    /// `None` while no leader is known
+    pub leader: Option<String>,
+    pub term: u64,
+    pub commit_index: u64,
+    /// Index of the last entry of the log of the server
+    pub last_index: u64,
+    pub peers: Vec<PeerStatus>,
+}
+
+/// Replication of one peer of a `ClusterStatus`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PeerStatus {
+    pub node_id: String,
+    /// Last entry known to be replicated to the peer, only tracked by the leader
+    pub match_index: Option<u64>,
+    /// Entries of the leader's log the peer has yet to acknowledge, only tracked by the leader
+    pub lag: Option<u64>,
+    /// Last time a message of the peer came in, `None` if none ever did
+    pub last_contact: Option<Instant>,
+}
+
+impl ClusterStatus {
+    /// Status of the Raft `node`, given when a message of each of its peers last came in, to be
+    /// set with `Server::set_cluster_status` as the node runs
+    pub fn of_raft_node<L, R, NodeId>(
+        node: &Node<L, R, NodeId>,
+        last_contact: &BTreeMap<NodeId, Instant>,
+    ) -> Self
+    where
+        L: Log,
+        R: RngCore,
+        NodeId: Ord + Clone + Display,
+    {
+        let metrics = node.metrics();
+        let last_index = node.log().last_index().id;
+        let peers = node
+            .peers()
+            .iter()
+            .chain(node.learners())
+            .filter(|peer| *peer != node.node_id())
+            .map(|peer| {
+                let match_index = node
+                    .replication_state(peer)
+                    .map(|replication| replication.match_idx.id);
+                PeerStatus {
+                    node_id: peer.to_string(),
+                    match_index,
+                    lag: match_index.map(|match_index| last_index.saturating_sub(match_index)),
+                    last_contact: last_contact.get(peer).copied(),
+                }
+            })
+            .collect();
+
+        ClusterStatus {
+            node_id: node.node_id().to_string(),
+            leader: node.leader().0.map(|leader| leader.to_string()),
+            term: metrics.current_term.id,
+            commit_index: metrics.commit_idx.id,
+            last_index,
+            peers,
+        }
+    }
+
+    /// Reply to `CLUSTER REDISLESS-STATUS`, in the `field:value` lines of `CLUSTER INFO` with a
+    /// line per peer as `INFO replication` has per replica. `now` dates the last contacts.
+    pub fn info(&self, now: Instant) -> String {
+        let mut info = format!(
+            "node_id:{}\r\nleader:{}\r\nterm:{}\r\ncommit_index:{}\r\nlast_index:{}\r\npeers:{}\r\n",
+            self.node_id,
+            self.leader.as_deref().unwrap_or(""),
+            self.term,
+            self.commit_index,
+            self.last_index,
+            self.peers.len(),
+        );
+        for (i, peer) in self.peers.iter().enumerate() {
+            info.push_str(&format!(
+                "peer{}:id={},match_index={},lag={},last_contact_ms={}\r\n",
+                i,
+                peer.node_id,
+                unknown_as_minus_one(peer.match_index),
+                unknown_as_minus_one(peer.lag),
+                unknown_as_minus_one(
+                    peer.last_contact
+                        .map(|at| now.saturating_duration_since(at).as_millis() as u64)
+                ),
+            ));
+        }
+        info
+    }
+}
+
+fn unknown_as_minus_one(value: Option<u64>) -> String {
+    value.map_or_else(|| "-1".to_string(), |value| value.to_string())
+}
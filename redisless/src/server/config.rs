@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use super::acl::AclUser;
+use super::memory::MemoryLimit;
+use super::rate_limit::RateLimit;
+use super::sentinel::DEFAULT_MASTER_NAME;
+use super::shadow::ShadowConfig;
+use super::tracking;
+use super::upstream::UpstreamConfig;
+use crate::protocol::parser::ProtocolLimits;
+use crate::storage::striped;
+
+/// Tuning applied to the RESP server and to every connection it accepts
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Close a connection when the client hasn't sent anything for this long (`None` never reaps it)
+    pub read_timeout: Option<Duration>,
+    /// Close a connection when the client doesn't read its replies for this long (`None` waits
+    /// forever). Other clients are served meanwhile.
+    pub write_timeout: Option<Duration>,
+    /// Idle time before TCP keepalive probes are sent (`None` disables keepalive)
+    pub tcp_keepalive: Option<Duration>,
+    /// Disable Nagle's algorithm so small replies are sent right away
+    pub tcp_nodelay: bool,
+    /// Connections above this limit are answered with an error and closed
+    pub maxclients: usize,
+    /// Threads executing commands, connections are all served by a single I/O thread
+    pub workers: usize,
+    /// Forward every command to an upstream Redis and log the replies that differ from ours
+    pub shadow: Option<ShadowConfig>,
+    /// Proxy commands to an upstream Redis, and serve them locally while it's unreachable
+    pub upstream: Option<UpstreamConfig>,
+    /// Events lasting at least this long are reported by LATENCY (`None` disables the monitor)
+    pub latency_monitor_threshold: Option<Duration>,
+    /// Commands running for this long are logged, and the commands sent meanwhile answered with
+    /// BUSY instead of waiting for the storage (`None` waits however long it takes)
+    pub busy_threshold: Option<Duration>,
+    /// Namespace of the keys seen by clients: prepended to the keys they send and stripped from
+    /// those sent back, FLUSHALL, FLUSHDB and DBSIZE only consider the keys starting with it.
+    /// Commands forwarded to an upstream Redis are sent as is.
+    pub key_prefix: Option<Vec<u8>>,
+    /// Name the server is reported under by the SENTINEL commands
+    pub sentinel_master_name: String,
+    /// Serve `/healthz` and `/metrics` (Prometheus text format) over HTTP on this address while
+    /// the server is started (`None` disables it)
+    pub http_addr: Option<SocketAddr>,
+    /// Commands to rename, mapped to the name clients must send instead, or to an empty name to
+    /// disable them. Commands sent by their original name are then unknown, as with the
+    /// `rename-command` directive of Redis.
+    pub rename_commands: HashMap<String, String>,
+    /// While listening on every interface, refuse the clients not connecting from the loopback
+    /// interface, as Redis does in protected mode. Only applies while the default user of
+    /// `users` needs no password.
+    pub protected_mode: bool,
+    /// Longest bulk string a client may send, as the `proto-max-bulk-len` directive of Redis.
+    /// Requests announcing more are answered with a protocol error and the connection closed.
+    pub proto_max_bulk_len: usize,
+    /// Most elements a request may have, checked like `proto_max_bulk_len`
+    pub proto_max_multibulk_len: usize,
+    /// Log the first call of each command RedisLess doesn't support, they are all counted by
+    /// `INFO errorstats` anyway
+    pub log_unsupported_commands: bool,
+    /// Answer every write with a READONLY error, as a read-only replica does, keeping the
+    /// dataset as it is. Also changed with `CONFIG SET read-only yes|no`.
+    pub read_only: bool,
+    /// Commands each connection may send per second (`None` doesn't limit them), to behave like
+    /// a throttled Redis provider
+    pub rate_limit: Option<RateLimit>,
+    /// Bytes of requests a connection may have received and not had answered yet, past which
+    /// it isn't read from until they are (`None` reads as long as a few requests are queued).
+    /// A single request larger than this is still read whole.
+    pub max_inflight_bytes: Option<usize>,
+    /// Keys the dataset may hold, expired ones not removed yet included. Writes that would add
+    /// more are answered with an OOM error, as Redis does past `maxmemory`, while those
+    /// removing keys still go through (`None` doesn't limit them).
+    pub max_keys: Option<u64>,
+    /// Bytes the values of the dataset may take, see `Storage::value_bytes`, past which the
+    /// writes growing it are refused as with `max_keys`. They are counted as keys are written
+    /// and removed, only the values changed since the last write are measured again.
+    pub max_value_bytes: Option<u64>,
+    /// Users clients authenticate as with AUTH, each limited to some commands and keys, as
+    /// those of an ACL file of Redis, see `AclUser::parse`. Connections run as the user named
+    /// `default` until they authenticate, and must do so first unless it's `nopass`. Without
+    /// such a user, they may run anything as before.
+    pub users: Vec<AclUser>,
+    /// Save every key to this file once the server stops, see `Server::save_snapshot`, and load
+    /// them back from it when it starts if the file is there, for a dataset prepared once to
+    /// be shared by test binaries without seeding it again. The server doesn't start when the
+    /// file can't be read back.
+    pub snapshot_on_stop: Option<PathBuf>,
+    /// Keys remembered for the connections with CLIENT TRACKING on, as the
+    /// `tracking-table-max-keys` directive of Redis. Past it, keys are invalidated for the
+    /// connections that read them and forgotten (0 doesn't limit them).
+    pub tracking_table_max_keys: usize,
+    /// Stripes the storage is split into, each locked on its own, so that commands on keys of
+    /// different stripes don't wait for each other, see `storage::striped::StripedStorage`. 1
+    /// locks the whole storage for every command.
+    pub storage_stripes: usize,
+}
+
+impl ServerConfig {
+    pub(crate) fn memory_limit(&self) -> MemoryLimit {
+        MemoryLimit {
+            max_keys: self.max_keys,
+            max_value_bytes: self.max_value_bytes,
+        }
+    }
+
+    pub(crate) fn protocol_limits(&self) -> ProtocolLimits {
+        ProtocolLimits {
+            max_bulk_len: self.proto_max_bulk_len,
+            max_multibulk_len: self.proto_max_multibulk_len,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            read_timeout: Some(Duration::from_secs(300)),
+            write_timeout: None,
+            tcp_keepalive: Some(Duration::from_secs(300)),
+            tcp_nodelay: true,
+            maxclients: 10_000,
+            workers: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4),
+            shadow: None,
+            upstream: None,
+            latency_monitor_threshold: None,
+            busy_threshold: None,
+            key_prefix: None,
+            sentinel_master_name: DEFAULT_MASTER_NAME.to_string(),
+            http_addr: None,
+            rename_commands: HashMap::new(),
+            protected_mode: true,
+            proto_max_bulk_len: ProtocolLimits::default().max_bulk_len,
+            proto_max_multibulk_len: ProtocolLimits::default().max_multibulk_len,
+            log_unsupported_commands: true,
+            read_only: false,
+            rate_limit: None,
+            max_inflight_bytes: None,
+            max_keys: None,
+            max_value_bytes: None,
+            users: Vec::new(),
+            snapshot_on_stop: None,
+            tracking_table_max_keys: tracking::DEFAULT_MAX_KEYS,
+            storage_stripes: striped::DEFAULT_STRIPES,
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use socket2::{SockRef, TcpKeepalive};
+
+use super::acl::AclUser;
+use super::blocking::Blocking;
+use super::config::ServerConfig;
+use super::context::{Blocked, ConnectionContext};
+use super::rate_limit::RateLimiter;
+use super::stats::{ClientPause, ConnectedClient};
+use super::tracking::Tracking;
+use super::worker::{ConnectionId, Job, JobResult, Requests};
+use crate::protocol::parser::{ProtocolLimits, RedisProtocolParser};
+use crate::protocol::writer::RespWriter;
+
+/// Requests buffered for a connection before the I/O loop stops reading from it
+const MAX_QUEUED_REQUESTS: usize = 1024;
+/// Pipelined requests handed to a worker at once
+const MAX_BATCHED_REQUESTS: usize = 128;
+/// Bytes read from the socket at once
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+/// Read, request and reply buffers grown past this are freed instead of being reused
+const MAX_KEPT_BUFFER_CAPACITY: usize = 1024 * 1024;
+
+/// A client connection owned by the I/O loop
+pub struct Connection {
+    stream: TcpStream,
+    addr: SocketAddr,
+    // counts as a connected client until the connection is dropped
+    _client: ConnectedClient,
+    // received bytes up to `filled`, the rest is room for the next reads
+    buffer: Vec<u8>,
+    filled: usize,
+    // bytes at the start of `buffer` forming the complete requests of `request_lens`
+    framed: usize,
+    protocol_limits: ProtocolLimits,
+    request_lens: VecDeque<usize>,
+    // bytes of the requests a worker is running, see `max_inflight_bytes`
+    running: usize,
+    max_inflight_bytes: Option<usize>,
+    // requests are copied in it to be handed to a worker, reused from one job to the next
+    spare_requests: Option<Requests>,
+    // travels with the requests, `None` while a worker is running them
+    context: Option<ConnectionContext>,
+    // replies are serialized in it, reused from one job to the next
+    writer: Option<RespWriter>,
+    // replies the client hasn't read all of yet, with how much of them was sent
+    unsent: Option<(RespWriter, usize)>,
+    // close once `unsent` is sent, QUIT was answered
+    quit: bool,
+    write_timeout: Option<Duration>,
+    last_write: Instant,
+    // the client won't send anything anymore
+    read_closed: bool,
+    closed: bool,
+    last_update: Instant,
+}
+
+impl Connection {
+    pub fn new(
+        stream: TcpStream,
+        client: ConnectedClient,
+        config: &ServerConfig,
+        default_user: Option<Arc<AclUser>>,
+    ) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(config.tcp_nodelay)?;
+
+        let socket = SockRef::from(&stream);
+        match config.tcp_keepalive {
+            Some(time) => socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?,
+            None => socket.set_keepalive(false)?,
+        }
+
+        let addr = stream.peer_addr()?;
+        Ok(Connection {
+            addr,
+            stream,
+            _client: client,
+            buffer: Vec::new(),
+            filled: 0,
+            framed: 0,
+            protocol_limits: config.protocol_limits(),
+            request_lens: VecDeque::new(),
+            running: 0,
+            max_inflight_bytes: config.max_inflight_bytes,
+            spare_requests: None,
+            context: Some(ConnectionContext {
+                client: Some(addr),
+                // the default user may have to authenticate with a password first
+                authenticated: default_user
+                    .as_ref()
+                    .is_none_or(|user| user.needs_no_password()),
+                user: default_user,
+                key_prefix: config.key_prefix.clone(),
+                protocol_limits: config.protocol_limits(),
+                rate_limiter: config.rate_limit.map(RateLimiter::new),
+                ..ConnectionContext::default()
+            }),
+            writer: None,
+            unsent: None,
+            quit: false,
+            write_timeout: config.write_timeout,
+            last_write: Instant::now(),
+            read_closed: false,
+            closed: false,
+            last_update: Instant::now(),
+        })
+    }
+
+    /// Read what the client sent without blocking, return true if anything was received
+    pub fn read(&mut self) -> bool {
+        if self.closed || self.read_closed || self.request_lens.len() >= MAX_QUEUED_REQUESTS {
+            return false;
+        }
+        let len = match self.max_inflight_bytes {
+            // a request is only cut short once something will be answered
+            Some(max) if self.running > 0 || !self.request_lens.is_empty() => {
+                let inflight = self.filled + self.running;
+                if inflight >= max {
+                    return false;
+                }
+                READ_BUFFER_SIZE.min(max - inflight)
+            }
+            _ => READ_BUFFER_SIZE,
+        };
+
+        // only the room never read into before needs zeroing
+        if self.buffer.len() < self.filled + len {
+            self.buffer.resize(self.filled + len, 0);
+        }
+        match self
+            .stream
+            .read(&mut self.buffer[self.filled..self.filled + len])
+        {
+            // the client closed its side of the connection, answer what it already sent
+            Ok(0) => {
+                self.read_closed = true;
+                false
+            }
+            Ok(len) => {
+                self.filled += len;
+                self.split_requests();
+                self.last_update = Instant::now();
+                true
+            }
+            Err(err)
+                if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::Interrupted =>
+            {
+                false
+            }
+            Err(_) => {
+                self.closed = true;
+                false
+            }
+        }
+    }
+
+    /// Frame every complete request received after those already framed
+    fn split_requests(&mut self) {
+        while self.framed < self.filled {
+            match RedisProtocolParser::frame_len_with_limits(
+                &self.buffer[self.framed..self.filled],
+                &self.protocol_limits,
+            ) {
+                Ok(Some(len)) => {
+                    self.request_lens.push_back(len);
+                    self.framed += len;
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    // nothing after this can be framed, let the worker answer the protocol
+                    // error then close the connection like Redis does
+                    self.request_lens.push_back(self.filled - self.framed);
+                    self.framed = self.filled;
+                    self.read_closed = true;
+                }
+            }
+        }
+    }
+
+    /// Drop the first `len` bytes of the read buffer, which were handed to a worker
+    fn consume(&mut self, len: usize) {
+        self.buffer.copy_within(len..self.filled, 0);
+        self.filled -= len;
+        self.framed -= len;
+
+        // keep the buffer for the next reads, unless a huge request made it grow
+        if self.filled == 0 && self.buffer.len() > MAX_KEPT_BUFFER_CAPACITY {
+            self.buffer = Vec::new();
+        }
+    }
+
+    /// Next pipelined requests to execute, as long as the previous ones have been answered
+    /// and their replies sent. While `pause` is on, they stop before the first it holds. A
+    /// blocked command only runs again once `blocking` has one of its keys written, or it timed
+    /// out. Those over a delaying rate limit wait for the next second.
+    pub fn next_job(
+        &mut self,
+        connection_id: ConnectionId,
+        pause: Option<&ClientPause>,
+        blocking: &Blocking,
+    ) -> Option<Job> {
+        if self.closed || self.request_lens.is_empty() || self.unsent.is_some() {
+            return None;
+        }
+        if self
+            .blocked()
+            .is_some_and(|blocked| !blocked.timed_out() && !blocking.is_ready(self.addr))
+        {
+            return None;
+        }
+        let mut context = self.context.take()?;
+        // a blocked command running again was already counted
+        let rerun = context.blocked.is_some() as usize;
+        let allowed = match context.rate_limiter.as_mut() {
+            Some(rate_limiter) if rate_limiter.delays() => {
+                rate_limiter.available() as usize + rerun
+            }
+            _ => MAX_BATCHED_REQUESTS,
+        };
+
+        let mut requests = self.spare_requests.take().unwrap_or_default();
+        let mut start = 0;
+        for &len in self
+            .request_lens
+            .iter()
+            .take(MAX_BATCHED_REQUESTS.min(allowed))
+        {
+            let request = &self.buffer[start..start + len];
+            if pause.is_some_and(|pause| pause.holds(request)) {
+                break;
+            }
+            requests.push(request);
+            start += len;
+        }
+        if requests.is_empty() {
+            self.context = Some(context);
+            self.spare_requests = Some(requests);
+            return None;
+        }
+
+        self.request_lens.drain(..requests.len());
+        self.consume(start);
+        self.running = start;
+        Some(Job {
+            connection_id,
+            requests,
+            context,
+            writer: self.writer.take().unwrap_or_default(),
+        })
+    }
+
+    /// Put back a job that couldn't be dispatched
+    pub fn requeue(&mut self, job: Job) {
+        let Job {
+            mut requests,
+            context,
+            writer,
+            ..
+        } = job;
+
+        self.unshift(&requests, requests.len());
+        self.running = 0;
+        requests.clear();
+        self.spare_requests = Some(requests);
+        self.context = Some(context);
+        self.writer = Some(writer);
+    }
+
+    /// Queue the last `count` of `requests` again, before those received since
+    fn unshift(&mut self, requests: &Requests, count: usize) {
+        let lens: Vec<usize> = requests
+            .iter()
+            .skip(requests.len() - count)
+            .map(<[u8]>::len)
+            .collect();
+        let bytes = requests.as_bytes();
+        let bytes = &bytes[bytes.len() - lens.iter().sum::<usize>()..];
+
+        self.buffer.splice(0..0, bytes.iter().copied());
+        self.filled += bytes.len();
+        self.framed += bytes.len();
+        for len in lens.into_iter().rev() {
+            self.request_lens.push_front(len);
+        }
+    }
+
+    pub fn write_reply(&mut self, result: JobResult) {
+        let JobResult {
+            mut requests,
+            writer,
+            quit,
+            unanswered,
+            context,
+            ..
+        } = result;
+        self.unshift(&requests, unanswered);
+        self.running = 0;
+        if requests.capacity() <= MAX_KEPT_BUFFER_CAPACITY {
+            requests.clear();
+            self.spare_requests = Some(requests);
+        }
+        self.context = Some(context);
+        self.quit = quit;
+        self.unsent = Some((writer, 0));
+        self.last_write = Instant::now();
+        self.write();
+    }
+
+    /// Queue the invalidation message of client tracking waiting for the connection after the
+    /// replies not sent yet, return true if there was one. It waits while a worker runs the
+    /// requests, to come after their replies.
+    pub fn push_invalidation(&mut self, tracking: &Tracking) -> bool {
+        if self.closed || self.context.is_none() {
+            return false;
+        }
+        let invalidation = match tracking.take(self.addr) {
+            Some(invalidation) => invalidation,
+            None => return false,
+        };
+
+        let writer = match &mut self.unsent {
+            Some((writer, _)) => writer,
+            unsent => {
+                self.last_write = Instant::now();
+                &mut unsent.insert((self.writer.take().unwrap_or_default(), 0)).0
+            }
+        };
+        invalidation.write_to(writer);
+        true
+    }
+
+    /// Send what the socket takes of the replies without blocking, return true if anything
+    /// was sent. The connection is closed when the client doesn't read them for longer than
+    /// `write_timeout`.
+    pub fn write(&mut self) -> bool {
+        let (writer, sent) = match self.unsent.as_mut() {
+            Some(unsent) if !self.closed => unsent,
+            _ => return false,
+        };
+
+        let before = *sent;
+        while *sent < writer.len() {
+            match self.stream.write(&writer.as_slice()[*sent..]) {
+                Ok(0) => {
+                    self.closed = true;
+                    return false;
+                }
+                Ok(len) => *sent += len,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => {
+                    self.closed = true;
+                    return false;
+                }
+            }
+        }
+        let progressed = *sent > before;
+        if progressed {
+            self.last_write = Instant::now();
+        }
+
+        if *sent < writer.len() {
+            if let Some(write_timeout) = self.write_timeout {
+                if self.last_write.elapsed() >= write_timeout {
+                    self.closed = true;
+                }
+            }
+            return progressed;
+        }
+
+        // keep the buffer for the next replies, unless a huge one made it grow
+        if let Some((mut writer, _)) = self.unsent.take() {
+            if writer.capacity() <= MAX_KEPT_BUFFER_CAPACITY {
+                writer.clear();
+                self.writer = Some(writer);
+            }
+        }
+        if self.quit {
+            self.closed = true;
+        }
+        progressed
+    }
+
+    /// Address of the client
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The blocking command the connection waits on, unless a worker is running it
+    pub fn blocked(&self) -> Option<&Blocked> {
+        self.context.as_ref()?.blocked.as_ref()
+    }
+
+    /// Closed by either side, or idle for longer than `read_timeout`
+    pub fn is_done(&self, read_timeout: Option<Duration>) -> bool {
+        if self.closed {
+            return true;
+        }
+
+        let answered =
+            self.context.is_some() && self.request_lens.is_empty() && self.unsent.is_none();
+        if self.read_closed {
+            // no one is left to answer once the wait is over
+            return answered || self.blocked().is_some();
+        }
+
+        // CLIENT NO-EVICT keeps idle connections open
+        let no_evict = self
+            .context
+            .as_ref()
+            .is_some_and(|context| context.no_evict);
+        match read_timeout {
+            Some(read_timeout) if !no_evict => {
+                answered && self.last_update.elapsed() >= read_timeout
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::acl::AclUser;
+use super::rate_limit::RateLimiter;
+use crate::command::command_error::RedisCommandError;
+use crate::command::Command;
+use crate::protocol::parser::ProtocolLimits;
+use crate::storage::models::RedisString;
+
+/// Databases a client can SELECT, the storage only holds one for now
+pub const DATABASES: usize = 1;
+
+/// State of a client connection, travelling with its requests to the worker running them
+#[derive(Debug)]
+pub struct ConnectionContext {
+    /// Address of the client, `None` for commands not sent over a connection
+    pub client: Option<SocketAddr>,
+    /// Database selected with SELECT
+    pub db: usize,
+    pub authenticated: bool,
+    /// User the connection runs as, see `ServerConfig::users`, `None` when it may run anything
+    pub user: Option<Arc<AclUser>>,
+    /// Commands queued since MULTI, `None` outside of a transaction
+    pub multi: Option<Vec<Command>>,
+    /// Channels the client is subscribed to
+    pub subscriptions: HashSet<RedisString>,
+    /// Name set with CLIENT SETNAME
+    pub name: Option<RedisString>,
+    /// Set with READONLY, reads may then be served by a follower and be stale
+    pub readonly: bool,
+    /// Prepended to the keys of every command, see `ServerConfig::key_prefix`
+    pub key_prefix: Option<RedisString>,
+    /// Prepended to the keys after `key_prefix`, once AUTH selected a virtual instance
+    pub instance_prefix: Option<RedisString>,
+    /// Longest bulk strings and arrays accepted from the client
+    pub protocol_limits: ProtocolLimits,
+    /// Set with CLIENT NO-EVICT, the connection is then kept open however long it's idle
+    pub no_evict: bool,
+    /// Switched to RESP3 with HELLO 3
+    pub resp3: bool,
+    /// Set with CLIENT TRACKING ON, the keys read are then recorded by `ServerStats::tracking`
+    pub tracking: bool,
+    /// The blocking command the connection waits on, e.g. BLPOP with every list empty
+    pub blocked: Option<Blocked>,
+    /// Commands sent against `ServerConfig::rate_limit`, `None` when there is no limit
+    pub rate_limiter: Option<RateLimiter>,
+}
+
+/// Wait of a blocking command. It stays first in line, the requests pipelined after it wait
+/// too, and it runs again once one of its keys was written, see `ServerStats::blocking`, or
+/// once it times out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Blocked {
+    /// When to give up, `None` to wait for as long as it takes
+    pub deadline: Option<Instant>,
+}
+
+impl Blocked {
+    pub fn timed_out(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl Default for ConnectionContext {
+    fn default() -> Self {
+        ConnectionContext {
+            client: None,
+            db: 0,
+            // there is no password to authenticate with yet
+            authenticated: true,
+            user: None,
+            multi: None,
+            subscriptions: HashSet::new(),
+            name: None,
+            readonly: false,
+            key_prefix: None,
+            instance_prefix: None,
+            protocol_limits: ProtocolLimits::default(),
+            no_evict: false,
+            resp3: false,
+            tracking: false,
+            blocked: None,
+            rate_limiter: None,
+        }
+    }
+}
+
+impl ConnectionContext {
+    /// Whether `command` may run in the current state, errors are named after the command
+    /// with `RedisCommandError::for_command`
+    pub fn check(&self, command: &Command) -> Result<(), RedisCommandError> {
+        if !self.authenticated
+            && !matches!(
+                command,
+                Command::Auth(..) | Command::Hello(_, Some(_), _) | Command::Reset | Command::Quit
+            )
+        {
+            return Err(RedisCommandError::NoAuth);
+        }
+
+        if let Some(user) = &self.user {
+            if !user.can_run(command) {
+                return Err(RedisCommandError::NoPermCommand(
+                    user.name().to_string(),
+                    String::new(),
+                ));
+            }
+            if !command.keys().iter().all(|key| user.can_access(key)) {
+                return Err(RedisCommandError::NoPermKey);
+            }
+        }
+
+        if !self.subscriptions.is_empty()
+            && !matches!(command, Command::Ping | Command::Reset | Command::Quit)
+        {
+            return Err(RedisCommandError::SubscribedContext(String::new()));
+        }
+
+        Ok(())
+    }
+
+    /// Prefix of every key the connection sees, see `key_prefix` and `instance_prefix`
+    pub fn full_key_prefix(&self) -> Option<RedisString> {
+        match (&self.key_prefix, &self.instance_prefix) {
+            (None, None) => None,
+            (prefix, instance) => Some(
+                [
+                    prefix.as_deref().unwrap_or_default(),
+                    instance.as_deref().unwrap_or_default(),
+                ]
+                .concat(),
+            ),
+        }
+    }
+
+    /// Back to the state the connection was opened in, for RESET: out of any transaction and
+    /// subscription, on database 0, without name, READWRITE, evictable, back to RESP2 without
+    /// tracking, and with the virtual instance selected by AUTH forgotten. The connection runs
+    /// as `default_user` again, and has to authenticate again if that user needs a password.
+    /// The client address and what comes from the server config, the rate limit included, stay.
+    pub fn reset(&mut self, default_user: Option<Arc<AclUser>>) {
+        *self = ConnectionContext {
+            client: self.client,
+            authenticated: default_user
+                .as_ref()
+                .is_none_or(|user| user.needs_no_password()),
+            user: default_user,
+            key_prefix: self.key_prefix.take(),
+            protocol_limits: self.protocol_limits,
+            rate_limiter: self.rate_limiter,
+            ..ConnectionContext::default()
+        };
+    }
+
+    /// Count a command against the rate limit, return `false` if it goes past it
+    pub fn admit(&mut self) -> bool {
+        self.rate_limiter
+            .as_mut()
+            .is_none_or(|rate_limiter| rate_limiter.admit())
+    }
+
+    /// Queue `command` if a transaction is open, otherwise hand it back to be run right away
+    pub fn queue(&mut self, command: Command) -> Option<Command> {
+        match self.multi.as_mut() {
+            Some(queued) if !matches!(command, Command::Reset | Command::Quit) => {
+                queued.push(command);
+                None
+            }
+            _ => Some(command),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use crossbeam_channel::{Receiver, Sender};
+
+/// What happened to a `Server`, see `Server::events`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ServerEvent {
+    Started,
+    /// Every client was disconnected first
+    Stopped,
+    ClientConnected(SocketAddr),
+    ClientDisconnected(SocketAddr),
+    /// The server couldn't start, or stopped accepting connections
+    Error(String),
+    /// The Raft node ID of the leader in `Server::set_cluster_status`, `None` while none is known
+    LeaderChanged(Option<String>),
+    /// CLUSTER FAILOVER was sent, for the Raft driver to hand leadership over to the node of the
+    /// server: `Node::transfer_leadership` on the leader, or with `force`, an election started
+    /// right away by the node itself without waiting on the leader
+    FailoverRequested {
+        force: bool,
+    },
+}
+
+/// Channels of everyone listening to the events of a server
+#[derive(Debug, Default)]
+pub struct ServerEvents {
+    senders: Mutex<Vec<Sender<ServerEvent>>>,
+}
+
+impl ServerEvents {
+    pub fn subscribe(&self) -> Receiver<ServerEvent> {
+        let (send, recv) = crossbeam_channel::unbounded();
+        self.lock().push(send);
+        recv
+    }
+
+    /// Send `event` to every receiver still around, forgetting the dropped ones
+    pub fn send(&self, event: ServerEvent) {
+        self.lock()
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Sender<ServerEvent>>> {
+        self.senders
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Seed of the jitter, the same on every start for a run to draw the same delays again
+const JITTER_SEED: u64 = 0;
+
+/// Latency added to commands by name, set with DEBUG FAULT to reproduce the behaviour of a
+/// slow cluster
+#[derive(Debug)]
+pub struct FaultInjector {
+    state: Mutex<FaultState>,
+}
+
+#[derive(Debug)]
+struct FaultState {
+    latencies: BTreeMap<String, InjectedLatency>,
+    random: StdRng,
+}
+
+/// Delay of every run of a command: `latency`, plus up to `jitter` more
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InjectedLatency {
+    pub latency: Duration,
+    pub jitter: Duration,
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        FaultInjector {
+            state: Mutex::new(FaultState {
+                latencies: BTreeMap::new(),
+                random: StdRng::seed_from_u64(JITTER_SEED),
+            }),
+        }
+    }
+}
+
+impl FaultInjector {
+    /// Delay every run of `command`, replacing the latency it was given before
+    pub fn set(&self, command: &str, latency: InjectedLatency) {
+        self.state()
+            .latencies
+            .insert(command.to_lowercase(), latency);
+    }
+
+    /// Stop delaying `command`, or every command when `None`, which also draws the jitter
+    /// from the start again
+    pub fn reset(&self, command: Option<&str>) {
+        let mut state = self.state();
+        match command {
+            Some(command) => {
+                state.latencies.remove(&command.to_lowercase());
+            }
+            None => {
+                state.latencies.clear();
+                state.random = StdRng::seed_from_u64(JITTER_SEED);
+            }
+        }
+    }
+
+    /// Commands delayed, by name
+    pub fn latencies(&self) -> Vec<(String, InjectedLatency)> {
+        self.state()
+            .latencies
+            .iter()
+            .map(|(command, latency)| (command.clone(), *latency))
+            .collect()
+    }
+
+    /// How long to delay this run of `command`, `None` when it isn't delayed
+    pub fn delay(&self, command: &str) -> Option<Duration> {
+        let mut state = self.state();
+        let InjectedLatency { latency, jitter } = *state.latencies.get(command)?;
+        let jitter_micros = jitter.as_micros() as u64;
+        let jitter = match jitter_micros {
+            0 => Duration::from_secs(0),
+            _ => Duration::from_micros(state.random.gen_range(0..=jitter_micros)),
+        };
+        Some(latency + jitter)
+    }
+
+    fn state(&self) -> MutexGuard<'_, FaultState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl InjectedLatency {
+    /// Line of DEBUG FAULT LIST for `command`, in the options DEBUG FAULT SET takes
+    pub fn describe(&self, command: &str) -> String {
+        format!(
+            "{} latency={} jitter={}",
+            command,
+            describe_duration(self.latency),
+            describe_duration(self.jitter)
+        )
+    }
+}
+
+fn describe_duration(duration: Duration) -> String {
+    let micros = duration.as_micros();
+    match micros % 1000 {
+        0 => format!("{}ms", micros / 1000),
+        _ => format!("{}us", micros),
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::stats::ServerStats;
+
+/// How long the accept loop sleeps when no health check is waiting
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(10);
+/// Health checks taking longer than this to send their request are dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+/// Requests are a line and a few headers, anything bigger is cut
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+/// HTTP listener answering `GET /healthz` and `GET /metrics`, see `ServerConfig::http_addr`.
+/// Requests are served one after the other by a single thread, until dropped.
+pub struct HttpListener {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HttpListener {
+    pub fn bind(addr: SocketAddr, stats: Arc<ServerStats>) -> io::Result<HttpListener> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stopped = stopped.clone();
+            thread::spawn(move || serve(listener, &stats, &stopped))
+        };
+
+        Ok(HttpListener {
+            stopped,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for HttpListener {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(listener: TcpListener, stats: &ServerStats, stopped: &AtomicBool) {
+    while !stopped.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // a client going away mid request only concerns itself
+                let _ = respond(stream, stats);
+            }
+            // nothing to accept, or failing to, wait before trying again
+            Err(_) => thread::sleep(ACCEPT_INTERVAL),
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, stats: &ServerStats) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_LEN {
+        match stream.read(&mut buf)? {
+            0 => break,
+            n => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let request_line = request.split(|c| *c == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|c| *c == b' ');
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some(b"GET"), Some(b"/healthz")) => ("200 OK", "OK\n".to_string()),
+        (Some(b"GET"), Some(b"/metrics")) => ("200 OK", stats.prometheus_metrics()),
+        (Some(b"GET"), _) => ("404 Not Found", "Not Found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Method Not Allowed\n".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,57 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::command::Command;
+
+/// Command run by a client of a `Server`, see `Server::command_journal`
+#[derive(Debug, PartialEq, Clone)]
+pub struct JournalEntry {
+    pub timestamp: SystemTime,
+    pub client: SocketAddr,
+    /// As sent by the client, before `ServerConfig::key_prefix` is applied
+    pub command: Command,
+}
+
+/// Channels of everyone reading the commands run by a server. Nothing is copied while nobody
+/// reads them.
+#[derive(Debug, Default)]
+pub struct CommandJournal {
+    enabled: AtomicBool,
+    senders: Mutex<Vec<Sender<JournalEntry>>>,
+}
+
+impl CommandJournal {
+    pub fn subscribe(&self) -> Receiver<JournalEntry> {
+        let (send, recv) = crossbeam_channel::unbounded();
+        let mut senders = self.lock();
+        senders.push(send);
+        self.enabled.store(true, Ordering::SeqCst);
+        recv
+    }
+
+    /// Send a copy of `command` to every receiver still around, forgetting the dropped ones
+    pub fn record(&self, client: SocketAddr, command: &Command) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let entry = JournalEntry {
+            timestamp: SystemTime::now(),
+            client,
+            command: command.clone(),
+        };
+        let mut senders = self.lock();
+        senders.retain(|sender| sender.send(entry.clone()).is_ok());
+        self.enabled.store(!senders.is_empty(), Ordering::SeqCst);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Sender<JournalEntry>>> {
+        self.senders
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
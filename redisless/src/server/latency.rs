@@ -0,0 +1,178 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Running a command
+pub const COMMAND_EVENT: &str = "command";
+/// Removing keys past their expiry without waiting for a client to access them
+pub const EXPIRE_CYCLE_EVENT: &str = "expire-cycle";
+
+/// Samples kept per event, same as redis
+const HISTORY_LEN: usize = 160;
+
+/// Latency spikes per event, as reported by the LATENCY command family
+#[derive(Debug)]
+pub struct LatencyMonitor {
+    threshold: Option<Duration>,
+    events: Mutex<HashMap<String, LatencyEvent>>,
+}
+
+/// Spike of `latency` milliseconds at `time`, in seconds since the epoch
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LatencySample {
+    pub time: i64,
+    pub latency: u64,
+}
+
+#[derive(Debug, Default)]
+struct LatencyEvent {
+    samples: VecDeque<LatencySample>,
+    max: u64,
+}
+
+impl LatencyMonitor {
+    /// Record the events lasting at least `threshold`, nothing when it's `None`
+    pub fn new(threshold: Option<Duration>) -> Self {
+        LatencyMonitor {
+            threshold,
+            events: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.threshold.is_some()
+    }
+
+    /// Record that `event` took `elapsed`, if that's a spike. Spikes within the same second
+    /// are merged into the worst of them.
+    pub fn record(&self, event: &str, elapsed: Duration) {
+        match self.threshold {
+            Some(threshold) if elapsed >= threshold => {}
+            _ => return,
+        }
+
+        let sample = LatencySample {
+            time: now(),
+            latency: elapsed.as_millis() as u64,
+        };
+        let mut events = self.lock();
+        let event = events.entry(event.to_string()).or_default();
+        event.max = event.max.max(sample.latency);
+
+        match event.samples.back_mut() {
+            Some(last) if last.time == sample.time => {
+                last.latency = last.latency.max(sample.latency);
+            }
+            _ => {
+                if event.samples.len() == HISTORY_LEN {
+                    event.samples.pop_front();
+                }
+                event.samples.push_back(sample);
+            }
+        }
+    }
+
+    /// Last spike and worst latency of every event, `LATENCY LATEST`
+    pub fn latest(&self) -> Vec<(String, LatencySample, u64)> {
+        let events = self.lock();
+        let mut latest: Vec<_> = events
+            .iter()
+            .filter_map(|(name, event)| {
+                let last = event.samples.back()?;
+                Some((name.clone(), *last, event.max))
+            })
+            .collect();
+        latest.sort_by(|a, b| a.0.cmp(&b.0));
+        latest
+    }
+
+    /// Spikes of `event`, oldest first, `LATENCY HISTORY`
+    pub fn history(&self, event: &str) -> Vec<LatencySample> {
+        self.lock()
+            .get(event)
+            .map(|event| event.samples.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forget the spikes of `events`, or of every event when empty. Return how many events had
+    /// spikes recorded, `LATENCY RESET`
+    pub fn reset<S: AsRef<str>>(&self, events: &[S]) -> usize {
+        let mut recorded = self.lock();
+        if events.is_empty() {
+            let reset = recorded.len();
+            recorded.clear();
+            return reset;
+        }
+
+        events
+            .iter()
+            .filter(|event| recorded.remove(event.as_ref()).is_some())
+            .count()
+    }
+
+    /// Human readable analysis of the spikes, `LATENCY DOCTOR`
+    pub fn doctor(&self) -> String {
+        if !self.is_enabled() {
+            return "I'm sorry, Dave, I can't do that. Latency monitoring is disabled in this \
+                RedisLess instance. You may set `ServerConfig::latency_monitor_threshold` in \
+                order to enable it.\n"
+                .to_string();
+        }
+
+        let events = self.lock();
+        if events.is_empty() {
+            return "Dave, no latency spike was observed during the lifetime of this RedisLess \
+                instance, not in the slightest bit. I honestly think you ought to sleep better \
+                tonight.\n"
+                .to_string();
+        }
+
+        let mut names: Vec<_> = events.keys().collect();
+        names.sort();
+
+        let mut report = "Dave, I have observed latency spikes in this RedisLess instance. \
+            You don't mind talking about it, do you Dave?\n\n"
+            .to_string();
+        let now = now();
+        for (i, name) in names.into_iter().enumerate() {
+            let event = &events[name];
+            let count = event.samples.len() as u64;
+            let sum: u64 = event.samples.iter().map(|s| s.latency).sum();
+            let avg = sum / count.max(1);
+            let deviation = event
+                .samples
+                .iter()
+                .map(|s| (s.latency as i64 - avg as i64).unsigned_abs())
+                .sum::<u64>()
+                / count.max(1);
+            let first = event.samples.front().map_or(now, |s| s.time);
+            let period = (now - first) as f64 / count.max(1) as f64;
+
+            let _ = writeln!(
+                report,
+                "{}. {}: {} latency spikes (average {}ms, mean deviation {}ms, period {:.2} sec). Worst all time event {}ms.",
+                i + 1,
+                name,
+                count,
+                avg,
+                deviation,
+                period,
+                event.max,
+            );
+        }
+        report
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, LatencyEvent>> {
+        self.events
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
@@ -0,0 +1,40 @@
+use crate::command::Command;
+use crate::storage::Storage;
+
+/// Caps on the dataset past which the commands that grow it are refused with OOM, see
+/// `ServerConfig::max_keys` and `ServerConfig::max_value_bytes`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryLimit {
+    pub max_keys: Option<u64>,
+    pub max_value_bytes: Option<u64>,
+}
+
+impl MemoryLimit {
+    pub fn is_set(&self) -> bool {
+        self.max_keys.is_some() || self.max_value_bytes.is_some()
+    }
+
+    /// Whether running `command`, one that grows the dataset, against `storage` would go past
+    /// the limit: it adds keys beyond `max_keys`, or the values already take `max_value_bytes`
+    /// or more. Like Redis checking the memory used before running a command, the write
+    /// reaching the byte limit goes through and only those after it are refused.
+    pub fn refuses<T: Storage>(&self, storage: &mut T, command: &Command) -> bool {
+        if let Some(max_keys) = self.max_keys {
+            let mut added: Vec<_> = command
+                .keys()
+                .into_iter()
+                .filter(|key| !storage.contains(key))
+                .collect();
+            // MSET may name a key more than once
+            added.sort_unstable();
+            added.dedup();
+            if storage.size() + added.len() as u64 > max_keys {
+                return true;
+            }
+        }
+        match self.max_value_bytes {
+            Some(max_value_bytes) => storage.value_bytes() >= max_value_bytes,
+            None => false,
+        }
+    }
+}
@@ -1,36 +1,148 @@
-use std::io::ErrorKind;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, SystemTime};
-
-use crossbeam_channel::{Receiver, Sender};
-use mpb::MPB;
-use rayon::ThreadPool;
-use uuid::Uuid;
-
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+
+use acl::Acl;
+use connection::Connection;
+use context::ConnectionContext;
+use events::ServerEvents;
+use http::HttpListener;
+use renames::CommandRenames;
+use sentinel::Sentinel;
+use stats::ServerStats;
 use util::*;
+use worker::{ConnectionId, WorkerPool};
 
+use crate::cluster::identity::NodeIdentity;
 use crate::cluster::peer::{Peer, PeersDiscovery, DEFAULT_NODE_LISTENING_PORT};
+use crate::command::command_error::RedisCommandError;
+use crate::storage::models::{DatasetSnapshot, RedisString};
+use crate::storage::striped::StripedStorage;
 use crate::storage::Storage;
 
+pub use acl::AclUser;
+pub use builder::{Seed, ServerBuilder};
+pub use cluster_status::{ClusterStatus, PeerStatus};
+pub use config::ServerConfig;
+pub use events::ServerEvent;
+pub use journal::JournalEntry;
+pub use rate_limit::{RateLimit, RateLimitExceeded};
+pub use role::ServerRole;
+pub use shadow::ShadowConfig;
+pub use upstream::UpstreamConfig;
+pub use virtual_instance::VirtualHandle;
+
 #[cfg(test)]
 mod tests;
 
-mod util;
+/// Requests handled by the thread owning the listener, acknowledged on `reply`
+enum ControlMsg {
+    Start { reply: Sender<ServerState> },
+    Stop { reply: Sender<ServerState> },
+}
 
-type CloseConnection = bool;
-type ReceivedDataLength = usize;
+mod acl;
+mod blocking;
+mod builder;
+mod cluster_status;
+mod config;
+mod connection;
+mod context;
+mod events;
+mod fault;
+mod http;
+mod journal;
+mod latency;
+mod memory;
+mod preload;
+mod rate_limit;
+mod renames;
+mod replication;
+mod role;
+mod sentinel;
+mod shadow;
+mod stats;
+mod tracking;
+mod upstream;
+mod util;
+mod virtual_instance;
+mod watchdog;
+mod worker;
+
+/// How long the I/O loop first waits for a reply when none of the connections has anything to read
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// How long the I/O loop waits at most once it has been idle for a while, doubling from the minimum
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How often keys past their expiry are removed without waiting for a client to access them
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+/// Keys removed per cycle at most, so the storage isn't locked for long
+const ACTIVE_EXPIRE_MAX_KEYS: usize = 1000;
 
 pub struct Server {
-    server_state_bus: MPB<ServerState>,
+    control_send: Sender<ControlMsg>,
+    config: ServerConfig,
     cluster_options: ServerClusterOptions,
+    events: Arc<ServerEvents>,
+    stats: Arc<ServerStats>,
+    storage: Arc<dyn SharedStorage>,
+    port: u16,
+}
+
+/// Storage of a server, whatever its type, as reached from the `Server` handle
+trait SharedStorage: Send + Sync {
+    fn export(&self) -> DatasetSnapshot;
+    fn import(&self, snapshot: DatasetSnapshot);
+    fn remove_matching(&self, pattern: &[u8]) -> u32;
+    fn bulk_load(&self, items: Vec<(RedisString, RedisString)>) -> u64;
+    fn run_resp(
+        &self,
+        stats: &ServerStats,
+        context: ConnectionContext,
+        data: &[u8],
+    ) -> io::Result<u64>;
+}
+
+impl<T: Storage + Send> SharedStorage for StripedStorage<T> {
+    fn export(&self) -> DatasetSnapshot {
+        self.lock_all().export()
+    }
+
+    fn import(&self, snapshot: DatasetSnapshot) {
+        self.lock_all().import(snapshot)
+    }
+
+    fn remove_matching(&self, pattern: &[u8]) -> u32 {
+        self.lock_all().remove_matching(pattern)
+    }
+
+    fn bulk_load(&self, items: Vec<(RedisString, RedisString)>) -> u64 {
+        self.lock_all().bulk_load(items)
+    }
+
+    fn run_resp(
+        &self,
+        stats: &ServerStats,
+        context: ConnectionContext,
+        data: &[u8],
+    ) -> io::Result<u64> {
+        preload::run_resp(self, stats, context, data)
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum ServerState {
+    #[deprecated(note = "state changes are requested with `Server::start`")]
     Start,
     Started,
+    #[deprecated(note = "state changes are requested with `Server::stop`")]
     Stop,
     Stopped,
     Timeout,
@@ -42,6 +154,7 @@ pub struct ServerClusterOptions {
     group_id: String,
     peers_discovery: PeersDiscovery,
     listening_socket_addr: SocketAddr,
+    identity_file: Option<PathBuf>,
 }
 
 impl ServerClusterOptions {
@@ -54,8 +167,17 @@ impl ServerClusterOptions {
             group_id,
             peers_discovery,
             listening_socket_addr,
+            identity_file: None,
         }
     }
+
+    /// Keep the identity of the cluster node in the file at `path`, created if missing, for the
+    /// server to rejoin the cluster as the same node once restarted. It's saved again when the
+    /// server stops.
+    pub fn with_identity_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.identity_file = Some(path.into());
+        self
+    }
 }
 
 impl Default for ServerClusterOptions {
@@ -67,6 +189,7 @@ impl Default for ServerClusterOptions {
                 IpAddr::V4(Ipv4Addr::UNSPECIFIED),
                 DEFAULT_NODE_LISTENING_PORT,
             ),
+            identity_file: None,
         }
     }
 }
@@ -76,186 +199,583 @@ impl Server {
         Server::new_with_cluster_options(storage, ServerClusterOptions::default(), port)
     }
 
+    /// Listen on the first port of `ports` no one else listens on, see `port`. With `0..=0`, the
+    /// system picks a free port, which is what tests run in parallel should use.
+    pub fn new_on_free_port<T: Storage + Send + 'static>(
+        storage: T,
+        ports: RangeInclusive<u16>,
+    ) -> io::Result<Self> {
+        Server::new_with_config_on_free_port(storage, ServerConfig::default(), ports)
+    }
+
+    pub fn new_with_config_on_free_port<T: Storage + Send + 'static>(
+        storage: T,
+        config: ServerConfig,
+        ports: RangeInclusive<u16>,
+    ) -> io::Result<Self> {
+        let listener = bind_free_port(ports)?;
+        let port = listener.local_addr()?.port();
+        Ok(Server::with_listener(
+            storage,
+            config,
+            ServerClusterOptions::default(),
+            port,
+            Some(listener),
+        ))
+    }
+
+    pub fn new_with_config<T: Storage + Send + 'static>(
+        storage: T,
+        config: ServerConfig,
+        port: u16,
+    ) -> Self {
+        Server::new_with_config_and_cluster_options(
+            storage,
+            config,
+            ServerClusterOptions::default(),
+            port,
+        )
+    }
+
+    /// Proxy to the Redis at `upstream` (`host:port`) while it's reachable, and fall back to
+    /// `storage` while it isn't. Writes served locally are replayed once the upstream is back.
+    pub fn new_with_upstream<A: Into<String>, T: Storage + Send + 'static>(
+        storage: T,
+        upstream: A,
+        port: u16,
+    ) -> Self {
+        let config = ServerConfig {
+            upstream: Some(UpstreamConfig::new(upstream)),
+            ..ServerConfig::default()
+        };
+        Server::new_with_config(storage, config, port)
+    }
+
     pub fn new_with_cluster_options<T: Storage + Send + 'static>(
         storage: T,
         cluster_options: ServerClusterOptions,
         port: u16,
     ) -> Self {
+        Server::new_with_config_and_cluster_options(
+            storage,
+            ServerConfig::default(),
+            cluster_options,
+            port,
+        )
+    }
+
+    pub fn new_with_config_and_cluster_options<T: Storage + Send + 'static>(
+        storage: T,
+        config: ServerConfig,
+        cluster_options: ServerClusterOptions,
+        port: u16,
+    ) -> Self {
+        Server::with_listener(storage, config, cluster_options, port, None)
+    }
+
+    /// `listener`, already bound to `port`, is used the first time the server starts
+    fn with_listener<T: Storage + Send + 'static>(
+        storage: T,
+        config: ServerConfig,
+        cluster_options: ServerClusterOptions,
+        port: u16,
+        listener: Option<TcpListener>,
+    ) -> Self {
+        let (control_send, control_recv) = crossbeam_channel::unbounded();
+        let events = Arc::new(ServerEvents::default());
+        let stats = Arc::new(
+            ServerStats::new(config.maxclients)
+                .with_events(events.clone())
+                .with_latency_threshold(config.latency_monitor_threshold)
+                .with_busy_threshold(config.busy_threshold)
+                .with_sentinel(Sentinel::new(config.sentinel_master_name.clone(), port))
+                .with_renames(CommandRenames::new(&config.rename_commands))
+                .with_unsupported_commands_logged(config.log_unsupported_commands)
+                .with_read_only(config.read_only)
+                .with_memory_limit(config.memory_limit())
+                .with_tracking_table_max_keys(config.tracking_table_max_keys)
+                .with_acl(Acl::new(&config.users)),
+        );
+        let storage = Arc::new(StripedStorage::new(storage, config.storage_stripes));
         let s = Server {
-            server_state_bus: MPB::new(),
+            control_send,
+            config,
+            stats,
+            storage: storage.clone(),
+            port,
             cluster_options,
+            events,
         };
 
-        s._init_configuration(format!("0.0.0.0:{}", port), storage);
+        s._init_configuration(format!("0.0.0.0:{}", port), storage, control_recv, listener);
         s
     }
 
     fn _init_configuration<A: Into<String>, T: Storage + Send + 'static>(
         &self,
         addr: A,
-        storage: T,
+        storage: Arc<StripedStorage<T>>,
+        control_recv: Receiver<ControlMsg>,
+        mut listener: Option<TcpListener>,
     ) {
         let addr = addr.into();
-        let state_send = self.server_state_bus.sender();
-        let state_recv = self.server_state_bus.receiver();
-
-        let id = Uuid::new_v4();
-        let peer = Peer::new(
-            id.to_string(),
+        let config = self.config.clone();
+
+        let identity_file = self.cluster_options.identity_file.clone();
+        let identity = match &identity_file {
+            Some(path) => NodeIdentity::load_or_create(path).unwrap_or_else(|err| {
+                log::error!(
+                    "can't use the node identity in {}, starting as a new node: {}",
+                    path.display(),
+                    err
+                );
+                NodeIdentity::new()
+            }),
+            None => NodeIdentity::new(),
+        };
+        let peer = Peer::from_identity(
+            identity,
             PeersDiscovery::Automatic(self.cluster_options.listening_socket_addr.port()),
             self.cluster_options.listening_socket_addr,
         );
 
         let mut cluster_node = peer.into_cluster_node();
+        if let Some(path) = identity_file {
+            cluster_node = cluster_node.with_identity_file(path);
+        }
+        let events = self.events.clone();
+        let stats = self.stats.clone();
 
         let _ = thread::spawn(move || {
             let addr = addr;
-            let storage = Arc::new(Mutex::new(storage));
 
-            loop {
-                if let Ok(server_state) = state_recv.recv() {
-                    if server_state == ServerState::Start {
+            // the loop ends when the `Server` is dropped
+            while let Ok(msg) = control_recv.recv() {
+                match msg {
+                    ControlMsg::Start { reply } => {
+                        let listeners = listener
+                            .take()
+                            .map_or_else(|| TcpListener::bind(&addr), Ok)
+                            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+                            .and_then(|listener| {
+                                let http = config
+                                    .http_addr
+                                    .map(|http_addr| HttpListener::bind(http_addr, stats.clone()))
+                                    .transpose()?;
+                                Ok((listener, http))
+                            });
+                        // the keys saved when last stopped are there for the first client
+                        let listeners = listeners.and_then(|listeners| {
+                            if let Some(path) = config
+                                .snapshot_on_stop
+                                .as_ref()
+                                .filter(|path| path.exists())
+                            {
+                                storage.lock_all().import(read_snapshot(path)?);
+                            }
+                            Ok(listeners)
+                        });
+                        let (listener, http) = match listeners {
+                            Ok(listeners) => listeners,
+                            Err(err) => {
+                                events.send(ServerEvent::Error(err.to_string()));
+                                let _ = reply.send(ServerState::Error(err.to_string()));
+                                continue;
+                            }
+                        };
+
+                        // notify that the server has been started
+                        events.send(ServerEvent::Started);
+                        let _ = reply.send(ServerState::Started);
+
                         // start local RESP server
-                        start_server(&addr, &state_send, &state_recv, &storage);
+                        let stop_reply = start_server(
+                            listener,
+                            &config,
+                            &control_recv,
+                            &stats,
+                            &events,
+                            &storage,
+                        );
+
+                        // the HTTP listener stops along with the RESP one
+                        drop(http);
+
+                        // every client is disconnected, none of their writes is lost
+                        if let Some(path) = &config.snapshot_on_stop {
+                            let snapshot = storage.lock_all().export();
+                            if let Err(err) = write_snapshot(path, &snapshot) {
+                                log::error!(
+                                    "can't save the snapshot to {}: {}",
+                                    path.display(),
+                                    err
+                                );
+                                events.send(ServerEvent::Error(err.to_string()));
+                            }
+                        }
 
                         // start current node listener
                         cluster_node.start_listener();
+                        if let Err(err) = cluster_node.save_identity() {
+                            log::error!("can't save the node identity: {}", err);
+                        }
+
+                        // notify that the server has been stopped, all the connections are closed
+                        match stop_reply {
+                            Some(reply) => {
+                                events.send(ServerEvent::Stopped);
+                                let _ = reply.send(ServerState::Stopped);
+                            }
+                            None => return,
+                        }
+                    }
+                    ControlMsg::Stop { reply } => {
+                        // not started
+                        let _ = reply.send(ServerState::Stopped);
                     }
                 }
             }
         });
     }
 
-    fn change_state(&self, change_to: ServerState) -> Option<ServerState> {
-        let send_state_ch = self.server_state_bus.sender();
-
-        let post_change_to_state = match change_to {
-            ServerState::Start => ServerState::Started,
-            ServerState::Stop => ServerState::Stopped,
-            ServerState::Started
-            | ServerState::Stopped
-            | ServerState::Timeout
-            | ServerState::Error(_) => return None,
-        };
-
-        let _ = thread::spawn(move || {
-            let _ = thread::sleep(Duration::from_millis(100));
-            let _ = send_state_ch.send(change_to);
-        });
+    fn change_state(&self, msg: fn(Sender<ServerState>) -> ControlMsg) -> Option<ServerState> {
+        let (reply, reply_recv) = crossbeam_channel::bounded(1);
 
-        // wait for changing state
-        let receiver = self.server_state_bus.receiver(); // TODO cache receiver to reuse it?
+        if self.control_send.send(msg(reply)).is_err() {
+            return Some(ServerState::Error("server thread is gone".to_string()));
+        }
 
-        while let Ok(server_state) = receiver.recv_timeout(Duration::from_secs(5)) {
-            if server_state == post_change_to_state {
-                return Some(server_state);
-            }
+        match reply_recv.recv() {
+            Ok(server_state) => Some(server_state),
+            Err(_) => Some(ServerState::Error("server thread is gone".to_string())),
         }
+    }
 
-        Some(ServerState::Timeout)
+    /// Events from now on, until the receiver is dropped. Every receiver gets every event.
+    pub fn events(&self) -> Receiver<ServerEvent> {
+        self.events.subscribe()
     }
 
     /// start server
     pub fn start(&self) -> Option<ServerState> {
-        self.change_state(ServerState::Start)
+        self.change_state(|reply| ControlMsg::Start { reply })
     }
 
     /// stop server
     pub fn stop(&self) -> Option<ServerState> {
-        self.change_state(ServerState::Stop)
+        self.change_state(|reply| ControlMsg::Stop { reply })
+    }
+
+    /// Copy of every key, whether the server is started or not
+    pub fn export(&self) -> DatasetSnapshot {
+        self.storage.export()
+    }
+
+    /// Replace every key with those of `snapshot`, as returned by `export`
+    pub fn import(&self, snapshot: DatasetSnapshot) {
+        self.storage.import(snapshot)
+    }
+
+    /// Write every key to the file at `path`, replaced at once so that it's never left half
+    /// written, for `load_snapshot` to read back
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        write_snapshot(path.as_ref(), &self.export())
+    }
+
+    /// Replace every key with those saved by `save_snapshot` in the file at `path`, and return
+    /// how many were loaded. The keys are left as they were when the file can't be read back.
+    pub fn load_snapshot<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+        let snapshot = read_snapshot(path.as_ref())?;
+        let keys = snapshot.entries.len();
+        self.import(snapshot);
+        Ok(keys)
+    }
+
+    /// Remove every key matching the glob-style `pattern` at once, return how many. Unlike the
+    /// DELPATTERN command, `ServerConfig::key_prefix` isn't applied.
+    pub fn del_pattern(&self, pattern: &[u8]) -> u32 {
+        self.storage.remove_matching(pattern)
+    }
+
+    /// Run every command of the file at `path`, a stream of RESP requests as written for
+    /// `redis-cli --pipe` mass insertion, without the round trips of a client, and return how
+    /// many ran. `ServerConfig::key_prefix` is applied as for clients. Fails at the first
+    /// malformed or failing command, those before it stay applied.
+    pub fn preload_from_resp_file<P: AsRef<Path>>(&self, path: P) -> io::Result<u64> {
+        let data = fs::read(path)?;
+        let context = ConnectionContext {
+            key_prefix: self.config.key_prefix.clone(),
+            protocol_limits: self.config.protocol_limits(),
+            ..ConnectionContext::default()
+        };
+        self.storage.run_resp(&self.stats, context, &data)
+    }
+
+    /// Write the string keys of the file at `path`, a CSV of `key,value` records, at once, and
+    /// return how many were written. Fields with commas, quotes or line breaks are quoted with
+    /// `"`, their quotes doubled. Like `del_pattern`, `ServerConfig::key_prefix` isn't applied.
+    /// Nothing is written when the file isn't a valid CSV.
+    pub fn preload_from_csv_file<P: AsRef<Path>>(&self, path: P) -> io::Result<u64> {
+        let items = preload::parse_csv(&fs::read(path)?)?;
+        Ok(self.storage.bulk_load(items))
+    }
+
+    /// Commands run from now on, in the order they ran, until the receiver is dropped. Every
+    /// receiver gets every command. Commands rejected before running, e.g. unknown ones, are
+    /// left out.
+    pub fn command_journal(&self) -> Receiver<JournalEntry> {
+        self.stats.journal().subscribe()
+    }
+
+    /// Keyspace of its own named `name`, registered on first use, for the connections that sent
+    /// `AUTH` with its token. Several test workers can thus share the server without seeing the
+    /// keys of one another: FLUSHALL, FLUSHDB, DBSIZE and SCAN only consider the keys of the
+    /// instance. Connections that didn't authenticate see the keys of every instance.
+    pub fn virtual_instance(&self, name: &str) -> VirtualHandle {
+        self.stats
+            .virtual_instances()
+            .get_or_create(name, self.port)
+    }
+
+    /// Port the server listens on, the one picked by `new_on_free_port` for those it created
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn role(&self) -> ServerRole {
+        self.stats.role()
+    }
+
+    /// Serve every command as the leader, or redirect writes to the leader as a follower.
+    /// Followers serve reads to the connections that sent READONLY, those reads may be stale.
+    pub fn set_role(&self, role: ServerRole) {
+        self.stats.set_role(role)
+    }
+
+    /// Health of the cluster as last set with `set_cluster_status`, `None` until then. Also
+    /// reported by `CLUSTER REDISLESS-STATUS`.
+    pub fn cluster_status(&self) -> Option<ClusterStatus> {
+        self.stats.cluster_status()
+    }
+
+    /// Report the health of the cluster, usually `ClusterStatus::of_raft_node` whenever the Raft
+    /// node runs. `ServerEvent::LeaderChanged` is sent when the leader differs from the last one.
+    pub fn set_cluster_status(&self, status: ClusterStatus) {
+        let leader = status.leader.clone();
+        let previous = self.stats.set_cluster_status(status);
+        if previous.is_none_or(|previous| previous.leader != leader) {
+            self.events.send(ServerEvent::LeaderChanged(leader));
+        }
     }
 }
 
+/// Serve clients until a stop is requested, and return the channel to acknowledge it on once
+/// every connection is closed. Return `None` when the `Server` has been dropped.
 fn start_server<T: Storage + Send + 'static>(
-    addr: &str,
-    state_send: &Sender<ServerState>,
-    state_recv: &Receiver<ServerState>,
-    storage: &Arc<Mutex<T>>,
-) {
-    let listener = match TcpListener::bind(addr) {
-        Ok(listener) => {
-            // notify that the server has been started
-            let _ = state_send.send(ServerState::Started);
-            let _ = listener.set_nonblocking(true);
-            listener
-        }
-        Err(_) => {
-            thread::sleep(Duration::from_millis(10));
-            return;
-        }
-    };
-
-    let thread_pool = match rayon::ThreadPoolBuilder::new()
-        .thread_name(|_| "request handler".to_string())
-        .build()
-    {
-        Ok(pool) => pool,
-        Err(err) => {
-            panic!("{:?}", err);
+    listener: TcpListener,
+    config: &ServerConfig,
+    control_recv: &Receiver<ControlMsg>,
+    stats: &Arc<ServerStats>,
+    events: &ServerEvents,
+    storage: &Arc<StripedStorage<T>>,
+) -> Option<Sender<ServerState>> {
+    let (result_send, result_recv) = crossbeam_channel::unbounded();
+    let workers = WorkerPool::new(config, storage, stats, &result_send);
+    let mut connections = HashMap::<ConnectionId, Connection>::new();
+    let mut next_connection_id: ConnectionId = 0;
+    let mut last_active_expire = Instant::now();
+    // a default user with a password already keeps remote clients out
+    let protected = config.protected_mode
+        && listener
+            .local_addr()
+            .is_ok_and(|addr| addr.ip().is_unspecified())
+        && stats
+            .acl()
+            .default_user()
+            .is_none_or(|user| user.needs_no_password());
+
+    let mut poll_interval = MIN_POLL_INTERVAL;
+
+    loop {
+        let mut idle = true;
+
+        // accept incoming connections
+        loop {
+            match listener.accept() {
+                Ok((tcp_stream, peer)) => {
+                    idle = false;
+
+                    if protected && !is_loopback(peer.ip()) {
+                        reject_tcp_stream(&tcp_stream, RedisCommandError::ProtectedMode);
+                        continue;
+                    }
+
+                    let client = match stats.try_connect() {
+                        Some(client) => client,
+                        None => {
+                            // maxclients reached
+                            reject_tcp_stream(&tcp_stream, RedisCommandError::MaxClientsReached);
+                            continue;
+                        }
+                    };
+
+                    if let Ok(connection) =
+                        Connection::new(tcp_stream, client, config, stats.acl().default_user())
+                    {
+                        events.send(ServerEvent::ClientConnected(connection.addr()));
+                        connections.insert(next_connection_id, connection);
+                        next_connection_id = next_connection_id.wrapping_add(1);
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    events.send(ServerEvent::Error(format!(
+                        "listener stopped accepting connections: {}",
+                        err
+                    )));
+                    disconnect_all(&mut connections, stats, events);
+
+                    // the listener is broken, wait to be stopped
+                    return control_recv.iter().find_map(|msg| match msg {
+                        ControlMsg::Stop { reply } => Some(reply),
+                        ControlMsg::Start { reply } => {
+                            let _ = reply.send(ServerState::Error(
+                                "listener stopped accepting connections".to_string(),
+                            ));
+                            None
+                        }
+                    });
+                }
+            }
         }
-    };
 
-    // listen incoming requests
-    for stream in listener.incoming() {
-        match stream {
-            Ok(tcp_stream) => {
-                handle_tcp_stream(tcp_stream, &thread_pool, &state_send, &state_recv, &storage);
+        // push the invalidations of client tracking, send what's left of the replies, read
+        // requests and hand them to the workers, one job at a time per connection, keeping
+        // those held by CLIENT PAUSE or blocked queued
+        let pause = stats.client_pause();
+        let mut blocked_clients = 0;
+        for (connection_id, connection) in connections.iter_mut() {
+            if connection.push_invalidation(stats.tracking()) {
+                idle = false;
             }
-            Err(err) if err.kind() == ErrorKind::WouldBlock => {
-                thread::sleep(Duration::from_millis(10));
+            if connection.write() {
+                idle = false;
             }
-            Err(_) => {
-                break;
+            if connection.read() {
+                idle = false;
             }
-        }
 
-        if stop_sig_received(&state_recv, &state_send) {
-            // let's gracefully shutdown the server
-            break;
+            if let Some(job) = connection.next_job(*connection_id, pause.as_ref(), stats.blocking())
+            {
+                if let Some(job) = workers.try_dispatch(job) {
+                    // every worker is busy, retry on the next iteration
+                    connection.requeue(job);
+                }
+            }
+            if connection.blocked().is_some() {
+                blocked_clients += 1;
+            }
         }
-    }
-}
-
-fn handle_tcp_stream<T: Storage + Send + 'static>(
-    tcp_stream: TcpStream,
-    thread_pool: &ThreadPool,
-    state_send: &Sender<ServerState>,
-    state_recv: &Receiver<ServerState>,
-    storage: &Arc<Mutex<T>>,
-) {
-    let storage = storage.clone();
-    let state_recv = state_recv.clone();
-    let state_send = state_send.clone();
+        stats.set_blocked_clients(blocked_clients);
 
-    let _ = thread_pool.spawn(move || {
-        let mut last_update = SystemTime::now();
+        // write the replies back, waiting a bit for one when there was nothing else to do, and
+        // longer each time nothing happens so an idle server doesn't spin
+        let mut result = match idle {
+            true => result_recv.recv_timeout(poll_interval).ok(),
+            false => result_recv.try_recv().ok(),
+        };
+        poll_interval = match idle && result.is_none() {
+            true => (poll_interval * 2).min(MAX_POLL_INTERVAL),
+            false => MIN_POLL_INTERVAL,
+        };
 
-        loop {
-            let (close_connection, received_data_length) = handle_request(&storage, &tcp_stream);
-
-            if received_data_length > 0 {
-                // reset the last time we received data
-                last_update = SystemTime::now();
-            } else {
-                // delay the loop
-                thread::sleep(Duration::from_millis(10));
+        while let Some(job_result) = result {
+            if let Some(connection) = connections.get_mut(&job_result.connection_id) {
+                connection.write_reply(job_result);
             }
 
-            if stop_sig_received(&state_recv, &state_send) || close_connection {
-                // let's close the connection
-                return;
-            }
+            result = result_recv.try_recv().ok();
+        }
 
-            if let Ok(duration) = last_update.duration_since(SystemTime::now()) {
-                if duration.as_secs() >= 300 {
-                    // close the connection after 300 secs of inactivity
-                    return;
-                }
+        if last_active_expire.elapsed() >= ACTIVE_EXPIRE_INTERVAL {
+            let started = Instant::now();
+            let expired = storage.lock_all().remove_expired(ACTIVE_EXPIRE_MAX_KEYS);
+            stats.keys_expired(expired);
+            stats
+                .latency()
+                .record(latency::EXPIRE_CYCLE_EVENT, started.elapsed());
+            last_active_expire = Instant::now();
+        }
+
+        connections.retain(|_, connection| {
+            let is_done = connection.is_done(config.read_timeout);
+            if is_done {
+                stats.tracking().stop(connection.addr());
+                stats.blocking().stop(connection.addr());
+                events.send(ServerEvent::ClientDisconnected(connection.addr()));
             }
+            !is_done
+        });
 
-            if close_connection {
-                return;
+        match control_recv.try_recv() {
+            // let's gracefully shutdown the server
+            Ok(ControlMsg::Stop { reply }) => {
+                // blocked commands hold no worker, closing their connection is all it takes
+                disconnect_all(&mut connections, stats, events);
+                stats.set_blocked_clients(0);
+                return Some(reply);
+            }
+            Ok(ControlMsg::Start { reply }) => {
+                // already started
+                let _ = reply.send(ServerState::Started);
             }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => return None,
         }
-    });
+    }
+}
+
+/// Listener on the first port of `ports` that's free, on every interface like `Server::new`
+fn bind_free_port(ports: RangeInclusive<u16>) -> io::Result<TcpListener> {
+    let description = format!("{}..={}", ports.start(), ports.end());
+    for port in ports {
+        match TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)) {
+            Ok(listener) => return Ok(listener),
+            Err(err)
+                if err.kind() == ErrorKind::AddrInUse
+                    || err.kind() == ErrorKind::PermissionDenied => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::Error::new(
+        ErrorKind::AddrInUse,
+        format!("no free port in {}", description),
+    ))
+}
+
+/// Write `snapshot` to the file at `path`, replaced at once so that it's never left half written
+fn write_snapshot(path: &Path, snapshot: &DatasetSnapshot) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    fs::write(&tmp, snapshot.encode())?;
+    fs::rename(&tmp, path)
+}
+
+/// Snapshot written by `write_snapshot` to the file at `path`
+fn read_snapshot(path: &Path) -> io::Result<DatasetSnapshot> {
+    DatasetSnapshot::decode(&fs::read(path)?)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "not a snapshot saved by RedisLess"))
+}
+
+fn disconnect_all(
+    connections: &mut HashMap<ConnectionId, Connection>,
+    stats: &ServerStats,
+    events: &ServerEvents,
+) {
+    for (_, connection) in connections.drain() {
+        stats.tracking().stop(connection.addr());
+        stats.blocking().stop(connection.addr());
+        events.send(ServerEvent::ClientDisconnected(connection.addr()));
+    }
 }
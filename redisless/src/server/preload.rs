@@ -0,0 +1,118 @@
+//! Datasets loaded into the storage of a server before clients connect, see
+//! `Server::preload_from_resp_file` and `Server::preload_from_csv_file`
+
+use std::io::{self, ErrorKind};
+
+use crate::protocol::parser::RedisProtocolParser;
+use crate::storage::models::RedisString;
+use crate::storage::striped::StripedStorage;
+use crate::storage::Storage;
+
+use super::context::ConnectionContext;
+use super::stats::ServerStats;
+use super::util::run_command_and_get_response;
+
+/// Run every command of `data`, a stream of RESP requests as sent by `redis-cli --pipe`, and
+/// return how many ran. Stops at the first malformed or failing command, those before it stay
+/// applied.
+pub fn run_resp<T: Storage>(
+    storage: &StripedStorage<T>,
+    stats: &ServerStats,
+    mut context: ConnectionContext,
+    mut data: &[u8],
+) -> io::Result<u64> {
+    let mut commands = 0;
+    while !data.is_empty() {
+        let len = match RedisProtocolParser::frame_len_with_limits(data, &context.protocol_limits) {
+            Ok(Some(len)) => len,
+            Ok(None) => return Err(invalid_data(commands, "truncated command")),
+            Err(err) => return Err(invalid_data(commands, err)),
+        };
+
+        let response = run_command_and_get_response(storage, stats, &mut context, &data[..len]);
+        if let Some(err) = response.error_replied() {
+            return Err(invalid_data(commands, err));
+        }
+        // nothing else writes meanwhile, a blocking command would wait forever
+        context.blocked = None;
+        commands += 1;
+        data = &data[len..];
+    }
+    Ok(commands)
+}
+
+/// `(key, value)` of each record of `data`, a CSV with two fields per record. Fields with
+/// commas, quotes or line breaks are quoted with `"`, their quotes doubled. Empty lines are
+/// skipped.
+pub fn parse_csv(data: &[u8]) -> io::Result<Vec<(RedisString, RedisString)>> {
+    let mut records = Vec::new();
+    let mut fields: Vec<RedisString> = Vec::new();
+    let mut field = Vec::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut bytes = data.iter().peekable();
+
+    while let Some(byte) = bytes.next() {
+        match (quoted, byte) {
+            (true, b'"') if bytes.peek() == Some(&&b'"') => {
+                bytes.next();
+                field.push(b'"');
+            }
+            (true, b'"') => quoted = false,
+            (true, byte) => {
+                if *byte == b'\n' {
+                    line += 1;
+                }
+                field.push(*byte);
+            }
+            (false, b'"') if field.is_empty() => quoted = true,
+            (false, b',') => fields.push(std::mem::take(&mut field)),
+            (false, b'\r') if bytes.peek() == Some(&&b'\n') => {}
+            (false, b'\n') => {
+                end_record(&mut records, &mut fields, &mut field, line)?;
+                line += 1;
+            }
+            (false, byte) => field.push(*byte),
+        }
+    }
+
+    if quoted {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("line {}: unterminated quoted field", line),
+        ));
+    }
+    end_record(&mut records, &mut fields, &mut field, line)?;
+    Ok(records)
+}
+
+fn end_record(
+    records: &mut Vec<(RedisString, RedisString)>,
+    fields: &mut Vec<RedisString>,
+    field: &mut RedisString,
+    line: usize,
+) -> io::Result<()> {
+    if fields.is_empty() && field.is_empty() {
+        return Ok(());
+    }
+
+    fields.push(std::mem::take(field));
+    if fields.len() != 2 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("line {}: expected 2 fields, found {}", line, fields.len()),
+        ));
+    }
+    // will never panic, there are two fields
+    let value = fields.pop().unwrap();
+    let key = fields.pop().unwrap();
+    records.push((key, value));
+    Ok(())
+}
+
+fn invalid_data<E: ToString>(command: u64, err: E) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("command {}: {}", command + 1, err.to_string()),
+    )
+}
@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+/// Commands a connection may send each second, see `ServerConfig::rate_limit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub commands_per_second: u32,
+    /// What becomes of the commands sent past the limit
+    pub exceeded: RateLimitExceeded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitExceeded {
+    /// Answer them with an error, as providers rejecting the requests over a quota do
+    Reject,
+    /// Leave them unread until the next second, as providers shaping the traffic do
+    Delay,
+}
+
+/// Commands sent by a connection during the current second, against its `RateLimit`
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    second_start: Instant,
+    admitted: u32,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            second_start: Instant::now(),
+            admitted: 0,
+        }
+    }
+
+    /// Whether the commands past the limit wait rather than being rejected
+    pub fn delays(&self) -> bool {
+        self.limit.exceeded == RateLimitExceeded::Delay
+    }
+
+    /// Commands that may still be sent during the current second
+    pub fn available(&mut self) -> u32 {
+        if self.second_start.elapsed() >= Duration::from_secs(1) {
+            self.second_start = Instant::now();
+            self.admitted = 0;
+        }
+        self.limit.commands_per_second.saturating_sub(self.admitted)
+    }
+
+    /// Count a command, return `false` if it goes past the limit
+    pub fn admit(&mut self) -> bool {
+        if self.available() == 0 {
+            return false;
+        }
+        self.admitted += 1;
+        true
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::{HashMap, HashSet};
+
+/// Commands renamed or disabled, see `ServerConfig::rename_commands`
+#[derive(Debug, Default)]
+pub struct CommandRenames {
+    /// Original name of each renamed command, by its new name
+    originals: HashMap<Vec<u8>, Vec<u8>>,
+    /// Names no longer known: those of the renamed and disabled commands
+    hidden: HashSet<Vec<u8>>,
+}
+
+impl CommandRenames {
+    /// `renames` maps commands to the name clients must now send, or to an empty name to
+    /// disable them. Names are case insensitive.
+    pub fn new(renames: &HashMap<String, String>) -> Self {
+        let mut command_renames = CommandRenames::default();
+        for (original, renamed) in renames {
+            let original = original.to_lowercase().into_bytes();
+            let renamed = renamed.to_lowercase().into_bytes();
+            if original == renamed {
+                continue;
+            }
+
+            command_renames.hidden.insert(original.clone());
+            if !renamed.is_empty() {
+                command_renames.originals.insert(renamed, original);
+            }
+        }
+        command_renames
+    }
+
+    /// Name of the command to run when a client sends `name`, `None` when no command is known
+    /// by that name anymore
+    pub fn resolve<'a>(&'a self, name: &'a [u8]) -> Option<&'a [u8]> {
+        if self.originals.is_empty() && self.hidden.is_empty() {
+            return Some(name);
+        }
+
+        let lowercase = name.to_ascii_lowercase();
+        match self.originals.get(&lowercase) {
+            Some(original) => Some(original),
+            None if self.hidden.contains(&lowercase) => None,
+            None => Some(name),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::{Mutex, MutexGuard};
+
+use rand::RngCore;
+
+/// Hex digits of a replication ID
+const ID_LEN: usize = 40;
+
+/// Replication ID and offset of the dataset, as reported by `INFO replication`. The ID names
+/// the history of writes the dataset went through, and the offset how far into it the dataset
+/// is: the bytes of every write request applied so far, as Redis counts those it propagates.
+#[derive(Debug)]
+pub struct Replication {
+    state: Mutex<ReplicationState>,
+}
+
+#[derive(Debug)]
+struct ReplicationState {
+    id: String,
+    offset: u64,
+    // the ID the history went by before, with the offset up to which it's shared with it
+    previous: Option<(String, u64)>,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Replication {
+            state: Mutex::new(ReplicationState {
+                id: new_id(),
+                offset: 0,
+                previous: None,
+            }),
+        }
+    }
+}
+
+impl Replication {
+    /// Move the offset past a write request of `len` bytes
+    pub fn advance(&self, len: usize) {
+        self.state().offset += len as u64;
+    }
+
+    /// Start a history of its own, forgetting the previous one, as DEBUG CHANGE-REPL-ID does
+    pub fn change_id(&self) {
+        let mut state = self.state();
+        state.id = new_id();
+        state.previous = None;
+    }
+
+    /// Start a new history branching off the current one, as a replica promoted to master
+    /// does: the current ID is kept as the previous one, shared up to the current offset
+    pub fn shift_id(&self) {
+        let mut state = self.state();
+        let id = std::mem::replace(&mut state.id, new_id());
+        state.previous = Some((id, state.offset + 1));
+    }
+
+    /// The `master_replid*` and `*repl_offset` fields of `INFO replication`
+    pub fn info(&self) -> String {
+        let state = self.state();
+        let (id2, second_offset) = match &state.previous {
+            Some((id, offset)) => (id.clone(), *offset as i64),
+            None => ("0".repeat(ID_LEN), -1),
+        };
+        format!(
+            "master_replid:{}\r\nmaster_replid2:{}\r\nmaster_repl_offset:{}\r\nsecond_repl_offset:{}\r\n",
+            state.id, id2, state.offset, second_offset
+        )
+    }
+
+    fn state(&self) -> MutexGuard<'_, ReplicationState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Random replication ID, 40 hex digits as those of Redis
+fn new_id() -> String {
+    let mut bytes = [0u8; ID_LEN / 2];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
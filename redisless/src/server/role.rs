@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+
+use raft::log::Log;
+use raft::node::Node;
+use rand::RngCore;
+
+use crate::cluster::key_slot;
+use crate::command::command_error::RedisCommandError;
+use crate::command::Command;
+
+/// Part played by the server in its cluster, see `Server::set_role`
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ServerRole {
+    /// Serves every command, the default for a server on its own
+    #[default]
+    Leader,
+    /// Redirects writes to `leader`, `None` while no leader is known. Reads are served
+    /// to connections that sent READONLY, and may be stale as the follower lags behind.
+    Follower { leader: Option<SocketAddr> },
+}
+
+impl ServerRole {
+    /// Role of the server running the Raft `node`, whose group members serve RESP clients at
+    /// `addrs`, to be set with `Server::set_role` whenever the Raft leader changes
+    pub fn of_raft_node<L, R, NodeId>(
+        node: &Node<L, R, NodeId>,
+        addrs: &BTreeMap<NodeId, SocketAddr>,
+    ) -> Self
+    where
+        L: Log,
+        R: RngCore,
+        NodeId: Ord + Clone + Display,
+    {
+        if node.is_leader() {
+            return ServerRole::Leader;
+        }
+
+        ServerRole::Follower {
+            leader: node
+                .leader()
+                .0
+                .and_then(|leader| addrs.get(leader))
+                .copied(),
+        }
+    }
+
+    /// Address of the leader as reported by `CLUSTER LEADER`: `addr`, that of the server, when it
+    /// leads, `None` while no leader is known
+    pub fn leader_addr(&self, addr: SocketAddr) -> Option<SocketAddr> {
+        match self {
+            ServerRole::Leader => Some(addr),
+            ServerRole::Follower { leader } => *leader,
+        }
+    }
+
+    /// Whether `command` may be served by this server, for a connection in READONLY mode
+    /// when `readonly` is set
+    pub fn check(&self, command: &Command, readonly: bool) -> Result<(), RedisCommandError> {
+        let leader = match self {
+            ServerRole::Leader => return Ok(()),
+            ServerRole::Follower { leader } => leader,
+        };
+
+        // commands about the server or the connection are always served
+        let served = match command {
+            command if command.is_write() => false,
+            command if command.is_read() => readonly,
+            _ => true,
+        };
+        if served {
+            return Ok(());
+        }
+
+        match leader {
+            // the leader holds every slot, commands without a key are reported in the first
+            Some(leader) => {
+                let slot = command.first_key().map_or(0, |key| key_slot(key));
+                Err(RedisCommandError::Moved(slot, *leader))
+            }
+            None => Err(RedisCommandError::ClusterDown),
+        }
+    }
+
+    /// Role fields opening the `INFO replication` section, see `ServerStats::replication_info`
+    pub fn replication_info(&self) -> String {
+        match self {
+            ServerRole::Leader => "# Replication\r\nrole:master\r\n".to_string(),
+            ServerRole::Follower { leader: Some(leader) } => format!(
+                "# Replication\r\nrole:slave\r\nmaster_host:{}\r\nmaster_port:{}\r\nmaster_link_status:up\r\n",
+                leader.ip(),
+                leader.port(),
+            ),
+            ServerRole::Follower { leader: None } => {
+                "# Replication\r\nrole:slave\r\nmaster_link_status:down\r\n".to_string()
+            }
+        }
+    }
+}
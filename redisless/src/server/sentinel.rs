@@ -0,0 +1,97 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use super::role::ServerRole;
+
+/// Name Sentinel-aware clients look the master up by, unless configured otherwise
+pub const DEFAULT_MASTER_NAME: &str = "mymaster";
+
+/// What the SENTINEL commands report: a single master, the server itself or the leader it
+/// follows, so clients discovering the master through Sentinel can be pointed at the server
+#[derive(Debug, Clone)]
+pub struct Sentinel {
+    master_name: String,
+    addr: SocketAddr,
+}
+
+impl Default for Sentinel {
+    fn default() -> Self {
+        Sentinel::new(DEFAULT_MASTER_NAME, 6379)
+    }
+}
+
+impl Sentinel {
+    /// Master called `master_name`, served on `port` of the loopback interface
+    pub fn new<S: Into<String>>(master_name: S, port: u16) -> Self {
+        Sentinel {
+            master_name: master_name.into(),
+            addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port),
+        }
+    }
+
+    pub fn master_name(&self) -> &str {
+        &self.master_name
+    }
+
+    /// Address the server is reached at
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Address of the master called `name`, if known
+    pub fn master_addr(&self, name: &[u8], role: ServerRole) -> Option<SocketAddr> {
+        if name != self.master_name.as_bytes() {
+            return None;
+        }
+
+        role.leader_addr(self.addr)
+    }
+
+    /// Fields of the master called `name` as listed by `SENTINEL masters`
+    pub fn master_fields(&self, name: &[u8], role: ServerRole) -> Option<Vec<(&str, String)>> {
+        let addr = self.master_addr(name, role)?;
+        let replicas = match role {
+            ServerRole::Leader => 0,
+            ServerRole::Follower { .. } => 1,
+        };
+
+        Some(vec![
+            ("name", self.master_name.clone()),
+            ("ip", addr.ip().to_string()),
+            ("port", addr.port().to_string()),
+            ("flags", "master".to_string()),
+            ("role-reported", "master".to_string()),
+            ("num-slaves", replicas.to_string()),
+            ("num-other-sentinels", "0".to_string()),
+            ("quorum", "1".to_string()),
+        ])
+    }
+
+    /// Fields of each replica of the master called `name` as listed by `SENTINEL slaves`: the
+    /// server itself when it follows. `None` when there is no such master.
+    pub fn replica_fields(
+        &self,
+        name: &[u8],
+        role: ServerRole,
+    ) -> Option<Vec<Vec<(&str, String)>>> {
+        if name != self.master_name.as_bytes() {
+            return None;
+        }
+
+        let master = match role {
+            ServerRole::Follower {
+                leader: Some(leader),
+            } => leader,
+            _ => return Some(Vec::new()),
+        };
+
+        Some(vec![vec![
+            ("name", self.addr.to_string()),
+            ("ip", self.addr.ip().to_string()),
+            ("port", self.addr.port().to_string()),
+            ("flags", "slave".to_string()),
+            ("role-reported", "slave".to_string()),
+            ("master-host", master.ip().to_string()),
+            ("master-port", master.port().to_string()),
+        ]])
+    }
+}
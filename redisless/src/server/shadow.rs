@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use super::stats::ServerStats;
+use super::upstream::UpstreamConnection;
+
+/// Upstream Redis every command is forwarded to, its replies are compared with ours and
+/// mismatches are logged. Meant to validate compatibility, e.g. in staging.
+#[derive(Debug, Clone)]
+pub struct ShadowConfig {
+    /// `host:port` of the upstream Redis
+    pub upstream: String,
+    /// Give up on an upstream reply after this long, the connection is then reopened
+    pub timeout: Duration,
+}
+
+impl ShadowConfig {
+    pub fn new<A: Into<String>>(upstream: A) -> Self {
+        ShadowConfig {
+            upstream: upstream.into(),
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Connection of a worker to the shadowed Redis
+pub struct Shadow {
+    connection: UpstreamConnection,
+}
+
+impl Shadow {
+    pub fn new(config: ShadowConfig) -> Self {
+        Shadow {
+            connection: UpstreamConnection::new(config.upstream, config.timeout),
+        }
+    }
+
+    /// Forward `request` upstream and compare the upstream reply with `reply`
+    pub fn compare(&mut self, request: &[u8], reply: &[u8], stats: &ServerStats) {
+        match self.connection.forward(request) {
+            Ok(upstream_reply) if upstream_reply == reply => {}
+            Ok(upstream_reply) => {
+                stats.shadow_mismatch();
+                log::warn!(
+                    "shadow mismatch on {:?}: replied {:?}, upstream replied {:?}",
+                    String::from_utf8_lossy(request),
+                    String::from_utf8_lossy(reply),
+                    String::from_utf8_lossy(&upstream_reply),
+                );
+            }
+            Err(err) => {
+                stats.shadow_error();
+                log::warn!("shadow upstream {} failed: {}", self.connection.addr(), err);
+            }
+        }
+    }
+}
@@ -0,0 +1,570 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use super::acl::Acl;
+use super::blocking::Blocking;
+use super::cluster_status::ClusterStatus;
+use super::events::ServerEvents;
+use super::fault::FaultInjector;
+use super::journal::CommandJournal;
+use super::latency::LatencyMonitor;
+use super::memory::MemoryLimit;
+use super::renames::CommandRenames;
+use super::replication::Replication;
+use super::role::ServerRole;
+use super::sentinel::Sentinel;
+use super::tracking::Tracking;
+use super::util::get_command;
+use super::virtual_instance::VirtualInstances;
+use super::watchdog::Watchdog;
+
+/// Distinct unsupported command names counted apart, the next ones only count in the total
+const MAX_UNSUPPORTED_NAMES: usize = 1024;
+
+/// Counters and state shared by the `Server`, the listener and every connection, surfaced by
+/// `INFO`
+#[derive(Debug)]
+pub struct ServerStats {
+    maxclients: usize,
+    role: RwLock<ServerRole>,
+    replication: Replication,
+    cluster_status: RwLock<Option<ClusterStatus>>,
+    events: Arc<ServerEvents>,
+    read_only: AtomicBool,
+    memory_limit: MemoryLimit,
+    acl: Acl,
+    latency: LatencyMonitor,
+    faults: FaultInjector,
+    tracking: Tracking,
+    blocking: Blocking,
+    watchdog: Watchdog,
+    virtual_instances: VirtualInstances,
+    sentinel: Sentinel,
+    journal: CommandJournal,
+    renames: CommandRenames,
+    pause: Mutex<Option<ClientPause>>,
+    connected_clients: AtomicUsize,
+    blocked_clients: AtomicUsize,
+    rejected_connections: AtomicUsize,
+    total_connections_received: AtomicUsize,
+    total_commands_processed: AtomicUsize,
+    keyspace_hits: AtomicUsize,
+    keyspace_misses: AtomicUsize,
+    expired_keys: AtomicUsize,
+    shadow_mismatches: AtomicUsize,
+    shadow_errors: AtomicUsize,
+    log_unsupported_commands: bool,
+    unsupported_commands: AtomicUsize,
+    unsupported_command_names: Mutex<BTreeMap<String, usize>>,
+}
+
+impl ServerStats {
+    pub fn new(maxclients: usize) -> Self {
+        ServerStats {
+            maxclients,
+            role: RwLock::new(ServerRole::default()),
+            replication: Replication::default(),
+            cluster_status: RwLock::new(None),
+            events: Arc::new(ServerEvents::default()),
+            read_only: AtomicBool::new(false),
+            memory_limit: MemoryLimit::default(),
+            acl: Acl::default(),
+            latency: LatencyMonitor::new(None),
+            faults: FaultInjector::default(),
+            tracking: Tracking::default(),
+            blocking: Blocking::default(),
+            watchdog: Watchdog::new(None),
+            virtual_instances: VirtualInstances::default(),
+            sentinel: Sentinel::default(),
+            journal: CommandJournal::default(),
+            renames: CommandRenames::default(),
+            pause: Mutex::new(None),
+            connected_clients: AtomicUsize::new(0),
+            blocked_clients: AtomicUsize::new(0),
+            rejected_connections: AtomicUsize::new(0),
+            total_connections_received: AtomicUsize::new(0),
+            total_commands_processed: AtomicUsize::new(0),
+            keyspace_hits: AtomicUsize::new(0),
+            keyspace_misses: AtomicUsize::new(0),
+            expired_keys: AtomicUsize::new(0),
+            shadow_mismatches: AtomicUsize::new(0),
+            shadow_errors: AtomicUsize::new(0),
+            log_unsupported_commands: false,
+            unsupported_commands: AtomicUsize::new(0),
+            unsupported_command_names: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Register a new client, or return `None` when `maxclients` is already reached
+    pub fn try_connect(self: &Arc<Self>) -> Option<ConnectedClient> {
+        let connected = self.connected_clients.fetch_add(1, Ordering::SeqCst);
+        if connected >= self.maxclients {
+            self.connected_clients.fetch_sub(1, Ordering::SeqCst);
+            self.rejected_connections.fetch_add(1, Ordering::SeqCst);
+            return None;
+        }
+
+        self.total_connections_received
+            .fetch_add(1, Ordering::SeqCst);
+        Some(ConnectedClient {
+            stats: self.clone(),
+        })
+    }
+
+    /// Record the events lasting at least `threshold`, see `ServerConfig::latency_monitor_threshold`
+    pub fn with_latency_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.latency = LatencyMonitor::new(threshold);
+        self
+    }
+
+    pub fn latency(&self) -> &LatencyMonitor {
+        &self.latency
+    }
+
+    /// Latency added to commands by DEBUG FAULT
+    pub fn faults(&self) -> &FaultInjector {
+        &self.faults
+    }
+
+    /// Remember up to `max_keys` keys for client tracking, see `ServerConfig::tracking_table_max_keys`
+    pub fn with_tracking_table_max_keys(mut self, max_keys: usize) -> Self {
+        self.tracking = Tracking::new(max_keys);
+        self
+    }
+
+    /// Keys tracked by the connections with CLIENT TRACKING on
+    pub fn tracking(&self) -> &Tracking {
+        &self.tracking
+    }
+
+    /// Keys the connections blocked on BLPOP wait for
+    pub fn blocking(&self) -> &Blocking {
+        &self.blocking
+    }
+
+    /// Answer BUSY while a command runs for `threshold` or longer, see `ServerConfig::busy_threshold`
+    pub fn with_busy_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.watchdog = Watchdog::new(threshold);
+        self
+    }
+
+    pub fn watchdog(&self) -> &Watchdog {
+        &self.watchdog
+    }
+
+    /// Report the server as `sentinel` to the SENTINEL commands
+    pub fn with_sentinel(mut self, sentinel: Sentinel) -> Self {
+        self.sentinel = sentinel;
+        self
+    }
+
+    pub fn sentinel(&self) -> &Sentinel {
+        &self.sentinel
+    }
+
+    /// Send the events of commands, CLUSTER FAILOVER's, to the receivers of `Server::events`
+    pub fn with_events(mut self, events: Arc<ServerEvents>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn events(&self) -> &ServerEvents {
+        &self.events
+    }
+
+    /// Know the commands by the names in `renames`, see `ServerConfig::rename_commands`
+    pub fn with_renames(mut self, renames: CommandRenames) -> Self {
+        self.renames = renames;
+        self
+    }
+
+    pub fn renames(&self) -> &CommandRenames {
+        &self.renames
+    }
+
+    /// Log the first call of each unsupported command, see `ServerConfig::log_unsupported_commands`
+    pub fn with_unsupported_commands_logged(mut self, logged: bool) -> Self {
+        self.log_unsupported_commands = logged;
+        self
+    }
+
+    pub fn journal(&self) -> &CommandJournal {
+        &self.journal
+    }
+
+    pub fn virtual_instances(&self) -> &VirtualInstances {
+        &self.virtual_instances
+    }
+
+    pub fn role(&self) -> ServerRole {
+        *self
+            .role
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Take on `role`. A follower promoted to leader starts a new replication history, as a
+    /// promoted Redis replica does.
+    pub fn set_role(&self, role: ServerRole) {
+        let previous = std::mem::replace(
+            &mut *self
+                .role
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            role,
+        );
+        if let (ServerRole::Follower { .. }, ServerRole::Leader) = (previous, role) {
+            self.replication.shift_id();
+        }
+    }
+
+    pub fn replication(&self) -> &Replication {
+        &self.replication
+    }
+
+    /// `INFO replication` section
+    pub fn replication_info(&self) -> String {
+        self.role().replication_info() + &self.replication.info()
+    }
+
+    pub fn cluster_status(&self) -> Option<ClusterStatus> {
+        self.cluster_status
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Replace the cluster status, returning the previous one
+    pub fn set_cluster_status(&self, status: ClusterStatus) -> Option<ClusterStatus> {
+        self.cluster_status
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .replace(status)
+    }
+
+    /// Answer writes with READONLY from the start, see `ServerConfig::read_only`
+    pub fn with_read_only(self, read_only: bool) -> Self {
+        self.set_read_only(read_only);
+        self
+    }
+
+    /// Whether writes are answered with READONLY
+    pub fn read_only(&self) -> bool {
+        self.read_only.load(Ordering::SeqCst)
+    }
+
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::SeqCst);
+    }
+
+    /// Refuse the writes growing the dataset past `limit`, see `ServerConfig::max_keys`
+    pub fn with_memory_limit(mut self, limit: MemoryLimit) -> Self {
+        self.memory_limit = limit;
+        self
+    }
+
+    pub fn memory_limit(&self) -> &MemoryLimit {
+        &self.memory_limit
+    }
+
+    /// Let clients authenticate as the users of `acl`, see `ServerConfig::users`
+    pub fn with_acl(mut self, acl: Acl) -> Self {
+        self.acl = acl;
+        self
+    }
+
+    pub fn acl(&self) -> &Acl {
+        &self.acl
+    }
+
+    /// Hold back the commands of every client, or only the writes, for `timeout`, CLIENT PAUSE.
+    /// A pause already running is extended rather than shortened, and stays on every command
+    /// if it was.
+    pub fn pause_clients(&self, timeout: Duration, writes_only: bool) {
+        let mut pause = self
+            .pause
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let until = Instant::now() + timeout;
+        *pause = Some(match pause.filter(|pause| pause.until > Instant::now()) {
+            Some(running) => ClientPause {
+                until: running.until.max(until),
+                writes_only: running.writes_only && writes_only,
+            },
+            None => ClientPause { until, writes_only },
+        });
+    }
+
+    /// End the pause before its timeout, CLIENT UNPAUSE
+    pub fn unpause_clients(&self) {
+        *self
+            .pause
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+
+    /// The pause commands are held back by, `None` once it timed out
+    pub fn client_pause(&self) -> Option<ClientPause> {
+        self.pause
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .filter(|pause| pause.until > Instant::now())
+    }
+
+    pub fn connected_clients(&self) -> usize {
+        self.connected_clients.load(Ordering::SeqCst)
+    }
+
+    pub fn rejected_connections(&self) -> usize {
+        self.rejected_connections.load(Ordering::SeqCst)
+    }
+
+    pub fn command_processed(&self) {
+        self.total_commands_processed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Connections waiting on a blocking command, as last counted by the I/O loop
+    pub fn set_blocked_clients(&self, blocked: usize) {
+        self.blocked_clients.store(blocked, Ordering::SeqCst);
+    }
+
+    pub fn blocked_clients(&self) -> usize {
+        self.blocked_clients.load(Ordering::SeqCst)
+    }
+
+    /// A command read `key`, a hit when it exists
+    pub fn keyspace_lookup(&self, hit: bool) {
+        match hit {
+            true => self.keyspace_hits.fetch_add(1, Ordering::SeqCst),
+            false => self.keyspace_misses.fetch_add(1, Ordering::SeqCst),
+        };
+    }
+
+    /// The expire cycle removed `count` keys past their expiry, those removed when accessed
+    /// are not seen by the server
+    pub fn keys_expired(&self, count: usize) {
+        self.expired_keys.fetch_add(count, Ordering::SeqCst);
+    }
+
+    /// A client called `name`, a command RedisLess doesn't support
+    pub fn command_not_supported(&self, name: &str) {
+        self.unsupported_commands.fetch_add(1, Ordering::SeqCst);
+
+        // the name is the client's, make it fit an INFO field
+        let name: String = name
+            .to_lowercase()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_graphic() && c != ':' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let mut names = self
+            .unsupported_command_names
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(count) = names.get_mut(&name) {
+            *count += 1;
+            return;
+        }
+        if names.len() >= MAX_UNSUPPORTED_NAMES {
+            return;
+        }
+
+        if self.log_unsupported_commands {
+            log::warn!(
+                "a client called {}, a command RedisLess doesn't support",
+                name
+            );
+        }
+        names.insert(name, 1);
+    }
+
+    /// Calls of each unsupported command, by name
+    pub fn unsupported_commands(&self) -> BTreeMap<String, usize> {
+        self.unsupported_command_names
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Zero the counters of `INFO stats` and `INFO errorstats`, `CONFIG RESETSTAT`
+    pub fn reset(&self) {
+        for counter in [
+            &self.rejected_connections,
+            &self.total_connections_received,
+            &self.total_commands_processed,
+            &self.keyspace_hits,
+            &self.keyspace_misses,
+            &self.expired_keys,
+            &self.unsupported_commands,
+        ] {
+            counter.store(0, Ordering::SeqCst);
+        }
+        // the names already logged are logged again
+        self.unsupported_command_names
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+
+    pub fn shadow_mismatch(&self) {
+        self.shadow_mismatches.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn shadow_error(&self) {
+        self.shadow_errors.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn shadow_mismatches(&self) -> usize {
+        self.shadow_mismatches.load(Ordering::SeqCst)
+    }
+
+    pub fn shadow_errors(&self) -> usize {
+        self.shadow_errors.load(Ordering::SeqCst)
+    }
+
+    /// `INFO shadow` section
+    pub fn shadow_info(&self) -> String {
+        format!(
+            "# Shadow\r\nshadow_mismatches:{}\r\nshadow_errors:{}\r\n",
+            self.shadow_mismatches(),
+            self.shadow_errors(),
+        )
+    }
+
+    /// `INFO stats` section
+    pub fn stats_info(&self) -> String {
+        let load = |counter: &AtomicUsize| counter.load(Ordering::SeqCst);
+        format!(
+            "# Stats\r\ntotal_connections_received:{}\r\ntotal_commands_processed:{}\r\nrejected_connections:{}\r\nexpired_keys:{}\r\nkeyspace_hits:{}\r\nkeyspace_misses:{}\r\n",
+            load(&self.total_connections_received),
+            load(&self.total_commands_processed),
+            load(&self.rejected_connections),
+            load(&self.expired_keys),
+            load(&self.keyspace_hits),
+            load(&self.keyspace_misses),
+        )
+    }
+
+    /// `INFO errorstats` section: the calls of unsupported commands, in total and by name
+    pub fn errorstats_info(&self) -> String {
+        let mut info = format!(
+            "# Errorstats\r\nerrorstat_NotSupported:count={}\r\n",
+            self.unsupported_commands.load(Ordering::SeqCst)
+        );
+        for (name, count) in self.unsupported_commands() {
+            info.push_str(&format!("notsupported_{}:count={}\r\n", name, count));
+        }
+        info
+    }
+
+    /// Counters in the Prometheus text format, served on `/metrics`
+    pub fn prometheus_metrics(&self) -> String {
+        let load = |counter: &AtomicUsize| counter.load(Ordering::SeqCst);
+        let metrics = [
+            (
+                "connected_clients",
+                "gauge",
+                "Clients connected",
+                self.connected_clients(),
+            ),
+            (
+                "connections_received_total",
+                "counter",
+                "Connections accepted",
+                load(&self.total_connections_received),
+            ),
+            (
+                "rejected_connections_total",
+                "counter",
+                "Connections rejected because of maxclients",
+                self.rejected_connections(),
+            ),
+            (
+                "commands_processed_total",
+                "counter",
+                "Commands run",
+                load(&self.total_commands_processed),
+            ),
+            (
+                "keyspace_hits_total",
+                "counter",
+                "Keys found by the commands reading them",
+                load(&self.keyspace_hits),
+            ),
+            (
+                "keyspace_misses_total",
+                "counter",
+                "Keys not found by the commands reading them",
+                load(&self.keyspace_misses),
+            ),
+            (
+                "expired_keys_total",
+                "counter",
+                "Keys removed by the expire cycle",
+                load(&self.expired_keys),
+            ),
+            (
+                "unsupported_commands_total",
+                "counter",
+                "Calls of commands RedisLess doesn't support",
+                load(&self.unsupported_commands),
+            ),
+        ];
+
+        metrics
+            .iter()
+            .map(|(name, kind, help, value)| {
+                format!(
+                    "# HELP redisless_{0} {2}.\n# TYPE redisless_{0} {1}\nredisless_{0} {3}\n",
+                    name, kind, help, value
+                )
+            })
+            .collect()
+    }
+
+    /// `INFO clients` section
+    pub fn clients_info(&self) -> String {
+        format!(
+            "# Clients\r\nconnected_clients:{}\r\nblocked_clients:{}\r\nmaxclients:{}\r\nrejected_connections:{}\r\n",
+            self.connected_clients(),
+            self.blocked_clients(),
+            self.maxclients,
+            self.rejected_connections(),
+        )
+    }
+}
+
+/// Counts as a connected client until dropped
+/// Commands held back by the I/O loop until `until`, see `ServerStats::pause_clients`
+#[derive(Debug, Clone, Copy)]
+pub struct ClientPause {
+    pub until: Instant,
+    pub writes_only: bool,
+}
+
+impl ClientPause {
+    /// Whether `request` has to wait for the end of the pause
+    pub fn holds(&self, request: &[u8]) -> bool {
+        !self.writes_only
+            || get_command(request)
+                .map(|command| command.is_write())
+                .unwrap_or(false)
+    }
+}
+
+pub struct ConnectedClient {
+    stats: Arc<ServerStats>,
+}
+
+impl Drop for ConnectedClient {
+    fn drop(&mut self) {
+        self.stats.connected_clients.fetch_sub(1, Ordering::SeqCst);
+    }
+}
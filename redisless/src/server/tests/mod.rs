@@ -1,21 +1,40 @@
 use redis::{Commands, Connection, RedisResult};
-use std::{thread::sleep, time::Duration};
-
-use crate::server::ServerState;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::command::TrackingOptions;
+use crate::protocol::writer::RespWriter;
+use crate::server::blocking::Blocking;
+use crate::server::context::ConnectionContext;
+use crate::server::stats::ServerStats;
+use crate::server::tracking::{Invalidation, Tracking};
+use crate::server::util::run_command_and_get_response;
+use crate::server::{
+    AclUser, ClusterStatus, PeerStatus, RateLimit, RateLimitExceeded, ServerBuilder, ServerConfig,
+    ServerEvent, ServerRole, ServerState, ShadowConfig, UpstreamConfig, VirtualHandle,
+};
 use crate::storage::in_memory::InMemoryStorage;
-use crate::Server;
+use crate::storage::models::{DatasetSnapshot, RedisValue, StorageEntry};
+use crate::storage::striped::{StripedStorage, DEFAULT_STRIPES};
+use crate::storage::Storage;
+use crate::{Command, Server};
 
-fn get_redis_client_connection(port: u16) -> (Server, Connection) {
-    let server = Server::new(InMemoryStorage::new(), port);
+fn get_redis_client_connection() -> (Server, Connection) {
+    let server = Server::new_on_free_port(InMemoryStorage::new(), 0..=0).unwrap();
     assert_eq!(server.start(), Some(ServerState::Started));
 
-    let redis_client = redis::Client::open(format!("redis://127.0.0.1:{}/", port)).unwrap();
+    let redis_client =
+        redis::Client::open(format!("redis://127.0.0.1:{}/", server.port())).unwrap();
     (server, redis_client.get_connection().unwrap())
 }
 #[test]
 #[serial]
 fn test_incr_decr_commands() {
-    let (server, mut con) = get_redis_client_connection(3365);
+    let (server, mut con) = get_redis_client_connection();
 
     let _: () = con.set("some_number", "12").unwrap();
     let _: () = con.incr("some_number", 1).unwrap();
@@ -37,13 +56,50 @@ fn test_incr_decr_commands() {
     let value: u32 = con.get("63").unwrap();
     assert_eq!(value, 79_u32);
 
+    // going past the range of a 64 bits integer leaves the value as it was, version included
+    let _: () = con.set("max", i64::MAX).unwrap();
+    let version: u64 = redis::cmd("CASVERSION").arg("max").query(&mut con).unwrap();
+    let err = con.incr::<_, _, i64>("max", 1).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("increment or decrement would overflow"));
+    let value: i64 = con.get("max").unwrap();
+    assert_eq!(value, i64::MAX);
+    let unchanged: u64 = redis::cmd("CASVERSION").arg("max").query(&mut con).unwrap();
+    assert_eq!(unchanged, version);
+    let _: () = con.set("min", i64::MIN).unwrap();
+    let err = con.decr::<_, _, i64>("min", 1).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("increment or decrement would overflow"));
+    let err = con.decr::<_, _, i64>("zero", i64::MIN).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("increment or decrement would overflow"));
+    let value: i64 = con.incr("min", i64::MAX).unwrap();
+    assert_eq!(value, -1);
+
+    // the value changes where it's stored, its expiry stays
+    let _: () = con.set_ex("expiring", "1", 60).unwrap();
+    let value: i64 = con.incr("expiring", 1).unwrap();
+    assert_eq!(value, 2);
+    let ttl: i64 = con.ttl("expiring").unwrap();
+    assert!(ttl > 0);
+
+    let _: () = con.set("text", "abc").unwrap();
+    let err = con.incr::<_, _, i64>("text", 1).unwrap_err();
+    assert_eq!(err.code(), Some("ERR"));
+    let _: () = con.rpush("list", "a").unwrap();
+    let err = con.incr::<_, _, i64>("list", 1).unwrap_err();
+    assert_eq!(err.code(), Some("WRONGTYPE"));
+
     assert_eq!(server.stop(), Some(ServerState::Stopped));
 }
 
 #[test]
 #[serial]
 fn test_redis_implementation() {
-    let (server, mut con) = get_redis_client_connection(3366);
+    let (server, mut con) = get_redis_client_connection();
 
     let _: () = con.set("key", "value").unwrap();
     let exists: bool = con.exists("key").unwrap();
@@ -104,10 +160,27 @@ fn test_redis_implementation() {
     assert_eq!(server.stop(), Some(ServerState::Stopped));
 }
 
+#[test]
+#[serial]
+fn incr_not_an_integer() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let _: () = con.set("key", "value").unwrap();
+    let x: RedisResult<i64> = con.incr("key", 1);
+    let err = x.unwrap_err();
+    assert_eq!(err.code(), Some("ERR"));
+    assert_eq!(
+        err.detail(),
+        Some("value is not an integer or out of range")
+    );
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
 #[test]
 #[serial]
 fn expire_and_ttl() {
-    let (server, mut con) = get_redis_client_connection(3369);
+    let (server, mut con) = get_redis_client_connection();
 
     let ttl: i32 = con.ttl("key").unwrap();
     assert_eq!(ttl, -2);
@@ -172,7 +245,7 @@ fn expire_and_ttl() {
 #[test]
 #[serial]
 fn get_set() {
-    let (server, mut con) = get_redis_client_connection(3332);
+    let (server, mut con) = get_redis_client_connection();
 
     let _: String = con.set("key1", "valueA").unwrap();
     let x: String = con.getset("key1", "valueB").unwrap();
@@ -196,7 +269,7 @@ fn get_set() {
 #[test]
 #[serial]
 fn dbsize() {
-    let (server, mut con) = get_redis_client_connection(3331);
+    let (server, mut con) = get_redis_client_connection();
 
     let x: u64 = redis::cmd("DBSIZE").query(&mut con).unwrap(); //con.dbsize().unwrap();
     assert_eq!(x, 0);
@@ -215,7 +288,7 @@ fn dbsize() {
 #[test]
 #[serial]
 fn mset() {
-    let (server, mut con) = get_redis_client_connection(3343);
+    let (server, mut con) = get_redis_client_connection();
 
     let key_value_pairs = &[("key0", "val0"), ("key1", "val1"), ("key2", "val2")][..];
 
@@ -232,7 +305,7 @@ fn mset() {
 #[test]
 #[serial]
 fn mset_nx() {
-    let (server, mut con) = get_redis_client_connection(3342);
+    let (server, mut con) = get_redis_client_connection();
 
     let key_value_pairs = &[("key0", "val0"), ("key1", "val1"), ("key2", "val2")][..];
 
@@ -262,7 +335,7 @@ fn mset_nx() {
 #[test]
 #[serial]
 fn mget() {
-    let (server, mut con) = get_redis_client_connection(3345);
+    let (server, mut con) = get_redis_client_connection();
 
     let key_value_pairs = &[("key0", "val0"), ("key1", "val1"), ("key2", "val2")][..];
 
@@ -286,10 +359,78 @@ fn mget() {
     assert_eq!(server.stop(), Some(ServerState::Stopped));
 }
 
+#[test]
+#[serial]
+fn del_multiple_keys() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let _: () = con.set("key0", "val0").unwrap();
+    let _: () = con.set("key1", "val1").unwrap();
+    let _: () = con.sadd("setkey", "val").unwrap();
+
+    let deleted: u32 = con.del(&["key0", "key1", "setkey", "missing"]).unwrap();
+    assert_eq!(deleted, 3);
+
+    let exists: bool = con.exists("setkey").unwrap();
+    assert!(!exists);
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn hscan_sscan() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let fields = &[("field1", "val1"), ("field2", "val2"), ("other", "val3")][..];
+    let _: () = con.hset_multiple("hashkey", fields).unwrap();
+
+    let (cursor, items): (u64, HashMap<String, String>) = redis::cmd("HSCAN")
+        .arg("hashkey")
+        .arg(0)
+        .arg("MATCH")
+        .arg("field*")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(cursor, 0);
+    assert_eq!(items.len(), 2);
+    assert_eq!(items["field1"], "val1");
+    assert_eq!(items["field2"], "val2");
+
+    let _: () = con.sadd("setkey", &["a", "b", "c"][..]).unwrap();
+    let mut members = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let (next, page): (u64, Vec<String>) = redis::cmd("SSCAN")
+            .arg("setkey")
+            .arg(cursor)
+            .arg("COUNT")
+            .arg(1)
+            .query(&mut con)
+            .unwrap();
+        members.extend(page);
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    members.sort();
+    assert_eq!(members, vec!["a", "b", "c"]);
+
+    let err = redis::cmd("SSCAN")
+        .arg("hashkey")
+        .arg(0)
+        .query::<(u64, Vec<String>)>(&mut con)
+        .unwrap_err();
+    assert_eq!(err.code(), Some("WRONGTYPE"));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
 #[test]
 #[serial]
 fn hset() {
-    let (server, mut con) = get_redis_client_connection(3347);
+    let (server, mut con) = get_redis_client_connection();
 
     let key_value_pairs = &[("fkey0", "val0"), ("fkey1", "val1"), ("fkey2", "val2")][..];
     let _: () = con
@@ -306,13 +447,32 @@ fn hset() {
     let x: Option<String> = con.hget("key1", "fkey3").ok();
     assert_eq!(x, None);
 
+    // HSET answers how many fields it added, HMSET just OK
+    let added: i64 = redis::cmd("HSET")
+        .arg(&["key0", "fkey2", "new", "fkey3", "val3"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(added, 1);
+    let reply: String = redis::cmd("HMSET")
+        .arg(&["key0", "fkey4", "val4"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(reply, "OK");
+
+    // a string isn't turned into a hash
+    let _: () = con.set("string", "value").unwrap();
+    let err = con.hset::<_, _, _, i64>("string", "f", "v").unwrap_err();
+    assert_eq!(err.code(), Some("WRONGTYPE"));
+    let x: String = con.get("string").unwrap();
+    assert_eq!(x, "value");
+
     assert_eq!(server.stop(), Some(ServerState::Stopped));
 }
 
 #[test]
 #[serial]
 fn llen() {
-    let (server, mut con) = get_redis_client_connection(3377);
+    let (server, mut con) = get_redis_client_connection();
 
     let values = &["val1", "val2", "val3", "val4"];
     let x = con
@@ -331,7 +491,7 @@ fn llen() {
 #[test]
 #[serial]
 fn rpush() {
-    let (server, mut con) = get_redis_client_connection(3344);
+    let (server, mut con) = get_redis_client_connection();
     let values = &["val1", "val2", "val3"][..];
     let x = con
         .rpush::<&'static str, &[&str], u32>("listkey", values)
@@ -349,7 +509,7 @@ fn rpush() {
 #[test]
 #[serial]
 fn lpush() {
-    let (server, mut con) = get_redis_client_connection(3348);
+    let (server, mut con) = get_redis_client_connection();
     let values = &["val1", "val2", "val3"][..];
     let _ = con
         .rpush::<&'static str, &[&str], u32>("listkey", values)
@@ -367,10 +527,27 @@ fn lpush() {
     assert_eq!(server.stop(), Some(ServerState::Stopped));
 }
 
+#[test]
+#[serial]
+fn lrange() {
+    let (server, mut con) = get_redis_client_connection();
+    let _: u32 = con.rpush("listkey", &["val1", "val2", "val3"][..]).unwrap();
+    let _: u32 = con.lpush("listkey", &["val0", "val-1"][..]).unwrap();
+
+    let values: Vec<String> = con.lrange("listkey", 0, -1).unwrap();
+    assert_eq!(values, vec!["val-1", "val0", "val1", "val2", "val3"]);
+    let values: Vec<String> = con.lrange("listkey", -2, 10).unwrap();
+    assert_eq!(values, vec!["val2", "val3"]);
+    let values: Vec<String> = con.lrange("nokey", 0, -1).unwrap();
+    assert!(values.is_empty());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
 #[test]
 #[serial]
 fn rpushx_lpushx() {
-    let (server, mut con) = get_redis_client_connection(3352);
+    let (server, mut con) = get_redis_client_connection();
     let values = &["val1", "val2", "val3"][..];
     let a = con
         .rpush_exists::<&'static str, &[&str], u32>("new_key", values)
@@ -401,7 +578,7 @@ fn rpushx_lpushx() {
 #[test]
 #[serial]
 fn rpop_lpop() {
-    let (server, mut con) = get_redis_client_connection(3354);
+    let (server, mut con) = get_redis_client_connection();
     let values = &["val1", "val2"][..];
     let _ = con
         .rpush::<&'static str, &[&str], u32>("listkey", values)
@@ -419,7 +596,7 @@ fn rpop_lpop() {
 #[test]
 #[serial]
 fn lindex_lset_linsert() {
-    let (server, mut con) = get_redis_client_connection(3355);
+    let (server, mut con) = get_redis_client_connection();
     let values = &["val1", "val2", "val3"][..];
     let _ = con
         .rpush::<&'static str, &[&str], u32>("listkey", values)
@@ -444,7 +621,7 @@ fn lindex_lset_linsert() {
 #[test]
 #[serial]
 fn ltrim_lrem_rpoplpush() {
-    let (server, mut con) = get_redis_client_connection(3356);
+    let (server, mut con) = get_redis_client_connection();
     let values = &["val1", "val2", "val3", "val4", "val5", "val6"][..];
     let _ = con
         .rpush::<&'static str, &[&str], u32>("listkey", values)
@@ -481,7 +658,7 @@ fn ltrim_lrem_rpoplpush() {
 #[test]
 #[serial]
 fn sadd_scard_srem() {
-    let (server, mut con) = get_redis_client_connection(3357);
+    let (server, mut con) = get_redis_client_connection();
 
     let values = &["val1", "val2", "val3", "val1"][..];
     let x: i64 = con.sadd("setkey", values).unwrap();
@@ -516,9 +693,26 @@ fn start_and_stop_server_multiple_times() {
     }
 }
 
+#[test]
+fn start_and_stop_are_acknowledged() {
+    let server = Server::new(InMemoryStorage::new(), 3374);
+    // stopping a server that isn't started is a no-op
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert_eq!(server.start(), Some(ServerState::Started));
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    // the port is already taken
+    let other_server = Server::new(InMemoryStorage::new(), 3374);
+    assert!(matches!(other_server.start(), Some(ServerState::Error(_))));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert_eq!(other_server.start(), Some(ServerState::Started));
+    assert_eq!(other_server.stop(), Some(ServerState::Stopped));
+}
+
 #[test]
 fn append() {
-    let (server, mut con) = get_redis_client_connection(3346);
+    let (server, mut con) = get_redis_client_connection();
 
     let _: String = con.set("key1", "value1").unwrap();
     let len: usize = con.append("key1", "value1").unwrap();
@@ -533,3 +727,3257 @@ fn append() {
 
     assert_eq!(server.stop(), Some(ServerState::Stopped));
 }
+
+#[test]
+#[serial]
+fn pipelined_and_split_requests() {
+    let port = 3379;
+    let server = Server::new(InMemoryStorage::new(), port);
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    let mut stream = TcpStream::connect(format!("localhost:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    // several requests in a single write are all answered, in order
+    let _ = stream.write_all(
+        b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$5\r\nvalue\r\n\
+          *2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n\
+          *1\r\n$4\r\nPING\r\n",
+    );
+    let expected = b"+OK\r\n$5\r\nvalue\r\n+PONG\r\n";
+    let mut res = vec![0; expected.len()];
+    stream.read_exact(&mut res).unwrap();
+    assert_eq!(res, expected.to_vec());
+
+    // a request split across writes is answered once complete
+    let _ = stream.write_all(b"*2\r\n$3\r\nGE");
+    sleep(Duration::from_millis(50));
+    let _ = stream.write_all(b"T\r\n$5\r\nmykey\r\n");
+    let expected = b"$5\r\nvalue\r\n";
+    let mut res = vec![0; expected.len()];
+    stream.read_exact(&mut res).unwrap();
+    assert_eq!(res, expected.to_vec());
+
+    // values larger than a read buffer
+    let value = "x".repeat(100_000);
+    let mut con = redis::Client::open(format!("redis://127.0.0.1:{}/", port))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+    let _: () = con.set("bigkey", &value).unwrap();
+    let res: String = con.get("bigkey").unwrap();
+    assert_eq!(res, value);
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn malformed_requests_close_only_their_connection() {
+    let port = 3380;
+    let server = Server::new(InMemoryStorage::new(), port);
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    for request in &[
+        &b"*1\r\n$99999999999999999999\r\n"[..],
+        b"*2\r\n$3\r\nGET\r\n$1\r\nab\r\n",
+    ] {
+        let mut stream = TcpStream::connect(format!("localhost:{}", port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let _ = stream.write_all(request);
+
+        let mut res = Vec::new();
+        let _ = stream.read_to_end(&mut res);
+        assert!(res.starts_with(b"-ERR Protocol error"));
+    }
+
+    let mut con = redis::Client::open(format!("redis://127.0.0.1:{}/", port))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+    let pong: String = redis::cmd("PING").query(&mut con).unwrap();
+    assert_eq!(pong, "PONG");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn oversized_requests_are_protocol_errors() {
+    let port = 3404;
+    let config = ServerConfig {
+        proto_max_bulk_len: 16,
+        proto_max_multibulk_len: 4,
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, port);
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    for (request, error) in &[
+        (
+            &b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$17\r\n"[..],
+            &b"-ERR Protocol error: invalid bulk length\r\n"[..],
+        ),
+        (
+            b"*1000000000\r\n",
+            b"-ERR Protocol error: invalid multibulk length\r\n",
+        ),
+    ] {
+        let mut stream = TcpStream::connect(format!("localhost:{}", port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let _ = stream.write_all(request);
+
+        // answered and closed right away, the announced bytes are never waited for
+        let mut res = Vec::new();
+        let _ = stream.read_to_end(&mut res);
+        assert_eq!(res, *error);
+    }
+
+    let mut con = redis::Client::open(format!("redis://127.0.0.1:{}/", port))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+    let _: () = con.set("k", "0123456789abcdef").unwrap();
+    assert!(con.set::<_, _, ()>("k", "0123456789abcdefg").is_err());
+
+    let mut con = redis::Client::open(format!("redis://127.0.0.1:{}/", port))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+    let res: String = con.get("k").unwrap();
+    assert_eq!(res, "0123456789abcdef");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn seeded_keys_are_there_for_the_first_client() {
+    let server = ServerBuilder::new(InMemoryStorage::new())
+        .seed(|db| {
+            db.set("k", "v")
+                .hset("h", &[("f", "v")])
+                .hset("h", &[("g", "w")])
+                .rpush("l", &["a", "b"])
+                .sadd("s", &["m"])
+                .expire("k", Duration::from_secs(5));
+        })
+        .seed(|db| {
+            db.set("s", "replaced");
+        })
+        .build()
+        .unwrap();
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let redis_client =
+        redis::Client::open(format!("redis://127.0.0.1:{}/", server.port())).unwrap();
+    let mut con = redis_client.get_connection().unwrap();
+
+    let value: String = con.get("k").unwrap();
+    assert_eq!(value, "v");
+    let ttl: i64 = con.ttl("k").unwrap();
+    assert!(ttl > 0 && ttl <= 5);
+    let fields: HashMap<String, String> = con.hgetall("h").unwrap();
+    assert_eq!(fields.len(), 2);
+    assert_eq!(fields["g"], "w");
+    let values: Vec<String> = con.lrange("l", 0, -1).unwrap();
+    assert_eq!(values, vec!["a", "b"]);
+    let value: String = con.get("s").unwrap();
+    assert_eq!(value, "replaced");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn connections_are_rate_limited() {
+    let ping = b"*1\r\n$4\r\nPING\r\n";
+    let serve = |rate_limit: RateLimit, max_inflight_bytes: Option<usize>, pings: usize| {
+        let config = ServerConfig {
+            rate_limit: Some(rate_limit),
+            max_inflight_bytes,
+            ..ServerConfig::default()
+        };
+        let server =
+            Server::new_with_config_on_free_port(InMemoryStorage::new(), config, 0..=0).unwrap();
+        assert_eq!(server.start(), Some(ServerState::Started));
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", server.port())).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream.write_all(&ping.repeat(pings)).unwrap();
+        (server, stream)
+    };
+
+    let rejecting = RateLimit {
+        commands_per_second: 3,
+        exceeded: RateLimitExceeded::Reject,
+    };
+    let (server, mut stream) = serve(rejecting, None, 5);
+    let expected = [
+        b"+PONG\r\n".repeat(3),
+        b"-ERR rate limit exceeded\r\n".repeat(2),
+    ]
+    .concat();
+    let mut res = vec![0; expected.len()];
+    stream.read_exact(&mut res).unwrap();
+    assert_eq!(res, expected);
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+
+    // the requests over the limit wait for the next second, however few bytes may be in flight
+    let delaying = RateLimit {
+        commands_per_second: 3,
+        exceeded: RateLimitExceeded::Delay,
+    };
+    let started = Instant::now();
+    let (server, mut stream) = serve(delaying, Some(ping.len()), 7);
+    let mut res = vec![0; 7 * b"+PONG\r\n".len()];
+    stream.read_exact(&mut res).unwrap();
+    assert_eq!(res, b"+PONG\r\n".repeat(7));
+    assert!(started.elapsed() >= Duration::from_millis(1900));
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn large_replies_to_slow_readers() {
+    let port = 3407;
+    let config = ServerConfig {
+        write_timeout: Some(Duration::from_millis(500)),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, port);
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    let mut con = redis::Client::open(format!("redis://127.0.0.1:{}/", port))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+    let value = "x".repeat(4 * 1024 * 1024);
+    let _: () = con.set("bigkey", &value).unwrap();
+
+    // far more than the socket buffers hold, answered while the client isn't reading
+    let request =
+        b"*5\r\n$4\r\nMGET\r\n$6\r\nbigkey\r\n$6\r\nbigkey\r\n$6\r\nbigkey\r\n$6\r\nbigkey\r\n";
+    let mut slow = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    slow.write_all(request).unwrap();
+    sleep(Duration::from_millis(200));
+    let pong: String = redis::cmd("PING").query(&mut con).unwrap();
+    assert_eq!(pong, "PONG");
+
+    let expected_len = b"*4\r\n".len() + 4 * (b"$4194304\r\n\r\n".len() + value.len());
+    let mut res = vec![0; expected_len];
+    slow.read_exact(&mut res).unwrap();
+    assert!(res.starts_with(b"*4\r\n$4194304\r\nxxx"));
+    assert!(res.ends_with(b"xxx\r\n"));
+
+    // a client not reading its replies at all is eventually disconnected
+    let mut stuck = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stuck.write_all(request).unwrap();
+    sleep(Duration::from_millis(1000));
+    stuck
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let started = Instant::now();
+    let mut res = Vec::new();
+    let _ = stuck.read_to_end(&mut res);
+    assert!(res.len() < expected_len);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn idle_connection_is_reaped() {
+    let config = ServerConfig {
+        read_timeout: Some(Duration::from_millis(200)),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3371);
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    let mut stream = TcpStream::connect("127.0.0.1:3371").unwrap();
+    let _ = stream.write(b"*1\r\n$4\r\nPING\r\n");
+    let mut pong_res = [0; 7];
+    let _ = stream.read(&mut pong_res);
+    assert_eq!(pong_res, b"+PONG\r\n"[..]);
+
+    sleep(Duration::from_millis(500));
+
+    // the server closed the connection
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let mut buf = [0; 1];
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn maxclients_reached() {
+    let config = ServerConfig {
+        maxclients: 1,
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3372);
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    let redis_client = redis::Client::open("redis://127.0.0.1:3372/").unwrap();
+    let mut con = redis_client.get_connection().unwrap();
+    let _: () = con.set("key", "value").unwrap();
+
+    let mut stream = TcpStream::connect("127.0.0.1:3372").unwrap();
+    let mut buf = Vec::new();
+    let _ = stream.read_to_end(&mut buf);
+    assert_eq!(buf, b"-ERR max number of clients reached\r\n".to_vec());
+
+    let info: String = redis::cmd("INFO").arg("clients").query(&mut con).unwrap();
+    assert!(info.contains("connected_clients:1\r\n"));
+    assert!(info.contains("maxclients:1\r\n"));
+    assert!(info.contains("rejected_connections:1\r\n"));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn idle_clients_do_not_starve_workers() {
+    let config = ServerConfig {
+        workers: 1,
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3373);
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    let idle_streams: Vec<TcpStream> = (0..12)
+        .map(|_| TcpStream::connect("127.0.0.1:3373").unwrap())
+        .collect();
+
+    let redis_client = redis::Client::open("redis://127.0.0.1:3373/").unwrap();
+    let mut con = redis_client.get_connection().unwrap();
+    let _: () = con.set("key", "value").unwrap();
+    let x: String = con.get("key").unwrap();
+    assert_eq!(x, "value");
+
+    let info: String = redis::cmd("INFO").arg("clients").query(&mut con).unwrap();
+    assert!(info.contains("connected_clients:13\r\n"));
+
+    drop(idle_streams);
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+fn connection_context_gates_commands() {
+    let storage = Arc::new(StripedStorage::new(InMemoryStorage::new(), DEFAULT_STRIPES));
+    let stats = ServerStats::new(10);
+    let run = |context: &mut ConnectionContext, request: &[u8]| {
+        let mut writer = RespWriter::default();
+        run_command_and_get_response(&storage, &stats, context, request).write_to(&mut writer);
+        writer.as_slice().to_vec()
+    };
+    let get = b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n";
+    let set = b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$5\r\nvalue\r\n";
+    let ping = b"*1\r\n$4\r\nPING\r\n";
+
+    let mut context = ConnectionContext {
+        authenticated: false,
+        ..ConnectionContext::default()
+    };
+    assert_eq!(
+        run(&mut context, get),
+        b"-NOAUTH Authentication required.\r\n"
+    );
+
+    let mut context = ConnectionContext::default();
+    context.subscriptions.insert(b"news".to_vec());
+    assert_eq!(run(&mut context, ping), b"+PONG\r\n");
+    assert!(run(&mut context, get).starts_with(b"-ERR Can't execute 'get'"));
+
+    let mut context = ConnectionContext {
+        multi: Some(Vec::new()),
+        ..ConnectionContext::default()
+    };
+    assert_eq!(run(&mut context, set), b"+QUEUED\r\n");
+    assert_eq!(run(&mut context, get), b"+QUEUED\r\n");
+    assert_eq!(context.multi.as_ref().map(Vec::len), Some(2));
+    // nothing ran yet
+    let mut context = ConnectionContext::default();
+    assert_eq!(run(&mut context, get), b"$-1\r\n");
+}
+
+#[test]
+fn concurrent_commands_see_consistent_storage() {
+    let storage = StripedStorage::new(InMemoryStorage::new(), DEFAULT_STRIPES);
+    let stats = ServerStats::new(10);
+    let run = |request: &[u8]| {
+        let mut writer = RespWriter::default();
+        let mut context = ConnectionContext::default();
+        run_command_and_get_response(&storage, &stats, &mut context, request).write_to(&mut writer);
+        String::from_utf8(writer.as_slice().to_vec()).unwrap()
+    };
+    let incr = b"*2\r\n$4\r\nINCR\r\n$7\r\ncounter\r\n";
+    let mget = b"*3\r\n$4\r\nMGET\r\n$1\r\na\r\n$1\r\nb\r\n";
+    let rpoplpush = b"*3\r\n$9\r\nRPOPLPUSH\r\n$4\r\nring\r\n$4\r\nring\r\n";
+    let llen = b"*2\r\n$4\r\nLLEN\r\n$4\r\nring\r\n";
+    run(b"*5\r\n$5\r\nRPUSH\r\n$4\r\nring\r\n$1\r\n1\r\n$1\r\n2\r\n$1\r\n3\r\n");
+    let (threads, runs) = (4, 250);
+
+    let mut replies: Vec<String> = std::thread::scope(|scope| {
+        let incrs: Vec<_> = (0..threads)
+            .map(|_| scope.spawn(|| (0..runs).map(|_| run(incr)).collect::<Vec<_>>()))
+            .collect();
+        scope.spawn(|| {
+            for i in 0..runs {
+                let value = i.to_string();
+                run(format!(
+                    "*5\r\n$4\r\nMSET\r\n$1\r\na\r\n${}\r\n{}\r\n$1\r\nb\r\n${}\r\n{}\r\n",
+                    value.len(),
+                    value,
+                    value.len(),
+                    value
+                )
+                .as_bytes());
+            }
+        });
+        scope.spawn(|| {
+            for _ in 0..runs {
+                // both keys are missing or hold the same value, never one from each MSET
+                let reply = run(mget);
+                let values: Vec<&str> = reply
+                    .split("\r\n")
+                    .skip(1)
+                    .filter(|line| !line.is_empty() && (!line.starts_with('$') || *line == "$-1"))
+                    .collect();
+                assert_eq!(values.len(), 2, "{}", reply);
+                assert_eq!(values[0], values[1], "{}", reply);
+            }
+        });
+        scope.spawn(|| {
+            for _ in 0..runs {
+                run(rpoplpush);
+            }
+        });
+        scope.spawn(|| {
+            for _ in 0..runs {
+                // the element popped is pushed back under the same lock
+                assert_eq!(run(llen), ":3\r\n");
+            }
+        });
+        incrs
+            .into_iter()
+            .flat_map(|incrs| incrs.join().unwrap())
+            .collect()
+    });
+
+    // every INCR saw the value the one before left
+    replies.sort();
+    replies.dedup();
+    assert_eq!(replies.len(), threads * runs);
+    assert_eq!(
+        run(b"*2\r\n$3\r\nGET\r\n$7\r\ncounter\r\n"),
+        format!(
+            "${}\r\n{}\r\n",
+            (threads * runs).to_string().len(),
+            threads * runs
+        )
+    );
+    // each key read is counted once: two by MGET, one by LLEN and GET
+    let info = stats.stats_info();
+    let counted = |field: &str| -> usize {
+        info.lines()
+            .find_map(|line| line.strip_prefix(field))
+            .and_then(|count| count.parse().ok())
+            .unwrap()
+    };
+    assert_eq!(
+        counted("keyspace_hits:") + counted("keyspace_misses:"),
+        runs * 2 + runs + 1
+    );
+}
+
+#[test]
+fn acl_users_are_parsed_from_acl_file_lines() {
+    let parse = |line: &str| AclUser::parse(line).unwrap();
+    let get = Command::Get(b"app:key".to_vec());
+    let set = Command::Set(b"app:key".to_vec(), b"value".to_vec());
+    let flushall = Command::FlushAll;
+
+    let user = parse("user alice on >secret ~app:* ~cache:* +@all -@dangerous");
+    assert_eq!(user.name(), "alice");
+    assert!(user.accepts(b"secret"));
+    assert!(!user.accepts(b"other"));
+    assert!(!user.needs_no_password());
+    assert!(user.can_run(&get) && user.can_run(&set));
+    assert!(!user.can_run(&flushall));
+    assert!(user.can_access(b"app:key") && user.can_access(b"cache:key"));
+    assert!(!user.can_access(b"other"));
+
+    // the last rule about a command decides
+    let user = parse("user bob on nopass allkeys -@all +@read -get +flushall");
+    assert!(user.accepts(b"anything") && user.needs_no_password());
+    assert!(!user.can_run(&get) && !user.can_run(&set));
+    assert!(user.can_run(&Command::MGet(vec![b"key".to_vec()])));
+    assert!(user.can_run(&flushall));
+    assert!(user.can_access(b"any"));
+    // authenticating is always allowed
+    assert!(user.can_run(&Command::Auth(None, b"secret".to_vec())));
+
+    // users start disabled, with nothing granted
+    let user = parse("user carol >secret");
+    assert!(!user.accepts(b"secret"));
+    assert!(!user.can_run(&Command::Ping));
+    assert!(!user.can_access(b"key"));
+
+    assert!(AclUser::parse("alice on").is_err());
+    assert!(AclUser::parse("user alice +@unknown").is_err());
+    assert!(AclUser::parse("user alice +config|get").is_err());
+    assert!(AclUser::parse("user alice %R~app:*").is_err());
+}
+
+#[test]
+#[serial]
+fn acl_users_are_limited_to_their_commands_and_keys() {
+    let config = ServerConfig {
+        users: vec![
+            AclUser::parse("user default on nopass ~public:* +@read").unwrap(),
+            AclUser::parse("user alice on >secret ~app:* +@all -@dangerous").unwrap(),
+        ],
+        ..ServerConfig::default()
+    };
+    let server =
+        Server::new_with_config_on_free_port(InMemoryStorage::new(), config, 0..=0).unwrap();
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let redis_client =
+        redis::Client::open(format!("redis://127.0.0.1:{}/", server.port())).unwrap();
+    let mut con = redis_client.get_connection().unwrap();
+
+    // connections run as the default user
+    let value: Option<String> = con.get("public:key").unwrap();
+    assert_eq!(value, None);
+    let err = con.set::<_, _, ()>("public:key", "value").unwrap_err();
+    assert_eq!(err.code(), Some("NOPERM"));
+    assert!(err
+        .to_string()
+        .contains("User default has no permissions to run the 'set' command"));
+    let err = con.get::<_, String>("app:key").unwrap_err();
+    assert_eq!(err.code(), Some("NOPERM"));
+    assert!(err.to_string().contains("No permissions to access a key"));
+
+    let err = redis::cmd("AUTH")
+        .arg("alice")
+        .arg("wrong")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert_eq!(err.code(), Some("WRONGPASS"));
+    let _: () = redis::cmd("AUTH")
+        .arg("alice")
+        .arg("secret")
+        .query(&mut con)
+        .unwrap();
+    let _: () = con.set("app:key", "value").unwrap();
+    let err = con.set::<_, _, ()>("public:key", "value").unwrap_err();
+    assert_eq!(err.code(), Some("NOPERM"));
+    let err = redis::cmd("FLUSHALL").query::<()>(&mut con).unwrap_err();
+    assert_eq!(err.code(), Some("NOPERM"));
+    let value: String = con.get("app:key").unwrap();
+    assert_eq!(value, "value");
+
+    // RESET goes back to the default user
+    let _: () = redis::cmd("RESET").query(&mut con).unwrap();
+    let err = con.get::<_, String>("app:key").unwrap_err();
+    assert_eq!(err.code(), Some("NOPERM"));
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+
+    // without a password, the default user has to authenticate first
+    let config = ServerConfig {
+        users: vec![AclUser::parse("user default on >secret allkeys allcommands").unwrap()],
+        ..ServerConfig::default()
+    };
+    let server =
+        Server::new_with_config_on_free_port(InMemoryStorage::new(), config, 0..=0).unwrap();
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let redis_client =
+        redis::Client::open(format!("redis://127.0.0.1:{}/", server.port())).unwrap();
+    let mut con = redis_client.get_connection().unwrap();
+    let err = con.get::<_, String>("key").unwrap_err();
+    assert_eq!(err.code(), Some("NOAUTH"));
+    let _: () = redis::cmd("AUTH").arg("secret").query(&mut con).unwrap();
+    let _: () = con.set("key", "value").unwrap();
+
+    // and once more after RESET
+    let _: () = redis::cmd("RESET").query(&mut con).unwrap();
+    let err = con.get::<_, String>("key").unwrap_err();
+    assert_eq!(err.code(), Some("NOAUTH"));
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+fn reset_restores_the_connection_state() {
+    let storage = Arc::new(StripedStorage::new(InMemoryStorage::new(), DEFAULT_STRIPES));
+    let stats = ServerStats::new(10);
+    let run = |context: &mut ConnectionContext, request: &[u8]| {
+        let mut writer = RespWriter::default();
+        run_command_and_get_response(&storage, &stats, context, request).write_to(&mut writer);
+        writer.as_slice().to_vec()
+    };
+    let reset = b"*1\r\n$5\r\nRESET\r\n";
+    let client = "127.0.0.1:4242".parse().unwrap();
+
+    let mut context = ConnectionContext {
+        client: Some(client),
+        db: 0,
+        multi: Some(Vec::new()),
+        name: Some(b"pooled".to_vec()),
+        readonly: true,
+        key_prefix: Some(b"app:".to_vec()),
+        instance_prefix: Some(b"tenant:".to_vec()),
+        ..ConnectionContext::default()
+    };
+    context.subscriptions.insert(b"news".to_vec());
+    // neither queued in the transaction nor refused while subscribed
+    assert_eq!(run(&mut context, reset), b"+RESET\r\n");
+
+    assert!(context.multi.is_none());
+    assert!(context.subscriptions.is_empty());
+    assert_eq!(context.db, 0);
+    assert!(context.name.is_none());
+    assert!(!context.readonly);
+    assert!(context.instance_prefix.is_none());
+    assert_eq!(context.client, Some(client));
+    assert_eq!(context.key_prefix, Some(b"app:".to_vec()));
+
+    // back to the default user, which needs no password here
+    let mut context = ConnectionContext {
+        authenticated: false,
+        ..ConnectionContext::default()
+    };
+    assert_eq!(run(&mut context, reset), b"+RESET\r\n");
+    assert!(context.authenticated);
+    assert!(context.user.is_none());
+}
+
+#[test]
+#[serial]
+fn select_and_client_name() {
+    let (server, mut con) = get_redis_client_connection();
+    let mut other_con = redis::Client::open(format!("redis://127.0.0.1:{}/", server.port()))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+
+    let ok: String = redis::cmd("SELECT").arg(0).query(&mut con).unwrap();
+    assert_eq!(ok, "OK");
+    let res: RedisResult<String> = redis::cmd("SELECT").arg(1).query(&mut con);
+    assert!(res.is_err());
+
+    let name: Option<String> = redis::cmd("CLIENT").arg("GETNAME").query(&mut con).unwrap();
+    assert_eq!(name, None);
+    let ok: String = redis::cmd("CLIENT")
+        .arg("SETNAME")
+        .arg("worker-1")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(ok, "OK");
+    let res: RedisResult<String> = redis::cmd("CLIENT")
+        .arg("SETNAME")
+        .arg("worker 1")
+        .query(&mut con);
+    assert!(res.is_err());
+
+    // the name belongs to the connection that set it
+    let name: Option<String> = redis::cmd("CLIENT").arg("GETNAME").query(&mut con).unwrap();
+    assert_eq!(name, Some("worker-1".to_string()));
+    let name: Option<String> = redis::cmd("CLIENT")
+        .arg("GETNAME")
+        .query(&mut other_con)
+        .unwrap();
+    assert_eq!(name, None);
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn shadowing_upstream() {
+    let (upstream, mut upstream_con) = get_redis_client_connection();
+    let config = ServerConfig {
+        shadow: Some(ShadowConfig::new(format!("127.0.0.1:{}", upstream.port()))),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3383);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = redis::Client::open("redis://127.0.0.1:3383/")
+        .unwrap()
+        .get_connection()
+        .unwrap();
+
+    // writes reach the upstream, identical replies aren't reported
+    let _: () = con.set("key", "value").unwrap();
+    let value: String = upstream_con.get("key").unwrap();
+    assert_eq!(value, "value");
+    let value: String = con.get("key").unwrap();
+    assert_eq!(value, "value");
+    let info: String = redis::cmd("INFO").arg("shadow").query(&mut con).unwrap();
+    assert!(info.contains("shadow_mismatches:0\r\n"));
+
+    // data only the upstream has makes replies differ
+    let _: () = upstream_con.set("upstream_only", "value").unwrap();
+    let value: Option<String> = con.get("upstream_only").unwrap();
+    assert_eq!(value, None);
+    let info: String = redis::cmd("INFO").arg("shadow").query(&mut con).unwrap();
+    assert!(info.contains("shadow_mismatches:1\r\n"));
+    assert!(info.contains("shadow_errors:0\r\n"));
+
+    // an unreachable upstream doesn't get in the way of the clients
+    assert_eq!(upstream.stop(), Some(ServerState::Stopped));
+    let value: String = con.get("key").unwrap();
+    assert_eq!(value, "value");
+    let info: String = redis::cmd("INFO").arg("shadow").query(&mut con).unwrap();
+    assert!(!info.contains("shadow_errors:0\r\n"));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn upstream_failover() {
+    let (upstream, mut upstream_con) = get_redis_client_connection();
+    let server = Server::new_with_upstream(
+        InMemoryStorage::new(),
+        format!("127.0.0.1:{}", upstream.port()),
+        3385,
+    );
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = redis::Client::open("redis://127.0.0.1:3385/")
+        .unwrap()
+        .get_connection()
+        .unwrap();
+
+    // proxied to the upstream while it's reachable
+    let _: () = con.set("key", "value").unwrap();
+    let value: String = upstream_con.get("key").unwrap();
+    assert_eq!(value, "value");
+    let _: () = upstream_con.set("upstream_only", "value").unwrap();
+    let value: String = con.get("upstream_only").unwrap();
+    assert_eq!(value, "value");
+
+    // served from the local storage while it's down
+    assert_eq!(upstream.stop(), Some(ServerState::Stopped));
+    let value: String = con.get("key").unwrap();
+    assert_eq!(value, "value");
+    let _: () = con.set("offline", "value").unwrap();
+    let value: String = con.get("offline").unwrap();
+    assert_eq!(value, "value");
+
+    // the writes it missed are replayed once it's back
+    assert_eq!(upstream.start(), Some(ServerState::Started));
+    sleep(Duration::from_millis(1100));
+    let value: String = con.get("key").unwrap();
+    assert_eq!(value, "value");
+    let mut upstream_con = redis::Client::open(format!("redis://127.0.0.1:{}/", upstream.port()))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+    let value: String = upstream_con.get("offline").unwrap();
+    assert_eq!(value, "value");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert_eq!(upstream.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn slow_upstream_replies_are_awaited_concurrently() {
+    let workers = ServerConfig {
+        workers: 4,
+        ..ServerConfig::default()
+    };
+    let upstream =
+        Server::new_with_config_on_free_port(InMemoryStorage::new(), workers.clone(), 0..=0)
+            .unwrap();
+    assert_eq!(upstream.start(), Some(ServerState::Started));
+    let config = ServerConfig {
+        upstream: Some(UpstreamConfig::new(format!(
+            "127.0.0.1:{}",
+            upstream.port()
+        ))),
+        ..workers
+    };
+    let server =
+        Server::new_with_config_on_free_port(InMemoryStorage::new(), config, 0..=0).unwrap();
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}/", server.port())).unwrap();
+
+    let mut upstream_con = redis::Client::open(format!("redis://127.0.0.1:{}/", upstream.port()))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+    let _: () = redis::cmd("DEBUG")
+        .arg(&["fault", "set", "GET", "latency=300ms"])
+        .query(&mut upstream_con)
+        .unwrap();
+
+    // each worker waits on its own reply, not on those of the others
+    let started = Instant::now();
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let mut con = client.get_connection().unwrap();
+            std::thread::spawn(move || {
+                let _: Option<String> = con.get("key").unwrap();
+            })
+        })
+        .collect();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert!(started.elapsed() < Duration::from_millis(550));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert_eq!(upstream.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn refused_commands_never_reach_the_upstream() {
+    let (upstream, mut upstream_con) = get_redis_client_connection();
+    let config = ServerConfig {
+        upstream: Some(UpstreamConfig::new(format!(
+            "127.0.0.1:{}",
+            upstream.port()
+        ))),
+        users: vec![AclUser::parse("user default on nopass ~* +@read").unwrap()],
+        ..ServerConfig::default()
+    };
+    let server =
+        Server::new_with_config_on_free_port(InMemoryStorage::new(), config, 0..=0).unwrap();
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = redis::Client::open(format!("redis://127.0.0.1:{}/", server.port()))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+
+    // the local refusal stands and the upstream isn't written
+    let err = con.set::<_, _, ()>("key", "value").unwrap_err();
+    assert_eq!(err.code(), Some("NOPERM"));
+    let value: Option<String> = upstream_con.get("key").unwrap();
+    assert_eq!(value, None);
+
+    // what the user may run is still proxied
+    let _: () = upstream_con.set("upstream_only", "value").unwrap();
+    let value: String = con.get("upstream_only").unwrap();
+    assert_eq!(value, "value");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert_eq!(upstream.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn scan_while_another_client_writes() {
+    let (server, mut con) = get_redis_client_connection();
+    let mut other_con = redis::Client::open(format!("redis://127.0.0.1:{}/", server.port()))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+
+    for i in 0..100 {
+        let _: () = con.set(format!("key{}", i), i).unwrap();
+    }
+
+    let mut keys = Vec::new();
+    let mut cursor = 0;
+    let mut round = 0;
+    loop {
+        let (next, page): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg("key*")
+            .query(&mut con)
+            .unwrap();
+        keys.extend(page);
+
+        let _: () = other_con.set(format!("other{}", round), round).unwrap();
+        let _: () = other_con.set(format!("key-new{}", round), round).unwrap();
+        round += 1;
+
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+
+    assert!(keys.iter().all(|key| key.starts_with("key")));
+    for i in 0..100 {
+        assert!(keys.contains(&format!("key{}", i)));
+    }
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn expired_keys_are_removed_without_being_accessed() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let _: () = con.set("persistent", "value").unwrap();
+    let _: () = redis::cmd("PSETEX")
+        .arg("volatile")
+        .arg(50)
+        .arg("value")
+        .query(&mut con)
+        .unwrap();
+    let size: u64 = redis::cmd("DBSIZE").query(&mut con).unwrap();
+    assert_eq!(size, 2);
+
+    sleep(Duration::from_millis(300));
+    let size: u64 = redis::cmd("DBSIZE").query(&mut con).unwrap();
+    assert_eq!(size, 1);
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn flushall_and_dbsize() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let _: () = con.set("string", "v").unwrap();
+    let _: () = con.rpush("list", "v").unwrap();
+    let _: () = con.sadd("set", "v").unwrap();
+    let _: () = con.hset("hash", "f", "v").unwrap();
+    let size: u64 = redis::cmd("DBSIZE").query(&mut con).unwrap();
+    assert_eq!(size, 4);
+
+    // emptied aggregates don't count
+    let _: () = con.srem("set", "v").unwrap();
+    let _: () = con.lpop("list").unwrap();
+    let size: u64 = redis::cmd("DBSIZE").query(&mut con).unwrap();
+    assert_eq!(size, 2);
+
+    let ok: String = redis::cmd("FLUSHALL").query(&mut con).unwrap();
+    assert_eq!(ok, "OK");
+    let size: u64 = redis::cmd("DBSIZE").query(&mut con).unwrap();
+    assert_eq!(size, 0);
+
+    let _: () = con.set("string", "v").unwrap();
+    let ok: String = redis::cmd("FLUSHDB").arg("ASYNC").query(&mut con).unwrap();
+    assert_eq!(ok, "OK");
+    let exists: bool = con.exists("string").unwrap();
+    assert!(!exists);
+
+    let res: RedisResult<String> = redis::cmd("FLUSHALL").arg("LATER").query(&mut con);
+    assert!(res.is_err());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn lifecycle_events() {
+    let timeout = Duration::from_secs(5);
+    let server = Server::new(InMemoryStorage::new(), 3390);
+    let events = server.events();
+
+    assert_eq!(server.start(), Some(ServerState::Started));
+    assert_eq!(events.recv_timeout(timeout), Ok(ServerEvent::Started));
+
+    let stream = TcpStream::connect("127.0.0.1:3390").unwrap();
+    let addr = stream.local_addr().unwrap();
+    assert_eq!(
+        events.recv_timeout(timeout),
+        Ok(ServerEvent::ClientConnected(addr))
+    );
+    drop(stream);
+    assert_eq!(
+        events.recv_timeout(timeout),
+        Ok(ServerEvent::ClientDisconnected(addr))
+    );
+
+    // the port is taken
+    let other = Server::new(InMemoryStorage::new(), 3390);
+    let other_events = other.events();
+    assert!(matches!(other.start(), Some(ServerState::Error(_))));
+    assert!(matches!(
+        other_events.recv_timeout(timeout),
+        Ok(ServerEvent::Error(_))
+    ));
+
+    let stream = TcpStream::connect("127.0.0.1:3390").unwrap();
+    let addr = stream.local_addr().unwrap();
+    assert_eq!(
+        events.recv_timeout(timeout),
+        Ok(ServerEvent::ClientConnected(addr))
+    );
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert_eq!(
+        events.recv_timeout(timeout),
+        Ok(ServerEvent::ClientDisconnected(addr))
+    );
+    assert_eq!(events.recv_timeout(timeout), Ok(ServerEvent::Stopped));
+    assert!(events.try_recv().is_err());
+}
+
+#[test]
+#[serial]
+fn bitfield_counters() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let replies: Vec<Option<i64>> = redis::cmd("BITFIELD")
+        .arg("counters")
+        .arg(&["INCRBY", "u8", "#0", "250"])
+        .arg(&["OVERFLOW", "FAIL", "INCRBY", "u8", "#0", "10"])
+        .arg(&["GET", "u8", "#0"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(replies, vec![Some(250), None, Some(250)]);
+
+    let _: () = con.expire("counters", 100).unwrap();
+    let replies: Vec<i64> = redis::cmd("BITFIELD")
+        .arg("counters")
+        .arg(&["SET", "u8", "#1", "7"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(replies, vec![0]);
+    let value: Vec<u8> = con.get("counters").unwrap();
+    assert_eq!(value, vec![250, 7]);
+    let ttl: i64 = con.ttl("counters").unwrap();
+    assert!(ttl > 0);
+
+    // reads don't create the key
+    let replies: Vec<i64> = redis::cmd("BITFIELD")
+        .arg("missing")
+        .arg(&["GET", "i16", "0"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(replies, vec![0]);
+    let exists: bool = con.exists("missing").unwrap();
+    assert!(!exists);
+
+    let _: () = con.rpush("list", "v").unwrap();
+    let res: RedisResult<Vec<i64>> = redis::cmd("BITFIELD")
+        .arg("list")
+        .arg(&["GET", "u8", "0"])
+        .query(&mut con);
+    assert!(res.is_err());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn followers_redirect_to_the_leader() {
+    let (server, mut con) = get_redis_client_connection();
+    let _: () = con.set("mykey", "value").unwrap();
+
+    let leader = "127.0.0.1:6380".parse().unwrap();
+    server.set_role(ServerRole::Follower {
+        leader: Some(leader),
+    });
+    let info: String = redis::cmd("INFO")
+        .arg("replication")
+        .query(&mut con)
+        .unwrap();
+    assert!(info.contains("role:slave\r\nmaster_host:127.0.0.1\r\nmaster_port:6380\r\n"));
+
+    let err = con.set::<_, _, ()>("mykey", "other").unwrap_err();
+    assert_eq!(err.code(), Some("MOVED"));
+    assert_eq!(err.detail(), Some("14687 127.0.0.1:6380"));
+    assert!(con.get::<_, String>("mykey").is_err());
+
+    // possibly stale reads are served once asked for
+    let _: () = redis::cmd("READONLY").query(&mut con).unwrap();
+    let value: String = con.get("mykey").unwrap();
+    assert_eq!(value, "value");
+    assert!(con.set::<_, _, ()>("mykey", "other").is_err());
+
+    let _: () = redis::cmd("READWRITE").query(&mut con).unwrap();
+    assert!(con.get::<_, String>("mykey").is_err());
+    let pong: String = redis::cmd("PING").query(&mut con).unwrap();
+    assert_eq!(pong, "PONG");
+
+    server.set_role(ServerRole::Follower { leader: None });
+    let err = con.set::<_, _, ()>("mykey", "other").unwrap_err();
+    assert_eq!(err.code(), Some("CLUSTERDOWN"));
+
+    server.set_role(ServerRole::Leader);
+    let _: () = con.set("mykey", "other").unwrap();
+    let info: String = redis::cmd("INFO")
+        .arg("replication")
+        .query(&mut con)
+        .unwrap();
+    assert!(info.contains("role:master\r\n"));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn commands_are_delayed_by_injected_latency() {
+    let (server, mut con) = get_redis_client_connection();
+    let fault = |con: &mut Connection, args: &[&str]| -> RedisResult<()> {
+        redis::cmd("DEBUG").arg("fault").arg(args).query(con)
+    };
+    let timed = |con: &mut Connection| {
+        let start = Instant::now();
+        let _: Option<String> = con.get("k").unwrap();
+        start.elapsed()
+    };
+
+    fault(&mut con, &["set", "GET", "latency=100ms", "jitter=50ms"]).unwrap();
+    fault(&mut con, &["set", "exec", "latency=500ms"]).unwrap();
+    let faults: Vec<String> = redis::cmd("DEBUG")
+        .arg("fault")
+        .arg("list")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(
+        faults,
+        vec![
+            "exec latency=500ms jitter=0ms".to_string(),
+            "get latency=100ms jitter=50ms".to_string()
+        ]
+    );
+
+    for _ in 0..3 {
+        let elapsed = timed(&mut con);
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+    }
+    let err = fault(&mut con, &["set", "get", "latency=fast"]).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("Invalid fault option 'latency=fast'"));
+
+    fault(&mut con, &["reset", "get"]).unwrap();
+    assert!(timed(&mut con) < Duration::from_millis(100));
+    fault(&mut con, &["reset"]).unwrap();
+    let faults: Vec<String> = redis::cmd("DEBUG")
+        .arg("fault")
+        .arg("list")
+        .query(&mut con)
+        .unwrap();
+    assert!(faults.is_empty());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn replication_ids_and_offsets_are_reported() {
+    let (server, mut con) = get_redis_client_connection();
+    let replication = |con: &mut Connection| -> HashMap<String, String> {
+        let info: String = redis::cmd("INFO").arg("replication").query(con).unwrap();
+        info.lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    };
+
+    let info = replication(&mut con);
+    let id = info["master_replid"].clone();
+    assert_eq!(id.len(), 40);
+    assert!(id.bytes().all(|b| b.is_ascii_hexdigit()));
+    assert_eq!(info["master_replid2"], "0".repeat(40));
+    assert_eq!(info["master_repl_offset"], "0");
+    assert_eq!(info["second_repl_offset"], "-1");
+
+    // only the writes that went through move the offset, by the bytes of their request
+    let _: () = con.set("k", "v").unwrap();
+    let _: String = con.get("k").unwrap();
+    assert!(con.lpush::<_, _, ()>("k", "v").is_err());
+    let info = replication(&mut con);
+    assert_eq!(info["master_repl_offset"], "27");
+    assert_eq!(info["master_replid"], id);
+
+    let _: () = redis::cmd("DEBUG")
+        .arg("change-repl-id")
+        .query(&mut con)
+        .unwrap();
+    let info = replication(&mut con);
+    assert_ne!(info["master_replid"], id);
+    assert_eq!(info["master_replid2"], "0".repeat(40));
+    assert_eq!(info["master_repl_offset"], "27");
+
+    // a promoted follower keeps its former history as the second one
+    let id = info["master_replid"].clone();
+    server.set_role(ServerRole::Follower { leader: None });
+    server.set_role(ServerRole::Leader);
+    let info = replication(&mut con);
+    assert_ne!(info["master_replid"], id);
+    assert_eq!(info["master_replid2"], id);
+    assert_eq!(info["second_repl_offset"], "28");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn latency_spikes_are_reported() {
+    let config = ServerConfig {
+        latency_monitor_threshold: Some(Duration::ZERO),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3393);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = redis::Client::open("redis://127.0.0.1:3393/")
+        .unwrap()
+        .get_connection()
+        .unwrap();
+
+    let _: () = con.set("mykey", "value").unwrap();
+    let latest: Vec<(String, i64, i64, i64)> =
+        redis::cmd("LATENCY").arg("LATEST").query(&mut con).unwrap();
+    assert!(latest.iter().any(|(event, ..)| event == "command"));
+
+    let history: Vec<(i64, i64)> = redis::cmd("LATENCY")
+        .arg("HISTORY")
+        .arg("command")
+        .query(&mut con)
+        .unwrap();
+    assert!(!history.is_empty());
+    let history: Vec<(i64, i64)> = redis::cmd("LATENCY")
+        .arg("HISTORY")
+        .arg("nope")
+        .query(&mut con)
+        .unwrap();
+    assert!(history.is_empty());
+
+    let doctor: String = redis::cmd("LATENCY").arg("DOCTOR").query(&mut con).unwrap();
+    assert!(doctor.contains(". command: "));
+
+    let reset: i64 = redis::cmd("LATENCY")
+        .arg("RESET")
+        .arg("command")
+        .arg("nope")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(reset, 1);
+
+    let err = redis::cmd("LATENCY")
+        .arg("NOPE")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert_eq!(err.code(), Some("ERR"));
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+
+    // disabled by default
+    let (server, mut con) = get_redis_client_connection();
+    let doctor: String = redis::cmd("LATENCY").arg("DOCTOR").query(&mut con).unwrap();
+    assert!(doctor.contains("Latency monitoring is disabled"));
+    let latest: Vec<(String, i64, i64, i64)> =
+        redis::cmd("LATENCY").arg("LATEST").query(&mut con).unwrap();
+    assert!(latest.is_empty());
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn info_stats_and_resetstat() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let _: () = con.set("mykey", "value").unwrap();
+    let _: () = con.pset_ex("shortlived", "value", 1).unwrap();
+    let _: Option<String> = con.get("mykey").unwrap();
+    let _: Option<String> = con.get("missing").unwrap();
+    let _: Vec<Option<String>> = con.get(&["mykey", "missing", "other"]).unwrap();
+    // the expire cycle runs every 100ms
+    sleep(Duration::from_millis(300));
+
+    let info: String = redis::cmd("INFO").arg("stats").query(&mut con).unwrap();
+    assert!(info.starts_with("# Stats\r\n"));
+    assert!(info.contains("total_connections_received:1\r\n"));
+    assert!(info.contains("total_commands_processed:6\r\n"));
+    assert!(info.contains("expired_keys:1\r\n"));
+    assert!(info.contains("keyspace_hits:2\r\n"));
+    assert!(info.contains("keyspace_misses:3\r\n"));
+
+    let _: () = redis::cmd("CONFIG")
+        .arg("RESETSTAT")
+        .query(&mut con)
+        .unwrap();
+    let info: String = redis::cmd("INFO").arg("stats").query(&mut con).unwrap();
+    assert!(info.contains("total_connections_received:0\r\n"));
+    assert!(info.contains("total_commands_processed:1\r\n"));
+    assert!(info.contains("keyspace_hits:0\r\nkeyspace_misses:0\r\n"));
+
+    let err = redis::cmd("CONFIG")
+        .arg("NOPE")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert_eq!(err.code(), Some("ERR"));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn command_introspection() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let count: usize = redis::cmd("COMMAND").arg("COUNT").query(&mut con).unwrap();
+    assert_eq!(count, crate::command::SUPPORTED_COMMANDS.len());
+    let all: Vec<redis::Value> = redis::cmd("COMMAND").query(&mut con).unwrap();
+    assert_eq!(all.len(), count);
+
+    let infos: Vec<redis::Value> = redis::cmd("COMMAND")
+        .arg("INFO")
+        .arg("get")
+        .arg("MSET")
+        .arg("ping")
+        .arg("nope")
+        .query(&mut con)
+        .unwrap();
+    let info = |name: &str, arity, flags: &[&str], keys: [i64; 3]| {
+        redis::Value::Bulk(vec![
+            redis::Value::Data(name.as_bytes().to_vec()),
+            redis::Value::Int(arity),
+            redis::Value::Bulk(
+                flags
+                    .iter()
+                    .map(|flag| redis::Value::Status(flag.to_string()))
+                    .collect(),
+            ),
+            redis::Value::Int(keys[0]),
+            redis::Value::Int(keys[1]),
+            redis::Value::Int(keys[2]),
+        ])
+    };
+    assert_eq!(
+        infos,
+        vec![
+            info("get", 2, &["readonly"], [1, 1, 1]),
+            info("mset", -3, &["write"], [1, -1, 2]),
+            info("ping", -1, &[], [0, 0, 0]),
+            redis::Value::Nil,
+        ]
+    );
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn compare_and_set() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let version: u64 = redis::cmd("CASVERSION").arg("k").query(&mut con).unwrap();
+    assert_eq!(version, 0);
+    let version: Option<u64> = redis::cmd("CAS")
+        .arg("k")
+        .arg(0)
+        .arg("a")
+        .query(&mut con)
+        .unwrap();
+    let version = version.unwrap();
+    assert_eq!(
+        redis::cmd("CASVERSION")
+            .arg("k")
+            .query::<u64>(&mut con)
+            .unwrap(),
+        version
+    );
+
+    // someone else wrote in between
+    let _: () = con.set("k", "b").unwrap();
+    let res: Option<u64> = redis::cmd("CAS")
+        .arg("k")
+        .arg(version)
+        .arg("c")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(res, None);
+    let value: String = con.get("k").unwrap();
+    assert_eq!(value, "b");
+
+    let version: u64 = redis::cmd("CASVERSION").arg("k").query(&mut con).unwrap();
+    let res: Option<u64> = redis::cmd("CAS")
+        .arg("k")
+        .arg(version)
+        .arg("c")
+        .query(&mut con)
+        .unwrap();
+    assert!(res.is_some());
+    let value: String = con.get("k").unwrap();
+    assert_eq!(value, "c");
+
+    assert!(crate::capabilities::capabilities().supports_feature("cas"));
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn delete_by_pattern() {
+    let port = 3410;
+    let config = ServerConfig {
+        key_prefix: Some(b"app*:".to_vec()),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, port);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = redis::Client::open(format!("redis://127.0.0.1:{}/", port))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+
+    let _: () = con
+        .set_multiple(&[("user:1", "a"), ("user:2", "b"), ("session:1", "c")])
+        .unwrap();
+    let _: () = con.rpush("user:list", "d").unwrap();
+    let removed: u32 = redis::cmd("DELPATTERN")
+        .arg("user:*")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(removed, 3);
+    let keys: Vec<String> = con.scan().unwrap().collect();
+    assert_eq!(keys, vec!["session:1".to_string()]);
+
+    // the pattern only reaches the keys under the prefix
+    server.import(DatasetSnapshot::default());
+    let _: () = con.set("a", "1").unwrap();
+    let mut snapshot = server.export();
+    snapshot.entries.push(StorageEntry {
+        key: b"other".to_vec(),
+        value: RedisValue::String(b"2".to_vec()),
+        expiry: None,
+    });
+    server.import(snapshot);
+    let removed: u32 = redis::cmd("DELPATTERN").arg("*").query(&mut con).unwrap();
+    assert_eq!(removed, 1);
+
+    // the embedded API sees the keys as stored
+    assert_eq!(server.del_pattern(b"o*"), 1);
+    assert!(server.export().entries.is_empty());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn unsupported_commands_are_counted() {
+    let (server, mut con) = get_redis_client_connection();
+
+    for name in &["XADD", "xadd", "EVAL"] {
+        let err = redis::cmd(name).arg("k").query::<()>(&mut con).unwrap_err();
+        assert_eq!(err.code(), Some("ERR"));
+    }
+    let _: () = con.set("k", "v").unwrap();
+
+    let info: String = redis::cmd("INFO")
+        .arg("errorstats")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(
+        info,
+        "# Errorstats\r\nerrorstat_NotSupported:count=3\r\nnotsupported_eval:count=1\r\nnotsupported_xadd:count=2\r\n"
+    );
+    let info: String = redis::cmd("INFO").arg("all").query(&mut con).unwrap();
+    assert!(info.contains("notsupported_xadd:count=2\r\n"));
+
+    let _: () = redis::cmd("CONFIG")
+        .arg("RESETSTAT")
+        .query(&mut con)
+        .unwrap();
+    let info: String = redis::cmd("INFO")
+        .arg("errorstats")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(info, "# Errorstats\r\nerrorstat_NotSupported:count=0\r\n");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn memory_purge() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let keys: Vec<String> = (0..2000).map(|i| format!("key{}", i)).collect();
+    let items: Vec<(&str, &str)> = keys.iter().map(|key| (key.as_str(), "value")).collect();
+    let _: () = con.set_multiple(&items).unwrap();
+    let deleted: usize = con.del(&keys[1..]).unwrap();
+    assert_eq!(deleted, 1999);
+
+    let _: () = redis::cmd("MEMORY").arg("PURGE").query(&mut con).unwrap();
+    let res: String = con.get("key0").unwrap();
+    assert_eq!(res, "value");
+
+    let err = redis::cmd("MEMORY")
+        .arg("PURGE")
+        .arg("now")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert_eq!(err.code(), Some("ERR"));
+    let err = redis::cmd("MEMORY")
+        .arg("NOPE")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert_eq!(err.code(), Some("ERR"));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn export_and_import_a_running_server() {
+    let (server, mut con) = get_redis_client_connection();
+    let _: () = con.set("fixture", "value").unwrap();
+    let _: () = con.rpush("list", &["a", "b"]).unwrap();
+    let snapshot = server.export();
+    assert_eq!(snapshot.entries.len(), 2);
+
+    let _: () = con.set("fixture", "changed").unwrap();
+    let _: () = con.set("other", "value").unwrap();
+    server.import(snapshot);
+
+    let value: String = con.get("fixture").unwrap();
+    assert_eq!(value, "value");
+    let list: Vec<String> = con.lrange("list", 0, -1).unwrap();
+    assert_eq!(list, vec!["a", "b"]);
+    let exists: bool = con.exists("other").unwrap();
+    assert!(!exists);
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn key_prefix_namespaces_keys() {
+    let config = ServerConfig {
+        key_prefix: Some(b"suite*1:".to_vec()),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3396);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = redis::Client::open("redis://127.0.0.1:3396/")
+        .unwrap()
+        .get_connection()
+        .unwrap();
+
+    let _: () = con.set("a", "1").unwrap();
+    let _: () = con.set_multiple(&[("b", "2"), ("c", "3")]).unwrap();
+    let _: () = con.rpush("list", "x").unwrap();
+    let _: () = redis::cmd("RPOPLPUSH")
+        .arg("list")
+        .arg("other")
+        .query(&mut con)
+        .unwrap();
+    let values: Vec<String> = con.get(&["a", "b", "c"]).unwrap();
+    assert_eq!(values, vec!["1", "2", "3"]);
+
+    let snapshot = server.export();
+    let mut keys: Vec<Vec<u8>> = snapshot.entries.into_iter().map(|e| e.key).collect();
+    keys.sort();
+    assert_eq!(
+        keys,
+        vec![
+            b"suite*1:a".to_vec(),
+            b"suite*1:b".to_vec(),
+            b"suite*1:c".to_vec(),
+            b"suite*1:other".to_vec(),
+        ]
+    );
+
+    let mut scanned: Vec<String> = con.scan_match("*").unwrap().collect();
+    scanned.sort();
+    assert_eq!(scanned, vec!["a", "b", "c", "other"]);
+
+    // keys of other namespaces are left alone
+    let mut outside = InMemoryStorage::new();
+    outside.write(b"suite*2:a", b"1");
+    outside.write(b"suite*1:d", b"4");
+    let mut snapshot = server.export();
+    snapshot.entries.extend(outside.export().entries);
+    server.import(snapshot);
+    let size: i64 = redis::cmd("DBSIZE").query(&mut con).unwrap();
+    assert_eq!(size, 5);
+    let _: () = redis::cmd("FLUSHALL").query(&mut con).unwrap();
+    let size: i64 = redis::cmd("DBSIZE").query(&mut con).unwrap();
+    assert_eq!(size, 0);
+    let keys: Vec<Vec<u8>> = server.export().entries.into_iter().map(|e| e.key).collect();
+    assert_eq!(keys, vec![b"suite*2:a".to_vec()]);
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn virtual_instances_are_isolated() {
+    let (server, mut con) = get_redis_client_connection();
+    let first = server.virtual_instance("first");
+    let second = server.virtual_instance("second");
+    assert_eq!(server.virtual_instance("first"), first);
+    assert_ne!(first.token(), second.token());
+
+    assert!(first.url().contains(first.token()));
+    let connect = |instance: &VirtualHandle| {
+        let mut con = redis::Client::open(format!("redis://127.0.0.1:{}/", server.port()))
+            .unwrap()
+            .get_connection()
+            .unwrap();
+        let _: () = redis::cmd("AUTH")
+            .arg(instance.token())
+            .query(&mut con)
+            .unwrap();
+        con
+    };
+    let mut first_con = connect(&first);
+    let mut second_con = connect(&second);
+
+    let _: () = first_con.set("mykey", "first").unwrap();
+    let _: () = first_con.set("other", "first").unwrap();
+    let _: () = second_con.set("mykey", "second").unwrap();
+    let value: String = first_con.get("mykey").unwrap();
+    assert_eq!(value, "first");
+    let value: String = second_con.get("mykey").unwrap();
+    assert_eq!(value, "second");
+    let size: i64 = redis::cmd("DBSIZE").query(&mut first_con).unwrap();
+    assert_eq!(size, 2);
+    let size: i64 = redis::cmd("DBSIZE").query(&mut con).unwrap();
+    assert_eq!(size, 3);
+
+    let _: () = redis::cmd("FLUSHALL").query(&mut first_con).unwrap();
+    let exists: bool = first_con.exists("mykey").unwrap();
+    assert!(!exists);
+    let value: String = second_con.get("mykey").unwrap();
+    assert_eq!(value, "second");
+
+    let err = redis::cmd("AUTH")
+        .arg("nope")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert_eq!(err.code(), Some("WRONGPASS"));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn null_replies() {
+    let (server, _con) = get_redis_client_connection();
+
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+    for (request, reply) in &[
+        (
+            &b"*2\r\n$3\r\nGET\r\n$7\r\nmissing\r\n"[..],
+            &b"$-1\r\n"[..],
+        ),
+        (
+            b"*3\r\n$4\r\nMGET\r\n$7\r\nmissing\r\n$5\r\nother\r\n",
+            b"*2\r\n$-1\r\n$-1\r\n",
+        ),
+        (
+            b"*4\r\n$6\r\nLRANGE\r\n$7\r\nmissing\r\n$1\r\n0\r\n$2\r\n-1\r\n",
+            b"*0\r\n",
+        ),
+        (
+            b"*2\r\n$4\r\nSCAN\r\n$1\r\n0\r\n",
+            b"*2\r\n$1\r\n0\r\n*0\r\n",
+        ),
+        (
+            b"*3\r\n$8\r\nSENTINEL\r\n$23\r\nget-master-addr-by-name\r\n$5\r\nother\r\n",
+            b"*-1\r\n",
+        ),
+    ] {
+        stream.write_all(request).unwrap();
+        let mut res = vec![0; reply.len()];
+        stream.read_exact(&mut res).unwrap();
+        assert_eq!(res, *reply);
+    }
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn sentinel_reports_the_master() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let addr: (String, String) = redis::cmd("SENTINEL")
+        .arg("get-master-addr-by-name")
+        .arg("mymaster")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(addr, ("127.0.0.1".to_string(), server.port().to_string()));
+    let addr: Option<(String, String)> = redis::cmd("SENTINEL")
+        .arg("get-master-addr-by-name")
+        .arg("other")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(addr, None);
+
+    let masters: Vec<HashMap<String, String>> = redis::cmd("SENTINEL")
+        .arg("masters")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(masters.len(), 1);
+    assert_eq!(masters[0]["name"], "mymaster");
+    assert_eq!(masters[0]["port"], server.port().to_string());
+    assert_eq!(masters[0]["flags"], "master");
+    let replicas: Vec<HashMap<String, String>> = redis::cmd("SENTINEL")
+        .arg("slaves")
+        .arg("mymaster")
+        .query(&mut con)
+        .unwrap();
+    assert!(replicas.is_empty());
+
+    // a follower reports the leader, and itself as its replica
+    server.set_role(ServerRole::Follower {
+        leader: Some("127.0.0.1:6380".parse().unwrap()),
+    });
+    let addr: (String, String) = redis::cmd("SENTINEL")
+        .arg("get-master-addr-by-name")
+        .arg("mymaster")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(addr, ("127.0.0.1".to_string(), "6380".to_string()));
+    let replicas: Vec<HashMap<String, String>> = redis::cmd("SENTINEL")
+        .arg("replicas")
+        .arg("mymaster")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(replicas.len(), 1);
+    assert_eq!(replicas[0]["port"], server.port().to_string());
+    assert_eq!(replicas[0]["master-port"], "6380");
+
+    let err = redis::cmd("SENTINEL")
+        .arg("slaves")
+        .arg("other")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert_eq!(err.code(), Some("ERR"));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn command_journal_records_commands_in_order() {
+    let (server, mut con) = get_redis_client_connection();
+    let _: () = con.set("before", "value").unwrap();
+
+    let journal = server.command_journal();
+    let _: () = con.set("cache", "value").unwrap();
+    let _ = redis::cmd("NOPE").query::<()>(&mut con);
+    let _: () = con.del("cache").unwrap();
+
+    let commands: Vec<Command> = journal.try_iter().map(|entry| entry.command).collect();
+    assert_eq!(
+        commands,
+        vec![
+            Command::Set(b"cache".to_vec(), b"value".to_vec()),
+            Command::Del(vec![b"cache".to_vec()]),
+        ]
+    );
+
+    drop(journal);
+    let _: () = con.set("after", "value").unwrap();
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn http_healthz_and_metrics() {
+    let config = ServerConfig {
+        http_addr: Some("127.0.0.1:3401".parse().unwrap()),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3400);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = redis::Client::open("redis://127.0.0.1:3400/")
+        .unwrap()
+        .get_connection()
+        .unwrap();
+    let _: () = con.set("mykey", "value").unwrap();
+
+    let get = |path: &str| {
+        let mut stream = TcpStream::connect("127.0.0.1:3401").unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+
+    let response = get("/healthz");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nOK\n"));
+    let response = get("/metrics");
+    assert!(response.contains("# TYPE redisless_connected_clients gauge\n"));
+    assert!(response.contains("\nredisless_connected_clients 1\n"));
+    assert!(response.contains("\nredisless_commands_processed_total 1\n"));
+    assert!(get("/nope").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    drop(con);
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert!(TcpStream::connect("127.0.0.1:3401").is_err());
+}
+
+#[test]
+#[serial]
+fn renamed_and_disabled_commands() {
+    let config = ServerConfig {
+        rename_commands: vec![
+            ("FLUSHALL".to_string(), String::new()),
+            ("info".to_string(), "SECRET-INFO".to_string()),
+        ]
+        .into_iter()
+        .collect(),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3402);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut stream = TcpStream::connect("127.0.0.1:3402").unwrap();
+    let mut request = |request: &[u8]| {
+        stream.write_all(request).unwrap();
+        let mut buf = [0; 1024];
+        let len = stream.read(&mut buf).unwrap();
+        buf[..len].to_vec()
+    };
+
+    assert_eq!(
+        request(b"*1\r\n$8\r\nflushall\r\n"),
+        b"-ERR unknown command `flushall`, with args beginning with: \r\n".to_vec()
+    );
+    assert!(request(b"*2\r\n$4\r\nINFO\r\n$7\r\nclients\r\n").starts_with(b"-ERR unknown command"));
+    assert!(request(b"*2\r\n$11\r\nsecret-info\r\n$7\r\nclients\r\n").starts_with(b"$"));
+    assert_eq!(request(b"*1\r\n$4\r\nPING\r\n"), b"+PONG\r\n".to_vec());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn protected_mode_denies_remote_clients() {
+    // address of the interface routing outside, connecting a UDP socket sends nothing
+    let remote_ip = std::net::UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| socket.connect("192.0.2.1:9").map(|_| socket))
+        .and_then(|socket| socket.local_addr())
+        .map(|addr| addr.ip());
+    let remote_ip = match remote_ip {
+        Ok(ip) if !ip.is_loopback() => ip,
+        // only the loopback interface to connect from
+        _ => return,
+    };
+    let connect = |port: u16| {
+        let mut stream = TcpStream::connect((remote_ip, port)).unwrap();
+        stream.write_all(b"*1\r\n$4\r\nPING\r\n").unwrap();
+        let mut buf = [0; 2048];
+        let len = stream.read(&mut buf).unwrap();
+        buf[..len].to_vec()
+    };
+
+    let (server, _) = get_redis_client_connection();
+    assert!(connect(server.port()).starts_with(b"-DENIED Redis is running in protected mode"));
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+
+    let config = ServerConfig {
+        protected_mode: false,
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3403);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    assert_eq!(connect(3403), b"+PONG\r\n".to_vec());
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+
+    // remote clients are let in to authenticate once the default user has a password
+    let config = ServerConfig {
+        users: vec![AclUser::parse("user default on >secret allkeys allcommands").unwrap()],
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3403);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    assert!(connect(3403).starts_with(b"-NOAUTH"));
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn cluster_leader_follows_the_role() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let leader: Option<String> = redis::cmd("CLUSTER").arg("leader").query(&mut con).unwrap();
+    assert_eq!(
+        leader.as_deref(),
+        Some(format!("127.0.0.1:{}", server.port()).as_str())
+    );
+
+    server.set_role(ServerRole::Follower {
+        leader: Some("10.0.0.2:6379".parse().unwrap()),
+    });
+    let leader: Option<String> = redis::cmd("CLUSTER").arg("leader").query(&mut con).unwrap();
+    assert_eq!(leader.as_deref(), Some("10.0.0.2:6379"));
+
+    server.set_role(ServerRole::Follower { leader: None });
+    let leader: Option<String> = redis::cmd("CLUSTER").arg("leader").query(&mut con).unwrap();
+    assert_eq!(leader, None);
+
+    assert!(redis::cmd("CLUSTER")
+        .arg("nodes")
+        .query::<String>(&mut con)
+        .is_err());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn cluster_status_is_reported() {
+    let (server, mut con) = get_redis_client_connection();
+    let events = server.events();
+    let mut leader_changes =
+        std::iter::from_fn(|| events.recv_timeout(Duration::from_secs(1)).ok())
+            .filter(|event| matches!(event, ServerEvent::LeaderChanged(_)));
+
+    let err = redis::cmd("CLUSTER")
+        .arg("redisless-status")
+        .query::<String>(&mut con)
+        .unwrap_err();
+    assert!(err.to_string().contains("cluster support disabled"));
+    assert_eq!(server.cluster_status(), None);
+
+    let status = ClusterStatus {
+        node_id: "a".to_string(),
+        leader: Some("a".to_string()),
+        term: 3,
+        commit_index: 7,
+        last_index: 9,
+        peers: vec![
+            PeerStatus {
+                node_id: "b".to_string(),
+                match_index: Some(9),
+                lag: Some(0),
+                last_contact: Some(Instant::now()),
+            },
+            PeerStatus {
+                node_id: "c".to_string(),
+                match_index: Some(2),
+                lag: Some(7),
+                last_contact: None,
+            },
+        ],
+    };
+    server.set_cluster_status(status.clone());
+    assert_eq!(server.cluster_status(), Some(status.clone()));
+    assert_eq!(
+        leader_changes.next(),
+        Some(ServerEvent::LeaderChanged(Some("a".to_string())))
+    );
+
+    let info: String = redis::cmd("CLUSTER")
+        .arg("redisless-status")
+        .query(&mut con)
+        .unwrap();
+    assert!(info.starts_with(
+        "node_id:a\r\nleader:a\r\nterm:3\r\ncommit_index:7\r\nlast_index:9\r\npeers:2\r\n"
+    ));
+    assert!(info.contains("peer0:id=b,match_index=9,lag=0,last_contact_ms="));
+    assert!(info.ends_with("peer1:id=c,match_index=2,lag=7,last_contact_ms=-1\r\n"));
+
+    // the same leader again sends no event
+    server.set_cluster_status(ClusterStatus { term: 4, ..status });
+    server.set_cluster_status(ClusterStatus::default());
+    assert_eq!(
+        leader_changes.next(),
+        Some(ServerEvent::LeaderChanged(None))
+    );
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn cluster_failover_is_requested_from_the_raft_driver() {
+    let (server, mut con) = get_redis_client_connection();
+    let events = server.events();
+    let mut failovers = std::iter::from_fn(|| events.recv_timeout(Duration::from_secs(1)).ok())
+        .filter(|event| matches!(event, ServerEvent::FailoverRequested { .. }));
+    let mut failover = |force: bool| {
+        let mut cmd = redis::cmd("CLUSTER");
+        cmd.arg("failover");
+        if force {
+            cmd.arg("force");
+        }
+        cmd.query::<String>(&mut con).map_err(|err| err.to_string())
+    };
+
+    assert!(failover(false)
+        .unwrap_err()
+        .contains("cluster support disabled"));
+
+    let status = ClusterStatus {
+        node_id: "a".to_string(),
+        leader: Some("a".to_string()),
+        ..ClusterStatus::default()
+    };
+    server.set_cluster_status(status.clone());
+    assert!(failover(true).unwrap_err().contains("to a replica"));
+
+    server.set_cluster_status(ClusterStatus {
+        leader: None,
+        ..status.clone()
+    });
+    assert!(failover(false)
+        .unwrap_err()
+        .contains("please use CLUSTER FAILOVER FORCE"));
+    assert_eq!(failover(true), Ok("OK".to_string()));
+    assert_eq!(
+        failovers.next(),
+        Some(ServerEvent::FailoverRequested { force: true })
+    );
+
+    server.set_cluster_status(ClusterStatus {
+        leader: Some("b".to_string()),
+        ..status
+    });
+    assert_eq!(failover(false), Ok("OK".to_string()));
+    assert_eq!(
+        failovers.next(),
+        Some(ServerEvent::FailoverRequested { force: false })
+    );
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn object_encoding_follows_the_config() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let _: () = con.set("counter", 10).unwrap();
+    let _: () = con.sadd("ints", &[1, 2, 3][..]).unwrap();
+    let _: () = con.rpush("list", &["a", "b"][..]).unwrap();
+    let _: () = con.hset("hash", "f", "v").unwrap();
+    let encoding = |con: &mut Connection, key: &str| -> Option<String> {
+        redis::cmd("OBJECT")
+            .arg("encoding")
+            .arg(key)
+            .query(con)
+            .unwrap()
+    };
+    assert_eq!(encoding(&mut con, "counter").as_deref(), Some("int"));
+    assert_eq!(encoding(&mut con, "ints").as_deref(), Some("intset"));
+    assert_eq!(encoding(&mut con, "list").as_deref(), Some("listpack"));
+    assert_eq!(encoding(&mut con, "hash").as_deref(), Some("listpack"));
+    assert_eq!(encoding(&mut con, "missing"), None);
+
+    let config: Vec<String> = redis::cmd("CONFIG")
+        .arg("get")
+        .arg("set-max-*")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(
+        config,
+        vec![
+            "set-max-intset-entries",
+            "512",
+            "set-max-listpack-entries",
+            "128",
+            "set-max-listpack-value",
+            "64"
+        ]
+    );
+
+    let _: () = redis::cmd("CONFIG")
+        .arg("set")
+        .arg("set-max-intset-entries")
+        .arg("3")
+        .query(&mut con)
+        .unwrap();
+    let _: () = con.sadd("ints", 4).unwrap();
+    assert_eq!(encoding(&mut con, "ints").as_deref(), Some("hashtable"));
+    let _: () = con.sadd("words", "a").unwrap();
+    assert_eq!(encoding(&mut con, "words").as_deref(), Some("listpack"));
+
+    let err = redis::cmd("CONFIG")
+        .arg("set")
+        .arg("set-max-intset-entries")
+        .arg("many")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("couldn't be parsed into an integer"));
+    let err = redis::cmd("CONFIG")
+        .arg("set")
+        .arg("nope")
+        .arg("1")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert!(err.to_string().contains("Unknown option"));
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn read_only_servers_refuse_writes() {
+    let mut storage = InMemoryStorage::new();
+    storage.write(b"key", b"value");
+    let config = ServerConfig {
+        read_only: true,
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config_on_free_port(storage, config, 0..=0).unwrap();
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let redis_client =
+        redis::Client::open(format!("redis://127.0.0.1:{}/", server.port())).unwrap();
+    let mut con = redis_client.get_connection().unwrap();
+
+    let value: String = con.get("key").unwrap();
+    assert_eq!(value, "value");
+    let err = con.set::<_, _, ()>("key", "other").unwrap_err();
+    assert_eq!(err.code(), Some("READONLY"));
+    let err = con.del::<_, ()>("key").unwrap_err();
+    assert_eq!(err.code(), Some("READONLY"));
+    let value: String = con.get("key").unwrap();
+    assert_eq!(value, "value");
+
+    let config: Vec<String> = redis::cmd("CONFIG")
+        .arg("get")
+        .arg("read-only")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(config, vec!["read-only", "yes"]);
+    let err = redis::cmd("CONFIG")
+        .arg("set")
+        .arg("read-only")
+        .arg("maybe")
+        .query::<()>(&mut con)
+        .unwrap_err();
+    assert!(err.to_string().contains("must be 'yes' or 'no'"));
+
+    let _: () = redis::cmd("CONFIG")
+        .arg("set")
+        .arg("read-only")
+        .arg("no")
+        .query(&mut con)
+        .unwrap();
+    let _: () = con.set("key", "other").unwrap();
+    let value: String = con.get("key").unwrap();
+    assert_eq!(value, "other");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn writes_past_the_memory_limit_are_refused() {
+    let serve = |config: ServerConfig| {
+        let server =
+            Server::new_with_config_on_free_port(InMemoryStorage::new(), config, 0..=0).unwrap();
+        assert_eq!(server.start(), Some(ServerState::Started));
+        let redis_client =
+            redis::Client::open(format!("redis://127.0.0.1:{}/", server.port())).unwrap();
+        let con = redis_client.get_connection().unwrap();
+        (server, con)
+    };
+
+    let (server, mut con) = serve(ServerConfig {
+        max_keys: Some(2),
+        ..ServerConfig::default()
+    });
+    let _: () = con.set("a", "1").unwrap();
+    let _: () = con.rpush("list", "x").unwrap();
+    let err = con.set::<_, _, ()>("b", "1").unwrap_err();
+    assert_eq!(err.code(), Some("OOM"));
+    assert!(err
+        .to_string()
+        .contains("command not allowed when used memory > 'maxmemory'"));
+    // no key is added by writing those there are
+    let _: () = con.set("a", "2").unwrap();
+    let _: () = con.rpush("list", "y").unwrap();
+    let err = con
+        .set_multiple::<_, _, ()>(&[("a", "3"), ("b", "3")])
+        .unwrap_err();
+    assert_eq!(err.code(), Some("OOM"));
+    let value: String = con.get("a").unwrap();
+    assert_eq!(value, "2");
+    // removing keys makes room
+    let _: () = con.del("list").unwrap();
+    let _: () = con.set("b", "1").unwrap();
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+
+    let (server, mut con) = serve(ServerConfig {
+        max_value_bytes: Some(8),
+        ..ServerConfig::default()
+    });
+    let _: () = con.set("a", "12345").unwrap();
+    // reaching the limit goes through, as Redis only checks it before running a command
+    let _: () = con.append("a", "6789").unwrap();
+    let err = con.sadd::<_, _, ()>("set", "m").unwrap_err();
+    assert_eq!(err.code(), Some("OOM"));
+    let err = con.incr::<_, _, ()>("counter", 1).unwrap_err();
+    assert_eq!(err.code(), Some("OOM"));
+    let _: () = con.expire("a", 100).unwrap();
+    let value: String = con.get("a").unwrap();
+    assert_eq!(value, "123456789");
+    let _: () = con.del("a").unwrap();
+    let _: () = con.sadd("set", "m").unwrap();
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn keys_are_reported_by_type() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let info: String = redis::cmd("INFO").arg("keyspace").query(&mut con).unwrap();
+    assert_eq!(info, "# Keyspace\r\n");
+
+    let _: () = con.set("a", "value").unwrap();
+    let _: () = con.set("b", "value").unwrap();
+    let _: () = con.rpush("list", "value").unwrap();
+    let _: () = con.sadd("set", "member").unwrap();
+    let info: String = redis::cmd("INFO").arg("keyspace").query(&mut con).unwrap();
+    assert_eq!(
+        info,
+        "# Keyspace\r\ndb0:keys=4,string=2,list=1,set=1,hash=0\r\n"
+    );
+    let info: String = redis::cmd("INFO").arg("all").query(&mut con).unwrap();
+    assert!(info.contains("db0:keys=4,"));
+
+    let stats: Vec<(String, i64)> = redis::cmd("DEBUG")
+        .arg("typestats")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(
+        stats,
+        vec![
+            ("string".to_string(), 2),
+            ("list".to_string(), 1),
+            ("set".to_string(), 1),
+            ("hash".to_string(), 0),
+        ]
+    );
+    assert!(redis::cmd("DEBUG")
+        .arg("typestats")
+        .arg("extra")
+        .query::<String>(&mut con)
+        .is_err());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn datasets_are_preloaded_from_files() {
+    use std::fs;
+    use std::io::ErrorKind;
+
+    let dir = std::env::temp_dir();
+    let resp_path = dir.join(format!("redisless-preload-{}.resp", std::process::id()));
+    let csv_path = dir.join(format!("redisless-preload-{}.csv", std::process::id()));
+
+    let mut resp = RespWriter::default();
+    for n in 0..1000 {
+        resp.array_len(3);
+        resp.bulk_string(b"SET");
+        resp.bulk_string(format!("key:{}", n).as_bytes());
+        resp.bulk_string(n.to_string().as_bytes());
+    }
+    resp.array_len(4);
+    resp.bulk_string(b"RPUSH");
+    resp.bulk_string(b"list");
+    resp.bulk_string(b"a");
+    resp.bulk_string(b"b");
+    fs::write(&resp_path, resp.as_slice()).unwrap();
+    fs::write(
+        &csv_path,
+        "plain,value\r\n\"with,comma\",\"say \"\"hi\"\"\nbye\"\n\nempty,\n",
+    )
+    .unwrap();
+
+    let config = ServerConfig {
+        key_prefix: Some(b"app:".to_vec()),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, 3419);
+    // before clients connect
+    assert_eq!(server.preload_from_resp_file(&resp_path).unwrap(), 1001);
+    assert_eq!(server.preload_from_csv_file(&csv_path).unwrap(), 3);
+    assert_eq!(server.start(), Some(ServerState::Started));
+
+    let client = redis::Client::open("redis://127.0.0.1:3419/").unwrap();
+    let mut con = client.get_connection().unwrap();
+    let value: String = con.get("key:999").unwrap();
+    assert_eq!(value, "999");
+    let list: Vec<String> = con.lrange("list", 0, -1).unwrap();
+    assert_eq!(list, vec!["a", "b"]);
+    // CSV keys are written as they are
+    let dataset = server.export();
+    let value = |key: &[u8]| {
+        dataset
+            .entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .map(|entry| entry.value.clone())
+    };
+    assert_eq!(
+        value(b"with,comma"),
+        Some(RedisValue::String(b"say \"hi\"\nbye".to_vec()))
+    );
+    assert_eq!(value(b"empty"), Some(RedisValue::String(Vec::new())));
+    assert!(value(b"plain").is_some());
+
+    // the commands before a failing one stay applied
+    fs::write(
+        &resp_path,
+        b"*3\r\n$3\r\nSET\r\n$1\r\nx\r\n$1\r\n1\r\n*2\r\n$4\r\nINCR\r\n$4\r\nlist\r\n*1\r\n$4\r\nPING\r\n",
+    )
+    .unwrap();
+    let err = server.preload_from_resp_file(&resp_path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("command 2: "));
+    let value: String = con.get("x").unwrap();
+    assert_eq!(value, "1");
+
+    fs::write(&resp_path, b"*3\r\n$3\r\nSET\r\n$1\r\nx").unwrap();
+    let err = server.preload_from_resp_file(&resp_path).unwrap_err();
+    assert_eq!(err.to_string(), "command 1: truncated command");
+
+    fs::write(&csv_path, "a,b\nc,d,e\n").unwrap();
+    let err = server.preload_from_csv_file(&csv_path).unwrap_err();
+    assert_eq!(err.to_string(), "line 2: expected 2 fields, found 3");
+    assert!(server
+        .preload_from_csv_file(dir.join("missing.csv"))
+        .is_err());
+    assert!(!server
+        .export()
+        .entries
+        .iter()
+        .any(|entry| entry.key == b"a"));
+
+    let _ = fs::remove_file(&resp_path);
+    let _ = fs::remove_file(&csv_path);
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn debug_reload_keeps_the_digest() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let digest: String = redis::cmd("DEBUG").arg("digest").query(&mut con).unwrap();
+    assert_eq!(digest, "0000000000000000");
+
+    let _: () = con.set_ex("string", "value", 100).unwrap();
+    let _: () = con.rpush("list", &["a", "b", "c"]).unwrap();
+    let _: () = con.sadd("set", &["m", "n"]).unwrap();
+    let _: () = con.hset("hash", "f", "v").unwrap();
+    let digest: String = redis::cmd("DEBUG").arg("digest").query(&mut con).unwrap();
+    assert_ne!(digest, "0000000000000000");
+
+    let _: () = redis::cmd("DEBUG").arg("reload").query(&mut con).unwrap();
+    let reloaded: String = redis::cmd("DEBUG").arg("digest").query(&mut con).unwrap();
+    assert_eq!(reloaded, digest);
+    let ttl: i64 = con.ttl("string").unwrap();
+    assert!(ttl > 0 && ttl <= 100);
+    let list: Vec<String> = con.lrange("list", 0, -1).unwrap();
+    assert_eq!(list, vec!["a", "b", "c"]);
+
+    let _: () = con.hset("hash", "f", "other").unwrap();
+    let changed: String = redis::cmd("DEBUG").arg("digest").query(&mut con).unwrap();
+    assert_ne!(changed, digest);
+
+    assert!(redis::cmd("DEBUG")
+        .arg("loadaof")
+        .query::<String>(&mut con)
+        .is_err());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn client_pause_holds_commands_back() {
+    let port = 3421;
+    let config = ServerConfig {
+        read_timeout: Some(Duration::from_secs(1)),
+        ..ServerConfig::default()
+    };
+    let server = Server::new_with_config(InMemoryStorage::new(), config, port);
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let connect = || {
+        redis::Client::open(format!("redis://127.0.0.1:{}/", port))
+            .unwrap()
+            .get_connection()
+            .unwrap()
+    };
+    let mut con = connect();
+    let mut other = connect();
+
+    // reads go on while writes wait for the end of the pause
+    let _: () = con.set("key", "before").unwrap();
+    let _: () = redis::cmd("CLIENT")
+        .arg("pause")
+        .arg(300)
+        .arg("write")
+        .query(&mut con)
+        .unwrap();
+    let started = Instant::now();
+    let value: String = other.get("key").unwrap();
+    assert_eq!(value, "before");
+    assert!(started.elapsed() < Duration::from_millis(200));
+    let _: () = other.set("key", "after").unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(250));
+
+    let _: () = redis::cmd("CLIENT")
+        .arg("pause")
+        .arg(300)
+        .query(&mut con)
+        .unwrap();
+    let started = Instant::now();
+    let value: String = other.get("key").unwrap();
+    assert_eq!(value, "after");
+    assert!(started.elapsed() >= Duration::from_millis(250));
+
+    let _: () = redis::cmd("CLIENT")
+        .arg("pause")
+        .arg(10_000)
+        .arg("write")
+        .query(&mut con)
+        .unwrap();
+    let _: () = redis::cmd("CLIENT").arg("unpause").query(&mut con).unwrap();
+    let started = Instant::now();
+    let _: () = other.set("key", "unpaused").unwrap();
+    assert!(started.elapsed() < Duration::from_millis(200));
+
+    for args in &[
+        &["pause", "soon"][..],
+        &["pause", "100", "reads"],
+        &["no-evict", "maybe"],
+    ] {
+        assert!(redis::cmd("CLIENT")
+            .arg(*args)
+            .query::<String>(&mut con)
+            .is_err());
+    }
+
+    // only the connection that set NO-EVICT outlives the read timeout
+    let _: () = redis::cmd("CLIENT")
+        .arg("no-evict")
+        .arg("on")
+        .query(&mut con)
+        .unwrap();
+    sleep(Duration::from_millis(1500));
+    let pong: String = redis::cmd("PING").query(&mut con).unwrap();
+    assert_eq!(pong, "PONG");
+    assert!(redis::cmd("PING").query::<String>(&mut other).is_err());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn snapshots_survive_restarts() {
+    use std::fs;
+    use std::io::ErrorKind;
+
+    let path = std::env::temp_dir().join(format!("redisless-snapshot-{}.resp", std::process::id()));
+    let (server, mut con) = get_redis_client_connection();
+    let _: () = con.set_ex("string", "value", 100).unwrap();
+    let _: () = con.rpush("list", &["a", "b"]).unwrap();
+    server.save_snapshot(&path).unwrap();
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+
+    let (server, mut con) = get_redis_client_connection();
+    let _: () = con.set("other", "value").unwrap();
+    assert_eq!(server.load_snapshot(&path).unwrap(), 2);
+    let value: String = con.get("string").unwrap();
+    assert_eq!(value, "value");
+    let ttl: i64 = con.ttl("string").unwrap();
+    assert!(ttl > 0 && ttl <= 100);
+    let list: Vec<String> = con.lrange("list", 0, -1).unwrap();
+    assert_eq!(list, vec!["a", "b"]);
+    let exists: bool = con.exists("other").unwrap();
+    assert!(!exists);
+
+    fs::write(&path, b"+OK\r\n").unwrap();
+    let err = server.load_snapshot(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let dbsize: usize = redis::cmd("DBSIZE").query(&mut con).unwrap();
+    assert_eq!(dbsize, 2);
+
+    let _ = fs::remove_file(&path);
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn snapshots_are_saved_on_stop_and_loaded_on_start() {
+    use std::fs;
+
+    let path = std::env::temp_dir().join(format!(
+        "redisless-snapshot-on-stop-{}.resp",
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    let serve = || {
+        let config = ServerConfig {
+            snapshot_on_stop: Some(path.clone()),
+            ..ServerConfig::default()
+        };
+        Server::new_with_config_on_free_port(InMemoryStorage::new(), config, 0..=0).unwrap()
+    };
+    let connect = |server: &Server| {
+        redis::Client::open(format!("redis://127.0.0.1:{}/", server.port()))
+            .unwrap()
+            .get_connection()
+            .unwrap()
+    };
+
+    // nothing to load yet
+    let server = serve();
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = connect(&server);
+    let _: () = con.set("string", "value").unwrap();
+    let _: () = con.rpush("list", &["a", "b"]).unwrap();
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert!(path.exists());
+
+    let other = serve();
+    assert_eq!(other.start(), Some(ServerState::Started));
+    let mut con = connect(&other);
+    let value: String = con.get("string").unwrap();
+    assert_eq!(value, "value");
+    let list: Vec<String> = con.lrange("list", 0, -1).unwrap();
+    assert_eq!(list, vec!["a", "b"]);
+    let _: () = con.del("list").unwrap();
+    assert_eq!(other.stop(), Some(ServerState::Stopped));
+
+    // what was written since is saved again
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = connect(&server);
+    let exists: bool = con.exists("list").unwrap();
+    assert!(!exists);
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+
+    fs::write(&path, b"+OK\r\n").unwrap();
+    assert!(matches!(serve().start(), Some(ServerState::Error(_))));
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn commands_are_answered_busy_while_one_holds_the_storage() {
+    let storage = StripedStorage::new(InMemoryStorage::new(), DEFAULT_STRIPES);
+    let stats = ServerStats::new(10).with_busy_threshold(Some(Duration::from_millis(100)));
+    let run = |request: &[u8]| {
+        let mut writer = RespWriter::default();
+        run_command_and_get_response(&storage, &stats, &mut ConnectionContext::default(), request)
+            .write_to(&mut writer);
+        writer.as_slice().to_vec()
+    };
+    let get = b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n";
+    let (held_send, held_recv) = crossbeam_channel::bounded(0);
+
+    std::thread::scope(|scope| {
+        // a command holding the storage for longer than the threshold
+        scope.spawn(|| {
+            let _running = stats
+                .watchdog()
+                .admit(&storage.all_stripes(), "debug")
+                .unwrap();
+            let _storage = storage.lock_all();
+            held_send.send(()).unwrap();
+            sleep(Duration::from_millis(400));
+        });
+        held_recv.recv().unwrap();
+
+        let started = Instant::now();
+        assert_eq!(
+            run(get),
+            b"-BUSY Redis is busy running a command, try again once it's done\r\n".to_vec()
+        );
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(90) && elapsed < Duration::from_millis(350));
+        // connection commands don't need the storage
+        assert_eq!(run(b"*1\r\n$4\r\nPING\r\n"), b"+PONG\r\n".to_vec());
+    });
+
+    assert_eq!(run(get), b"$-1\r\n".to_vec());
+}
+
+#[test]
+fn expired_lists_and_sets_are_treated_as_missing() {
+    let storage = StripedStorage::new(InMemoryStorage::new(), DEFAULT_STRIPES);
+    let stats = ServerStats::new(10);
+    let run = |args: &[&str]| {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        let mut writer = RespWriter::default();
+        let mut context = ConnectionContext::default();
+        run_command_and_get_response(&storage, &stats, &mut context, request.as_bytes())
+            .write_to(&mut writer);
+        String::from_utf8(writer.as_slice().to_vec()).unwrap()
+    };
+
+    // expired, but left in the storage as no command touched them since. Each command gets
+    // its own key, the first one to look at a key removes it.
+    for key in ["lindex", "lset", "linsert", "ltrim", "lrem"] {
+        let _ = run(&["RPUSH", key, "a", "b"]);
+        let _ = run(&["PEXPIRE", key, "1"]);
+    }
+    for key in ["scard", "srem"] {
+        let _ = run(&["SADD", key, "a", "b"]);
+        let _ = run(&["PEXPIRE", key, "1"]);
+    }
+    sleep(Duration::from_millis(10));
+    assert_eq!(storage.lock_all().size(), 7);
+
+    assert_eq!(run(&["LINDEX", "lindex", "0"]), "$-1\r\n");
+    assert_eq!(run(&["LSET", "lset", "0", "c"]), "-ERR no such key\r\n");
+    assert_eq!(run(&["LINSERT", "linsert", "BEFORE", "a", "c"]), ":0\r\n");
+    assert_eq!(run(&["LTRIM", "ltrim", "0", "1"]), "+OK\r\n");
+    assert_eq!(run(&["LREM", "lrem", "0", "a"]), ":0\r\n");
+    assert_eq!(run(&["SCARD", "scard"]), ":0\r\n");
+    assert_eq!(run(&["SREM", "srem", "a"]), ":0\r\n");
+    assert_eq!(storage.lock_all().size(), 0);
+}
+
+#[test]
+fn expired_keys_have_no_ttl() {
+    let storage = StripedStorage::new(InMemoryStorage::new(), DEFAULT_STRIPES);
+    let stats = ServerStats::new(10);
+    let run = |args: &[&str]| {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        let mut writer = RespWriter::default();
+        let mut context = ConnectionContext::default();
+        run_command_and_get_response(&storage, &stats, &mut context, request.as_bytes())
+            .write_to(&mut writer);
+        String::from_utf8(writer.as_slice().to_vec()).unwrap()
+    };
+
+    // expired, but left in the storage as no command touched them since
+    for key in ["ttl", "pttl"] {
+        let _ = run(&["SET", key, "v"]);
+        let _ = run(&["PEXPIRE", key, "1"]);
+    }
+    sleep(Duration::from_millis(10));
+    assert_eq!(storage.lock_all().size(), 2);
+
+    assert_eq!(run(&["TTL", "ttl"]), ":-2\r\n");
+    assert_eq!(run(&["PTTL", "pttl"]), ":-2\r\n");
+    assert_eq!(storage.lock_all().size(), 0);
+}
+
+#[test]
+fn string_reads_refuse_other_types() {
+    let storage = StripedStorage::new(InMemoryStorage::new(), DEFAULT_STRIPES);
+    let stats = ServerStats::new(10);
+    let run = |args: &[&str]| {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        let mut writer = RespWriter::default();
+        let mut context = ConnectionContext::default();
+        run_command_and_get_response(&storage, &stats, &mut context, request.as_bytes())
+            .write_to(&mut writer);
+        String::from_utf8(writer.as_slice().to_vec()).unwrap()
+    };
+    let wrong_type = "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+
+    let _ = run(&["SET", "string", "v"]);
+    let _ = run(&["RPUSH", "list", "a"]);
+    let _ = run(&["SADD", "set", "a"]);
+    let _ = run(&["HSET", "hash", "f", "v"]);
+    for key in ["list", "set", "hash"] {
+        assert_eq!(run(&["GET", key]), wrong_type);
+        assert_eq!(run(&["GETSET", key, "v"]), wrong_type);
+    }
+    assert_eq!(run(&["TYPE", "list"]), "+list\r\n");
+
+    // MGET answers nil for them instead
+    assert_eq!(
+        run(&["MGET", "string", "list", "set", "hash", "missing"]),
+        "*5\r\n$1\r\nv\r\n$-1\r\n$-1\r\n$-1\r\n$-1\r\n"
+    );
+}
+
+#[test]
+#[serial]
+fn debug_digest_value_ignores_keys_and_expiries() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let _: () = con.set("a", "value").unwrap();
+    let _: () = con.set_ex("b", "value", 100).unwrap();
+    let _: () = con.set("c", "other").unwrap();
+    let _: () = con.sadd("s1", &["m", "n"]).unwrap();
+    let _: () = con.sadd("s2", &["n", "m"]).unwrap();
+    let digests: Vec<String> = redis::cmd("DEBUG")
+        .arg("digest-value")
+        .arg(&["a", "b", "c", "s1", "s2", "missing"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(digests[0], digests[1]);
+    assert_ne!(digests[0], digests[2]);
+    assert_eq!(digests[3], digests[4]);
+    assert_eq!(digests[5], "0000000000000000");
+
+    let digests: Vec<String> = redis::cmd("DEBUG")
+        .arg("digest-value")
+        .query(&mut con)
+        .unwrap();
+    assert!(digests.is_empty());
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn overwrites_keep_or_clear_the_expiry_as_redis_does() {
+    let (server, mut con) = get_redis_client_connection();
+
+    // command run against "key" holding the value created by the first one, with a TTL, and
+    // whether "key", or "renamed" for RENAME, still has the TTL afterwards
+    let matrix: &[(&[&str], &[&str], bool)] = &[
+        (&["SET", "key", "1"], &["SET", "key", "2"], false),
+        (&["SET", "key", "1"], &["SET", "key", "2", "KEEPTTL"], true),
+        (&["SET", "key", "1"], &["GETSET", "key", "2"], false),
+        (&["SET", "key", "1"], &["MSET", "key", "2"], false),
+        (&["SET", "key", "1"], &["SETNX", "key", "2"], true),
+        (&["SET", "key", "1"], &["APPEND", "key", "2"], true),
+        (&["SET", "key", "1"], &["INCR", "key"], true),
+        (&["SET", "key", "1"], &["INCRBY", "key", "2"], true),
+        (&["SET", "key", "1"], &["DECR", "key"], true),
+        (
+            &["SET", "key", "1"],
+            &["BITFIELD", "key", "SET", "u8", "0", "1"],
+            true,
+        ),
+        (&["SET", "key", "1"], &["RENAME", "key", "renamed"], true),
+        (&["SADD", "key", "a", "b"], &["SET", "key", "2"], false),
+        (&["SADD", "key", "a", "b"], &["SADD", "key", "c"], true),
+        (&["SADD", "key", "a", "b"], &["SREM", "key", "a"], true),
+        (
+            &["SADD", "key", "a", "b"],
+            &["RENAME", "key", "renamed"],
+            true,
+        ),
+        (&["HSET", "key", "f", "v"], &["HSET", "key", "g", "w"], true),
+        (&["RPUSH", "key", "a", "b"], &["RPUSH", "key", "c"], true),
+        (&["RPUSH", "key", "a", "b"], &["LPUSH", "key", "c"], true),
+        (
+            &["RPUSH", "key", "a", "b"],
+            &["LSET", "key", "0", "c"],
+            true,
+        ),
+        (&["RPUSH", "key", "a", "b"], &["RPOP", "key"], true),
+        (
+            &["RPUSH", "key", "a", "b"],
+            &["RPOPLPUSH", "key", "key"],
+            true,
+        ),
+    ];
+
+    for (create, overwrite, kept) in matrix {
+        let _: () = redis::cmd("FLUSHALL").query(&mut con).unwrap();
+        let _: () = redis::cmd(create[0])
+            .arg(&create[1..])
+            .query(&mut con)
+            .unwrap();
+        let _: () = con.expire("key", 100).unwrap();
+
+        let _: redis::Value = redis::cmd(overwrite[0])
+            .arg(&overwrite[1..])
+            .query(&mut con)
+            .unwrap();
+        let key = if overwrite[0] == "RENAME" {
+            "renamed"
+        } else {
+            "key"
+        };
+        let ttl: i64 = con.pttl(key).unwrap();
+        assert_eq!(ttl > 0, *kept, "{:?} then {:?}", create, overwrite);
+        if !kept {
+            assert_eq!(ttl, -1, "{:?} then {:?}", create, overwrite);
+        }
+    }
+
+    let result: RedisResult<()> = redis::cmd("RENAME")
+        .arg("missing")
+        .arg("other")
+        .query(&mut con);
+    assert_eq!(result.unwrap_err().to_string(), "ERR: no such key");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn hash_fields_expire() {
+    let (server, mut con) = get_redis_client_connection();
+
+    let _: () = con
+        .hset_multiple("hash", &[("f", "1"), ("g", "2"), ("h", "3")])
+        .unwrap();
+    let _: () = con.expire("hash", 100).unwrap();
+    let field_command = |name: &str, args: &[&str], fields: &[&str]| {
+        let mut cmd = redis::cmd(name);
+        cmd.arg("hash")
+            .arg(args)
+            .arg("FIELDS")
+            .arg(fields.len())
+            .arg(fields);
+        cmd
+    };
+
+    let replies: Vec<i64> = field_command("HEXPIRE", &["100"], &["f", "missing"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(replies, vec![1, -2]);
+    let replies: Vec<i64> = field_command("HPEXPIRE", &["100"], &["g"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(replies, vec![1]);
+    let replies: Vec<i64> = field_command("HTTL", &[], &["f", "h", "missing"])
+        .query(&mut con)
+        .unwrap();
+    assert!(replies[0] > 90 && replies[0] <= 100);
+    assert_eq!(replies[1..], [-1, -2]);
+    let replies: Vec<i64> = field_command("HPERSIST", &[], &["f", "h", "missing"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(replies, vec![1, -1, -2]);
+    // the key keeps its own expiry
+    let ttl: i64 = con.ttl("hash").unwrap();
+    assert!(ttl > 90);
+
+    // the field expired by HPEXPIRE is gone once read
+    sleep(Duration::from_millis(150));
+    let value: Option<String> = con.hget("hash", "g").unwrap();
+    assert_eq!(value, None);
+    let replies: Vec<i64> = field_command("HPTTL", &[], &["g"]).query(&mut con).unwrap();
+    assert_eq!(replies, vec![-2]);
+    let all: HashMap<String, String> = con.hgetall("hash").unwrap();
+    assert_eq!(all.len(), 2);
+
+    // HSET drops the expiry of the fields it sets
+    let _: Vec<i64> = field_command("HEXPIRE", &["100"], &["f"])
+        .query(&mut con)
+        .unwrap();
+    let _: () = con.hset("hash", "f", "4").unwrap();
+    let replies: Vec<i64> = field_command("HTTL", &[], &["f"]).query(&mut con).unwrap();
+    assert_eq!(replies, vec![-1]);
+
+    // a deadline already past deletes the fields, and the key with the last one
+    let replies: Vec<i64> = field_command("HEXPIRE", &["0"], &["f", "h"])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(replies, vec![2, 2]);
+    let exists: bool = con.exists("hash").unwrap();
+    assert!(!exists);
+    let replies: Vec<i64> = field_command("HTTL", &[], &["f"]).query(&mut con).unwrap();
+    assert_eq!(replies, vec![-2]);
+
+    let _: () = con.set("string", "value").unwrap();
+    let result: RedisResult<Vec<i64>> = redis::cmd("HTTL")
+        .arg(&["string", "FIELDS", "1", "f"])
+        .query(&mut con);
+    assert_eq!(result.unwrap_err().code(), Some("WRONGTYPE"));
+    for (args, error) in [
+        (
+            &["hash", "10", "FIELDS", "2", "f"][..],
+            "The `numfields` parameter must match the number of arguments",
+        ),
+        (
+            &["hash", "10", "FIELDS", "0", "f"],
+            "Parameter `numFields` should be greater than 0",
+        ),
+        (&["hash", "10", "NX", "FIELDS", "1", "f"], "syntax error"),
+    ] {
+        let result: RedisResult<Vec<i64>> = redis::cmd("HEXPIRE").arg(args).query(&mut con);
+        assert_eq!(result.unwrap_err().detail(), Some(error));
+    }
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn blpop_waits_for_a_push() {
+    let (server, mut con) = get_redis_client_connection();
+    let port = server.port();
+
+    // a key already holding elements is popped right away, a missing one times out
+    let _: () = con.rpush("ready", &["a", "b"]).unwrap();
+    let popped: (String, String) = redis::cmd("BLPOP")
+        .arg("missing")
+        .arg("ready")
+        .arg(0)
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(popped, ("ready".to_string(), "a".to_string()));
+    let started = Instant::now();
+    let popped: Option<(String, String)> = redis::cmd("BLPOP")
+        .arg("missing")
+        .arg(0.2)
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(popped, None);
+    assert!(started.elapsed() >= Duration::from_millis(200));
+
+    let _: () = con.set("string", "value").unwrap();
+    let result: RedisResult<Option<(String, String)>> =
+        redis::cmd("BLPOP").arg("string").arg(0).query(&mut con);
+    assert_eq!(result.unwrap_err().code(), Some("WRONGTYPE"));
+
+    // the requests pipelined after a blocked command wait along with it
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .write_all(b"*3\r\n$5\r\nBLPOP\r\n$5\r\nqueue\r\n$1\r\n0\r\n*1\r\n$4\r\nPING\r\n")
+        .unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let mut buf = [0; 64];
+    assert!(stream.read(&mut buf).is_err());
+
+    let info: String = redis::cmd("INFO").arg("clients").query(&mut con).unwrap();
+    assert!(info.contains("blocked_clients:1\r\n"));
+    let _: () = con.rpush("queue", "job").unwrap();
+
+    let expected = b"*2\r\n$5\r\nqueue\r\n$3\r\njob\r\n+PONG\r\n";
+    let mut received = Vec::new();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    while received.len() < expected.len() {
+        let len = stream.read(&mut buf).unwrap();
+        assert!(len > 0);
+        received.extend_from_slice(&buf[..len]);
+    }
+    assert_eq!(received, expected.to_vec());
+    let len: usize = con.llen("queue").unwrap();
+    assert_eq!(len, 0);
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+fn blocked_clients_wake_on_their_keys_only() {
+    let blocking = Blocking::default();
+    let a = "127.0.0.1:1001".parse().unwrap();
+    let b = "127.0.0.1:1002".parse().unwrap();
+    let queue = b"queue".to_vec();
+    let other = b"other".to_vec();
+    blocking.wait(a, &[queue.clone()]);
+    blocking.wait(b, &[other.clone(), queue.clone()]);
+
+    blocking.written(&[&b"unrelated".to_vec()]);
+    assert!(!blocking.is_ready(a) && !blocking.is_ready(b));
+    blocking.written(&[&other]);
+    assert!(!blocking.is_ready(a) && blocking.is_ready(b));
+
+    // waiting again starts over, stopping forgets the keys
+    blocking.wait(b, &[other.clone()]);
+    assert!(!blocking.is_ready(b));
+    blocking.stop(a);
+    blocking.written(&[&queue]);
+    assert!(!blocking.is_ready(a) && !blocking.is_ready(b));
+    blocking.stop(b);
+    assert!(!blocking.is_active());
+}
+
+#[test]
+#[serial]
+fn stop_closes_blocked_connections_right_away() {
+    let (server, mut con) = get_redis_client_connection();
+    let port = server.port();
+
+    let mut streams: Vec<TcpStream> = (0..48)
+        .map(|_| {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream
+                .write_all(b"*3\r\n$5\r\nBLPOP\r\n$5\r\nqueue\r\n$1\r\n0\r\n")
+                .unwrap();
+            stream
+        })
+        .collect();
+
+    let started = Instant::now();
+    loop {
+        let info: String = redis::cmd("INFO").arg("clients").query(&mut con).unwrap();
+        if info.contains("blocked_clients:48\r\n") {
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5), "{}", info);
+        sleep(Duration::from_millis(10));
+    }
+
+    // blocked commands don't hold the workers, the stop doesn't wait for them to time out
+    let started = Instant::now();
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    for stream in streams.iter_mut() {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    }
+
+    // the server serves again once restarted, the connections blocked before are gone
+    assert_eq!(server.start(), Some(ServerState::Started));
+    let mut con = redis::Client::open(format!("redis://127.0.0.1:{}/", port))
+        .unwrap()
+        .get_connection()
+        .unwrap();
+    let _: () = con.rpush("queue", "job").unwrap();
+    let len: usize = con.llen("queue").unwrap();
+    assert_eq!(len, 1);
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+#[serial]
+fn tracked_keys_are_invalidated_once_written() {
+    let (server, mut con) = get_redis_client_connection();
+    let connect = || {
+        let stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    };
+    let request = |stream: &mut TcpStream, args: &[&str]| {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        stream.write_all(request.as_bytes()).unwrap();
+    };
+    let expect = |stream: &mut TcpStream, expected: &[u8]| {
+        let mut received = vec![0; expected.len()];
+        stream.read_exact(&mut received).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&received),
+            String::from_utf8_lossy(expected)
+        );
+    };
+
+    // invalidations are pushed, which takes RESP3
+    let mut default = connect();
+    request(&mut default, &["CLIENT", "TRACKING", "ON"]);
+    expect(&mut default, b"-ERR Client tracking invalidation messages are pushed over RESP3, switch to it with HELLO 3 first\r\n");
+    let hello = b"%6\r\n$6\r\nserver\r\n$5\r\nredis\r\n$7\r\nversion\r\n$5\r\n7.4.0\r\n\
+          $5\r\nproto\r\n:3\r\n$4\r\nmode\r\n$10\r\nstandalone\r\n$4\r\nrole\r\n$6\r\nmaster\r\n\
+          $7\r\nmodules\r\n*0\r\n";
+    request(&mut default, &["HELLO", "3"]);
+    expect(&mut default, hello);
+    request(&mut default, &["CLIENT", "TRACKING", "ON"]);
+    expect(&mut default, b"+OK\r\n");
+
+    let mut bcast = connect();
+    request(&mut bcast, &["HELLO", "3"]);
+    expect(&mut bcast, hello);
+    request(
+        &mut bcast,
+        &["CLIENT", "TRACKING", "ON", "BCAST", "PREFIX", "user:"],
+    );
+    expect(&mut bcast, b"+OK\r\n");
+
+    // only the keys read by the connection are invalidated for it
+    let _: () = con.set("k", "v").unwrap();
+    let _: () = con.set("other", "v").unwrap();
+    request(&mut default, &["GET", "k"]);
+    expect(&mut default, b"$1\r\nv\r\n");
+    let _: () = con.set("other", "v2").unwrap();
+    let _: () = con.set("k", "v2").unwrap();
+    expect(
+        &mut default,
+        b">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n",
+    );
+
+    // until read again, a key is invalidated once
+    let _: () = con.set("k", "v3").unwrap();
+    request(&mut default, &["PING"]);
+    expect(&mut default, b"+PONG\r\n");
+
+    // in BCAST mode, every key starting with a prefix is, whether read or not
+    let _: () = con.set("user:1", "alice").unwrap();
+    expect(
+        &mut bcast,
+        b">2\r\n$10\r\ninvalidate\r\n*1\r\n$6\r\nuser:1\r\n",
+    );
+
+    // flushing invalidates every key
+    let _: () = redis::cmd("FLUSHALL").query(&mut con).unwrap();
+    expect(&mut default, b">2\r\n$10\r\ninvalidate\r\n_\r\n");
+    expect(&mut bcast, b">2\r\n$10\r\ninvalidate\r\n_\r\n");
+
+    // nothing is pushed once tracking is off
+    request(&mut default, &["GET", "k"]);
+    expect(&mut default, b"$-1\r\n");
+    request(&mut default, &["CLIENT", "TRACKING", "OFF"]);
+    expect(&mut default, b"+OK\r\n");
+    let _: () = con.set("k", "v").unwrap();
+    request(&mut default, &["PING"]);
+    expect(&mut default, b"+PONG\r\n");
+
+    assert_eq!(server.stop(), Some(ServerState::Stopped));
+}
+
+#[test]
+fn tracking_table_is_indexed_and_capped() {
+    let tracking = Tracking::new(2);
+    let reader: SocketAddr = "127.0.0.1:1".parse().unwrap();
+    let bcast: SocketAddr = "127.0.0.1:2".parse().unwrap();
+    let everything: SocketAddr = "127.0.0.1:3".parse().unwrap();
+    let key = |key: &str| key.as_bytes().to_vec();
+    let keys = |keys: &[&str]| Some(Invalidation::Keys(keys.iter().map(|k| key(k)).collect()));
+
+    tracking.start(reader, TrackingOptions::default(), None);
+    let prefixed = TrackingOptions {
+        bcast: true,
+        prefixes: vec![key("user:")],
+        noloop: false,
+    };
+    tracking.start(bcast, prefixed, None);
+    let all = TrackingOptions {
+        bcast: true,
+        ..TrackingOptions::default()
+    };
+    tracking.start(everything, all, None);
+
+    // only the connections that read a key, or with a prefix it starts with, are invalidated
+    tracking.read(reader, &[key("a"), key("b")]);
+    tracking.invalidate(None, &[&key("user:1")]);
+    assert_eq!(tracking.take(reader), None);
+    assert_eq!(tracking.take(bcast), keys(&["user:1"]));
+    assert_eq!(tracking.take(everything), keys(&["user:1"]));
+
+    // past the limit, a key read before is invalidated to make room
+    tracking.read(reader, &[key("c")]);
+    let evicted = match tracking.take(reader) {
+        Some(Invalidation::Keys(evicted)) => evicted,
+        invalidation => panic!("unexpected invalidation {:?}", invalidation),
+    };
+    assert_eq!(evicted.len(), 1);
+    assert!(evicted.contains(&key("a")) || evicted.contains(&key("b")));
+    tracking.invalidate(None, &[&key("c")]);
+    assert_eq!(tracking.take(reader), keys(&["c"]));
+    assert_eq!(tracking.take(everything), keys(&["c"]));
+
+    // every prefix a key starts with counts, the shorter ones included
+    let nested = TrackingOptions {
+        bcast: true,
+        prefixes: vec![key("u"), key("user:1")],
+        noloop: false,
+    };
+    tracking.start(bcast, nested, None);
+    tracking.invalidate(None, &[&key("user:10"), &key("users")]);
+    assert_eq!(tracking.take(bcast), keys(&["user:10", "users"]));
+    assert_eq!(tracking.take(everything), keys(&["user:10", "users"]));
+
+    // a connection no longer tracking is left out of the prefixes
+    tracking.stop(bcast);
+    tracking.invalidate(None, &[&key("user:2")]);
+    assert_eq!(tracking.take(bcast), None);
+    assert_eq!(tracking.take(everything), keys(&["user:2"]));
+
+    // nor does it hold on to the keys it read, which would take room from the others
+    let other: SocketAddr = "127.0.0.1:4".parse().unwrap();
+    tracking.read(reader, &[key("a"), key("b")]);
+    tracking.stop(reader);
+    tracking.start(other, TrackingOptions::default(), None);
+    tracking.read(other, &[key("d"), key("e")]);
+    assert_eq!(tracking.take(other), None);
+    tracking.invalidate(None, &[&key("a"), &key("d")]);
+    assert_eq!(tracking.take(reader), None);
+    assert_eq!(tracking.take(other), keys(&["d"]));
+}
+
+#[test]
+fn commands_on_keys_of_other_stripes_run_meanwhile() {
+    let storage = StripedStorage::new(InMemoryStorage::new(), DEFAULT_STRIPES);
+    let stats = ServerStats::new(10);
+    let run = |args: &[&str]| {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        let mut writer = RespWriter::default();
+        let mut context = ConnectionContext::default();
+        run_command_and_get_response(&storage, &stats, &mut context, request.as_bytes())
+            .write_to(&mut writer);
+        String::from_utf8(writer.as_slice().to_vec()).unwrap()
+    };
+
+    // as if a long LRANGE held the stripe of `list`
+    let held = storage.stripes_of(&["list"]).lock();
+    let key = (0..)
+        .map(|n| format!("key:{}", n))
+        .find(|key| storage.stripes_of(&[key]).are_free())
+        .unwrap();
+
+    std::thread::scope(|scope| {
+        let started = Instant::now();
+        let push = scope.spawn(|| run(&["RPUSH", "list", "a"]));
+        assert_eq!(run(&["SET", &key, "value"]), "+OK\r\n");
+        assert_eq!(run(&["GET", &key]), "$5\r\nvalue\r\n");
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        // the push waits for the stripe of its key
+        sleep(Duration::from_millis(100));
+        assert!(!push.is_finished());
+        drop(held);
+        assert_eq!(push.join().unwrap(), ":1\r\n");
+    });
+}
+
+#[test]
+fn multi_key_commands_lock_their_stripes_in_order() {
+    let storage = StripedStorage::new(InMemoryStorage::new(), DEFAULT_STRIPES);
+    let stats = ServerStats::new(10);
+    let run = |args: &[&str]| {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        let mut writer = RespWriter::default();
+        let mut context = ConnectionContext::default();
+        run_command_and_get_response(&storage, &stats, &mut context, request.as_bytes())
+            .write_to(&mut writer);
+    };
+    let held = storage.stripes_of(&["a"]).lock();
+    let b = (0..)
+        .map(|n| format!("b:{}", n))
+        .find(|key| storage.stripes_of(&[key]).are_free())
+        .unwrap();
+    drop(held);
+
+    // the same keys named the other way around, by commands running at the same time: locked
+    // in the order of the keys, one would wait for the other forever
+    let commands: [&[&str]; 4] = [
+        &["MSET", "a", "1", &b, "2"],
+        &["MSET", &b, "1", "a", "2"],
+        &["RENAME", "a", &b],
+        &["RENAME", &b, "a"],
+    ];
+    std::thread::scope(|scope| {
+        for command in &commands {
+            let run = &run;
+            scope.spawn(move || {
+                for _ in 0..1000 {
+                    run(command);
+                }
+            });
+        }
+    });
+    // RENAME leaves one of them, MSET writes both
+    assert!(matches!(storage.lock_all().size(), 1 | 2));
+}
@@ -0,0 +1,304 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::command::TrackingOptions;
+use crate::protocol::writer::RespWriter;
+use crate::storage::models::RedisString;
+
+/// Keys read by the connections with CLIENT TRACKING on, and the invalidation messages waiting
+/// to be pushed to them once those keys are written, for clients to drop what they cached.
+/// Connections are known by the address of their client. Keys expiring aren't invalidated.
+#[derive(Debug)]
+pub struct Tracking {
+    state: Mutex<TrackingState>,
+    // keys remembered at most, see `ServerConfig::tracking_table_max_keys`
+    max_keys: usize,
+    // connections tracking keys, so that writes skip the lock while there are none
+    clients: AtomicUsize,
+    // connections with an invalidation message waiting, so that the I/O loop skips the lock
+    // while there are none
+    pending: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct TrackingState {
+    clients: HashMap<SocketAddr, TrackingClient>,
+    // connections that read each key since it was last written, for those not in BCAST mode,
+    // each of which remembers those keys in `TrackingClient::keys` as well
+    readers: HashMap<RedisString, HashSet<SocketAddr>>,
+    // connections in BCAST mode by prefix, the empty one for those given none
+    prefixes: BTreeMap<RedisString, HashSet<SocketAddr>>,
+}
+
+#[derive(Debug)]
+struct TrackingClient {
+    options: TrackingOptions,
+    // prefix of every key the connection sees, taken off the keys pushed to it
+    key_prefix: Option<RedisString>,
+    // keys it's among the readers of, to forget them without going through every key
+    keys: HashSet<RedisString>,
+    invalidation: Option<Invalidation>,
+}
+
+/// Invalidation message pushed to a tracking connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invalidation {
+    Keys(BTreeSet<RedisString>),
+    /// Every key, after FLUSHALL or FLUSHDB
+    All,
+}
+
+/// Keys remembered by default, as the `tracking-table-max-keys` directive of Redis
+pub const DEFAULT_MAX_KEYS: usize = 1_000_000;
+
+impl Default for Tracking {
+    fn default() -> Self {
+        Tracking::new(DEFAULT_MAX_KEYS)
+    }
+}
+
+impl Tracking {
+    /// Tracking remembering up to `max_keys` keys read (0 doesn't limit them), past which keys
+    /// are invalidated for the connections that read them and forgotten
+    pub fn new(max_keys: usize) -> Self {
+        Tracking {
+            state: Mutex::default(),
+            max_keys,
+            clients: AtomicUsize::new(0),
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    /// Whether any connection tracks keys
+    pub fn is_active(&self) -> bool {
+        self.clients.load(Ordering::SeqCst) > 0
+    }
+
+    /// Turn tracking on for `client`, whose keys all start with `key_prefix`. The options
+    /// replace those it was given before, the keys it read are forgotten.
+    pub fn start(
+        &self,
+        client: SocketAddr,
+        options: TrackingOptions,
+        key_prefix: Option<RedisString>,
+    ) {
+        let mut state = self.state();
+        state.forget_reads(client);
+        let previous = state.clients.remove(&client);
+        match &previous {
+            Some(previous) => state.forget_prefixes(client, &previous.options),
+            None => {
+                self.clients.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        for prefix in bcast_prefixes(&options) {
+            state.prefixes.entry(prefix).or_default().insert(client);
+        }
+        state.clients.insert(
+            client,
+            TrackingClient {
+                options,
+                key_prefix,
+                keys: HashSet::new(),
+                // what was invalidated before still is
+                invalidation: previous.and_then(|previous| previous.invalidation),
+            },
+        );
+    }
+
+    /// Turn tracking off for `client`, dropping the invalidation message it wasn't pushed yet
+    pub fn stop(&self, client: SocketAddr) {
+        if !self.is_active() {
+            return;
+        }
+        let mut state = self.state();
+        state.forget_reads(client);
+        if let Some(removed) = state.clients.remove(&client) {
+            self.clients.fetch_sub(1, Ordering::SeqCst);
+            if removed.invalidation.is_some() {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+            }
+            state.forget_prefixes(client, &removed.options);
+        }
+    }
+
+    /// Remember that `client` read `keys`, to invalidate them once they're written. Nothing
+    /// is remembered in BCAST mode, where the prefixes decide what's invalidated.
+    pub fn read(&self, client: SocketAddr, keys: &[RedisString]) {
+        let mut state = self.state();
+        let TrackingState {
+            clients, readers, ..
+        } = &mut *state;
+        let tracking = match clients.get_mut(&client) {
+            Some(tracking) if !tracking.options.bcast => tracking,
+            _ => return,
+        };
+        for key in keys {
+            readers.entry(key.clone()).or_default().insert(client);
+            tracking.keys.insert(key.clone());
+        }
+
+        // past the limit, the readers of other keys are told to drop them instead
+        while self.max_keys > 0 && state.readers.len() > self.max_keys {
+            let evicted = match state.readers.keys().find(|key| !keys.contains(key)) {
+                Some(key) => key.clone(),
+                None => break,
+            };
+            for addr in state.take_readers(&evicted) {
+                if let Some(client) = state.clients.get_mut(&addr) {
+                    client.invalidate(&evicted, &self.pending);
+                }
+            }
+        }
+    }
+
+    /// Invalidate `keys`, just written by `writer`, for the connections that read them since
+    /// and those in BCAST mode with a prefix they start with
+    pub fn invalidate(&self, writer: Option<SocketAddr>, keys: &[&RedisString]) {
+        if !self.is_active() || keys.is_empty() {
+            return;
+        }
+        let mut state = self.state();
+        for &key in keys {
+            let key_readers = state.take_readers(key);
+            let TrackingState {
+                clients, prefixes, ..
+            } = &mut *state;
+            // looked up by each prefix of the key, however many prefixes there are
+            let bcast = (0..=key.len())
+                .filter_map(|len| prefixes.get(&key[..len]))
+                .flatten();
+            for addr in key_readers.iter().chain(bcast) {
+                match clients.get_mut(addr) {
+                    Some(client) if !(client.options.noloop && writer == Some(*addr)) => {
+                        client.invalidate(key, &self.pending)
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Invalidate every key for every tracking connection, as FLUSHALL and FLUSHDB do
+    pub fn invalidate_all(&self) {
+        if !self.is_active() {
+            return;
+        }
+        let mut state = self.state();
+        state.readers.clear();
+        for client in state.clients.values_mut() {
+            client.keys.clear();
+            if client.invalidation.is_none() {
+                self.pending.fetch_add(1, Ordering::SeqCst);
+            }
+            client.invalidation = Some(Invalidation::All);
+        }
+    }
+
+    /// The invalidation message waiting for `client`, its keys as the connection sees them
+    pub fn take(&self, client: SocketAddr) -> Option<Invalidation> {
+        if self.pending.load(Ordering::SeqCst) == 0 {
+            return None;
+        }
+        let mut state = self.state();
+        let tracking = state.clients.get_mut(&client)?;
+        let invalidation = tracking.invalidation.take()?;
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+        Some(match (invalidation, &tracking.key_prefix) {
+            (Invalidation::Keys(keys), Some(prefix)) => Invalidation::Keys(
+                keys.into_iter()
+                    .map(|key| key.get(prefix.len()..).unwrap_or_default().to_vec())
+                    .collect(),
+            ),
+            (invalidation, _) => invalidation,
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, TrackingState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl TrackingState {
+    fn forget_reads(&mut self, client: SocketAddr) {
+        let keys = match self.clients.get_mut(&client) {
+            Some(tracking) => std::mem::take(&mut tracking.keys),
+            None => return,
+        };
+        for key in keys {
+            if let Some(readers) = self.readers.get_mut(&key) {
+                readers.remove(&client);
+                if readers.is_empty() {
+                    self.readers.remove(&key);
+                }
+            }
+        }
+    }
+
+    // the connections that read `key`, which is forgotten for them
+    fn take_readers(&mut self, key: &[u8]) -> HashSet<SocketAddr> {
+        let readers = self.readers.remove(key).unwrap_or_default();
+        for addr in &readers {
+            if let Some(tracking) = self.clients.get_mut(addr) {
+                tracking.keys.remove(key);
+            }
+        }
+        readers
+    }
+
+    fn forget_prefixes(&mut self, client: SocketAddr, options: &TrackingOptions) {
+        for prefix in bcast_prefixes(options) {
+            if let Some(clients) = self.prefixes.get_mut(&prefix) {
+                clients.remove(&client);
+                if clients.is_empty() {
+                    self.prefixes.remove(&prefix);
+                }
+            }
+        }
+    }
+}
+
+// prefixes a connection is indexed under, none unless it's in BCAST mode
+fn bcast_prefixes(options: &TrackingOptions) -> Vec<RedisString> {
+    match (options.bcast, options.prefixes.is_empty()) {
+        (false, _) => Vec::new(),
+        (true, true) => vec![RedisString::new()],
+        (true, false) => options.prefixes.clone(),
+    }
+}
+
+impl TrackingClient {
+    fn invalidate(&mut self, key: &RedisString, pending: &AtomicUsize) {
+        match &mut self.invalidation {
+            None => {
+                pending.fetch_add(1, Ordering::SeqCst);
+                self.invalidation = Some(Invalidation::Keys(BTreeSet::from([key.clone()])));
+            }
+            Some(Invalidation::Keys(keys)) => {
+                keys.insert(key.clone());
+            }
+            Some(Invalidation::All) => {}
+        }
+    }
+}
+
+impl Invalidation {
+    /// The RESP3 push message, `>2 invalidate` followed by the keys, or by null for every key
+    pub fn write_to(&self, writer: &mut RespWriter) {
+        writer.push_len(2);
+        writer.bulk_string(b"invalidate");
+        match self {
+            Invalidation::Keys(keys) => {
+                writer.array_len(keys.len());
+                for key in keys {
+                    writer.bulk_string(key);
+                }
+            }
+            Invalidation::All => writer.resp3_null(),
+        }
+    }
+}
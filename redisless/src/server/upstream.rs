@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::protocol::parser::RedisProtocolParser;
+
+/// Commands never sent upstream: those changing the state of the connection they are sent on,
+/// as an upstream connection is shared by several clients, and those describing the server
+pub const LOCAL_COMMANDS: [&str; 8] = [
+    "client",
+    "command",
+    "info",
+    "quit",
+    "readonly",
+    "readwrite",
+    "reset",
+    "select",
+];
+
+/// Writes kept for the upstream while it's down, the oldest are dropped past this
+const MAX_PENDING_WRITES: usize = 100_000;
+
+pub fn is_local_command(command_name: &str) -> bool {
+    LOCAL_COMMANDS.contains(&command_name.to_lowercase().as_str())
+}
+
+/// Upstream Redis served in front of the local storage, see `Server::new_with_upstream`
+#[derive(Debug, Clone)]
+pub struct UpstreamConfig {
+    /// `host:port` of the upstream Redis
+    pub upstream: String,
+    /// Give up on an upstream reply after this long and serve from the local storage
+    pub timeout: Duration,
+    /// Wait this long after a failure before trying the upstream again
+    pub retry_interval: Duration,
+}
+
+impl UpstreamConfig {
+    pub fn new<A: Into<String>>(upstream: A) -> Self {
+        UpstreamConfig {
+            upstream: upstream.into(),
+            timeout: Duration::from_secs(1),
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Connection to an upstream Redis, opened on first use
+pub struct UpstreamConnection {
+    addr: String,
+    timeout: Duration,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+}
+
+impl UpstreamConnection {
+    pub fn new(addr: String, timeout: Duration) -> Self {
+        UpstreamConnection {
+            addr,
+            timeout,
+            stream: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Send `request` and wait for its reply
+    pub fn forward(&mut self, request: &[u8]) -> io::Result<Vec<u8>> {
+        let res = self.send_and_read(request);
+        if res.is_err() {
+            // the reply may still come, don't mistake it for the next one
+            self.stream = None;
+            self.buffer.clear();
+        }
+        res
+    }
+
+    fn send_and_read(&mut self, request: &[u8]) -> io::Result<Vec<u8>> {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => {
+                let stream = TcpStream::connect(&self.addr)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                stream.set_nodelay(true)?;
+                self.stream.get_or_insert(stream)
+            }
+        };
+
+        stream.write_all(request)?;
+
+        let mut chunk = [0; 4096];
+        loop {
+            match RedisProtocolParser::frame_len(&self.buffer) {
+                Ok(Some(len)) => return Ok(self.buffer.drain(..len).collect()),
+                Ok(None) => {}
+                Err(err) => return Err(io::Error::new(ErrorKind::InvalidData, err.to_string())),
+            }
+
+            match stream.read(&mut chunk)? {
+                0 => return Err(ErrorKind::UnexpectedEof.into()),
+                n => self.buffer.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+}
+
+/// Upstream every worker forwards to, each clone over a connection of its own. Whether it's down
+/// and the writes it missed are shared, and only locked around the requests sent to it, so a slow
+/// upstream holds up the workers waiting on its replies and not the others.
+pub struct Failover {
+    connection: UpstreamConnection,
+    state: Arc<Mutex<FailoverState>>,
+}
+
+struct FailoverState {
+    retry_interval: Duration,
+    // set while the upstream is unreachable
+    down_since: Option<Instant>,
+    // writes served locally while the upstream was down, replayed once it's back
+    pending_writes: VecDeque<Vec<u8>>,
+    // set while a worker replays `pending_writes`, the others then serve locally so that their
+    // writes reach the upstream after those
+    resyncing: bool,
+}
+
+impl Failover {
+    pub fn new(config: UpstreamConfig) -> Self {
+        Failover {
+            connection: UpstreamConnection::new(config.upstream, config.timeout),
+            state: Arc::new(Mutex::new(FailoverState {
+                retry_interval: config.retry_interval,
+                down_since: None,
+                pending_writes: VecDeque::new(),
+                resyncing: false,
+            })),
+        }
+    }
+
+    /// Reply from the upstream to a request already executed locally, or `None` when the upstream
+    /// is unreachable and the local reply has to be served. Writes are kept to be replayed then.
+    pub fn forward(&mut self, request: &[u8], is_write: bool) -> Option<Vec<u8>> {
+        let mut replayed = self.state().resync(request, is_write)?;
+
+        let mut reply = Ok(Vec::new());
+        while let Some(write) = replayed.front() {
+            reply = self.connection.forward(write);
+            if reply.is_err() {
+                break;
+            }
+            replayed.pop_front();
+        }
+        if reply.is_ok() {
+            reply = self.connection.forward(request);
+        }
+
+        let mut state = self.state();
+        state.resyncing = false;
+        match reply {
+            Ok(reply) => {
+                state.down_since = None;
+                Some(reply)
+            }
+            Err(err) => {
+                if state.down_since.is_none() {
+                    log::warn!(
+                        "upstream {} is unreachable, serving from the local storage: {}",
+                        self.connection.addr(),
+                        err
+                    );
+                }
+                state.down_since = Some(Instant::now());
+
+                // what wasn't replayed goes before the writes kept meanwhile
+                replayed.append(&mut state.pending_writes);
+                state.pending_writes = replayed;
+                while state.pending_writes.len() > MAX_PENDING_WRITES {
+                    state.pending_writes.pop_front();
+                }
+                if is_write {
+                    state.keep(request);
+                }
+                None
+            }
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, FailoverState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Clone for Failover {
+    /// The same upstream, over a connection of its own
+    fn clone(&self) -> Self {
+        Failover {
+            connection: UpstreamConnection::new(
+                self.connection.addr.clone(),
+                self.connection.timeout,
+            ),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl FailoverState {
+    /// The writes missed by the upstream to replay before `request`, as long as it's time to try
+    /// it again and no other worker is replaying them, `None` otherwise and `request` is kept
+    fn resync(&mut self, request: &[u8], is_write: bool) -> Option<VecDeque<Vec<u8>>> {
+        let waiting = self
+            .down_since
+            .is_some_and(|down_since| down_since.elapsed() < self.retry_interval);
+        if waiting || self.resyncing {
+            if is_write {
+                self.keep(request);
+            }
+            return None;
+        }
+        if self.down_since.is_some() {
+            // the next failure restarts the wait
+            self.down_since = Some(Instant::now());
+        }
+
+        self.resyncing = !self.pending_writes.is_empty();
+        Some(std::mem::take(&mut self.pending_writes))
+    }
+
+    fn keep(&mut self, request: &[u8]) {
+        if self.pending_writes.len() >= MAX_PENDING_WRITES {
+            self.pending_writes.pop_front();
+        }
+        self.pending_writes.push_back(request.to_vec());
+    }
+}
@@ -1,96 +1,77 @@
 mod run_command;
 // re-export run_command
-use crossbeam_channel::{Receiver, Sender};
 pub use run_command::*;
 
-use crate::server::ServerState;
+use crate::server::renames::CommandRenames;
+use crate::server::ServerStats;
 
 use std::{
-    io::{BufReader, Read, Write},
-    net::TcpStream,
-    sync::{Arc, Mutex, MutexGuard},
-    thread,
-    time::Duration,
+    io::Write,
+    net::{IpAddr, TcpStream},
 };
 
 use crate::{
     command::{command_error::RedisCommandError, Command},
-    protocol::{self, parser::RedisProtocolParser, Resp},
-    storage::Storage,
+    protocol::{
+        self,
+        parser::{ProtocolLimits, RedisProtocolParser},
+        Resp,
+    },
 };
 
-use super::{CloseConnection, ReceivedDataLength};
-
-pub fn lock_then_release<T: Storage>(storage: &Arc<Mutex<T>>) -> MutexGuard<T> {
-    loop {
-        match storage.lock() {
-            Ok(storage) => {
-                return storage;
-            }
-            Err(_) => {
-                thread::sleep(Duration::from_millis(10));
-            }
-        }
-    }
+/// Answer a connection that is refused, e.g. over `maxclients`, with `error` before closing it
+pub fn reject_tcp_stream(mut stream: &TcpStream, error: RedisCommandError) {
+    let _ = stream.write(&error.to_vec());
 }
 
-pub fn stop_sig_received(recv: &Receiver<ServerState>, sender: &Sender<ServerState>) -> bool {
-    if let Ok(recv_state) = recv.try_recv() {
-        if recv_state == ServerState::Stop {
-            // notify that the server has been stopped
-            let _ = sender.send(ServerState::Stopped);
-            return true;
-        }
+/// Whether `ip` is a loopback address, IPv4 ones mapped to IPv6 included
+pub fn is_loopback(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.to_ipv4().is_some_and(|ip| ip.is_loopback()),
     }
-
-    false
 }
 
-pub fn get_command(bytes: &[u8; 512]) -> Result<Command, RedisCommandError> {
-    match RedisProtocolParser::parse(bytes) {
-        Ok((Resp::Array(v), _)) => match Command::parse(v) {
-            Ok(command) => Ok(command),
-            Err(err) => Err(err),
-        },
-        Err(err) => Err(RedisCommandError::ProtocolParse(err)),
-        _ => Err(RedisCommandError::CommandNotFound),
-    }
+pub fn get_command(bytes: &[u8]) -> Result<Command, RedisCommandError> {
+    get_renamed_command(
+        bytes,
+        &CommandRenames::default(),
+        &ProtocolLimits::default(),
+    )
 }
 
-fn get_bytes_from_request(stream: &TcpStream) -> ([u8; 512], usize) {
-    let mut buf_reader = BufReader::new(stream);
-    let mut buf = [0; 512];
-    let mut buf_length = 0_usize;
-
-    while let Ok(s) = buf_reader.read(&mut buf) {
-        buf_length += s;
-
-        if s < 512 {
-            break;
+/// Command sent by the client, known by the names `renames` leaves
+pub fn get_renamed_command(
+    bytes: &[u8],
+    renames: &CommandRenames,
+    limits: &ProtocolLimits,
+) -> Result<Command, RedisCommandError> {
+    match RedisProtocolParser::parse_with_limits(bytes, limits) {
+        Ok((Resp::Array(mut v), _)) => {
+            if let Some(Resp::BulkString(name)) = v.first() {
+                match renames.resolve(name) {
+                    Some(original) => v[0] = Resp::BulkString(original),
+                    None => {
+                        return Err(RedisCommandError::NotSupported(
+                            String::from_utf8_lossy(name).to_string(),
+                        ))
+                    }
+                }
+            }
+            Command::parse(v)
         }
+        Err(err) => Err(RedisCommandError::ProtocolParse(err)),
+        _ => Err(RedisCommandError::CommandNotFound),
     }
-
-    (buf, buf_length)
 }
 
-pub fn handle_request<T: Storage>(
-    storage: &Arc<Mutex<T>>,
-    mut stream: &TcpStream,
-) -> (CloseConnection, ReceivedDataLength) {
-    let (buf, buf_length) = get_bytes_from_request(stream);
-
-    match buf.get(0) {
-        Some(x) if *x == 0 => {
-            return (false, buf_length);
-        }
-        _ => {}
+/// Name of the command as sent by the client, used to word errors about it
+pub fn get_command_name(bytes: &[u8]) -> String {
+    match RedisProtocolParser::parse(bytes) {
+        Ok((Resp::Array(v), _)) => match v.first() {
+            Some(Resp::BulkString(name)) => String::from_utf8_lossy(name).to_string(),
+            _ => String::new(),
+        },
+        _ => String::new(),
     }
-
-    let res = run_command_and_get_response(storage, &buf);
-    let quit = if res.is_quit() { true } else { false };
-    let reply = res.reply();
-    //eprintln!("?{}", std::str::from_utf8(&reply).unwrap());
-    let _ = stream.write(&reply);
-
-    (quit, 1)
 }
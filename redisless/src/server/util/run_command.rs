@@ -1,601 +1,1476 @@
 use std::{
-    collections::{HashMap, HashSet},
-    sync::{Arc, Mutex},
+    collections::HashMap,
+    io::Write,
+    time::{Duration, Instant},
 };
 
-use chrono::format::format;
-
 use crate::{
-    command::Command,
+    command::{
+        bitfield::apply_bitfield_ops,
+        command_error::RedisCommandError,
+        table::{command_spec, command_table, CommandKind, CommandSpec},
+        Command,
+    },
     protocol::response::{RedisResponse, RedisResponseType},
-    storage::{models::RedisString, Storage},
+    server::{
+        acl::DEFAULT_USER,
+        context::{Blocked, ConnectionContext, DATABASES},
+        fault::InjectedLatency,
+        ServerEvent, ServerRole,
+    },
+    storage::{
+        models::{DatasetSnapshot, EncodingLimits, RedisString, RedisType},
+        scan::glob_match,
+        striped::{StripedGuard, StripedStorage, Stripes},
+        Storage,
+    },
 };
 
 use super::*;
 
+/// Parameter of CONFIG answering writes with READONLY when set to `yes`
+const READ_ONLY_PARAMETER: &str = "read-only";
+
+/// Version HELLO reports, that of the Redis whose commands are answered like it does. Clients
+/// check it before turning on what they rely on, such as client-side caching.
+const REDIS_VERSION: &str = "7.4.0";
+
+/// DEL removing at least this many keys gives the memory they held back right away
+const SHRINK_AFTER_REMOVED: usize = 1024;
+
+/// Run the request `bytes` sent by the connection of `context`, and reply to it.
+///
+/// # Storage lock
+///
+/// A command touching the storage takes its lock once, which makes it atomic to every other
+/// command: what it reads and what it writes are never interleaved with another write. The
+/// lock is held for no longer than it takes to look the keys up, change them, and copy out
+/// what the reply holds; the reply is built once it's released. It only covers the stripes of
+/// the keys the command names, see `StripedStorage`, and every stripe for a command naming no
+/// key: commands on keys of other stripes run meanwhile.
+///
+/// | Commands                                           | Lock held while                     |
+/// |----------------------------------------------------|-------------------------------------|
+/// | GET, MGET, HGET, HGETALL, HTTL, LLEN, LINDEX, SCARD | the keys are counted as keyspace hits or misses and their values copied |
+/// | LRANGE, HSCAN, SSCAN, SCAN                         | the elements of the reply are copied |
+/// | TYPE, EXISTS, TTL, PTTL, OBJECT, CASVERSION        | the key is looked up                |
+/// | SET, MSET, SETEX, APPEND, EXPIRE, pushes, DEL, ... | the keys are written                |
+/// | SETNX, MSETNX, GETSET, CAS, INCRBY, BITFIELD, pops, RPOPLPUSH, LSET, ... | the keys are read, then written depending on what was read |
+/// | BLPOP                                              | the lists are popped, each time it runs but never while it waits |
+/// | FLUSHALL, FLUSHDB, DELPATTERN, DEBUG RELOAD        | the keys are removed or replaced    |
+/// | DBSIZE, INFO keyspace, DEBUG TYPESTATS, DEBUG DIGEST | the keys are counted, or the dataset copied |
+/// | CONFIG GET, CONFIG SET                             | the encoding limits are read or replaced |
+/// | connection and other admin commands                | never taken                         |
+///
+/// Before a command runs, `Watchdog::admit` only waits for the lock to be free, without
+/// holding it. With `ServerConfig::max_keys` or `ServerConfig::max_value_bytes` set, the
+/// commands growing the dataset take it over every stripe once more beforehand, to be refused
+/// when full. What follows the command, counting the write, recording the keys read for client
+/// tracking and invalidating the keys written, happens once the lock is released.
 pub fn run_command_and_get_response<T: Storage>(
-    storage: &Arc<Mutex<T>>,
-    bytes: &[u8; 512],
+    storage: &StripedStorage<T>,
+    stats: &ServerStats,
+    context: &mut ConnectionContext,
+    bytes: &[u8],
 ) -> RedisResponse {
-    use protocol::response::RedisResponseType::*;
-    let command = get_command(bytes);
-    let response = match command {
-        Ok(command) => match command {
-            Command::Set(k, v) => {
-                lock_then_release(storage).write(k.as_slice(), v.as_slice());
-                RedisResponse::okay()
+    // a blocked command running again was already journaled and counted when first sent
+    let rerun = context.blocked.is_some();
+    if !rerun && !context.admit() {
+        return RedisResponse::error(RedisCommandError::RateLimitExceeded);
+    }
+    let command = get_renamed_command(bytes, stats.renames(), &context.protocol_limits).and_then(
+        |mut command| {
+            context
+                .check(&command)
+                .map_err(|err| err.for_command(&get_command_name(bytes)))?;
+            stats.role().check(&command, context.readonly)?;
+            if command.is_write() && stats.read_only() {
+                return Err(RedisCommandError::ReadOnlyReplica);
             }
-            Command::Append(k, v) => {
-                let len = lock_then_release(storage).extend(k.as_slice(), v.as_slice());
-                RedisResponse::single(Integer(len as i64))
+            if let Some(client) = context.client.filter(|_| !rerun) {
+                stats.journal().record(client, &command);
             }
-            Command::Setex(k, expiry, v) | Command::PSetex(k, expiry, v) => {
-                let mut storage = lock_then_release(storage);
-
-                storage.write(k.as_slice(), v.as_slice());
-                storage.expire(k.as_slice(), expiry);
-
-                RedisResponse::okay()
+            if let Some(prefix) = context.full_key_prefix() {
+                command.prefix_keys(&prefix);
             }
-            Command::Setnx(k, v) => {
-                let mut storage = lock_then_release(storage);
-                match storage.contains(&k[..]) {
-                    // Key exists, will not re set key
-                    true => RedisResponse::single(Integer(0)),
-                    // Key does not exist, will set key
-                    false => {
-                        storage.write(&k, &v);
-                        RedisResponse::single(Integer(1))
-                    }
+            Ok(command)
+        },
+    );
+    let command = match command {
+        Ok(command) => match context.queue(command) {
+            Some(command) => Ok(command),
+            None => return RedisResponse::queued(),
+        },
+        Err(err) => Err(err),
+    };
+    if let Err(RedisCommandError::NotSupported(name)) = &command {
+        stats.command_not_supported(name);
+    }
+    // injected before the command counts as running, only its own client waits
+    if let (Ok(command), false) = (&command, rerun) {
+        if let Some(delay) = stats.faults().delay(command.name()) {
+            std::thread::sleep(delay);
+        }
+    }
+    // locked in the order of their index by every command, see `StripedStorage`
+    let stripes = match &command {
+        Ok(command) if command.first_key().is_some() => storage.stripes_of(&command.keys()),
+        _ => storage.all_stripes(),
+    };
+    // held until the reply is ready
+    let _running = match &command {
+        Ok(command) if command.spec().kind != CommandKind::Connection => {
+            match stats.watchdog().admit(&stripes, command.name()) {
+                Ok(running) => running,
+                Err(err) => return RedisResponse::error(err),
+            }
+        }
+        _ => None,
+    };
+    // refused as long as the dataset is as big as it may grow, the writes freeing room aside
+    let limit = stats.memory_limit();
+    if let Ok(command) = &command {
+        if command.grows() && limit.is_set() && limit.refuses(&mut storage.lock_all(), command) {
+            return RedisResponse::error(RedisCommandError::OutOfMemory);
+        }
+    }
+    if let (Ok(_), false) = (&command, rerun) {
+        stats.command_processed();
+    }
+    let wrote = matches!(&command, Ok(command) if command.is_write());
+    // recorded before the keys are read and again after, so that a write landing in between
+    // can't leave the client with a value it would never be told is stale
+    let tracked_reads = match (&command, context.client) {
+        (Ok(command), Some(client)) if context.tracking && command.is_read() => {
+            let keys: Vec<RedisString> = command.keys().into_iter().cloned().collect();
+            stats.tracking().read(client, &keys);
+            Some((client, keys))
+        }
+        _ => None,
+    };
+    let invalidated = match &command {
+        // neither tells which keys it removed
+        Ok(Command::FlushAll) | Ok(Command::FlushDb) | Ok(Command::DelPattern(_)) => None,
+        Ok(command) if wrote && (stats.tracking().is_active() || stats.blocking().is_active()) => {
+            Some(command.keys().into_iter().cloned().collect::<Vec<_>>())
+        }
+        _ => None,
+    };
+    // popping from a list never gives a blocked command anything to work on
+    let wakes_blocked = !matches!(&command, Ok(Command::BLPop(..)));
+    let response = match command {
+        Ok(command) => execute(command, &stripes, stats, context),
+        Err(err) => RedisResponse::error(err),
+    };
+    // the writes that went through are counted as Redis counts the bytes it propagates to its
+    // replicas
+    if wrote && !rerun && !response.is_blocked() && response.error_replied().is_none() {
+        stats.replication().advance(bytes.len());
+    }
+    if let Some((client, keys)) = tracked_reads {
+        stats.tracking().read(client, &keys);
+    }
+    if wrote && !response.is_blocked() && response.error_replied().is_none() {
+        match invalidated {
+            Some(keys) => {
+                let keys = keys.iter().collect::<Vec<_>>();
+                stats.tracking().invalidate(context.client, &keys);
+                if wakes_blocked {
+                    stats.blocking().written(&keys);
                 }
             }
-            Command::MSet(items) => {
-                let mut storage = lock_then_release(storage);
-                items.iter().for_each(|(k, v)| storage.write(k, v));
-                RedisResponse::okay()
+            None => stats.tracking().invalidate_all(),
+        }
+    }
+    response
+}
+
+/// Run `command`, already checked against the state of the connection and the server
+fn execute<T: Storage>(
+    command: Command,
+    storage: &Stripes<'_, T>,
+    stats: &ServerStats,
+    context: &mut ConnectionContext,
+) -> RedisResponse {
+    use protocol::response::RedisResponseType::*;
+    match command {
+        Command::Set(k, v) => {
+            storage.lock().write(k.as_slice(), v.as_slice());
+            RedisResponse::okay()
+        }
+        Command::SetKeepTtl(k, v) => {
+            storage.lock().write_keeping_expiry(&k, &v);
+            RedisResponse::okay()
+        }
+        Command::Cas(k, version, v) => {
+            let mut storage = storage.lock();
+            if storage.version(&k) != version {
+                return RedisResponse::single(Nil);
             }
-            Command::MSetnx(items) => {
-                // Either set all or not set any at all if any already exist
-                let mut storage = lock_then_release(storage);
-                match items.iter().all(|(key, _)| !storage.contains(key)) {
-                    // None of the keys already exist in the storage
-                    true => {
-                        items.iter().for_each(|(k, v)| storage.write(k, v));
-                        RedisResponse::single(Integer(1))
-                    }
-                    // Some key exists, don't write any of the keys
-                    false => RedisResponse::single(Integer(0)),
-                }
+            storage.write(&k, &v);
+            RedisResponse::single(Integer(storage.version(&k) as i64))
+        }
+        Command::CasVersion(k) => RedisResponse::single(Integer(storage.lock().version(&k) as i64)),
+        Command::Append(k, v) => {
+            let len = storage.lock().extend(k.as_slice(), v.as_slice());
+            RedisResponse::single(Integer(len as i64))
+        }
+        Command::BitField(k, ops) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&k);
+            if keytype != "string".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
             }
-            Command::Expire(k, expiry) | Command::PExpire(k, expiry) => {
-                let e = lock_then_release(storage).expire(k.as_slice(), expiry);
-                RedisResponse::single(Integer(e as i64))
+
+            let mut value = storage.read(&k).map(<[u8]>::to_vec).unwrap_or_default();
+            let (replies, written) = apply_bitfield_ops(&mut value, &ops);
+            if written {
+                // the key is modified rather than replaced
+                storage.write_keeping_expiry(&k, &value);
             }
-            Command::Get(k) => match lock_then_release(storage).read(k.as_slice()) {
-                Some(value) => RedisResponse::single(SimpleString(value.to_vec())),
-                None => RedisResponse::single(Nil),
-            },
-            Command::GetSet(k, v) => {
-                let mut storage = lock_then_release(storage);
 
-                let response = match storage.read(k.as_slice()) {
-                    Some(value) => RedisResponse::single(SimpleString(value.to_vec())),
-                    None => RedisResponse::single(Nil),
-                };
-                storage.write(k.as_slice(), v.as_slice());
-                response
-            }
-            Command::MGet(keys) => {
-                let mut storage = lock_then_release(storage);
-                let mut responses = Vec::<RedisResponseType>::with_capacity(keys.len());
-                for key in keys {
-                    let response = match storage.read(key.as_slice()) {
-                        Some(value) => RedisResponseType::SimpleString(value.to_vec()),
-                        None => RedisResponseType::Nil,
-                    };
-                    responses.push(response);
+            RedisResponse::array(
+                replies
+                    .into_iter()
+                    .map(|reply| reply.map_or(Nil, Integer))
+                    .collect(),
+            )
+        }
+        Command::Setex(k, expiry, v) | Command::PSetex(k, expiry, v) => {
+            let mut storage = storage.lock();
+
+            storage.write(k.as_slice(), v.as_slice());
+            storage.expire(k.as_slice(), expiry);
+
+            RedisResponse::okay()
+        }
+        Command::Setnx(k, v) => {
+            let mut storage = storage.lock();
+            match storage.contains(&k[..]) {
+                // Key exists, will not re set key
+                true => RedisResponse::single(Integer(0)),
+                // Key does not exist, will set key
+                false => {
+                    storage.write(&k, &v);
+                    RedisResponse::single(Integer(1))
                 }
-                RedisResponse::array(responses)
             }
-            Command::HSet(map_key, items) => {
-                let mut hash_map = HashMap::<RedisString, RedisString>::with_capacity(items.len());
-
-                for (k, v) in items {
-                    hash_map.insert(k.to_vec(), v.to_vec());
+        }
+        Command::MSet(items) => {
+            let items = borrow_items(&items);
+            storage.lock().mwrite(&items);
+            RedisResponse::okay()
+        }
+        Command::MSetnx(items) => {
+            // Either set all or not set any at all if any already exist
+            let mut storage = storage.lock();
+            match items.iter().all(|(key, _)| !storage.contains(key)) {
+                // None of the keys already exist in the storage
+                true => {
+                    storage.mwrite(&borrow_items(&items));
+                    RedisResponse::single(Integer(1))
                 }
+                // Some key exists, don't write any of the keys
+                false => RedisResponse::single(Integer(0)),
+            }
+        }
+        Command::Expire(k, expiry) | Command::PExpire(k, expiry) => {
+            let e = storage.lock().expire(k.as_slice(), expiry);
+            RedisResponse::single(Integer(e as i64))
+        }
+        Command::Get(k) => {
+            let mut storage = lock_for_reading(storage, stats, &[&k]);
+            let keytype = storage.type_of(&k);
+            if keytype != "string".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
 
-                let mut storage = lock_then_release(storage);
-                storage.hwrite(&map_key, hash_map);
-                RedisResponse::okay()
+            match storage.read(&k) {
+                Some(value) => RedisResponse::single(BulkString(value.to_vec())),
+                None => RedisResponse::single(Nil),
             }
-            Command::HGet(map_key, field_key) => {
-                match lock_then_release(storage).hread(map_key.as_slice(), field_key.as_slice()) {
-                    Some(value) => RedisResponse::single(SimpleString(value.to_vec())),
-                    None => RedisResponse::single(Nil),
-                }
+        }
+        Command::GetSet(k, v) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&k);
+            if keytype != "string".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
             }
-            Command::RPush(key, values) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype != "list".as_bytes() && keytype != "none".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let mut len = values.len();
-                let mut new_vals = values.to_vec();
-                match storage.lread(&key) {
-                    Some(vals) => {
-                        let mut vals = vals.to_vec();
-                        vals.append(&mut new_vals);
-                        len = vals.len();
-                        storage.lwrite(&key, vals);
-                        RedisResponse::single(Integer(len as i64))
-                    }
-                    None => {
-                        storage.lwrite(&key, new_vals);
-                        RedisResponse::single(Integer(len as i64))
-                    }
-                }
+
+            let response = match storage.read(k.as_slice()) {
+                Some(value) => RedisResponse::single(BulkString(value.to_vec())),
+                None => RedisResponse::single(Nil),
+            };
+            storage.write(k.as_slice(), v.as_slice());
+            response
+        }
+        Command::MGet(keys) => {
+            let keys = borrow_keys(&keys);
+            let values = lock_for_reading(storage, stats, &keys).mread(&keys);
+            let responses = values
+                .into_iter()
+                .map(|value| match value {
+                    Some(value) => RedisResponseType::BulkString(value),
+                    None => RedisResponseType::Nil,
+                })
+                .collect();
+            RedisResponse::array(responses)
+        }
+        Command::HSet(map_key, items) => match hset(storage, &map_key, items) {
+            Ok(added) => RedisResponse::single(Integer(added as i64)),
+            Err(err) => RedisResponse::error(err),
+        },
+        Command::HMSet(map_key, items) => match hset(storage, &map_key, items) {
+            Ok(_) => RedisResponse::okay(),
+            Err(err) => RedisResponse::error(err),
+        },
+        Command::HGet(map_key, field_key) => {
+            match lock_for_reading(storage, stats, &[&map_key]).hread(&map_key, &field_key) {
+                Some(value) => RedisResponse::single(BulkString(value.to_vec())),
+                None => RedisResponse::single(Nil),
             }
-            Command::LPush(key, values) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype != "list".as_bytes() && keytype != "none".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let mut len = values.len();
-                let mut values: Vec<RedisString> = values.to_vec().into_iter().rev().collect();
-                match storage.lread(&key) {
-                    Some(old_vals) => {
-                        let mut old_vals = old_vals.to_vec();
-                        values.append(&mut old_vals);
-                        len = values.len();
-                        storage.lwrite(&key, values);
-                        RedisResponse::single(Integer(len as i64))
-                    }
-                    None => {
-                        storage.lwrite(&key, values);
-                        RedisResponse::single(Integer(len as i64))
-                    }
-                }
+        }
+        Command::HScan(key, cursor, options) => {
+            let mut storage = lock_for_reading(storage, stats, &[&key]);
+            let keytype = storage.type_of(&key);
+            if keytype != "hash".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
             }
-            Command::LLen(key) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype != "list".as_bytes() && keytype != "none".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                match storage.lread(&key) {
-                    Some(vals) => RedisResponse::single(Integer(vals.len() as i64)),
-                    None => RedisResponse::single(Integer(0)),
-                }
+            let (cursor, items) = storage
+                .hscan(&key, cursor, &options)
+                .unwrap_or((0, Vec::new()));
+            drop(storage);
+            let elements = items
+                .into_iter()
+                .flat_map(|(field, value)| vec![BulkString(field), BulkString(value)])
+                .collect();
+            RedisResponse::array(scan_reply(cursor, elements))
+        }
+        Command::HGetAll(key) => {
+            let mut storage = lock_for_reading(storage, stats, &[&key]);
+            let keytype = storage.type_of(&key);
+            if keytype != "hash".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
             }
-            Command::RPushx(key, values) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::single(Integer(0));
-                }
-                if keytype != "list".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let mut new_vals = values.to_vec();
-                match storage.lread(&key) {
-                    Some(vals) => {
-                        let mut vals = vals.to_vec();
-                        vals.append(&mut new_vals);
-                        let len = vals.len();
-                        storage.lwrite(&key, vals);
-                        RedisResponse::single(Integer(len as i64))
-                    }
-                    None => RedisResponse::single(Integer(0)),
-                }
+            let elements = match storage.hread_all(&key) {
+                Some(hash) => hash
+                    .data
+                    .iter()
+                    .flat_map(|(field, value)| {
+                        vec![BulkString(field.to_vec()), BulkString(value.to_vec())]
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            RedisResponse::array(elements)
+        }
+        Command::HExpire(key, expiry, fields) | Command::HPExpire(key, expiry, fields) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype != "hash".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
             }
-            Command::LPushx(key, values) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::single(Integer(0));
-                }
-                if keytype != "list".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let mut values: Vec<RedisString> = values.to_vec().into_iter().rev().collect();
-                match storage.lread(&key) {
-                    Some(old_vals) => {
-                        let mut old_vals = old_vals.to_vec();
-                        values.append(&mut old_vals);
-                        let len = values.len();
-                        storage.lwrite(&key, values);
-                        RedisResponse::single(Integer(len as i64))
+            let hash = match storage.hread_mut(&key) {
+                Some(hash) => hash,
+                None => return RedisResponse::array(fields.iter().map(|_| Integer(-2)).collect()),
+            };
+            let replies = fields
+                .into_iter()
+                .map(|field| {
+                    if !hash.data.contains_key(&field) {
+                        Integer(-2)
+                    } else if expiry.duration_left_millis() <= 0 {
+                        // a deadline already past deletes the field
+                        hash.remove(&field);
+                        Integer(2)
+                    } else {
+                        hash.expiries.insert(field, expiry);
+                        Integer(1)
                     }
-                    None => RedisResponse::single(Integer(0)),
-                }
+                })
+                .collect();
+            if hash.data.is_empty() {
+                storage.remove(&key);
             }
-            Command::RPop(key) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::single(Nil);
-                }
-                if keytype != "list".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                match storage.lread(&key) {
-                    Some(values) => {
-                        let mut values = values.to_vec();
-                        match values.pop() {
-                            Some(value) => {
-                                if values.is_empty() {
-                                    storage.remove(&key);
-                                } else {
-                                    storage.lwrite(&key, values);
-                                }
-                                RedisResponse::single(BulkString(value))
-                            }
-                            None => RedisResponse::single(Nil),
-                        }
-                    }
-                    None => RedisResponse::single(Nil),
-                }
+            RedisResponse::array(replies)
+        }
+        Command::HPersist(key, fields) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype != "hash".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
             }
-            Command::LPop(key) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::single(Nil);
-                }
-                if keytype != "list".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                match storage.lread(&key) {
-                    Some(values) => {
-                        let mut values = values.to_vec();
-                        let value = values.remove(0);
-                        if values.is_empty() {
-                            storage.remove(&key);
-                        } else {
-                            storage.lwrite(&key, values);
-                        }
-                        RedisResponse::single(BulkString(value))
-                    }
-                    None => RedisResponse::single(Nil),
-                }
+            let hash = match storage.hread_mut(&key) {
+                Some(hash) => hash,
+                None => return RedisResponse::array(fields.iter().map(|_| Integer(-2)).collect()),
+            };
+            let replies = fields
+                .iter()
+                .map(|field| match hash.expiries.remove(field) {
+                    Some(_) => Integer(1),
+                    None if hash.data.contains_key(field) => Integer(-1),
+                    None => Integer(-2),
+                })
+                .collect();
+            RedisResponse::array(replies)
+        }
+        Command::HTtl(key, fields) => hash_field_ttls(storage, stats, &key, &fields, 1000),
+        Command::HPttl(key, fields) => hash_field_ttls(storage, stats, &key, &fields, 1),
+        Command::RPush(key, values) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype != "list".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
             }
-            Command::LIndex(key, index) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::single(Nil);
-                }
-                if keytype != "list".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let mut index = index;
-                let values = storage.lread(&key).unwrap().to_vec();
-                let len = values.len() as i64;
-                if index < 0 {
-                    index = index + len;
-                }
-                if index < 0 || index >= len {
-                    return RedisResponse::single(Nil);
-                }
-                match values.get(index as usize) {
-                    Some(value) => RedisResponse::single(SimpleString(value.to_vec())),
-                    None => RedisResponse::single(Nil),
-                }
+            let len = storage.lpush_back(&key, values);
+            RedisResponse::single(Integer(len as i64))
+        }
+        Command::LPush(key, values) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype != "list".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            let len = storage.lpush_front(&key, values);
+            RedisResponse::single(Integer(len as i64))
+        }
+        Command::LLen(key) => {
+            let mut storage = lock_for_reading(storage, stats, &[&key]);
+            let keytype = storage.type_of(&key);
+            if keytype != "list".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            match storage.lread(&key) {
+                Some(vals) => RedisResponse::single(Integer(vals.len() as i64)),
+                None => RedisResponse::single(Integer(0)),
+            }
+        }
+        Command::LRange(key, start, stop) => {
+            let mut storage = lock_for_reading(storage, stats, &[&key]);
+            let keytype = storage.type_of(&key);
+            if keytype != "list".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            let values = storage.lrange(&key, start, stop);
+            drop(storage);
+            RedisResponse::array(values.into_iter().map(BulkString).collect())
+        }
+        Command::RPushx(key, values) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::single(Integer(0));
             }
-            Command::LSet(key, index, value) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
+            if keytype != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            let len = storage.lpush_back(&key, values);
+            RedisResponse::single(Integer(len as i64))
+        }
+        Command::LPushx(key, values) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::single(Integer(0));
+            }
+            if keytype != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            let len = storage.lpush_front(&key, values);
+            RedisResponse::single(Integer(len as i64))
+        }
+        Command::RPop(key) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::single(Nil);
+            }
+            if keytype != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            match storage.lpop_back(&key) {
+                Some(value) => RedisResponse::single(BulkString(value)),
+                None => RedisResponse::single(Nil),
+            }
+        }
+        Command::LPop(key) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::single(Nil);
+            }
+            if keytype != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            match storage.lpop_front(&key) {
+                Some(value) => RedisResponse::single(BulkString(value)),
+                None => RedisResponse::single(Nil),
+            }
+        }
+        Command::BLPop(keys, timeout) => {
+            let mut storage = storage.lock();
+            for key in keys.iter() {
+                let keytype = storage.type_of(key);
                 if keytype == "none".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::NoSuchKey);
+                    continue;
+                }
+                context.blocked = None;
+                if let Some(client) = context.client {
+                    stats.blocking().stop(client);
                 }
                 if keytype != "list".as_bytes() {
                     return RedisResponse::error(RedisCommandError::WrongTypeOperation);
                 }
-                let mut index = index;
-                let mut values = storage.lread(&key).unwrap().to_vec();
-                let len = values.len() as i64;
-                if index < 0 {
-                    index = index + len;
-                }
-                if index < 0 || index >= len {
-                    return RedisResponse::error(RedisCommandError::IndexOutOfRange);
+                if let Some(value) = storage.lpop_front(key) {
+                    let prefix_len = context.full_key_prefix().map_or(0, |prefix| prefix.len());
+                    return RedisResponse::array(vec![
+                        BulkString(key[prefix_len..].to_vec()),
+                        BulkString(value),
+                    ]);
                 }
-                let _ = std::mem::replace(&mut values[index as usize], value);
-                storage.lwrite(&key, values);
-                RedisResponse::okay()
             }
-            Command::LInsert(key, place, pivot, value) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::single(Integer(0));
-                }
-                if keytype != "list".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                if place != b"BEFORE" && place != b"AFTER" {
-                    return RedisResponse::error(RedisCommandError::SyntaxErr);
+
+            if context.blocked.is_some_and(|blocked| blocked.timed_out()) {
+                context.blocked = None;
+                if let Some(client) = context.client {
+                    stats.blocking().stop(client);
                 }
-                let mut values = storage.lread(&key).unwrap().to_vec();
-                let index = values.iter().position(|v| v == &pivot);
-                match index {
-                    Some(mut i) => {
-                        if place == b"AFTER" {
-                            i = i + 1;
-                        }
-                        values.insert(i, value);
-                        let len = values.len();
-                        storage.lwrite(&key, values);
-                        RedisResponse::single(Integer(len as i64))
+                return RedisResponse::single(NullArray);
+            }
+            // while the lists are still locked, for a push landing right after to wake it
+            if let Some(client) = context.client {
+                stats.blocking().wait(client, &keys);
+            }
+            if context.blocked.is_none() {
+                context.blocked = Some(Blocked {
+                    deadline: timeout.map(|timeout| Instant::now() + timeout),
+                });
+            }
+            RedisResponse::blocked()
+        }
+        Command::LIndex(key, index) => {
+            let mut storage = lock_for_reading(storage, stats, &[&key]);
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::single(Nil);
+            }
+            if keytype != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            let mut index = index;
+            // will never panic, the key holds a list
+            let values = storage.lread(&key).unwrap();
+            let len = values.len() as i64;
+            if index < 0 {
+                index += len;
+            }
+            if index < 0 || index >= len {
+                return RedisResponse::single(Nil);
+            }
+            match values.get(index as usize) {
+                Some(value) => RedisResponse::single(BulkString(value.to_vec())),
+                None => RedisResponse::single(Nil),
+            }
+        }
+        Command::LSet(key, index, value) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::NoSuchKey);
+            }
+            if keytype != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            let mut index = index;
+            let limits = storage.encoding_limits();
+            // will never panic, the key holds a list
+            let values = storage.lread_mut(&key).unwrap();
+            let len = values.len() as i64;
+            if index < 0 {
+                index += len;
+            }
+            if index < 0 || index >= len {
+                return RedisResponse::error(RedisCommandError::IndexOutOfRange);
+            }
+            values.set(index as usize, value, &limits);
+            RedisResponse::okay()
+        }
+        Command::LInsert(key, place, pivot, value) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::single(Integer(0));
+            }
+            if keytype != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            if place != b"BEFORE" && place != b"AFTER" {
+                return RedisResponse::error(RedisCommandError::SyntaxErr);
+            }
+            let limits = storage.encoding_limits();
+            // will never panic, the key holds a list
+            let values = storage.lread_mut(&key).unwrap();
+            let index = values.iter().position(|v| v == &pivot[..]);
+            match index {
+                Some(mut i) => {
+                    if place == b"AFTER" {
+                        i += 1;
                     }
-                    None => RedisResponse::single(Integer(-1)),
+                    values.insert(i, value, &limits);
+                    RedisResponse::single(Integer(values.len() as i64))
                 }
+                None => RedisResponse::single(Integer(-1)),
             }
-            Command::LTrim(key, start, stop) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::okay();
-                }
-                if keytype != "list".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let mut values = storage.lread(&key).unwrap().to_vec();
-                let len = values.len() as i64;
-                let mut start = start;
-                let mut stop = stop;
-                if start < 0 {
-                    start = start + len;
-                }
-                if stop < 0 {
-                    stop = stop + len;
-                }
-                if start < 0 {
-                    start = 0;
-                }
-                if stop < start || start > len {
-                    storage.remove(&key);
-                    return RedisResponse::okay();
-                }
-                stop = if stop >= len { len } else { stop + 1 };
-                let vals: Vec<_> = values.drain(start as usize..stop as usize).collect();
-                if vals.is_empty() {
-                    storage.remove(&key);
-                } else {
-                    storage.lwrite(&key, vals);
-                }
-                RedisResponse::okay()
+        }
+        Command::LTrim(key, start, stop) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::okay();
             }
-            Command::LRem(key, count, value) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::single(Integer(0));
-                }
-                if keytype != "list".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let values = storage.lread(&key).unwrap().to_vec();
-                let len = values.len();
-                let mut count = count;
-                let mut vals = vec![];
-                let mut rem = 0;
-                if count < 0 {
-                    for v in values.iter().rev() {
-                        if *v == value && count < 0 {
-                            count += 1;
-                            rem += 1;
-                            continue;
-                        }
-                        vals.push(v.clone());
+            if keytype != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            // will never panic, the key holds a list
+            let values = storage.lread_mut(&key).unwrap();
+            let len = values.len() as i64;
+            let mut start = start;
+            let mut stop = stop;
+            if start < 0 {
+                start += len;
+            }
+            if stop < 0 {
+                stop += len;
+            }
+            if start < 0 {
+                start = 0;
+            }
+            if stop < start || start > len {
+                storage.remove(&key);
+                return RedisResponse::okay();
+            }
+            stop = if stop >= len { len } else { stop + 1 };
+            values.truncate(stop as usize);
+            values.drain_front(start as usize);
+            if values.is_empty() {
+                storage.remove(&key);
+            }
+            RedisResponse::okay()
+        }
+        Command::LRem(key, count, value) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::single(Integer(0));
+            }
+            if keytype != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            // will never panic, the key holds a list
+            let values = storage.lread_mut(&key).unwrap();
+            let len = values.len();
+            let mut count = count;
+            let mut rem = 0;
+            if count < 0 {
+                // remove starting from the tail
+                let mut i = len;
+                while i > 0 && count < 0 {
+                    i -= 1;
+                    if values.get(i) == Some(&value[..]) {
+                        values.remove(i);
+                        count += 1;
+                        rem += 1;
                     }
-                    vals = vals.into_iter().rev().collect();
-                    storage.lwrite(&key, vals);
-                    return RedisResponse::single(Integer(rem));
                 }
+            } else {
                 if count == 0 {
                     count = len as i64;
                 }
-                for v in values.iter() {
-                    if *v == value && count > 0 {
+                values.retain(|v| {
+                    if v == &value[..] && count > 0 {
                         count -= 1;
                         rem += 1;
-                        continue;
+                        return false;
                     }
-                    vals.push(v.clone());
-                }
-                if vals.is_empty() {
-                    storage.remove(&key);
-                } else {
-                    storage.lwrite(&key, vals);
-                }
-                RedisResponse::single(Integer(rem))
+                    true
+                });
             }
-            Command::RPopLPush(src, dest) => {
-                let mut storage = lock_then_release(storage);
-                let src_type = storage.type_of(&src);
-                if src_type == "none".as_bytes() {
-                    return RedisResponse::single(Nil);
-                }
-                if src_type != "list".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let dest_type = storage.type_of(&dest);
-                if dest_type != "list".as_bytes() && dest_type != "none".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let mut src_values = storage.lread(&src).unwrap().to_vec();
-                let mut dest_values = match storage.lread(&dest) {
-                    Some(vals) => vals.to_vec(),
-                    None => Vec::new(),
-                };
-                match src_values.pop() {
-                    Some(val) => {
-                        let value = val.clone();
-                        dest_values.insert(0, val);
-                        storage.lwrite(&dest, dest_values);
-                        if src_values.is_empty() {
-                            storage.remove(&src);
-                        } else {
-                            storage.lwrite(&src, src_values);
-                        }
-                        RedisResponse::single(BulkString(value))
-                    }
-                    None => RedisResponse::single(Nil),
-                }
+            if values.is_empty() {
+                storage.remove(&key);
             }
-            Command::SAdd(key, values) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype != "set".as_bytes() && keytype != "none".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
-                }
-                let mut len = values.len();
-                match storage.sread(&key) {
-                    Some(old_vals) => {
-                        let diff: HashSet<_> = values.difference(old_vals).collect();
-                        len = diff.len();
-                        let vals: HashSet<_> = values.union(old_vals).cloned().collect();
-                        storage.swrite(&key, vals);
-                        RedisResponse::single(Integer(len as i64))
-                    }
-                    None => {
-                        storage.swrite(&key, values);
-                        RedisResponse::single(Integer(len as i64))
-                    }
-                }
+            RedisResponse::single(Integer(rem))
+        }
+        Command::RPopLPush(src, dest) => {
+            let mut storage = storage.lock();
+            let src_type = storage.type_of(&src);
+            if src_type == "none".as_bytes() {
+                return RedisResponse::single(Nil);
             }
-            Command::SCard(key) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::single(Integer(0));
+            if src_type != "list".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            let dest_type = storage.type_of(&dest);
+            if dest_type != "list".as_bytes() && dest_type != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            match storage.lpop_back(&src) {
+                Some(value) => {
+                    storage.lpush_front(&dest, vec![value.clone()]);
+                    RedisResponse::single(BulkString(value))
                 }
-                if keytype != "set".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+                None => RedisResponse::single(Nil),
+            }
+        }
+        Command::Rename(key, new_key) => match storage.lock().rename(&key, &new_key) {
+            true => RedisResponse::okay(),
+            false => RedisResponse::error(RedisCommandError::NoSuchKey),
+        },
+        Command::SAdd(key, values) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype != "set".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            let limits = storage.encoding_limits();
+            let added = match storage.sread_mut(&key) {
+                Some(members) => values
+                    .into_iter()
+                    .map(|value| members.insert(value, &limits))
+                    .filter(|added| *added)
+                    .count(),
+                None => {
+                    let added = values.len();
+                    storage.swrite(&key, values);
+                    added
                 }
-                let values = storage.sread(&key).unwrap();
-                let len = values.len() as i64;
-                RedisResponse::single(Integer(len))
+            };
+            RedisResponse::single(Integer(added as i64))
+        }
+        Command::SCard(key) => {
+            let mut storage = lock_for_reading(storage, stats, &[&key]);
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::single(Integer(0));
             }
-            Command::SRem(key, values) => {
-                let mut storage = lock_then_release(storage);
-                let keytype = storage.type_of(&key);
-                if keytype == "none".as_bytes() {
-                    return RedisResponse::single(Integer(0));
+            if keytype != "set".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            // will never panic, the key holds a set
+            let values = storage.sread(&key).unwrap();
+            let len = values.len() as i64;
+            RedisResponse::single(Integer(len))
+        }
+        Command::SRem(key, values) => {
+            let mut storage = storage.lock();
+            let keytype = storage.type_of(&key);
+            if keytype == "none".as_bytes() {
+                return RedisResponse::single(Integer(0));
+            }
+            if keytype != "set".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            // will never panic, the key holds a set
+            let members = storage.sread_mut(&key).unwrap();
+            let removed = values.iter().filter(|value| members.remove(value)).count();
+            if members.is_empty() {
+                storage.remove(&key);
+            }
+            RedisResponse::single(Integer(removed as i64))
+        }
+        Command::SScan(key, cursor, options) => {
+            let mut storage = lock_for_reading(storage, stats, &[&key]);
+            let keytype = storage.type_of(&key);
+            if keytype != "set".as_bytes() && keytype != "none".as_bytes() {
+                return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            let (cursor, members) = storage
+                .sscan(&key, cursor, &options)
+                .unwrap_or((0, Vec::new()));
+            drop(storage);
+            let elements = members.into_iter().map(BulkString).collect();
+            RedisResponse::array(scan_reply(cursor, elements))
+        }
+        Command::Scan(cursor, options) => {
+            let (cursor, keys) = storage.lock().scan_keys(cursor, &options);
+            let prefix_len = context.full_key_prefix().map_or(0, |prefix| prefix.len());
+            let elements = keys
+                .into_iter()
+                .map(|key| BulkString(key[prefix_len..].to_vec()))
+                .collect();
+            RedisResponse::array(scan_reply(cursor, elements))
+        }
+        Command::Del(keys) => {
+            let mut storage = storage.lock();
+            let d = storage.mremove(&borrow_keys(&keys));
+            if d as usize >= SHRINK_AFTER_REMOVED {
+                storage.shrink_to_fit();
+            }
+            RedisResponse::single(Integer(d as i64))
+        }
+        Command::DelPattern(pattern) => {
+            let removed = storage.lock().remove_matching(&pattern);
+            RedisResponse::single(Integer(removed as i64))
+        }
+        Command::Incr(k) => incr_by(storage, &k, 1),
+        Command::IncrBy(k, increment) => incr_by(storage, &k, increment),
+        Command::Type(k) => {
+            let mut s = storage.lock();
+            let value_type = s.type_of(k.as_slice());
+            RedisResponse::single(SimpleString(value_type.to_vec()))
+        }
+        Command::Exists(k) => {
+            let exists = storage.lock().contains(&k);
+            let exists: i64 = match exists {
+                true => 1,
+                false => 0,
+            };
+            RedisResponse::single(Integer(exists))
+        }
+        Command::Ttl(k) => {
+            let mut storage = storage.lock();
+            // expired keys not removed yet are missing all the same
+            let expiry = match storage.contains(&k) {
+                true => storage.meta(&k).map(|meta| meta.expiry),
+                false => None,
+            };
+            let ttl = match expiry {
+                Some(Some(expiry)) => expiry.duration_left_millis() / 1000,
+                Some(None) => -1,
+                None => -2,
+            };
+            RedisResponse::single(Integer(ttl))
+        }
+        Command::Pttl(k) => {
+            let mut storage = storage.lock();
+            // expired keys not removed yet are missing all the same
+            let expiry = match storage.contains(&k) {
+                true => storage.meta(&k).map(|meta| meta.expiry),
+                false => None,
+            };
+            let ttl = match expiry {
+                Some(Some(expiry)) => expiry.duration_left_millis(),
+                Some(None) => -1,
+                None => -2,
+            };
+            RedisResponse::single(Integer(ttl))
+        }
+        Command::Info(section) => {
+            let info = match section.map(|s| s.to_ascii_lowercase()).as_deref() {
+                // a bare INFO has always been answered with an empty bulk string
+                None => String::new(),
+                Some(b"clients") | Some(b"default") => stats.clients_info(),
+                Some(b"shadow") => stats.shadow_info(),
+                Some(b"stats") => stats.stats_info(),
+                Some(b"replication") => stats.replication_info(),
+                Some(b"errorstats") => stats.errorstats_info(),
+                Some(b"keyspace") => keyspace_info(&storage.lock()),
+                Some(b"all") | Some(b"everything") => format!(
+                    "{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n{}",
+                    stats.clients_info(),
+                    stats.stats_info(),
+                    stats.replication_info(),
+                    stats.shadow_info(),
+                    stats.errorstats_info(),
+                    keyspace_info(&storage.lock())
+                ),
+                Some(_) => String::new(),
+            };
+            RedisResponse::single(BulkString(info.into_bytes()))
+        }
+        Command::Select(db) => {
+            if db < 0 || db as usize >= DATABASES {
+                RedisResponse::error(RedisCommandError::DbIndexOutOfRange)
+            } else {
+                context.db = db as usize;
+                RedisResponse::okay()
+            }
+        }
+        Command::Auth(username, password) => {
+            match authenticate(stats, context, username.as_deref(), &password) {
+                Ok(()) => RedisResponse::okay(),
+                Err(err) => RedisResponse::error(err),
+            }
+        }
+        Command::Hello(version, credentials, name) => {
+            let resp3 = match version {
+                None => context.resp3,
+                Some(2) => false,
+                Some(3) => true,
+                Some(_) => return RedisResponse::error(RedisCommandError::NoProto),
+            };
+            if let Some((username, password)) = credentials {
+                if let Err(err) = authenticate(stats, context, Some(&username), &password) {
+                    return RedisResponse::error(err);
                 }
-                if keytype != "set".as_bytes() {
-                    return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+            }
+            if let Some(name) = name {
+                if !name.iter().all(|c| (b'!'..=b'~').contains(c)) {
+                    return RedisResponse::error(RedisCommandError::InvalidClientName);
                 }
-                let mut vals = storage.sread(&key).unwrap().to_owned();
-                let mut rem = 0;
-                for v in values {
-                    if vals.remove(&v) {
-                        rem = rem + 1;
-                    }
+                context.name = if name.is_empty() { None } else { Some(name) };
+            }
+            // invalidations can't be pushed over RESP2
+            if !resp3 && context.tracking {
+                if let Some(client) = context.client {
+                    stats.tracking().stop(client);
                 }
-                storage.swrite(&key, vals);
-                RedisResponse::single(Integer(rem))
-            }
-            Command::Del(k) => {
-                let d = lock_then_release(storage).remove(k.as_slice());
-                RedisResponse::single(Integer(d as i64))
-            }
-            Command::Incr(k) => {
-                let mut storage = lock_then_release(storage);
-
-                match storage.read(k.as_slice()) {
-                    Some(value) => {
-                        if let Ok(mut int_val) = std::str::from_utf8(value).unwrap().parse::<i64>()
-                        {
-                            int_val += 1;
-                            let new_value = int_val.to_string().into_bytes();
-                            storage.write(k.as_slice(), new_value.as_slice());
-                            RedisResponse::single(Integer(int_val as i64))
-                        } else {
-                            // handle this error
-                            unimplemented!()
-                        }
-                    }
-                    None => {
-                        let val = "1";
-                        storage.write(&k, val.as_bytes());
-                        RedisResponse::single(Integer(1))
+                context.tracking = false;
+            }
+            context.resp3 = resp3;
+
+            let role = match stats.role() {
+                ServerRole::Leader => "master",
+                ServerRole::Follower { .. } => "replica",
+            };
+            let fields = vec![
+                ("server", BulkString(b"redis".to_vec())),
+                ("version", BulkString(REDIS_VERSION.as_bytes().to_vec())),
+                ("proto", Integer(if resp3 { 3 } else { 2 })),
+                ("mode", BulkString(b"standalone".to_vec())),
+                ("role", BulkString(role.as_bytes().to_vec())),
+                ("modules", Array(Vec::new())),
+            ];
+            let fields = fields
+                .into_iter()
+                .map(|(field, value)| (BulkString(field.as_bytes().to_vec()), value));
+            RedisResponse::single(match resp3 {
+                true => Map(fields.collect()),
+                false => Array(
+                    fields
+                        .flat_map(|(field, value)| vec![field, value])
+                        .collect(),
+                ),
+            })
+        }
+        Command::ClientSetName(name) => {
+            // same rule as redis, a name is printable and has no spaces
+            if name.iter().all(|c| (b'!'..=b'~').contains(c)) {
+                context.name = if name.is_empty() { None } else { Some(name) };
+                RedisResponse::okay()
+            } else {
+                RedisResponse::error(RedisCommandError::InvalidClientName)
+            }
+        }
+        Command::ClientGetName => match &context.name {
+            Some(name) => RedisResponse::single(BulkString(name.clone())),
+            None => RedisResponse::single(Nil),
+        },
+        Command::ClientPause(timeout, writes_only) => {
+            stats.pause_clients(Duration::from_millis(timeout), writes_only);
+            RedisResponse::okay()
+        }
+        Command::ClientUnpause => {
+            stats.unpause_clients();
+            RedisResponse::okay()
+        }
+        Command::ClientNoEvict(no_evict) => {
+            context.no_evict = no_evict;
+            RedisResponse::okay()
+        }
+        Command::ClientTracking(Some(_)) if !context.resp3 => {
+            RedisResponse::error(RedisCommandError::TrackingWithoutResp3)
+        }
+        Command::ClientTracking(options) => {
+            // commands not sent over a connection have no one to push invalidations to
+            if let Some(client) = context.client {
+                context.tracking = options.is_some();
+                match options {
+                    Some(options) => {
+                        stats
+                            .tracking()
+                            .start(client, options, context.full_key_prefix())
                     }
+                    None => stats.tracking().stop(client),
                 }
             }
-            Command::IncrBy(k, increment) => {
-                let mut storage = lock_then_release(storage);
-
-                match storage.read(k.as_slice()) {
-                    Some(value) => {
-                        if let Ok(mut int_val) = std::str::from_utf8(value).unwrap().parse::<i64>()
-                        {
-                            int_val += increment;
-                            let new_value = int_val.to_string().into_bytes();
-                            storage.write(k.as_slice(), new_value.as_slice());
-                            RedisResponse::single(Integer(int_val as i64))
-                        } else {
-                            //RedisResponse::error(...)
-                            unimplemented!()
-                        }
-                    }
-                    None => {
-                        let val = increment.to_string();
-                        storage.write(&k, val.as_bytes());
-                        RedisResponse::single(Integer(increment))
-                    }
+            RedisResponse::okay()
+        }
+        Command::CommandCount => RedisResponse::single(Integer(command_table().len() as i64)),
+        Command::CommandInfo(names) if names.is_empty() => {
+            RedisResponse::array(command_table().iter().map(command_info).collect())
+        }
+        Command::CommandInfo(names) => {
+            let infos = names
+                .iter()
+                .map(
+                    |name| match command_spec(&String::from_utf8_lossy(name).to_lowercase()) {
+                        Some(spec) => command_info(spec),
+                        None => Nil,
+                    },
+                )
+                .collect();
+            RedisResponse::array(infos)
+        }
+        Command::ConfigGet(pattern) => {
+            let limits = storage.lock().encoding_limits();
+            let pattern = pattern.to_ascii_lowercase();
+            let read_only = match stats.read_only() {
+                true => "yes",
+                false => "no",
+            };
+            let elements = EncodingLimits::PARAMETERS
+                .iter()
+                .map(|name| (*name, limits.get(name).unwrap_or_default()))
+                .chain(Some((READ_ONLY_PARAMETER, read_only.to_string())))
+                .filter(|(name, _)| glob_match(&pattern, name.as_bytes()))
+                .flat_map(|(name, value)| {
+                    vec![
+                        BulkString(name.as_bytes().to_vec()),
+                        BulkString(value.into_bytes()),
+                    ]
+                })
+                .collect();
+            RedisResponse::array(elements)
+        }
+        Command::ConfigSet(name, value) => {
+            let name = String::from_utf8_lossy(&name).to_lowercase();
+            if name == READ_ONLY_PARAMETER {
+                let read_only = match value.to_ascii_lowercase().as_slice() {
+                    b"yes" => true,
+                    b"no" => false,
+                    _ => return RedisResponse::error(RedisCommandError::InvalidConfigBool(name)),
+                };
+                stats.set_read_only(read_only);
+                return RedisResponse::okay();
+            }
+            if !EncodingLimits::PARAMETERS.contains(&name.as_str()) {
+                return RedisResponse::error(RedisCommandError::UnknownConfigOption(name));
+            }
+            let mut storage = storage.lock();
+            let mut limits = storage.encoding_limits();
+            if !limits.set(&name, &value) {
+                return RedisResponse::error(RedisCommandError::InvalidConfigValue(name));
+            }
+            storage.set_encoding_limits(limits);
+            RedisResponse::okay()
+        }
+        Command::ConfigResetStat => {
+            stats.reset();
+            RedisResponse::okay()
+        }
+        Command::LatencyLatest => {
+            let latest = stats
+                .latency()
+                .latest()
+                .into_iter()
+                .map(|(event, last, max)| {
+                    Array(vec![
+                        BulkString(event.into_bytes()),
+                        Integer(last.time),
+                        Integer(last.latency as i64),
+                        Integer(max as i64),
+                    ])
+                })
+                .collect();
+            RedisResponse::array(latest)
+        }
+        Command::LatencyHistory(event) => {
+            let history = stats
+                .latency()
+                .history(&String::from_utf8_lossy(&event))
+                .into_iter()
+                .map(|sample| Array(vec![Integer(sample.time), Integer(sample.latency as i64)]))
+                .collect();
+            RedisResponse::array(history)
+        }
+        Command::LatencyReset(events) => {
+            let events: Vec<_> = events
+                .iter()
+                .map(|event| String::from_utf8_lossy(event))
+                .collect();
+            let reset = stats.latency().reset(&events);
+            RedisResponse::single(Integer(reset as i64))
+        }
+        Command::LatencyDoctor => {
+            RedisResponse::single(BulkString(stats.latency().doctor().into_bytes()))
+        }
+        Command::DebugTypeStats => {
+            let storage = storage.lock();
+            let counts = RedisType::ALL
+                .iter()
+                .flat_map(|data_type| {
+                    vec![
+                        BulkString(data_type.name().as_bytes().to_vec()),
+                        Integer(storage.count_of(*data_type) as i64),
+                    ]
+                })
+                .collect();
+            RedisResponse::array(counts)
+        }
+        Command::DebugReload => {
+            // a round trip through the encoding of the dataset, as a restart loading it would
+            let mut storage = storage.lock();
+            let dump = storage.export().encode();
+            match DatasetSnapshot::decode(&dump) {
+                Some(dataset) => {
+                    storage.import(dataset);
+                    RedisResponse::okay()
                 }
+                None => RedisResponse::error(RedisCommandError::ReloadFailed),
             }
-            Command::Type(k) => {
-                let mut s = lock_then_release(storage);
-                let value_type = s.type_of(k.as_slice());
-                RedisResponse::single(SimpleString(value_type.to_vec()))
+        }
+        Command::DebugChangeReplId => {
+            stats.replication().change_id();
+            RedisResponse::okay()
+        }
+        Command::DebugDigest => {
+            let dataset = storage.lock().export();
+            let digest = dataset.digest();
+            RedisResponse::single(SimpleString(format!("{:016x}", digest).into_bytes()))
+        }
+        Command::DebugDigestValue(keys) => {
+            let mut storage = storage.lock();
+            let digests = keys
+                .iter()
+                .map(|key| {
+                    let digest = storage
+                        .dump_entry(key)
+                        .map_or(0, |entry| entry.value.digest());
+                    SimpleString(format!("{:016x}", digest).into_bytes())
+                })
+                .collect();
+            RedisResponse::array(digests)
+        }
+        Command::DebugFaultSet(command, latency, jitter) => {
+            let command = String::from_utf8_lossy(&command);
+            let latency = InjectedLatency { latency, jitter };
+            stats.faults().set(&command, latency);
+            RedisResponse::okay()
+        }
+        Command::DebugFaultReset(command) => {
+            let command = command.map(|command| String::from_utf8_lossy(&command).to_string());
+            stats.faults().reset(command.as_deref());
+            RedisResponse::okay()
+        }
+        Command::DebugFaultList => {
+            let latencies = stats.faults().latencies();
+            RedisResponse::array(
+                latencies
+                    .iter()
+                    .map(|(command, latency)| BulkString(latency.describe(command).into_bytes()))
+                    .collect(),
+            )
+        }
+        Command::ClusterLeader => match stats.role().leader_addr(stats.sentinel().addr()) {
+            Some(addr) => RedisResponse::single(BulkString(addr.to_string().into_bytes())),
+            None => RedisResponse::single(Nil),
+        },
+        Command::ClusterRedislessStatus => match stats.cluster_status() {
+            Some(status) => {
+                RedisResponse::single(BulkString(status.info(Instant::now()).into_bytes()))
             }
-            Command::Exists(k) => {
-                let exists = lock_then_release(storage).contains(&k);
-                let exists: i64 = match exists {
-                    true => 1,
-                    false => 0,
-                };
-                RedisResponse::single(Integer(exists))
+            None => RedisResponse::error(RedisCommandError::ClusterSupportDisabled),
+        },
+        Command::ClusterFailover(force) => match stats.cluster_status() {
+            Some(status) if status.leader.as_ref() == Some(&status.node_id) => {
+                RedisResponse::error(RedisCommandError::FailoverOnLeader)
             }
-            Command::Ttl(k) => {
-                let ttl = if let Some(meta) = lock_then_release(storage).meta(&k) {
-                    if let Some(expiry) = meta.expiry {
-                        expiry.duration_left_millis() / 1000
-                    } else {
-                        -1
-                    }
-                } else {
-                    -2
-                };
-                RedisResponse::single(Integer(ttl))
+            Some(status) if status.leader.is_none() && !force => {
+                RedisResponse::error(RedisCommandError::FailoverWithoutLeader)
             }
-            Command::Pttl(k) => {
-                let ttl = if let Some(meta) = lock_then_release(storage).meta(&k) {
-                    if let Some(expiry) = meta.expiry {
-                        expiry.duration_left_millis()
-                    } else {
-                        -1
-                    }
-                } else {
-                    -2
-                };
-                RedisResponse::single(Integer(ttl))
+            Some(_) => {
+                stats
+                    .events()
+                    .send(ServerEvent::FailoverRequested { force });
+                RedisResponse::okay()
+            }
+            None => RedisResponse::error(RedisCommandError::ClusterSupportDisabled),
+        },
+        Command::SentinelGetMasterAddrByName(name) => {
+            match stats.sentinel().master_addr(&name, stats.role()) {
+                Some(addr) => RedisResponse::array(vec![
+                    BulkString(addr.ip().to_string().into_bytes()),
+                    BulkString(addr.port().to_string().into_bytes()),
+                ]),
+                None => RedisResponse::single(NullArray),
+            }
+        }
+        Command::SentinelMasters => {
+            let sentinel = stats.sentinel();
+            let masters = sentinel
+                .master_fields(sentinel.master_name().as_bytes(), stats.role())
+                .into_iter()
+                .map(sentinel_fields)
+                .collect();
+            RedisResponse::array(masters)
+        }
+        Command::SentinelSlaves(name) => {
+            match stats.sentinel().replica_fields(&name, stats.role()) {
+                Some(replicas) => {
+                    RedisResponse::array(replicas.into_iter().map(sentinel_fields).collect())
+                }
+                None => RedisResponse::error(RedisCommandError::NoSuchMaster),
             }
-            Command::Info => RedisResponse::single(BulkString("".as_bytes().to_vec())),
-            Command::Ping => RedisResponse::pong(),
-            Command::Dbsize => {
-                let storage = lock_then_release(storage);
-                let size = storage.size() as i64;
-                RedisResponse::single(Integer(size))
+        }
+        Command::Ping => RedisResponse::pong(),
+        Command::Dbsize => {
+            let storage = storage.lock();
+            let size = match context.full_key_prefix() {
+                Some(prefix) => storage
+                    .iter_keys()
+                    .filter(|key| key.starts_with(&prefix))
+                    .count() as i64,
+                None => storage.size() as i64,
+            };
+            RedisResponse::single(Integer(size))
+        }
+        Command::FlushAll | Command::FlushDb => {
+            // there is a single database, both clear it
+            let mut storage = storage.lock();
+            match context.full_key_prefix() {
+                Some(prefix) => {
+                    let keys: Vec<RedisString> = storage
+                        .iter_keys()
+                        .filter(|key| key.starts_with(&prefix))
+                        .map(<[u8]>::to_vec)
+                        .collect();
+                    storage.mremove(&borrow_keys(&keys));
+                }
+                None => storage.flush(),
+            }
+            storage.shrink_to_fit();
+            RedisResponse::okay()
+        }
+        Command::MemoryPurge => {
+            storage.lock().shrink_to_fit();
+            RedisResponse::okay()
+        }
+        Command::ObjectEncoding(key) => match storage.lock().encoding(&key) {
+            Some(encoding) => {
+                RedisResponse::single(BulkString(encoding.name().as_bytes().to_vec()))
             }
-            Command::Quit => RedisResponse::quit(),
+            None => RedisResponse::single(Nil),
         },
-        Err(err) => RedisResponse::error(err),
+        Command::ReadOnly => {
+            context.readonly = true;
+            RedisResponse::okay()
+        }
+        Command::ReadWrite => {
+            context.readonly = false;
+            RedisResponse::okay()
+        }
+        Command::Reset => {
+            if let Some(client) = context.client.filter(|_| context.tracking) {
+                stats.tracking().stop(client);
+            }
+            context.reset(stats.acl().default_user());
+            RedisResponse::single(SimpleString(b"RESET".to_vec()))
+        }
+        Command::Quit => RedisResponse::quit(),
+    }
+}
+
+/// Run the connection as `username` once `password` is checked against `ServerConfig::users`.
+/// For the default user, `password` may instead be the token of a virtual instance, whose keys
+/// the connection then uses.
+fn authenticate(
+    stats: &ServerStats,
+    context: &mut ConnectionContext,
+    username: Option<&[u8]>,
+    password: &[u8],
+) -> Result<(), RedisCommandError> {
+    let username = username.unwrap_or(DEFAULT_USER.as_bytes());
+    if username == DEFAULT_USER.as_bytes() {
+        if let Some(prefix) = stats.virtual_instances().key_prefix(password) {
+            context.instance_prefix = Some(prefix);
+            context.authenticated = true;
+            return Ok(());
+        }
+    }
+
+    match stats.acl().user(username) {
+        Some(user) if user.accepts(password) => {
+            context.user = Some(user);
+            context.authenticated = true;
+            Ok(())
+        }
+        _ => Err(RedisCommandError::WrongPass),
+    }
+}
+
+/// INCRBY, the value being parsed and rewritten where it's stored
+fn incr_by<T: Storage>(storage: &Stripes<'_, T>, key: &[u8], increment: i64) -> RedisResponse {
+    use protocol::response::RedisResponseType::*;
+    let mut storage = storage.lock();
+
+    // worked out before the key is touched, so a refused increment leaves its version as is
+    let parsed = storage.read(key).map(|value| {
+        std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse::<i64>().ok())
+            .ok_or(RedisCommandError::NotAnInteger)
+    });
+    let current = match parsed {
+        Some(current) => current,
+        None if storage.contains(key) => Err(RedisCommandError::WrongTypeOperation),
+        None => Ok(0),
     };
-    response
+    let int_val = match current.and_then(|current| {
+        current
+            .checked_add(increment)
+            .ok_or(RedisCommandError::IncrOverflow)
+    }) {
+        Ok(int_val) => int_val,
+        Err(err) => return RedisResponse::error(err),
+    };
+
+    let updated = storage.update_in_place(key, |value| {
+        value.clear();
+        // never fails, writing to a Vec
+        let _ = write!(value, "{}", int_val);
+    });
+    if updated.is_none() {
+        storage.write(key, int_val.to_string().as_bytes());
+    }
+    RedisResponse::single(Integer(int_val))
+}
+
+/// Set the fields of the hash at `key`, creating it if needed, return how many weren't there
+fn hset<T: Storage>(
+    storage: &Stripes<'_, T>,
+    key: &[u8],
+    items: Vec<(RedisString, RedisString)>,
+) -> Result<usize, RedisCommandError> {
+    let mut storage = storage.lock();
+    let keytype = storage.type_of(key);
+    if keytype != "hash".as_bytes() && keytype != "none".as_bytes() {
+        return Err(RedisCommandError::WrongTypeOperation);
+    }
+
+    let limits = storage.encoding_limits();
+    let added = match storage.hread_mut(key) {
+        Some(hash) => {
+            let mut added = 0;
+            for (field, value) in items {
+                if !hash.data.contains_key(&field) {
+                    added += 1;
+                }
+                hash.insert(field, value, &limits);
+            }
+            added
+        }
+        None => {
+            let fields: HashMap<_, _> = items.into_iter().collect();
+            let added = fields.len();
+            storage.hwrite(key, fields);
+            added
+        }
+    };
+    Ok(added)
+}
+
+/// Time left before each of `fields` expires, in units of `millis_per_unit`, as HTTL and HPTTL
+/// reply: -1 for a field without expiry, -2 for a missing one
+fn hash_field_ttls<T: Storage>(
+    storage: &Stripes<'_, T>,
+    stats: &ServerStats,
+    key: &[u8],
+    fields: &[RedisString],
+    millis_per_unit: i64,
+) -> RedisResponse {
+    use protocol::response::RedisResponseType::*;
+    let mut storage = lock_for_reading(storage, stats, &[key]);
+    let keytype = storage.type_of(key);
+    if keytype != "hash".as_bytes() && keytype != "none".as_bytes() {
+        return RedisResponse::error(RedisCommandError::WrongTypeOperation);
+    }
+
+    let hash = match storage.hread_all(key) {
+        Some(hash) => hash,
+        None => return RedisResponse::array(fields.iter().map(|_| Integer(-2)).collect()),
+    };
+    let replies = fields
+        .iter()
+        .map(|field| match hash.expiries.get(field) {
+            Some(expiry) => Integer(expiry.duration_left_millis() / millis_per_unit),
+            None if hash.data.contains_key(field) => Integer(-1),
+            None => Integer(-2),
+        })
+        .collect();
+    RedisResponse::array(replies)
+}
+
+/// Lock the storage for a command reading the values of `keys`, each counted as a keyspace hit
+/// or miss under the same lock as the read itself
+fn lock_for_reading<'a, T: Storage>(
+    storage: &Stripes<'a, T>,
+    stats: &ServerStats,
+    keys: &[&[u8]],
+) -> StripedGuard<'a, T> {
+    let mut storage = storage.lock();
+    for key in keys {
+        stats.keyspace_lookup(storage.contains(key));
+    }
+    storage
+}
+
+fn borrow_keys(keys: &[RedisString]) -> Vec<&[u8]> {
+    keys.iter().map(|key| key.as_slice()).collect()
+}
+
+fn borrow_items(items: &[(RedisString, RedisString)]) -> Vec<(&[u8], &[u8])> {
+    items
+        .iter()
+        .map(|(key, value)| (key.as_slice(), value.as_slice()))
+        .collect()
+}
+
+/// `INFO keyspace` section: the keys of the only database, by type, none when it's empty
+fn keyspace_info<T: Storage>(storage: &T) -> String {
+    let mut info = "# Keyspace\r\n".to_string();
+    let keys = storage.size();
+    if keys > 0 {
+        info.push_str(&format!("db0:keys={}", keys));
+        for data_type in RedisType::ALL {
+            info.push_str(&format!(
+                ",{}={}",
+                data_type.name(),
+                storage.count_of(data_type)
+            ));
+        }
+        info.push_str("\r\n");
+    }
+    info
+}
+
+/// `[field, value, ...]` as SENTINEL replies describe a server
+fn sentinel_fields(fields: Vec<(&str, String)>) -> RedisResponseType {
+    RedisResponseType::Array(
+        fields
+            .into_iter()
+            .flat_map(|(field, value)| {
+                vec![
+                    RedisResponseType::BulkString(field.as_bytes().to_vec()),
+                    RedisResponseType::BulkString(value.into_bytes()),
+                ]
+            })
+            .collect(),
+    )
+}
+
+/// `[name, arity, [flag, ...], first key, last key, step]` as listed by COMMAND
+fn command_info(spec: &CommandSpec) -> RedisResponseType {
+    let flags = spec
+        .kind
+        .flag()
+        .map(|flag| RedisResponseType::SimpleString(flag.as_bytes().to_vec()))
+        .into_iter()
+        .collect();
+
+    RedisResponseType::Array(vec![
+        RedisResponseType::BulkString(spec.name.as_bytes().to_vec()),
+        RedisResponseType::Integer(spec.arity.to_redis()),
+        RedisResponseType::Array(flags),
+        RedisResponseType::Integer(spec.first_key as i64),
+        RedisResponseType::Integer(spec.last_key),
+        RedisResponseType::Integer(spec.step as i64),
+    ])
+}
+
+/// `[cursor, [element, ...]]` as expected by clients iterating with *SCAN
+fn scan_reply(cursor: u64, elements: Vec<RedisResponseType>) -> Vec<RedisResponseType> {
+    vec![
+        RedisResponseType::BulkString(cursor.to_string().into_bytes()),
+        RedisResponseType::Array(elements),
+    ]
 }
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use uuid::Uuid;
+
+use crate::storage::models::RedisString;
+
+/// Named keyspace of a server, see `Server::virtual_instance`. A connection uses it once it
+/// sent `AUTH <token>`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VirtualHandle {
+    name: String,
+    token: String,
+    port: u16,
+}
+
+impl VirtualHandle {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Password the clients of the instance authenticate with
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// `redis://` URL of the instance, with its token as password
+    pub fn url(&self) -> String {
+        format!("redis://:{}@127.0.0.1:{}/", self.token, self.port)
+    }
+}
+
+/// Virtual instances of a server by name, each with the token selecting it
+#[derive(Debug, Default)]
+pub struct VirtualInstances {
+    tokens: RwLock<HashMap<String, String>>,
+}
+
+impl VirtualInstances {
+    /// Instance called `name`, registered on first use
+    pub fn get_or_create(&self, name: &str, port: u16) -> VirtualHandle {
+        let mut tokens = self
+            .tokens
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let token = tokens
+            .entry(name.to_string())
+            .or_insert_with(|| Uuid::new_v4().to_string());
+
+        VirtualHandle {
+            name: name.to_string(),
+            token: token.clone(),
+            port,
+        }
+    }
+
+    /// Prefix of the keys of the instance `token` selects, `None` if it selects none
+    pub fn key_prefix(&self, token: &[u8]) -> Option<RedisString> {
+        let tokens = self
+            .tokens
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        tokens
+            .iter()
+            .find(|(_, instance_token)| instance_token.as_bytes() == token)
+            .map(|(name, _)| format!("{{{}}}:", name).into_bytes())
+    }
+}
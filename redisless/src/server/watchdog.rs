@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command::command_error::RedisCommandError;
+use crate::storage::striped::Stripes;
+
+/// How often a command waiting for the storage checks whether it's still held
+const ADMISSION_POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Commands running against the storage, so that one holding it for too long doesn't freeze
+/// every other client, see `ServerConfig::busy_threshold`. A command can't be interrupted
+/// without leaving the storage half modified: the one running is left to finish and reported
+/// once it does, those sent meanwhile are answered with BUSY instead of waiting.
+#[derive(Debug)]
+pub struct Watchdog {
+    threshold: Option<Duration>,
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, (&'static str, Instant)>>,
+}
+
+impl Watchdog {
+    /// Watch the commands running for `threshold` or longer, nothing when it's `None`
+    pub fn new(threshold: Option<Duration>) -> Self {
+        Watchdog {
+            threshold,
+            next_id: AtomicU64::new(0),
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait for the `stripes` of the storage to be free before running the command `name`, and
+    /// keep track of it until the returned guard is dropped. Fails with BUSY when a command has
+    /// been running for longer than the threshold meanwhile.
+    pub fn admit<T>(
+        &self,
+        stripes: &Stripes<'_, T>,
+        name: &'static str,
+    ) -> Result<Option<RunningCommand<'_>>, RedisCommandError> {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return Ok(None),
+        };
+
+        loop {
+            if stripes.are_free() {
+                break;
+            }
+            if self
+                .longest_running()
+                .is_some_and(|elapsed| elapsed >= threshold)
+            {
+                return Err(RedisCommandError::Busy);
+            }
+            thread::sleep(ADMISSION_POLL_INTERVAL);
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.lock().insert(id, (name, Instant::now()));
+        Ok(Some(RunningCommand {
+            watchdog: self,
+            id,
+            threshold,
+        }))
+    }
+
+    fn longest_running(&self) -> Option<Duration> {
+        self.lock()
+            .values()
+            .map(|(_, started)| started.elapsed())
+            .max()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, (&'static str, Instant)>> {
+        self.running
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A command admitted by `Watchdog::admit`, running until dropped
+pub struct RunningCommand<'a> {
+    watchdog: &'a Watchdog,
+    id: u64,
+    threshold: Duration,
+}
+
+impl Drop for RunningCommand<'_> {
+    fn drop(&mut self) {
+        if let Some((name, started)) = self.watchdog.lock().remove(&self.id) {
+            let elapsed = started.elapsed();
+            if elapsed >= self.threshold {
+                log::warn!(
+                    "{} held the storage for {} ms, the commands sent meanwhile were answered with BUSY",
+                    name,
+                    elapsed.as_millis()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+
+use super::config::ServerConfig;
+use super::context::ConnectionContext;
+use super::latency::COMMAND_EVENT;
+use super::shadow::Shadow;
+use super::stats::ServerStats;
+use super::upstream::{is_local_command, Failover};
+use super::util::{get_command, get_command_name, run_command_and_get_response};
+use crate::command::command_error::RedisCommandError;
+use crate::protocol::response::RedisResponse;
+use crate::protocol::writer::RespWriter;
+use crate::storage::striped::StripedStorage;
+use crate::storage::Storage;
+
+/// Jobs that can wait for a free worker before the I/O loop stops dispatching
+const QUEUED_JOBS_PER_WORKER: usize = 64;
+
+pub type ConnectionId = usize;
+
+/// Pipelined requests laid one after the other in a single buffer, reused from one job to the
+/// next so queuing requests doesn't allocate
+#[derive(Default)]
+pub struct Requests {
+    bytes: Vec<u8>,
+    // where each request ends in `bytes`
+    ends: Vec<usize>,
+}
+
+impl Requests {
+    pub fn push(&mut self, request: &[u8]) {
+        self.bytes.extend_from_slice(request);
+        self.ends.push(self.bytes.len());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        starts
+            .zip(self.ends.iter().copied())
+            .map(move |(start, end)| &self.bytes[start..end])
+    }
+
+    /// Every request, one after the other
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Bytes held for the requests, including the room left by previous ones
+    pub fn capacity(&self) -> usize {
+        self.bytes.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+        self.ends.clear();
+    }
+}
+
+/// Pipelined requests read from a connection, waiting to be executed in order
+pub struct Job {
+    pub connection_id: ConnectionId,
+    pub requests: Requests,
+    pub context: ConnectionContext,
+    /// Where the replies are serialized
+    pub writer: RespWriter,
+}
+
+/// The replies to a `Job`, to be written back by the I/O loop
+pub struct JobResult {
+    pub connection_id: ConnectionId,
+    /// The requests answered, handed back for their buffer to be reused
+    pub requests: Requests,
+    pub writer: RespWriter,
+    pub quit: bool,
+    /// Requests left unanswered at the end of `requests`, from a blocking command waiting on
+    /// `context.blocked` on, to run again later
+    pub unanswered: usize,
+    pub context: ConnectionContext,
+}
+
+/// Fixed set of threads executing commands against the storage
+pub struct WorkerPool {
+    job_send: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn new<T: Storage + Send + 'static>(
+        config: &ServerConfig,
+        storage: &Arc<StripedStorage<T>>,
+        stats: &Arc<ServerStats>,
+        result_send: &Sender<JobResult>,
+    ) -> Self {
+        let size = config.workers.max(1);
+        let (job_send, job_recv) = crossbeam_channel::bounded(size * QUEUED_JOBS_PER_WORKER);
+        let failover = config.upstream.clone().map(Failover::new);
+
+        let workers = (0..size)
+            .filter_map(|idx| {
+                let job_recv = job_recv.clone();
+                let result_send = result_send.clone();
+                let storage = storage.clone();
+                let stats = stats.clone();
+                let upstreams = Upstreams {
+                    // each worker mirrors its commands on its own upstream connection
+                    shadow: config.shadow.clone().map(Shadow::new),
+                    failover: failover.clone(),
+                };
+
+                thread::Builder::new()
+                    .name(format!("request handler {}", idx))
+                    .spawn(move || run_worker(&job_recv, &result_send, &storage, &stats, upstreams))
+                    .ok()
+            })
+            .collect();
+
+        WorkerPool {
+            job_send: Some(job_send),
+            workers,
+        }
+    }
+
+    /// Queue `job` for execution, or hand it back when every worker is busy and the queue is full
+    pub fn try_dispatch(&self, job: Job) -> Option<Job> {
+        match self.job_send.as_ref() {
+            Some(job_send) => match job_send.try_send(job) {
+                Ok(()) => None,
+                Err(TrySendError::Full(job)) | Err(TrySendError::Disconnected(job)) => Some(job),
+            },
+            None => Some(job),
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // disconnecting the queue makes every worker return once it's drained
+        self.job_send = None;
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run_worker<T: Storage>(
+    job_recv: &Receiver<Job>,
+    result_send: &Sender<JobResult>,
+    storage: &Arc<StripedStorage<T>>,
+    stats: &ServerStats,
+    mut upstreams: Upstreams,
+) {
+    for job in job_recv {
+        let Job {
+            connection_id,
+            requests,
+            mut context,
+            mut writer,
+        } = job;
+        let mut quit = false;
+        let mut unanswered = 0;
+
+        for (idx, request) in requests.iter().enumerate() {
+            // a request triggering a bug must not take the worker down with it
+            let started = Instant::now();
+            let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                run_command_and_get_response(storage, stats, &mut context, request)
+            }))
+            .unwrap_or_else(|_| RedisResponse::error(RedisCommandError::Internal));
+            stats.latency().record(COMMAND_EVENT, started.elapsed());
+            if res.is_blocked() {
+                // what's pipelined after the blocking command waits along with it
+                unanswered = requests.len() - idx;
+                break;
+            }
+            quit = res.is_quit();
+            // a command the server refused never reaches the upstreams, whose reply would
+            // otherwise stand in for the refusal
+            let refused = res.error_replied().is_some_and(|err| err.is_refusal());
+            let start = writer.len();
+            res.write_to(&mut writer);
+            if !refused {
+                upstreams.forward(request, &mut writer, start, stats);
+            }
+
+            if quit {
+                // whatever was pipelined after QUIT is never answered
+                break;
+            }
+        }
+
+        let result = JobResult {
+            connection_id,
+            requests,
+            writer,
+            quit,
+            unanswered,
+            context,
+        };
+
+        if result_send.send(result).is_err() {
+            // the I/O loop is gone
+            return;
+        }
+    }
+}
+
+/// Redis servers a worker forwards the requests it executed to
+struct Upstreams {
+    shadow: Option<Shadow>,
+    failover: Option<Failover>,
+}
+
+impl Upstreams {
+    /// Forward `request`, which the server ran and whose local reply was written in `writer`
+    /// from `start`, and replace that reply with the one to send back
+    fn forward(
+        &mut self,
+        request: &[u8],
+        writer: &mut RespWriter,
+        start: usize,
+        stats: &ServerStats,
+    ) {
+        if self.shadow.is_none() && self.failover.is_none() {
+            return;
+        }
+        if is_local_command(&get_command_name(request)) {
+            return;
+        }
+
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.compare(request, &writer.as_slice()[start..], stats);
+        }
+
+        if let Some(failover) = self.failover.as_mut() {
+            let is_write = get_command(request)
+                .map(|command| command.is_write())
+                .unwrap_or(false);
+            let upstream_reply = failover.forward(request, is_write);
+
+            if let Some(upstream_reply) = upstream_reply {
+                writer.truncate(start);
+                writer.raw(&upstream_reply);
+            }
+        }
+    }
+}
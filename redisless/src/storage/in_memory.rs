@@ -1,42 +1,247 @@
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 
 use prost::bytes::BufMut;
 
 use super::models::*;
+use super::scan::{ScanMap, ScanOptions};
 use crate::storage::Storage;
 
 pub struct InMemoryStorage {
     data_mapper: HashMap<RedisString, RedisMeta>,
+    // the keys of `data_mapper` in the order SCAN walks them
+    key_positions: ScanMap<()>,
     string_store: HashMap<RedisString, RedisString>,
-    list_store: HashMap<RedisString, Vec<RedisString>>,
-    set_store: HashMap<RedisString, HashSet<RedisString>>,
+    list_store: HashMap<RedisString, RedisList>,
+    set_store: HashMap<RedisString, RedisSet>,
     hash_store: HashMap<RedisString, RedisHashMap>,
+    // deadlines of the keys with an expiry, soonest first. Entries are left behind when a key is
+    // removed, overwritten or given another expiry, and skipped once they reach the top.
+    expirations: BinaryHeap<Reverse<(i64, RedisString)>>,
+    // last version given to a key, see `Storage::version`
+    last_version: u64,
+    // false when expirations are replicated: expired keys are then only removed once told to, so
+    // every node serves the same keys whatever its clock says
+    expires_locally: bool,
+    // keys are iterated in byte order, see `new_deterministic`
+    deterministic: bool,
+    // bytes of the values as last measured, `None` until `value_bytes` is first called
+    value_bytes: Option<u64>,
+    // keys whose value may have changed since it was last measured
+    resized: HashSet<RedisString>,
+    encoding_limits: EncodingLimits,
 }
 
 impl InMemoryStorage {
     pub fn new() -> Self {
         Self {
             data_mapper: HashMap::new(),
+            key_positions: ScanMap::new(),
             string_store: HashMap::new(),
             list_store: HashMap::new(),
             set_store: HashMap::new(),
             hash_store: HashMap::new(),
+            expirations: BinaryHeap::new(),
+            last_version: 0,
+            expires_locally: true,
+            deterministic: false,
+            value_bytes: None,
+            resized: HashSet::new(),
+            encoding_limits: EncodingLimits::default(),
         }
     }
+
+    /// Storage iterating its keys in byte order, so that exports, snapshots and what's derived
+    /// from them are the same from one run to the next, at the cost of sorting the keys every
+    /// time. SCAN and its variants return the same pages for the same keys either way.
+    pub fn new_deterministic() -> Self {
+        Self {
+            deterministic: true,
+            ..Self::new()
+        }
+    }
+
+    /// Storage of a cluster node, which keeps expired keys until the leader deletes them through
+    /// the Raft log, see `cluster::expiry`
+    pub fn new_with_replicated_expiry() -> Self {
+        Self {
+            expires_locally: false,
+            ..Self::new()
+        }
+    }
+
+    /// Whether `meta` is expired and must be treated as missing
+    fn is_expired(&self, meta: &RedisMeta) -> bool {
+        self.expires_locally && meta.is_expired()
+    }
+
+    /// Record `key` as holding a value of `data_type` without expiry, dropping the value of
+    /// another type it may have held so every store agrees with `data_mapper`
+    fn set_meta(&mut self, key: &[u8], data_type: RedisType) {
+        let previous = self
+            .data_mapper
+            .insert(key.to_vec(), RedisMeta::new(data_type, None));
+        self.touch(key);
+
+        match previous {
+            Some(previous) => {
+                self.uncount(&previous);
+                if previous.data_type != data_type {
+                    self.remove_value(key, previous.data_type);
+                }
+            }
+            None => {
+                self.key_positions.insert(key.to_vec(), ());
+            }
+        }
+    }
+
+    /// Give `key` a new version, as its value or expiry is about to change
+    fn touch(&mut self, key: &[u8]) {
+        if let Some(meta) = self.data_mapper.get_mut(key) {
+            self.last_version += 1;
+            meta.version = self.last_version;
+            if self.value_bytes.is_some() && !self.resized.contains(key) {
+                self.resized.insert(key.to_vec());
+            }
+        }
+    }
+
+    /// Take the bytes last measured for the value of `meta` off `value_bytes`, as it's replaced
+    /// or removed
+    fn uncount(&mut self, meta: &RedisMeta) {
+        if let Some(value_bytes) = self.value_bytes.as_mut() {
+            *value_bytes = value_bytes.saturating_sub(meta.bytes);
+        }
+    }
+
+    /// Bytes of the value stored at `key`, see `Storage::value_bytes`
+    fn measure(&self, key: &[u8], data_type: RedisType) -> u64 {
+        let bytes = match data_type {
+            RedisType::String => self.string_store.get(key).map_or(0, |value| value.len()),
+            RedisType::List => self
+                .list_store
+                .get(key)
+                .map_or(0, |list| list.iter().map(|element| element.len()).sum()),
+            RedisType::Set => self
+                .set_store
+                .get(key)
+                .map_or(0, |set| set.iter().map(|member| member.len()).sum()),
+            RedisType::Hash => self.hash_store.get(key).map_or(0, |hash| {
+                hash.data
+                    .iter()
+                    .map(|(field, value)| field.len() + value.len())
+                    .sum()
+            }),
+        };
+        bytes as u64
+    }
+
+    /// Remove the value of `key` from the store of `data_type`, return whether there was one
+    fn remove_value(&mut self, key: &[u8], data_type: RedisType) -> bool {
+        use RedisType::*;
+        match data_type {
+            String => self.string_store.remove(key).is_some(),
+            Hash => self.hash_store.remove(key).is_some(),
+            List => self.list_store.remove(key).is_some(),
+            Set => self.set_store.remove(key).is_some(),
+        }
+    }
+
+    /// The hash stored at `key` once its expired fields are removed, the key goes with them when
+    /// none is left
+    fn live_hash(&mut self, key: &[u8]) -> Option<&mut RedisHashMap> {
+        // drops the key if it's expired
+        if !self.contains(key) {
+            return None;
+        }
+
+        let hash = self.hash_store.get_mut(key)?;
+        if hash.remove_expired() > 0 {
+            if hash.data.is_empty() {
+                self.remove(key);
+                return None;
+            }
+            self.touch(key);
+        }
+        self.hash_store.get_mut(key)
+    }
+
+    /// Whether `key` still expires at `timestamp`
+    fn is_expiration_current(&self, timestamp: i64, key: &[u8]) -> bool {
+        match self.data_mapper.get(key) {
+            Some(meta) => meta.expiry == Some(Expiry { timestamp }),
+            None => false,
+        }
+    }
+
+    /// Rebuild `expirations` once left behind entries make up most of it
+    fn compact_expirations(&mut self) {
+        if self.expirations.len() <= 2 * self.data_mapper.len() + 64 {
+            return;
+        }
+
+        self.expirations = self
+            .data_mapper
+            .iter()
+            .filter_map(|(key, meta)| {
+                meta.expiry
+                    .map(|expiry| Reverse((expiry.timestamp, key.clone())))
+            })
+            .collect();
+    }
 }
 
 impl Storage for InMemoryStorage {
     fn write(&mut self, key: &[u8], value: &[u8]) {
-        let meta = RedisMeta::new(RedisType::String, None);
-        self.data_mapper.insert(key.to_vec(), meta);
+        self.set_meta(key, RedisType::String);
         self.string_store.insert(key.to_vec(), value.to_vec());
     }
+
+    fn write_keeping_expiry(&mut self, key: &[u8], value: &[u8]) {
+        // drops the key if it's expired, its expiry then goes with it
+        let expiry = match self.contains(key) {
+            true => self.data_mapper.get(key).and_then(|meta| meta.expiry),
+            false => None,
+        };
+
+        self.write(key, value);
+        // `expirations` still holds the deadline, which is current again
+        if let Some(meta) = self.data_mapper.get_mut(key) {
+            meta.expiry = expiry;
+        }
+    }
+
+    fn mwrite(&mut self, items: &[(&[u8], &[u8])]) {
+        for (key, value) in items {
+            self.write(key, value);
+        }
+    }
+
+    fn bulk_load<I: IntoIterator<Item = (RedisString, RedisString)>>(&mut self, items: I) -> u64 {
+        let items = items.into_iter();
+        // grow the maps once rather than over and over
+        let (len, _) = items.size_hint();
+        self.data_mapper.reserve(len);
+        self.string_store.reserve(len);
+
+        let mut written = 0;
+        for (key, value) in items {
+            self.set_meta(&key, RedisType::String);
+            self.string_store.insert(key, value);
+            written += 1;
+        }
+        written
+    }
+
     fn extend(&mut self, key: &[u8], tail: &[u8]) -> u64 {
-        match self.string_store.get_mut(key) {
-            Some(v) => {
-                v.put_slice(tail);
-                v.len() as u64
-            }
+        let extended = self.update_in_place(key, |value| {
+            value.put_slice(tail);
+            value.len() as u64
+        });
+
+        match extended {
+            Some(len) => len,
             None => {
                 self.write(key, tail);
                 tail.len() as u64
@@ -44,9 +249,34 @@ impl Storage for InMemoryStorage {
         }
     }
 
+    fn update_in_place<R, F: FnOnce(&mut RedisString) -> R>(
+        &mut self,
+        key: &[u8],
+        update: F,
+    ) -> Option<R> {
+        // drops the key if it's expired
+        if !self.contains(key) {
+            return None;
+        }
+
+        let value = self.string_store.get_mut(key)?;
+        let result = update(value);
+        self.touch(key);
+        Some(result)
+    }
+
     fn expire(&mut self, key: &[u8], expiry: Expiry) -> u32 {
+        // drops the key if it's expired, it's not brought back
+        if !self.contains(key) {
+            return 0;
+        }
+
         if let Some(meta) = self.data_mapper.get_mut(key) {
             meta.expiry = Some(expiry);
+            self.touch(key);
+            self.expirations
+                .push(Reverse((expiry.timestamp, key.to_vec())));
+            self.compact_expirations();
             1 // timeout was set
         } else {
             0 // key does not exist
@@ -55,44 +285,80 @@ impl Storage for InMemoryStorage {
 
     fn read(&mut self, key: &[u8]) -> Option<&[u8]> {
         if let Some(value) = self.data_mapper.get(key) {
-            match value.is_expired() {
+            match self.is_expired(value) {
                 true => {
                     self.remove(key);
                     None
                 }
-                false => Some(self.string_store.get(key).unwrap()),
+                // nothing for the keys holding another type
+                false => self.string_store.get(key).map(|value| value.as_slice()),
             }
         } else {
             None
         }
     }
 
+    fn mread(&mut self, keys: &[&[u8]]) -> Vec<Option<RedisString>> {
+        keys.iter()
+            .map(|key| self.read(key).map(|value| value.to_vec()))
+            .collect()
+    }
+
     fn meta(&self, key: &[u8]) -> Option<&RedisMeta> {
         self.data_mapper.get(key)
     }
 
+    fn encoding(&mut self, key: &[u8]) -> Option<Encoding> {
+        // drops the key if it's expired, and a hash with its last field
+        if !self.contains(key) {
+            return None;
+        }
+        if self.meta(key)?.data_type == RedisType::Hash {
+            self.live_hash(key)?;
+        }
+
+        let encoding = match self.meta(key)?.data_type {
+            RedisType::String => Encoding::of_string(self.string_store.get(key)?),
+            RedisType::List => self.list_store.get(key)?.encoding(),
+            RedisType::Set => self.set_store.get(key)?.encoding(),
+            RedisType::Hash => self.hash_store.get(key)?.encoding(),
+        };
+        Some(encoding)
+    }
+
+    fn encoding_limits(&self) -> EncodingLimits {
+        self.encoding_limits
+    }
+
+    fn set_encoding_limits(&mut self, limits: EncodingLimits) {
+        self.encoding_limits = limits;
+    }
+
+    fn version(&self, key: &[u8]) -> u64 {
+        match self.data_mapper.get(key) {
+            Some(meta) if !self.is_expired(meta) => meta.version,
+            _ => 0,
+        }
+    }
+
     fn remove(&mut self, key: &[u8]) -> u32 {
-        use RedisType::*;
-        match self.data_mapper.remove_entry(key) {
-            Some((key, meta)) => match meta.data_type {
-                String => match self.string_store.remove(&key) {
-                    Some(_) => 1,
-                    None => 0,
-                },
-                Hash => match self.hash_store.remove(&key) {
-                    Some(_) => 1,
-                    None => 0,
-                },
-                List => match self.list_store.remove(&key) {
-                    Some(_) => 1,
-                    None => 0,
-                },
-                Set => unimplemented!(),
-            },
-            None => 0,
+        let meta = match self.data_mapper.remove(key) {
+            Some(meta) => meta,
+            None => return 0,
+        };
+        self.key_positions.remove(key);
+        self.uncount(&meta);
+        self.resized.remove(key);
+        match self.remove_value(key, meta.data_type) {
+            true => 1,
+            false => 0,
         }
     }
 
+    fn mremove(&mut self, keys: &[&[u8]]) -> u32 {
+        keys.iter().map(|key| self.remove(key)).sum()
+    }
+
     /// If the key was present **and** the key was not expired, return `true`
     ///
     /// If the key present but was expired, remove the key and return `false`
@@ -100,7 +366,7 @@ impl Storage for InMemoryStorage {
     /// If the key was not present at all, return `false`
     fn contains(&mut self, key: &[u8]) -> bool {
         if let Some(meta) = self.data_mapper.get(key) {
-            match meta.is_expired() {
+            match self.is_expired(meta) {
                 true => {
                     self.remove(key);
                     false
@@ -113,37 +379,27 @@ impl Storage for InMemoryStorage {
     }
 
     fn type_of(&mut self, key: &[u8]) -> &[u8] {
+        // drops the key if it's expired
+        if !self.contains(key) {
+            return b"none";
+        }
+
         let t = match self.meta(key) {
-            Some(RedisMeta {
-                data_type: RedisType::String,
-                ..
-            }) => "string",
-            Some(RedisMeta {
-                data_type: RedisType::List,
-                ..
-            }) => "list",
-            Some(RedisMeta {
-                data_type: RedisType::Set,
-                ..
-            }) => "set",
-            Some(RedisMeta {
-                data_type: RedisType::Hash,
-                ..
-            }) => "hash",
+            Some(meta) => meta.data_type.name(),
             None => "none",
         };
         t.as_bytes()
     }
 
-    fn lwrite(&mut self, key: &[u8], values: Vec<RedisString>) {
-        let meta = RedisMeta::new(RedisType::List, None);
-        self.data_mapper.insert(key.to_vec(), meta);
-        self.list_store.insert(key.to_vec(), values);
+    fn lwrite(&mut self, key: &[u8], values: VecDeque<RedisString>) {
+        self.set_meta(key, RedisType::List);
+        let list = RedisList::new(values, &self.encoding_limits);
+        self.list_store.insert(key.to_vec(), list);
     }
 
-    fn lread(&mut self, key: &[u8]) -> Option<&Vec<RedisString>> {
+    fn lread(&mut self, key: &[u8]) -> Option<&RedisList> {
         if let Some(meta) = self.data_mapper.get(key) {
-            match meta.is_expired() {
+            match self.is_expired(meta) {
                 true => {
                     self.remove(key);
                     None
@@ -158,15 +414,103 @@ impl Storage for InMemoryStorage {
         }
     }
 
+    /// The list stored at `key`, to be updated in place
+    ///
+    /// The caller is responsible for removing the key if it leaves the list empty
+    fn lread_mut(&mut self, key: &[u8]) -> Option<&mut RedisList> {
+        if !self.contains(key) {
+            return None;
+        }
+
+        // the caller is given the list to change it
+        self.touch(key);
+        self.list_store.get_mut(key)
+    }
+
+    /// Push each value at the head of the list, creating it if needed, return the new length
+    fn lpush_front(&mut self, key: &[u8], values: Vec<RedisString>) -> usize {
+        if self.lread_mut(key).is_none() {
+            self.lwrite(key, VecDeque::with_capacity(values.len()));
+        }
+
+        // will never panic, the list was created above
+        let list = self.list_store.get_mut(key).unwrap();
+        for value in values {
+            list.push_front(value, &self.encoding_limits);
+        }
+        list.len()
+    }
+
+    /// Push each value at the tail of the list, creating it if needed, return the new length
+    fn lpush_back(&mut self, key: &[u8], values: Vec<RedisString>) -> usize {
+        if self.lread_mut(key).is_none() {
+            self.lwrite(key, VecDeque::with_capacity(values.len()));
+        }
+
+        // will never panic, the list was created above
+        let list = self.list_store.get_mut(key).unwrap();
+        for value in values {
+            list.push_back(value, &self.encoding_limits);
+        }
+        list.len()
+    }
+
+    fn lpop_front(&mut self, key: &[u8]) -> Option<RedisString> {
+        let list = self.lread_mut(key)?;
+        let value = list.pop_front();
+        if list.is_empty() {
+            self.remove(key);
+        }
+        value
+    }
+
+    fn lpop_back(&mut self, key: &[u8]) -> Option<RedisString> {
+        let list = self.lread_mut(key)?;
+        let value = list.pop_back();
+        if list.is_empty() {
+            self.remove(key);
+        }
+        value
+    }
+
+    /// Elements between `start` and `stop` included, negative indexes count from the tail
+    fn lrange(&mut self, key: &[u8], start: i64, stop: i64) -> Vec<RedisString> {
+        let list = match self.lread(key) {
+            Some(list) => list,
+            None => return Vec::new(),
+        };
+
+        let len = list.len() as i64;
+        let start = if start < 0 {
+            (start + len).max(0)
+        } else {
+            start
+        };
+        let stop = if stop < 0 {
+            stop + len
+        } else {
+            stop.min(len - 1)
+        };
+        if start > stop || start >= len {
+            return Vec::new();
+        }
+
+        list.iter()
+            .skip(start as usize)
+            .take((stop - start + 1) as usize)
+            .map(|value| value.to_vec())
+            .collect()
+    }
+
     fn swrite(&mut self, key: &[u8], values: HashSet<RedisString>) {
-        let meta = RedisMeta::new(RedisType::Set, None);
-        self.data_mapper.insert(key.to_vec(), meta);
-        self.set_store.insert(key.to_vec(), values);
+        self.set_meta(key, RedisType::Set);
+        let set = RedisSet::new(values, &self.encoding_limits);
+        self.set_store.insert(key.to_vec(), set);
     }
 
-    fn sread(&mut self, key: &[u8]) -> Option<&HashSet<RedisString>> {
+    fn sread(&mut self, key: &[u8]) -> Option<&RedisSet> {
         if let Some(meta) = self.data_mapper.get(key) {
-            match meta.is_expired() {
+            match self.is_expired(meta) {
                 true => {
                     self.remove(key);
                     None
@@ -181,37 +525,266 @@ impl Storage for InMemoryStorage {
         }
     }
 
+    fn sread_mut(&mut self, key: &[u8]) -> Option<&mut RedisSet> {
+        if !self.contains(key) {
+            return None;
+        }
+
+        // the caller is given the set to change it
+        self.touch(key);
+        self.set_store.get_mut(key)
+    }
+
+    fn sscan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Option<(u64, Vec<RedisString>)> {
+        Some(self.sread(key)?.scan(cursor, options))
+    }
+
     fn hwrite(&mut self, key: &[u8], value: HashMap<RedisString, RedisString>) {
-        let meta = RedisMeta::new(RedisType::Hash, None);
-        self.data_mapper.insert(key.to_vec(), meta);
-        self.hash_store
-            .insert(key.to_vec(), RedisHashMap::new(value));
+        self.set_meta(key, RedisType::Hash);
+        let hash = RedisHashMap::new(value, &self.encoding_limits);
+        self.hash_store.insert(key.to_vec(), hash);
     }
 
     fn hread(&mut self, key: &[u8], field_key: &[u8]) -> Option<&[u8]> {
-        if let Some(meta) = self.data_mapper.get(key) {
-            match meta.is_expired() {
-                true => {
-                    self.remove(key);
-                    None
+        let hash = self.live_hash(key)?;
+        hash.data.get(field_key)
+    }
+
+    fn hread_all(&mut self, key: &[u8]) -> Option<&RedisHashMap> {
+        self.live_hash(key).map(|hash| &*hash)
+    }
+
+    fn hread_mut(&mut self, key: &[u8]) -> Option<&mut RedisHashMap> {
+        self.live_hash(key)?;
+
+        // the caller is given the hash to change it
+        self.touch(key);
+        self.hash_store.get_mut(key)
+    }
+
+    fn hscan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Option<(u64, Vec<(RedisString, RedisString)>)> {
+        Some(self.live_hash(key)?.data.scan(cursor, options))
+    }
+
+    fn size(&self) -> u64 {
+        self.data_mapper.len() as u64
+    }
+
+    fn count_of(&self, data_type: RedisType) -> u64 {
+        // every store holds the keys of its type and no other
+        let count = match data_type {
+            RedisType::String => self.string_store.len(),
+            RedisType::List => self.list_store.len(),
+            RedisType::Set => self.set_store.len(),
+            RedisType::Hash => self.hash_store.len(),
+        };
+        count as u64
+    }
+
+    fn value_bytes(&mut self) -> u64 {
+        if self.value_bytes.is_none() {
+            // counted from now on, starting with every value there is
+            self.value_bytes = Some(0);
+            self.resized = self.data_mapper.keys().cloned().collect();
+        }
+
+        let mut value_bytes = self.value_bytes.unwrap_or_default();
+        for key in std::mem::take(&mut self.resized) {
+            let data_type = match self.data_mapper.get(&key) {
+                Some(meta) => meta.data_type,
+                None => continue,
+            };
+            let bytes = self.measure(&key, data_type);
+            if let Some(meta) = self.data_mapper.get_mut(&key) {
+                value_bytes = (value_bytes + bytes).saturating_sub(meta.bytes);
+                meta.bytes = bytes;
+            }
+        }
+        self.value_bytes = Some(value_bytes);
+        value_bytes
+    }
+
+    fn iter_keys(&self) -> Box<dyn Iterator<Item = &[u8]> + '_> {
+        let keys = self
+            .data_mapper
+            .iter()
+            .filter(move |(_, meta)| !self.is_expired(meta))
+            .map(|(key, _)| key.as_slice());
+        if !self.deterministic {
+            return Box::new(keys);
+        }
+
+        let mut keys: Vec<&[u8]> = keys.collect();
+        keys.sort_unstable();
+        Box::new(keys.into_iter())
+    }
+
+    fn scan_keys(&self, cursor: u64, options: &ScanOptions) -> (u64, Vec<RedisString>) {
+        // the type is filtered once the page is picked as MATCH is, so that COUNT stays the
+        // number of keys looked at
+        let (cursor, keys) = self.key_positions.scan(cursor, options);
+        let keys = keys
+            .into_iter()
+            .filter(|(key, _)| match self.data_mapper.get(key) {
+                Some(meta) => !self.is_expired(meta) && options.matches_type(meta.data_type),
+                None => false,
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        (cursor, keys)
+    }
+
+    fn flush(&mut self) {
+        self.data_mapper.clear();
+        self.key_positions.clear();
+        self.string_store.clear();
+        self.list_store.clear();
+        self.set_store.clear();
+        self.hash_store.clear();
+        self.expirations.clear();
+        self.resized.clear();
+        if let Some(value_bytes) = self.value_bytes.as_mut() {
+            *value_bytes = 0;
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.compact_expirations();
+        self.data_mapper.shrink_to_fit();
+        self.string_store.shrink_to_fit();
+        self.list_store.shrink_to_fit();
+        self.set_store.shrink_to_fit();
+        self.hash_store.shrink_to_fit();
+        self.expirations.shrink_to_fit();
+        self.resized.shrink_to_fit();
+    }
+
+    fn next_expiry(&mut self) -> Option<Expiry> {
+        while let Some(Reverse((timestamp, key))) = self.expirations.peek() {
+            if self.is_expiration_current(*timestamp, key) {
+                return Some(Expiry {
+                    timestamp: *timestamp,
+                });
+            }
+            self.expirations.pop();
+        }
+        None
+    }
+
+    fn remove_expired(&mut self, max: usize) -> usize {
+        if !self.expires_locally {
+            return 0;
+        }
+
+        let mut removed = 0;
+        while removed < max {
+            match self.next_expiry() {
+                Some(expiry) if expiry.duration_left_millis() <= 0 => {
+                    // will never panic, `next_expiry` just peeked it
+                    let Reverse((_, key)) = self.expirations.pop().unwrap();
+                    self.remove(&key);
+                    removed += 1;
                 }
-                // good to go
-                false => {
-                    // will never panic since we already checked if the key existed in data_mapper
-                    if let Some(field_value) = self.hash_store.get(key).unwrap().data.get(field_key)
-                    {
-                        Some(field_value)
-                    } else {
-                        None
-                    }
+                _ => break,
+            }
+        }
+        removed
+    }
+
+    fn due_expirations(&mut self, max: usize) -> Vec<RedisString> {
+        let mut due = Vec::new();
+        while due.len() < max {
+            match self.next_expiry() {
+                Some(expiry) if expiry.duration_left_millis() <= 0 => {
+                    // will never panic, `next_expiry` just peeked it
+                    due.push(self.expirations.pop().unwrap());
                 }
+                _ => break,
             }
-        } else {
-            None
         }
+
+        let keys = due.iter().map(|Reverse((_, key))| key.clone()).collect();
+        // the keys stay due until they are removed
+        self.expirations.extend(due);
+        keys
     }
 
-    fn size(&self) -> u64 {
-        self.data_mapper.len() as u64
+    fn dump_entry(&mut self, key: &[u8]) -> Option<StorageEntry> {
+        if !self.contains(key) {
+            return None;
+        }
+        // leaves out the expired fields, and the hash if they were all expired
+        if self.meta(key).map(|meta| meta.data_type) == Some(RedisType::Hash) {
+            self.live_hash(key)?;
+        }
+
+        // will never panic since we already checked if the key existed in data_mapper
+        let meta = self.data_mapper.get(key).unwrap();
+        let value = match meta.data_type {
+            RedisType::String => RedisValue::String(self.string_store.get(key)?.clone()),
+            RedisType::List => RedisValue::List(self.list_store.get(key)?.to_vec_deque()),
+            RedisType::Set => RedisValue::Set(self.set_store.get(key)?.to_hash_set()),
+            RedisType::Hash => RedisValue::Hash(self.hash_store.get(key)?.data.to_hash_map()),
+        };
+
+        Some(StorageEntry {
+            key: key.to_vec(),
+            value,
+            expiry: meta.expiry,
+        })
+    }
+
+    fn rename(&mut self, key: &[u8], new_key: &[u8]) -> bool {
+        // entries have no room for the expiries of the fields of a hash, they are moved aside
+        let field_expiries = self
+            .live_hash(key)
+            .map(|hash| std::mem::take(&mut hash.expiries));
+        let entry = match self.dump_entry(key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        self.remove(key);
+        self.restore_entry(StorageEntry {
+            key: new_key.to_vec(),
+            ..entry
+        });
+        if let (Some(expiries), Some(hash)) = (field_expiries, self.hash_store.get_mut(new_key)) {
+            hash.expiries = expiries;
+        }
+        true
+    }
+
+    fn restore_entry(&mut self, entry: StorageEntry) {
+        let StorageEntry { key, value, expiry } = entry;
+        match value {
+            RedisValue::String(value) => self.write(&key, &value),
+            RedisValue::List(values) => self.lwrite(&key, values),
+            RedisValue::Set(values) => self.swrite(&key, values),
+            RedisValue::Hash(values) => self.hwrite(&key, values),
+        }
+
+        if let Some(expiry) = expiry {
+            self.expire(&key, expiry);
+        }
+    }
+
+    fn new_stripe(&self) -> Option<Self> {
+        Some(Self {
+            expires_locally: self.expires_locally,
+            deterministic: self.deterministic,
+            encoding_limits: self.encoding_limits,
+            ..Self::new()
+        })
     }
 }
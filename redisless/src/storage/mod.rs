@@ -3,28 +3,185 @@ mod tests;
 
 pub mod in_memory;
 pub mod models;
+pub mod scan;
+pub mod striped;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use models::expiry::Expiry;
 use models::RedisString;
 
-use self::models::RedisMeta;
+use self::models::{
+    DatasetSnapshot, Encoding, EncodingLimits, RedisHashMap, RedisList, RedisMeta, RedisSet,
+    RedisType, StorageEntry,
+};
+use self::scan::ScanOptions;
 
+/// Keys and their values, by type
+///
+/// Writes follow the expiry rules of Redis. `write`, `mwrite`, `lwrite`, `swrite` and `hwrite`
+/// replace the value and drop the expiry of the key, as SET, GETSET or MSET do. Changing the
+/// value where it lies keeps the expiry, as INCR, APPEND, SADD or HSET do: `update_in_place`,
+/// `extend`, the `*read_mut` methods and the list pushes and pops. `write_keeping_expiry` and
+/// `rename` replace the value but keep the expiry, for SET KEEPTTL and RENAME.
 pub trait Storage {
     fn write(&mut self, key: &[u8], value: &[u8]);
+    /// Write the string `value` as `write` does, but keep the expiry `key` may have
+    fn write_keeping_expiry(&mut self, key: &[u8], value: &[u8]);
+    fn mwrite(&mut self, items: &[(&[u8], &[u8])]);
     fn extend(&mut self, key: &[u8], value: &[u8]) -> u64;
     fn expire(&mut self, key: &[u8], expiry: Expiry) -> u32;
     fn read(&mut self, key: &[u8]) -> Option<&[u8]>;
+    fn mread(&mut self, keys: &[&[u8]]) -> Vec<Option<RedisString>>;
     fn remove(&mut self, key: &[u8]) -> u32;
+    fn mremove(&mut self, keys: &[&[u8]]) -> u32;
     fn contains(&mut self, key: &[u8]) -> bool;
+    /// Name of the type of the value at `key`, `none` if it's missing or expired
     fn type_of(&mut self, key: &[u8]) -> &[u8];
-    fn lwrite(&mut self, key: &[u8], values: Vec<RedisString>);
-    fn lread(&mut self, key: &[u8]) -> Option<&Vec<RedisString>>;
+    fn lwrite(&mut self, key: &[u8], values: VecDeque<RedisString>);
+    fn lread(&mut self, key: &[u8]) -> Option<&RedisList>;
+    fn lread_mut(&mut self, key: &[u8]) -> Option<&mut RedisList>;
+    fn lpush_front(&mut self, key: &[u8], values: Vec<RedisString>) -> usize;
+    fn lpush_back(&mut self, key: &[u8], values: Vec<RedisString>) -> usize;
+    fn lpop_front(&mut self, key: &[u8]) -> Option<RedisString>;
+    fn lpop_back(&mut self, key: &[u8]) -> Option<RedisString>;
+    fn lrange(&mut self, key: &[u8], start: i64, stop: i64) -> Vec<RedisString>;
     fn swrite(&mut self, key: &[u8], values: HashSet<RedisString>);
-    fn sread(&mut self, key: &[u8]) -> Option<&HashSet<RedisString>>;
+    fn sread(&mut self, key: &[u8]) -> Option<&RedisSet>;
+    /// The set stored at `key`, to be updated in place
+    ///
+    /// The caller is responsible for removing the key if it leaves the set empty
+    fn sread_mut(&mut self, key: &[u8]) -> Option<&mut RedisSet>;
+    fn sscan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Option<(u64, Vec<RedisString>)>;
     fn hwrite(&mut self, key: &[u8], value: HashMap<RedisString, RedisString>);
     fn hread(&mut self, key: &[u8], field_key: &[u8]) -> Option<&[u8]>;
+    /// The hash stored at `key`, without the fields that expired
+    fn hread_all(&mut self, key: &[u8]) -> Option<&RedisHashMap>;
+    /// The hash stored at `key`, to be updated in place
+    fn hread_mut(&mut self, key: &[u8]) -> Option<&mut RedisHashMap>;
+    fn hscan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Option<(u64, Vec<(RedisString, RedisString)>)>;
+    /// Number of keys of any type, including expired keys not removed yet
     fn size(&self) -> u64;
+    /// Number of keys of `data_type`, including expired keys not removed yet
+    fn count_of(&self, data_type: RedisType) -> u64;
+    /// Bytes of the values of every key, including expired keys not removed yet: strings, list
+    /// elements, set members, and hash fields with their values. Only the values written since
+    /// the previous call are measured again, the first call walks every value.
+    fn value_bytes(&mut self) -> u64;
+    /// Every key that isn't expired, in no particular order
+    fn iter_keys(&self) -> Box<dyn Iterator<Item = &[u8]> + '_>;
+    /// Keys that aren't expired and of the type asked for if any, walked with a cursor as done
+    /// by SCAN
+    fn scan_keys(&self, cursor: u64, options: &ScanOptions) -> (u64, Vec<RedisString>);
+    /// Remove every key, whatever its type
+    fn flush(&mut self);
+    /// Give the memory held for keys since removed back to the allocator
+    fn shrink_to_fit(&mut self);
+    /// Soonest expiry among the keys, expired or not
+    fn next_expiry(&mut self) -> Option<Expiry>;
+    /// Remove up to `max` of the keys whose expiry is past, soonest first, return how many.
+    /// Storages whose expirations are replicated leave them to the Raft leader and remove none.
+    fn remove_expired(&mut self, max: usize) -> usize;
+    /// Up to `max` of the keys whose expiry is past, soonest first, without removing them
+    fn due_expirations(&mut self, max: usize) -> Vec<RedisString>;
+    /// Copy of the value, type and expiry stored under `key`. The expiries of the fields of a
+    /// hash are left out.
+    fn dump_entry(&mut self, key: &[u8]) -> Option<StorageEntry>;
+    /// Store a copy returned by `dump_entry`, replacing whatever was under its key
+    fn restore_entry(&mut self, entry: StorageEntry);
     fn meta(&self, key: &[u8]) -> Option<&RedisMeta>;
+    /// Encoding of the value under `key`, as replied by OBJECT ENCODING
+    fn encoding(&mut self, key: &[u8]) -> Option<Encoding>;
+    /// Sizes up to which lists, sets and hashes keep a compact encoding, to be given to the
+    /// values changed in place through `lread_mut`, `sread_mut` and `hread_mut`
+    fn encoding_limits(&self) -> EncodingLimits;
+    /// Change the limits for the values written or grown from now on, as CONFIG SET does
+    fn set_encoding_limits(&mut self, limits: EncodingLimits);
+    /// Version of the value under `key`, changed by every write to it and never reused, 0 when
+    /// there is no such key. Lets clients compare-and-set with CAS.
+    fn version(&self, key: &[u8]) -> u64;
+    /// Change the string stored under `key` where it lies, keeping its expiry, and return what
+    /// `update` returns. `None` when `key` holds no string, `update` is then not called. The key
+    /// gets a new version even if `update` leaves the value as it was.
+    fn update_in_place<R, F: FnOnce(&mut RedisString) -> R>(
+        &mut self,
+        key: &[u8],
+        update: F,
+    ) -> Option<R>;
+
+    /// Remove every key matching the glob-style `pattern`, return how many
+    fn remove_matching(&mut self, pattern: &[u8]) -> u32 {
+        let keys: Vec<RedisString> = self
+            .iter_keys()
+            .filter(|key| scan::glob_match(pattern, key))
+            .map(|key| key.to_vec())
+            .collect();
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+        self.mremove(&keys)
+    }
+
+    /// Move the value of `key` and its expiry to `new_key`, replacing whatever `new_key` held.
+    /// `false` when there is no `key`.
+    fn rename(&mut self, key: &[u8], new_key: &[u8]) -> bool {
+        let entry = match self.dump_entry(key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        self.remove(key);
+        self.restore_entry(StorageEntry {
+            key: new_key.to_vec(),
+            ..entry
+        });
+        true
+    }
+
+    /// Write the string `value` of every `(key, value)` of `items`, as SET without options does,
+    /// and return how many were written. Faster than one write at a time for large datasets,
+    /// e.g. test fixtures loaded at startup.
+    fn bulk_load<I: IntoIterator<Item = (RedisString, RedisString)>>(&mut self, items: I) -> u64
+    where
+        Self: Sized,
+    {
+        let mut written = 0;
+        for (key, value) in items {
+            self.write(&key, &value);
+            written += 1;
+        }
+        written
+    }
+
+    /// Copy of every key that isn't expired, with its value and expiry
+    fn export(&mut self) -> DatasetSnapshot {
+        let keys: Vec<RedisString> = self.iter_keys().map(|key| key.to_vec()).collect();
+        let entries = keys.iter().filter_map(|key| self.dump_entry(key)).collect();
+        DatasetSnapshot { entries }
+    }
+
+    /// Replace every key with those of `snapshot`, as returned by `export`
+    fn import(&mut self, snapshot: DatasetSnapshot) {
+        self.flush();
+        for entry in snapshot.entries {
+            self.restore_entry(entry);
+        }
+    }
+
+    /// Empty storage set up as this one, to hold the keys of another stripe of a
+    /// `StripedStorage`. `None` for a storage that can't be split, locked as a whole then.
+    fn new_stripe(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
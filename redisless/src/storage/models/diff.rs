@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use super::{DatasetSnapshot, Expiry, RedisString, RedisValue, StorageEntry};
+
+/// What changed from one snapshot to the next, see `diff`. Keys are in byte order.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct DatasetDiff {
+    /// Keys only found in the second snapshot
+    pub added: Vec<StorageEntry>,
+    /// Keys only found in the first snapshot
+    pub removed: Vec<StorageEntry>,
+    /// Keys found in both, with another value or expiry
+    pub changed: Vec<KeyDiff>,
+}
+
+impl DatasetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// How a key found in both snapshots changed
+#[derive(Debug, PartialEq, Clone)]
+pub struct KeyDiff {
+    pub key: RedisString,
+    /// `None` when only the expiry changed
+    pub value: Option<ValueDiff>,
+    /// Expiry before and after, `None` when it didn't change
+    pub expiry: Option<(Option<Expiry>, Option<Expiry>)>,
+}
+
+/// How the value of a key changed, detailed as far as its type allows
+#[derive(Debug, PartialEq, Clone)]
+pub enum ValueDiff {
+    /// The key holds a value of another type
+    Type {
+        before: RedisValue,
+        after: RedisValue,
+    },
+    String {
+        before: RedisString,
+        after: RedisString,
+    },
+    List {
+        before: VecDeque<RedisString>,
+        after: VecDeque<RedisString>,
+    },
+    /// Members in byte order
+    Set {
+        added: Vec<RedisString>,
+        removed: Vec<RedisString>,
+    },
+    /// Fields in byte order, those changed with their value before and after
+    Hash {
+        added: Vec<(RedisString, RedisString)>,
+        removed: Vec<(RedisString, RedisString)>,
+        changed: Vec<(RedisString, RedisString, RedisString)>,
+    },
+}
+
+/// Keys added, removed and changed from `before` to `after`, e.g. to check that code under test
+/// made exactly the expected writes between two `Server::export`
+pub fn diff(before: &DatasetSnapshot, after: &DatasetSnapshot) -> DatasetDiff {
+    let by_key = |snapshot: &'_ DatasetSnapshot| -> BTreeMap<RedisString, StorageEntry> {
+        snapshot
+            .entries
+            .iter()
+            .map(|entry| (entry.key.clone(), entry.clone()))
+            .collect()
+    };
+    let mut before = by_key(before);
+    let mut diff = DatasetDiff::default();
+
+    for (key, after) in by_key(after) {
+        let before = match before.remove(&key) {
+            Some(before) => before,
+            None => {
+                diff.added.push(after);
+                continue;
+            }
+        };
+
+        let value = diff_values(before.value, after.value);
+        let expiry = Some((before.expiry, after.expiry)).filter(|(before, after)| before != after);
+        if value.is_some() || expiry.is_some() {
+            diff.changed.push(KeyDiff { key, value, expiry });
+        }
+    }
+
+    // left are those without an entry after
+    diff.removed = before.into_values().collect();
+    diff
+}
+
+fn diff_values(before: RedisValue, after: RedisValue) -> Option<ValueDiff> {
+    if before == after {
+        return None;
+    }
+
+    Some(match (before, after) {
+        (RedisValue::String(before), RedisValue::String(after)) => {
+            ValueDiff::String { before, after }
+        }
+        (RedisValue::List(before), RedisValue::List(after)) => ValueDiff::List { before, after },
+        (RedisValue::Set(before), RedisValue::Set(after)) => {
+            let mut added: Vec<RedisString> = after.difference(&before).cloned().collect();
+            let mut removed: Vec<RedisString> = before.difference(&after).cloned().collect();
+            added.sort_unstable();
+            removed.sort_unstable();
+            ValueDiff::Set { added, removed }
+        }
+        (RedisValue::Hash(mut before), RedisValue::Hash(after)) => {
+            let mut added = Vec::new();
+            let mut changed = Vec::new();
+            for (field, value) in after {
+                match before.remove(&field) {
+                    None => added.push((field, value)),
+                    Some(previous) if previous != value => changed.push((field, previous, value)),
+                    Some(_) => {}
+                }
+            }
+            let mut removed: Vec<(RedisString, RedisString)> = before.into_iter().collect();
+            added.sort_unstable();
+            removed.sort_unstable();
+            changed.sort_unstable();
+            ValueDiff::Hash {
+                added,
+                removed,
+                changed,
+            }
+        }
+        (before, after) => ValueDiff::Type { before, after },
+    })
+}
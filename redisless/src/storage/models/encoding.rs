@@ -0,0 +1,147 @@
+use super::entry::parse_number;
+
+/// How a value is laid out in memory, as replied by OBJECT ENCODING
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Encoding {
+    /// String holding a 64-bit integer in its canonical form
+    Int,
+    /// String of at most 44 bytes
+    EmbStr,
+    Raw,
+    ListPack,
+    /// Hash in a listpack with some of its fields given an expiry
+    ListPackEx,
+    QuickList,
+    IntSet,
+    HashTable,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Int => "int",
+            Encoding::EmbStr => "embstr",
+            Encoding::Raw => "raw",
+            Encoding::ListPack => "listpack",
+            Encoding::ListPackEx => "listpackex",
+            Encoding::QuickList => "quicklist",
+            Encoding::IntSet => "intset",
+            Encoding::HashTable => "hashtable",
+        }
+    }
+
+    /// Encoding Redis reports for the string `value`, which is always stored the same here
+    pub fn of_string(value: &[u8]) -> Self {
+        if canonical_int(value).is_some() {
+            Encoding::Int
+        } else if value.len() <= 44 {
+            Encoding::EmbStr
+        } else {
+            Encoding::Raw
+        }
+    }
+}
+
+/// Sizes up to which lists, sets and hashes keep a compact encoding, the `*-max-*` parameters of
+/// CONFIG. Values growing past them are converted for good, as Redis does, while those created
+/// before a change keep their encoding.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EncodingLimits {
+    pub hash_max_listpack_entries: usize,
+    /// Longest field or value of a hash in a listpack
+    pub hash_max_listpack_value: usize,
+    /// Entries of a list in a listpack when positive, otherwise its size: -1 for 4 KiB, -2 for
+    /// 8 KiB and so on up to -5 for 64 KiB
+    pub list_max_listpack_size: i64,
+    pub set_max_intset_entries: usize,
+    pub set_max_listpack_entries: usize,
+    /// Longest member of a set in a listpack
+    pub set_max_listpack_value: usize,
+}
+
+impl Default for EncodingLimits {
+    /// The defaults of Redis
+    fn default() -> Self {
+        EncodingLimits {
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            list_max_listpack_size: -2,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+        }
+    }
+}
+
+impl EncodingLimits {
+    /// Names of the parameters, as given to CONFIG GET and CONFIG SET
+    pub const PARAMETERS: [&'static str; 6] = [
+        "hash-max-listpack-entries",
+        "hash-max-listpack-value",
+        "list-max-listpack-size",
+        "set-max-intset-entries",
+        "set-max-listpack-entries",
+        "set-max-listpack-value",
+    ];
+
+    /// Value of the parameter `name`, `None` if there is no such parameter
+    pub fn get(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "hash-max-listpack-entries" => self.hash_max_listpack_entries.to_string(),
+            "hash-max-listpack-value" => self.hash_max_listpack_value.to_string(),
+            "list-max-listpack-size" => self.list_max_listpack_size.to_string(),
+            "set-max-intset-entries" => self.set_max_intset_entries.to_string(),
+            "set-max-listpack-entries" => self.set_max_listpack_entries.to_string(),
+            "set-max-listpack-value" => self.set_max_listpack_value.to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Set the parameter `name` to the integer `value`, `false` if `value` isn't one. There
+    /// must be such a parameter, see `PARAMETERS`.
+    pub fn set(&mut self, name: &str, value: &[u8]) -> bool {
+        let limit = match name {
+            "hash-max-listpack-entries" => &mut self.hash_max_listpack_entries,
+            "hash-max-listpack-value" => &mut self.hash_max_listpack_value,
+            "list-max-listpack-size" => {
+                return match parse_number(value) {
+                    Some(size) => {
+                        self.list_max_listpack_size = size;
+                        true
+                    }
+                    None => false,
+                }
+            }
+            "set-max-intset-entries" => &mut self.set_max_intset_entries,
+            "set-max-listpack-entries" => &mut self.set_max_listpack_entries,
+            "set-max-listpack-value" => &mut self.set_max_listpack_value,
+            _ => return false,
+        };
+        match parse_number(value) {
+            Some(value) => {
+                *limit = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether a list of `len` entries taking `bytes` fits in a listpack
+    pub fn list_fits(&self, len: usize, bytes: usize) -> bool {
+        match self.list_max_listpack_size {
+            size if size > 0 => len as i64 <= size,
+            size => bytes <= 4096 << ((-size).clamp(1, 5) - 1),
+        }
+    }
+}
+
+/// The integer `value` holds, if it's written the way Redis would write it back: no sign for
+/// positive numbers, no leading zero
+pub fn canonical_int(value: &[u8]) -> Option<i64> {
+    let int: i64 = parse_number(value)?;
+    match int.to_string().as_bytes() == value {
+        true => Some(int),
+        false => None,
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::protocol::parser::RedisProtocolParser;
+use crate::protocol::writer::RespWriter;
+use crate::protocol::Resp;
+
+use super::{Expiry, RedisString};
+
+/// First element of an encoded dataset
+const DATASET: &[u8] = b"dataset";
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A value and its type, detached from the storage it was read from
+#[derive(Debug, PartialEq, Clone)]
+pub enum RedisValue {
+    String(RedisString),
+    List(VecDeque<RedisString>),
+    Set(HashSet<RedisString>),
+    Hash(HashMap<RedisString, RedisString>),
+}
+
+impl RedisValue {
+    /// Name of the type as returned by the TYPE command
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RedisValue::String(_) => "string",
+            RedisValue::List(_) => "list",
+            RedisValue::Set(_) => "set",
+            RedisValue::Hash(_) => "hash",
+        }
+    }
+
+    /// Checksum of the type and elements, as replied by DEBUG DIGEST-VALUE: the same for the
+    /// same value whatever the key holding it and its expiry
+    pub fn digest(&self) -> u64 {
+        self.digest_elements(fnv(FNV_OFFSET, self.type_name().as_bytes()))
+    }
+
+    /// `digest` carried on with the elements, those of sets and hashes in any order
+    fn digest_elements(&self, digest: u64) -> u64 {
+        match self {
+            RedisValue::String(value) => fnv(digest, value),
+            RedisValue::List(values) => values.iter().fold(digest, |digest, v| fnv(digest, v)),
+            RedisValue::Set(values) => {
+                let members = values
+                    .iter()
+                    .fold(0u64, |sum, v| sum.wrapping_add(fnv(FNV_OFFSET, v)));
+                fnv(digest, &members.to_le_bytes())
+            }
+            RedisValue::Hash(values) => {
+                let fields = values.iter().fold(0u64, |sum, (field, value)| {
+                    sum.wrapping_add(fnv(fnv(FNV_OFFSET, field), value))
+                });
+                fnv(digest, &fields.to_le_bytes())
+            }
+        }
+    }
+}
+
+/// Everything stored under a key, as returned by `Storage::dump_entry`
+#[derive(Debug, PartialEq, Clone)]
+pub struct StorageEntry {
+    pub key: RedisString,
+    pub value: RedisValue,
+    pub expiry: Option<Expiry>,
+}
+
+impl StorageEntry {
+    /// `[key, type, expiry in ms or an empty string, element...]` with the fields and values of
+    /// hashes one after the other. Members of sets and fields of hashes are in byte order, the
+    /// same entry is always encoded the same.
+    pub fn encode(&self, writer: &mut RespWriter) {
+        let elements: Vec<&[u8]> = match &self.value {
+            RedisValue::String(value) => vec![value],
+            RedisValue::List(values) => values.iter().map(|v| &v[..]).collect(),
+            RedisValue::Set(values) => {
+                let mut members: Vec<&[u8]> = values.iter().map(|v| &v[..]).collect();
+                members.sort_unstable();
+                members
+            }
+            RedisValue::Hash(values) => {
+                let mut fields: Vec<_> = values.iter().collect();
+                fields.sort_unstable();
+                fields
+                    .into_iter()
+                    .flat_map(|(field, value)| vec![&field[..], &value[..]])
+                    .collect()
+            }
+        };
+        let expiry = match self.expiry {
+            Some(expiry) => expiry.timestamp.to_string(),
+            None => String::new(),
+        };
+
+        writer.array_len(elements.len() + 3);
+        writer.bulk_string(&self.key);
+        writer.bulk_string(self.value.type_name().as_bytes());
+        writer.bulk_string(expiry.as_bytes());
+        for element in elements {
+            writer.bulk_string(element);
+        }
+    }
+
+    pub fn decode(entry: Resp) -> Option<StorageEntry> {
+        let fields = match entry {
+            Resp::Array(fields) => fields,
+            _ => return None,
+        };
+
+        let mut fields = fields.into_iter().map(bulk_string);
+        let key = fields.next()??.to_vec();
+        let type_name = fields.next()??;
+        let expiry = match fields.next()?? {
+            b"" => None,
+            timestamp => Some(Expiry {
+                timestamp: parse_number(timestamp)?,
+            }),
+        };
+        let elements = fields
+            .map(|element| element.map(|element| element.to_vec()))
+            .collect::<Option<Vec<RedisString>>>()?;
+
+        let value = match type_name {
+            b"string" if elements.len() == 1 => RedisValue::String(elements.into_iter().next()?),
+            b"list" => RedisValue::List(elements.into_iter().collect::<VecDeque<_>>()),
+            b"set" => RedisValue::Set(elements.into_iter().collect::<HashSet<_>>()),
+            b"hash" if elements.len() % 2 == 0 => {
+                let mut elements = elements.into_iter();
+                let mut values = HashMap::new();
+                while let (Some(field), Some(value)) = (elements.next(), elements.next()) {
+                    values.insert(field, value);
+                }
+                RedisValue::Hash(values)
+            }
+            _ => return None,
+        };
+
+        Some(StorageEntry { key, value, expiry })
+    }
+
+    /// Checksum of the key, value and expiry, the same whatever the order of the members of sets
+    /// and the fields of hashes
+    fn digest(&self) -> u64 {
+        let expiry = self.expiry.map(|expiry| expiry.timestamp.to_le_bytes());
+        let mut digest = fnv(FNV_OFFSET, &self.key);
+        digest = fnv(digest, self.value.type_name().as_bytes());
+        digest = fnv(
+            digest,
+            expiry.as_ref().map_or(&[][..], |expiry| &expiry[..]),
+        );
+        self.value.digest_elements(digest)
+    }
+}
+
+/// Every key of a storage with its value and expiry, as returned by `Storage::export`.
+///
+/// Made of plain data only so host applications can store it in the format of their choice,
+/// e.g. to keep the state of a test as a fixture, and hand it back to `Storage::import`.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct DatasetSnapshot {
+    pub entries: Vec<StorageEntry>,
+}
+
+impl DatasetSnapshot {
+    /// `["dataset", entry...]`, see `StorageEntry::encode`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = RespWriter::default();
+        writer.array_len(self.entries.len() + 1);
+        writer.bulk_string(DATASET);
+        for entry in &self.entries {
+            entry.encode(&mut writer);
+        }
+        writer.as_slice().to_vec()
+    }
+
+    pub fn decode(data: &[u8]) -> Option<DatasetSnapshot> {
+        let args = match RedisProtocolParser::parse(data) {
+            Ok((Resp::Array(args), b"")) => args,
+            _ => return None,
+        };
+
+        let mut args = args.into_iter();
+        if bulk_string(args.next()?)? != DATASET {
+            return None;
+        }
+        let entries = args.map(StorageEntry::decode).collect::<Option<_>>()?;
+        Some(DatasetSnapshot { entries })
+    }
+
+    /// Checksum of every key with its value and expiry, as replied by DEBUG DIGEST: the same for
+    /// the same data whatever the order of the entries, 0 when there are none. Stable across
+    /// runs and versions, unlike the hashers of the standard library.
+    pub fn digest(&self) -> u64 {
+        self.entries
+            .iter()
+            .fold(0, |digest, entry| digest.wrapping_add(entry.digest()))
+    }
+}
+
+/// 64-bit FNV-1a of `bytes` preceded by their length, so that consecutive fields can't be told
+/// apart from others split differently
+fn fnv(hash: u64, bytes: &[u8]) -> u64 {
+    let len = (bytes.len() as u64).to_le_bytes();
+    len.iter().chain(bytes).fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+pub(crate) fn bulk_string(resp: Resp<'_>) -> Option<&[u8]> {
+    match resp {
+        Resp::BulkString(bytes) => Some(bytes),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_number<T: std::str::FromStr>(bytes: &[u8]) -> Option<T> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
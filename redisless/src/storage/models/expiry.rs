@@ -10,6 +10,10 @@ pub struct TimeOverflow {}
 
 impl Expiry {
     pub fn new_from_millis(duration: u64) -> Result<Self, TimeOverflow> {
+        if duration > i64::MAX as u64 {
+            return Err(TimeOverflow {});
+        }
+
         Utc::now()
             .checked_add_signed(Duration::milliseconds(duration as i64))
             .map(|t| Self {
@@ -19,6 +23,11 @@ impl Expiry {
     }
 
     pub fn new_from_secs(duration: u64) -> Result<Self, TimeOverflow> {
+        // `Duration::seconds` panics past this
+        if duration > (i64::MAX / 1000) as u64 {
+            return Err(TimeOverflow {});
+        }
+
         Utc::now()
             .checked_add_signed(Duration::seconds(duration as i64))
             .map(|t| Self {
@@ -1,13 +1,234 @@
-use super::RedisString;
+use super::listpack::{self, ListPack};
+use super::{Encoding, EncodingLimits, Expiry, RedisString};
+use crate::storage::scan::{scan, ScanMap, ScanMapIter, ScanOptions};
 use std::collections::HashMap;
 
+/// Fields of a hash, some of which may expire on their own, see HEXPIRE
 #[derive(Debug, PartialEq)]
 pub struct RedisHashMap {
-    pub data: HashMap<RedisString, RedisString>,
+    pub data: HashFields,
+    /// Expiry of the fields given one, none of which is missing from `data`
+    pub expiries: HashMap<RedisString, Expiry>,
 }
 
 impl RedisHashMap {
-    pub fn new(data: HashMap<RedisString, RedisString>) -> Self {
-        Self { data }
+    pub fn new(data: HashMap<RedisString, RedisString>, limits: &EncodingLimits) -> Self {
+        Self {
+            data: HashFields::new(data, limits),
+            expiries: HashMap::new(),
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        match &self.data {
+            HashFields::ListPack(_) if !self.expiries.is_empty() => Encoding::ListPackEx,
+            HashFields::ListPack(_) => Encoding::ListPack,
+            HashFields::HashTable(_) => Encoding::HashTable,
+        }
+    }
+
+    /// Set `field` to `value`, dropping the expiry the field may have had as HSET does
+    pub fn insert(&mut self, field: RedisString, value: RedisString, limits: &EncodingLimits) {
+        self.expiries.remove(&field);
+        self.data.insert(field, value, limits);
+    }
+
+    /// Remove `field` and its expiry, return whether there was such a field
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        self.expiries.remove(field);
+        self.data.remove(field)
+    }
+
+    /// Remove the fields whose expiry is past, return how many
+    pub fn remove_expired(&mut self) -> usize {
+        let expired: Vec<RedisString> = self
+            .expiries
+            .iter()
+            .filter(|(_, expiry)| expiry.duration_left_millis() <= 0)
+            .map(|(field, _)| field.clone())
+            .collect();
+
+        for field in &expired {
+            self.remove(field);
+        }
+        expired.len()
+    }
+}
+
+/// Fields of a hash with their value, packed one after the other while they stay within the
+/// `hash-max-listpack-*` limits
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum HashFields {
+    ListPack(ListPack),
+    HashTable(ScanMap<RedisString>),
+}
+
+impl HashFields {
+    pub fn new(data: HashMap<RedisString, RedisString>, limits: &EncodingLimits) -> Self {
+        let fits = data.len() <= limits.hash_max_listpack_entries
+            && data
+                .iter()
+                .all(|(field, value)| fits_listpack(field, value, limits));
+        if !fits {
+            return HashFields::HashTable(data.into_iter().collect());
+        }
+
+        HashFields::ListPack(
+            data.iter()
+                .flat_map(|(field, value)| [field, value])
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            HashFields::ListPack(pack) => pack.len() / 2,
+            HashFields::HashTable(data) => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        match self {
+            HashFields::ListPack(pack) => Iter::ListPack(pack.iter()),
+            HashFields::HashTable(data) => Iter::HashTable(data.iter()),
+        }
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&[u8]> {
+        match self {
+            HashFields::ListPack(pack) => {
+                let mut entries = pack.iter();
+                while let (Some(f), Some(value)) = (entries.next(), entries.next()) {
+                    if f == field {
+                        return Some(value);
+                    }
+                }
+                None
+            }
+            HashFields::HashTable(data) => data.get(field).map(|value| value.as_slice()),
+        }
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Set `field` to `value`, return whether it's a new field. The fields are converted to a
+    /// hash table first if they no longer fit in a listpack.
+    pub fn insert(
+        &mut self,
+        field: RedisString,
+        value: RedisString,
+        limits: &EncodingLimits,
+    ) -> bool {
+        if let HashFields::ListPack(pack) = self {
+            let index = field_index(pack, &field);
+            let len = pack.len() / 2 + index.map_or(1, |_| 0);
+            if len > limits.hash_max_listpack_entries || !fits_listpack(&field, &value, limits) {
+                *self = HashFields::HashTable(
+                    self.iter()
+                        .map(|(field, value)| (field.to_vec(), value.to_vec()))
+                        .collect(),
+                );
+            } else {
+                return match index {
+                    Some(index) => !pack.replace(index + 1, &value),
+                    None => {
+                        pack.push_back(&field);
+                        pack.push_back(&value);
+                        true
+                    }
+                };
+            }
+        }
+
+        match self {
+            HashFields::HashTable(data) => data.insert(field, value).is_none(),
+            // converted above
+            HashFields::ListPack(_) => false,
+        }
+    }
+
+    /// Remove `field`, return whether there was such a field
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        match self {
+            HashFields::ListPack(pack) => match field_index(pack, field) {
+                Some(index) => {
+                    pack.remove(index);
+                    pack.remove(index);
+                    true
+                }
+                None => false,
+            },
+            HashFields::HashTable(data) => data.remove(field).is_some(),
+        }
+    }
+
+    /// Fields with their value from `cursor` on, see `scan::scan`
+    pub fn scan(
+        &self,
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> (u64, Vec<(RedisString, RedisString)>) {
+        let (cursor, page) = match self {
+            HashFields::HashTable(data) => {
+                let (cursor, page) = data.scan(cursor, options);
+                let page = page
+                    .into_iter()
+                    .map(|(field, value)| (field.as_slice(), value.as_slice()))
+                    .collect();
+                (cursor, page)
+            }
+            // few enough fields to look at them all
+            HashFields::ListPack(_) => scan(self.iter(), |(field, _)| field, cursor, options),
+        };
+        let page = page
+            .into_iter()
+            .map(|(field, value)| (field.to_vec(), value.to_vec()))
+            .collect();
+        (cursor, page)
+    }
+
+    /// Copy of the fields with their value
+    pub fn to_hash_map(&self) -> HashMap<RedisString, RedisString> {
+        self.iter()
+            .map(|(field, value)| (field.to_vec(), value.to_vec()))
+            .collect()
+    }
+}
+
+/// Index in `pack` of the entry holding `field`
+fn field_index(pack: &ListPack, field: &[u8]) -> Option<usize> {
+    pack.iter()
+        .step_by(2)
+        .position(|f| f == field)
+        .map(|i| 2 * i)
+}
+
+fn fits_listpack(field: &[u8], value: &[u8], limits: &EncodingLimits) -> bool {
+    field.len() <= limits.hash_max_listpack_value && value.len() <= limits.hash_max_listpack_value
+}
+
+/// Fields of `HashFields` with their value, in no particular order
+#[derive(Debug, Clone)]
+pub enum Iter<'a> {
+    ListPack(listpack::Iter<'a>),
+    HashTable(ScanMapIter<'a, RedisString>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::ListPack(entries) => Some((entries.next()?, entries.next()?)),
+            Iter::HashTable(data) => data
+                .next()
+                .map(|(field, value)| (field.as_slice(), value.as_slice())),
+        }
     }
 }
@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+
+use super::listpack::{self, ListPack};
+use super::{Encoding, EncodingLimits, RedisString};
+
+/// Elements of a list, packed while the list stays within `list-max-listpack-size`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RedisList {
+    ListPack(ListPack),
+    QuickList(VecDeque<RedisString>),
+}
+
+impl Default for RedisList {
+    fn default() -> Self {
+        RedisList::ListPack(ListPack::new())
+    }
+}
+
+impl RedisList {
+    pub fn new(values: VecDeque<RedisString>, limits: &EncodingLimits) -> Self {
+        let bytes: usize = values
+            .iter()
+            .map(|value| listpack::entry_size(value.len()))
+            .sum();
+        match limits.list_fits(values.len(), bytes) {
+            true => RedisList::ListPack(values.iter().collect()),
+            false => RedisList::QuickList(values),
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        match self {
+            RedisList::ListPack(_) => Encoding::ListPack,
+            RedisList::QuickList(_) => Encoding::QuickList,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            RedisList::ListPack(pack) => pack.len(),
+            RedisList::QuickList(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        match self {
+            RedisList::ListPack(pack) => Iter::ListPack(pack.iter()),
+            RedisList::QuickList(values) => Iter::QuickList(values.iter()),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        match self {
+            RedisList::ListPack(pack) => pack.get(index),
+            RedisList::QuickList(values) => values.get(index).map(|value| value.as_slice()),
+        }
+    }
+
+    /// Replace the element at `index`, return `false` if there is none
+    pub fn set(&mut self, index: usize, value: RedisString, limits: &EncodingLimits) -> bool {
+        let replaced = match self {
+            RedisList::ListPack(pack) => pack.replace(index, &value),
+            RedisList::QuickList(values) => match values.get_mut(index) {
+                Some(element) => {
+                    *element = value;
+                    true
+                }
+                None => false,
+            },
+        };
+        self.fit(limits);
+        replaced
+    }
+
+    pub fn insert(&mut self, index: usize, value: RedisString, limits: &EncodingLimits) {
+        match self {
+            RedisList::ListPack(pack) => pack.insert(index, &value),
+            RedisList::QuickList(values) => values.insert(index, value),
+        }
+        self.fit(limits);
+    }
+
+    pub fn push_front(&mut self, value: RedisString, limits: &EncodingLimits) {
+        match self {
+            RedisList::ListPack(pack) => pack.push_front(&value),
+            RedisList::QuickList(values) => values.push_front(value),
+        }
+        self.fit(limits);
+    }
+
+    pub fn push_back(&mut self, value: RedisString, limits: &EncodingLimits) {
+        match self {
+            RedisList::ListPack(pack) => pack.push_back(&value),
+            RedisList::QuickList(values) => values.push_back(value),
+        }
+        self.fit(limits);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<RedisString> {
+        match self {
+            RedisList::ListPack(pack) => pack.remove(index),
+            RedisList::QuickList(values) => values.remove(index),
+        }
+    }
+
+    pub fn pop_front(&mut self) -> Option<RedisString> {
+        match self {
+            RedisList::ListPack(pack) => pack.pop_front(),
+            RedisList::QuickList(values) => values.pop_front(),
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<RedisString> {
+        match self {
+            RedisList::ListPack(pack) => pack.pop_back(),
+            RedisList::QuickList(values) => values.pop_back(),
+        }
+    }
+
+    /// Keep the elements for which `keep` returns `true`, in order
+    pub fn retain<F: FnMut(&[u8]) -> bool>(&mut self, mut keep: F) {
+        match self {
+            RedisList::ListPack(pack) => pack.retain(keep),
+            RedisList::QuickList(values) => values.retain(|value| keep(value)),
+        }
+    }
+
+    /// Keep the first `len` elements only
+    pub fn truncate(&mut self, len: usize) {
+        match self {
+            RedisList::ListPack(pack) => pack.truncate(len),
+            RedisList::QuickList(values) => values.truncate(len),
+        }
+    }
+
+    /// Remove the first `count` elements
+    pub fn drain_front(&mut self, count: usize) {
+        match self {
+            RedisList::ListPack(pack) => pack.drain_front(count),
+            RedisList::QuickList(values) => {
+                values.drain(..count.min(values.len()));
+            }
+        }
+    }
+
+    /// Copy of the elements, first to last
+    pub fn to_vec_deque(&self) -> VecDeque<RedisString> {
+        self.iter().map(|value| value.to_vec()).collect()
+    }
+
+    /// Unpack the list once it no longer fits in a listpack
+    fn fit(&mut self, limits: &EncodingLimits) {
+        if let RedisList::ListPack(pack) = self {
+            if !limits.list_fits(pack.len(), pack.byte_len()) {
+                *self = RedisList::QuickList(self.to_vec_deque());
+            }
+        }
+    }
+}
+
+/// Elements of a `RedisList`, first to last
+#[derive(Debug, Clone)]
+pub enum Iter<'a> {
+    ListPack(listpack::Iter<'a>),
+    QuickList(std::collections::vec_deque::Iter<'a, RedisString>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::ListPack(entries) => entries.next(),
+            Iter::QuickList(values) => values.next().map(|value| value.as_slice()),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::iter::FromIterator;
+
+use super::RedisString;
+
+/// Strings packed one after the other in a single buffer, each preceded by its length as a
+/// LEB128 varint, as in the listpacks of Redis. Small lists, sets and hashes are kept in one to
+/// spare an allocation and the bookkeeping of a collection per element, at the cost of walking
+/// the entries to find one.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ListPack {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl ListPack {
+    pub fn new() -> Self {
+        ListPack::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes taken by the entries and their lengths
+    pub fn byte_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            bytes: &self.bytes,
+            left: self.len,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.iter().nth(index)
+    }
+
+    pub fn position(&self, entry: &[u8]) -> Option<usize> {
+        self.iter().position(|e| e == entry)
+    }
+
+    pub fn push_back(&mut self, entry: &[u8]) {
+        encode(&mut self.bytes, entry);
+        self.len += 1;
+    }
+
+    pub fn push_front(&mut self, entry: &[u8]) {
+        self.insert(0, entry);
+    }
+
+    /// Insert `entry` at `index`, after every entry if `index` is past the last one
+    pub fn insert(&mut self, index: usize, entry: &[u8]) {
+        let offset = self.offset(index);
+        let mut encoded = Vec::with_capacity(entry.len() + 10);
+        encode(&mut encoded, entry);
+        self.bytes.splice(offset..offset, encoded);
+        self.len += 1;
+    }
+
+    /// Replace the entry at `index`, return `false` if there is none
+    pub fn replace(&mut self, index: usize, entry: &[u8]) -> bool {
+        if index >= self.len {
+            return false;
+        }
+
+        let start = self.offset(index);
+        let end = start + encoded_len(&self.bytes[start..]);
+        let mut encoded = Vec::with_capacity(entry.len() + 10);
+        encode(&mut encoded, entry);
+        self.bytes.splice(start..end, encoded);
+        true
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<RedisString> {
+        if index >= self.len {
+            return None;
+        }
+
+        let start = self.offset(index);
+        let (header, len) = decode_len(&self.bytes[start..]);
+        let end = start + header + len;
+        let entry = self.bytes[start + header..end].to_vec();
+        self.bytes.drain(start..end);
+        self.len -= 1;
+        Some(entry)
+    }
+
+    pub fn pop_front(&mut self) -> Option<RedisString> {
+        self.remove(0)
+    }
+
+    pub fn pop_back(&mut self) -> Option<RedisString> {
+        self.remove(self.len.checked_sub(1)?)
+    }
+
+    /// Keep the first `len` entries only
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            let offset = self.offset(len);
+            self.bytes.truncate(offset);
+            self.len = len;
+        }
+    }
+
+    /// Remove the first `count` entries
+    pub fn drain_front(&mut self, count: usize) {
+        let count = count.min(self.len);
+        let offset = self.offset(count);
+        self.bytes.drain(..offset);
+        self.len -= count;
+    }
+
+    /// Keep the entries for which `keep` returns `true`, in order
+    pub fn retain<F: FnMut(&[u8]) -> bool>(&mut self, mut keep: F) {
+        let mut kept = ListPack::new();
+        for entry in self.iter().filter(|entry| keep(entry)) {
+            kept.push_back(entry);
+        }
+        *self = kept;
+    }
+
+    /// Byte offset of the entry at `index`, the end of the buffer past the last one
+    fn offset(&self, index: usize) -> usize {
+        let mut offset = 0;
+        for _ in 0..index.min(self.len) {
+            offset += encoded_len(&self.bytes[offset..]);
+        }
+        offset
+    }
+}
+
+impl<T: AsRef<[u8]>> FromIterator<T> for ListPack {
+    fn from_iter<I: IntoIterator<Item = T>>(entries: I) -> Self {
+        let mut pack = ListPack::new();
+        for entry in entries {
+            pack.push_back(entry.as_ref());
+        }
+        pack
+    }
+}
+
+/// Entries of a `ListPack`, first to last
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    bytes: &'a [u8],
+    left: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+
+        let (header, len) = decode_len(self.bytes);
+        let (entry, rest) = self.bytes[header..].split_at(len);
+        self.bytes = rest;
+        self.left -= 1;
+        Some(entry)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// Bytes taken in a `ListPack` by an entry of `len` bytes, its length included
+pub fn entry_size(len: usize) -> usize {
+    let mut header = 1;
+    while len >> (7 * header) > 0 {
+        header += 1;
+    }
+    header + len
+}
+
+fn encode(bytes: &mut Vec<u8>, entry: &[u8]) {
+    let mut len = entry.len();
+    while len >= 0x80 {
+        bytes.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    bytes.push(len as u8);
+    bytes.extend_from_slice(entry);
+}
+
+/// Size of the varint at the start of `bytes` and the length it holds
+fn decode_len(bytes: &[u8]) -> (usize, usize) {
+    let mut len = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        len |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return (i + 1, len);
+        }
+    }
+    // entries are only ever written by `encode`
+    unreachable!("truncated listpack entry")
+}
+
+/// Size of the entry at the start of `bytes`, its length included
+fn encoded_len(bytes: &[u8]) -> usize {
+    let (header, len) = decode_len(bytes);
+    header + len
+}
@@ -3,11 +3,20 @@ use super::{Expiry, RedisType};
 pub struct RedisMeta {
     pub data_type: RedisType,
     pub expiry: Option<Expiry>,
+    /// Changed by every write to the key, see `Storage::version`
+    pub version: u64,
+    /// Bytes of the value when last measured, see `Storage::value_bytes`
+    pub bytes: u64,
 }
 
 impl RedisMeta {
     pub fn new(data_type: RedisType, expiry: Option<Expiry>) -> Self {
-        Self { data_type, expiry }
+        Self {
+            data_type,
+            expiry,
+            version: 0,
+            bytes: 0,
+        }
     }
 
     pub fn is_expired(&self) -> bool {
@@ -1,18 +1,58 @@
+pub mod diff;
+pub mod encoding;
+pub mod entry;
 pub mod expiry;
 pub mod hash;
+pub mod list;
+pub mod listpack;
 pub mod meta;
+pub mod set;
 
 // re-export so one can use with models::Expiry
 // rather than models::expiry::Expiry
+pub use diff::{diff, DatasetDiff, KeyDiff, ValueDiff};
+pub use encoding::{Encoding, EncodingLimits};
+pub use entry::{DatasetSnapshot, RedisValue, StorageEntry};
 pub use expiry::Expiry;
-pub use hash::RedisHashMap;
+pub use hash::{HashFields, RedisHashMap};
+pub use list::RedisList;
+pub use listpack::ListPack;
 pub use meta::RedisMeta;
+pub use set::RedisSet;
 
 pub type RedisString = Vec<u8>;
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum RedisType {
     String,
     List,
     Set,
     Hash,
 }
+
+impl RedisType {
+    pub const ALL: [RedisType; 4] = [
+        RedisType::String,
+        RedisType::List,
+        RedisType::Set,
+        RedisType::Hash,
+    ];
+
+    /// Type named `name` in any case, as given to SCAN TYPE
+    pub fn from_name(name: &[u8]) -> Option<RedisType> {
+        RedisType::ALL
+            .iter()
+            .find(|data_type| name.eq_ignore_ascii_case(data_type.name().as_bytes()))
+            .copied()
+    }
+
+    /// Name of the type as replied by TYPE
+    pub fn name(&self) -> &'static str {
+        match self {
+            RedisType::String => "string",
+            RedisType::List => "list",
+            RedisType::Set => "set",
+            RedisType::Hash => "hash",
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use super::encoding::canonical_int;
+use super::listpack::{self, ListPack};
+use super::{Encoding, EncodingLimits, RedisString};
+use crate::storage::scan::{scan, ScanMap, ScanMapIter, ScanOptions};
+
+/// Members of a set: sorted integers while they all are and stay within
+/// `set-max-intset-entries`, packed while they stay within the `set-max-listpack-*` limits
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RedisSet {
+    IntSet(Vec<i64>),
+    ListPack(ListPack),
+    HashTable(ScanMap<()>),
+}
+
+impl RedisSet {
+    pub fn new(members: HashSet<RedisString>, limits: &EncodingLimits) -> Self {
+        if members.len() <= limits.set_max_intset_entries {
+            let ints: Option<Vec<i64>> = members.iter().map(|m| canonical_int(m)).collect();
+            if let Some(mut ints) = ints {
+                ints.sort_unstable();
+                return RedisSet::IntSet(ints);
+            }
+        }
+
+        if fits_listpack(members.len(), members.iter(), limits) {
+            return RedisSet::ListPack(members.iter().collect());
+        }
+        RedisSet::HashTable(members.into_iter().map(|member| (member, ())).collect())
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        match self {
+            RedisSet::IntSet(_) => Encoding::IntSet,
+            RedisSet::ListPack(_) => Encoding::ListPack,
+            RedisSet::HashTable(_) => Encoding::HashTable,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            RedisSet::IntSet(ints) => ints.len(),
+            RedisSet::ListPack(pack) => pack.len(),
+            RedisSet::HashTable(members) => members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every member, the integers of an intset written back as strings
+    pub fn iter(&self) -> Iter<'_> {
+        match self {
+            RedisSet::IntSet(ints) => Iter::IntSet(ints.iter()),
+            RedisSet::ListPack(pack) => Iter::ListPack(pack.iter()),
+            RedisSet::HashTable(members) => Iter::HashTable(members.iter()),
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            RedisSet::IntSet(ints) => {
+                canonical_int(member).is_some_and(|int| ints.binary_search(&int).is_ok())
+            }
+            RedisSet::ListPack(pack) => pack.position(member).is_some(),
+            RedisSet::HashTable(members) => members.contains_key(member),
+        }
+    }
+
+    /// Add `member`, return whether it wasn't there yet. The set is converted first if the
+    /// member doesn't fit in its encoding, as SADD does.
+    pub fn insert(&mut self, member: RedisString, limits: &EncodingLimits) -> bool {
+        match self {
+            RedisSet::IntSet(ints) => match canonical_int(&member) {
+                Some(int) => {
+                    let index = match ints.binary_search(&int) {
+                        Ok(_) => return false,
+                        Err(index) => index,
+                    };
+                    ints.insert(index, int);
+                    if ints.len() > limits.set_max_intset_entries {
+                        *self = RedisSet::HashTable(self.to_scan_map());
+                    }
+                    return true;
+                }
+                None => {
+                    let len = ints.len() + 1;
+                    let members = self.iter().chain(Some(Cow::Borrowed(&member[..])));
+                    *self = match fits_listpack(len, members, limits) {
+                        true => RedisSet::ListPack(self.iter().collect()),
+                        false => RedisSet::HashTable(self.to_scan_map()),
+                    };
+                }
+            },
+            RedisSet::ListPack(pack) => {
+                if pack.position(&member).is_some() {
+                    return false;
+                }
+                if pack.len() >= limits.set_max_listpack_entries
+                    || member.len() > limits.set_max_listpack_value
+                {
+                    *self = RedisSet::HashTable(self.to_scan_map());
+                }
+            }
+            RedisSet::HashTable(_) => {}
+        }
+
+        match self {
+            RedisSet::ListPack(pack) => {
+                pack.push_back(&member);
+                true
+            }
+            RedisSet::HashTable(members) => members.insert(member, ()).is_none(),
+            // sets only leave the intset encoding, never get back to it
+            RedisSet::IntSet(_) => false,
+        }
+    }
+
+    /// Remove `member`, return whether it was there
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            RedisSet::IntSet(ints) => {
+                let index = canonical_int(member).and_then(|int| ints.binary_search(&int).ok());
+                match index {
+                    Some(index) => {
+                        ints.remove(index);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            RedisSet::ListPack(pack) => match pack.position(member) {
+                Some(index) => pack.remove(index).is_some(),
+                None => false,
+            },
+            RedisSet::HashTable(members) => members.remove(member).is_some(),
+        }
+    }
+
+    /// Members from `cursor` on, see `scan::scan`
+    pub fn scan(&self, cursor: u64, options: &ScanOptions) -> (u64, Vec<RedisString>) {
+        match self {
+            RedisSet::HashTable(members) => {
+                let (cursor, page) = members.scan(cursor, options);
+                let page = page.into_iter().map(|(member, _)| member.clone()).collect();
+                (cursor, page)
+            }
+            // few enough members to look at them all
+            _ => {
+                let (cursor, page) = scan(self.iter(), |member| &member[..], cursor, options);
+                (cursor, page.into_iter().map(Cow::into_owned).collect())
+            }
+        }
+    }
+
+    /// Copy of the members
+    pub fn to_hash_set(&self) -> HashSet<RedisString> {
+        self.iter().map(Cow::into_owned).collect()
+    }
+
+    fn to_scan_map(&self) -> ScanMap<()> {
+        self.iter()
+            .map(|member| (member.into_owned(), ()))
+            .collect()
+    }
+}
+
+/// Whether `len` members fit in a listpack
+fn fits_listpack<I, T>(len: usize, mut members: I, limits: &EncodingLimits) -> bool
+where
+    I: Iterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    len <= limits.set_max_listpack_entries
+        && members.all(|member| member.as_ref().len() <= limits.set_max_listpack_value)
+}
+
+/// Members of a `RedisSet`, in no particular order
+#[derive(Debug, Clone)]
+pub enum Iter<'a> {
+    IntSet(std::slice::Iter<'a, i64>),
+    ListPack(listpack::Iter<'a>),
+    HashTable(ScanMapIter<'a, ()>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Cow<'a, [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::IntSet(ints) => ints
+                .next()
+                .map(|int| Cow::Owned(int.to_string().into_bytes())),
+            Iter::ListPack(entries) => entries.next().map(Cow::Borrowed),
+            Iter::HashTable(members) => {
+                members.next().map(|(member, _)| Cow::Borrowed(&member[..]))
+            }
+        }
+    }
+}
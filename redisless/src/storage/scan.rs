@@ -0,0 +1,287 @@
+use std::collections::btree_map::{self, BTreeMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::iter::{Flatten, FromIterator};
+
+use super::models::{RedisString, RedisType};
+
+/// Elements looked at per call when the client doesn't send COUNT
+pub const DEFAULT_SCAN_COUNT: usize = 10;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScanOptions {
+    /// Only return elements matching this glob-style pattern
+    pub pattern: Option<RedisString>,
+    /// Amount of work done by one call, not the number of elements returned
+    pub count: usize,
+    /// Only return keys holding this type, for SCAN
+    pub data_type: Option<RedisType>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            pattern: None,
+            count: DEFAULT_SCAN_COUNT,
+            data_type: None,
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn matches(&self, element: &[u8]) -> bool {
+        match &self.pattern {
+            Some(pattern) => glob_match(pattern, element),
+            None => true,
+        }
+    }
+
+    pub fn matches_type(&self, data_type: RedisType) -> bool {
+        self.data_type.is_none_or(|wanted| wanted == data_type)
+    }
+}
+
+/// Walk `elements` in the order of their cursor position, starting at `cursor`.
+///
+/// Each element sits at a fixed position derived from its content and the cursor is the
+/// position to resume from, not an index in the collection. Elements present during the whole
+/// iteration are thus returned exactly once, whatever is added, removed or rehashed in between
+/// calls, while elements added or removed meanwhile may or may not be returned.
+/// Returns the cursor to continue from (0 once done) and the elements matching `options`.
+///
+/// Every element is looked at, this is for the small collections packed in a listpack or an
+/// intset. Those kept in a `ScanMap` are walked from the cursor on with `ScanMap::scan`, on the
+/// same positions so that a collection changing encoding keeps its cursor valid.
+pub fn scan<T, I, F>(elements: I, element: F, cursor: u64, options: &ScanOptions) -> (u64, Vec<T>)
+where
+    I: Iterator<Item = T>,
+    F: Fn(&T) -> &[u8],
+{
+    let mut candidates: Vec<(u64, T)> = elements
+        .map(|value| (position(element(&value)), value))
+        .filter(|(position, _)| *position >= cursor)
+        .collect();
+
+    let count = options.count.max(1);
+    let next_cursor = if candidates.len() > count {
+        // only the first `count` positions are needed, no need to sort everything
+        let (_, last, _) =
+            candidates.select_nth_unstable_by_key(count - 1, |(position, _)| *position);
+        let last = last.0;
+        // never split elements sharing a position across two calls
+        candidates.retain(|(position, _)| *position <= last);
+        last.checked_add(1).unwrap_or(0)
+    } else {
+        0
+    };
+    candidates.sort_unstable_by_key(|(position, _)| *position);
+
+    let page = candidates
+        .into_iter()
+        .filter(|(_, value)| options.matches(element(value)))
+        .map(|(_, value)| value)
+        .collect();
+
+    (next_cursor, page)
+}
+
+/// Position of `element` in the order of a scan, also giving the stripe of a key, see
+/// `StripedStorage`
+pub(crate) fn position(element: &[u8]) -> u64 {
+    // `DefaultHasher::new` always uses the same keys, positions are stable between calls
+    let mut hasher = DefaultHasher::new();
+    element.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Map kept in the order of the cursor position of its keys, see `scan`, so that a scan
+/// resumes from a cursor without looking at what comes before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanMap<V> {
+    // entries sharing a position, in byte order of their key
+    buckets: BTreeMap<u64, Vec<(RedisString, V)>>,
+    len: usize,
+}
+
+impl<V> Default for ScanMap<V> {
+    fn default() -> Self {
+        ScanMap {
+            buckets: BTreeMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<V> ScanMap<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let bucket = self.buckets.get(&position(key))?;
+        let index = bucket.binary_search_by(|(k, _)| k[..].cmp(key)).ok()?;
+        Some(&bucket[index].1)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Set `key` to `value`, return the value it replaces
+    pub fn insert(&mut self, key: RedisString, value: V) -> Option<V> {
+        let bucket = self.buckets.entry(position(&key)).or_default();
+        match bucket.binary_search_by(|(k, _)| k.cmp(&key)) {
+            Ok(index) => Some(std::mem::replace(&mut bucket[index].1, value)),
+            Err(index) => {
+                bucket.insert(index, (key, value));
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let position = position(key);
+        let bucket = self.buckets.get_mut(&position)?;
+        let index = bucket.binary_search_by(|(k, _)| k[..].cmp(key)).ok()?;
+        let (_, value) = bucket.remove(index);
+        if bucket.is_empty() {
+            self.buckets.remove(&position);
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+        self.len = 0;
+    }
+
+    /// Every entry, in the order of their position
+    pub fn iter(&self) -> ScanMapIter<'_, V> {
+        self.buckets.values().flatten()
+    }
+
+    /// Same as `scan` from `cursor` on, only looking at the `options.count` entries returned
+    /// and not at those before or after them
+    pub fn scan(&self, cursor: u64, options: &ScanOptions) -> (u64, Vec<&(RedisString, V)>) {
+        let count = options.count.max(1);
+        let mut looked_at = 0;
+        let mut page = Vec::new();
+        let mut buckets = self.buckets.range(cursor..);
+        for (position, bucket) in buckets.by_ref() {
+            looked_at += bucket.len();
+            page.extend(bucket.iter().filter(|(key, _)| options.matches(key)));
+            if looked_at >= count {
+                let next_cursor = match buckets.next() {
+                    Some(_) => position + 1,
+                    None => 0,
+                };
+                return (next_cursor, page);
+            }
+        }
+        (0, page)
+    }
+}
+
+impl<V> FromIterator<(RedisString, V)> for ScanMap<V> {
+    fn from_iter<I: IntoIterator<Item = (RedisString, V)>>(entries: I) -> Self {
+        let mut map = ScanMap::new();
+        for (key, value) in entries {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+/// Entries of a `ScanMap`, in the order of their position
+pub type ScanMapIter<'a, V> = Flatten<btree_map::Values<'a, u64, Vec<(RedisString, V)>>>;
+
+/// Glob-style matching as done by Redis for KEYS, SCAN and friends.
+///
+/// Supports `*`, `?`, `[abc]`, `[^abc]`, `[a-z]` and `\` to escape the next character.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // where to resume from if what follows the last `*` doesn't match
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        let matched = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, s));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p, string[s]),
+            Some(b'\\') if p + 1 < pattern.len() => {
+                if pattern[p + 1] == string[s] {
+                    Some(p + 2)
+                } else {
+                    None
+                }
+            }
+            Some(c) if *c == string[s] => Some(p + 1),
+            _ => None,
+        };
+
+        match (matched, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                s += 1;
+            }
+            (None, Some((star, star_s))) => {
+                // let the `*` eat one more character
+                p = star + 1;
+                s = star_s + 1;
+                backtrack = Some((star, star_s + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p.min(pattern.len())..].iter().all(|c| *c == b'*')
+}
+
+/// Match `c` against the `[...]` class opening at `pattern[start]`, return the index after it
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<usize> {
+    let mut p = start + 1;
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            matched |= pattern[p + 1] == c;
+            p += 2;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (low, high) = if pattern[p] <= pattern[p + 2] {
+                (pattern[p], pattern[p + 2])
+            } else {
+                (pattern[p + 2], pattern[p])
+            };
+            matched |= low <= c && c <= high;
+            p += 3;
+        } else {
+            matched |= pattern[p] == c;
+            p += 1;
+        }
+    }
+
+    if matched != negate {
+        // an unterminated class runs until the end of the pattern, like in Redis
+        Some((p + 1).min(pattern.len()))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,565 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+use super::models::{
+    Encoding, EncodingLimits, Expiry, RedisHashMap, RedisList, RedisMeta, RedisSet, RedisString,
+    RedisType, StorageEntry,
+};
+use super::scan::{self, ScanOptions};
+use super::Storage;
+
+/// Stripes of the storage of a server, see `ServerConfig::storage_stripes`
+pub const DEFAULT_STRIPES: usize = 16;
+
+const NOT_LOCKED: &str = "key of a stripe that isn't locked, missing from `Command::keys`";
+
+/// Storage split into stripes, each holding the keys of a range of SCAN positions behind a lock
+/// of its own, so that a command only waits for those on keys of the same stripes: a long
+/// LRANGE or SADD holds up the commands on its stripe, not a GET of a key of another one.
+///
+/// A command locks the stripes of the keys it names in the order of their index, two commands
+/// naming keys of several stripes thus never wait for each other. Those walking or counting the
+/// whole keyspace, such as SCAN, DBSIZE or FLUSHALL, lock every stripe. Stripes are locked with
+/// a `Mutex` rather than a `RwLock`, reading a key removes it when it's expired.
+pub struct StripedStorage<T> {
+    stripes: Vec<Mutex<T>>,
+    // high bits of the position of a key giving its stripe, see `scan::position`
+    bits: u32,
+}
+
+impl<T: Storage> StripedStorage<T> {
+    /// Split `storage` into `stripes`, rounded up to a power of two, moving its keys to the
+    /// stripe they belong to. A storage without `Storage::new_stripe` stays whole, behind a
+    /// single lock.
+    pub fn new(mut storage: T, stripes: usize) -> Self {
+        let bits = match storage.new_stripe() {
+            Some(_) => stripes.max(1).next_power_of_two().trailing_zeros(),
+            None => 0,
+        };
+        let mut others: Vec<T> = (1..1usize << bits)
+            .filter_map(|_| storage.new_stripe())
+            .collect();
+
+        // expired keys would be left behind in the first stripe
+        storage.remove_expired(usize::MAX);
+        let moved: Vec<RedisString> = storage
+            .iter_keys()
+            .filter(|key| stripe_of(bits, key) != 0)
+            .map(|key| key.to_vec())
+            .collect();
+        for key in moved {
+            move_key(
+                &mut storage,
+                &mut others[stripe_of(bits, &key) - 1],
+                &key,
+                &key,
+            );
+        }
+
+        let stripes = std::iter::once(storage)
+            .chain(others)
+            .map(Mutex::new)
+            .collect();
+        StripedStorage { stripes, bits }
+    }
+
+    /// The stripes holding `keys`, to lock for a command naming them
+    pub fn stripes_of<K: AsRef<[u8]>>(&self, keys: &[K]) -> Stripes<'_, T> {
+        let mut indexes: Vec<usize> = keys
+            .iter()
+            .map(|key| stripe_of(self.bits, key.as_ref()))
+            .collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+        Stripes {
+            storage: self,
+            indexes,
+        }
+    }
+
+    /// Every stripe, to lock for a command walking or counting the whole keyspace
+    pub fn all_stripes(&self) -> Stripes<'_, T> {
+        Stripes {
+            storage: self,
+            indexes: (0..self.stripes.len()).collect(),
+        }
+    }
+
+    pub fn lock_all(&self) -> StripedGuard<'_, T> {
+        self.all_stripes().lock()
+    }
+}
+
+/// Stripes to lock for a command, see `StripedStorage::stripes_of`
+pub struct Stripes<'a, T> {
+    storage: &'a StripedStorage<T>,
+    // in ascending order, without duplicates
+    indexes: Vec<usize>,
+}
+
+impl<'a, T> Stripes<'a, T> {
+    /// Lock the stripes, in the order of their index
+    pub fn lock(&self) -> StripedGuard<'a, T> {
+        let mut guards: Vec<Option<MutexGuard<'a, T>>> =
+            self.storage.stripes.iter().map(|_| None).collect();
+        for &index in &self.indexes {
+            // a poisoned lock never recovers by waiting, and a command that panicked while
+            // holding it must not make the stripe unusable for every other client
+            let guard = self.storage.stripes[index]
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            guards[index] = Some(guard);
+        }
+        StripedGuard {
+            guards,
+            bits: self.storage.bits,
+        }
+    }
+
+    /// Whether none of the stripes is locked right now, without locking them
+    pub fn are_free(&self) -> bool {
+        self.indexes.iter().all(|&index| {
+            !matches!(
+                self.storage.stripes[index].try_lock(),
+                Err(TryLockError::WouldBlock)
+            )
+        })
+    }
+}
+
+/// Stripes locked by `Stripes::lock`, each key reached in its own stripe. Reaching a key of a
+/// stripe that isn't locked panics. What walks or counts the keys only sees those of the stripes
+/// locked.
+pub struct StripedGuard<'a, T> {
+    // by stripe index, `None` for those not locked
+    guards: Vec<Option<MutexGuard<'a, T>>>,
+    bits: u32,
+}
+
+impl<T> StripedGuard<'_, T> {
+    fn stripe(&self, key: &[u8]) -> &T {
+        self.guards[stripe_of(self.bits, key)]
+            .as_deref()
+            .expect(NOT_LOCKED)
+    }
+
+    fn stripe_mut(&mut self, key: &[u8]) -> &mut T {
+        self.guards[stripe_of(self.bits, key)]
+            .as_deref_mut()
+            .expect(NOT_LOCKED)
+    }
+}
+
+impl<T: Storage> Storage for StripedGuard<'_, T> {
+    fn write(&mut self, key: &[u8], value: &[u8]) {
+        self.stripe_mut(key).write(key, value)
+    }
+
+    fn write_keeping_expiry(&mut self, key: &[u8], value: &[u8]) {
+        self.stripe_mut(key).write_keeping_expiry(key, value)
+    }
+
+    fn mwrite(&mut self, items: &[(&[u8], &[u8])]) {
+        for item in items {
+            self.stripe_mut(item.0).mwrite(std::slice::from_ref(item));
+        }
+    }
+
+    fn extend(&mut self, key: &[u8], value: &[u8]) -> u64 {
+        self.stripe_mut(key).extend(key, value)
+    }
+
+    fn expire(&mut self, key: &[u8], expiry: Expiry) -> u32 {
+        self.stripe_mut(key).expire(key, expiry)
+    }
+
+    fn read(&mut self, key: &[u8]) -> Option<&[u8]> {
+        self.stripe_mut(key).read(key)
+    }
+
+    fn mread(&mut self, keys: &[&[u8]]) -> Vec<Option<RedisString>> {
+        keys.iter()
+            .flat_map(|key| self.stripe_mut(key).mread(&[*key]))
+            .collect()
+    }
+
+    fn remove(&mut self, key: &[u8]) -> u32 {
+        self.stripe_mut(key).remove(key)
+    }
+
+    fn mremove(&mut self, keys: &[&[u8]]) -> u32 {
+        keys.iter()
+            .map(|key| self.stripe_mut(key).mremove(&[*key]))
+            .sum()
+    }
+
+    fn contains(&mut self, key: &[u8]) -> bool {
+        self.stripe_mut(key).contains(key)
+    }
+
+    fn type_of(&mut self, key: &[u8]) -> &[u8] {
+        self.stripe_mut(key).type_of(key)
+    }
+
+    fn lwrite(&mut self, key: &[u8], values: VecDeque<RedisString>) {
+        self.stripe_mut(key).lwrite(key, values)
+    }
+
+    fn lread(&mut self, key: &[u8]) -> Option<&RedisList> {
+        self.stripe_mut(key).lread(key)
+    }
+
+    fn lread_mut(&mut self, key: &[u8]) -> Option<&mut RedisList> {
+        self.stripe_mut(key).lread_mut(key)
+    }
+
+    fn lpush_front(&mut self, key: &[u8], values: Vec<RedisString>) -> usize {
+        self.stripe_mut(key).lpush_front(key, values)
+    }
+
+    fn lpush_back(&mut self, key: &[u8], values: Vec<RedisString>) -> usize {
+        self.stripe_mut(key).lpush_back(key, values)
+    }
+
+    fn lpop_front(&mut self, key: &[u8]) -> Option<RedisString> {
+        self.stripe_mut(key).lpop_front(key)
+    }
+
+    fn lpop_back(&mut self, key: &[u8]) -> Option<RedisString> {
+        self.stripe_mut(key).lpop_back(key)
+    }
+
+    fn lrange(&mut self, key: &[u8], start: i64, stop: i64) -> Vec<RedisString> {
+        self.stripe_mut(key).lrange(key, start, stop)
+    }
+
+    fn swrite(&mut self, key: &[u8], values: HashSet<RedisString>) {
+        self.stripe_mut(key).swrite(key, values)
+    }
+
+    fn sread(&mut self, key: &[u8]) -> Option<&RedisSet> {
+        self.stripe_mut(key).sread(key)
+    }
+
+    fn sread_mut(&mut self, key: &[u8]) -> Option<&mut RedisSet> {
+        self.stripe_mut(key).sread_mut(key)
+    }
+
+    fn sscan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Option<(u64, Vec<RedisString>)> {
+        self.stripe_mut(key).sscan(key, cursor, options)
+    }
+
+    fn hwrite(&mut self, key: &[u8], value: HashMap<RedisString, RedisString>) {
+        self.stripe_mut(key).hwrite(key, value)
+    }
+
+    fn hread(&mut self, key: &[u8], field_key: &[u8]) -> Option<&[u8]> {
+        self.stripe_mut(key).hread(key, field_key)
+    }
+
+    fn hread_all(&mut self, key: &[u8]) -> Option<&RedisHashMap> {
+        self.stripe_mut(key).hread_all(key)
+    }
+
+    fn hread_mut(&mut self, key: &[u8]) -> Option<&mut RedisHashMap> {
+        self.stripe_mut(key).hread_mut(key)
+    }
+
+    fn hscan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Option<(u64, Vec<(RedisString, RedisString)>)> {
+        self.stripe_mut(key).hscan(key, cursor, options)
+    }
+
+    fn size(&self) -> u64 {
+        self.guards
+            .iter()
+            .flatten()
+            .map(|stripe| stripe.size())
+            .sum()
+    }
+
+    fn count_of(&self, data_type: RedisType) -> u64 {
+        self.guards
+            .iter()
+            .flatten()
+            .map(|stripe| stripe.count_of(data_type))
+            .sum()
+    }
+
+    fn value_bytes(&mut self) -> u64 {
+        self.guards
+            .iter_mut()
+            .flatten()
+            .map(|stripe| stripe.value_bytes())
+            .sum()
+    }
+
+    fn iter_keys(&self) -> Box<dyn Iterator<Item = &[u8]> + '_> {
+        Box::new(MergedKeys::new(
+            self.guards
+                .iter()
+                .flatten()
+                .map(|stripe| stripe.iter_keys())
+                .collect(),
+        ))
+    }
+
+    fn scan_keys(&self, cursor: u64, options: &ScanOptions) -> (u64, Vec<RedisString>) {
+        // stripes hold consecutive ranges of positions, a scan goes on with the next one once a
+        // stripe is done, for what's left of COUNT
+        let count = options.count.max(1);
+        let mut keys = Vec::new();
+        for index in stripe_at(self.bits, cursor)..self.guards.len() {
+            if keys.len() >= count {
+                let more = self.guards[index..]
+                    .iter()
+                    .flatten()
+                    .any(|stripe| stripe.size() > 0);
+                let cursor = if more {
+                    first_position(self.bits, index)
+                } else {
+                    0
+                };
+                return (cursor, keys);
+            }
+            let stripe = match self.guards[index].as_deref() {
+                Some(stripe) => stripe,
+                None => continue,
+            };
+
+            let options = ScanOptions {
+                count: count - keys.len(),
+                ..options.clone()
+            };
+            let (next, mut page) =
+                stripe.scan_keys(cursor.max(first_position(self.bits, index)), &options);
+            keys.append(&mut page);
+            if next != 0 {
+                return (next, keys);
+            }
+        }
+        (0, keys)
+    }
+
+    fn flush(&mut self) {
+        self.guards
+            .iter_mut()
+            .flatten()
+            .for_each(|stripe| stripe.flush())
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.guards
+            .iter_mut()
+            .flatten()
+            .for_each(|stripe| stripe.shrink_to_fit())
+    }
+
+    fn next_expiry(&mut self) -> Option<Expiry> {
+        self.guards
+            .iter_mut()
+            .flatten()
+            .filter_map(|stripe| stripe.next_expiry())
+            .min_by_key(|expiry| expiry.timestamp)
+    }
+
+    fn remove_expired(&mut self, max: usize) -> usize {
+        // soonest first across the stripes, one key at a time
+        let mut removed = 0;
+        while removed < max {
+            let soonest = self
+                .guards
+                .iter_mut()
+                .flatten()
+                .filter_map(|stripe| Some((stripe.next_expiry()?.timestamp, stripe)))
+                .min_by_key(|(timestamp, _)| *timestamp);
+            match soonest {
+                Some((_, stripe)) => match stripe.remove_expired(1) {
+                    0 => break,
+                    _ => removed += 1,
+                },
+                None => break,
+            }
+        }
+        removed
+    }
+
+    fn due_expirations(&mut self, max: usize) -> Vec<RedisString> {
+        let mut due = Vec::new();
+        for stripe in self.guards.iter_mut().flatten() {
+            for key in stripe.due_expirations(max) {
+                let expiry = stripe.meta(&key).and_then(|meta| meta.expiry);
+                due.push((expiry.map(|expiry| expiry.timestamp), key));
+            }
+        }
+        // soonest first across the stripes
+        due.sort_unstable();
+        due.into_iter().take(max).map(|(_, key)| key).collect()
+    }
+
+    fn dump_entry(&mut self, key: &[u8]) -> Option<StorageEntry> {
+        self.stripe_mut(key).dump_entry(key)
+    }
+
+    fn restore_entry(&mut self, entry: StorageEntry) {
+        let index = stripe_of(self.bits, &entry.key);
+        self.guards[index]
+            .as_deref_mut()
+            .expect(NOT_LOCKED)
+            .restore_entry(entry)
+    }
+
+    fn meta(&self, key: &[u8]) -> Option<&RedisMeta> {
+        self.stripe(key).meta(key)
+    }
+
+    fn encoding(&mut self, key: &[u8]) -> Option<Encoding> {
+        self.stripe_mut(key).encoding(key)
+    }
+
+    fn encoding_limits(&self) -> EncodingLimits {
+        // the same in every stripe
+        self.guards
+            .iter()
+            .flatten()
+            .next()
+            .map(|stripe| stripe.encoding_limits())
+            .unwrap_or_default()
+    }
+
+    fn set_encoding_limits(&mut self, limits: EncodingLimits) {
+        self.guards
+            .iter_mut()
+            .flatten()
+            .for_each(|stripe| stripe.set_encoding_limits(limits))
+    }
+
+    fn version(&self, key: &[u8]) -> u64 {
+        self.stripe(key).version(key)
+    }
+
+    fn update_in_place<R, F: FnOnce(&mut RedisString) -> R>(
+        &mut self,
+        key: &[u8],
+        update: F,
+    ) -> Option<R> {
+        self.stripe_mut(key).update_in_place(key, update)
+    }
+
+    fn rename(&mut self, key: &[u8], new_key: &[u8]) -> bool {
+        let (from, to) = (stripe_of(self.bits, key), stripe_of(self.bits, new_key));
+        if from == to {
+            return self.stripe_mut(key).rename(key, new_key);
+        }
+
+        // will never panic, `from` and `to` differ
+        let (low, high) = self.guards.split_at_mut(from.max(to));
+        let (from, to) = if from < to {
+            (&mut low[from], &mut high[0])
+        } else {
+            (&mut high[0], &mut low[to])
+        };
+        move_key(
+            from.as_deref_mut().expect(NOT_LOCKED),
+            to.as_deref_mut().expect(NOT_LOCKED),
+            key,
+            new_key,
+        )
+    }
+
+    fn bulk_load<I: IntoIterator<Item = (RedisString, RedisString)>>(&mut self, items: I) -> u64 {
+        let mut by_stripe: Vec<Vec<_>> = self.guards.iter().map(|_| Vec::new()).collect();
+        for (key, value) in items {
+            by_stripe[stripe_of(self.bits, &key)].push((key, value));
+        }
+        by_stripe
+            .into_iter()
+            .enumerate()
+            .filter(|(_, items)| !items.is_empty())
+            .map(|(index, items)| {
+                self.guards[index]
+                    .as_deref_mut()
+                    .expect(NOT_LOCKED)
+                    .bulk_load(items)
+            })
+            .sum()
+    }
+}
+
+/// Keys of several iterators, the smallest of their next ones first: in byte order when each
+/// iterator is
+struct MergedKeys<'a> {
+    iters: Vec<Box<dyn Iterator<Item = &'a [u8]> + 'a>>,
+    next: BinaryHeap<Reverse<(&'a [u8], usize)>>,
+}
+
+impl<'a> MergedKeys<'a> {
+    fn new(mut iters: Vec<Box<dyn Iterator<Item = &'a [u8]> + 'a>>) -> Self {
+        let next = iters
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, iter)| Some(Reverse((iter.next()?, index))))
+            .collect();
+        MergedKeys { iters, next }
+    }
+}
+
+impl<'a> Iterator for MergedKeys<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let Reverse((key, index)) = self.next.pop()?;
+        if let Some(next) = self.iters[index].next() {
+            self.next.push(Reverse((next, index)));
+        }
+        Some(key)
+    }
+}
+
+/// Move `key` of `from` to `new_key` of `to`, as `Storage::rename` does within a storage
+fn move_key<T: Storage>(from: &mut T, to: &mut T, key: &[u8], new_key: &[u8]) -> bool {
+    // entries have no room for the expiries of the fields of a hash, they are moved aside
+    let field_expiries = from
+        .hread_mut(key)
+        .map(|hash| std::mem::take(&mut hash.expiries));
+    let entry = match from.dump_entry(key) {
+        Some(entry) => entry,
+        None => return false,
+    };
+
+    from.remove(key);
+    to.restore_entry(StorageEntry {
+        key: new_key.to_vec(),
+        ..entry
+    });
+    if let (Some(expiries), Some(hash)) = (field_expiries, to.hread_mut(new_key)) {
+        hash.expiries = expiries;
+    }
+    true
+}
+
+/// Stripe of `key` among `1 << bits`
+fn stripe_of(bits: u32, key: &[u8]) -> usize {
+    stripe_at(bits, scan::position(key))
+}
+
+/// Stripe holding the keys at `position`
+fn stripe_at(bits: u32, position: u64) -> usize {
+    // shifting a u64 by 64 overflows, every key is in the only stripe then
+    position.checked_shr(64 - bits).unwrap_or(0) as usize
+}
+
+/// Position of the first keys of the stripe at `index`
+fn first_position(bits: u32, index: usize) -> u64 {
+    (index as u64).checked_shl(64 - bits).unwrap_or(0)
+}
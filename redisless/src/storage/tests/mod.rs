@@ -1,7 +1,16 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::{thread::sleep, time::Duration};
 
+use crate::storage::scan::{glob_match, ScanOptions};
+use crate::storage::striped::StripedStorage;
 use crate::storage::Storage;
-use crate::storage::{in_memory::InMemoryStorage, models::Expiry};
+use crate::storage::{
+    in_memory::InMemoryStorage,
+    models::{
+        diff, DatasetSnapshot, Encoding, EncodingLimits, Expiry, KeyDiff, ListPack, RedisList,
+        RedisType, RedisValue, ValueDiff,
+    },
+};
 
 #[test]
 fn test_in_memory_storage() {
@@ -13,6 +22,73 @@ fn test_in_memory_storage() {
     assert_eq!(mem.read(b"does not exist"), None);
 }
 
+#[test]
+fn shrink_to_fit_keeps_keys() {
+    let mut mem = InMemoryStorage::new();
+    for i in 0..10_000 {
+        let key = format!("key{}", i).into_bytes();
+        mem.write(&key, b"xxx");
+        mem.expire(&key, Expiry::new_from_secs(60).unwrap());
+    }
+    for i in 1..10_000 {
+        mem.remove(format!("key{}", i).as_bytes());
+    }
+    mem.lpush_back(b"list", vec![b"a".to_vec()]);
+
+    mem.shrink_to_fit();
+    assert_eq!(mem.size(), 2);
+    assert_eq!(mem.read(b"key0"), Some(&b"xxx"[..]));
+    assert!(mem.next_expiry().is_some());
+    assert_eq!(mem.lrange(b"list", 0, -1), vec![b"a".to_vec()]);
+
+    mem.flush();
+    mem.shrink_to_fit();
+    assert_eq!(mem.size(), 0);
+}
+
+#[test]
+fn versions_change_on_every_write() {
+    let mut mem = InMemoryStorage::new();
+    assert_eq!(mem.version(b"key"), 0);
+
+    mem.write(b"key", b"a");
+    let written = mem.version(b"key");
+    assert_ne!(written, 0);
+    mem.read(b"key");
+    assert_eq!(mem.version(b"key"), written);
+    mem.extend(b"key", b"b");
+    let extended = mem.version(b"key");
+    assert_ne!(extended, written);
+    mem.expire(b"key", Expiry::new_from_secs(60).unwrap());
+    assert_ne!(mem.version(b"key"), extended);
+
+    mem.lpush_back(b"list", vec![b"a".to_vec()]);
+    let pushed = mem.version(b"list");
+    mem.lpush_front(b"list", vec![b"b".to_vec()]);
+    assert_ne!(mem.version(b"list"), pushed);
+
+    // a key written again after being removed doesn't get an old version back
+    mem.remove(b"key");
+    assert_eq!(mem.version(b"key"), 0);
+    mem.write(b"key", b"a");
+    assert_ne!(mem.version(b"key"), written);
+}
+
+#[test]
+fn remove_matching() {
+    let mut mem = InMemoryStorage::new();
+    mem.write(b"user:1", b"a");
+    mem.write(b"user:2", b"b");
+    mem.lpush_back(b"user:list", vec![b"c".to_vec()]);
+    mem.write(b"session:1", b"d");
+
+    assert_eq!(mem.remove_matching(b"user:?"), 2);
+    assert_eq!(mem.remove_matching(b"user:?"), 0);
+    assert_eq!(mem.size(), 2);
+    assert_eq!(mem.remove_matching(b"*"), 2);
+    assert_eq!(mem.size(), 0);
+}
+
 #[test]
 fn test_dbsize() {
     let mut mem = InMemoryStorage::new();
@@ -50,6 +126,17 @@ fn test_expire() {
         sleep(Duration::from_millis(duration));
         assert_eq!(mem.read(b"key"), None);
     }
+
+    // an expired key isn't brought back, even before it's removed
+    mem.write(b"key", b"xxx");
+    mem.expire(b"key", Expiry::new_from_millis(1).unwrap());
+    sleep(Duration::from_millis(10));
+    assert_eq!(mem.expire(b"key", Expiry::new_from_secs(60).unwrap()), 0);
+    assert_eq!(mem.read(b"key"), None);
+    assert_eq!(
+        mem.expire(b"missing", Expiry::new_from_secs(60).unwrap()),
+        0
+    );
 }
 
 #[test]
@@ -75,3 +162,1037 @@ fn extend() {
     assert_eq!(len, 8);
     assert_eq!(x, b"value222");
 }
+
+#[test]
+fn update_in_place() {
+    let mut mem = InMemoryStorage::new();
+    assert_eq!(mem.update_in_place(b"key", |_| unreachable!()), None::<()>);
+
+    mem.write(b"key", b"val");
+    let expiry = Expiry::new_from_secs(60).unwrap();
+    mem.expire(b"key", expiry);
+    let written = mem.version(b"key");
+    let len = mem.update_in_place(b"key", |value| {
+        value.extend_from_slice(b"ue");
+        value.len()
+    });
+    assert_eq!(len, Some(5));
+    assert_eq!(mem.read(b"key").unwrap(), b"value");
+    assert_eq!(mem.meta(b"key").unwrap().expiry, Some(expiry));
+    assert_ne!(mem.version(b"key"), written);
+
+    // only strings are updated
+    mem.lpush_back(b"list", vec![b"a".to_vec()]);
+    assert_eq!(mem.update_in_place(b"list", |_| unreachable!()), None::<()>);
+
+    // nor are expired ones
+    mem.write(b"expired", b"a");
+    mem.expire(b"expired", Expiry::new_from_millis(1).unwrap());
+    sleep(Duration::from_millis(5));
+    assert_eq!(
+        mem.update_in_place(b"expired", |_| unreachable!()),
+        None::<()>
+    );
+    assert!(!mem.contains(b"expired"));
+}
+
+#[test]
+fn multi_key_operations() {
+    let mut mem = InMemoryStorage::new();
+    mem.mwrite(&[(b"key1", b"value1"), (b"key2", b"value2")]);
+    assert_eq!(mem.size(), 2);
+
+    let values = mem.mread(&[b"key1", b"missing", b"key2"]);
+    assert_eq!(
+        values,
+        vec![Some(b"value1".to_vec()), None, Some(b"value2".to_vec())]
+    );
+
+    assert_eq!(mem.mremove(&[b"key1", b"missing", b"key2"]), 2);
+    assert_eq!(mem.size(), 0);
+}
+
+#[test]
+fn glob_patterns() {
+    assert!(glob_match(b"*", b""));
+    assert!(glob_match(b"h?llo", b"hello"));
+    assert!(glob_match(b"h*llo", b"heeeello"));
+    assert!(glob_match(b"h[ae]llo", b"hallo"));
+    assert!(!glob_match(b"h[ae]llo", b"hillo"));
+    assert!(glob_match(b"h[^e]llo", b"hallo"));
+    assert!(!glob_match(b"h[^e]llo", b"hello"));
+    assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+    assert!(glob_match(b"user:*:name", b"user:42:name"));
+    assert!(!glob_match(b"user:*:name", b"user:42:email"));
+    assert!(glob_match(b"h\\*llo", b"h*llo"));
+    assert!(!glob_match(b"h\\*llo", b"hello"));
+}
+
+#[test]
+fn scan_aggregates() {
+    let mut mem = InMemoryStorage::new();
+
+    let members: HashSet<Vec<u8>> = (0..100).map(|i| format!("m{}", i).into_bytes()).collect();
+    mem.swrite(b"set", members.clone());
+
+    let options = ScanOptions {
+        pattern: None,
+        count: 7,
+        data_type: None,
+    };
+    let mut scanned = HashSet::new();
+    let mut cursor = 0;
+    loop {
+        let (next, page) = mem.sscan(b"set", cursor, &options).unwrap();
+        scanned.extend(page);
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    assert_eq!(scanned, members);
+
+    let fields: HashMap<Vec<u8>, Vec<u8>> = (0..20)
+        .map(|i| (format!("f{}", i).into_bytes(), b"v".to_vec()))
+        .collect();
+    mem.hwrite(b"hash", fields);
+
+    let options = ScanOptions {
+        pattern: Some(b"f1*".to_vec()),
+        count: 100,
+        data_type: None,
+    };
+    let (cursor, page) = mem.hscan(b"hash", 0, &options).unwrap();
+    assert_eq!(cursor, 0);
+    assert_eq!(page.len(), 11);
+
+    assert!(mem.hscan(b"missing", 0, &options).is_none());
+
+    // members of a hash table are walked from the cursor on, while the set changes
+    let stable: HashSet<Vec<u8>> = (0..1000).map(|i| format!("m{}", i).into_bytes()).collect();
+    mem.swrite(b"large", stable.clone());
+    assert_eq!(mem.encoding(b"large"), Some(Encoding::HashTable));
+    let options = ScanOptions {
+        pattern: None,
+        count: 10,
+        data_type: None,
+    };
+    let limits = mem.encoding_limits();
+    let mut scanned = Vec::new();
+    let mut cursor = 0;
+    let mut round = 0;
+    loop {
+        let (next, page) = mem.sscan(b"large", cursor, &options).unwrap();
+        assert!(page.len() <= 10);
+        scanned.extend(page);
+
+        let set = mem.sread_mut(b"large").unwrap();
+        set.insert(format!("added{}", round).into_bytes(), &limits);
+        round += 1;
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    for member in &stable {
+        assert_eq!(scanned.iter().filter(|m| *m == member).count(), 1);
+    }
+}
+
+#[test]
+fn scan_keys_under_mutation() {
+    let mut mem = InMemoryStorage::new();
+
+    let stable: HashSet<Vec<u8>> = (0..500).map(|i| format!("k{}", i).into_bytes()).collect();
+    for key in &stable {
+        mem.write(key, b"v");
+    }
+    for i in 0..100 {
+        mem.write(format!("removed{}", i).as_bytes(), b"v");
+    }
+
+    let options = ScanOptions {
+        pattern: None,
+        count: 10,
+        data_type: None,
+    };
+    let mut scanned = Vec::new();
+    let mut cursor = 0;
+    let mut round = 0;
+    loop {
+        let (next, page) = mem.scan_keys(cursor, &options);
+        scanned.extend(page);
+
+        // keys come and go between calls, growing the map past a few reallocations
+        mem.remove(format!("removed{}", round).as_bytes());
+        for i in 0..20 {
+            mem.write(format!("added{}-{}", round, i).as_bytes(), b"v");
+        }
+        round += 1;
+
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+
+    // keys present during the whole iteration are returned exactly once
+    for key in &stable {
+        assert_eq!(scanned.iter().filter(|k| *k == key).count(), 1);
+    }
+    let unique: HashSet<&Vec<u8>> = scanned.iter().collect();
+    assert_eq!(unique.len(), scanned.len());
+
+    let options = ScanOptions {
+        pattern: Some(b"k1?".to_vec()),
+        count: 10_000,
+        data_type: None,
+    };
+    let (cursor, page) = mem.scan_keys(0, &options);
+    assert_eq!(cursor, 0);
+    assert_eq!(page.len(), 10);
+}
+
+#[test]
+fn scan_keys_of_a_type() {
+    let mut mem = InMemoryStorage::new();
+
+    for i in 0..50 {
+        mem.write(format!("string{}", i).as_bytes(), b"v");
+        let members: HashSet<Vec<u8>> = vec![b"m".to_vec()].into_iter().collect();
+        mem.swrite(format!("set{}", i).as_bytes(), members);
+    }
+
+    let options = ScanOptions {
+        pattern: None,
+        count: 10,
+        data_type: Some(RedisType::Set),
+    };
+    let mut scanned = HashSet::new();
+    let mut cursor = 0;
+    let mut calls = 0;
+    loop {
+        let (next, page) = mem.scan_keys(cursor, &options);
+        // COUNT bounds the keys looked at, not those of the type
+        assert!(page.len() <= 10);
+        scanned.extend(page);
+        calls += 1;
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    assert!(calls >= 10);
+    assert_eq!(scanned.len(), 50);
+    assert!(scanned.iter().all(|key| key.starts_with(b"set")));
+}
+
+#[test]
+fn next_expiry_and_remove_expired() {
+    let mut mem = InMemoryStorage::new();
+    assert_eq!(mem.next_expiry(), None);
+
+    for key in &[&b"a"[..], b"b", b"c", b"d"] {
+        mem.write(key, b"v");
+    }
+    let soon = Expiry::new_from_millis(10).unwrap();
+    let later = Expiry::new_from_secs(100).unwrap();
+    mem.expire(b"a", soon);
+    mem.expire(b"b", later);
+    assert_eq!(mem.next_expiry(), Some(soon));
+
+    // overwriting a key drops its expiry
+    mem.write(b"a", b"v");
+    assert_eq!(mem.next_expiry(), Some(later));
+    // so does removing it
+    mem.remove(b"b");
+    assert_eq!(mem.next_expiry(), None);
+
+    mem.expire(b"c", soon);
+    mem.expire(b"d", soon);
+    mem.expire(b"a", later);
+    assert_eq!(mem.remove_expired(10), 0);
+    sleep(Duration::from_millis(20));
+    assert_eq!(mem.remove_expired(1), 1);
+    assert_eq!(mem.remove_expired(10), 1);
+    assert_eq!(mem.size(), 1);
+    assert_eq!(mem.next_expiry(), Some(later));
+}
+
+#[test]
+fn size_and_flush_across_types() {
+    let mut mem = InMemoryStorage::new();
+    mem.write(b"string", b"v");
+    mem.lwrite(b"list", vec![b"v".to_vec()].into());
+    mem.swrite(b"set", vec![b"v".to_vec()].into_iter().collect());
+    mem.hwrite(
+        b"hash",
+        vec![(b"f".to_vec(), b"v".to_vec())].into_iter().collect(),
+    );
+    assert_eq!(mem.size(), 4);
+
+    // a key taking another type leaves nothing behind in the store of the former one
+    mem.write(b"list", b"v");
+    assert_eq!(mem.size(), 4);
+    assert_eq!(mem.lread(b"list"), None);
+    mem.lwrite(b"list", VecDeque::new());
+    assert_eq!(mem.lread(b"list"), Some(&RedisList::default()));
+
+    mem.expire(b"string", Expiry::new_from_secs(10).unwrap());
+    mem.flush();
+    assert_eq!(mem.size(), 0);
+    assert_eq!(mem.iter_keys().count(), 0);
+    assert_eq!(mem.next_expiry(), None);
+    for key in &[&b"string"[..], b"list", b"set", b"hash"] {
+        assert!(!mem.contains(key));
+    }
+}
+
+#[test]
+fn value_bytes_follow_writes_removals_and_expirations() {
+    let mut mem = InMemoryStorage::new();
+    mem.write(b"string", b"value");
+    mem.lwrite(b"list", vec![b"a".to_vec(), b"bc".to_vec()].into());
+    assert_eq!(mem.value_bytes(), 8);
+
+    // counted from then on, whatever changes the value
+    mem.write(b"string", b"longer value");
+    mem.lpush_back(b"list", vec![b"def".to_vec()]);
+    mem.hwrite(
+        b"hash",
+        vec![(b"f".to_vec(), b"v".to_vec())].into_iter().collect(),
+    );
+    assert_eq!(mem.value_bytes(), 20);
+    mem.lread_mut(b"list").unwrap().pop_front();
+    mem.write(b"hash", b"now a string");
+    assert_eq!(mem.value_bytes(), 29);
+
+    mem.remove(b"string");
+    assert_eq!(mem.value_bytes(), 17);
+    mem.expire(b"list", Expiry::new_from_millis(10).unwrap());
+    sleep(Duration::from_millis(20));
+    assert_eq!(mem.remove_expired(10), 1);
+    assert_eq!(mem.value_bytes(), 12);
+    mem.flush();
+    assert_eq!(mem.value_bytes(), 0);
+}
+
+#[test]
+fn value_bytes_match_a_recount() {
+    let recount = |mem: &mut InMemoryStorage| {
+        let mut fresh = InMemoryStorage::new();
+        fresh.import(mem.export());
+        fresh.value_bytes()
+    };
+    let mut mem = InMemoryStorage::new();
+    mem.write(b"string", b"a long enough value");
+    mem.lpush_back(b"list", vec![b"abc".to_vec(), b"de".to_vec()]);
+    mem.swrite(
+        b"set",
+        vec![b"xyz".to_vec(), b"w".to_vec()].into_iter().collect(),
+    );
+    mem.hwrite(
+        b"hash",
+        vec![(b"field".to_vec(), b"value".to_vec())]
+            .into_iter()
+            .collect(),
+    );
+    assert_eq!(mem.value_bytes(), recount(&mut mem));
+
+    // overwritten with something shorter, shrunk in place and by type
+    mem.write(b"string", b"short");
+    mem.update_in_place(b"string", |value| value.truncate(2));
+    mem.lread_mut(b"list").unwrap().pop_front();
+    mem.sread_mut(b"set").unwrap().remove(b"xyz".as_ref());
+    mem.hread_mut(b"hash").unwrap().remove(b"field");
+    mem.write(b"list", b"x");
+    assert_eq!(mem.value_bytes(), recount(&mut mem));
+
+    // and deleted
+    mem.remove(b"string");
+    mem.mremove(&[b"list", b"set"]);
+    assert_eq!(mem.value_bytes(), recount(&mut mem));
+    mem.remove(b"hash");
+    assert_eq!(mem.value_bytes(), 0);
+}
+
+#[test]
+fn list_in_place_operations() {
+    let mut mem = InMemoryStorage::new();
+    assert_eq!(
+        mem.lpush_back(b"list", vec![b"b".to_vec(), b"c".to_vec()]),
+        2
+    );
+    assert_eq!(
+        mem.lpush_front(b"list", vec![b"a".to_vec(), b"z".to_vec()]),
+        4
+    );
+    assert_eq!(
+        mem.lrange(b"list", 0, -1),
+        vec![b"z".to_vec(), b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]
+    );
+    assert_eq!(
+        mem.lrange(b"list", -2, 100),
+        vec![b"b".to_vec(), b"c".to_vec()]
+    );
+    assert!(mem.lrange(b"list", 3, 1).is_empty());
+    assert!(mem.lrange(b"missing", 0, -1).is_empty());
+
+    assert_eq!(mem.lpop_front(b"list"), Some(b"z".to_vec()));
+    assert_eq!(mem.lpop_back(b"list"), Some(b"c".to_vec()));
+    let limits = mem.encoding_limits();
+    mem.lread_mut(b"list")
+        .unwrap()
+        .set(0, b"x".to_vec(), &limits);
+    assert_eq!(
+        mem.lrange(b"list", 0, -1),
+        vec![b"x".to_vec(), b"b".to_vec()]
+    );
+
+    // popping the last element removes the key
+    assert_eq!(mem.lpop_back(b"list"), Some(b"b".to_vec()));
+    assert_eq!(mem.lpop_back(b"list"), Some(b"x".to_vec()));
+    assert_eq!(mem.lpop_back(b"list"), None);
+    assert!(!mem.contains(b"list"));
+}
+
+#[test]
+fn iter_keys_and_dump_entry() {
+    let mut mem = InMemoryStorage::new();
+    mem.write(b"string", b"value");
+    mem.lpush_back(b"list", vec![b"a".to_vec(), b"b".to_vec()]);
+    mem.swrite(b"set", vec![b"m".to_vec()].into_iter().collect());
+    mem.hwrite(
+        b"hash",
+        vec![(b"f".to_vec(), b"v".to_vec())].into_iter().collect(),
+    );
+    mem.write(b"expired", b"value");
+    mem.expire(b"expired", Expiry::new_from_millis(0).unwrap());
+
+    let mut keys: Vec<&[u8]> = mem.iter_keys().collect();
+    keys.sort();
+    assert_eq!(keys, vec![&b"hash"[..], b"list", b"set", b"string"]);
+
+    let entry = mem.dump_entry(b"list").unwrap();
+    assert_eq!(entry.key, b"list".to_vec());
+    assert_eq!(entry.value.type_name(), "list");
+    assert_eq!(
+        entry.value,
+        RedisValue::List(vec![b"a".to_vec(), b"b".to_vec()].into_iter().collect())
+    );
+    assert_eq!(entry.expiry, None);
+
+    let expiry = Expiry::new_from_secs(60).unwrap();
+    mem.expire(b"hash", expiry);
+    let entry = mem.dump_entry(b"hash").unwrap();
+    assert_eq!(entry.expiry, Some(expiry));
+
+    assert_eq!(mem.dump_entry(b"expired"), None);
+    assert_eq!(mem.dump_entry(b"missing"), None);
+}
+
+#[test]
+fn restore_entry() {
+    let mut mem = InMemoryStorage::new();
+    mem.lpush_back(b"list", vec![b"a".to_vec()]);
+    let expiry = Expiry::new_from_secs(60).unwrap();
+    mem.expire(b"list", expiry);
+    mem.hwrite(
+        b"hash",
+        vec![(b"f".to_vec(), b"v".to_vec())].into_iter().collect(),
+    );
+    let list = mem.dump_entry(b"list").unwrap();
+    let hash = mem.dump_entry(b"hash").unwrap();
+
+    let mut restored = InMemoryStorage::new();
+    // replaced whatever its type
+    restored.write(b"list", b"value");
+    restored.restore_entry(list.clone());
+    restored.restore_entry(hash.clone());
+    assert_eq!(restored.dump_entry(b"list"), Some(list));
+    assert_eq!(restored.dump_entry(b"hash"), Some(hash));
+    assert_eq!(restored.type_of(b"list"), b"list");
+    assert_eq!(restored.next_expiry(), Some(expiry));
+}
+
+#[test]
+fn export_and_import() {
+    let mut mem = InMemoryStorage::new();
+    mem.write(b"string", b"value");
+    mem.expire(b"string", Expiry::new_from_secs(60).unwrap());
+    mem.lpush_back(b"list", vec![b"a".to_vec(), b"b".to_vec()]);
+    mem.swrite(b"set", vec![b"m".to_vec()].into_iter().collect());
+    mem.write(b"expired", b"value");
+    mem.expire(b"expired", Expiry::new_from_millis(0).unwrap());
+    sleep(Duration::from_millis(5));
+
+    let mut snapshot = mem.export();
+    snapshot.entries.sort_by(|a, b| a.key.cmp(&b.key));
+    let keys: Vec<&[u8]> = snapshot.entries.iter().map(|e| e.key.as_slice()).collect();
+    assert_eq!(keys, vec![&b"list"[..], b"set", b"string"]);
+
+    let mut imported = InMemoryStorage::new();
+    imported.write(b"other", b"value");
+    imported.import(snapshot.clone());
+    assert!(!imported.contains(b"other"));
+    let mut reexported = imported.export();
+    reexported.entries.sort_by(|a, b| a.key.cmp(&b.key));
+    assert_eq!(reexported, snapshot);
+}
+
+#[test]
+fn replicated_expiry_keeps_expired_keys() {
+    let mut mem = InMemoryStorage::new_with_replicated_expiry();
+    for key in &[&b"a"[..], b"b", b"c"] {
+        mem.write(key, b"v");
+    }
+    mem.expire(b"a", Expiry::new_from_millis(10).unwrap());
+    mem.expire(b"b", Expiry::new_from_millis(10).unwrap());
+    mem.expire(b"c", Expiry::new_from_secs(100).unwrap());
+    assert!(mem.due_expirations(10).is_empty());
+    sleep(Duration::from_millis(20));
+
+    assert_eq!(mem.remove_expired(10), 0);
+    assert!(mem.contains(b"a"));
+    assert_eq!(mem.read(b"b"), Some(&b"v"[..]));
+    assert_eq!(mem.iter_keys().count(), 3);
+
+    let mut due = mem.due_expirations(10);
+    due.sort();
+    assert_eq!(due, vec![b"a".to_vec(), b"b".to_vec()]);
+    assert_eq!(mem.due_expirations(1).len(), 1);
+    // still due until removed
+    assert_eq!(mem.due_expirations(10).len(), 2);
+    mem.remove(b"a");
+    assert_eq!(mem.due_expirations(10), vec![b"b".to_vec()]);
+}
+
+#[test]
+fn keys_are_counted_by_type() {
+    let counts = |mem: &InMemoryStorage| -> Vec<u64> {
+        RedisType::ALL
+            .iter()
+            .map(|data_type| mem.count_of(*data_type))
+            .collect()
+    };
+
+    let mut mem = InMemoryStorage::new();
+    assert_eq!(counts(&mem), vec![0, 0, 0, 0]);
+
+    mem.write(b"a", b"value");
+    mem.write(b"b", b"value");
+    mem.lpush_back(b"list", vec![b"a".to_vec()]);
+    mem.swrite(b"set", vec![b"m".to_vec()].into_iter().collect());
+    mem.hwrite(
+        b"hash",
+        vec![(b"f".to_vec(), b"v".to_vec())].into_iter().collect(),
+    );
+    assert_eq!(counts(&mem), vec![2, 1, 1, 1]);
+    assert_eq!(counts(&mem).iter().sum::<u64>(), mem.size());
+
+    // a key changing type moves to the count of its new type
+    mem.lpush_back(b"a", vec![b"a".to_vec()]);
+    assert_eq!(counts(&mem), vec![1, 2, 1, 1]);
+    mem.remove(b"set");
+    assert_eq!(counts(&mem), vec![1, 2, 0, 1]);
+    assert_eq!(counts(&mem).iter().sum::<u64>(), mem.size());
+
+    mem.flush();
+    assert_eq!(counts(&mem), vec![0, 0, 0, 0]);
+}
+
+#[test]
+fn bulk_load_writes_strings() {
+    let mut mem = InMemoryStorage::new();
+    mem.lpush_back(b"key:1", vec![b"a".to_vec()]);
+
+    let items = (0..100).map(|n| {
+        (
+            format!("key:{}", n).into_bytes(),
+            n.to_string().into_bytes(),
+        )
+    });
+    assert_eq!(mem.bulk_load(items), 100);
+    assert_eq!(mem.size(), 100);
+    assert_eq!(mem.read(b"key:42"), Some(&b"42"[..]));
+    // as SET does, whatever the key held before
+    assert_eq!(mem.type_of(b"key:1"), b"string");
+    assert_eq!(mem.count_of(RedisType::List), 0);
+    assert_eq!(mem.bulk_load(Vec::new()), 0);
+}
+
+#[test]
+fn datasets_are_encoded_and_digested() {
+    let mut mem = InMemoryStorage::new();
+    assert_eq!(mem.export().digest(), 0);
+
+    mem.write(b"string", b"value");
+    mem.expire(b"string", Expiry::new_from_secs(60).unwrap());
+    mem.lpush_back(b"list", vec![b"a".to_vec(), b"b".to_vec()]);
+    mem.swrite(
+        b"set",
+        vec![b"m".to_vec(), b"n".to_vec()].into_iter().collect(),
+    );
+    mem.hwrite(
+        b"hash",
+        vec![
+            (b"f".to_vec(), b"v".to_vec()),
+            (b"g".to_vec(), b"w".to_vec()),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    let dataset = mem.export();
+    let decoded = DatasetSnapshot::decode(&dataset.encode()).unwrap();
+    assert_eq!(decoded, dataset);
+    assert_eq!(DatasetSnapshot::decode(b"*1\r\n$8\r\nsnapshot\r\n"), None);
+    let mut truncated = dataset.encode();
+    truncated.pop();
+    assert_eq!(DatasetSnapshot::decode(&truncated), None);
+
+    // whatever the order of the entries
+    let digest = dataset.digest();
+    assert_ne!(digest, 0);
+    let mut reversed = dataset.clone();
+    reversed.entries.reverse();
+    assert_eq!(reversed.digest(), digest);
+
+    // but not the order of lists
+    mem.lpush_back(b"list", vec![b"c".to_vec()]);
+    let pushed = mem.export().digest();
+    assert_ne!(pushed, digest);
+    mem.lwrite(
+        b"list",
+        vec![b"c".to_vec(), b"a".to_vec(), b"b".to_vec()]
+            .into_iter()
+            .collect(),
+    );
+    assert_ne!(mem.export().digest(), pushed);
+
+    // nor expiries
+    let mut expiring = dataset.clone();
+    for entry in &mut expiring.entries {
+        entry.expiry = None;
+    }
+    assert_ne!(expiring.digest(), digest);
+}
+
+#[test]
+fn deterministic_storage_iterates_keys_in_order() {
+    let keys: Vec<Vec<u8>> = (0..100)
+        .map(|n| format!("key:{}", n).into_bytes())
+        .collect();
+    let fill = |keys: &mut dyn Iterator<Item = &Vec<u8>>| {
+        let mut mem = InMemoryStorage::new_deterministic();
+        for key in keys {
+            mem.swrite(
+                key,
+                vec![b"m".to_vec(), b"n".to_vec(), key.clone()]
+                    .into_iter()
+                    .collect(),
+            );
+        }
+        mem
+    };
+    let mut forward = fill(&mut keys.iter());
+    let mut backward = fill(&mut keys.iter().rev());
+
+    let mut sorted = keys.clone();
+    sorted.sort();
+    let iterated: Vec<Vec<u8>> = forward.iter_keys().map(<[u8]>::to_vec).collect();
+    assert_eq!(iterated, sorted);
+    let exported: Vec<Vec<u8>> = backward
+        .export()
+        .entries
+        .into_iter()
+        .map(|entry| entry.key)
+        .collect();
+    assert_eq!(exported, sorted);
+
+    // members are encoded in order too, the snapshots are the same byte for byte
+    assert_eq!(forward.export().encode(), backward.export().encode());
+}
+
+#[test]
+fn snapshots_are_diffed_by_key() {
+    let mut mem = InMemoryStorage::new();
+    mem.write(b"string", b"value");
+    mem.write(b"removed", b"value");
+    mem.write(b"expiring", b"value");
+    mem.swrite(
+        b"set",
+        vec![b"m".to_vec(), b"n".to_vec()].into_iter().collect(),
+    );
+    mem.hwrite(
+        b"hash",
+        vec![
+            (b"f".to_vec(), b"v".to_vec()),
+            (b"g".to_vec(), b"w".to_vec()),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    mem.write(b"retyped", b"value");
+    let before = mem.export();
+    assert!(diff(&before, &before).is_empty());
+
+    mem.write(b"string", b"other");
+    mem.remove(b"removed");
+    mem.write(b"added", b"value");
+    let expiry = Expiry::new_from_secs(60).unwrap();
+    mem.expire(b"expiring", expiry);
+    mem.swrite(
+        b"set",
+        vec![b"n".to_vec(), b"o".to_vec()].into_iter().collect(),
+    );
+    mem.hwrite(
+        b"hash",
+        vec![
+            (b"g".to_vec(), b"x".to_vec()),
+            (b"h".to_vec(), b"y".to_vec()),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    mem.remove(b"retyped");
+    mem.lpush_back(b"retyped", vec![b"a".to_vec()]);
+    let after = mem.export();
+
+    let diff = diff(&before, &after);
+    let keys = |entries: &[crate::storage::models::StorageEntry]| -> Vec<Vec<u8>> {
+        entries.iter().map(|entry| entry.key.clone()).collect()
+    };
+    assert_eq!(keys(&diff.added), vec![b"added".to_vec()]);
+    assert_eq!(keys(&diff.removed), vec![b"removed".to_vec()]);
+    assert_eq!(
+        diff.changed,
+        vec![
+            KeyDiff {
+                key: b"expiring".to_vec(),
+                value: None,
+                expiry: Some((None, Some(expiry))),
+            },
+            KeyDiff {
+                key: b"hash".to_vec(),
+                value: Some(ValueDiff::Hash {
+                    added: vec![(b"h".to_vec(), b"y".to_vec())],
+                    removed: vec![(b"f".to_vec(), b"v".to_vec())],
+                    changed: vec![(b"g".to_vec(), b"w".to_vec(), b"x".to_vec())],
+                }),
+                expiry: None,
+            },
+            KeyDiff {
+                key: b"retyped".to_vec(),
+                value: Some(ValueDiff::Type {
+                    before: RedisValue::String(b"value".to_vec()),
+                    after: RedisValue::List(vec![b"a".to_vec()].into_iter().collect()),
+                }),
+                expiry: None,
+            },
+            KeyDiff {
+                key: b"set".to_vec(),
+                value: Some(ValueDiff::Set {
+                    added: vec![b"o".to_vec()],
+                    removed: vec![b"m".to_vec()],
+                }),
+                expiry: None,
+            },
+            KeyDiff {
+                key: b"string".to_vec(),
+                value: Some(ValueDiff::String {
+                    before: b"value".to_vec(),
+                    after: b"other".to_vec(),
+                }),
+                expiry: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn modifications_keep_the_expiry_replacements_drop_it() {
+    let mut mem = InMemoryStorage::new();
+    let expiry = Expiry::new_from_secs(60).unwrap();
+    let expiry_of = |mem: &InMemoryStorage, key: &[u8]| mem.meta(key).and_then(|meta| meta.expiry);
+
+    mem.write(b"string", b"value");
+    mem.expire(b"string", expiry);
+    mem.write_keeping_expiry(b"string", b"other");
+    assert_eq!(mem.read(b"string"), Some(&b"other"[..]));
+    assert_eq!(expiry_of(&mem, b"string"), Some(expiry));
+    mem.write(b"string", b"value");
+    assert_eq!(expiry_of(&mem, b"string"), None);
+
+    mem.swrite(b"set", vec![b"a".to_vec()].into_iter().collect());
+    mem.expire(b"set", expiry);
+    let limits = mem.encoding_limits();
+    mem.sread_mut(b"set")
+        .unwrap()
+        .insert(b"b".to_vec(), &limits);
+    assert_eq!(mem.sread(b"set").unwrap().len(), 2);
+    assert_eq!(expiry_of(&mem, b"set"), Some(expiry));
+
+    mem.hwrite(b"hash", HashMap::new());
+    mem.expire(b"hash", expiry);
+    let hash = mem.hread_mut(b"hash").unwrap();
+    hash.insert(b"f".to_vec(), b"v".to_vec(), &limits);
+    assert_eq!(mem.hread(b"hash", b"f"), Some(&b"v"[..]));
+    assert_eq!(expiry_of(&mem, b"hash"), Some(expiry));
+
+    assert!(mem.rename(b"hash", b"renamed"));
+    assert!(!mem.contains(b"hash"));
+    assert_eq!(mem.hread(b"renamed", b"f"), Some(&b"v"[..]));
+    assert_eq!(expiry_of(&mem, b"renamed"), Some(expiry));
+    assert!(!mem.rename(b"hash", b"renamed"));
+
+    // a deadline kept by a rewrite still removes the key once due
+    mem.write(b"due", b"value");
+    mem.expire(b"due", Expiry::new_from_millis(1).unwrap());
+    mem.write_keeping_expiry(b"due", b"other");
+    sleep(Duration::from_millis(5));
+    assert_eq!(mem.remove_expired(10), 1);
+    assert!(mem.sread_mut(b"missing").is_none());
+}
+
+#[test]
+fn hash_fields_expire_on_their_own() {
+    let mut mem = InMemoryStorage::new();
+    let fields: HashMap<_, _> = vec![
+        (b"f".to_vec(), b"v".to_vec()),
+        (b"g".to_vec(), b"w".to_vec()),
+    ]
+    .into_iter()
+    .collect();
+    mem.hwrite(b"hash", fields);
+
+    let hash = mem.hread_mut(b"hash").unwrap();
+    hash.expiries
+        .insert(b"f".to_vec(), Expiry::new_from_millis(1).unwrap());
+    hash.expiries
+        .insert(b"g".to_vec(), Expiry::new_from_secs(60).unwrap());
+    assert!(mem.rename(b"hash", b"renamed"));
+    assert_eq!(mem.hread_all(b"renamed").unwrap().expiries.len(), 2);
+    sleep(Duration::from_millis(5));
+
+    // expired fields are removed once read, the others keep their expiry
+    assert_eq!(mem.hread(b"renamed", b"f"), None);
+    assert_eq!(mem.hread(b"renamed", b"g"), Some(&b"w"[..]));
+    let entry = mem.dump_entry(b"renamed").unwrap();
+    assert_eq!(
+        entry.value,
+        RedisValue::Hash(vec![(b"g".to_vec(), b"w".to_vec())].into_iter().collect())
+    );
+
+    // HSET drops the expiry of the field it sets
+    let limits = mem.encoding_limits();
+    let hash = mem.hread_mut(b"renamed").unwrap();
+    hash.insert(b"g".to_vec(), b"x".to_vec(), &limits);
+    assert!(hash.expiries.is_empty());
+
+    // the key goes with its last field
+    let hash = mem.hread_mut(b"renamed").unwrap();
+    hash.expiries
+        .insert(b"g".to_vec(), Expiry::new_from_millis(1).unwrap());
+    sleep(Duration::from_millis(5));
+    assert!(mem.hread_all(b"renamed").is_none());
+    assert!(!mem.contains(b"renamed"));
+    assert_eq!(mem.size(), 0);
+}
+
+#[test]
+fn list_packs_keep_their_entries_in_order() {
+    let long = vec![b'x'; 300];
+    let mut pack: ListPack = vec![&b"b"[..], b"", &long].into_iter().collect();
+    pack.push_front(b"a");
+    pack.push_back(b"d");
+    pack.insert(3, b"c");
+    assert_eq!(pack.len(), 6);
+    assert_eq!(
+        pack.iter().collect::<Vec<_>>(),
+        vec![&b"a"[..], b"b", b"", b"c", &long, b"d"]
+    );
+
+    assert!(pack.replace(2, b"empty no more"));
+    assert!(!pack.replace(6, b"nope"));
+    assert_eq!(pack.remove(4), Some(long));
+    assert_eq!(pack.pop_back(), Some(b"d".to_vec()));
+    assert_eq!(pack.pop_front(), Some(b"a".to_vec()));
+    assert_eq!(pack.position(b"c"), Some(2));
+    assert_eq!(pack.get(1), Some(&b"empty no more"[..]));
+
+    pack.retain(|entry| entry != b"b");
+    pack.drain_front(1);
+    assert_eq!(pack.iter().collect::<Vec<_>>(), vec![&b"c"[..]]);
+    pack.truncate(0);
+    assert!(pack.is_empty());
+    assert_eq!(pack.byte_len(), 0);
+}
+
+#[test]
+fn aggregates_are_packed_until_they_outgrow_their_limits() {
+    let mut mem = InMemoryStorage::new();
+    let limits = EncodingLimits {
+        hash_max_listpack_entries: 2,
+        hash_max_listpack_value: 8,
+        list_max_listpack_size: 3,
+        set_max_intset_entries: 3,
+        set_max_listpack_entries: 2,
+        set_max_listpack_value: 8,
+    };
+    mem.set_encoding_limits(limits);
+
+    mem.write(b"int", b"-12");
+    mem.write(b"embstr", b"012");
+    mem.write(b"raw", &[b'x'; 45]);
+    assert_eq!(mem.encoding(b"int"), Some(Encoding::Int));
+    assert_eq!(mem.encoding(b"embstr"), Some(Encoding::EmbStr));
+    assert_eq!(mem.encoding(b"raw"), Some(Encoding::Raw));
+    assert_eq!(mem.encoding(b"missing"), None);
+
+    mem.lpush_back(b"list", vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+    assert_eq!(mem.encoding(b"list"), Some(Encoding::ListPack));
+    mem.lpush_front(b"list", vec![b"z".to_vec()]);
+    assert_eq!(mem.encoding(b"list"), Some(Encoding::QuickList));
+    assert_eq!(mem.lrange(b"list", 0, -1).len(), 4);
+    // lists never get back to a listpack, as in Redis
+    mem.lpop_front(b"list");
+    assert_eq!(mem.encoding(b"list"), Some(Encoding::QuickList));
+
+    let ints: HashSet<Vec<u8>> = vec![b"3".to_vec(), b"-1".to_vec()].into_iter().collect();
+    mem.swrite(b"ints", ints.clone());
+    assert_eq!(mem.encoding(b"ints"), Some(Encoding::IntSet));
+    let set = mem.sread_mut(b"ints").unwrap();
+    assert!(!set.insert(b"3".to_vec(), &limits));
+    assert!(set.insert(b"2".to_vec(), &limits));
+    assert!(set.contains(b"2"));
+    // "02" is no integer Redis would write back
+    assert!(!set.contains(b"02"));
+    assert_eq!(set.encoding(), Encoding::IntSet);
+    assert!(set.insert(b"4".to_vec(), &limits));
+    assert_eq!(set.encoding(), Encoding::HashTable);
+    assert_eq!(set.len(), 4);
+
+    mem.swrite(b"mixed", ints);
+    let set = mem.sread_mut(b"mixed").unwrap();
+    assert!(set.insert(b"a".to_vec(), &limits));
+    // three members are over `set_max_listpack_entries`
+    assert_eq!(set.encoding(), Encoding::HashTable);
+    let mut members: Vec<_> = set.iter().map(|member| member.into_owned()).collect();
+    members.sort();
+    assert_eq!(members, vec![b"-1".to_vec(), b"3".to_vec(), b"a".to_vec()]);
+
+    mem.swrite(b"words", vec![b"a".to_vec()].into_iter().collect());
+    let set = mem.sread_mut(b"words").unwrap();
+    assert_eq!(set.encoding(), Encoding::ListPack);
+    assert!(set.remove(b"a"));
+    assert!(!set.remove(b"a"));
+    assert!(set.insert(b"way too long".to_vec(), &limits));
+    assert_eq!(set.encoding(), Encoding::HashTable);
+
+    mem.hwrite(
+        b"hash",
+        vec![(b"f".to_vec(), b"v".to_vec())].into_iter().collect(),
+    );
+    assert_eq!(mem.encoding(b"hash"), Some(Encoding::ListPack));
+    let hash = mem.hread_mut(b"hash").unwrap();
+    hash.insert(b"f".to_vec(), b"w".to_vec(), &limits);
+    hash.insert(b"g".to_vec(), b"v".to_vec(), &limits);
+    assert_eq!(hash.data.len(), 2);
+    assert_eq!(hash.data.get(b"f"), Some(&b"w"[..]));
+    hash.expiries
+        .insert(b"g".to_vec(), Expiry::new_from_secs(60).unwrap());
+    assert_eq!(mem.encoding(b"hash"), Some(Encoding::ListPackEx));
+    let hash = mem.hread_mut(b"hash").unwrap();
+    hash.insert(b"h".to_vec(), b"v".to_vec(), &limits);
+    assert_eq!(hash.encoding(), Encoding::HashTable);
+    assert!(hash.remove(b"f"));
+    assert_eq!(hash.data.get(b"f"), None);
+
+    // values keep their content whatever their encoding
+    let entry = mem.dump_entry(b"hash").unwrap();
+    assert_eq!(
+        entry.value,
+        RedisValue::Hash(
+            vec![
+                (b"g".to_vec(), b"v".to_vec()),
+                (b"h".to_vec(), b"v".to_vec())
+            ]
+            .into_iter()
+            .collect()
+        )
+    );
+}
+
+#[test]
+fn striped_storage_reaches_each_key_in_its_stripe() {
+    let keys: Vec<Vec<u8>> = (0..100)
+        .map(|n| format!("key:{}", n).into_bytes())
+        .collect();
+    let mut mem = InMemoryStorage::new_deterministic();
+    for key in &keys {
+        mem.write(key, b"value");
+    }
+    // the keys already there are moved to their stripe
+    let striped = StripedStorage::new(mem, 16);
+
+    // the stripes of other keys stay free while one is locked
+    let held = striped.stripes_of(&[b"hash"]).lock();
+    let other = (0..)
+        .map(|n| format!("renamed:{}", n).into_bytes())
+        .find(|key| striped.stripes_of(&[key]).are_free())
+        .unwrap();
+    assert!(!striped.stripes_of(&[&other[..], b"hash"]).are_free());
+    drop(held);
+
+    let mut storage = striped.lock_all();
+    assert_eq!(storage.size(), 100);
+    // in byte order across the stripes, and scanned once each
+    let mut sorted = keys.clone();
+    sorted.sort();
+    let iterated: Vec<Vec<u8>> = storage.iter_keys().map(<[u8]>::to_vec).collect();
+    assert_eq!(iterated, sorted);
+    let options = ScanOptions {
+        count: 7,
+        ..ScanOptions::default()
+    };
+    let mut scanned = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let (next, mut page) = storage.scan_keys(cursor, &options);
+        assert!(page.len() <= 7);
+        scanned.append(&mut page);
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    scanned.sort();
+    assert_eq!(scanned, sorted);
+
+    // moved to another stripe along with the expiries of its fields
+    let fields: HashMap<_, _> = vec![(b"f".to_vec(), b"v".to_vec())].into_iter().collect();
+    storage.hwrite(b"hash", fields);
+    storage
+        .hread_mut(b"hash")
+        .unwrap()
+        .expiries
+        .insert(b"f".to_vec(), Expiry::new_from_secs(60).unwrap());
+    assert!(storage.rename(b"hash", &other));
+    assert!(!storage.contains(b"hash"));
+    assert_eq!(storage.hread_all(&other).unwrap().expiries.len(), 1);
+    assert_eq!(storage.count_of(RedisType::Hash), 1);
+
+    // expired soonest first whatever their stripe
+    for key in &keys[..10] {
+        storage.expire(key, Expiry::new_from_millis(1).unwrap());
+    }
+    sleep(Duration::from_millis(5));
+    assert_eq!(storage.due_expirations(100).len(), 10);
+    assert_eq!(storage.remove_expired(4), 4);
+    assert_eq!(storage.remove_expired(100), 6);
+    assert_eq!(storage.size(), 91);
+
+    storage.flush();
+    assert_eq!(storage.size(), 0);
+}
@@ -0,0 +1,197 @@
+//! Linearizability of SET/GET histories: every key has to behave as a register whose operations
+//! each took effect at a single instant between their invocation and their completion.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::time::Instant;
+
+/// What an operation did to its key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    Set(Vec<u8>),
+    /// The value read, `None` when the key didn't exist
+    Get(Option<Vec<u8>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub client: usize,
+    pub key: Vec<u8>,
+    pub access: Access,
+    pub invoked: Instant,
+    /// `None` when the client never got a reply, e.g. a SET which timed out: it may have taken
+    /// effect at any point after its invocation, or not at all
+    pub completed: Option<Instant>,
+}
+
+/// Operations of concurrent clients, on keys which didn't exist beforehand
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    operations: Vec<Operation>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    pub fn push(&mut self, operation: Operation) {
+        self.operations.push(operation);
+    }
+
+    pub fn extend<I: IntoIterator<Item = Operation>>(&mut self, operations: I) {
+        self.operations.extend(operations);
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Find an order of the operations of each key consistent with their real time order and
+    /// with the values read, or the key for which there is none
+    pub fn check_linearizable(&self) -> Result<(), Violation> {
+        let mut keys: BTreeMap<&[u8], Vec<&Operation>> = BTreeMap::new();
+        for operation in &self.operations {
+            // a read without a reply tells nothing
+            if operation.completed.is_none() {
+                if let Access::Get(_) = operation.access {
+                    continue;
+                }
+            }
+            keys.entry(&operation.key).or_default().push(operation);
+        }
+
+        for (key, mut operations) in keys {
+            operations.sort_by_key(|operation| operation.invoked);
+            if !Search::new(&operations).run() {
+                return Err(Violation {
+                    key: key.to_vec(),
+                    operations: operations.into_iter().cloned().collect(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Operations on a key which can't be linearized
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub key: Vec<u8>,
+    /// Every operation on the key, by invocation time
+    pub operations: Vec<Operation>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the {} operations on key {:?} are not linearizable",
+            self.operations.len(),
+            String::from_utf8_lossy(&self.key)
+        )
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// Depth first search of a linearization, in the manner of Wing and Gong, skipping the states
+/// already explored: the same operations linearized leaving the same value
+struct Search<'a> {
+    operations: &'a [&'a Operation],
+    // the first operation setting the same value as each operation, to compare values by index
+    same_value: Vec<usize>,
+    visited: HashSet<(Vec<u64>, Option<usize>)>,
+}
+
+impl<'a> Search<'a> {
+    fn new(operations: &'a [&'a Operation]) -> Self {
+        let same_value = operations
+            .iter()
+            .enumerate()
+            .map(|(idx, operation)| {
+                operations
+                    .iter()
+                    .position(|other| other.access == operation.access)
+                    .unwrap_or(idx)
+            })
+            .collect();
+
+        Search {
+            operations,
+            same_value,
+            visited: HashSet::new(),
+        }
+    }
+
+    fn run(&mut self) -> bool {
+        let mut linearized = vec![0; self.operations.len().div_ceil(64)];
+        let completed = self
+            .operations
+            .iter()
+            .filter(|operation| operation.completed.is_some())
+            .count();
+        self.search(&mut linearized, None, completed)
+    }
+
+    /// `value` is the index of the operation whose SET is the current value of the key
+    fn search(&mut self, linearized: &mut Vec<u64>, value: Option<usize>, left: usize) -> bool {
+        if left == 0 {
+            return true;
+        }
+        if !self.visited.insert((linearized.clone(), value)) {
+            return false;
+        }
+
+        let is_linearized =
+            |linearized: &[u64], idx: usize| linearized[idx / 64] & (1 << (idx % 64)) != 0;
+
+        // the operation completing first has to be linearized before those invoked after it
+        let deadline = (0..self.operations.len())
+            .filter(|idx| !is_linearized(linearized, *idx))
+            .filter_map(|idx| self.operations[idx].completed)
+            .min();
+
+        for idx in 0..self.operations.len() {
+            let operation = self.operations[idx];
+            if deadline.is_some_and(|deadline| operation.invoked > deadline) {
+                // sorted by invocation, none of the next ones can come first either
+                break;
+            }
+            if is_linearized(linearized, idx) {
+                continue;
+            }
+
+            let next_value = match &operation.access {
+                Access::Set(_) => Some(self.same_value[idx]),
+                Access::Get(read) => {
+                    let current = value.map(|value| match &self.operations[value].access {
+                        Access::Set(current) => current,
+                        // only SETs are ever the current value
+                        Access::Get(_) => unreachable!(),
+                    });
+                    if current != read.as_ref() {
+                        continue;
+                    }
+                    value
+                }
+            };
+
+            linearized[idx / 64] |= 1 << (idx % 64);
+            let left = left - operation.completed.map_or(0, |_| 1);
+            if self.search(linearized, next_value, left) {
+                return true;
+            }
+            linearized[idx / 64] &= !(1 << (idx % 64));
+        }
+        false
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+type Connections = Arc<Mutex<HashMap<u64, TcpStream>>>;
+
+/// TCP proxy to `target` which can be cut, the way the nodes in `down` get no message in the
+/// Raft tests of `cluster`. Cutting it closes the connections going through it, and the
+/// connections opened until it's healed. Threads stop when the link is dropped.
+pub struct Link {
+    addr: SocketAddr,
+    cut: Arc<AtomicBool>,
+    connections: Connections,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Link {
+    /// Listen on a free local port, see `Link::addr`
+    pub fn start(target: SocketAddr) -> io::Result<Link> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let cut = Arc::new(AtomicBool::new(false));
+        let connections = Connections::default();
+        let stopped = Arc::new(AtomicBool::new(false));
+        {
+            let cut = cut.clone();
+            let connections = connections.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || accept(listener, target, cut, connections, stopped));
+        }
+
+        Ok(Link {
+            addr,
+            cut,
+            connections,
+            stopped,
+        })
+    }
+
+    /// Address to connect to instead of the target
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn cut(&self) {
+        self.cut.store(true, Ordering::SeqCst);
+        shutdown_all(&self.connections);
+    }
+
+    pub fn heal(&self) {
+        self.cut.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cut(&self) -> bool {
+        self.cut.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        shutdown_all(&self.connections);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn shutdown_all(connections: &Connections) {
+    for connection in lock(connections).values() {
+        let _ = connection.shutdown(Shutdown::Both);
+    }
+}
+
+fn accept(
+    listener: TcpListener,
+    target: SocketAddr,
+    cut: Arc<AtomicBool>,
+    connections: Connections,
+    stopped: Arc<AtomicBool>,
+) {
+    let mut next_connection_id = 0u64;
+
+    while !stopped.load(Ordering::SeqCst) {
+        let client = match listener.accept() {
+            // dropped right away while the link is cut
+            Ok((client, _)) if cut.load(Ordering::SeqCst) => {
+                let _ = client.shutdown(Shutdown::Both);
+                continue;
+            }
+            Ok((client, _)) => client,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_INTERVAL);
+                continue;
+            }
+            Err(err) => {
+                log::error!("link to {} stopped accepting connections: {}", target, err);
+                return;
+            }
+        };
+
+        let server = match client
+            .set_nonblocking(false)
+            .and_then(|_| TcpStream::connect_timeout(&target, CONNECT_TIMEOUT))
+        {
+            Ok(server) => server,
+            Err(err) => {
+                log::info!("link to {} dropped a connection: {}", target, err);
+                continue;
+            }
+        };
+        let _ = client.set_nodelay(true);
+        let _ = server.set_nodelay(true);
+
+        // the streams are read by the copying threads, their clones are written to and shut down
+        let clones = (|| {
+            Ok::<_, io::Error>((
+                [client.try_clone()?, client.try_clone()?],
+                [server.try_clone()?, server.try_clone()?],
+            ))
+        })();
+        let ([client_writer, client_shutdown], [server_writer, server_shutdown]) = match clones {
+            Ok(clones) => clones,
+            Err(err) => {
+                log::info!("link to {} dropped a connection: {}", target, err);
+                continue;
+            }
+        };
+
+        let (client_id, server_id) = (next_connection_id, next_connection_id.wrapping_add(1));
+        next_connection_id = next_connection_id.wrapping_add(2);
+        {
+            let mut connections = lock(&connections);
+            connections.insert(client_id, client_shutdown);
+            connections.insert(server_id, server_shutdown);
+        }
+
+        let streams = [
+            (client_id, client, server_writer),
+            (server_id, server, client_writer),
+        ];
+        for (id, mut from, mut to) in streams {
+            let connections = connections.clone();
+            thread::spawn(move || {
+                let _ = io::copy(&mut from, &mut to);
+                // closing both ways ends the copy in the other direction too
+                let _ = from.shutdown(Shutdown::Both);
+                let _ = to.shutdown(Shutdown::Both);
+                lock(&connections).remove(&id);
+            });
+        }
+    }
+}
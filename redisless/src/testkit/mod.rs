@@ -0,0 +1,294 @@
+//! In-process clusters to test the consistency seen by clients. The first node of a
+//! `TestCluster` is the leader, the others are followers proxying every command to it, see
+//! `Server::new_with_upstream`. Each follower reaches the leader through a `Link` which can be
+//! cut to partition it away. Clients spread over the nodes run a `Workload` of SETs and GETs, and
+//! the resulting `History` is checked for linearizability.
+//!
+//! ```no_run
+//! use std::thread::sleep;
+//! use std::time::Duration;
+//!
+//! use redisless::testkit::{TestCluster, Workload};
+//!
+//! let cluster = TestCluster::start(3, 16380).unwrap();
+//! let history = cluster.run_workload(&Workload::default(), |cluster| {
+//!     sleep(Duration::from_millis(50));
+//!     cluster.isolate(2);
+//!     sleep(Duration::from_millis(50));
+//!     cluster.heal(2);
+//! });
+//! if let Err(violation) = history.check_linearizable() {
+//!     println!("{}: {:?}", violation, violation.operations);
+//! }
+//! ```
+
+pub mod linearizability;
+pub mod link;
+#[cfg(test)]
+mod tests;
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+pub use linearizability::{Access, History, Operation, Violation};
+pub use link::Link;
+
+use crate::protocol::parser::RedisProtocolParser;
+use crate::protocol::writer::RespWriter;
+use crate::protocol::Resp;
+use crate::server::{Server, ServerConfig, ServerState, UpstreamConfig};
+use crate::storage::in_memory::InMemoryStorage;
+
+/// Followers give up on the leader after this long and serve the command themselves
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(1);
+/// Followers try the leader again this long after losing it
+const UPSTREAM_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct TestCluster {
+    servers: Vec<Server>,
+    addrs: Vec<SocketAddr>,
+    // the link of each follower to the leader, `None` for the leader
+    links: Vec<Option<Link>>,
+}
+
+impl TestCluster {
+    /// Start `nodes` servers listening on the ports from `first_port`, the first being the leader
+    pub fn start(nodes: usize, first_port: u16) -> io::Result<TestCluster> {
+        let mut cluster = TestCluster {
+            servers: Vec::with_capacity(nodes),
+            addrs: Vec::with_capacity(nodes),
+            links: Vec::with_capacity(nodes),
+        };
+
+        for port in (first_port..).take(nodes) {
+            let link = match cluster.addrs.first() {
+                Some(leader) => Some(Link::start(*leader)?),
+                None => None,
+            };
+            let config = ServerConfig {
+                upstream: link.as_ref().map(|link| UpstreamConfig {
+                    upstream: link.addr().to_string(),
+                    timeout: UPSTREAM_TIMEOUT,
+                    retry_interval: UPSTREAM_RETRY_INTERVAL,
+                }),
+                ..ServerConfig::default()
+            };
+
+            let server = Server::new_with_config(InMemoryStorage::new(), config, port);
+            match server.start() {
+                Some(ServerState::Started) => {}
+                state => {
+                    return Err(io::Error::other(format!(
+                        "server on port {} didn't start: {:?}",
+                        port, state
+                    )))
+                }
+            }
+            cluster.servers.push(server);
+            cluster.addrs.push(SocketAddr::from(([127, 0, 0, 1], port)));
+            cluster.links.push(link);
+        }
+
+        Ok(cluster)
+    }
+
+    pub fn len(&self) -> usize {
+        self.servers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    /// Address clients connect to for the node `node`
+    pub fn addr(&self, node: usize) -> SocketAddr {
+        self.addrs[node]
+    }
+
+    pub fn server(&self, node: usize) -> &Server {
+        &self.servers[node]
+    }
+
+    /// Partition the follower `node` away from the leader, it then serves commands on its own
+    pub fn isolate(&self, node: usize) {
+        if let Some(link) = &self.links[node] {
+            link.cut();
+        }
+    }
+
+    /// Reconnect the follower `node` to the leader
+    pub fn heal(&self, node: usize) {
+        if let Some(link) = &self.links[node] {
+            link.heal();
+        }
+    }
+
+    pub fn heal_all(&self) {
+        for node in 0..self.len() {
+            self.heal(node);
+        }
+    }
+
+    /// Run `workload` and call `during` meanwhile, e.g. to inject partitions. Returns once every
+    /// client is done.
+    pub fn run_workload<F: FnOnce(&TestCluster)>(&self, workload: &Workload, during: F) -> History {
+        let clients: Vec<_> = (0..workload.clients)
+            .map(|client| {
+                let addr = self.addr(client % self.len());
+                let workload = workload.clone();
+                thread::spawn(move || run_client(client, addr, &workload))
+            })
+            .collect();
+
+        during(self);
+
+        let mut history = History::new();
+        for client in clients {
+            history.extend(client.join().expect("workload client panicked"));
+        }
+        history
+    }
+}
+
+impl Drop for TestCluster {
+    fn drop(&mut self) {
+        for server in &self.servers {
+            let _ = server.stop();
+        }
+    }
+}
+
+/// SETs and GETs run by each client, on random keys
+#[derive(Debug, Clone)]
+pub struct Workload {
+    /// Clients running concurrently, spread over the nodes in turn
+    pub clients: usize,
+    /// Operations run by each client, one at a time
+    pub operations: usize,
+    /// Keys the operations are spread over
+    pub keys: usize,
+    /// Probability of an operation to be a SET
+    pub write_ratio: f64,
+    /// Give up on a reply after this long, a SET then has an unknown outcome
+    pub timeout: Duration,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Workload {
+            clients: 4,
+            operations: 100,
+            keys: 2,
+            write_ratio: 0.5,
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Reply of a workload command
+enum Reply {
+    Ok,
+    Value(Option<Vec<u8>>),
+    Error,
+}
+
+fn run_client(client: usize, addr: SocketAddr, workload: &Workload) -> Vec<Operation> {
+    let mut rng = rand::thread_rng();
+    let mut operations = Vec::with_capacity(workload.operations);
+    let mut connection: Option<TcpStream> = None;
+    let mut buffer = Vec::new();
+
+    for n in 0..workload.operations {
+        let key = format!("key-{}", rng.gen_range(0..workload.keys.max(1))).into_bytes();
+        // values are unique for the reads to tell which SET they see
+        let set = rng.gen_bool(workload.write_ratio);
+        let value = format!("{}-{}", client, n).into_bytes();
+
+        if connection.is_none() {
+            match connect(addr, workload.timeout) {
+                Ok(stream) => connection = Some(stream),
+                // nothing was sent, the operation didn't happen
+                Err(_) => continue,
+            }
+        }
+        // will never panic, just connected
+        let stream = connection.as_mut().unwrap();
+
+        let mut request = RespWriter::default();
+        if set {
+            request.array_len(3);
+            request.bulk_string(b"SET");
+            request.bulk_string(&key);
+            request.bulk_string(&value);
+        } else {
+            request.array_len(2);
+            request.bulk_string(b"GET");
+            request.bulk_string(&key);
+        }
+
+        let invoked = Instant::now();
+        let reply = send(stream, &mut buffer, request.as_slice());
+        let completed = Instant::now();
+        if reply.is_err() {
+            // the reply may still come, don't mistake it for the next one
+            connection = None;
+            buffer.clear();
+        }
+
+        let (access, completed) = match (set, reply) {
+            (true, Ok(Reply::Ok)) => (Access::Set(value), Some(completed)),
+            (true, _) => (Access::Set(value), None),
+            (false, Ok(Reply::Value(read))) => (Access::Get(read), Some(completed)),
+            // a read which failed tells nothing
+            (false, _) => continue,
+        };
+        operations.push(Operation {
+            client,
+            key,
+            access,
+            invoked,
+            completed,
+        });
+    }
+    operations
+}
+
+fn connect(addr: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+fn send(stream: &mut TcpStream, buffer: &mut Vec<u8>, request: &[u8]) -> io::Result<Reply> {
+    stream.write_all(request)?;
+
+    let mut chunk = [0; 4096];
+    let len = loop {
+        match RedisProtocolParser::frame_len(buffer) {
+            Ok(Some(len)) => break len,
+            Ok(None) => {}
+            Err(err) => return Err(io::Error::new(ErrorKind::InvalidData, err.to_string())),
+        }
+
+        match stream.read(&mut chunk)? {
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            n => buffer.extend_from_slice(&chunk[..n]),
+        }
+    };
+
+    let reply = match RedisProtocolParser::parse(&buffer[..len]) {
+        Ok((Resp::String(_), _)) => Reply::Ok,
+        Ok((Resp::BulkString(value), _)) => Reply::Value(Some(value.to_vec())),
+        Ok((Resp::Nil, _)) => Reply::Value(None),
+        Ok(_) => Reply::Error,
+        Err(err) => return Err(io::Error::new(ErrorKind::InvalidData, err.to_string())),
+    };
+    buffer.drain(..len);
+    Ok(reply)
+}
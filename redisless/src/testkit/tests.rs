@@ -0,0 +1,232 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::OnceLock;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use super::{connect, send, Access, History, Link, Operation, Reply, TestCluster, Workload};
+
+/// Operation on `key`, its times in milliseconds from a common start
+fn operation(client: usize, access: Access, invoked: u64, completed: Option<u64>) -> Operation {
+    static START: OnceLock<Instant> = OnceLock::new();
+    let start = *START.get_or_init(Instant::now);
+    Operation {
+        client,
+        key: b"key".to_vec(),
+        access,
+        invoked: start + Duration::from_millis(invoked),
+        completed: completed.map(|completed| start + Duration::from_millis(completed)),
+    }
+}
+
+fn set(value: &str) -> Access {
+    Access::Set(value.as_bytes().to_vec())
+}
+
+fn get(value: Option<&str>) -> Access {
+    Access::Get(value.map(|value| value.as_bytes().to_vec()))
+}
+
+fn history(operations: Vec<Operation>) -> History {
+    let mut history = History::new();
+    history.extend(operations);
+    history
+}
+
+#[test]
+fn sequential_histories() {
+    let ok = history(vec![
+        operation(0, get(None), 0, Some(1)),
+        operation(0, set("a"), 2, Some(3)),
+        operation(1, get(Some("a")), 4, Some(5)),
+        operation(1, set("b"), 6, Some(7)),
+        operation(0, get(Some("b")), 8, Some(9)),
+    ]);
+    assert!(ok.check_linearizable().is_ok());
+
+    let stale = history(vec![
+        operation(0, set("a"), 0, Some(1)),
+        operation(0, set("b"), 2, Some(3)),
+        operation(1, get(Some("a")), 4, Some(5)),
+    ]);
+    let violation = stale.check_linearizable().unwrap_err();
+    assert_eq!(violation.key, b"key".to_vec());
+    assert_eq!(violation.operations.len(), 3);
+
+    let never_set = history(vec![operation(0, get(Some("a")), 0, Some(1))]);
+    assert!(never_set.check_linearizable().is_err());
+}
+
+#[test]
+fn concurrent_histories() {
+    // the read overlaps both writes, it may see either of them
+    for read in &["a", "b"] {
+        let history = history(vec![
+            operation(0, set("a"), 0, Some(10)),
+            operation(1, set("b"), 1, Some(10)),
+            operation(2, get(Some(read)), 2, Some(10)),
+        ]);
+        assert!(history.check_linearizable().is_ok());
+    }
+
+    // both reads are after both writes, they must agree
+    let disagreeing = history(vec![
+        operation(0, set("a"), 0, Some(2)),
+        operation(1, set("b"), 0, Some(2)),
+        operation(2, get(Some("a")), 3, Some(4)),
+        operation(2, get(Some("b")), 5, Some(6)),
+    ]);
+    assert!(disagreeing.check_linearizable().is_err());
+
+    // the value can't go back to one overwritten
+    let flapping = history(vec![
+        operation(0, set("a"), 0, Some(1)),
+        operation(1, set("b"), 2, Some(20)),
+        operation(2, get(Some("b")), 3, Some(4)),
+        operation(2, get(Some("a")), 5, Some(6)),
+    ]);
+    assert!(flapping.check_linearizable().is_err());
+}
+
+#[test]
+fn unknown_outcomes() {
+    // a SET without reply may have happened
+    let applied = history(vec![
+        operation(0, set("a"), 0, None),
+        operation(1, get(Some("a")), 5, Some(6)),
+    ]);
+    assert!(applied.check_linearizable().is_ok());
+
+    // or not
+    let lost = history(vec![
+        operation(0, set("a"), 0, None),
+        operation(1, get(None), 5, Some(6)),
+    ]);
+    assert!(lost.check_linearizable().is_ok());
+
+    // but not before it was sent
+    let early = history(vec![
+        operation(1, get(Some("a")), 0, Some(1)),
+        operation(0, set("a"), 2, None),
+    ]);
+    assert!(early.check_linearizable().is_err());
+}
+
+#[test]
+fn keys_are_checked_apart() {
+    let mut other = operation(1, get(None), 4, Some(5));
+    other.key = b"other".to_vec();
+    let history = history(vec![operation(0, set("a"), 0, Some(1)), other]);
+    assert!(history.check_linearizable().is_ok());
+}
+
+#[test]
+fn links_can_be_cut() {
+    // echoes every connection
+    let target = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target_addr = target.local_addr().unwrap();
+    thread::spawn(move || {
+        for mut stream in target.incoming().flatten() {
+            thread::spawn(move || {
+                let mut reader = stream.try_clone().unwrap();
+                let _ = std::io::copy(&mut reader, &mut stream);
+            });
+        }
+    });
+
+    let link = Link::start(target_addr).unwrap();
+    let echo = |addr: SocketAddr| -> std::io::Result<Vec<u8>> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        stream.write_all(b"ping")?;
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply)?;
+        Ok(reply.to_vec())
+    };
+
+    assert_eq!(echo(link.addr()).unwrap(), b"ping".to_vec());
+
+    let mut open = TcpStream::connect(link.addr()).unwrap();
+    open.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    open.write_all(b"ping").unwrap();
+    let mut reply = [0; 4];
+    open.read_exact(&mut reply).unwrap();
+
+    link.cut();
+    assert!(link.is_cut());
+    // the open connection is closed, and the new ones too
+    let mut rest = Vec::new();
+    assert_eq!(open.read_to_end(&mut rest).unwrap_or(0), 0);
+    assert!(echo(link.addr()).is_err());
+
+    link.heal();
+    assert_eq!(echo(link.addr()).unwrap(), b"ping".to_vec());
+}
+
+#[test]
+#[serial]
+fn cluster_is_linearizable_without_partitions() {
+    let cluster = TestCluster::start(3, 3411).unwrap();
+    let workload = Workload {
+        clients: 6,
+        operations: 50,
+        ..Workload::default()
+    };
+
+    let history = cluster.run_workload(&workload, |_| {});
+
+    // every client got a reply to every command
+    assert_eq!(history.len(), 6 * 50);
+    assert!(history
+        .operations()
+        .iter()
+        .all(|operation| operation.completed.is_some()));
+    if let Err(violation) = history.check_linearizable() {
+        panic!("{}: {:?}", violation, violation.operations);
+    }
+}
+
+#[test]
+#[serial]
+fn isolated_followers_serve_stale_reads() {
+    let cluster = TestCluster::start(2, 3414).unwrap();
+    let timeout = Duration::from_secs(2);
+    let mut history = History::new();
+    let mut run = |node: usize, client: usize, access: Access| {
+        let mut stream = connect(cluster.addr(node), timeout).unwrap();
+        let request: &[u8] = match &access {
+            Access::Set(_) => b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$1\r\na\r\n",
+            Access::Get(_) => b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
+        };
+
+        let invoked = Instant::now();
+        let reply = send(&mut stream, &mut Vec::new(), request).unwrap();
+        let access = match (access, reply) {
+            (Access::Set(value), Reply::Ok) => Access::Set(value),
+            (Access::Get(_), Reply::Value(value)) => Access::Get(value),
+            _ => panic!("unexpected reply"),
+        };
+        history.push(Operation {
+            client,
+            key: b"key".to_vec(),
+            access,
+            invoked,
+            completed: Some(Instant::now()),
+        });
+    };
+
+    // read your writes through the follower while it reaches the leader
+    run(0, 0, set("a"));
+    run(1, 1, get(None));
+    cluster.isolate(1);
+    sleep(Duration::from_millis(50));
+    // served from the follower's own storage, which never saw the write
+    run(1, 1, get(None));
+
+    let violation = history.check_linearizable().unwrap_err();
+    assert_eq!(violation.operations.len(), 3);
+    assert_eq!(
+        violation.operations[1].access,
+        Access::Get(Some(b"a".to_vec()))
+    );
+}
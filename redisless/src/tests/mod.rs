@@ -1,8 +1,12 @@
 use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::net::{TcpListener, TcpStream};
+
+use std::ffi::CStr;
 
 use crate::{
-    redisless_server_free, redisless_server_new, redisless_server_start, redisless_server_stop,
+    redisless_capabilities, redisless_capabilities_free, redisless_server_free,
+    redisless_server_new, redisless_server_new_on_free_port, redisless_server_port,
+    redisless_server_start, redisless_server_stop,
 };
 
 #[test]
@@ -32,9 +36,9 @@ fn start_and_stop_server_from_c_binding() {
 
         // run command `GET mykey`
         let _ = stream.write(b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n");
-        let mut get_res = [0; 8];
+        let mut get_res = [0; 11];
         let _ = stream.read(&mut get_res);
-        assert_eq!(get_res, b"+value\r\n"[..]);
+        assert_eq!(get_res, b"$5\r\nvalue\r\n"[..]);
 
         // run command `DEL mykey`
         let _ = stream.write(b"*2\r\n$3\r\nDEL\r\n$5\r\nmykey\r\n");
@@ -54,3 +58,52 @@ fn start_and_stop_server_from_c_binding() {
         redisless_server_free(server);
     }
 }
+
+#[test]
+#[serial]
+fn free_port_from_c_binding() {
+    let taken = TcpListener::bind("0.0.0.0:4450").unwrap();
+    assert!(redisless_server_new_on_free_port(4450, 4450).is_null());
+
+    let server = redisless_server_new_on_free_port(4450, 4460);
+    assert!(!server.is_null());
+    let port = unsafe { redisless_server_port(server) };
+    assert!((4451..=4460).contains(&port), "picked port {}", port);
+    assert_eq!(unsafe { redisless_server_port(std::ptr::null_mut()) }, 0);
+
+    unsafe {
+        assert!(redisless_server_start(server), "server didn't start");
+    }
+    let mut stream = TcpStream::connect(format!("localhost:{}", port)).unwrap();
+    let _ = stream.write(b"*1\r\n$4\r\nPING\r\n");
+    let mut pong_res = [0; 7];
+    let _ = stream.read(&mut pong_res);
+    assert_eq!(pong_res, b"+PONG\r\n"[..]);
+
+    unsafe {
+        assert!(redisless_server_stop(server), "server didn't stop");
+        redisless_server_free(server);
+    }
+    drop(taken);
+}
+
+#[test]
+fn capabilities_from_c_binding() {
+    let capabilities = redisless_capabilities();
+    assert!(!capabilities.is_null());
+
+    let json = unsafe { CStr::from_ptr(capabilities) }
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(json.starts_with(r#"{"version":""#));
+    assert!(json.contains(r#""get""#));
+    assert!(json.contains(r#""features":["pipelining""#));
+
+    unsafe { redisless_capabilities_free(capabilities) };
+
+    let capabilities = crate::capabilities::capabilities();
+    assert!(capabilities.supports_command("GET"));
+    assert!(!capabilities.supports_command("SUBSCRIBE"));
+    assert!(capabilities.supports_feature("pipelining"));
+}